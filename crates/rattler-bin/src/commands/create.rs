@@ -13,7 +13,9 @@ use rattler_conda_types::{
     Channel, ChannelConfig, GenericVirtualPackage, MatchSpec, ParseStrictness, Platform,
     PrefixRecord, RepoDataRecord, Version,
 };
-use rattler_networking::{AuthenticationMiddleware, AuthenticationStorage};
+use rattler_networking::{
+    AuthenticationMiddleware, AuthenticationStorage, AzureMiddleware, CircuitBreakerMiddleware,
+};
 use rattler_repodata_gateway::{Gateway, RepoData};
 use rattler_solve::{
     libsolv_c::{self},
@@ -53,6 +55,10 @@ pub struct Opt {
 
     #[clap(long)]
     strategy: Option<SolveStrategy>,
+
+    /// Only install the requested packages, without their dependencies.
+    #[clap(long)]
+    no_deps: bool,
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -135,11 +141,20 @@ pub async fn create(opt: Opt) -> anyhow::Result<()> {
         .build()
         .expect("failed to create client");
 
+    // The same client is used for repodata and packages so hosts that keep failing are avoided
+    // for both.
     let authentication_storage = AuthenticationStorage::default();
-    let download_client = reqwest_middleware::ClientBuilder::new(download_client)
+    // The storage middlewares rewrite `az://` and `gcs://` urls, they come first so the other
+    // middlewares see the actual url of the request.
+    let download_client =
+        reqwest_middleware::ClientBuilder::new(download_client).with(AzureMiddleware::from_env());
+    #[cfg(feature = "gcs")]
+    let download_client = download_client.with(rattler_networking::GCSMiddleware::new());
+    let download_client = download_client
         .with_arc(Arc::new(AuthenticationMiddleware::new(
             authentication_storage,
         )))
+        .with(CircuitBreakerMiddleware::new())
         .build();
 
     // Get the package names from the matchspecs so we can only load the package records that we need.
@@ -225,17 +240,33 @@ pub async fn create(opt: Opt) -> anyhow::Result<()> {
         specs,
         timeout: opt.timeout.map(Duration::from_millis),
         strategy: opt.strategy.map_or_else(Default::default, Into::into),
+        no_deps: opt.no_deps,
         ..SolverTask::from_iter(&repo_data)
     };
 
     // Next, use a solver to solve this specific problem. This provides us with all the operations
     // we need to apply to our environment to bring it up to date.
-    let required_packages =
+    let mut required_packages =
         wrap_in_progress("solving", move || match opt.solver.unwrap_or_default() {
             Solver::Resolvo => resolvo::Solver.solve(solver_task),
             Solver::LibSolv => libsolv_c::Solver.solve(solver_task),
         })?;
 
+    // Without dependencies the solution only contains the requested packages,
+    // keep the other installed packages as they are.
+    if opt.no_deps {
+        let installed = installed_packages
+            .iter()
+            .filter(|installed| {
+                !required_packages.iter().any(|record| {
+                    record.package_record.name == installed.repodata_record.package_record.name
+                })
+            })
+            .map(|installed| installed.repodata_record.clone())
+            .collect::<Vec<_>>();
+        required_packages.extend(installed);
+    }
+
     if opt.dry_run {
         // Construct a transaction to
         let transaction = Transaction::from_current_and_desired(
@@ -0,0 +1,405 @@
+//! Offline environment bundles.
+//!
+//! A bundle is a single tar archive that contains everything that is needed to
+//! recreate a locked environment on a machine without network access:
+//!
+//! * `conda-lock.yml`: a lock file with the environment for a single platform.
+//! * `channel/<subdir>/<file_name>`: the package archives of the environment.
+//! * `channel/<subdir>/repodata.json`: the records of the bundled packages.
+//!
+//! The `channel` directory is a valid local channel, so the bundle can also be
+//! extracted and used to solve against. Use [`export_bundle`] to create a
+//! bundle and [`import_bundle`] to install the environment from a bundle.
+
+use std::{collections::BTreeMap, io, path::Path};
+
+use fxhash::{FxHashMap, FxHashSet};
+use rattler_conda_types::{package::ArchiveType, ChannelInfo, Platform, RepoData, RepoDataRecord};
+use rattler_digest::Sha256;
+use rattler_lock::{ConversionError, LockFile};
+use simple_spawn_blocking::{tokio::run_blocking_task, Cancelled};
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+use crate::{
+    install::{InstallationResult, Installer, InstallerError},
+    package_cache::{PackageCache, PackageCacheError},
+};
+
+/// The name of the lock file inside a bundle.
+pub const BUNDLE_LOCK_FILE_NAME: &str = "conda-lock.yml";
+
+/// The name of the directory inside a bundle that contains the channel with
+/// the bundled packages.
+pub const BUNDLE_CHANNEL_DIR: &str = "channel";
+
+/// An error that can occur while exporting or importing a bundle.
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    /// The environment does not exist in the lock file.
+    #[error("the environment '{0}' does not exist in the lock file")]
+    EnvironmentNotFound(String),
+
+    /// The environment is not locked for the platform.
+    #[error("the environment '{0}' is not locked for {1}")]
+    PlatformNotFound(String, Platform),
+
+    /// The bundle does not contain exactly one environment for one platform.
+    #[error("the bundle must contain a single environment for a single platform")]
+    InvalidBundle,
+
+    /// A locked package could not be converted to a record.
+    #[error(transparent)]
+    ConversionError(#[from] ConversionError),
+
+    /// Failed to download a package archive.
+    #[error("failed to download {0}")]
+    DownloadError(String, #[source] reqwest_middleware::Error),
+
+    /// The downloaded archive does not match the hash of the locked package.
+    #[error("the sha256 hash of {0} does not match the lock file")]
+    HashMismatch(String),
+
+    /// Failed to read or write the lock file of the bundle.
+    #[error("failed to read the lock file of the bundle")]
+    LockFileError(#[source] rattler_lock::ParseCondaLockError),
+
+    /// Failed to populate the package cache from the bundle.
+    #[error("failed to add {0} to the package cache")]
+    PackageCacheError(String, #[source] PackageCacheError),
+
+    /// Failed to install the environment.
+    #[error(transparent)]
+    InstallerError(#[from] InstallerError),
+
+    /// An IO error occurred.
+    #[error("{0}")]
+    IoError(String, #[source] io::Error),
+
+    /// The operation was cancelled.
+    #[error("the operation was cancelled")]
+    Cancelled,
+}
+
+impl From<Cancelled> for BundleError {
+    fn from(_value: Cancelled) -> Self {
+        BundleError::Cancelled
+    }
+}
+
+/// Exports the environment of a lock file for a single platform to a bundle
+/// at `destination`.
+///
+/// The package archives are downloaded with the given client, or copied if
+/// they refer to local files. Archives are verified against the sha256 hashes
+/// of the lock file if available. PyPI packages are not included in the
+/// bundle.
+pub async fn export_bundle(
+    lock_file: &LockFile,
+    environment_name: &str,
+    platform: Platform,
+    client: &reqwest_middleware::ClientWithMiddleware,
+    destination: &Path,
+) -> Result<(), BundleError> {
+    let environment = lock_file
+        .environment(environment_name)
+        .ok_or_else(|| BundleError::EnvironmentNotFound(environment_name.to_string()))?;
+    let records = environment
+        .conda_repodata_records_for_platform(platform)?
+        .ok_or_else(|| BundleError::PlatformNotFound(environment_name.to_string(), platform))?;
+
+    let staging_dir = tempfile::tempdir()
+        .map_err(|e| BundleError::IoError("failed to create a temporary directory".into(), e))?;
+    let channel_dir = staging_dir.path().join(BUNDLE_CHANNEL_DIR);
+
+    // Store the package archives and index them per subdirectory.
+    let mut subdirs: BTreeMap<String, RepoData> = BTreeMap::new();
+    subdirs.insert(
+        Platform::NoArch.as_str().to_string(),
+        empty_repo_data(Platform::NoArch.as_str()),
+    );
+    for record in &records {
+        let subdir = &record.package_record.subdir;
+        let archive_path = channel_dir.join(subdir).join(&record.file_name);
+        fetch_archive(client, record, &archive_path).await?;
+
+        let repo_data = subdirs
+            .entry(subdir.clone())
+            .or_insert_with(|| empty_repo_data(subdir));
+        let packages = match ArchiveType::try_from(&record.file_name) {
+            Some(ArchiveType::Conda) => &mut repo_data.conda_packages,
+            _ => &mut repo_data.packages,
+        };
+        packages.insert(record.file_name.clone(), record.package_record.clone());
+    }
+    for (subdir, repo_data) in &subdirs {
+        let subdir_dir = channel_dir.join(subdir);
+        fs_err::create_dir_all(&subdir_dir)
+            .map_err(|e| BundleError::IoError(format!("failed to create {subdir}"), e))?;
+        let contents = serde_json::to_vec(repo_data)
+            .map_err(|e| BundleError::IoError("failed to serialize repodata".into(), e.into()))?;
+        fs_err::write(subdir_dir.join("repodata.json"), contents)
+            .map_err(|e| BundleError::IoError("failed to write repodata".into(), e))?;
+    }
+
+    // Write a lock file that only contains the bundled environment.
+    let mut builder = LockFile::builder();
+    builder.set_channels(environment_name, environment.channels().iter().cloned());
+    for record in records {
+        builder.add_conda_package(environment_name, platform, record.into());
+    }
+    builder
+        .finish()
+        .to_path(&staging_dir.path().join(BUNDLE_LOCK_FILE_NAME))
+        .map_err(|e| BundleError::IoError("failed to write the lock file".into(), e))?;
+
+    // Pack everything into a single archive.
+    let destination = destination.to_path_buf();
+    run_blocking_task(move || {
+        let file = fs_err::File::create(&destination).map_err(|e| {
+            BundleError::IoError(format!("failed to create {}", destination.display()), e)
+        })?;
+        let mut archive = tar::Builder::new(file);
+        archive
+            .append_dir_all(".", staging_dir.path())
+            .and_then(|()| archive.into_inner())
+            .and_then(|file| file.sync_all())
+            .map_err(|e| BundleError::IoError("failed to write the bundle".into(), e))
+    })
+    .await
+}
+
+/// Recreates the environment of a bundle in `prefix` without accessing the
+/// network.
+///
+/// The packages of the bundle are added to the `package_cache` after which
+/// the environment is installed with the `installer` using the same cache.
+/// The records of the installed packages refer to the original locations of
+/// the packages, not to the bundle.
+pub async fn import_bundle(
+    bundle: &Path,
+    prefix: &Path,
+    package_cache: PackageCache,
+    installer: Installer,
+) -> Result<InstallationResult, BundleError> {
+    let extract_dir = tempfile::tempdir()
+        .map_err(|e| BundleError::IoError("failed to create a temporary directory".into(), e))?;
+    let bundle_path = bundle.to_path_buf();
+    let extract_path = extract_dir.path().to_path_buf();
+    run_blocking_task(move || {
+        let file = fs_err::File::open(&bundle_path).map_err(|e| {
+            BundleError::IoError(format!("failed to open {}", bundle_path.display()), e)
+        })?;
+        tar::Archive::new(file)
+            .unpack(&extract_path)
+            .map_err(|e| BundleError::IoError("failed to extract the bundle".into(), e))
+    })
+    .await?;
+
+    let lock_file = LockFile::from_path(&extract_dir.path().join(BUNDLE_LOCK_FILE_NAME))
+        .map_err(BundleError::LockFileError)?;
+    let (platform, records) = bundled_records(&lock_file)?;
+
+    // Populate the package cache with the bundled archives so the installer
+    // does not have to fetch them.
+    let client = reqwest_middleware::ClientWithMiddleware::from(reqwest::Client::new());
+    for record in &records {
+        let archive_path = extract_dir
+            .path()
+            .join(BUNDLE_CHANNEL_DIR)
+            .join(&record.package_record.subdir)
+            .join(&record.file_name);
+        let url = Url::from_file_path(&archive_path).map_err(|()| {
+            BundleError::IoError(
+                format!("invalid path {}", archive_path.display()),
+                io::ErrorKind::InvalidInput.into(),
+            )
+        })?;
+        package_cache
+            .get_or_fetch_from_url(&record.package_record, url, client.clone(), None)
+            .await
+            .map_err(|e| BundleError::PackageCacheError(record.file_name.clone(), e))?;
+    }
+
+    Ok(installer
+        .with_package_cache(package_cache)
+        .with_target_platform(platform)
+        .install(prefix, records)
+        .await?)
+}
+
+/// Returns the platform and the records of the single environment in the lock
+/// file of a bundle.
+fn bundled_records(lock_file: &LockFile) -> Result<(Platform, Vec<RepoDataRecord>), BundleError> {
+    let mut environments = lock_file.environments();
+    let (Some((_, environment)), None) = (environments.next(), environments.next()) else {
+        return Err(BundleError::InvalidBundle);
+    };
+    let mut records = environment.conda_repodata_records()?.into_iter();
+    match (records.next(), records.next()) {
+        (Some(records), None) => Ok(records),
+        _ => Err(BundleError::InvalidBundle),
+    }
+}
+
+fn empty_repo_data(subdir: &str) -> RepoData {
+    RepoData {
+        info: Some(ChannelInfo {
+            subdir: subdir.to_string(),
+            base_url: None,
+        }),
+        packages: FxHashMap::default(),
+        conda_packages: FxHashMap::default(),
+        removed: FxHashSet::default(),
+        version: Some(2),
+    }
+}
+
+/// Downloads or copies the archive of a record to `destination` and verifies
+/// its hash.
+async fn fetch_archive(
+    client: &reqwest_middleware::ClientWithMiddleware,
+    record: &RepoDataRecord,
+    destination: &Path,
+) -> Result<(), BundleError> {
+    let io_error = |e| BundleError::IoError(format!("failed to write {}", record.file_name), e);
+    if let Some(dir) = destination.parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(io_error)?;
+    }
+
+    if record.url.scheme() == "file" {
+        let source = record
+            .url
+            .to_file_path()
+            .map_err(|()| io_error(io::ErrorKind::InvalidInput.into()))?;
+        tokio::fs::copy(source, destination)
+            .await
+            .map_err(io_error)?;
+    } else {
+        let download_error = |e| BundleError::DownloadError(record.file_name.clone(), e);
+        let mut response = client
+            .get(record.url.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status().map_err(Into::into))
+            .map_err(download_error)?;
+        let mut file = tokio::fs::File::create(destination)
+            .await
+            .map_err(io_error)?;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| download_error(e.into()))?
+        {
+            file.write_all(&chunk).await.map_err(io_error)?;
+        }
+        file.flush().await.map_err(io_error)?;
+    }
+
+    if let Some(expected) = record.package_record.sha256 {
+        let path = destination.to_path_buf();
+        let file_name = record.file_name.clone();
+        let actual = run_blocking_task(move || {
+            rattler_digest::compute_file_digest::<Sha256>(&path)
+                .map_err(|e| BundleError::IoError(format!("failed to read {file_name}"), e))
+        })
+        .await?;
+        if actual != expected {
+            return Err(BundleError::HashMismatch(record.file_name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use rattler_conda_types::{Platform, PrefixRecord};
+    use rattler_lock::{LockFile, DEFAULT_ENVIRONMENT_NAME};
+
+    use super::{export_bundle, import_bundle, BUNDLE_CHANNEL_DIR};
+    use crate::{
+        get_repodata_record, get_test_data_dir, install::Installer, package_cache::PackageCache,
+    };
+
+    #[tokio::test]
+    async fn test_export_import_bundle() {
+        // Copy the packages to a separate directory that is removed after the
+        // export to make sure the import only uses the bundle.
+        let source_dir = tempfile::tempdir().unwrap();
+        let mut records = Vec::new();
+        for file_name in [
+            "clobber-1-0.1.0-h4616a5c_0.tar.bz2",
+            "clobber-2-0.1.0-h4616a5c_0.tar.bz2",
+        ] {
+            let path = source_dir.path().join(file_name);
+            std::fs::copy(get_test_data_dir().join("clobber").join(file_name), &path).unwrap();
+            records.push(get_repodata_record(&path));
+        }
+
+        let platform = Platform::current();
+        let mut builder = LockFile::builder();
+        for record in records.clone() {
+            builder.add_conda_package(DEFAULT_ENVIRONMENT_NAME, platform, record.into());
+        }
+        let lock_file = builder.finish();
+
+        let bundle_dir = tempfile::tempdir().unwrap();
+        let bundle_path = bundle_dir.path().join("bundle.tar");
+        let client = reqwest_middleware::ClientWithMiddleware::from(reqwest::Client::new());
+        export_bundle(
+            &lock_file,
+            DEFAULT_ENVIRONMENT_NAME,
+            platform,
+            &client,
+            &bundle_path,
+        )
+        .await
+        .unwrap();
+        drop(source_dir);
+
+        // The bundle contains a channel with the packages.
+        let mut archive = tar::Archive::new(std::fs::File::open(&bundle_path).unwrap());
+        let entries = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        let subdir = &records[0].package_record.subdir;
+        for file_name in [records[0].file_name.as_str(), "repodata.json"] {
+            let path = format!("{BUNDLE_CHANNEL_DIR}/{subdir}/{file_name}");
+            assert!(
+                entries.iter().any(|entry| entry.ends_with(&path)),
+                "{path} is missing from {entries:?}"
+            );
+        }
+
+        let prefix = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        import_bundle(
+            &bundle_path,
+            prefix.path(),
+            PackageCache::new(cache_dir.path()),
+            Installer::new(),
+        )
+        .await
+        .unwrap();
+
+        let mut installed = PrefixRecord::collect_from_prefix(prefix.path())
+            .unwrap()
+            .into_iter()
+            .map(|record| record.repodata_record.url)
+            .collect::<Vec<_>>();
+        installed.sort();
+        let mut expected = records.into_iter().map(|r| r.url).collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(installed, expected);
+    }
+}
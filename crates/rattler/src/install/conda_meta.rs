@@ -0,0 +1,289 @@
+//! Transactional writes of the prefix records in the `conda-meta` directory.
+//!
+//! Prefix records that are created during a transaction are first written to
+//! a staging directory inside `conda-meta`, records that are removed are
+//! recorded there as well. When the transaction is committed a marker file is
+//! written to the staging directory after which the removed records are
+//! deleted and the new records are moved into `conda-meta` one by one. Each
+//! move is an atomic rename, so a record is never observed half-written.
+//!
+//! If the process is interrupted, a staging directory is left behind. The next
+//! transaction in the same prefix finishes the commit if the marker was
+//! written, otherwise the staged records are discarded.
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use rattler_conda_types::PrefixRecord;
+
+/// The prefix of the names of the staging directories inside `conda-meta`.
+pub const CONDA_META_STAGING_PREFIX: &str = ".staging-";
+
+/// The name of the file that marks a staging directory as committed.
+const COMMIT_MARKER: &str = ".committed";
+
+/// The extension of the files in a staging directory that mark a record in
+/// `conda-meta` for removal.
+const REMOVAL_EXTENSION: &str = ".remove";
+
+/// Prefix records that are staged to be written to `conda-meta`.
+#[derive(Debug, Clone)]
+pub(crate) struct CondaMetaTransaction {
+    conda_meta_dir: PathBuf,
+    staging_dir: PathBuf,
+}
+
+impl CondaMetaTransaction {
+    /// Starts a new transaction in the given prefix. Staging directories that
+    /// were left behind by interrupted transactions should be recovered
+    /// beforehand with [`recover_conda_meta`].
+    pub fn begin(prefix: &Path) -> io::Result<Self> {
+        let conda_meta_dir = prefix.join("conda-meta");
+        fs_err::create_dir_all(&conda_meta_dir)?;
+        let staging_dir = tempfile::Builder::new()
+            .prefix(CONDA_META_STAGING_PREFIX)
+            .tempdir_in(&conda_meta_dir)?
+            .into_path();
+
+        Ok(Self {
+            conda_meta_dir,
+            staging_dir,
+        })
+    }
+
+    /// Writes a prefix record to the staging directory. The record becomes
+    /// visible in `conda-meta` when the transaction is committed.
+    pub fn stage(&self, record: &PrefixRecord) -> io::Result<()> {
+        write_synced(&self.staging_dir.join(record.file_name()), record)
+    }
+
+    /// Marks a prefix record for removal. The record is removed from
+    /// `conda-meta` when the transaction is committed.
+    pub fn stage_removal(&self, record: &PrefixRecord) -> io::Result<()> {
+        let marker = format!("{}{REMOVAL_EXTENSION}", record.file_name());
+        File::create(self.staging_dir.join(marker))?.sync_all()
+    }
+
+    /// Removes the records that are marked for removal and moves all staged
+    /// records into `conda-meta`.
+    pub fn commit(self) -> io::Result<()> {
+        File::create(self.staging_dir.join(COMMIT_MARKER))?.sync_all()?;
+        finish_commit(&self.staging_dir, &self.conda_meta_dir)
+    }
+}
+
+/// Writes the prefix record to `path` atomically by first writing it to a
+/// temporary file in the same directory and renaming it afterwards.
+pub(crate) fn write_prefix_record_atomically(path: &Path, record: &PrefixRecord) -> io::Result<()> {
+    let directory = path.parent().unwrap_or_else(|| Path::new("."));
+    let temp_file = tempfile::Builder::new()
+        .prefix(CONDA_META_STAGING_PREFIX)
+        .tempfile_in(directory)?;
+    write_synced(temp_file.path(), record)?;
+    temp_file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Writes the record and makes sure it is flushed to disk.
+fn write_synced(path: &Path, record: &PrefixRecord) -> io::Result<()> {
+    let file = File::create(path)?;
+    record.write_to(&file, true)?;
+    file.sync_all()
+}
+
+/// Removes the records that are marked for removal, moves the staged records
+/// into `conda-meta` and removes the staging directory.
+///
+/// All removals are finished before the first record is moved, a record that
+/// replaces a removed record with the same file name is therefore never
+/// removed when an interrupted commit is finished.
+fn finish_commit(staging_dir: &Path, conda_meta_dir: &Path) -> io::Result<()> {
+    for entry in fs_err::read_dir(staging_dir)? {
+        let entry = entry?;
+        let marker_name = entry.file_name().to_string_lossy().into_owned();
+        let Some(file_name) = marker_name.strip_suffix(REMOVAL_EXTENSION) else {
+            continue;
+        };
+        match fs_err::remove_file(conda_meta_dir.join(file_name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        fs_err::remove_file(entry.path())?;
+    }
+
+    for entry in fs_err::read_dir(staging_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if file_name.to_string_lossy().ends_with(".json") {
+            fs_err::rename(entry.path(), conda_meta_dir.join(file_name))?;
+        }
+    }
+    fs_err::remove_dir_all(staging_dir)?;
+
+    // The index only speeds up reading the records, an outdated index is
+    // still valid so failing to update it does not fail the commit.
+    if let Some(prefix) = conda_meta_dir.parent() {
+        if let Err(e) = PrefixRecord::write_prefix_index(prefix) {
+            tracing::warn!(
+                "failed to update the prefix index of {}: {e}",
+                prefix.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Recovers the staging directories that were left behind in the
+/// `conda-meta` directory of the prefix by interrupted transactions.
+///
+/// Transactions that were interrupted while they were being committed are
+/// finished, the staged records of all other transactions are discarded. Note
+/// that this must not be called while another process is modifying the same
+/// prefix.
+pub fn recover_conda_meta(prefix: &Path) -> io::Result<()> {
+    let conda_meta_dir = prefix.join("conda-meta");
+    let read_dir = match fs_err::read_dir(&conda_meta_dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for entry in read_dir {
+        let entry = entry?;
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(CONDA_META_STAGING_PREFIX)
+        {
+            continue;
+        }
+
+        let path = entry.path();
+        if !entry.file_type()?.is_dir() {
+            // A temporary file of an interrupted atomic write.
+            fs_err::remove_file(&path)?;
+        } else if path.join(COMMIT_MARKER).is_file() {
+            tracing::warn!(
+                "finishing an interrupted transaction in {}",
+                prefix.display()
+            );
+            finish_commit(&path, &conda_meta_dir)?;
+        } else {
+            tracing::warn!(
+                "discarding the prefix records of an interrupted transaction in {}",
+                prefix.display()
+            );
+            fs_err::remove_dir_all(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use rattler_conda_types::{
+        PackageName, PackageRecord, PrefixRecord, RepoDataRecord, Version, PREFIX_INDEX_FILE_NAME,
+    };
+    use url::Url;
+
+    use super::{recover_conda_meta, CondaMetaTransaction, COMMIT_MARKER};
+
+    fn prefix_record(name: &str) -> PrefixRecord {
+        let repodata_record = RepoDataRecord {
+            package_record: PackageRecord::new(
+                PackageName::new_unchecked(name),
+                Version::from_str("1.0").unwrap(),
+                String::from("0"),
+            ),
+            file_name: format!("{name}-1.0-0.conda"),
+            url: Url::parse(&format!("https://example.com/noarch/{name}-1.0-0.conda")).unwrap(),
+            channel: String::from("https://example.com"),
+        };
+        PrefixRecord::from_repodata_record(repodata_record, None, None, vec![], None, None)
+    }
+
+    #[test]
+    fn test_conda_meta_transaction() {
+        let prefix = tempfile::tempdir().unwrap();
+        let installed = |prefix: &std::path::Path| {
+            let mut names = PrefixRecord::collect_from_prefix(prefix)
+                .unwrap()
+                .into_iter()
+                .map(|record| record.repodata_record.package_record.name)
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        // Staged records are only visible after the commit.
+        let transaction = CondaMetaTransaction::begin(prefix.path()).unwrap();
+        transaction.stage(&prefix_record("foo")).unwrap();
+        assert!(installed(prefix.path()).is_empty());
+        transaction.commit().unwrap();
+        assert_eq!(
+            installed(prefix.path()),
+            [PackageName::new_unchecked("foo")]
+        );
+        assert!(prefix
+            .path()
+            .join("conda-meta")
+            .join(PREFIX_INDEX_FILE_NAME)
+            .is_file());
+
+        // An interrupted transaction that was not committed is discarded.
+        let transaction = CondaMetaTransaction::begin(prefix.path()).unwrap();
+        transaction.stage(&prefix_record("bar")).unwrap();
+        let staging_dir = transaction.staging_dir.clone();
+        recover_conda_meta(prefix.path()).unwrap();
+        assert!(!staging_dir.exists());
+        assert_eq!(
+            installed(prefix.path()),
+            [PackageName::new_unchecked("foo")]
+        );
+
+        // Removed records are only removed after the commit.
+        let transaction = CondaMetaTransaction::begin(prefix.path()).unwrap();
+        transaction.stage_removal(&prefix_record("foo")).unwrap();
+        transaction.stage(&prefix_record("qux")).unwrap();
+        assert_eq!(
+            installed(prefix.path()),
+            [PackageName::new_unchecked("foo")]
+        );
+        transaction.commit().unwrap();
+        assert_eq!(
+            installed(prefix.path()),
+            [PackageName::new_unchecked("qux")]
+        );
+
+        // A record that is removed and staged again is kept.
+        let transaction = CondaMetaTransaction::begin(prefix.path()).unwrap();
+        transaction.stage_removal(&prefix_record("qux")).unwrap();
+        transaction.stage(&prefix_record("qux")).unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(
+            installed(prefix.path()),
+            [PackageName::new_unchecked("qux")]
+        );
+
+        // An interrupted commit is finished.
+        let transaction = CondaMetaTransaction::begin(prefix.path()).unwrap();
+        transaction.stage(&prefix_record("baz")).unwrap();
+        std::fs::write(transaction.staging_dir.join(COMMIT_MARKER), "").unwrap();
+        let staging_dir = transaction.staging_dir.clone();
+        recover_conda_meta(prefix.path()).unwrap();
+        assert!(!staging_dir.exists());
+        assert_eq!(
+            installed(prefix.path()),
+            [
+                PackageName::new_unchecked("baz"),
+                PackageName::new_unchecked("qux")
+            ]
+        );
+    }
+}
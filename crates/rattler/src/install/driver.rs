@@ -1,22 +1,29 @@
 use std::{
     borrow::Borrow,
     collections::{HashMap, HashSet},
+    fmt::{Debug, Formatter},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     sync::{Arc, Mutex, MutexGuard},
 };
 
 use indexmap::IndexSet;
 use itertools::Itertools;
-use rattler_conda_types::{prefix_record::PathType, PackageRecord, PrefixRecord};
+use rattler_conda_types::{
+    prefix_record::{PathType, PathsEntry},
+    PackageRecord, Platform, PrefixRecord,
+};
 use simple_spawn_blocking::{tokio::run_blocking_task, Cancelled};
 use thiserror::Error;
 use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
 
 use super::{
     clobber_registry::{ClobberError, ClobberRegistry, ClobberedPath},
+    conda_meta::write_prefix_record_atomically,
+    frozen::{FrozenMarker, FrozenPrefixError},
     link_script::{PrePostLinkError, PrePostLinkResult},
     unlink::{recursively_remove_empty_directories, UnlinkError},
-    Transaction,
+    PycCompilationResult, PycCompiler, Reporter, Transaction,
 };
 use crate::install::link_script::LinkScriptError;
 
@@ -31,6 +38,10 @@ pub struct InstallDriver {
     io_concurrency_semaphore: Option<Arc<Semaphore>>,
     clobber_registry: Arc<Mutex<ClobberRegistry>>,
     execute_link_scripts: bool,
+    override_frozen_prefix: bool,
+    compile_pyc: bool,
+    pyc_concurrency_limit: Option<NonZeroUsize>,
+    reporter: Option<Arc<dyn Reporter>>,
 }
 
 impl Default for InstallDriver {
@@ -43,11 +54,28 @@ impl Default for InstallDriver {
 }
 
 /// A builder to configure a new `InstallDriver`.
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct InstallDriverBuilder {
     io_concurrency_semaphore: Option<Arc<Semaphore>>,
     clobber_registry: Option<ClobberRegistry>,
     execute_link_scripts: bool,
+    override_frozen_prefix: bool,
+    compile_pyc: bool,
+    pyc_concurrency_limit: Option<NonZeroUsize>,
+    reporter: Option<Arc<dyn Reporter>>,
+}
+
+impl Debug for InstallDriverBuilder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstallDriverBuilder")
+            .field("io_concurrency_semaphore", &self.io_concurrency_semaphore)
+            .field("clobber_registry", &self.clobber_registry)
+            .field("execute_link_scripts", &self.execute_link_scripts)
+            .field("override_frozen_prefix", &self.override_frozen_prefix)
+            .field("compile_pyc", &self.compile_pyc)
+            .field("pyc_concurrency_limit", &self.pyc_concurrency_limit)
+            .finish_non_exhaustive()
+    }
 }
 
 /// The result of the post-processing step.
@@ -59,6 +87,11 @@ pub struct PostProcessResult {
 
     /// The paths that were clobbered during the installation process.
     pub clobbered_paths: HashMap<PathBuf, ClobberedPath>,
+
+    /// The result of compiling the python files of the installed noarch
+    /// python packages. This is only present if compilation is enabled and
+    /// was possible for the transaction.
+    pub pyc_compilation_result: Option<PycCompilationResult>,
 }
 
 /// An error that might have occurred during post-processing
@@ -70,6 +103,11 @@ pub enum PostProcessingError {
     /// Failed to determine the currently installed packages.
     #[error("failed to determine the installed packages")]
     FailedToDetectInstalledPackages(#[source] std::io::Error),
+
+    /// Failed to record the compiled python files in the prefix record of a
+    /// package.
+    #[error("failed to write {0}")]
+    FailedToWritePrefixRecord(String, #[source] std::io::Error),
 }
 
 impl InstallDriverBuilder {
@@ -113,6 +151,49 @@ impl InstallDriverBuilder {
         }
     }
 
+    /// Sets whether transactions are allowed to modify a prefix that is marked
+    /// as frozen. By default a frozen prefix is never modified. See
+    /// [`FrozenMarker`].
+    pub fn with_override_frozen_prefix(self, override_frozen_prefix: bool) -> Self {
+        Self {
+            override_frozen_prefix,
+            ..self
+        }
+    }
+
+    /// Sets whether the python files of noarch python packages should be
+    /// compiled to bytecode after they have been linked. The generated files
+    /// are recorded in the prefix records of the packages so that they are
+    /// removed when the package is uninstalled.
+    ///
+    /// Compilation uses the python interpreter of the target environment and
+    /// is therefore only performed when installing for the current platform.
+    pub fn with_compile_pyc(self, compile_pyc: bool) -> Self {
+        Self {
+            compile_pyc,
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of python interpreters that are used
+    /// concurrently to compile python files. Defaults to the number of
+    /// available cpus.
+    pub fn with_pyc_concurrency_limit(self, limit: NonZeroUsize) -> Self {
+        Self {
+            pyc_concurrency_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Sets the reporter that is notified of the progress of the
+    /// post-processing steps, like the resolution of clobbered files.
+    pub fn with_reporter(self, reporter: Arc<dyn Reporter>) -> Self {
+        Self {
+            reporter: Some(reporter),
+            ..self
+        }
+    }
+
     pub fn finish(self) -> InstallDriver {
         InstallDriver {
             io_concurrency_semaphore: self.io_concurrency_semaphore,
@@ -122,6 +203,10 @@ impl InstallDriverBuilder {
                 .map(Arc::new)
                 .unwrap_or_default(),
             execute_link_scripts: self.execute_link_scripts,
+            override_frozen_prefix: self.override_frozen_prefix,
+            compile_pyc: self.compile_pyc,
+            pyc_concurrency_limit: self.pyc_concurrency_limit,
+            reporter: self.reporter,
         }
     }
 }
@@ -148,13 +233,47 @@ impl InstallDriver {
         self.clobber_registry.lock().unwrap()
     }
 
+    /// Returns an error if the transaction would modify a prefix that is
+    /// marked as frozen, unless the driver was configured to override the
+    /// marker. Transactions without any operations do not modify the prefix
+    /// and are always allowed.
+    ///
+    /// This is also enforced by [`Self::pre_process`], call this to reject a
+    /// transaction before doing any other work.
+    pub fn check_frozen_prefix<Old, New>(
+        &self,
+        transaction: &Transaction<Old, New>,
+        target_prefix: &Path,
+    ) -> Result<(), FrozenPrefixError> {
+        if self.override_frozen_prefix || transaction.operations.is_empty() {
+            return Ok(());
+        }
+
+        match FrozenMarker::from_prefix(target_prefix) {
+            Ok(None) => Ok(()),
+            Ok(Some(marker)) => Err(FrozenPrefixError::Frozen {
+                prefix: target_prefix.to_path_buf(),
+                marker,
+            }),
+            Err(err) => Err(FrozenPrefixError::FailedToReadMarker(
+                target_prefix.to_path_buf(),
+                err,
+            )),
+        }
+    }
+
     /// Call this before any packages are installed to perform any pre
     /// processing that is required.
+    ///
+    /// Returns an error if the prefix is frozen, see
+    /// [`Self::check_frozen_prefix`].
     pub fn pre_process<Old: Borrow<PrefixRecord>, New>(
         &self,
         transaction: &Transaction<Old, New>,
         target_prefix: &Path,
     ) -> Result<Option<PrePostLinkResult>, PrePostLinkError> {
+        self.check_frozen_prefix(transaction, target_prefix)?;
+
         if self.execute_link_scripts {
             match self.run_pre_unlink_scripts(transaction, target_prefix) {
                 Ok(res) => {
@@ -193,17 +312,21 @@ impl InstallDriver {
     /// Call this after all packages have been installed to perform any post
     /// processing that is required.
     ///
-    /// This function will select a winner among multiple packages that might
-    /// write to a single package and will also execute any
-    /// `post-link.sh/bat` scripts
+    /// This function will compile the python files of noarch python packages
+    /// if enabled, select a winner among multiple packages that might write
+    /// to a single package and will also execute any `post-link.sh/bat`
+    /// scripts
     pub fn post_process<Old: Borrow<PrefixRecord> + AsRef<New>, New: AsRef<PackageRecord>>(
         &self,
         transaction: &Transaction<Old, New>,
         target_prefix: &Path,
     ) -> Result<PostProcessResult, PostProcessingError> {
-        let prefix_records = PrefixRecord::collect_from_prefix(target_prefix)
+        let mut prefix_records = PrefixRecord::collect_from_prefix_with_index(target_prefix)
             .map_err(PostProcessingError::FailedToDetectInstalledPackages)?;
 
+        let pyc_compilation_result =
+            self.compile_pyc_files(transaction, &mut prefix_records, target_prefix)?;
+
         let required_packages =
             PackageRecord::sort_topologically(prefix_records.iter().collect::<Vec<_>>());
 
@@ -212,9 +335,15 @@ impl InstallDriver {
                 tracing::warn!("Failed to remove empty directories: {} (ignored)", e);
             });
 
+        if let Some(reporter) = &self.reporter {
+            reporter.on_clobber_resolution_start();
+        }
         let clobbered_paths = self
             .clobber_registry()
             .unclobber(&required_packages, target_prefix)?;
+        if let Some(reporter) = &self.reporter {
+            reporter.on_clobber_resolution_complete(&clobbered_paths);
+        }
 
         let post_link_result = if self.execute_link_scripts {
             Some(self.run_post_link_scripts(transaction, &required_packages, target_prefix))
@@ -225,9 +354,117 @@ impl InstallDriver {
         Ok(PostProcessResult {
             post_link_result,
             clobbered_paths,
+            pyc_compilation_result,
         })
     }
 
+    /// Compiles the python files of the noarch python packages that were
+    /// installed by the transaction and adds the generated bytecode files to
+    /// the prefix records of these packages.
+    ///
+    /// Files that fail to compile are reported in the result but do not cause
+    /// this function to fail.
+    fn compile_pyc_files<Old, New: AsRef<PackageRecord>>(
+        &self,
+        transaction: &Transaction<Old, New>,
+        prefix_records: &mut [PrefixRecord],
+        target_prefix: &Path,
+    ) -> Result<Option<PycCompilationResult>, PostProcessingError> {
+        let python_info = match &transaction.python_info {
+            Some(python_info)
+                if self.compile_pyc && transaction.platform == Platform::current() =>
+            {
+                python_info
+            }
+            _ => return Ok(None),
+        };
+
+        let installed_noarch_python = transaction
+            .installed_packages()
+            .map(AsRef::<PackageRecord>::as_ref)
+            .filter(|record| record.noarch.is_python())
+            .map(|record| &record.name)
+            .collect::<HashSet<_>>();
+        let mut records = prefix_records
+            .iter_mut()
+            .filter(|record| {
+                installed_noarch_python.contains(&record.repodata_record.package_record.name)
+            })
+            .collect::<Vec<_>>();
+
+        let python_files = records
+            .iter()
+            .flat_map(|record| record.paths_data.paths.iter())
+            .filter(|entry| {
+                entry
+                    .relative_path
+                    .extension()
+                    .map_or(false, |ext| ext == "py")
+            })
+            .map(|entry| target_prefix.join(&entry.relative_path))
+            .collect::<Vec<_>>();
+        if python_files.is_empty() {
+            return Ok(None);
+        }
+
+        let mut compiler = PycCompiler::new(target_prefix.join(python_info.path()));
+        if let Some(limit) = self.pyc_concurrency_limit {
+            compiler = compiler.with_max_workers(limit);
+        }
+        let result = compiler.compile(python_files);
+        for failure in &result.failed {
+            tracing::warn!(
+                "failed to compile '{}': {}",
+                failure.path.display(),
+                failure.reason
+            );
+        }
+
+        // Record the bytecode files so they are removed when the package is
+        // uninstalled.
+        let conda_meta_path = target_prefix.join("conda-meta");
+        for record in &mut records {
+            let bytecode_entries = record
+                .paths_data
+                .paths
+                .iter()
+                .filter_map(|entry| {
+                    let bytecode_path = result
+                        .bytecode_paths
+                        .get(&target_prefix.join(&entry.relative_path))?;
+                    let relative_path = bytecode_path.strip_prefix(target_prefix).ok()?;
+                    Some(PathsEntry {
+                        relative_path: relative_path.to_path_buf(),
+                        original_path: None,
+                        path_type: PathType::PycFile,
+                        no_link: false,
+                        sha256: None,
+                        sha256_in_prefix: None,
+                        size_in_bytes: None,
+                        file_mode: None,
+                        prefix_placeholder: None,
+                    })
+                })
+                .collect::<Vec<_>>();
+            if bytecode_entries.is_empty() {
+                continue;
+            }
+
+            record.files.extend(
+                bytecode_entries
+                    .iter()
+                    .map(|entry| entry.relative_path.clone()),
+            );
+            record.paths_data.paths.extend(bytecode_entries);
+
+            let file_name = record.file_name();
+            write_prefix_record_atomically(&conda_meta_path.join(&file_name), record)
+                .map_err(|e| PostProcessingError::FailedToWritePrefixRecord(file_name, e))?;
+        }
+
+        Ok(Some(result))
+    }
+
     /// Remove all empty directories that are not part of the new prefix
     /// records.
     pub fn remove_empty_directories<Old: Borrow<PrefixRecord>, New>(
@@ -9,13 +9,12 @@ use rattler_digest::HashingWriter;
 use rattler_digest::Sha256;
 use std::{fs::File, io, io::Write, path::Path};
 
-/// Get the bytes of the windows launcher executable.
-pub fn get_windows_launcher(platform: &Platform) -> &'static [u8] {
+/// Get the bytes of the windows launcher executable. Returns `None` if there is no launcher
+/// available for the platform.
+pub fn get_windows_launcher(platform: &Platform) -> Option<&'static [u8]> {
     match platform {
-        Platform::Win32 => unimplemented!("32 bit windows is not supported for entry points"),
-        Platform::Win64 => include_bytes!("../../resources/launcher64.exe"),
-        Platform::WinArm64 => unimplemented!("arm64 windows is not supported for entry points"),
-        _ => panic!("unsupported platform"),
+        Platform::Win64 => Some(include_bytes!("../../resources/launcher64.exe")),
+        _ => None,
     }
 }
 
@@ -32,6 +31,9 @@ pub fn get_windows_launcher(platform: &Platform) -> &'static [u8] {
 /// The special executable is embedded in the library. The source code for the launcher can be found
 /// here: <https://github.com/conda/conda-build/tree/master/conda_build/launcher_sources>.
 ///
+/// Returns an error of kind [`io::ErrorKind::Unsupported`] if there is no launcher available for
+/// the target platform, see [`get_windows_launcher`].
+///
 /// See [`create_unix_python_entry_point`] for the unix variant of this function.
 pub fn create_windows_python_entry_point(
     target_dir: &Path,
@@ -40,6 +42,13 @@ pub fn create_windows_python_entry_point(
     python_info: &PythonInfo,
     target_platform: &Platform,
 ) -> Result<[PathsEntry; 2], std::io::Error> {
+    let launcher_bytes = get_windows_launcher(target_platform).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("python entry points are not supported on {target_platform}"),
+        )
+    })?;
+
     // Construct the path to where we will be creating the python entry point script.
     let relative_path_script_py = python_info
         .bin_dir
@@ -61,8 +70,7 @@ pub fn create_windows_python_entry_point(
         .bin_dir
         .join(format!("{}.exe", &entry_point.command));
 
-    // Include the bytes of the launcher directly in the binary so we can write it to disk.
-    let launcher_bytes = get_windows_launcher(target_platform);
+    // The bytes of the launcher are included directly in the binary so we can write it to disk.
     std::fs::write(target_dir.join(&relative_path_script_exe), launcher_bytes)?;
 
     let fixed_launcher_digest = rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>(
@@ -196,9 +204,54 @@ fn write_and_hash(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<(Output
 mod test {
     use crate::install::PythonInfo;
     use rattler_conda_types::package::EntryPoint;
+    use rattler_conda_types::prefix_record::PathType;
     use rattler_conda_types::{Platform, Version};
     use std::str::FromStr;
 
+    #[test]
+    fn test_create_entry_points() {
+        let target_dir = tempfile::tempdir().unwrap();
+        let entry_point = EntryPoint::from_str("jupyter-lab = jupyterlab.labapp:main").unwrap();
+        let version = Version::from_str("3.11.0").unwrap();
+
+        let python_info = PythonInfo::from_version(&version, Platform::Linux64).unwrap();
+        let entry = super::create_unix_python_entry_point(
+            target_dir.path(),
+            "/prefix",
+            &entry_point,
+            &python_info,
+        )
+        .unwrap();
+        assert_eq!(entry.path_type, PathType::UnixPythonEntryPoint);
+        assert_eq!(entry.relative_path, std::path::Path::new("bin/jupyter-lab"));
+        assert!(target_dir.path().join(&entry.relative_path).is_file());
+
+        let python_info = PythonInfo::from_version(&version, Platform::Win64).unwrap();
+        let [script, exe] = super::create_windows_python_entry_point(
+            target_dir.path(),
+            "C:\\prefix",
+            &entry_point,
+            &python_info,
+            &Platform::Win64,
+        )
+        .unwrap();
+        assert_eq!(script.path_type, PathType::WindowsPythonEntryPointScript);
+        assert_eq!(exe.path_type, PathType::WindowsPythonEntryPointExe);
+        assert!(target_dir.path().join(&script.relative_path).is_file());
+        assert!(target_dir.path().join(&exe.relative_path).is_file());
+
+        // Platforms without a launcher result in an error instead of a panic.
+        let err = super::create_windows_python_entry_point(
+            target_dir.path(),
+            "C:\\prefix",
+            &entry_point,
+            &python_info,
+            &Platform::WinArm64,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    }
+
     #[test]
     fn test_entry_point_script() {
         let script = super::python_entry_point_template(
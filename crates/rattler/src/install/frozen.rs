@@ -0,0 +1,199 @@
+//! Support for frozen prefixes.
+//!
+//! A prefix can be marked as frozen by placing a `conda-meta/frozen` file in
+//! it. The [`super::InstallDriver`] refuses to modify a frozen prefix unless
+//! it is explicitly told to override the marker. This can be used to protect
+//! environments that should not be modified by accident.
+//!
+//! The marker file is either empty or contains a JSON object with an optional
+//! `message` that explains why the prefix is frozen.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The path of the marker file relative to the prefix.
+pub const FROZEN_MARKER_PATH: &str = "conda-meta/frozen";
+
+/// The contents of the `conda-meta/frozen` marker file of a frozen prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrozenMarker {
+    /// An optional message that explains why the prefix is frozen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl FrozenMarker {
+    /// Constructs a new marker with the given message.
+    pub fn with_message(message: impl Into<String>) -> Self {
+        Self {
+            message: Some(message.into()),
+        }
+    }
+
+    /// Reads the marker from the given prefix. Returns `None` if the prefix is
+    /// not frozen.
+    ///
+    /// A marker file that is empty or that cannot be parsed still marks the
+    /// prefix as frozen, it just doesn't carry a message.
+    pub fn from_prefix(prefix: &Path) -> Result<Option<Self>, std::io::Error> {
+        let contents = match fs_err::read_to_string(prefix.join(FROZEN_MARKER_PATH)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        if contents.trim().is_empty() {
+            return Ok(Some(Self::default()));
+        }
+
+        Ok(Some(serde_json::from_str(&contents).unwrap_or_else(|e| {
+            tracing::warn!(
+                "failed to parse {}: {e}",
+                prefix.join(FROZEN_MARKER_PATH).display()
+            );
+            Self::default()
+        })))
+    }
+
+    /// Marks the given prefix as frozen by writing the marker file.
+    pub fn write_to_prefix(&self, prefix: &Path) -> Result<(), std::io::Error> {
+        let path = prefix.join(FROZEN_MARKER_PATH);
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        fs_err::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Removes the marker from the given prefix. Returns `true` if the prefix
+    /// was frozen.
+    pub fn remove_from_prefix(prefix: &Path) -> Result<bool, std::io::Error> {
+        match fs_err::remove_file(prefix.join(FROZEN_MARKER_PATH)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// An error that is returned when a transaction would modify a frozen prefix.
+#[derive(Debug, Error)]
+pub enum FrozenPrefixError {
+    /// The prefix is marked as frozen.
+    #[error(
+        "the prefix '{}' is frozen and cannot be modified{}",
+        .prefix.display(),
+        .marker.message.as_ref().map(|message| format!(": {message}")).unwrap_or_default()
+    )]
+    Frozen {
+        /// The prefix that is frozen.
+        prefix: PathBuf,
+
+        /// The marker that was found in the prefix.
+        marker: FrozenMarker,
+    },
+
+    /// Failed to determine whether the prefix is frozen.
+    #[error("failed to read the frozen marker of '{}'", .0.display())]
+    FailedToReadMarker(PathBuf, #[source] std::io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::install::{link_script::PrePostLinkError, InstallDriver, Transaction};
+    use rattler_conda_types::{
+        PackageName, PackageRecord, Platform, PrefixRecord, RepoDataRecord, Version,
+    };
+
+    #[test]
+    fn test_marker_roundtrip() {
+        let prefix = tempfile::tempdir().unwrap();
+        assert_eq!(FrozenMarker::from_prefix(prefix.path()).unwrap(), None);
+
+        let marker = FrozenMarker::with_message("managed by the platform team");
+        marker.write_to_prefix(prefix.path()).unwrap();
+        assert_eq!(
+            FrozenMarker::from_prefix(prefix.path()).unwrap(),
+            Some(marker)
+        );
+
+        // An empty marker also freezes the prefix.
+        fs_err::write(prefix.path().join(FROZEN_MARKER_PATH), "").unwrap();
+        assert_eq!(
+            FrozenMarker::from_prefix(prefix.path()).unwrap(),
+            Some(FrozenMarker::default())
+        );
+
+        assert!(FrozenMarker::remove_from_prefix(prefix.path()).unwrap());
+        assert!(!FrozenMarker::remove_from_prefix(prefix.path()).unwrap());
+        assert_eq!(FrozenMarker::from_prefix(prefix.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_driver_refuses_frozen_prefix() {
+        let prefix = tempfile::tempdir().unwrap();
+        let installed = PrefixRecord::from_repodata_record(
+            RepoDataRecord {
+                package_record: PackageRecord::new(
+                    PackageName::new_unchecked("foo"),
+                    Version::major(1),
+                    String::from("0"),
+                ),
+                file_name: String::from("foo-1-0.conda"),
+                url: "https://conda.anaconda.org/conda-forge/noarch/foo-1-0.conda"
+                    .parse()
+                    .unwrap(),
+                channel: String::from("https://conda.anaconda.org/conda-forge/"),
+            },
+            None,
+            None,
+            Vec::new(),
+            None,
+            None,
+        );
+        let transaction = Transaction::<PrefixRecord, RepoDataRecord>::from_current_and_desired(
+            vec![installed.clone()],
+            Vec::new(),
+            Platform::current(),
+        )
+        .unwrap();
+        assert!(!transaction.operations.is_empty());
+
+        FrozenMarker::with_message("do not touch")
+            .write_to_prefix(prefix.path())
+            .unwrap();
+        let err = InstallDriver::default()
+            .check_frozen_prefix(&transaction, prefix.path())
+            .unwrap_err();
+        assert!(err.to_string().contains("do not touch"), "{err}");
+
+        // The driver also refuses to pre-process the transaction.
+        let err = InstallDriver::default()
+            .pre_process(&transaction, prefix.path())
+            .unwrap_err();
+        assert!(matches!(err, PrePostLinkError::FrozenPrefix(_)), "{err}");
+
+        InstallDriver::builder()
+            .with_override_frozen_prefix(true)
+            .finish()
+            .check_frozen_prefix(&transaction, prefix.path())
+            .unwrap();
+
+        // A transaction that does not change anything is allowed.
+        let no_op = Transaction::<PrefixRecord, RepoDataRecord>::from_current_and_desired(
+            vec![installed.clone()],
+            vec![installed.repodata_record.clone()],
+            Platform::current(),
+        )
+        .unwrap();
+        assert!(no_op.operations.is_empty());
+        InstallDriver::default()
+            .check_frozen_prefix(&no_op, prefix.path())
+            .unwrap();
+    }
+}
@@ -2,8 +2,9 @@ use simple_spawn_blocking::Cancelled;
 
 use crate::{
     install::{
-        clobber_registry::ClobberError, driver::PostProcessingError, link_script::PrePostLinkError,
-        unlink::UnlinkError, InstallError, TransactionError,
+        clobber_registry::ClobberError, driver::PostProcessingError, frozen::FrozenPrefixError,
+        link_script::PrePostLinkError, unlink::UnlinkError, InstallError, PackageVerificationError,
+        TransactionError,
     },
     package_cache::PackageCacheError,
 };
@@ -23,6 +24,10 @@ pub enum InstallerError {
     #[error("failed to fetch {0}")]
     FailedToFetch(String, #[source] PackageCacheError),
 
+    /// A package failed the verification of the trust policy
+    #[error("failed to verify {0}")]
+    UntrustedPackage(String, #[source] PackageVerificationError),
+
     /// Failed to link a certain package
     #[error("failed to link {0}")]
     LinkError(String, #[source] InstallError),
@@ -47,6 +52,19 @@ pub enum InstallerError {
     #[error("failed to unclobber clobbered files")]
     ClobberError(#[from] ClobberError),
 
+    /// The prefix is marked as frozen
+    #[error(transparent)]
+    FrozenPrefix(#[from] FrozenPrefixError),
+
+    /// The transaction was denied by an install policy
+    #[error("the transaction was denied by a policy: {0}")]
+    PolicyViolation(String),
+
+    /// An install policy required confirmation of the transaction but it was
+    /// not confirmed
+    #[error("the transaction was not confirmed: {0}")]
+    PolicyConfirmationDeclined(String),
+
     /// The operation was cancelled
     #[error("the operation was cancelled")]
     Cancelled,
@@ -65,6 +83,61 @@ impl From<PostProcessingError> for InstallerError {
             PostProcessingError::FailedToDetectInstalledPackages(err) => {
                 InstallerError::FailedToDetectInstalledPackages(err)
             }
+            PostProcessingError::FailedToWritePrefixRecord(path, err) => {
+                InstallerError::IoError(format!("failed to write {path}"), err)
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use rattler_networking::retry_policies::RetryConfig;
+    use url::Url;
+
+    use crate::{
+        get_repodata_record, get_test_data_dir,
+        install::{Installer, InstallerError},
+        package_cache::PackageCache,
+    };
+
+    #[tokio::test]
+    async fn test_fetch_error_is_redacted() {
+        let prefix = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        // Nothing is listening on the port of a listener that has been dropped.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut record = get_repodata_record(
+            get_test_data_dir().join("clobber/clobber-1-0.1.0-h4616a5c_0.tar.bz2"),
+        );
+        record.url = Url::parse(&format!(
+            "http://127.0.0.1:{port}/t/secret-token/conda-forge/linux-64/{}",
+            record.file_name
+        ))
+        .unwrap();
+
+        let err = Installer::new()
+            .with_package_cache(PackageCache::new(cache_dir.path()))
+            .with_retry_config(RetryConfig::default().with_max_retries(0))
+            .install(prefix.path(), [record])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, InstallerError::FailedToFetch(..)), "{err:?}");
+
+        let chain = std::iter::successors(Some(&err as &dyn std::error::Error), |err| err.source())
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert!(
+            chain
+                .iter()
+                .all(|message| !message.contains("secret-token")),
+            "{chain:?}"
+        );
+        assert!(!format!("{err:?}").contains("secret-token"), "{err:?}");
+    }
+}
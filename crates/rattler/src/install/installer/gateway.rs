@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use url::Url;
+
+use super::Reporter;
+
+/// Forwards the events of a [`rattler_repodata_gateway::Gateway`] to a
+/// [`Reporter`].
+///
+/// This allows using the same [`Reporter`] to report the progress of fetching
+/// repodata and installing packages.
+#[derive(Clone)]
+pub struct GatewayReporter {
+    reporter: Arc<dyn Reporter>,
+}
+
+impl GatewayReporter {
+    /// Constructs a new instance that forwards events to the given reporter.
+    pub fn new(reporter: Arc<dyn Reporter>) -> Self {
+        Self { reporter }
+    }
+}
+
+impl rattler_repodata_gateway::Reporter for GatewayReporter {
+    fn on_download_start(&self, url: &Url) -> usize {
+        self.reporter.on_repodata_download_start(url)
+    }
+
+    fn on_download_progress(
+        &self,
+        _url: &Url,
+        index: usize,
+        bytes_downloaded: usize,
+        total_bytes: Option<usize>,
+    ) {
+        self.reporter
+            .on_repodata_download_progress(index, bytes_downloaded, total_bytes);
+    }
+
+    fn on_download_complete(&self, _url: &Url, index: usize) {
+        self.reporter.on_repodata_download_complete(index);
+    }
+}
@@ -1,32 +1,45 @@
 mod error;
+#[cfg(feature = "gateway")]
+mod gateway;
 #[cfg(feature = "indicatif")]
 mod indicatif;
+mod policy;
 mod reporter;
 use std::{
     collections::HashMap,
     future::ready,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 pub use error::InstallerError;
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt, TryFutureExt};
+#[cfg(feature = "gateway")]
+pub use gateway::GatewayReporter;
 #[cfg(feature = "indicatif")]
 pub use indicatif::{
     DefaultProgressFormatter, IndicatifReporter, IndicatifReporterBuilder, Placement,
     ProgressFormatter,
 };
+use policy::evaluate_policies;
+pub use policy::{InstallPolicy, PlannedTransaction, PolicyConfirmation, PolicyDecision};
 use rattler_conda_types::{
     prefix_record::{Link, LinkType},
     Platform, PrefixRecord, RepoDataRecord,
 };
-use rattler_networking::retry_policies::default_retry_policy;
+use rattler_networking::retry_policies::RetryConfig;
 pub use reporter::Reporter;
 use reqwest::Client;
 use simple_spawn_blocking::tokio::run_blocking_task;
 use tokio::{sync::Semaphore, task::JoinError};
 
-use super::{unlink_package, AppleCodeSignBehavior, InstallDriver, InstallOptions, Transaction};
+use super::{
+    conda_meta::{recover_conda_meta, CondaMetaTransaction},
+    unlink::unlink_package_files,
+    AppleCodeSignBehavior, InstallDriver, InstallOptions, ModifiedFilePolicy, PreservedFile,
+    PycCompilationResult, Transaction, TrustPolicy,
+};
 use crate::install::link_script::LinkScriptError;
 use crate::{
     default_cache_dir,
@@ -43,9 +56,18 @@ pub struct Installer {
     execute_link_scripts: bool,
     io_semaphore: Option<Arc<Semaphore>>,
     reporter: Option<Arc<dyn Reporter>>,
+    policies: Vec<Arc<dyn InstallPolicy>>,
+    policy_confirmation: Option<Arc<dyn PolicyConfirmation>>,
     target_platform: Option<Platform>,
     apple_code_sign_behavior: AppleCodeSignBehavior,
+    windows_long_paths: bool,
     alternative_target_prefix: Option<PathBuf>,
+    compile_pyc: bool,
+    pyc_concurrency_limit: Option<NonZeroUsize>,
+    override_frozen_prefix: bool,
+    modified_file_policy: ModifiedFilePolicy,
+    trust_policy: TrustPolicy,
+    retry_config: RetryConfig,
     // TODO: Determine upfront if these are possible.
     // allow_symbolic_links: Option<bool>,
     // allow_hard_links: Option<bool>,
@@ -69,6 +91,17 @@ pub struct InstallationResult {
 
     /// The paths that were clobbered during the installation process.
     pub clobbered_paths: HashMap<PathBuf, ClobberedPath>,
+
+    /// The result of compiling the python files of the installed noarch python
+    /// packages to bytecode. `None` if no compilation was performed. Files
+    /// that could not be compiled are reported here instead of failing the
+    /// installation.
+    pub pyc_compilation_result: Option<PycCompilationResult>,
+
+    /// The files that were modified by the user and were therefore preserved
+    /// when the package they belong to was removed or updated. See
+    /// [`Installer::with_modified_file_policy`].
+    pub preserved_files: Vec<PreservedFile>,
 }
 
 impl Installer {
@@ -202,6 +235,51 @@ impl Installer {
         self
     }
 
+    /// Adds a policy that is evaluated before the transaction is executed.
+    /// The installation fails if any of the policies denies the transaction.
+    /// See [`InstallPolicy`].
+    #[must_use]
+    pub fn with_policy<P: InstallPolicy + 'static>(mut self, policy: P) -> Self {
+        self.policies.push(Arc::new(policy));
+        self
+    }
+
+    /// Adds a policy that is evaluated before the transaction is executed.
+    ///
+    /// This function is similar to [`Self::with_policy`], but modifies an
+    /// existing instance.
+    pub fn add_policy<P: InstallPolicy + 'static>(&mut self, policy: P) -> &mut Self {
+        self.policies.push(Arc::new(policy));
+        self
+    }
+
+    /// Sets the handler that is asked to confirm a transaction if a policy
+    /// requires confirmation. Without a handler such transactions are
+    /// rejected.
+    #[must_use]
+    pub fn with_policy_confirmation<C: PolicyConfirmation + 'static>(
+        self,
+        confirmation: C,
+    ) -> Self {
+        Self {
+            policy_confirmation: Some(Arc::new(confirmation)),
+            ..self
+        }
+    }
+
+    /// Sets the handler that is asked to confirm a transaction if a policy
+    /// requires confirmation.
+    ///
+    /// This function is similar to [`Self::with_policy_confirmation`], but
+    /// modifies an existing instance.
+    pub fn set_policy_confirmation<C: PolicyConfirmation + 'static>(
+        &mut self,
+        confirmation: C,
+    ) -> &mut Self {
+        self.policy_confirmation = Some(Arc::new(confirmation));
+        self
+    }
+
     /// Sets the packages that are currently installed in the prefix. If this
     /// is not set, the installation process will first figure this out.
     #[must_use]
@@ -264,6 +342,160 @@ impl Installer {
         self
     }
 
+    /// Sets whether extended-length paths are used to link files on Windows,
+    /// see [`InstallOptions::windows_long_paths`]. Has no effect on other
+    /// platforms.
+    #[must_use]
+    pub fn with_windows_long_paths(self, windows_long_paths: bool) -> Self {
+        Self {
+            windows_long_paths,
+            ..self
+        }
+    }
+
+    /// Sets whether extended-length paths are used to link files on Windows.
+    ///
+    /// This function is similar to [`Self::with_windows_long_paths`], but
+    /// modifies an existing instance.
+    pub fn set_windows_long_paths(&mut self, windows_long_paths: bool) -> &mut Self {
+        self.windows_long_paths = windows_long_paths;
+        self
+    }
+
+    /// Sets whether the python files of noarch python packages should be
+    /// compiled to bytecode after they have been linked.
+    ///
+    /// Compilation uses the python interpreter of the target environment and
+    /// is therefore only performed when installing for the current platform.
+    /// All files of the transaction are compiled by a single pool of
+    /// interpreters. The generated files are recorded in the prefix records
+    /// of the packages so they are removed when the packages are uninstalled.
+    #[must_use]
+    pub fn with_compile_pyc(self, compile_pyc: bool) -> Self {
+        Self {
+            compile_pyc,
+            ..self
+        }
+    }
+
+    /// Sets whether the python files of noarch python packages should be
+    /// compiled to bytecode after they have been linked.
+    ///
+    /// This function is similar to [`Self::with_compile_pyc`], but modifies an
+    /// existing instance.
+    pub fn set_compile_pyc(&mut self, compile_pyc: bool) -> &mut Self {
+        self.compile_pyc = compile_pyc;
+        self
+    }
+
+    /// Sets the maximum number of python interpreters that are used to compile
+    /// python files to bytecode. By default this is determined by the number
+    /// of available cores.
+    #[must_use]
+    pub fn with_pyc_concurrency_limit(self, limit: NonZeroUsize) -> Self {
+        Self {
+            pyc_concurrency_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of python interpreters that are used to compile
+    /// python files to bytecode.
+    ///
+    /// This function is similar to [`Self::with_pyc_concurrency_limit`], but
+    /// modifies an existing instance.
+    pub fn set_pyc_concurrency_limit(&mut self, limit: NonZeroUsize) -> &mut Self {
+        self.pyc_concurrency_limit = Some(limit);
+        self
+    }
+
+    /// Sets whether the installer is allowed to modify a prefix that is marked
+    /// as frozen. By default the installer refuses to modify a frozen prefix.
+    /// See [`crate::install::FrozenMarker`].
+    #[must_use]
+    pub fn with_override_frozen_prefix(self, override_frozen_prefix: bool) -> Self {
+        Self {
+            override_frozen_prefix,
+            ..self
+        }
+    }
+
+    /// Sets whether the installer is allowed to modify a prefix that is marked
+    /// as frozen.
+    ///
+    /// This function is similar to [`Self::with_override_frozen_prefix`], but
+    /// modifies an existing instance.
+    pub fn set_override_frozen_prefix(&mut self, override_frozen_prefix: bool) -> &mut Self {
+        self.override_frozen_prefix = override_frozen_prefix;
+        self
+    }
+
+    /// Determines what happens to files that were modified after they were
+    /// installed when the package they belong to is removed or updated. The
+    /// files that were preserved are reported in
+    /// [`InstallationResult::preserved_files`].
+    #[must_use]
+    pub fn with_modified_file_policy(self, modified_file_policy: ModifiedFilePolicy) -> Self {
+        Self {
+            modified_file_policy,
+            ..self
+        }
+    }
+
+    /// Determines what happens to files that were modified after they were
+    /// installed when the package they belong to is removed or updated.
+    ///
+    /// This function is similar to [`Self::with_modified_file_policy`], but
+    /// modifies an existing instance.
+    pub fn set_modified_file_policy(
+        &mut self,
+        modified_file_policy: ModifiedFilePolicy,
+    ) -> &mut Self {
+        self.modified_file_policy = modified_file_policy;
+        self
+    }
+
+    /// Determines whether the records of the packages are verified before
+    /// they are fetched into the cache and linked into the prefix. By default
+    /// packages are not verified. See [`TrustPolicy`].
+    #[must_use]
+    pub fn with_trust_policy(self, trust_policy: TrustPolicy) -> Self {
+        Self {
+            trust_policy,
+            ..self
+        }
+    }
+
+    /// Determines whether the records of the packages are verified before
+    /// they are fetched into the cache and linked into the prefix.
+    ///
+    /// This function is similar to [`Self::with_trust_policy`], but modifies
+    /// an existing instance.
+    pub fn set_trust_policy(&mut self, trust_policy: TrustPolicy) -> &mut Self {
+        self.trust_policy = trust_policy;
+        self
+    }
+
+    /// Determines how often and with which backoff failed package downloads
+    /// are retried. Defaults to [`RetryConfig::default`].
+    #[must_use]
+    pub fn with_retry_config(self, retry_config: RetryConfig) -> Self {
+        Self {
+            retry_config,
+            ..self
+        }
+    }
+
+    /// Determines how often and with which backoff failed package downloads
+    /// are retried.
+    ///
+    /// This function is similar to [`Self::with_retry_config`], but modifies
+    /// an existing instance.
+    pub fn set_retry_config(&mut self, retry_config: RetryConfig) -> &mut Self {
+        self.retry_config = retry_config;
+        self
+    }
+
     /// Install the packages in the given prefix.
     pub async fn install(
         self,
@@ -273,6 +505,7 @@ impl Installer {
         let downloader = self
             .downloader
             .unwrap_or_else(|| reqwest_middleware::ClientWithMiddleware::from(Client::default()));
+        let retry_config = self.retry_config;
         let package_cache = self.package_cache.unwrap_or_else(|| {
             PackageCache::new(
                 default_cache_dir()
@@ -281,6 +514,15 @@ impl Installer {
             )
         });
 
+        // Finish or discard the prefix records of an interrupted transaction
+        // before the prefix is modified.
+        let recover_prefix = prefix.as_ref().to_path_buf();
+        run_blocking_task(move || {
+            recover_conda_meta(&recover_prefix)
+                .map_err(InstallerError::FailedToDetectInstalledPackages)
+        })
+        .await?;
+
         // Create a future to determine the currently installed packages. We
         // can start this in parallel with the other operations and resolve it
         // when we need it.
@@ -297,13 +539,21 @@ impl Installer {
         };
 
         // Construct a driver.
-        let driver = InstallDriver::builder()
+        let mut driver = InstallDriver::builder()
             .execute_link_scripts(self.execute_link_scripts)
+            .with_override_frozen_prefix(self.override_frozen_prefix)
+            .with_compile_pyc(self.compile_pyc)
             .with_io_concurrency_semaphore(
                 self.io_semaphore.unwrap_or(Arc::new(Semaphore::new(100))),
             )
-            .with_prefix_records(&installed)
-            .finish();
+            .with_prefix_records(&installed);
+        if let Some(reporter) = &self.reporter {
+            driver = driver.with_reporter(reporter.clone());
+        }
+        if let Some(limit) = self.pyc_concurrency_limit {
+            driver = driver.with_pyc_concurrency_limit(limit);
+        }
+        let driver = driver.finish();
 
         // Construct a transaction from the current and desired situation.
         let target_platform = self.target_platform.unwrap_or_else(Platform::current);
@@ -320,18 +570,47 @@ impl Installer {
                 pre_link_script_result: None,
                 post_link_script_result: None,
                 clobbered_paths: HashMap::default(),
+                pyc_compilation_result: None,
+                preserved_files: Vec::new(),
             });
         }
 
+        // Make sure the transaction is allowed before anything is modified.
+        evaluate_policies(
+            &self.policies,
+            self.policy_confirmation.as_deref(),
+            &PlannedTransaction {
+                prefix: prefix.as_ref(),
+                transaction: &transaction,
+            },
+        )?;
+
         // Determine base installer options.
         let base_install_options = InstallOptions {
             target_prefix: self.alternative_target_prefix.clone(),
             platform: Some(target_platform),
             python_info: transaction.python_info.clone(),
             apple_codesign_behavior: self.apple_code_sign_behavior,
+            windows_long_paths: self.windows_long_paths,
+            modified_file_policy: self.modified_file_policy,
             ..InstallOptions::default()
         };
 
+        // Verify the packages before anything is fetched or modified.
+        for record in transaction
+            .operations
+            .iter()
+            .filter_map(|operation| operation.record_to_install())
+        {
+            self.trust_policy
+                .verify(record)
+                .map_err(|e| InstallerError::UntrustedPackage(record.file_name.clone(), e))?;
+        }
+
+        // Make sure the prefix is allowed to be modified before anything is
+        // changed.
+        driver.check_frozen_prefix(&transaction, prefix.as_ref())?;
+
         if let Some(reporter) = &self.reporter {
             reporter.on_transaction_start(&transaction);
         }
@@ -341,6 +620,12 @@ impl Installer {
             .pre_process(&transaction, prefix.as_ref())
             .map_err(InstallerError::PreProcessingFailed)?;
 
+        // The prefix records of the installed and removed packages are staged
+        // and conda-meta is only modified once all operations have finished.
+        let conda_meta = CondaMetaTransaction::begin(prefix.as_ref()).map_err(|e| {
+            InstallerError::IoError("failed to create conda-meta directory".to_string(), e)
+        })?;
+
         // Execute the operations in the transaction.
         let mut pending_futures = FuturesUnordered::new();
         for (idx, operation) in transaction.operations.iter().enumerate() {
@@ -350,6 +635,7 @@ impl Installer {
             let base_install_options = &base_install_options;
             let driver = &driver;
             let prefix = &prefix;
+            let conda_meta = &conda_meta;
             let operation_future = async move {
                 if let Some(reporter) = &reporter {
                     reporter.on_transaction_operation_start(idx);
@@ -370,6 +656,7 @@ impl Installer {
                             &record,
                             downloader,
                             &package_cache,
+                            retry_config,
                             populate_cache_report.clone(),
                         )
                         .await?;
@@ -391,14 +678,33 @@ impl Installer {
                 };
 
                 // Uninstall the package if it was removed.
+                let mut preserved_files = Vec::new();
                 if let Some(record) = operation.record_to_remove() {
                     let reporter = reporter
                         .as_deref()
                         .map(move |r| (r, r.on_unlink_start(idx, record)));
                     driver.clobber_registry().unregister_paths(record);
-                    unlink_package(prefix.as_ref(), record).await.map_err(|e| {
+                    preserved_files = unlink_package_files(
+                        prefix.as_ref(),
+                        record,
+                        base_install_options.modified_file_policy,
+                    )
+                    .await
+                    .map_err(|e| {
                         InstallerError::UnlinkError(record.repodata_record.file_name.clone(), e)
                     })?;
+                    let conda_meta = (*conda_meta).clone();
+                    let removed = record.clone();
+                    driver
+                        .run_blocking_io_task(move || {
+                            conda_meta.stage_removal(&removed).map_err(|e| {
+                                InstallerError::IoError(
+                                    format!("failed to remove {}", removed.file_name()),
+                                    e,
+                                )
+                            })
+                        })
+                        .await?;
                     if let Some((reporter, index)) = reporter {
                         reporter.on_unlink_complete(index);
                     }
@@ -415,6 +721,7 @@ impl Installer {
                         &cached_path,
                         base_install_options.clone(),
                         driver,
+                        conda_meta,
                     )
                     .await?;
                     if let Some((reporter, index)) = reporter {
@@ -426,20 +733,45 @@ impl Installer {
                     reporter.on_transaction_operation_complete(idx);
                 }
 
-                Ok::<_, InstallerError>(())
+                Ok::<_, InstallerError>(preserved_files)
             };
 
             pending_futures.push(operation_future);
         }
 
         // Wait for all transaction operations to finish
+        let mut preserved_files = Vec::new();
+        let mut operations_result = Ok(());
         while let Some(result) = pending_futures.next().await {
-            result?;
+            match result {
+                Ok(files) => preserved_files.extend(files),
+                Err(e) => {
+                    operations_result = Err(e);
+                    break;
+                }
+            }
         }
         drop(pending_futures);
 
-        // Post process the transaction
-        let post_process_result = driver.post_process(&transaction, prefix.as_ref())?;
+        // Commit the prefix records, also if an operation failed, so that
+        // conda-meta reflects the packages that were actually linked.
+        driver
+            .run_blocking_io_task(move || {
+                conda_meta.commit().map_err(|e| {
+                    InstallerError::IoError("failed to write the prefix records".to_string(), e)
+                })
+            })
+            .await?;
+        operations_result?;
+
+        // Post process the transaction. This compiles python files and runs
+        // link scripts which blocks, so it runs on a separate thread.
+        let target_prefix = prefix.as_ref().to_path_buf();
+        let (transaction, post_process_result) = run_blocking_task(move || {
+            let result = driver.post_process(&transaction, &target_prefix)?;
+            Ok::<_, InstallerError>((transaction, result))
+        })
+        .await?;
 
         if let Some(reporter) = &self.reporter {
             reporter.on_transaction_complete();
@@ -450,6 +782,8 @@ impl Installer {
             pre_link_script_result: pre_process_result,
             post_link_script_result: post_process_result.post_link_result,
             clobbered_paths: post_process_result.clobbered_paths,
+            pyc_compilation_result: post_process_result.pyc_compilation_result,
+            preserved_files,
         })
     }
 }
@@ -460,7 +794,8 @@ async fn link_package(
     cached_package_dir: &Path,
     install_options: InstallOptions,
     driver: &InstallDriver,
-) -> Result<(), InstallerError> {
+    conda_meta: &CondaMetaTransaction,
+) -> Result<PrefixRecord, InstallerError> {
     // Link the contents of the package into the prefix.
     let paths =
         crate::install::link_package(cached_package_dir, target_prefix, driver, install_options)
@@ -487,27 +822,14 @@ async fn link_package(
         }),
     };
 
-    let target_prefix = target_prefix.to_path_buf();
+    let conda_meta = conda_meta.clone();
     driver
         .run_blocking_io_task(move || {
-            let conda_meta_path = target_prefix.join("conda-meta");
-            std::fs::create_dir_all(&conda_meta_path).map_err(|e| {
-                InstallerError::IoError("failed to create conda-meta directory".to_string(), e)
+            conda_meta.stage(&prefix_record).map_err(|e| {
+                InstallerError::IoError(format!("failed to write {}", prefix_record.file_name()), e)
             })?;
 
-            let pkg_meta_path = format!(
-                "{}-{}-{}.json",
-                prefix_record
-                    .repodata_record
-                    .package_record
-                    .name
-                    .as_normalized(),
-                prefix_record.repodata_record.package_record.version,
-                prefix_record.repodata_record.package_record.build
-            );
-            prefix_record
-                .write_to_path(conda_meta_path.join(&pkg_meta_path), true)
-                .map_err(|e| InstallerError::IoError(format!("failed to write {pkg_meta_path}"), e))
+            Ok(prefix_record)
         })
         .await
 }
@@ -518,6 +840,7 @@ async fn populate_cache(
     record: &RepoDataRecord,
     downloader: reqwest_middleware::ClientWithMiddleware,
     cache: &PackageCache,
+    retry_config: RetryConfig,
     reporter: Option<(Arc<dyn Reporter>, usize)>,
 ) -> Result<PathBuf, InstallerError> {
     struct CacheReporterBridge {
@@ -545,6 +868,18 @@ async fn populate_cache(
         fn on_download_completed(&self, index: usize) {
             self.reporter.on_download_completed(index);
         }
+
+        fn on_extract_start(&self) -> usize {
+            self.reporter.on_extract_start(self.cache_index)
+        }
+
+        fn on_extract_completed(&self, index: usize) {
+            self.reporter.on_extract_completed(index);
+        }
+
+        fn on_extract_failed(&self, index: usize) {
+            self.reporter.on_extract_failed(index);
+        }
     }
 
     cache
@@ -552,7 +887,7 @@ async fn populate_cache(
             &record.package_record,
             record.url.clone(),
             downloader,
-            default_retry_policy(),
+            retry_config.build(),
             reporter.map(|(reporter, cache_index)| {
                 Arc::new(CacheReporterBridge {
                     reporter,
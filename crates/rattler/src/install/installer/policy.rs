@@ -0,0 +1,244 @@
+use std::{collections::BTreeSet, path::Path, sync::Arc};
+
+use rattler_conda_types::{PrefixRecord, RepoDataRecord};
+
+use super::InstallerError;
+use crate::install::Transaction;
+
+/// A transaction that is about to be executed by the [`super::Installer`].
+/// This is passed to an [`InstallPolicy`] to decide whether the transaction
+/// may be executed.
+pub struct PlannedTransaction<'a> {
+    /// The prefix that is modified by the transaction.
+    pub prefix: &'a Path,
+
+    /// The transaction that will be executed.
+    pub transaction: &'a Transaction<PrefixRecord, RepoDataRecord>,
+}
+
+impl PlannedTransaction<'_> {
+    /// Returns the records of all packages that will be installed.
+    pub fn records_to_install(&self) -> impl Iterator<Item = &RepoDataRecord> + '_ {
+        self.transaction.installed_packages()
+    }
+
+    /// Returns the total size in bytes of the packages that will be installed.
+    /// Packages of which the size is unknown are not included.
+    pub fn total_size(&self) -> u64 {
+        self.records_to_install()
+            .filter_map(|record| record.package_record.size)
+            .sum()
+    }
+
+    /// Returns the channels of the packages that will be installed.
+    pub fn channels(&self) -> BTreeSet<&str> {
+        self.records_to_install()
+            .map(|record| record.channel.as_str())
+            .collect()
+    }
+
+    /// Returns the licenses of the packages that will be installed. Packages
+    /// without a license are not included.
+    pub fn licenses(&self) -> BTreeSet<&str> {
+        self.records_to_install()
+            .filter_map(|record| record.package_record.license.as_deref())
+            .collect()
+    }
+}
+
+/// The outcome of evaluating an [`InstallPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyDecision {
+    /// The transaction may be executed.
+    Allow,
+
+    /// The transaction may only be executed after it has been confirmed with a
+    /// [`PolicyConfirmation`]. The string describes why confirmation is
+    /// required.
+    RequireConfirmation(String),
+
+    /// The transaction must not be executed. The string describes why.
+    Deny(String),
+}
+
+/// A policy that is evaluated by the [`super::Installer`] before a
+/// transaction is executed. Policies can be used to enforce guardrails, e.g.
+/// to reject packages with certain licenses or from unapproved channels.
+///
+/// The trait is implemented for closures that take a [`PlannedTransaction`]
+/// and return a [`PolicyDecision`].
+pub trait InstallPolicy: Send + Sync {
+    /// Evaluates the transaction that is about to be executed.
+    fn evaluate(&self, transaction: &PlannedTransaction<'_>) -> PolicyDecision;
+}
+
+impl<F> InstallPolicy for F
+where
+    F: Fn(&PlannedTransaction<'_>) -> PolicyDecision + Send + Sync,
+{
+    fn evaluate(&self, transaction: &PlannedTransaction<'_>) -> PolicyDecision {
+        self(transaction)
+    }
+}
+
+/// Confirms transactions for which an [`InstallPolicy`] returned
+/// [`PolicyDecision::RequireConfirmation`], e.g. by prompting the user.
+///
+/// The trait is implemented for closures that take a [`PlannedTransaction`]
+/// and the reasons why confirmation is required.
+pub trait PolicyConfirmation: Send + Sync {
+    /// Returns true if the transaction should be executed.
+    fn confirm(&self, transaction: &PlannedTransaction<'_>, reasons: &[String]) -> bool;
+}
+
+impl<F> PolicyConfirmation for F
+where
+    F: Fn(&PlannedTransaction<'_>, &[String]) -> bool + Send + Sync,
+{
+    fn confirm(&self, transaction: &PlannedTransaction<'_>, reasons: &[String]) -> bool {
+        self(transaction, reasons)
+    }
+}
+
+/// Evaluates all policies for the given transaction. Returns an error if any
+/// of the policies denies the transaction or if confirmation is required but
+/// not given.
+pub(super) fn evaluate_policies(
+    policies: &[Arc<dyn InstallPolicy>],
+    confirmation: Option<&dyn PolicyConfirmation>,
+    transaction: &PlannedTransaction<'_>,
+) -> Result<(), InstallerError> {
+    let mut denied = Vec::new();
+    let mut unconfirmed = Vec::new();
+    for policy in policies {
+        match policy.evaluate(transaction) {
+            PolicyDecision::Allow => {}
+            PolicyDecision::RequireConfirmation(reason) => unconfirmed.push(reason),
+            PolicyDecision::Deny(reason) => denied.push(reason),
+        }
+    }
+
+    if !denied.is_empty() {
+        return Err(InstallerError::PolicyViolation(denied.join("; ")));
+    }
+
+    if !unconfirmed.is_empty()
+        && !confirmation.is_some_and(|confirmation| confirmation.confirm(transaction, &unconfirmed))
+    {
+        return Err(InstallerError::PolicyConfirmationDeclined(
+            unconfirmed.join("; "),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use rattler_conda_types::{
+        PackageName, PackageRecord, Platform, PrefixRecord, RepoDataRecord, Version,
+    };
+    use url::Url;
+
+    use super::{PlannedTransaction, PolicyDecision};
+    use crate::install::{Installer, InstallerError, Transaction};
+
+    fn record(name: &str, channel: &str, license: &str) -> RepoDataRecord {
+        let mut package_record = PackageRecord::new(
+            PackageName::new_unchecked(name),
+            "1.0".parse::<Version>().unwrap(),
+            String::from("0"),
+        );
+        package_record.license = Some(license.to_string());
+        package_record.size = Some(100);
+        RepoDataRecord {
+            package_record,
+            file_name: format!("{name}-1.0-0.conda"),
+            url: Url::parse(&format!("{channel}/noarch/{name}-1.0-0.conda")).unwrap(),
+            channel: channel.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_planned_transaction() {
+        let transaction = Transaction::<PrefixRecord, RepoDataRecord>::from_current_and_desired(
+            Vec::new(),
+            vec![
+                record("foo", "https://conda.anaconda.org/conda-forge", "MIT"),
+                record("bar", "https://example.com/internal", "GPL-3.0"),
+            ],
+            Platform::NoArch,
+        )
+        .unwrap();
+        let planned = PlannedTransaction {
+            prefix: Path::new("/prefix"),
+            transaction: &transaction,
+        };
+        assert_eq!(planned.total_size(), 200);
+        assert_eq!(
+            planned.channels().into_iter().collect::<Vec<_>>(),
+            [
+                "https://conda.anaconda.org/conda-forge",
+                "https://example.com/internal"
+            ]
+        );
+        assert_eq!(
+            planned.licenses().into_iter().collect::<Vec<_>>(),
+            ["GPL-3.0", "MIT"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_policy_vetoes_installation() {
+        let prefix = tempfile::tempdir().unwrap();
+        let records = vec![record(
+            "foo",
+            "https://example.com/internal",
+            "GPL-3.0-or-later",
+        )];
+        let no_gpl = |transaction: &PlannedTransaction<'_>| {
+            if transaction
+                .licenses()
+                .iter()
+                .any(|license| license.starts_with("GPL"))
+            {
+                PolicyDecision::Deny(String::from("GPL licensed packages are not allowed"))
+            } else {
+                PolicyDecision::Allow
+            }
+        };
+
+        let err = Installer::new()
+            .with_installed_packages(Vec::new())
+            .with_policy(no_gpl)
+            .install(prefix.path(), records.clone())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, InstallerError::PolicyViolation(reason) if reason.contains("GPL")),
+            "{err}"
+        );
+
+        // Transactions that require confirmation are rejected unless they are
+        // confirmed.
+        let unapproved_channel = |_: &PlannedTransaction<'_>| {
+            PolicyDecision::RequireConfirmation(String::from("unapproved channel"))
+        };
+        let err = Installer::new()
+            .with_installed_packages(Vec::new())
+            .with_policy(unapproved_channel)
+            .with_policy_confirmation(|_: &PlannedTransaction<'_>, reasons: &[String]| {
+                assert_eq!(reasons, ["unapproved channel"]);
+                false
+            })
+            .install(prefix.path(), records)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, InstallerError::PolicyConfirmationDeclined(_)),
+            "{err}"
+        );
+    }
+}
@@ -1,8 +1,17 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use rattler_conda_types::{PrefixRecord, RepoDataRecord};
+use url::Url;
 
-use crate::install::Transaction;
+use crate::install::{clobber_registry::ClobberedPath, Transaction};
 
 /// A trait for reporting progress of the installation process.
+///
+/// The reporter covers all phases of creating an environment: fetching
+/// repodata (see `GatewayReporter`), populating the package cache
+/// (validation, download and extraction), linking and unlinking packages and
+/// resolving clobbered files. This allows front-ends to build a single progress
+/// UI for the entire process.
 pub trait Reporter: Send + Sync {
     /// Called when the transaction starts. This is the first method called.
     fn on_transaction_start(&self, transaction: &Transaction<PrefixRecord, RepoDataRecord>);
@@ -59,6 +68,32 @@ pub trait Reporter: Send + Sync {
     /// corresponding download.
     fn on_download_completed(&self, download_idx: usize);
 
+    /// Called when a package starts to be extracted into the cache. Packages
+    /// are extracted while they are downloaded so this overlaps with the
+    /// download callbacks.
+    ///
+    /// The value returned by this function is passed as the `extract_idx` to
+    /// `on_extract_completed`.
+    ///
+    /// The `cache_entry` is the value return by `on_populate_cache_start` for
+    /// the corresponding package.
+    fn on_extract_start(&self, cache_entry: usize) -> usize {
+        cache_entry
+    }
+
+    /// Called when the extraction of a package completed.
+    ///
+    /// The `extract_idx` is the value return by `on_extract_start` for the
+    /// corresponding package.
+    fn on_extract_completed(&self, _extract_idx: usize) {}
+
+    /// Called instead of `on_extract_completed` when a package could not be
+    /// fetched or extracted into the cache.
+    ///
+    /// The `extract_idx` is the value return by `on_extract_start` for the
+    /// corresponding package.
+    fn on_extract_failed(&self, _extract_idx: usize) {}
+
     /// Called when the cache for a package was populated
     ///
     /// The `cache_entry` is the value return by `on_populate_cache_start` for
@@ -92,6 +127,37 @@ pub trait Reporter: Send + Sync {
     /// Called when a transaction operation finishes.
     fn on_transaction_operation_complete(&self, operation: usize);
 
+    /// Called when the files that were written by multiple packages are
+    /// resolved. This happens after all packages have been linked.
+    fn on_clobber_resolution_start(&self) {}
+
+    /// Called when clobbered files have been resolved. The `clobbered_paths`
+    /// contain the paths that were written by multiple packages and the
+    /// package that the final file originates from.
+    fn on_clobber_resolution_complete(&self, _clobbered_paths: &HashMap<PathBuf, ClobberedPath>) {}
+
+    /// Called when the download of repodata starts.
+    ///
+    /// The value returned by this function is passed as the `index` to
+    /// `on_repodata_download_progress` and `on_repodata_download_complete`.
+    fn on_repodata_download_start(&self, _url: &Url) -> usize {
+        0
+    }
+
+    /// Called with regular updates on the progress of a repodata download.
+    ///
+    /// The `total_bytes` parameter is `None` if the total size is unknown.
+    fn on_repodata_download_progress(
+        &self,
+        _index: usize,
+        _bytes_downloaded: usize,
+        _total_bytes: Option<usize>,
+    ) {
+    }
+
+    /// Called when the download of repodata completes.
+    fn on_repodata_download_complete(&self, _index: usize) {}
+
     /// Called when the transaction completes. Unless an error occurs, this is
     /// the last function that is called.
     fn on_transaction_complete(&self);
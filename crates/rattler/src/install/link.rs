@@ -104,6 +104,24 @@ pub enum LinkFileError {
     FailedToComputeSha(#[source] std::io::Error),
 }
 
+impl LinkFileError {
+    /// Returns the underlying IO error, if any.
+    pub fn io_error(&self) -> Option<&std::io::Error> {
+        match self {
+            LinkFileError::IoError(_, err)
+            | LinkFileError::FailedToOpenSourceFile(err)
+            | LinkFileError::FailedToReadSourceFile(err)
+            | LinkFileError::FailedToReadSymlink(err)
+            | LinkFileError::FailedToLink(_, err)
+            | LinkFileError::FailedToReadSourceFileMetadata(err)
+            | LinkFileError::FailedToOpenDestinationFile(err)
+            | LinkFileError::FailedToUpdateDestinationFilePermissions(err)
+            | LinkFileError::FailedToComputeSha(err) => Some(err),
+            LinkFileError::FailedToSignAppleBinary | LinkFileError::MissingPythonInfo => None,
+        }
+    }
+}
+
 /// The successful result of calling [`link_file`].
 pub struct LinkedFile {
     /// True if an existing file already existed and linking overwrote the original file.
@@ -126,6 +144,14 @@ pub struct LinkedFile {
     pub prefix_placeholder: Option<String>,
 }
 
+/// Additional options that influence how [`link_file`] links a file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinkFileOptions {
+    /// Whether to use extended-length paths (paths prefixed with `\\?\`) on Windows, see
+    /// [`crate::install::InstallOptions::windows_long_paths`]. Has no effect on other platforms.
+    pub windows_long_paths: bool,
+}
+
 /// Installs a single file from a `package_dir` to the the `target_dir`. Replaces any
 /// `prefix_placeholder` in the file with the `prefix`.
 ///
@@ -145,10 +171,16 @@ pub fn link_file(
     allow_ref_links: bool,
     target_platform: Platform,
     apple_codesign_behavior: AppleCodeSignBehavior,
+    options: LinkFileOptions,
 ) -> Result<LinkedFile, LinkFileError> {
-    let source_path = package_dir.join(&path_json_entry.relative_path);
+    let mut source_path = package_dir.join(&path_json_entry.relative_path);
+    let mut destination_path = target_dir.join(&destination_relative_path);
 
-    let destination_path = target_dir.join(&destination_relative_path);
+    // Use extended-length paths to bypass the `MAX_PATH` limit on Windows.
+    if options.windows_long_paths {
+        source_path = extended_length_path(&source_path);
+        destination_path = extended_length_path(&destination_path);
+    }
 
     // Temporary variables to store intermediate computations in. If we already computed the file
     // size or the sha hash we dont have to recompute them at the end of the function.
@@ -432,6 +464,10 @@ fn hardlink_to_destination(
 
 /// Symlink the specified file from the source (or cached) directory. If the file already exists it
 /// is removed and the operation is retried.
+///
+/// On Windows, creating symbolic links requires either elevated privileges or Developer Mode. If
+/// the link points to a directory and the symlink cannot be created, a directory junction is
+/// created instead. Junctions do not require any special privileges.
 fn symlink_to_destination(
     source_path: &Path,
     destination_path: &Path,
@@ -439,14 +475,31 @@ fn symlink_to_destination(
     let linked_path = source_path
         .read_link()
         .map_err(LinkFileError::FailedToReadSymlink)?;
+    let is_dir = source_path.is_dir();
     loop {
-        match symlink(&linked_path, destination_path) {
+        match symlink(&linked_path, destination_path, is_dir) {
             Ok(_) => return Ok(LinkMethod::Softlink),
             Err(e) if e.kind() == ErrorKind::AlreadyExists => {
                 std::fs::remove_file(destination_path).map_err(|err| {
                     LinkFileError::IoError(String::from("removing clobbered file"), err)
                 })?;
             }
+            #[cfg(windows)]
+            Err(e) if is_dir && is_privilege_not_held(&e) => {
+                tracing::debug!(
+                    "failed to symlink {}: {e}, falling back to a directory junction.",
+                    destination_path.display()
+                );
+                // Junctions must point to an absolute path without any `..` components.
+                let target = destination_path.parent().map_or_else(
+                    || linked_path.clone(),
+                    |parent| extended_length_path(&parent.join(&linked_path)),
+                );
+                return match junction::create(&target, destination_path) {
+                    Ok(_) => Ok(LinkMethod::Softlink),
+                    Err(e) => Err(LinkFileError::FailedToLink(LinkMethod::Softlink, e)),
+                };
+            }
             Err(e) => {
                 tracing::debug!(
                     "failed to symlink {}: {e}, falling back to copying.",
@@ -681,11 +734,79 @@ pub fn copy_and_replace_cstring_placeholder(
     }
 }
 
-fn symlink(source_path: &Path, destination_path: &Path) -> std::io::Result<()> {
+#[allow(unused_variables)]
+fn symlink(source_path: &Path, destination_path: &Path, is_dir: bool) -> std::io::Result<()> {
     #[cfg(windows)]
-    return std::os::windows::fs::symlink_file(source_path, destination_path);
+    return if is_dir {
+        std::os::windows::fs::symlink_dir(source_path, destination_path)
+    } else {
+        std::os::windows::fs::symlink_file(source_path, destination_path)
+    };
     #[cfg(unix)]
     return std::os::unix::fs::symlink(source_path, destination_path);
+    #[cfg(not(any(windows, unix)))]
+    return Err(std::io::Error::new(
+        ErrorKind::Unsupported,
+        "symbolic links are not supported on this platform",
+    ));
+}
+
+/// Returns true if the error indicates that the process lacks the privilege to create symbolic
+/// links (`ERROR_PRIVILEGE_NOT_HELD`). This happens when Developer Mode is disabled and the process
+/// is not elevated.
+#[cfg(windows)]
+fn is_privilege_not_held(err: &std::io::Error) -> bool {
+    const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+    err.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD)
+}
+
+/// Converts an absolute path into an extended-length path (prefixed with `\\?\`) which is not
+/// subject to the `MAX_PATH` limit on Windows. Extended-length paths are not normalized by Windows,
+/// so the path is rebuilt from its components which also normalizes forward slashes.
+///
+/// Relative paths, paths that are already extended-length paths and paths on platforms other than
+/// Windows are returned as is.
+pub(crate) fn extended_length_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        use std::path::{Component, Prefix};
+
+        let mut components = path.components();
+        let mut result = match components.next() {
+            Some(Component::Prefix(prefix)) => match prefix.kind() {
+                Prefix::Disk(_) => {
+                    let mut result = std::ffi::OsString::from(r"\\?\");
+                    result.push(prefix.as_os_str());
+                    PathBuf::from(result)
+                }
+                Prefix::UNC(server, share) => {
+                    let mut result = std::ffi::OsString::from(r"\\?\UNC\");
+                    result.push(server);
+                    result.push(r"\");
+                    result.push(share);
+                    PathBuf::from(result)
+                }
+                _ => return path.to_path_buf(),
+            },
+            _ => return path.to_path_buf(),
+        };
+
+        for component in components {
+            match component {
+                Component::RootDir => result.push(r"\"),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    result.pop();
+                }
+                Component::Normal(part) => result.push(part),
+                Component::Prefix(_) => unreachable!("a prefix can only be the first component"),
+            }
+        }
+
+        result
+    }
+    #[cfg(not(windows))]
+    path.to_path_buf()
 }
 
 #[allow(unused_variables)]
@@ -774,6 +895,26 @@ mod test {
         assert_eq!(out.len(), input.len());
     }
 
+    #[test]
+    fn test_extended_length_path() {
+        let path = std::path::Path::new("relative/path");
+        assert_eq!(super::extended_length_path(path), path);
+
+        #[cfg(windows)]
+        {
+            assert_eq!(
+                super::extended_length_path(std::path::Path::new(r"C:\prefix/lib/../bin/foo.exe")),
+                std::path::Path::new(r"\\?\C:\prefix\bin\foo.exe")
+            );
+            assert_eq!(
+                super::extended_length_path(std::path::Path::new(r"\\server\share\prefix")),
+                std::path::Path::new(r"\\?\UNC\server\share\prefix")
+            );
+            let path = std::path::Path::new(r"\\?\C:\prefix");
+            assert_eq!(super::extended_length_path(path), path);
+        }
+    }
+
     #[test]
     fn test_replace_long_shebang() {
         let short_shebang = "#!/path/to/python -x 123";
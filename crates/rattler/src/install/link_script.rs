@@ -9,7 +9,7 @@ use rattler_conda_types::{PackageName, PackageRecord, Platform, PrefixRecord};
 use rattler_shell::shell::{Bash, CmdExe, ShellEnum};
 use thiserror::Error;
 
-use super::{InstallDriver, Transaction};
+use super::{FrozenPrefixError, InstallDriver, Transaction};
 
 /// Error type for link script errors
 #[derive(Debug, thiserror::Error)]
@@ -82,6 +82,10 @@ pub enum PrePostLinkError {
     /// Failed to determine the currently installed packages.
     #[error("failed to determine the installed packages")]
     FailedToDetectInstalledPackages(#[source] std::io::Error),
+
+    /// The prefix is marked as frozen and must not be modified.
+    #[error(transparent)]
+    FrozenPrefix(#[from] FrozenPrefixError),
 }
 
 /// Run the link scripts for a given package
@@ -0,0 +1,165 @@
+//! Installing the environment of a lock file.
+
+use std::path::Path;
+
+use rattler_conda_types::{Platform, RepoDataRecord};
+use rattler_lock::{ConversionError, LockFile, PypiPackageData, PypiPackageEnvironmentData};
+
+use super::{InstallationResult, Installer, InstallerError};
+use crate::package_cache::PackageCache;
+
+/// Error that can occur while installing the environment of a lock file.
+#[derive(Debug, thiserror::Error)]
+pub enum InstallLockFileError {
+    /// The environment does not exist in the lock file.
+    #[error("the environment '{0}' does not exist in the lock file")]
+    EnvironmentNotFound(String),
+
+    /// The environment is not locked for the platform.
+    #[error("the environment '{0}' is not locked for {1}")]
+    PlatformNotFound(String, Platform),
+
+    /// A locked package could not be converted to a record.
+    #[error(transparent)]
+    ConversionError(#[from] ConversionError),
+
+    /// A locked package does not specify a sha256 hash, so its contents
+    /// cannot be verified.
+    #[error("the locked package {0} does not specify a sha256 hash")]
+    MissingHash(String),
+
+    /// Failed to install the conda packages of the environment.
+    #[error(transparent)]
+    InstallerError(#[from] InstallerError),
+}
+
+/// The result of [`install_lockfile_environment`].
+#[derive(Debug)]
+pub struct LockFileInstallationResult {
+    /// The result of installing the conda packages of the environment.
+    pub installation: InstallationResult,
+
+    /// The pypi packages of the environment for the platform. These are not
+    /// installed, it is up to the caller to install them into the prefix.
+    pub pypi_packages: Vec<(PypiPackageData, PypiPackageEnvironmentData)>,
+}
+
+/// Installs the conda packages of an environment of a lock file into
+/// `prefix`.
+///
+/// Every locked conda package must specify a sha256 hash, the contents of the
+/// packages that are fetched into the `package_cache` are verified against
+/// these hashes. Packages are downloaded with the given `client`. Packages that
+/// are already installed in the prefix but are not part of the environment are
+/// removed.
+///
+/// PyPI packages are not installed, they are returned as part of the result
+/// so they can be installed by the caller. Use
+/// [`install_lockfile_environment_with_installer`] to configure the
+/// installation.
+pub async fn install_lockfile_environment(
+    prefix: &Path,
+    lock_file: &LockFile,
+    environment_name: &str,
+    platform: Platform,
+    package_cache: PackageCache,
+    client: reqwest_middleware::ClientWithMiddleware,
+) -> Result<LockFileInstallationResult, InstallLockFileError> {
+    install_lockfile_environment_with_installer(
+        prefix,
+        lock_file,
+        environment_name,
+        platform,
+        Installer::new()
+            .with_package_cache(package_cache)
+            .with_download_client(client),
+    )
+    .await
+}
+
+/// Installs the conda packages of an environment of a lock file into
+/// `prefix` like [`install_lockfile_environment`] using the given
+/// `installer`. The target platform of the installer is set to `platform`.
+pub async fn install_lockfile_environment_with_installer(
+    prefix: &Path,
+    lock_file: &LockFile,
+    environment_name: &str,
+    platform: Platform,
+    installer: Installer,
+) -> Result<LockFileInstallationResult, InstallLockFileError> {
+    let environment = lock_file
+        .environment(environment_name)
+        .ok_or_else(|| InstallLockFileError::EnvironmentNotFound(environment_name.to_string()))?;
+    let records = environment
+        .conda_repodata_records_for_platform(platform)?
+        .ok_or_else(|| {
+            InstallLockFileError::PlatformNotFound(environment_name.to_string(), platform)
+        })?;
+    verify_hashes(&records)?;
+    let pypi_packages = environment
+        .pypi_packages_for_platform(platform)
+        .unwrap_or_default();
+
+    let installation = installer
+        .with_target_platform(platform)
+        .install(prefix, records)
+        .await?;
+
+    Ok(LockFileInstallationResult {
+        installation,
+        pypi_packages,
+    })
+}
+
+/// Ensures that the contents of all records can be verified.
+fn verify_hashes(records: &[RepoDataRecord]) -> Result<(), InstallLockFileError> {
+    match records
+        .iter()
+        .find(|record| record.package_record.sha256.is_none())
+    {
+        Some(record) => Err(InstallLockFileError::MissingHash(record.file_name.clone())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use rattler_conda_types::Platform;
+    use rattler_lock::LockFile;
+
+    use super::{install_lockfile_environment, InstallLockFileError};
+    use crate::{get_test_data_dir, package_cache::PackageCache};
+
+    #[tokio::test]
+    async fn test_missing_environment_and_platform() {
+        let lock_file =
+            LockFile::from_path(&get_test_data_dir().join("conda-lock/v4/python-lock.yml"))
+                .unwrap();
+        let prefix = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let client = reqwest_middleware::ClientWithMiddleware::from(reqwest::Client::new());
+
+        let result = install_lockfile_environment(
+            prefix.path(),
+            &lock_file,
+            "does-not-exist",
+            Platform::Linux64,
+            PackageCache::new(cache.path()),
+            client.clone(),
+        )
+        .await;
+        assert_matches!(result, Err(InstallLockFileError::EnvironmentNotFound(_)));
+
+        let result = install_lockfile_environment(
+            prefix.path(),
+            &lock_file,
+            rattler_lock::DEFAULT_ENVIRONMENT_NAME,
+            Platform::EmscriptenWasm32,
+            PackageCache::new(cache.path()),
+            client,
+        )
+        .await;
+        assert_matches!(result, Err(InstallLockFileError::PlatformNotFound(_, _)));
+    }
+}
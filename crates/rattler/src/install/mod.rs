@@ -17,12 +17,18 @@
 //! is used to verify that the file was not tampered with.
 pub mod apple_codesign;
 mod clobber_registry;
+mod conda_meta;
 mod driver;
 mod entry_point;
+mod frozen;
 pub mod link;
 pub mod link_script;
+mod lock_file;
+mod pyc;
 mod python;
+mod remove_environment;
 mod transaction;
+mod trust;
 pub mod unlink;
 
 mod installer;
@@ -40,27 +46,47 @@ use std::{
 };
 
 pub use apple_codesign::AppleCodeSignBehavior;
+pub use clobber_registry::ClobberedPath;
+pub use conda_meta::{recover_conda_meta, CONDA_META_STAGING_PREFIX};
 pub use driver::InstallDriver;
+pub use frozen::{FrozenMarker, FrozenPrefixError, FROZEN_MARKER_PATH};
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+#[cfg(feature = "gateway")]
+pub use installer::GatewayReporter;
 #[cfg(feature = "indicatif")]
 pub use installer::{
     DefaultProgressFormatter, IndicatifReporter, IndicatifReporterBuilder, Placement,
     ProgressFormatter,
 };
-pub use installer::{Installer, InstallerError, Reporter};
+pub use installer::{
+    InstallPolicy, InstallationResult, Installer, InstallerError, PlannedTransaction,
+    PolicyConfirmation, PolicyDecision, Reporter,
+};
 use itertools::Itertools;
-pub use link::{link_file, LinkFileError, LinkMethod};
-pub use python::PythonInfo;
+pub use link::{link_file, LinkFileError, LinkFileOptions, LinkMethod};
+pub use lock_file::{
+    install_lockfile_environment, install_lockfile_environment_with_installer,
+    InstallLockFileError, LockFileInstallationResult,
+};
+pub use pyc::{PycCompilationFailure, PycCompilationResult, PycCompiler};
+pub use python::{PythonInfo, PythonInfoError};
 use rattler_conda_types::{
     package::{IndexJson, LinkJson, NoArchLinks, PackageFile, PathsJson},
     prefix_record::PathsEntry,
     Platform,
 };
+pub use remove_environment::{
+    remove_environment, remove_environment_with_policy, RemoveEnvironmentError,
+    RemoveEnvironmentReport,
+};
 use simple_spawn_blocking::Cancelled;
 use tokio::task::JoinError;
 use tracing::instrument;
 pub use transaction::{Transaction, TransactionError, TransactionOperation};
-pub use unlink::unlink_package;
+#[cfg(feature = "gateway")]
+pub use trust::SignedPackages;
+pub use trust::{AllowedHashes, PackageVerificationError, PackageVerifier, TrustPolicy};
+pub use unlink::{unlink_package, unlink_package_with_policy, ModifiedFilePolicy, PreservedFile};
 
 use crate::install::entry_point::{
     create_unix_python_entry_point, create_windows_python_entry_point,
@@ -90,6 +116,22 @@ pub enum InstallError {
     #[error("failed to link '{0}'")]
     FailedToLink(PathBuf, #[source] LinkFileError),
 
+    /// A file could not be linked because its path exceeds the maximum path
+    /// length of the filesystem. On Windows this can be resolved by enabling
+    /// [`InstallOptions::windows_long_paths`].
+    #[error("failed to link '{0}' because the path is too long")]
+    PathTooLong(PathBuf, #[source] LinkFileError),
+
+    /// A file could not be linked because the process lacks the required
+    /// permissions.
+    #[error("failed to link '{0}' because permission was denied")]
+    PermissionDenied(PathBuf, #[source] LinkFileError),
+
+    /// A file could not be linked because there is no space left on the
+    /// device.
+    #[error("failed to link '{0}' because there is not enough disk space")]
+    OutOfDiskSpace(PathBuf, #[source] LinkFileError),
+
     /// A directory could not be created.
     #[error("failed to create directory '{0}")]
     FailedToCreateDirectory(PathBuf, #[source] std::io::Error),
@@ -117,6 +159,52 @@ pub enum InstallError {
     PostProcessFailed(#[source] std::io::Error),
 }
 
+impl InstallError {
+    /// Constructs an error from a failure to link the file at the given path.
+    /// The error is classified based on the underlying IO error.
+    fn from_link_error(path: PathBuf, err: LinkFileError) -> Self {
+        match err.io_error() {
+            Some(io_err) if io_err.kind() == ErrorKind::PermissionDenied => {
+                InstallError::PermissionDenied(path, err)
+            }
+            Some(io_err) if is_path_too_long(io_err) => InstallError::PathTooLong(path, err),
+            Some(io_err) if is_out_of_disk_space(io_err) => InstallError::OutOfDiskSpace(path, err),
+            _ => InstallError::FailedToLink(path, err),
+        }
+    }
+}
+
+/// Returns true if the error indicates that a path exceeds the maximum path
+/// length (`ERROR_FILENAME_EXCED_RANGE` on Windows, `ENAMETOOLONG` on unix).
+fn is_path_too_long(err: &std::io::Error) -> bool {
+    #[cfg(windows)]
+    return err.raw_os_error() == Some(206);
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return err.raw_os_error() == Some(36);
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+    return err.raw_os_error() == Some(63);
+    #[cfg(not(any(windows, unix)))]
+    return {
+        let _ = err;
+        false
+    };
+}
+
+/// Returns true if the error indicates that there is no space left on the
+/// device (`ERROR_HANDLE_DISK_FULL` or `ERROR_DISK_FULL` on Windows, `ENOSPC`
+/// on unix).
+fn is_out_of_disk_space(err: &std::io::Error) -> bool {
+    #[cfg(windows)]
+    return matches!(err.raw_os_error(), Some(39 | 112));
+    #[cfg(unix)]
+    return err.raw_os_error() == Some(28);
+    #[cfg(not(any(windows, unix)))]
+    return {
+        let _ = err;
+        false
+    };
+}
+
 impl From<Cancelled> for InstallError {
     fn from(_: Cancelled) -> Self {
         InstallError::Cancelled
@@ -240,6 +328,17 @@ pub struct InstallOptions {
     /// used to sign with an ad-hoc certificate. Ad-hoc signing does not use
     /// an identity at all, and identifies exactly one instance of code.
     pub apple_codesign_behavior: AppleCodeSignBehavior,
+
+    /// Whether to use extended-length paths (paths prefixed with `\\?\`)
+    /// when linking files on Windows. This allows installing packages with
+    /// paths that exceed the 260 character `MAX_PATH` limit even if long path
+    /// support is not enabled system-wide. Has no effect on other platforms.
+    pub windows_long_paths: bool,
+
+    /// Determines what happens to files that were modified after they were
+    /// installed when the package they belong to is removed or updated. By
+    /// default modified files are removed.
+    pub modified_file_policy: ModifiedFilePolicy,
 }
 
 /// Given an extracted package archive (`package_dir`), installs its files to
@@ -336,10 +435,14 @@ pub async fn link_package(
     }
 
     let directories_target_dir = target_dir.to_path_buf();
+    let windows_long_paths = options.windows_long_paths;
     driver
         .run_blocking_io_task(move || {
             for directory in directories_to_construct.into_iter().sorted() {
-                let full_path = directories_target_dir.join(directory);
+                let mut full_path = directories_target_dir.join(directory);
+                if windows_long_paths {
+                    full_path = link::extended_length_path(&full_path);
+                }
                 match fs::create_dir(&full_path) {
                     Ok(_) => (),
                     Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
@@ -382,6 +485,9 @@ pub async fn link_package(
                     allow_ref_links && !cloned_entry.no_link,
                     platform,
                     options.apple_codesign_behavior,
+                    LinkFileOptions {
+                        windows_long_paths: options.windows_long_paths,
+                    },
                 )
             })
             .await
@@ -389,7 +495,10 @@ pub async fn link_package(
             {
                 Ok(Ok(linked_file)) => linked_file,
                 Ok(Err(e)) => {
-                    return Err(InstallError::FailedToLink(entry.relative_path.clone(), e))
+                    return Err(InstallError::from_link_error(
+                        entry.relative_path.clone(),
+                        e,
+                    ))
                 }
                 Err(Ok(payload)) => std::panic::resume_unwind(payload),
                 Err(Err(_err)) => return Err(InstallError::Cancelled),
@@ -433,7 +542,17 @@ pub async fn link_package(
     if let Some(link_json) = link_json {
         // Parse the `link.json` file and extract entry points from it.
         let entry_points = match link_json.noarch {
-            NoArchLinks::Python(entry_points) => entry_points.entry_points,
+            NoArchLinks::Python(entry_points) => {
+                // Invalid entry points are still installed, like conda does, but they
+                // might not work as expected.
+                if let Err(err) = entry_points.validate() {
+                    tracing::warn!(
+                        "{} contains an invalid entry point: {err}",
+                        index_json.name.as_source()
+                    );
+                }
+                entry_points.entry_points
+            }
             NoArchLinks::Generic => {
                 unreachable!("we only use link.json for noarch: python packages")
             }
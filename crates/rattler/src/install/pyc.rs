@@ -0,0 +1,264 @@
+//! Compilation of python source files to bytecode (`.pyc` files).
+//!
+//! Compilation is performed by a pool of python interpreters that is shared
+//! by all packages of a transaction. Each interpreter reads paths from its
+//! stdin and reports the result of compiling each individual file together
+//! with the location of the generated bytecode file. A file that
+//! fails to compile (or even crashes the interpreter) does not affect the
+//! compilation of other files.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{BufRead, BufReader, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::Mutex,
+};
+
+/// The script that is executed by each worker. It reads file paths from stdin
+/// and writes the result of compiling each file as a single line to stdout.
+const COMPILE_SCRIPT: &str = r#"
+import sys, py_compile
+for line in sys.stdin:
+    path = line.rstrip("\r\n")
+    try:
+        pyc = py_compile.compile(path, doraise=True)
+        print("ok\t" + str(pyc), flush=True)
+    except BaseException as e:
+        print("err\t" + str(e).replace("\r", " ").replace("\n", " "), flush=True)
+"#;
+
+/// A file that could not be compiled to bytecode.
+#[derive(Debug, Clone)]
+pub struct PycCompilationFailure {
+    /// The path of the python source file.
+    pub path: PathBuf,
+
+    /// A description of why the file could not be compiled.
+    pub reason: String,
+}
+
+/// The result of compiling python source files to bytecode.
+#[derive(Debug, Clone, Default)]
+pub struct PycCompilationResult {
+    /// The files that were successfully compiled.
+    pub compiled: Vec<PathBuf>,
+
+    /// The location of the bytecode file that was generated for each of the
+    /// compiled files.
+    pub bytecode_paths: HashMap<PathBuf, PathBuf>,
+
+    /// The files that could not be compiled.
+    pub failed: Vec<PycCompilationFailure>,
+}
+
+/// Compiles python source files using a pool of python interpreters.
+#[derive(Debug, Clone)]
+pub struct PycCompiler {
+    python: PathBuf,
+    max_workers: NonZeroUsize,
+}
+
+impl PycCompiler {
+    /// Constructs a new compiler that uses the given python interpreter. By
+    /// default, as many interpreters are started as there are cpus available.
+    pub fn new(python: impl Into<PathBuf>) -> Self {
+        Self {
+            python: python.into(),
+            max_workers: std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+        }
+    }
+
+    /// Sets the maximum number of python interpreters that compile files
+    /// concurrently.
+    #[must_use]
+    pub fn with_max_workers(self, max_workers: NonZeroUsize) -> Self {
+        Self {
+            max_workers,
+            ..self
+        }
+    }
+
+    /// Returns the path to the python interpreter used by this instance.
+    pub fn python(&self) -> &Path {
+        &self.python
+    }
+
+    /// Compiles all the given files. This function blocks until all files have
+    /// been processed.
+    ///
+    /// Failures are isolated per file: a file that cannot be compiled is
+    /// reported in [`PycCompilationResult::failed`] and does not prevent other
+    /// files from being compiled.
+    pub fn compile(&self, files: impl IntoIterator<Item = PathBuf>) -> PycCompilationResult {
+        let queue = Mutex::new(files.into_iter().collect::<VecDeque<_>>());
+        let worker_count = queue.lock().unwrap().len().min(self.max_workers.get());
+        let result = Mutex::new(PycCompilationResult::default());
+
+        std::thread::scope(|s| {
+            for _ in 0..worker_count {
+                s.spawn(|| self.run_worker(&queue, &result));
+            }
+        });
+
+        result.into_inner().unwrap()
+    }
+
+    /// Takes files from the queue and compiles them until the queue is empty.
+    fn run_worker(&self, queue: &Mutex<VecDeque<PathBuf>>, result: &Mutex<PycCompilationResult>) {
+        let mut worker: Option<Worker> = None;
+        loop {
+            let Some(path) = queue.lock().unwrap().pop_front() else {
+                break;
+            };
+
+            // Start a new interpreter if there is no running interpreter.
+            let current_worker = match worker.as_mut() {
+                Some(worker) => worker,
+                None => match Worker::spawn(&self.python) {
+                    Ok(new_worker) => worker.insert(new_worker),
+                    Err(err) => {
+                        result.lock().unwrap().failed.push(PycCompilationFailure {
+                            path,
+                            reason: format!(
+                                "failed to start python interpreter '{}': {err}",
+                                self.python.display()
+                            ),
+                        });
+                        continue;
+                    }
+                },
+            };
+
+            match current_worker.compile(&path) {
+                Ok(Ok(bytecode_path)) => {
+                    let mut result = result.lock().unwrap();
+                    result.bytecode_paths.insert(path.clone(), bytecode_path);
+                    result.compiled.push(path);
+                }
+                Ok(Err(reason)) => result
+                    .lock()
+                    .unwrap()
+                    .failed
+                    .push(PycCompilationFailure { path, reason }),
+                Err(err) => {
+                    // The interpreter is in an unknown state, discard it and start a new one
+                    // for the next file.
+                    worker = None;
+                    result.lock().unwrap().failed.push(PycCompilationFailure {
+                        path,
+                        reason: format!("the python interpreter stopped unexpectedly: {err}"),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// A single python interpreter that compiles files.
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Worker {
+    fn spawn(python: &Path) -> std::io::Result<Self> {
+        let mut child = Command::new(python)
+            .arg("-Wi")
+            .arg("-c")
+            .arg(COMPILE_SCRIPT)
+            .env("PYTHONIOENCODING", "utf-8")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Compiles a single file. The outer result indicates whether the
+    /// interpreter is still functional, the inner result indicates whether the
+    /// file was successfully compiled and contains the path of the bytecode
+    /// file.
+    fn compile(&mut self, path: &Path) -> std::io::Result<Result<PathBuf, String>> {
+        let Some(path_str) = path.to_str() else {
+            return Ok(Err("the path is not valid UTF-8".to_string()));
+        };
+        if path_str.contains(['\n', '\r']) {
+            return Ok(Err("the path contains a newline".to_string()));
+        }
+
+        writeln!(self.stdin, "{path_str}")?;
+        self.stdin.flush()?;
+
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let line = line.trim_end_matches(['\r', '\n']);
+        match line.split_once('\t') {
+            Some(("ok", bytecode_path)) => Ok(Ok(PathBuf::from(bytecode_path))),
+            Some(("err", reason)) => Ok(Err(reason.to_string())),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unexpected output from python interpreter: {line}"),
+            )),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // The interpreter is idle when the worker is dropped, so it can safely be killed.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroUsize;
+
+    use super::PycCompiler;
+
+    #[test]
+    fn test_missing_interpreter() {
+        let compiler = PycCompiler::new("/this/python/does/not/exist")
+            .with_max_workers(NonZeroUsize::new(2).unwrap());
+        let result = compiler.compile(["a.py".into(), "b.py".into(), "c.py".into()]);
+        assert!(result.compiled.is_empty());
+        assert_eq!(result.failed.len(), 3);
+    }
+
+    /// Requires a python interpreter on the system, run with `--ignored`.
+    #[test]
+    #[ignore]
+    fn test_compile_isolates_failures() {
+        let python = if cfg!(windows) {
+            "python.exe"
+        } else {
+            "python3"
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good.py");
+        let bad = dir.path().join("bad.py");
+        let missing = dir.path().join("missing.py");
+        std::fs::write(&good, "def main():\n    return 1\n").unwrap();
+        std::fs::write(&bad, "def main(:\n").unwrap();
+
+        let result = PycCompiler::new(python).compile([good.clone(), bad.clone(), missing]);
+        assert_eq!(result.compiled, vec![good.clone()]);
+        assert!(result.bytecode_paths[&good].is_file());
+        assert_eq!(result.failed.len(), 2);
+        assert!(dir.path().join("__pycache__").is_dir());
+    }
+}
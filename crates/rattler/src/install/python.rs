@@ -1,4 +1,4 @@
-use rattler_conda_types::{Platform, Version};
+use rattler_conda_types::{PackageRecord, Platform, Version};
 use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 
@@ -22,8 +22,10 @@ pub struct PythonInfo {
     pub bin_dir: PathBuf,
 }
 
+/// An error that can occur when determining the [`PythonInfo`] of a python package.
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum PythonInfoError {
+    /// The version of the python package does not have a major and minor version.
     #[error("invalid python version '{0}'")]
     InvalidVersion(String),
 }
@@ -67,6 +69,20 @@ impl PythonInfo {
         })
     }
 
+    /// Build an instance based on the record of the python package and the platform it is
+    /// installed for. Unlike [`PythonInfo::from_version`] this also respects the
+    /// `python_site_packages_path` of the record if it is set.
+    pub fn from_python_record(
+        record: &PackageRecord,
+        platform: Platform,
+    ) -> Result<Self, PythonInfoError> {
+        let mut info = Self::from_version(&record.version, platform)?;
+        if let Some(site_packages_path) = &record.python_site_packages_path {
+            info.site_packages_path = PathBuf::from(site_packages_path);
+        }
+        Ok(info)
+    }
+
     /// Returns the path to the python executable
     pub fn path(&self) -> &Path {
         &self.path
@@ -106,5 +122,35 @@ impl PythonInfo {
     pub fn is_relink_required(&self, previous: &PythonInfo) -> bool {
         self.short_version.0 != previous.short_version.0
             || self.short_version.1 != previous.short_version.1
+            || self.site_packages_path != previous.site_packages_path
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rattler_conda_types::PackageName;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_python_site_packages_path() {
+        let mut record = PackageRecord::new(
+            PackageName::new_unchecked("python"),
+            Version::from_str("3.13.0").unwrap(),
+            String::from("h0_cp313"),
+        );
+        let default = PythonInfo::from_python_record(&record, Platform::Linux64).unwrap();
+        assert_eq!(
+            default.site_packages_path,
+            Path::new("lib/python3.13/site-packages")
+        );
+
+        record.python_site_packages_path = Some(String::from("lib/python3.13t/site-packages"));
+        let free_threaded = PythonInfo::from_python_record(&record, Platform::Linux64).unwrap();
+        assert_eq!(
+            free_threaded.get_python_noarch_target_path(Path::new("site-packages/foo/__init__.py")),
+            Path::new("lib/python3.13t/site-packages/foo/__init__.py")
+        );
+        assert!(free_threaded.is_relink_required(&default));
     }
 }
@@ -0,0 +1,459 @@
+//! Removing a complete environment from disk.
+
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use rattler_conda_types::{PackageName, Platform, PrefixRecord, PREFIX_INDEX_FILE_NAME};
+use simple_spawn_blocking::{tokio::run_blocking_task, Cancelled};
+
+use super::{
+    link_script::{run_link_scripts, LinkScriptError, LinkScriptType},
+    unlink::{unlink_package_with_policy, UnlinkError},
+    FrozenMarker, FrozenPrefixError, ModifiedFilePolicy, PreservedFile,
+};
+
+/// Error that can occur while removing an environment.
+#[derive(Debug, thiserror::Error)]
+pub enum RemoveEnvironmentError {
+    /// The directory does not contain a `conda-meta` directory.
+    #[error("'{0}' is not a conda environment")]
+    NotAnEnvironment(PathBuf),
+
+    /// The directory is a path that must never be removed, like the root of
+    /// the filesystem or the home directory of the user.
+    #[error("refusing to remove '{0}'")]
+    ProtectedDirectory(PathBuf),
+
+    /// The environment is marked as frozen.
+    #[error(transparent)]
+    FrozenPrefix(#[from] FrozenPrefixError),
+
+    /// Failed to determine whether the environment is frozen.
+    #[error("failed to read the frozen marker of the environment")]
+    FailedToReadFrozenMarker(#[source] std::io::Error),
+
+    /// Failed to read the prefix records of the environment.
+    #[error("failed to read the installed packages of the environment")]
+    FailedToReadPrefixRecords(#[source] std::io::Error),
+
+    /// Failed to run the pre-unlink scripts of the packages.
+    #[error(transparent)]
+    PreUnlinkScriptError(#[from] LinkScriptError),
+
+    /// Failed to unlink a package or to remove a generated file.
+    #[error(transparent)]
+    UnlinkError(#[from] UnlinkError),
+
+    /// The operation was cancelled.
+    #[error("the operation was cancelled")]
+    Cancelled,
+}
+
+impl From<Cancelled> for RemoveEnvironmentError {
+    fn from(_: Cancelled) -> Self {
+        RemoveEnvironmentError::Cancelled
+    }
+}
+
+/// The result of [`remove_environment`].
+#[derive(Debug, Default, Clone)]
+pub struct RemoveEnvironmentReport {
+    /// The packages that were removed from the environment.
+    pub removed_packages: Vec<PackageName>,
+
+    /// The files of packages that were preserved because they were modified.
+    pub preserved_files: Vec<PreservedFile>,
+
+    /// The files that are still present in the environment after all packages
+    /// have been removed, relative to the prefix. These are files that do not
+    /// belong to any package, e.g. files created by the user.
+    pub remaining_files: Vec<PathBuf>,
+
+    /// True if the prefix directory itself was removed. This is only the case
+    /// if nothing was left behind.
+    pub removed_prefix: bool,
+}
+
+/// Removes the environment at `prefix` from disk.
+///
+/// All packages that are recorded in `conda-meta` are unlinked, after running
+/// their pre-unlink scripts. Afterwards files that were generated during the
+/// installation, like compiled python bytecode, are removed together with all
+/// empty directories. Files that do not belong to any package, as well as
+/// package files that were modified after they were installed, are left
+/// intact and are reported in the returned [`RemoveEnvironmentReport`]. The
+/// prefix directory itself is only removed when it is empty.
+///
+/// Before anything is removed this function verifies that `prefix` is an
+/// environment, that it is not a directory that should never be removed and
+/// that the environment is not frozen (see [`FrozenMarker`]).
+pub async fn remove_environment(
+    prefix: &Path,
+) -> Result<RemoveEnvironmentReport, RemoveEnvironmentError> {
+    remove_environment_with_policy(prefix, ModifiedFilePolicy::Keep).await
+}
+
+/// Removes the environment at `prefix` from disk like [`remove_environment`].
+/// Files that were modified after they were installed are handled according
+/// to `policy`.
+pub async fn remove_environment_with_policy(
+    prefix: &Path,
+    policy: ModifiedFilePolicy,
+) -> Result<RemoveEnvironmentReport, RemoveEnvironmentError> {
+    let owned_prefix = prefix.to_path_buf();
+    let (prefix_records, pycache_directories) =
+        run_blocking_task(move || prepare_removal(&owned_prefix)).await?;
+
+    let mut report = RemoveEnvironmentReport::default();
+    for record in prefix_records {
+        let preserved = unlink_package_with_policy(prefix, &record, policy).await?;
+        report.preserved_files.extend(preserved);
+        report
+            .removed_packages
+            .push(record.repodata_record.package_record.name);
+    }
+
+    let owned_prefix = prefix.to_path_buf();
+    run_blocking_task(move || finish_removal(&owned_prefix, &pycache_directories, report)).await
+}
+
+/// Verifies that the environment can be removed, runs the pre-unlink scripts
+/// of its packages and returns the prefix records together with the
+/// directories that may contain compiled python bytecode.
+fn prepare_removal(
+    prefix: &Path,
+) -> Result<(Vec<PrefixRecord>, HashSet<PathBuf>), RemoveEnvironmentError> {
+    check_can_remove(prefix)?;
+
+    let mut prefix_records = PrefixRecord::collect_from_prefix(prefix)
+        .map_err(RemoveEnvironmentError::FailedToReadPrefixRecords)?;
+    prefix_records.sort_by(|a, b| {
+        a.repodata_record
+            .package_record
+            .name
+            .cmp(&b.repodata_record.package_record.name)
+    });
+
+    let result = run_link_scripts(
+        LinkScriptType::PreUnlink,
+        prefix_records.iter(),
+        prefix,
+        &Platform::current(),
+    )?;
+    for package in result.failed_packages {
+        tracing::warn!(
+            "the pre-unlink script of {} failed",
+            package.as_normalized()
+        );
+    }
+
+    // The bytecode of the python files of the packages is compiled next to the
+    // files, determine where before the records are consumed.
+    let pycache_directories = prefix_records
+        .iter()
+        .flat_map(|record| &record.paths_data.paths)
+        .filter(|entry| {
+            entry
+                .relative_path
+                .extension()
+                .is_some_and(|ext| ext == "py")
+        })
+        .filter_map(|entry| entry.relative_path.parent())
+        .map(|parent| prefix.join(parent).join("__pycache__"))
+        .collect::<HashSet<_>>();
+
+    Ok((prefix_records, pycache_directories))
+}
+
+/// Removes the files that were generated while the environment was used and
+/// the prefix itself if nothing was left behind.
+fn finish_removal(
+    prefix: &Path,
+    pycache_directories: &HashSet<PathBuf>,
+    mut report: RemoveEnvironmentReport,
+) -> Result<RemoveEnvironmentReport, RemoveEnvironmentError> {
+    for directory in pycache_directories {
+        remove_generated_files(directory)?;
+    }
+    let conda_meta = prefix.join("conda-meta");
+    remove_if_exists(&conda_meta.join("history"))?;
+    remove_if_exists(&conda_meta.join(PREFIX_INDEX_FILE_NAME))?;
+    remove_empty_directories(prefix, prefix)?;
+
+    collect_files(prefix, prefix, &mut report.remaining_files)?;
+    report.remaining_files.sort();
+    if report.remaining_files.is_empty() {
+        match std::fs::remove_dir(prefix) {
+            Ok(()) => report.removed_prefix = true,
+            Err(e) => tracing::debug!("failed to remove {}: {e}", prefix.display()),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Verifies that `prefix` is an environment that can safely be removed.
+fn check_can_remove(prefix: &Path) -> Result<(), RemoveEnvironmentError> {
+    let canonical_prefix = std::fs::canonicalize(prefix)
+        .map_err(|_err| RemoveEnvironmentError::NotAnEnvironment(prefix.to_path_buf()))?;
+    let is_protected = canonical_prefix.parent().is_none()
+        || dirs::home_dir()
+            .and_then(|home| std::fs::canonicalize(home).ok())
+            .is_some_and(|home| home == canonical_prefix);
+    if is_protected {
+        return Err(RemoveEnvironmentError::ProtectedDirectory(
+            prefix.to_path_buf(),
+        ));
+    }
+
+    if !prefix.join("conda-meta").is_dir() {
+        return Err(RemoveEnvironmentError::NotAnEnvironment(
+            prefix.to_path_buf(),
+        ));
+    }
+
+    if let Some(marker) = FrozenMarker::from_prefix(prefix)
+        .map_err(RemoveEnvironmentError::FailedToReadFrozenMarker)?
+    {
+        return Err(FrozenPrefixError::Frozen {
+            prefix: prefix.to_path_buf(),
+            marker,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+fn read_dir(directory: &Path) -> Result<Vec<std::fs::DirEntry>, UnlinkError> {
+    std::fs::read_dir(directory)
+        .and_then(Iterator::collect)
+        .map_err(|e| UnlinkError::FailedToReadDirectory(directory.to_string_lossy().to_string(), e))
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), UnlinkError> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(UnlinkError::FailedToDeleteFile(
+            path.to_string_lossy().to_string(),
+            e,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the path of the python source file that the compiled bytecode
+/// file at `path` was generated from, e.g.
+/// `foo/__pycache__/bar.cpython-311.pyc` is compiled from `foo/bar.py`.
+fn pyc_source_path(path: &Path) -> Option<PathBuf> {
+    if path.extension()? != "pyc" {
+        return None;
+    }
+    let parent = path.parent()?;
+    let file_name = path.file_name()?.to_str()?;
+    if parent.file_name()? == "__pycache__" {
+        let module = file_name.split('.').next()?;
+        Some(parent.parent()?.join(format!("{module}.py")))
+    } else {
+        Some(path.with_extension("py"))
+    }
+}
+
+/// Removes the compiled python bytecode files in the `__pycache__`
+/// `directory` for which the source file no longer exists.
+fn remove_generated_files(directory: &Path) -> Result<(), UnlinkError> {
+    if !directory.is_dir() {
+        return Ok(());
+    }
+    for entry in read_dir(directory)? {
+        let path = entry.path();
+        if pyc_source_path(&path).is_some_and(|source| !source.exists()) {
+            remove_if_exists(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Recursively removes all empty directories below `directory`.
+fn remove_empty_directories(directory: &Path, prefix: &Path) -> Result<(), UnlinkError> {
+    for entry in read_dir(directory)? {
+        if entry
+            .file_type()
+            .map_or(false, |file_type| file_type.is_dir())
+        {
+            remove_empty_directories(&entry.path(), prefix)?;
+        }
+    }
+    if directory != prefix && read_dir(directory)?.is_empty() {
+        std::fs::remove_dir(directory).map_err(|e| {
+            UnlinkError::FailedToDeleteDirectory(directory.to_string_lossy().to_string(), e)
+        })?;
+    }
+    Ok(())
+}
+
+/// Collects the paths of all files below `directory` relative to `prefix`.
+fn collect_files(
+    directory: &Path,
+    prefix: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), UnlinkError> {
+    for entry in read_dir(directory)? {
+        let path = entry.path();
+        if entry
+            .file_type()
+            .map_or(false, |file_type| file_type.is_dir())
+        {
+            collect_files(&path, prefix, files)?;
+        } else {
+            files.push(path.strip_prefix(prefix).unwrap_or(&path).to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf, str::FromStr};
+
+    use rattler_conda_types::{
+        prefix_record::{PathType, PathsEntry},
+        PackageName, PackageRecord, PrefixRecord, RepoDataRecord, Version, PREFIX_INDEX_FILE_NAME,
+    };
+    use url::Url;
+
+    use super::{remove_environment, RemoveEnvironmentError};
+    use crate::{
+        get_repodata_record, get_test_data_dir,
+        install::{FrozenMarker, Installer},
+        package_cache::PackageCache,
+    };
+
+    fn install_fake_package(prefix: &std::path::Path) -> PrefixRecord {
+        let paths = ["lib/foo/__init__.py", "bin/foo"]
+            .into_iter()
+            .map(|path| {
+                let full_path = prefix.join(path);
+                fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+                fs::write(full_path, b"foo").unwrap();
+                PathsEntry {
+                    relative_path: PathBuf::from(path),
+                    original_path: None,
+                    path_type: PathType::HardLink,
+                    no_link: false,
+                    sha256: None,
+                    sha256_in_prefix: None,
+                    size_in_bytes: None,
+                    file_mode: None,
+                    prefix_placeholder: None,
+                }
+            })
+            .collect();
+
+        let repodata_record = RepoDataRecord {
+            package_record: PackageRecord::new(
+                PackageName::new_unchecked("foo"),
+                Version::from_str("1.0").unwrap(),
+                String::from("0"),
+            ),
+            file_name: String::from("foo-1.0-0.conda"),
+            url: Url::parse("https://example.com/noarch/foo-1.0-0.conda").unwrap(),
+            channel: String::from("https://example.com"),
+        };
+        let prefix_record =
+            PrefixRecord::from_repodata_record(repodata_record, None, None, paths, None, None);
+        let conda_meta = prefix.join("conda-meta");
+        fs::create_dir_all(&conda_meta).unwrap();
+        prefix_record
+            .write_to_path(conda_meta.join(prefix_record.file_name()), true)
+            .unwrap();
+        prefix_record
+    }
+
+    #[tokio::test]
+    async fn test_remove_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("env");
+
+        // A directory that is not an environment is never removed.
+        fs::create_dir_all(&prefix).unwrap();
+        assert!(matches!(
+            remove_environment(&prefix).await,
+            Err(RemoveEnvironmentError::NotAnEnvironment(_))
+        ));
+
+        install_fake_package(&prefix);
+        fs::create_dir_all(prefix.join("lib/foo/__pycache__")).unwrap();
+        fs::write(
+            prefix.join("lib/foo/__pycache__/__init__.cpython-311.pyc"),
+            b"",
+        )
+        .unwrap();
+        fs::write(prefix.join("notes.txt"), b"user file").unwrap();
+
+        // Bytecode that does not belong to a package is left intact.
+        fs::create_dir_all(prefix.join("scripts/__pycache__")).unwrap();
+        fs::write(prefix.join("scripts/__pycache__/tool.cpython-311.pyc"), b"").unwrap();
+
+        // A frozen environment is never removed.
+        FrozenMarker::default().write_to_prefix(&prefix).unwrap();
+        assert!(matches!(
+            remove_environment(&prefix).await,
+            Err(RemoveEnvironmentError::FrozenPrefix(_))
+        ));
+        assert!(FrozenMarker::remove_from_prefix(&prefix).unwrap());
+
+        let report = remove_environment(&prefix).await.unwrap();
+        assert_eq!(report.removed_packages, [PackageName::new_unchecked("foo")]);
+        assert_eq!(
+            report.remaining_files,
+            [
+                PathBuf::from("notes.txt"),
+                PathBuf::from("scripts/__pycache__/tool.cpython-311.pyc")
+            ]
+        );
+        assert!(!report.removed_prefix);
+        assert!(!prefix.join("lib").exists());
+        assert!(!prefix.join("conda-meta").exists());
+
+        // Once the user files are gone the prefix itself is removed.
+        fs::remove_file(prefix.join("notes.txt")).unwrap();
+        fs::remove_dir_all(prefix.join("scripts")).unwrap();
+        install_fake_package(&prefix);
+        let report = remove_environment(&prefix).await.unwrap();
+        assert!(report.remaining_files.is_empty());
+        assert!(report.removed_prefix);
+        assert!(!prefix.exists());
+    }
+
+    #[tokio::test]
+    async fn test_remove_installed_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("env");
+
+        let record = get_repodata_record(
+            get_test_data_dir().join("clobber/clobber-1-0.1.0-h4616a5c_0.tar.bz2"),
+        );
+        Installer::new()
+            .with_package_cache(PackageCache::new(cache_dir.path()))
+            .install(&prefix, [record])
+            .await
+            .unwrap();
+        assert!(prefix
+            .join("conda-meta")
+            .join(PREFIX_INDEX_FILE_NAME)
+            .is_file());
+
+        let report = remove_environment(&prefix).await.unwrap();
+        assert!(
+            report.remaining_files.is_empty(),
+            "{:?}",
+            report.remaining_files
+        );
+        assert!(report.removed_prefix);
+        assert!(!prefix.exists());
+    }
+}
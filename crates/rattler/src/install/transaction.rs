@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::install::python::PythonInfoError;
 use crate::install::PythonInfo;
@@ -113,6 +113,52 @@ impl<Old: AsRef<New>, New> Transaction<Old, New> {
 impl<Old: AsRef<PackageRecord>, New: AsRef<PackageRecord>> Transaction<Old, New> {
     /// Constructs a [`Transaction`] by taking the current situation and diffing that against the
     /// desired situation.
+    ///
+    /// Packages are identified by their name. The operations are derived as follows:
+    ///
+    /// * A package that is only part of `current` is removed with
+    ///   [`TransactionOperation::Remove`]. Removals come first, in the reverse order of
+    ///   `current`.
+    /// * A package that is only part of `desired` is installed with
+    ///   [`TransactionOperation::Install`].
+    /// * A package that is part of both but whose content differs is replaced with
+    ///   [`TransactionOperation::Change`]. The content is compared by the `sha256` or `md5`
+    ///   hashes of the records if both records have one, otherwise by their size, name, version
+    ///   and build string.
+    /// * A `noarch: python` package that is part of both with the same content is relinked with
+    ///   [`TransactionOperation::Reinstall`] if the python version of the environment changes
+    ///   in a way that requires it, see [`PythonInfo::is_relink_required`].
+    ///
+    /// Installs, changes and reinstalls keep the order of `desired`. Packages that are part of
+    /// both with the same content result in no operation.
+    ///
+    /// ```rust
+    /// # use rattler::install::{Transaction, TransactionOperation};
+    /// # use rattler_conda_types::{PackageName, PackageRecord, Platform, RepoDataRecord, Version};
+    /// # use std::str::FromStr;
+    /// # let record = |name: &str| RepoDataRecord {
+    /// #     package_record: PackageRecord::new(
+    /// #         PackageName::new_unchecked(name),
+    /// #         Version::from_str("1.0").unwrap(),
+    /// #         String::from("0"),
+    /// #     ),
+    /// #     file_name: format!("{name}-1.0-0.conda"),
+    /// #     url: format!("https://example.com/{name}-1.0-0.conda").parse().unwrap(),
+    /// #     channel: String::from("https://example.com/"),
+    /// # };
+    /// let current = vec![record("foo"), record("bar")];
+    /// let desired = vec![record("bar"), record("baz")];
+    ///
+    /// let transaction =
+    ///     Transaction::from_current_and_desired(current, desired, Platform::Linux64).unwrap();
+    /// assert_eq!(
+    ///     transaction.operations,
+    ///     [
+    ///         TransactionOperation::Remove(record("foo")),
+    ///         TransactionOperation::Install(record("baz")),
+    ///     ]
+    /// );
+    /// ```
     pub fn from_current_and_desired<
         CurIter: IntoIterator<Item = Old>,
         NewIter: IntoIterator<Item = New>,
@@ -120,17 +166,13 @@ impl<Old: AsRef<PackageRecord>, New: AsRef<PackageRecord>> Transaction<Old, New>
         current: CurIter,
         desired: NewIter,
         platform: Platform,
-    ) -> Result<Self, TransactionError>
-    where
-        CurIter::IntoIter: Clone,
-        NewIter::IntoIter: Clone,
-    {
-        let current_iter = current.into_iter();
-        let desired_iter = desired.into_iter();
+    ) -> Result<Self, TransactionError> {
+        let current = current.into_iter().collect::<Vec<_>>();
+        let desired = desired.into_iter().collect::<Vec<_>>();
 
         // Determine the python version used in the current situation.
-        let current_python_info = find_python_info(current_iter.clone(), platform)?;
-        let desired_python_info = find_python_info(desired_iter.clone(), platform)?;
+        let current_python_info = find_python_info(&current, platform)?;
+        let desired_python_info = find_python_info(&desired, platform)?;
         let needs_python_relink = match (&current_python_info, &desired_python_info) {
             (Some(current), Some(desired)) => desired.is_relink_required(current),
             _ => false,
@@ -138,19 +180,17 @@ impl<Old: AsRef<PackageRecord>, New: AsRef<PackageRecord>> Transaction<Old, New>
 
         let mut operations = Vec::new();
 
-        let mut current_map = current_iter
-            .clone()
-            .map(|r| (r.as_ref().name.clone(), r))
-            .collect::<std::collections::HashMap<_, _>>();
-
-        let desired_names = desired_iter
-            .clone()
+        let desired_names = desired
+            .iter()
             .map(|r| r.as_ref().name.clone())
             .collect::<HashSet<_>>();
 
         // Remove all current packages that are not in desired (but keep order of current)
-        for record in current_iter {
-            if !desired_names.contains(&record.as_ref().name) {
+        let mut current_map = HashMap::new();
+        for record in current {
+            if desired_names.contains(&record.as_ref().name) {
+                current_map.insert(record.as_ref().name.clone(), record);
+            } else {
                 operations.push(TransactionOperation::Remove(record));
             }
         }
@@ -159,7 +199,7 @@ impl<Old: AsRef<PackageRecord>, New: AsRef<PackageRecord>> Transaction<Old, New>
         operations.reverse();
 
         // Figure out the operations to perform, but keep the order of the original "desired" iterator
-        for record in desired_iter {
+        for record in desired {
             let name = &record.as_ref().name;
             let old_record = current_map.remove(name);
 
@@ -199,7 +239,7 @@ fn find_python_info(
     records
         .into_iter()
         .find(|r| is_python_record(r.as_ref()))
-        .map(|record| PythonInfo::from_version(&record.as_ref().version, platform))
+        .map(|record| PythonInfo::from_python_record(record.as_ref(), platform))
         .map_or(Ok(None), |info| info.map(Some))
 }
 
@@ -219,10 +259,188 @@ fn describe_same_content(from: &PackageRecord, to: &PackageRecord) -> bool {
     }
 
     // If the size doesnt match, the contents must be different
-    if matches!((from.size.as_ref(), to.size.as_ref()), (Some(a), Some(b)) if a == b) {
+    if matches!((from.size.as_ref(), to.size.as_ref()), (Some(a), Some(b)) if a != b) {
         return false;
     }
 
     // Otherwise, just check that the name, version and build string match
     from.name == to.name && from.version == to.version && from.build == to.build
 }
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use rattler_conda_types::{
+        NoArchType, PackageName, PackageRecord, Platform, RepoDataRecord, Version,
+    };
+    use rattler_digest::{parse_digest_from_hex, Sha256};
+
+    use super::{Transaction, TransactionOperation};
+
+    fn record(name: &str, version: &str) -> RepoDataRecord {
+        let file_name = format!("{name}-{version}-0.conda");
+        RepoDataRecord {
+            package_record: PackageRecord::new(
+                PackageName::new_unchecked(name),
+                Version::from_str(version).unwrap(),
+                String::from("0"),
+            ),
+            url: format!("https://conda.anaconda.org/conda-forge/linux-64/{file_name}")
+                .parse()
+                .unwrap(),
+            file_name,
+            channel: String::from("https://conda.anaconda.org/conda-forge/"),
+        }
+    }
+
+    fn noarch_python(name: &str, version: &str) -> RepoDataRecord {
+        let mut record = record(name, version);
+        record.package_record.noarch = NoArchType::python();
+        record
+    }
+
+    fn operations(
+        current: &[RepoDataRecord],
+        desired: &[RepoDataRecord],
+    ) -> Vec<TransactionOperation<RepoDataRecord, RepoDataRecord>> {
+        Transaction::from_current_and_desired(
+            current.iter().cloned(),
+            desired.iter().cloned(),
+            Platform::Linux64,
+        )
+        .unwrap()
+        .operations
+    }
+
+    #[test]
+    fn test_install_and_remove() {
+        let current = [record("a", "1"), record("b", "1"), record("c", "1")];
+        let desired = [record("d", "1"), record("b", "1"), record("e", "1")];
+        assert_eq!(
+            operations(&current, &desired),
+            [
+                TransactionOperation::Remove(record("c", "1")),
+                TransactionOperation::Remove(record("a", "1")),
+                TransactionOperation::Install(record("d", "1")),
+                TransactionOperation::Install(record("e", "1")),
+            ]
+        );
+
+        assert!(operations(&current, &current).is_empty());
+        assert!(operations(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_change() {
+        assert_eq!(
+            operations(&[record("a", "1")], &[record("a", "2")]),
+            [TransactionOperation::Change {
+                old: record("a", "1"),
+                new: record("a", "2"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_same_content() {
+        let sha256 = |hex: &str| parse_digest_from_hex::<Sha256>(&hex.repeat(64));
+
+        // Records with the same hash describe the same content, even if they come from a
+        // different channel.
+        let mut old = record("a", "1");
+        old.package_record.sha256 = sha256("a");
+        let mut new = record("a", "1");
+        new.package_record.sha256 = sha256("a");
+        new.channel = String::from("https://conda.anaconda.org/other/");
+        assert!(operations(&[old.clone()], &[new.clone()]).is_empty());
+
+        // A different hash means different content.
+        new.package_record.sha256 = sha256("b");
+        assert_eq!(
+            operations(&[old.clone()], &[new.clone()]),
+            [TransactionOperation::Change { old, new }]
+        );
+
+        // Without hashes, records are compared by name, version and build.
+        let old = record("a", "1");
+        let mut new = record("a", "1");
+        assert!(operations(&[old.clone()], &[new.clone()]).is_empty());
+
+        new.package_record.build = String::from("1");
+        assert_eq!(operations(&[old.clone()], &[new.clone()]).len(), 1);
+    }
+
+    #[test]
+    fn test_same_content_size() {
+        // Without hashes, records of the same size are compared by name, version and build.
+        let mut old = record("a", "1");
+        old.package_record.size = Some(100);
+        let mut new = record("a", "1");
+        new.package_record.size = Some(100);
+        assert!(operations(&[old.clone()], &[new.clone()]).is_empty());
+
+        // Records of a different size differ.
+        new.package_record.size = Some(200);
+        assert_eq!(
+            operations(&[old.clone()], &[new.clone()]),
+            [TransactionOperation::Change { old, new }]
+        );
+    }
+
+    #[test]
+    fn test_python_relink() {
+        let current = [record("python", "3.10.0"), noarch_python("requests", "1")];
+
+        // A change in the minor version of python requires a relink of noarch packages.
+        let desired = [record("python", "3.11.0"), noarch_python("requests", "1")];
+        let transaction = Transaction::from_current_and_desired(
+            current.iter().cloned(),
+            desired.iter().cloned(),
+            Platform::Linux64,
+        )
+        .unwrap();
+        assert_eq!(
+            transaction.operations,
+            [
+                TransactionOperation::Change {
+                    old: record("python", "3.10.0"),
+                    new: record("python", "3.11.0"),
+                },
+                TransactionOperation::Reinstall(noarch_python("requests", "1")),
+            ]
+        );
+        assert_eq!(
+            transaction.current_python_info.unwrap().short_version,
+            (3, 10)
+        );
+        assert_eq!(transaction.python_info.unwrap().short_version, (3, 11));
+
+        // A patch release does not.
+        let desired = [record("python", "3.10.1"), noarch_python("requests", "1")];
+        assert_eq!(
+            operations(&current, &desired),
+            [TransactionOperation::Change {
+                old: record("python", "3.10.0"),
+                new: record("python", "3.10.1"),
+            }]
+        );
+
+        // Neither does removing python.
+        let desired = [noarch_python("requests", "1")];
+        assert_eq!(
+            operations(&current, &desired),
+            [TransactionOperation::Remove(record("python", "3.10.0"))]
+        );
+    }
+
+    #[test]
+    fn test_invalid_python_version() {
+        let result = Transaction::<RepoDataRecord, RepoDataRecord>::from_current_and_desired(
+            [],
+            [record("python", "3")],
+            Platform::Linux64,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Verification of packages before they are installed.
+//!
+//! A [`TrustPolicy`] determines whether the [`crate::install::Installer`]
+//! verifies the records of the packages it installs before they are fetched
+//! into the cache and linked into the prefix. The records are verified by a
+//! [`PackageVerifier`], e.g. an [`AllowedHashes`] list or, with the `gateway`
+//! feature, the [`SignedPackages`] of repodata that was signed following
+//! conda's content trust model.
+//!
+//! The package cache validates a downloaded archive against the sha256 hash
+//! of its record, so verifying the hash of the record also verifies the
+//! contents of packages that are downloaded. Packages that are already
+//! present in the cache are reused without recomputing their hash, the cache
+//! itself must therefore be trusted.
+
+use std::{collections::HashSet, sync::Arc};
+
+#[cfg(feature = "gateway")]
+use std::collections::HashMap;
+
+use rattler_conda_types::RepoDataRecord;
+use rattler_digest::Sha256Hash;
+#[cfg(feature = "gateway")]
+use url::Url;
+
+/// The reason why a package could not be verified.
+#[derive(Debug, thiserror::Error)]
+pub enum PackageVerificationError {
+    /// The record of the package does not contain a sha256 hash.
+    #[error("the record does not contain a sha256 hash")]
+    MissingHash,
+
+    /// The sha256 hash of the package is not allowed.
+    #[error("the sha256 hash {0:x} is not in the list of allowed hashes")]
+    HashNotAllowed(Sha256Hash),
+
+    /// No signed record of the package is known.
+    #[error("the package is not signed")]
+    NotSigned,
+
+    /// The sha256 hash of the package does not match the signed record.
+    #[error("the sha256 hash of the package does not match the signed record")]
+    HashMismatch,
+
+    /// A custom verifier rejected the package.
+    #[error("{0}")]
+    Other(String),
+}
+
+/// A trait to verify the record of a package before it is installed.
+pub trait PackageVerifier: Send + Sync {
+    /// Returns an error if the package described by the record must not be
+    /// installed.
+    fn verify(&self, record: &RepoDataRecord) -> Result<(), PackageVerificationError>;
+}
+
+/// Determines whether packages are verified before they are installed.
+#[derive(Default, Clone)]
+pub enum TrustPolicy {
+    /// Packages are not verified.
+    #[default]
+    Disabled,
+
+    /// Packages are verified but packages that fail the verification are
+    /// installed anyway after a warning has been logged.
+    Warn(Arc<dyn PackageVerifier>),
+
+    /// Packages that fail the verification are not installed.
+    Enforce(Arc<dyn PackageVerifier>),
+}
+
+impl TrustPolicy {
+    /// Verifies the record of a package according to the policy.
+    pub fn verify(&self, record: &RepoDataRecord) -> Result<(), PackageVerificationError> {
+        match self {
+            TrustPolicy::Disabled => Ok(()),
+            TrustPolicy::Warn(verifier) => {
+                if let Err(err) = verifier.verify(record) {
+                    tracing::warn!("failed to verify {}: {err}", record.file_name);
+                }
+                Ok(())
+            }
+            TrustPolicy::Enforce(verifier) => verifier.verify(record),
+        }
+    }
+}
+
+/// A [`PackageVerifier`] that only allows packages with specific sha256
+/// hashes.
+#[derive(Debug, Default, Clone)]
+pub struct AllowedHashes {
+    hashes: HashSet<Sha256Hash>,
+}
+
+impl AllowedHashes {
+    /// Constructs an empty list of allowed hashes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows the package with the given sha256 hash.
+    pub fn insert(&mut self, sha256: Sha256Hash) -> bool {
+        self.hashes.insert(sha256)
+    }
+}
+
+impl FromIterator<Sha256Hash> for AllowedHashes {
+    fn from_iter<T: IntoIterator<Item = Sha256Hash>>(iter: T) -> Self {
+        Self {
+            hashes: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl PackageVerifier for AllowedHashes {
+    fn verify(&self, record: &RepoDataRecord) -> Result<(), PackageVerificationError> {
+        let sha256 = record
+            .package_record
+            .sha256
+            .ok_or(PackageVerificationError::MissingHash)?;
+        if self.hashes.contains(&sha256) {
+            Ok(())
+        } else {
+            Err(PackageVerificationError::HashNotAllowed(sha256))
+        }
+    }
+}
+
+/// A [`PackageVerifier`] that only allows packages whose records are signed
+/// in the repodata of their channel.
+///
+/// The signatures of the records are verified with the keys of a
+/// [`rattler_repodata_gateway::fetch::content_trust::KeyManager`] when the
+/// repodata is added. A package is allowed if its url belongs to a signed
+/// record and its sha256 hash matches the hash of the signed record.
+#[cfg(feature = "gateway")]
+#[derive(Debug, Default, Clone)]
+pub struct SignedPackages {
+    sha256: HashMap<Url, Sha256Hash>,
+}
+
+#[cfg(feature = "gateway")]
+impl SignedPackages {
+    /// Constructs an empty set of signed packages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the records of the repodata of a subdirectory whose signatures
+    /// are valid. Returns the number of records that were added.
+    pub fn add_repo_data(
+        &mut self,
+        key_mgr: &rattler_repodata_gateway::fetch::content_trust::KeyManager,
+        subdir_url: &Url,
+        repo_data: &serde_json::Value,
+    ) -> usize {
+        let signatures = repo_data.get("signatures");
+        let mut added = 0;
+        for key in ["packages", "packages.conda"] {
+            let Some(packages) = repo_data.get(key).and_then(serde_json::Value::as_object) else {
+                continue;
+            };
+            for (file_name, record) in packages {
+                let Some(signatures) = signatures.and_then(|signatures| signatures.get(file_name))
+                else {
+                    continue;
+                };
+                let Some(sha256) = record
+                    .get("sha256")
+                    .and_then(serde_json::Value::as_str)
+                    .and_then(rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>)
+                else {
+                    continue;
+                };
+                let Ok(url) = subdir_url.join(file_name) else {
+                    continue;
+                };
+                if key_mgr.verify_package(record, signatures) {
+                    self.sha256.insert(url, sha256);
+                    added += 1;
+                }
+            }
+        }
+        added
+    }
+}
+
+#[cfg(feature = "gateway")]
+impl PackageVerifier for SignedPackages {
+    fn verify(&self, record: &RepoDataRecord) -> Result<(), PackageVerificationError> {
+        let signed = self
+            .sha256
+            .get(&record.url)
+            .ok_or(PackageVerificationError::NotSigned)?;
+        if record.package_record.sha256.as_ref() == Some(signed) {
+            Ok(())
+        } else {
+            Err(PackageVerificationError::HashMismatch)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{str::FromStr, sync::Arc};
+
+    use assert_matches::assert_matches;
+    use rattler_conda_types::{PackageName, PackageRecord, RepoDataRecord, Version};
+    use rattler_digest::{parse_digest_from_hex, Sha256};
+
+    use super::{AllowedHashes, PackageVerificationError, TrustPolicy};
+
+    fn record(sha256: Option<&str>) -> RepoDataRecord {
+        let mut package_record = PackageRecord::new(
+            PackageName::new_unchecked("foo"),
+            Version::from_str("1.0").unwrap(),
+            String::from("0"),
+        );
+        package_record.sha256 =
+            sha256.map(|sha256| parse_digest_from_hex::<Sha256>(sha256).unwrap());
+        RepoDataRecord {
+            package_record,
+            file_name: String::from("foo-1.0-0.conda"),
+            url: "https://conda.anaconda.org/conda-forge/linux-64/foo-1.0-0.conda"
+                .parse()
+                .unwrap(),
+            channel: String::from("https://conda.anaconda.org/conda-forge/"),
+        }
+    }
+
+    #[test]
+    fn test_allowed_hashes() {
+        let allowed = "0101010101010101010101010101010101010101010101010101010101010101";
+        let other = "0202020202020202020202020202020202020202020202020202020202020202";
+        let verifier = Arc::new(
+            [parse_digest_from_hex::<Sha256>(allowed).unwrap()]
+                .into_iter()
+                .collect::<AllowedHashes>(),
+        );
+
+        let policy = TrustPolicy::Enforce(verifier.clone());
+        policy.verify(&record(Some(allowed))).unwrap();
+        assert_matches!(
+            policy.verify(&record(Some(other))),
+            Err(PackageVerificationError::HashNotAllowed(_))
+        );
+        assert_matches!(
+            policy.verify(&record(None)),
+            Err(PackageVerificationError::MissingHash)
+        );
+
+        // Only warns
+        TrustPolicy::Warn(verifier).verify(&record(None)).unwrap();
+        TrustPolicy::Disabled.verify(&record(None)).unwrap();
+    }
+
+    #[cfg(feature = "gateway")]
+    #[test]
+    fn test_signed_packages() {
+        use rattler_repodata_gateway::fetch::content_trust::TrustedRoot;
+
+        use super::SignedPackages;
+
+        let read = |name: &str| {
+            std::fs::read_to_string(crate::get_test_data_dir().join("content-trust").join(name))
+                .unwrap()
+        };
+        let key_mgr = TrustedRoot::from_json(&read("root.json"))
+            .unwrap()
+            .verify_key_mgr(&read("key_mgr.json"))
+            .unwrap();
+        let repo_data: serde_json::Value = serde_json::from_str(&read("repodata.json")).unwrap();
+
+        // Only `foo` is signed, the record of `bar` was modified after it was
+        // signed and `baz` has no signature.
+        let subdir_url = url::Url::parse("https://conda.anaconda.org/conda-forge/noarch/").unwrap();
+        let mut signed = SignedPackages::new();
+        assert_eq!(signed.add_repo_data(&key_mgr, &subdir_url, &repo_data), 1);
+
+        let signed_record = |file_name: &str, sha256: &str| {
+            let mut record = record(Some(sha256));
+            record.url = subdir_url.join(file_name).unwrap();
+            record.file_name = file_name.to_string();
+            record
+        };
+        let policy = TrustPolicy::Enforce(Arc::new(signed));
+        policy
+            .verify(&signed_record("foo-1.0-0.tar.bz2", &"1".repeat(64)))
+            .unwrap();
+        assert_matches!(
+            policy.verify(&signed_record("foo-1.0-0.tar.bz2", &"2".repeat(64))),
+            Err(PackageVerificationError::HashMismatch)
+        );
+        assert_matches!(
+            policy.verify(&signed_record("bar-2.0-0.conda", &"2".repeat(64))),
+            Err(PackageVerificationError::NotSigned)
+        );
+        assert_matches!(
+            policy.verify(&signed_record("baz-3.0-0.conda", &"3".repeat(64))),
+            Err(PackageVerificationError::NotSigned)
+        );
+    }
+}
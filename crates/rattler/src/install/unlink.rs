@@ -6,7 +6,16 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use rattler_conda_types::PrefixRecord;
+use rattler_conda_types::{
+    prefix_record::{PathType, PathsEntry},
+    PrefixRecord,
+};
+use rattler_digest::{compute_file_digest, Sha256};
+use simple_spawn_blocking::tokio::run_blocking_task;
+
+/// The suffix that is appended to the name of a modified file when it is
+/// backed up. Similar to how conda renames files to `.conda_trash`.
+pub const BACKUP_SUFFIX: &str = ".conda_backup";
 
 /// Error that can occur while unlinking a package.
 #[derive(Debug, thiserror::Error)]
@@ -22,6 +31,46 @@ pub enum UnlinkError {
     /// Failed to read a directory.
     #[error("failed to read directory: {0}")]
     FailedToReadDirectory(String, std::io::Error),
+
+    /// Failed to back up a file that was modified by the user.
+    #[error("failed to back up modified file: {0}")]
+    FailedToBackupFile(String, std::io::Error),
+}
+
+/// Determines what happens to files that were modified after they were
+/// installed when the package they belong to is removed or updated.
+///
+/// Whether a file was modified is determined by comparing the file in the
+/// prefix against the digest that was recorded when the package was
+/// installed.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum ModifiedFilePolicy {
+    /// Modified files are removed like any other file. This is the default
+    /// and does not require the files to be hashed.
+    #[default]
+    Remove,
+
+    /// Modified files are renamed by appending [`BACKUP_SUFFIX`] to their
+    /// file name before the package is removed.
+    Backup,
+
+    /// Modified files are left in place. Note that a kept file is still
+    /// overwritten if a package that is installed afterwards contains a file
+    /// at the same path, use [`ModifiedFilePolicy::Backup`] to retain the
+    /// modifications in that case.
+    Keep,
+}
+
+/// A file that was modified by the user and was therefore preserved when the
+/// package it belongs to was removed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PreservedFile {
+    /// The path of the modified file relative to the prefix.
+    pub relative_path: PathBuf,
+
+    /// The path relative to the prefix the file was moved to, or `None` if the
+    /// file was kept in place.
+    pub backup_path: Option<PathBuf>,
 }
 
 pub(crate) fn recursively_remove_empty_directories(
@@ -98,14 +147,125 @@ pub(crate) fn recursively_remove_empty_directories(
     }
 }
 
+/// Removes a file or a link. On Windows, symbolic links and junctions that
+/// point to a directory cannot be removed with [`tokio::fs::remove_file`].
+async fn remove_file_or_link(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        #[cfg(windows)]
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            match tokio::fs::symlink_metadata(path).await {
+                Ok(metadata) if metadata.is_dir() || metadata.is_symlink() => {
+                    tokio::fs::remove_dir(path).await
+                }
+                _ => Err(e),
+            }
+        }
+        result => result,
+    }
+}
+
+/// Returns true if the file of the entry exists in the prefix and its contents
+/// no longer match the digest that was recorded when it was installed.
+///
+/// The prefix placeholder of a file is replaced when it is linked, so the
+/// digest of the file in the package only applies to files without a
+/// placeholder. Files with a placeholder are only compared against the digest
+/// that was recorded after linking.
+async fn is_modified(target_prefix: &Path, entry: &PathsEntry) -> bool {
+    if matches!(entry.path_type, PathType::SoftLink | PathType::Directory) {
+        return false;
+    }
+    let expected = if entry.prefix_placeholder.is_some() {
+        entry.sha256_in_prefix
+    } else {
+        entry.sha256_in_prefix.or(entry.sha256)
+    };
+    let Some(expected) = expected else {
+        return false;
+    };
+    let path = target_prefix.join(&entry.relative_path);
+    match run_blocking_task(move || compute_file_digest::<Sha256>(&path)).await {
+        Ok(actual) => actual != expected,
+        Err(_) => false,
+    }
+}
+
 /// Completely remove the specified package from the environment.
 pub async fn unlink_package(
     target_prefix: &Path,
     prefix_record: &PrefixRecord,
 ) -> Result<(), UnlinkError> {
+    unlink_package_with_policy(target_prefix, prefix_record, ModifiedFilePolicy::Remove).await?;
+    Ok(())
+}
+
+/// Completely remove the specified package from the environment. Files that
+/// were modified after they were installed are handled according to `policy`.
+///
+/// Returns the files that were preserved because they were modified.
+pub async fn unlink_package_with_policy(
+    target_prefix: &Path,
+    prefix_record: &PrefixRecord,
+    policy: ModifiedFilePolicy,
+) -> Result<Vec<PreservedFile>, UnlinkError> {
+    let preserved = unlink_package_files(target_prefix, prefix_record, policy).await?;
+
+    // Remove the conda-meta file
+    let conda_meta_path = target_prefix
+        .join("conda-meta")
+        .join(prefix_record.file_name());
+
+    tokio::fs::remove_file(&conda_meta_path)
+        .await
+        .map_err(|e| {
+            UnlinkError::FailedToDeleteFile(conda_meta_path.to_string_lossy().to_string(), e)
+        })?;
+
+    Ok(preserved)
+}
+
+/// Removes the files of the specified package from the environment but keeps
+/// its prefix record in `conda-meta`. Files that were modified after they were
+/// installed are handled according to `policy`.
+///
+/// Returns the files that were preserved because they were modified.
+pub(crate) async fn unlink_package_files(
+    target_prefix: &Path,
+    prefix_record: &PrefixRecord,
+    policy: ModifiedFilePolicy,
+) -> Result<Vec<PreservedFile>, UnlinkError> {
+    let mut preserved = Vec::new();
+
     // Remove all entries
     for paths in prefix_record.paths_data.paths.iter() {
-        match tokio::fs::remove_file(target_prefix.join(&paths.relative_path)).await {
+        if policy != ModifiedFilePolicy::Remove && is_modified(target_prefix, paths).await {
+            let backup_path = if policy == ModifiedFilePolicy::Backup {
+                let mut file_name = paths.relative_path.as_os_str().to_owned();
+                file_name.push(BACKUP_SUFFIX);
+                let backup_path = PathBuf::from(file_name);
+                tokio::fs::rename(
+                    target_prefix.join(&paths.relative_path),
+                    target_prefix.join(&backup_path),
+                )
+                .await
+                .map_err(|e| {
+                    UnlinkError::FailedToBackupFile(
+                        paths.relative_path.to_string_lossy().to_string(),
+                        e,
+                    )
+                })?;
+                Some(backup_path)
+            } else {
+                None
+            };
+            preserved.push(PreservedFile {
+                relative_path: paths.relative_path.clone(),
+                backup_path,
+            });
+            continue;
+        }
+
+        match remove_file_or_link(&target_prefix.join(&paths.relative_path)).await {
             Ok(_) => {}
             Err(e) if e.kind() == ErrorKind::NotFound => {
                 // Simply ignore if the file is already gone.
@@ -119,18 +279,7 @@ pub async fn unlink_package(
         }
     }
 
-    // Remove the conda-meta file
-    let conda_meta_path = target_prefix
-        .join("conda-meta")
-        .join(prefix_record.file_name());
-
-    tokio::fs::remove_file(&conda_meta_path)
-        .await
-        .map_err(|e| {
-            UnlinkError::FailedToDeleteFile(conda_meta_path.to_string_lossy().to_string(), e)
-        })?;
-
-    Ok(())
+    Ok(preserved)
 }
 
 #[cfg(test)]
@@ -138,13 +287,19 @@ mod tests {
     use std::{
         fs::{self, File},
         io::Write,
-        path::Path,
+        path::{Path, PathBuf},
         str::FromStr,
     };
 
-    use rattler_conda_types::{Platform, PrefixRecord, RepoDataRecord, Version};
+    use rattler_conda_types::{
+        package::FileMode,
+        prefix_record::{PathType, PathsEntry},
+        PackageName, PackageRecord, Platform, PrefixRecord, RepoDataRecord, Version,
+    };
+    use rattler_digest::{compute_bytes_digest, Sha256};
     use url::Url;
 
+    use super::{unlink_package_with_policy, ModifiedFilePolicy, PreservedFile};
     use crate::{
         get_repodata_record,
         install::{
@@ -297,4 +452,86 @@ mod tests {
         assert_eq!(entries.len(), 1);
         assert_eq!(entries[0].as_ref().unwrap().file_name(), "conda-meta");
     }
+
+    #[tokio::test]
+    async fn test_unlink_preserves_modified_files() {
+        let target_prefix = tempfile::TempDir::new().unwrap();
+        let entry = |name: &str, content: &[u8]| {
+            fs::write(target_prefix.path().join(name), content).unwrap();
+            PathsEntry {
+                relative_path: PathBuf::from(name),
+                original_path: None,
+                path_type: PathType::HardLink,
+                no_link: false,
+                sha256: None,
+                sha256_in_prefix: Some(compute_bytes_digest::<Sha256>(content)),
+                size_in_bytes: Some(content.len() as u64),
+                file_mode: None,
+                prefix_placeholder: None,
+            }
+        };
+        // The prefix of a file with a placeholder was replaced when it was
+        // linked, so it no longer matches the digest of the file in the package.
+        let replaced = PathsEntry {
+            sha256: Some(compute_bytes_digest::<Sha256>(b"/opt/placeholder")),
+            sha256_in_prefix: None,
+            prefix_placeholder: Some(String::from("/opt/placeholder")),
+            file_mode: Some(FileMode::Text),
+            ..entry("replaced.txt", b"/opt/prefix")
+        };
+        let paths = vec![
+            entry("unmodified.txt", b"foo"),
+            entry("modified.txt", b"bar"),
+            replaced,
+        ];
+        fs::write(target_prefix.path().join("modified.txt"), b"user changes").unwrap();
+
+        let repodata_record = RepoDataRecord {
+            package_record: PackageRecord::new(
+                PackageName::new_unchecked("foo"),
+                Version::from_str("1.0").unwrap(),
+                String::from("0"),
+            ),
+            file_name: String::from("foo-1.0-0.conda"),
+            url: Url::parse("https://example.com/noarch/foo-1.0-0.conda").unwrap(),
+            channel: String::from("https://example.com"),
+        };
+        let prefix_record =
+            PrefixRecord::from_repodata_record(repodata_record, None, None, paths, None, None);
+
+        for (policy, backup_path) in [
+            (
+                ModifiedFilePolicy::Backup,
+                Some("modified.txt.conda_backup"),
+            ),
+            (ModifiedFilePolicy::Keep, None),
+        ] {
+            let conda_meta_path = target_prefix.path().join("conda-meta");
+            fs::create_dir_all(&conda_meta_path).unwrap();
+            prefix_record
+                .write_to_path(conda_meta_path.join(prefix_record.file_name()), true)
+                .unwrap();
+            fs::write(target_prefix.path().join("unmodified.txt"), b"foo").unwrap();
+            fs::write(target_prefix.path().join("modified.txt"), b"user changes").unwrap();
+            fs::write(target_prefix.path().join("replaced.txt"), b"/opt/prefix").unwrap();
+
+            let preserved =
+                unlink_package_with_policy(target_prefix.path(), &prefix_record, policy)
+                    .await
+                    .unwrap();
+            assert_eq!(
+                preserved,
+                [PreservedFile {
+                    relative_path: PathBuf::from("modified.txt"),
+                    backup_path: backup_path.map(PathBuf::from),
+                }]
+            );
+            assert!(!target_prefix.path().join("unmodified.txt").exists());
+            assert!(!target_prefix.path().join("replaced.txt").exists());
+            let preserved_path = target_prefix
+                .path()
+                .join(backup_path.unwrap_or("modified.txt"));
+            assert_eq!(fs::read(preserved_path).unwrap(), b"user changes");
+        }
+    }
 }
@@ -15,9 +15,12 @@
 
 use std::path::PathBuf;
 
+pub mod bundle;
 #[cfg(feature = "cli-tools")]
 pub mod cli;
 pub mod install;
+#[cfg(feature = "local-channel")]
+pub mod repo_data;
 pub use rattler_cache::{package_cache, validation};
 
 /// A helper function that returns a [`Channel`] instance that points to an
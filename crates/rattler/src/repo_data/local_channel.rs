@@ -0,0 +1,254 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use itertools::Itertools;
+use rattler_conda_types::{Channel, PackageRecord, Platform, RepoData, Version};
+
+/// Determines which packages are removed from a [`LocalChannel`] by
+/// [`LocalChannel::prune`]. Packages are grouped by name per subdir.
+///
+/// The default policy does not remove any packages.
+#[derive(Debug, Clone, Default)]
+pub struct PrunePolicy {
+    /// The number of versions of each package that are retained. Only the
+    /// newest versions are retained. `None` retains all versions.
+    pub keep_versions: Option<usize>,
+
+    /// The number of builds of each version of a package that are retained.
+    /// Builds are ordered by their build number and timestamp, only the newest
+    /// builds are retained. `None` retains all builds.
+    pub keep_builds: Option<usize>,
+}
+
+/// A channel stored in a directory on the local filesystem.
+///
+/// Packages can be added to and removed from the channel, the repodata of the
+/// affected subdir is updated incrementally. Use [`LocalChannel::channel`] to
+/// query the channel with the gateway or to pass it to a solver. Note that the
+/// gateway caches the repodata of local channels in memory, clear its cache to
+/// pick up modifications.
+#[derive(Debug, Clone)]
+pub struct LocalChannel {
+    root: PathBuf,
+}
+
+impl LocalChannel {
+    /// Opens the channel in the given directory. If the directory does not
+    /// exist yet an empty channel is created.
+    pub fn open(root: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        let root = root.as_ref();
+        fs_err::create_dir_all(root)?;
+        let channel = Self {
+            root: fs_err::canonicalize(root)?,
+        };
+
+        // Every channel must have a noarch subdir.
+        if !channel.root.join("noarch/repodata.json").is_file() {
+            rattler_index::index(&channel.root, Some(&Platform::NoArch))?;
+        }
+
+        Ok(channel)
+    }
+
+    /// Returns the directory that contains the channel.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the channel that can be used to query the packages of this
+    /// channel, e.g. with the gateway.
+    pub fn channel(&self) -> Channel {
+        Channel::from_directory(&self.root)
+    }
+
+    /// Returns the subdirs of the channel that contain repodata.
+    pub fn platforms(&self) -> Result<Vec<Platform>, std::io::Error> {
+        let mut platforms = Vec::new();
+        for entry in fs_err::read_dir(&self.root)? {
+            let entry = entry?;
+            let Ok(platform) = Platform::from_str(&entry.file_name().to_string_lossy()) else {
+                continue;
+            };
+            if entry.path().join("repodata.json").is_file() {
+                platforms.push(platform);
+            }
+        }
+        platforms.sort_by_key(|platform| platform.as_str());
+        Ok(platforms)
+    }
+
+    /// Reads the repodata of the given subdir. Returns `None` if the subdir
+    /// does not exist.
+    pub fn repodata(&self, platform: Platform) -> Result<Option<RepoData>, std::io::Error> {
+        let path = self.root.join(platform.as_str()).join("repodata.json");
+        if !path.is_file() {
+            return Ok(None);
+        }
+        RepoData::from_path(path).map(Some)
+    }
+
+    /// Adds a built package to the channel. The package is copied into the
+    /// subdir that is specified in its `index.json`. An existing package with
+    /// the same filename is replaced.
+    ///
+    /// Returns the record of the package.
+    pub fn add_package(
+        &self,
+        package_path: impl AsRef<Path>,
+    ) -> Result<PackageRecord, std::io::Error> {
+        rattler_index::add_package_to_repodata(
+            &self.root,
+            package_path.as_ref(),
+            &rattler_index::IndexOptions::default(),
+        )
+    }
+
+    /// Removes the package with the given filename from the given subdir.
+    ///
+    /// Returns the record of the package or `None` if the channel did not
+    /// contain the package.
+    pub fn remove_package(
+        &self,
+        platform: Platform,
+        file_name: &str,
+    ) -> Result<Option<PackageRecord>, std::io::Error> {
+        rattler_index::remove_package_from_repodata(
+            &self.root,
+            &platform,
+            file_name,
+            &rattler_index::IndexOptions::default(),
+        )
+    }
+
+    /// Reindexes all subdirs of the channel from the packages that are stored
+    /// in the channel directory.
+    pub fn reindex(&self) -> Result<(), std::io::Error> {
+        rattler_index::index(&self.root, None)
+    }
+
+    /// Removes the packages that are not retained by the given policy.
+    ///
+    /// Returns the records of the packages that were removed.
+    pub fn prune(&self, policy: &PrunePolicy) -> Result<Vec<PackageRecord>, std::io::Error> {
+        let mut pruned = Vec::new();
+        for platform in self.platforms()? {
+            let Some(repodata) = self.repodata(platform)? else {
+                continue;
+            };
+
+            let packages_by_name = repodata
+                .packages
+                .iter()
+                .chain(repodata.conda_packages.iter())
+                .into_group_map_by(|(_, record)| record.name.clone());
+
+            for packages in packages_by_name.into_values() {
+                for (file_name, record) in packages_to_prune(packages, policy) {
+                    if let Some(record) = self.remove_package(platform, file_name)? {
+                        pruned.push(record);
+                    } else {
+                        tracing::debug!(
+                            "{} was already removed from {platform}",
+                            record.name.as_normalized()
+                        );
+                    }
+                }
+            }
+        }
+        Ok(pruned)
+    }
+}
+
+/// Returns the packages of a single package name that are not retained by
+/// the policy. The `.tar.bz2` and `.conda` archive of the same build are
+/// treated as a single build.
+fn packages_to_prune<'a>(
+    packages: Vec<(&'a String, &'a PackageRecord)>,
+    policy: &PrunePolicy,
+) -> Vec<(&'a String, &'a PackageRecord)> {
+    // Group the packages by version and build.
+    let mut builds_by_version: HashMap<&Version, HashMap<&str, Vec<_>>> = HashMap::new();
+    for (file_name, record) in packages {
+        builds_by_version
+            .entry(&record.version)
+            .or_default()
+            .entry(record.build.as_str())
+            .or_default()
+            .push((file_name, record));
+    }
+
+    let mut pruned = Vec::new();
+    let versions = builds_by_version
+        .into_iter()
+        .sorted_by(|(a, _), (b, _)| b.cmp(a));
+    for (index, (_, builds)) in versions.enumerate() {
+        let keep_version = policy.keep_versions.map_or(true, |keep| index < keep);
+        let builds = builds.into_values().sorted_by_key(|archives| {
+            let record = archives[0].1;
+            std::cmp::Reverse((record.build_number, record.timestamp))
+        });
+        for (index, archives) in builds.enumerate() {
+            let keep_build = policy.keep_builds.map_or(true, |keep| index < keep);
+            if !(keep_version && keep_build) {
+                pruned.extend(archives);
+            }
+        }
+    }
+    pruned
+}
+
+#[cfg(test)]
+mod test {
+    use rattler_conda_types::Platform;
+
+    use super::{LocalChannel, PrunePolicy};
+    use crate::get_test_data_dir;
+
+    #[test]
+    fn test_local_channel() {
+        let dir = tempfile::tempdir().unwrap();
+        let channel = LocalChannel::open(dir.path().join("channel")).unwrap();
+        assert_eq!(channel.platforms().unwrap(), [Platform::NoArch]);
+        assert!(channel.channel().base_url.scheme() == "file");
+
+        for version in ["0.1.0", "0.2.0"] {
+            let record = channel
+                .add_package(
+                    get_test_data_dir()
+                        .join(format!("clobber/clobber-1-{version}-h4616a5c_0.tar.bz2")),
+                )
+                .unwrap();
+            assert_eq!(record.version.as_str(), version);
+        }
+        let repodata = channel.repodata(Platform::NoArch).unwrap().unwrap();
+        assert_eq!(repodata.packages.len(), 2);
+
+        // Pruning with the default policy does not remove anything.
+        assert!(channel.prune(&PrunePolicy::default()).unwrap().is_empty());
+
+        // Only retain the newest version.
+        let pruned = channel
+            .prune(&PrunePolicy {
+                keep_versions: Some(1),
+                ..PrunePolicy::default()
+            })
+            .unwrap();
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].version.as_str(), "0.1.0");
+        assert!(!channel
+            .root()
+            .join("noarch/clobber-1-0.1.0-h4616a5c_0.tar.bz2")
+            .exists());
+
+        // Reindexing results in the same repodata.
+        channel.reindex().unwrap();
+        let repodata = channel.repodata(Platform::NoArch).unwrap().unwrap();
+        assert_eq!(
+            repodata.packages.keys().collect::<Vec<_>>(),
+            ["clobber-1-0.2.0-h4616a5c_0.tar.bz2"]
+        );
+    }
+}
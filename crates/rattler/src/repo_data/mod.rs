@@ -0,0 +1,7 @@
+//! Functionality to work with repodata and the channels that serve it.
+//!
+//! This module is only available with the `local-channel` feature.
+
+mod local_channel;
+
+pub use local_channel::{LocalChannel, PrunePolicy};
@@ -5,25 +5,44 @@ use std::{
     error::Error,
     fmt::{Display, Formatter},
     future::Future,
-    path::PathBuf,
+    io,
+    path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
+use fslock::LockFile;
 use fxhash::FxHashMap;
 use itertools::Itertools;
 use parking_lot::Mutex;
-use rattler_conda_types::{package::ArchiveIdentifier, PackageRecord};
-use rattler_digest::Sha256Hash;
+use rattler_conda_types::{
+    package::{ArchiveIdentifier, ArchiveType},
+    PackageRecord,
+};
+use rattler_digest::{Sha256, Sha256Hash};
 use rattler_networking::retry_policies::{DoNotRetryPolicy, RetryDecision, RetryPolicy};
 use rattler_package_streaming::{DownloadReporter, ExtractError};
+use rattler_redaction::{
+    redact_known_secrets_from_str, redact_known_secrets_from_url, DisplayRedacted, Redact,
+    DEFAULT_REDACTION_STR,
+};
 use reqwest::StatusCode;
-use tokio::sync::broadcast;
+use tempfile::TempDir;
+use tokio::{io::AsyncWriteExt, sync::broadcast};
 use tracing::Instrument;
 use url::Url;
 
 use crate::validation::validate_package_directory;
 
+mod entry;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "server")]
+pub use server::PackageCacheServer;
+
+use entry::CacheEntryMetadata;
+pub use entry::{CacheEntry, CACHE_ENTRY_METADATA_FILE};
+
 /// A trait that can be implemented to report progress of the download and
 /// validation process.
 pub trait CacheReporter: Send + Sync {
@@ -37,6 +56,185 @@ pub trait CacheReporter: Send + Sync {
     fn on_download_progress(&self, index: usize, progress: u64, total: Option<u64>);
     /// Called when a download completes
     fn on_download_completed(&self, index: usize);
+    /// Called when the package starts to be extracted into the cache. For
+    /// remote packages the archive is extracted while it is downloaded, so
+    /// this overlaps with the download callbacks.
+    fn on_extract_start(&self) -> usize {
+        0
+    }
+    /// Called when the package has been extracted into the cache
+    fn on_extract_completed(&self, _index: usize) {}
+    /// Called instead of `on_extract_completed` when the package could not be
+    /// fetched or extracted into the cache
+    fn on_extract_failed(&self, _index: usize) {}
+}
+
+/// A trait that can be implemented to fetch packages into the [`PackageCache`]
+/// from a custom source, e.g. an artifact store or an internal protocol.
+///
+/// See [`ReqwestPackageFetcher`] for the default implementation that downloads
+/// packages over HTTP.
+#[async_trait::async_trait]
+pub trait PackageFetcher: Send + Sync {
+    /// The error that is returned when fetching a package fails.
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Fetches the package archive located at `url` and extracts it into
+    /// `destination`. If `expected_sha256` is provided the implementation
+    /// should verify the hash of the archive.
+    async fn fetch(
+        &self,
+        url: &Url,
+        destination: &Path,
+        expected_sha256: Option<Sha256Hash>,
+        reporter: Option<Arc<dyn DownloadReporter>>,
+    ) -> Result<(), Self::Error>;
+
+    /// Fetches the package archive located at `url` and stores it, without
+    /// extracting it, at the file `destination`. This is used by caches with
+    /// the [`ExtractionPolicy::Lazy`] policy.
+    ///
+    /// Returns `false` if the fetcher is unable to store archives, in which
+    /// case the package is extracted directly instead. This is the default.
+    async fn fetch_archive(
+        &self,
+        _url: &Url,
+        _destination: &Path,
+        _expected_sha256: Option<Sha256Hash>,
+        _reporter: Option<Arc<dyn DownloadReporter>>,
+    ) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    /// Returns true if the fetch failed because of a transient error and
+    /// should be retried according to the retry policy. By default errors
+    /// are not retried.
+    fn is_transient(&self, _error: &Self::Error) -> bool {
+        false
+    }
+}
+
+/// A [`PackageFetcher`] that downloads packages using a
+/// [`reqwest_middleware::ClientWithMiddleware`].
+#[derive(Clone)]
+pub struct ReqwestPackageFetcher {
+    client: reqwest_middleware::ClientWithMiddleware,
+}
+
+impl ReqwestPackageFetcher {
+    /// Constructs a new fetcher that uses the given client.
+    pub fn new(client: reqwest_middleware::ClientWithMiddleware) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageFetcher for ReqwestPackageFetcher {
+    type Error = ExtractError;
+
+    async fn fetch(
+        &self,
+        url: &Url,
+        destination: &Path,
+        expected_sha256: Option<Sha256Hash>,
+        reporter: Option<Arc<dyn DownloadReporter>>,
+    ) -> Result<(), Self::Error> {
+        rattler_package_streaming::reqwest::tokio::extract(
+            self.client.clone(),
+            url.clone(),
+            destination,
+            expected_sha256,
+            reporter,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_archive(
+        &self,
+        url: &Url,
+        destination: &Path,
+        expected_sha256: Option<Sha256Hash>,
+        reporter: Option<Arc<dyn DownloadReporter>>,
+    ) -> Result<bool, Self::Error> {
+        let dir = destination.parent().unwrap_or_else(|| Path::new("."));
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(ExtractError::CouldNotCreateDestination)?;
+        let temp_file = tempfile::NamedTempFile::new_in(dir)?;
+
+        if let Some(reporter) = &reporter {
+            reporter.on_download_start();
+        }
+        if url.scheme() == "file" {
+            let source = url
+                .to_file_path()
+                .map_err(|()| io::Error::new(io::ErrorKind::InvalidInput, "invalid file url"))?;
+            tokio::fs::copy(source, temp_file.path()).await?;
+        } else {
+            let mut response = self
+                .client
+                .get(url.clone())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status().map_err(Into::into))?;
+            let total_bytes = response.content_length();
+            let mut bytes_received = 0;
+            let mut file = tokio::fs::File::from_std(temp_file.reopen()?);
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .map_err(reqwest_middleware::Error::from)?
+            {
+                file.write_all(&chunk).await?;
+                bytes_received += chunk.len() as u64;
+                if let Some(reporter) = &reporter {
+                    reporter.on_download_progress(bytes_received, total_bytes);
+                }
+            }
+            file.flush().await?;
+        }
+        if let Some(reporter) = &reporter {
+            reporter.on_download_complete();
+        }
+
+        if let Some(expected_sha256) = expected_sha256 {
+            let path = temp_file.path().to_path_buf();
+            let sha256 = tokio::task::spawn_blocking(move || {
+                rattler_digest::compute_file_digest::<Sha256>(&path)
+            })
+            .await
+            .map_err(|_| ExtractError::Cancelled)??;
+            if sha256 != expected_sha256 {
+                return Err(ExtractError::IoError(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "the sha256 hash of {} does not match, expected {expected_sha256:x} but got {sha256:x}",
+                        url.display_redacted()
+                    ),
+                )));
+            }
+        }
+
+        temp_file.persist(destination).map_err(|e| e.error)?;
+        Ok(true)
+    }
+
+    fn is_transient(&self, error: &Self::Error) -> bool {
+        match error {
+            ExtractError::IoError(_) | ExtractError::CouldNotCreateDestination(_) => true,
+            ExtractError::ReqwestError(err) => {
+                err.is_timeout()
+                    || err.is_connect()
+                    || err.status().map_or(false, |status| {
+                        status.is_server_error()
+                            || status == StatusCode::TOO_MANY_REQUESTS
+                            || status == StatusCode::REQUEST_TIMEOUT
+                    })
+            }
+            _ => false,
+        }
+    }
 }
 
 /// A [`PackageCache`] manages a cache of extracted Conda packages on disk.
@@ -46,6 +244,9 @@ pub trait CacheReporter: Send + Sync {
 /// package is found in the cache it is returned immediately. However, if the
 /// cache is stale a user defined function is called to populate the cache. This
 /// separates the corners between caching and fetching of the content.
+///
+/// How packages that are fetched from a url are stored is determined by the
+/// [`ExtractionPolicy`] of the cache.
 #[derive(Clone)]
 pub struct PackageCache {
     inner: Arc<Mutex<PackageCacheInner>>,
@@ -111,12 +312,51 @@ impl Display for CacheKey {
     }
 }
 
+/// Determines how the packages that are fetched from a url are stored in a
+/// [`PackageCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExtractionPolicy {
+    /// Packages are extracted into the cache directory and stay extracted.
+    #[default]
+    Eager,
+
+    /// The package archives are stored compressed in the cache directory.
+    /// Packages are only extracted when they are requested, into a temporary
+    /// work directory that is removed when the last clone of the
+    /// [`PackageCache`] is dropped. Work directories that are left behind,
+    /// e.g. because a process was killed, are removed by the next cache that
+    /// creates a work directory. Stored archives are only reused if they
+    /// match the sha256 hash of the requested package, if it is known.
+    ///
+    /// This uses much less disk space at the cost of extracting the packages
+    /// again for every transaction. Packages that are added with
+    /// [`PackageCache::get_or_fetch`] are always extracted eagerly.
+    Lazy,
+}
+
 #[derive(Default)]
 struct PackageCacheInner {
     path: PathBuf,
     packages: FxHashMap<CacheKey, Arc<Mutex<Package>>>,
+    extraction_policy: ExtractionPolicy,
+    workdir: Option<Arc<Workdir>>,
 }
 
+/// The directory into which lazily stored packages are extracted. The lock
+/// file inside the directory is held while the directory is in use so other
+/// processes can tell whether it was left behind.
+struct Workdir {
+    // The lock is released before the directory is removed.
+    _lock: LockFile,
+    dir: TempDir,
+}
+
+/// The prefix of the names of the work directories in the cache directory.
+const WORKDIR_PREFIX: &str = ".extracted-";
+
+/// The name of the lock file inside a work directory.
+const WORKDIR_LOCK_FILE: &str = ".lock";
+
 #[derive(Default)]
 struct Package {
     path: Option<PathBuf>,
@@ -132,17 +372,81 @@ pub enum PackageCacheError {
     FetchError(#[from] Arc<dyn std::error::Error + Send + Sync + 'static>),
 }
 
+impl Redact for PackageCacheError {
+    fn redact(self) -> Self {
+        match self {
+            PackageCacheError::FetchError(err) => PackageCacheError::FetchError(err.redact()),
+        }
+    }
+}
+
 impl PackageCache {
     /// Constructs a new [`PackageCache`] located at the specified path.
     pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::new_with_extraction_policy(path, ExtractionPolicy::default())
+    }
+
+    /// Constructs a new [`PackageCache`] located at the specified path that
+    /// stores the packages that are fetched from a url according to the
+    /// given [`ExtractionPolicy`].
+    pub fn new_with_extraction_policy(
+        path: impl Into<PathBuf>,
+        extraction_policy: ExtractionPolicy,
+    ) -> Self {
         Self {
             inner: Arc::new(Mutex::new(PackageCacheInner {
                 path: path.into(),
                 packages: FxHashMap::default(),
+                extraction_policy,
+                workdir: None,
             })),
         }
     }
 
+    /// Sets how the packages that are fetched from a url are stored. See
+    /// [`ExtractionPolicy`]. The policy is shared by all clones of the cache.
+    #[must_use]
+    pub fn with_extraction_policy(self, extraction_policy: ExtractionPolicy) -> Self {
+        self.inner.lock().extraction_policy = extraction_policy;
+        self
+    }
+
+    /// Returns the [`ExtractionPolicy`] of this cache.
+    pub fn extraction_policy(&self) -> ExtractionPolicy {
+        self.inner.lock().extraction_policy
+    }
+
+    /// Returns the path at which the archive of the package is stored, see
+    /// [`ExtractionPolicy::Lazy`].
+    fn archive_path(&self, cache_key: &CacheKey, archive_type: ArchiveType) -> PathBuf {
+        self.inner
+            .lock()
+            .path
+            .join(format!("{cache_key}{}", archive_type.extension()))
+    }
+
+    /// Returns the directory into which lazily stored packages are extracted,
+    /// creating it if it does not exist yet. Work directories that were left
+    /// behind by processes that did not exit cleanly are removed when the
+    /// directory is created.
+    fn workdir(&self) -> io::Result<PathBuf> {
+        let mut inner = self.inner.lock();
+        if let Some(workdir) = &inner.workdir {
+            return Ok(workdir.dir.path().to_path_buf());
+        }
+        std::fs::create_dir_all(&inner.path)?;
+        remove_stale_workdirs(&inner.path);
+
+        let dir = tempfile::Builder::new()
+            .prefix(WORKDIR_PREFIX)
+            .tempdir_in(&inner.path)?;
+        let mut lock = LockFile::open(&dir.path().join(WORKDIR_LOCK_FILE))?;
+        lock.lock()?;
+        let path = dir.path().to_path_buf();
+        inner.workdir = Some(Arc::new(Workdir { _lock: lock, dir }));
+        Ok(path)
+    }
+
     /// Returns the directory that contains the specified package.
     ///
     /// If the package was previously successfully fetched and stored in the
@@ -165,14 +469,33 @@ impl PackageCache {
         E: std::error::Error + Send + Sync + 'static,
     {
         let cache_key = pkg.into();
+        let destination = self.inner.lock().path.join(cache_key.to_string());
+        self.get_or_fetch_at(cache_key, destination, fetch, reporter)
+            .await
+    }
 
+    /// Same as [`Self::get_or_fetch`] but extracts the package into
+    /// `pkg_cache_dir`.
+    async fn get_or_fetch_at<F, Fut, E>(
+        &self,
+        cache_key: CacheKey,
+        pkg_cache_dir: PathBuf,
+        fetch: F,
+        reporter: Option<Arc<dyn CacheReporter>>,
+    ) -> Result<PathBuf, PackageCacheError>
+    where
+        F: (FnOnce(PathBuf) -> Fut) + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: std::error::Error + Send + Sync + 'static,
+    {
         // Get the package entry
-        let (package, pkg_cache_dir) = {
-            let mut inner = self.inner.lock();
-            let destination = inner.path.join(cache_key.to_string());
-            let package = inner.packages.entry(cache_key).or_default().clone();
-            (package, destination)
-        };
+        let package = self
+            .inner
+            .lock()
+            .packages
+            .entry(cache_key)
+            .or_default()
+            .clone();
 
         let mut rx = {
             // Only sync code in this block
@@ -252,69 +575,233 @@ impl PackageCache {
         retry_policy: impl RetryPolicy + Send + 'static,
         reporter: Option<Arc<dyn CacheReporter>>,
     ) -> Result<PathBuf, PackageCacheError> {
-        let request_start = SystemTime::now();
+        self.get_or_fetch_with_fetcher(
+            pkg,
+            url,
+            ReqwestPackageFetcher::new(client),
+            retry_policy,
+            reporter,
+        )
+        .await
+    }
+
+    /// Returns the directory that contains the specified package.
+    ///
+    /// This is a wrapper around `get_or_fetch` which uses the given
+    /// [`PackageFetcher`] to fetch the package from the given URL if the
+    /// package could not be found in the cache. Transient errors, as
+    /// determined by [`PackageFetcher::is_transient`], are retried according
+    /// to the retry policy.
+    pub async fn get_or_fetch_with_fetcher<F: PackageFetcher + 'static>(
+        &self,
+        pkg: impl Into<CacheKey>,
+        url: Url,
+        fetcher: F,
+        retry_policy: impl RetryPolicy + Send + 'static,
+        reporter: Option<Arc<dyn CacheReporter>>,
+    ) -> Result<PathBuf, PackageCacheError> {
         let cache_key = pkg.into();
         let sha256 = cache_key.sha256();
         let download_reporter = reporter.clone();
-        self.get_or_fetch(cache_key, move |destination| async move {
-            let mut current_try = 0;
-            loop {
-                current_try += 1;
-                tracing::debug!("downloading {} to {}", &url, destination.display());
-
-                let result = rattler_package_streaming::reqwest::tokio::extract(
-                    client.clone(),
-                    url.clone(),
-                    &destination,
-                    sha256,
-                    download_reporter.clone().map(|reporter| Arc::new(PassthroughReporter {
-                        reporter,
-                        index: Mutex::new(None),
-                    }) as Arc::<dyn DownloadReporter>)
-                )
-                .await;
-
-                // Extract any potential error
-                let Err(err) = result else { return Ok(()); };
-
-                // Only retry on certain errors.
-                if !matches!(
-                    &err,
-                    ExtractError::IoError(_) | ExtractError::CouldNotCreateDestination(_)
-                ) && !matches!(&err, ExtractError::ReqwestError(err) if
-                    err.is_timeout() ||
-                    err.is_connect() ||
-                    err
-                        .status()
-                        .map_or(false, |status| status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT)
-                ) {
-                    return Err(err);
-                }
+        let passthrough_reporter = move || {
+            download_reporter.clone().map(|reporter| {
+                Arc::new(PassthroughReporter {
+                    reporter,
+                    index: Mutex::new(None),
+                }) as Arc<dyn DownloadReporter>
+            })
+        };
 
-                // Determine whether to retry based on the retry policy
-                let execute_after = match retry_policy.should_retry(request_start, current_try) {
-                    RetryDecision::Retry { execute_after } => execute_after,
-                    RetryDecision::DoNotRetry => return Err(err),
-                };
-                let duration = execute_after.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO);
-
-                // Wait for a second to let the remote service restore itself. This increases the
-                // chance of success.
-                tracing::warn!(
-                    "failed to download and extract {} to {}: {}. Retry #{}, Sleeping {:?} until the next attempt...",
-                    &url,
-                    destination.display(),
-                    err,
-                    current_try,
-                    duration
-                );
-                tokio::time::sleep(duration).await;
+        // With the lazy extraction policy the archive is stored next to the
+        // extracted packages and extracted into the work directory.
+        let archive_type = ArchiveType::split_str(url.path()).map(|(_, archive_type)| archive_type);
+        let (destination, archive_path) = match (self.extraction_policy(), archive_type) {
+            (ExtractionPolicy::Lazy, Some(archive_type)) => {
+                let workdir = self
+                    .workdir()
+                    .map_err(|e| PackageCacheError::FetchError(Arc::new(e)))?;
+                let archive_path = self.archive_path(&cache_key, archive_type);
+                (workdir.join(cache_key.to_string()), Some(archive_path))
             }
-        }, reporter)
+            _ => (self.inner.lock().path.join(cache_key.to_string()), None),
+        };
+
+        self.get_or_fetch_at(
+            cache_key,
+            destination,
+            move |destination| async move {
+                if let Some(archive_path) = archive_path {
+                    // An archive that was stored before is only reused if it
+                    // matches the expected hash.
+                    let is_stored = archive_path.is_file()
+                        && match sha256 {
+                            Some(sha256) => file_has_sha256(&archive_path, sha256).await,
+                            None => true,
+                        };
+                    let stored = is_stored
+                        || retry_transient(&fetcher, &url, &retry_policy, || {
+                            fetcher.fetch_archive(
+                                &url,
+                                &archive_path,
+                                sha256,
+                                passthrough_reporter(),
+                            )
+                        })
+                        .await
+                        .map_err(FetchError::Fetch)?;
+                    if stored {
+                        if let Err(err) = rattler_package_streaming::tokio::fs::extract(
+                            &archive_path,
+                            &destination,
+                        )
+                        .await
+                        {
+                            // Remove the archive so it is fetched again the next time.
+                            let _ = tokio::fs::remove_file(&archive_path).await;
+                            return Err(FetchError::Extract(archive_path, err));
+                        }
+                        write_cache_entry_metadata(&destination, &url).await;
+                        return Ok(());
+                    }
+                }
+
+                retry_transient(&fetcher, &url, &retry_policy, || {
+                    tracing::debug!(
+                        "downloading {} to {}",
+                        url.display_redacted(),
+                        destination.display()
+                    );
+                    fetcher.fetch(&url, &destination, sha256, passthrough_reporter())
+                })
+                .await
+                .map_err(FetchError::Fetch)?;
+                write_cache_entry_metadata(&destination, &url).await;
+                Ok(())
+            },
+            reporter,
+        )
         .await
     }
 }
 
+/// Removes the work directories in `cache_dir` that are not locked by a
+/// running process. Directories without a lock file are skipped because they
+/// might just have been created.
+fn remove_stale_workdirs(cache_dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(cache_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(WORKDIR_PREFIX)
+        {
+            continue;
+        }
+        let path = entry.path();
+        let lock_path = path.join(WORKDIR_LOCK_FILE);
+        if !lock_path.is_file() {
+            continue;
+        }
+        let Ok(mut lock) = LockFile::open(&lock_path) else {
+            continue;
+        };
+        if !lock.try_lock().unwrap_or(false) {
+            continue;
+        }
+        drop(lock);
+        tracing::debug!("removing stale work directory {}", path.display());
+        if let Err(e) = std::fs::remove_dir_all(&path) {
+            tracing::warn!(
+                "failed to remove stale work directory {}: {e}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Returns true if the sha256 hash of the file at `path` matches `expected`.
+async fn file_has_sha256(path: &Path, expected: Sha256Hash) -> bool {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || rattler_digest::compute_file_digest::<Sha256>(&path))
+        .await
+        .map_or(false, |sha256| {
+            sha256.map_or(false, |sha256| sha256 == expected)
+        })
+}
+
+/// An error that occurs while fetching a package from a url into the cache.
+#[derive(Debug, thiserror::Error)]
+enum FetchError<E> {
+    #[error(transparent)]
+    Fetch(E),
+
+    #[error("failed to extract {}", .0.display())]
+    Extract(PathBuf, #[source] ExtractError),
+}
+
+/// Records where the package that was extracted to `destination` originates
+/// from. Secrets in the url are redacted because the metadata is stored in
+/// plain text.
+async fn write_cache_entry_metadata(destination: &Path, url: &Url) {
+    let url =
+        redact_known_secrets_from_url(url, DEFAULT_REDACTION_STR).unwrap_or_else(|| url.clone());
+    if let Err(e) = CacheEntryMetadata::write(destination, Some(url)).await {
+        tracing::warn!(
+            "failed to write cache entry metadata to {}: {e}",
+            destination.display()
+        );
+    }
+}
+
+/// Runs `operation` until it succeeds, fails with an error that is not
+/// transient according to the fetcher, or the retry policy gives up.
+async fn retry_transient<F: PackageFetcher, T, Fut>(
+    fetcher: &F,
+    url: &Url,
+    retry_policy: &impl RetryPolicy,
+    mut operation: impl FnMut() -> Fut,
+) -> Result<T, F::Error>
+where
+    Fut: Future<Output = Result<T, F::Error>>,
+{
+    let request_start = SystemTime::now();
+    let mut current_try = 0;
+    loop {
+        current_try += 1;
+        let err = match operation().await {
+            Ok(result) => return Ok(result),
+            Err(err) => err,
+        };
+
+        // Only retry on certain errors.
+        if !fetcher.is_transient(&err) {
+            return Err(err);
+        }
+
+        // Determine whether to retry based on the retry policy
+        let execute_after = match retry_policy.should_retry(request_start, current_try) {
+            RetryDecision::Retry { execute_after } => execute_after,
+            RetryDecision::DoNotRetry => return Err(err),
+        };
+        let duration = execute_after
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO);
+
+        // Wait for a second to let the remote service restore itself. This increases
+        // the chance of success.
+        tracing::warn!(
+            "failed to fetch {}: {}. Retry #{}, Sleeping {:?} until the next attempt...",
+            url.display_redacted(),
+            redact_known_secrets_from_str(&err.to_string(), DEFAULT_REDACTION_STR),
+            current_try,
+            duration
+        );
+        tokio::time::sleep(duration).await;
+    }
+}
+
 /// Validates that the package that is currently stored is a valid package and
 /// otherwise calls the `fetch` method to populate the cache.
 async fn validate_or_fetch_to_cache<F, Fut, E>(
@@ -363,10 +850,21 @@ where
         }
     }
 
-    // Otherwise, defer to populate method to fill our cache.
-    fetch(path)
+    // Otherwise, defer to populate method to fill our cache. Fetch errors are
+    // produced by arbitrary fetchers and might include the url of the package,
+    // so known secrets are redacted from them.
+    let reporter = reporter.as_deref().map(|r| (r, r.on_extract_start()));
+    let result = fetch(path)
         .await
-        .map_err(|e| PackageCacheError::FetchError(Arc::new(e)))
+        .map_err(|e| PackageCacheError::FetchError(Arc::new(e)).redact());
+    if let Some((reporter, index)) = reporter {
+        if result.is_ok() {
+            reporter.on_extract_completed(index);
+        } else {
+            reporter.on_extract_failed(index);
+        }
+    }
+    result
 }
 
 struct PassthroughReporter {
@@ -424,13 +922,14 @@ mod test {
     use bytes::Bytes;
     use futures::stream;
     use rattler_conda_types::package::{ArchiveIdentifier, PackageFile, PathsJson};
+    use rattler_digest::Sha256;
     use rattler_networking::retry_policies::{DoNotRetryPolicy, ExponentialBackoffBuilder};
     use tempfile::tempdir;
     use tokio::sync::Mutex;
     use tokio_stream::StreamExt;
     use url::Url;
 
-    use super::PackageCache;
+    use super::{CacheKey, ExtractionPolicy, PackageCache, PackageFetcher};
     use crate::validation::validate_package_directory;
 
     fn get_test_data_dir() -> PathBuf {
@@ -642,4 +1141,263 @@ mod test {
         test_flaky_package_cache(conda, Middleware::FailAfterBytes(1000)).await;
         test_flaky_package_cache(conda, Middleware::FailAfterBytes(50)).await;
     }
+
+    /// A fetcher that fails the first attempt with a transient error and then
+    /// writes a single file to the destination.
+    struct FlakyFetcher {
+        attempts: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl PackageFetcher for FlakyFetcher {
+        type Error = std::io::Error;
+
+        async fn fetch(
+            &self,
+            url: &Url,
+            destination: &Path,
+            _expected_sha256: Option<rattler_digest::Sha256Hash>,
+            _reporter: Option<Arc<dyn rattler_package_streaming::DownloadReporter>>,
+        ) -> Result<(), Self::Error> {
+            let attempt = self
+                .attempts
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if attempt == 0 {
+                return Err(std::io::ErrorKind::ConnectionReset.into());
+            }
+            tokio::fs::create_dir_all(destination).await?;
+            tokio::fs::write(destination.join("url.txt"), url.as_str()).await
+        }
+
+        fn is_transient(&self, error: &Self::Error) -> bool {
+            error.kind() == std::io::ErrorKind::ConnectionReset
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_fetcher() {
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = Url::parse("artifacts://store/conda-forge/noarch/foo-1.0-0.conda").unwrap();
+
+        let package_dir = cache
+            .get_or_fetch_with_fetcher(
+                ArchiveIdentifier::try_from_url(&url).unwrap(),
+                url.clone(),
+                FlakyFetcher {
+                    attempts: attempts.clone(),
+                },
+                ExponentialBackoffBuilder::default().build_with_max_retries(3),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(
+            std::fs::read_to_string(package_dir.join("url.txt")).unwrap(),
+            url.as_str()
+        );
+    }
+    /// A reporter that records the events it receives.
+    #[derive(Default)]
+    struct RecordingReporter {
+        events: std::sync::Mutex<Vec<&'static str>>,
+    }
+
+    impl CacheReporter for RecordingReporter {
+        fn on_validate_start(&self) -> usize {
+            self.events.lock().unwrap().push("validate_start");
+            0
+        }
+
+        fn on_validate_complete(&self, _index: usize) {
+            self.events.lock().unwrap().push("validate_complete");
+        }
+
+        fn on_download_start(&self) -> usize {
+            self.events.lock().unwrap().push("download_start");
+            0
+        }
+
+        fn on_download_progress(&self, _index: usize, _progress: u64, _total: Option<u64>) {}
+
+        fn on_download_completed(&self, _index: usize) {
+            self.events.lock().unwrap().push("download_completed");
+        }
+
+        fn on_extract_start(&self) -> usize {
+            self.events.lock().unwrap().push("extract_start");
+            0
+        }
+
+        fn on_extract_completed(&self, _index: usize) {
+            self.events.lock().unwrap().push("extract_completed");
+        }
+
+        fn on_extract_failed(&self, _index: usize) {
+            self.events.lock().unwrap().push("extract_failed");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract_is_reported() {
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let url = Url::parse("artifacts://store/conda-forge/noarch/foo-1.0-0.conda").unwrap();
+        let reporter = Arc::new(RecordingReporter::default());
+
+        cache
+            .get_or_fetch_with_fetcher(
+                ArchiveIdentifier::try_from_url(&url).unwrap(),
+                url.clone(),
+                FlakyFetcher {
+                    attempts: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+                },
+                ExponentialBackoffBuilder::default().build_with_max_retries(3),
+                Some(reporter.clone()),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *reporter.events.lock().unwrap(),
+            vec!["extract_start", "extract_completed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_failure_is_reported() {
+        let packages_dir = tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        let url = Url::parse("artifacts://store/conda-forge/noarch/foo-1.0-0.conda").unwrap();
+        let reporter = Arc::new(RecordingReporter::default());
+
+        // The first attempt of the fetcher fails and it is not retried.
+        cache
+            .get_or_fetch_with_fetcher(
+                ArchiveIdentifier::try_from_url(&url).unwrap(),
+                url.clone(),
+                FlakyFetcher {
+                    attempts: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+                },
+                DoNotRetryPolicy,
+                Some(reporter.clone()),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            *reporter.events.lock().unwrap(),
+            vec!["extract_start", "extract_failed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lazy_extraction() {
+        let archive_name = "clobber-1-0.1.0-h4616a5c_0.tar.bz2";
+        let url =
+            Url::from_file_path(get_test_data_dir().join("clobber").join(archive_name)).unwrap();
+        let packages_dir = tempdir().unwrap();
+        let cache =
+            PackageCache::new(packages_dir.path()).with_extraction_policy(ExtractionPolicy::Lazy);
+
+        let package_dir = cache
+            .get_or_fetch_from_url(
+                ArchiveIdentifier::try_from_url(&url).unwrap(),
+                url.clone(),
+                reqwest::Client::new().into(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        // The archive is stored in the cache, the package is extracted into
+        // the work directory.
+        assert!(packages_dir.path().join(archive_name).is_file());
+        assert!(!packages_dir
+            .path()
+            .join("clobber-1-0.1.0-h4616a5c_0")
+            .exists());
+        assert!(package_dir
+            .parent()
+            .unwrap()
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with(".extracted-"));
+        validate_package_directory(&package_dir).unwrap();
+
+        // The work directory is removed together with the cache.
+        drop(cache);
+        assert!(!package_dir.exists());
+        assert!(packages_dir.path().join(archive_name).is_file());
+    }
+
+    #[tokio::test]
+    async fn test_lazy_extraction_verifies_stored_archive() {
+        let archive_name = "clobber-1-0.1.0-h4616a5c_0.tar.bz2";
+        let archive_path = get_test_data_dir().join("clobber").join(archive_name);
+        let sha256 = rattler_digest::compute_file_digest::<Sha256>(&archive_path).unwrap();
+        let url = Url::from_file_path(&archive_path).unwrap();
+        let packages_dir = tempdir().unwrap();
+        std::fs::write(packages_dir.path().join(archive_name), b"corrupt").unwrap();
+
+        let cache =
+            PackageCache::new_with_extraction_policy(packages_dir.path(), ExtractionPolicy::Lazy);
+        let package_dir = cache
+            .get_or_fetch_from_url(
+                CacheKey::from(ArchiveIdentifier::try_from_url(&url).unwrap()).with_sha256(sha256),
+                url,
+                reqwest::Client::new().into(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        // The corrupt archive is fetched again.
+        validate_package_directory(&package_dir).unwrap();
+        assert_eq!(
+            std::fs::read(packages_dir.path().join(archive_name)).unwrap(),
+            std::fs::read(&archive_path).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lazy_extraction_removes_stale_workdirs() {
+        let archive_name = "clobber-1-0.1.0-h4616a5c_0.tar.bz2";
+        let url =
+            Url::from_file_path(get_test_data_dir().join("clobber").join(archive_name)).unwrap();
+        let packages_dir = tempdir().unwrap();
+
+        // A work directory of a process that did not exit cleanly, and one
+        // that was just created by another process.
+        let stale = packages_dir.path().join(".extracted-stale");
+        std::fs::create_dir_all(stale.join("foo-1.0-0")).unwrap();
+        std::fs::write(stale.join(".lock"), "").unwrap();
+        let new = packages_dir.path().join(".extracted-new");
+        std::fs::create_dir_all(&new).unwrap();
+
+        // The work directory of another cache that is still in use.
+        let other_cache =
+            PackageCache::new_with_extraction_policy(packages_dir.path(), ExtractionPolicy::Lazy);
+        let other_workdir = other_cache.workdir().unwrap();
+
+        let cache =
+            PackageCache::new_with_extraction_policy(packages_dir.path(), ExtractionPolicy::Lazy);
+        cache
+            .get_or_fetch_from_url(
+                ArchiveIdentifier::try_from_url(&url).unwrap(),
+                url,
+                reqwest::Client::new().into(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert!(!stale.exists());
+        assert!(new.exists());
+        assert!(other_workdir.exists());
+    }
 }
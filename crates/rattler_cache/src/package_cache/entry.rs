@@ -0,0 +1,259 @@
+//! Inspection of the entries stored in a [`PackageCache`]. See [`CacheEntry`].
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{CacheKey, PackageCache};
+use crate::validation::{validate_package_directory, PackageValidationError};
+
+/// The name of the file inside the directory of a cache entry that stores
+/// where the package originates from.
+pub const CACHE_ENTRY_METADATA_FILE: &str = ".rattler-cache-entry.json";
+
+/// Information about the origin of a cache entry that is stored alongside the
+/// extracted package.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CacheEntryMetadata {
+    /// The url the package archive was fetched from.
+    pub url: Option<Url>,
+
+    /// The time the package was extracted, in seconds since the unix epoch.
+    pub extracted_at: u64,
+}
+
+impl CacheEntryMetadata {
+    /// Writes the metadata of a package that was just extracted to `package_dir`.
+    pub(crate) async fn write(package_dir: &Path, url: Option<Url>) -> io::Result<()> {
+        let metadata = Self {
+            url,
+            extracted_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+        let bytes = serde_json::to_vec(&metadata)?;
+        tokio::fs::write(package_dir.join(CACHE_ENTRY_METADATA_FILE), bytes).await
+    }
+}
+
+/// A package that is stored in a [`PackageCache`].
+///
+/// Entries are obtained through [`PackageCache::entries`] or
+/// [`PackageCache::entry`]. Information that is expensive to compute, like
+/// the size or the validation status, is only computed when requested.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    path: PathBuf,
+    url: Option<Url>,
+    extracted_at: Option<SystemTime>,
+}
+
+impl CacheEntry {
+    /// Reads the entry stored at `path`. Returns `None` if the directory does
+    /// not contain an extracted package.
+    fn from_path(path: PathBuf) -> Option<Self> {
+        if !path.join("info/index.json").is_file() {
+            return None;
+        }
+
+        let metadata = std::fs::read(path.join(CACHE_ENTRY_METADATA_FILE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<CacheEntryMetadata>(&bytes).ok());
+        let (url, extracted_at) = match metadata {
+            Some(metadata) => (
+                metadata.url,
+                Some(UNIX_EPOCH + Duration::from_secs(metadata.extracted_at)),
+            ),
+            // Entries that were created by an older version, or without
+            // knowing the origin, fall back to the modification time.
+            None => (
+                None,
+                std::fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok(),
+            ),
+        };
+
+        Some(Self {
+            path,
+            url,
+            extracted_at,
+        })
+    }
+
+    /// Returns the directory that contains the extracted package.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the name of the entry, e.g. `python-3.12.0-h1234_0`.
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Returns the url the package was fetched from, or `None` if the origin
+    /// of the package is unknown. The origin is only known for packages that
+    /// were fetched with [`PackageCache::get_or_fetch_with_fetcher`] or one of
+    /// the functions that wrap it.
+    pub fn url(&self) -> Option<&Url> {
+        self.url.as_ref()
+    }
+
+    /// Returns the time the package was extracted into the cache, if known.
+    pub fn extracted_at(&self) -> Option<SystemTime> {
+        self.extracted_at
+    }
+
+    /// Computes the total size in bytes of all the files of the entry.
+    pub fn size_in_bytes(&self) -> io::Result<u64> {
+        walkdir::WalkDir::new(&self.path)
+            .into_iter()
+            .map(|entry| {
+                let entry = entry?;
+                Ok(if entry.file_type().is_file() {
+                    entry.metadata()?.len()
+                } else {
+                    0
+                })
+            })
+            .sum()
+    }
+
+    /// Validates the contents of the entry against the `paths.json` of the
+    /// package. Invalid entries are fetched again the next time they are
+    /// requested from the cache.
+    pub fn validate(&self) -> Result<(), PackageValidationError> {
+        validate_package_directory(&self.path).map(|_| ())
+    }
+}
+
+impl PackageCache {
+    /// Returns all the packages that are stored in the cache on disk.
+    pub fn entries(&self) -> io::Result<impl Iterator<Item = CacheEntry>> {
+        let cache_dir = self.inner.lock().path.clone();
+        let read_dir = match std::fs::read_dir(cache_dir) {
+            Ok(read_dir) => Some(read_dir),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Ok(read_dir
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter_map(|entry| CacheEntry::from_path(entry.path())))
+    }
+
+    /// Returns the cache entry of the specified package, or `None` if the
+    /// package is not stored in the cache.
+    pub fn entry(&self, pkg: impl Into<CacheKey>) -> Option<CacheEntry> {
+        let path = self.inner.lock().path.join(pkg.into().to_string());
+        CacheEntry::from_path(path)
+    }
+
+    /// Removes an entry from the cache. The package is fetched again the next
+    /// time it is requested.
+    pub fn remove_entry(&self, entry: &CacheEntry) -> io::Result<()> {
+        let mut inner = self.inner.lock();
+        inner
+            .packages
+            .retain(|_, package| package.lock().path.as_deref() != Some(entry.path()));
+        match std::fs::remove_dir_all(entry.path()) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use rattler_conda_types::package::ArchiveIdentifier;
+    use rattler_networking::retry_policies::DoNotRetryPolicy;
+    use rattler_package_streaming::{DownloadReporter, ExtractError};
+    use rattler_redaction::DEFAULT_REDACTION_STR;
+    use url::Url;
+
+    use crate::package_cache::{PackageCache, PackageFetcher};
+
+    /// Extracts a package from the test-data directory regardless of the url.
+    struct LocalFetcher(PathBuf);
+
+    #[async_trait::async_trait]
+    impl PackageFetcher for LocalFetcher {
+        type Error = ExtractError;
+
+        async fn fetch(
+            &self,
+            _url: &Url,
+            destination: &Path,
+            _expected_sha256: Option<rattler_digest::Sha256Hash>,
+            _reporter: Option<Arc<dyn DownloadReporter>>,
+        ) -> Result<(), Self::Error> {
+            rattler_package_streaming::tokio::fs::extract(&self.0, destination).await?;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_entries() {
+        let archive = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/clobber/clobber-python-0.1.0-cpython.conda");
+        let url = Url::parse(
+            "https://example.com/t/secret-token/channel/noarch/clobber-python-0.1.0-cpython.conda",
+        )
+        .unwrap();
+        let packages_dir = tempfile::tempdir().unwrap();
+        let cache = PackageCache::new(packages_dir.path());
+        assert_eq!(cache.entries().unwrap().count(), 0);
+
+        let package_dir = cache
+            .get_or_fetch_with_fetcher(
+                ArchiveIdentifier::try_from_url(&url).unwrap(),
+                url.clone(),
+                LocalFetcher(archive),
+                DoNotRetryPolicy,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let entries = cache.entries().unwrap().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.path(), package_dir);
+        assert_eq!(entry.name(), "clobber-python-0.1.0-cpython");
+        // The token is not stored in the metadata of the entry.
+        assert_eq!(
+            entry.url().map(Url::as_str),
+            Some(
+                format!(
+                    "https://example.com/t/{DEFAULT_REDACTION_STR}/channel/noarch/clobber-python-0.1.0-cpython.conda"
+                )
+                .as_str()
+            )
+        );
+        assert!(entry.extracted_at().is_some());
+        assert!(entry.size_in_bytes().unwrap() > 0);
+        entry.validate().unwrap();
+
+        let entry = cache
+            .entry(ArchiveIdentifier::try_from_url(&url).unwrap())
+            .unwrap();
+        cache.remove_entry(&entry).unwrap();
+        assert!(!package_dir.exists());
+        assert_eq!(cache.entries().unwrap().count(), 0);
+    }
+}
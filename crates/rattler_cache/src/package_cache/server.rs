@@ -0,0 +1,379 @@
+//! A local HTTP server that exposes package archives from the package cache.
+//! See [`PackageCacheServer`].
+
+use std::{future::IntoFuture, io, net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::{Path as UrlPath, RawQuery, State},
+    http::{header, StatusCode},
+    response::Response,
+    routing::get,
+    Router,
+};
+use rattler_conda_types::{
+    package::{ArchiveIdentifier, ArchiveType},
+    Channel,
+};
+use rattler_digest::{Sha256, Sha256Hash};
+use rattler_redaction::DisplayRedacted;
+use tokio::sync::oneshot;
+use tokio_util::io::ReaderStream;
+use url::Url;
+
+use super::{file_has_sha256, CacheKey, PackageCache, PackageFetcher, ReqwestPackageFetcher};
+
+/// A local HTTP server that serves package archives under stable urls.
+///
+/// Some tools can only consume packages through urls. The server acts as a
+/// pull-through proxy for these tools: an archive that is located at
+/// `https://conda.anaconda.org/conda-forge/noarch/foo-1.0-0.conda` is served
+/// at `<server>/https/conda.anaconda.org/conda-forge/noarch/foo-1.0-0.conda`
+/// (see [`PackageCacheServer::url_for`]). If the archive is not cached yet it
+/// is downloaded from its original location first. Only archives from the
+/// channels the server is configured with are served.
+///
+/// The archives are stored in the [`PackageCache`] at the same location as
+/// the archives of a cache with the [`super::ExtractionPolicy::Lazy`] policy.
+/// If the url contains a `sha256` query parameter the hash of the archive is
+/// verified before it is served.
+///
+/// The server is shut down when this instance is dropped.
+pub struct PackageCacheServer {
+    local_addr: SocketAddr,
+    channels: Arc<[Url]>,
+    shutdown_sender: Option<oneshot::Sender<()>>,
+}
+
+struct ServerState {
+    cache: PackageCache,
+    channels: Arc<[Url]>,
+    fetcher: ReqwestPackageFetcher,
+}
+
+impl PackageCache {
+    /// Starts a [`PackageCacheServer`] that serves the archives of the given
+    /// channels from this cache on the given address. Use port `0` to let the
+    /// operating system pick a free port. Archives that are missing from the
+    /// cache are downloaded with the given client.
+    pub async fn serve(
+        &self,
+        addr: SocketAddr,
+        client: reqwest_middleware::ClientWithMiddleware,
+        channels: impl IntoIterator<Item = Channel>,
+    ) -> io::Result<PackageCacheServer> {
+        let channels = channels
+            .into_iter()
+            .map(|channel| {
+                let mut url = channel.base_url;
+                if !url.path().ends_with('/') {
+                    url.set_path(&format!("{}/", url.path()));
+                }
+                url
+            })
+            .collect();
+        PackageCacheServer::start(self.clone(), addr, client, channels).await
+    }
+}
+
+impl PackageCacheServer {
+    async fn start(
+        cache: PackageCache,
+        addr: SocketAddr,
+        client: reqwest_middleware::ClientWithMiddleware,
+        channels: Arc<[Url]>,
+    ) -> io::Result<Self> {
+        let state = Arc::new(ServerState {
+            cache,
+            channels: channels.clone(),
+            fetcher: ReqwestPackageFetcher::new(client),
+        });
+        let app = Router::new()
+            .route("/:scheme/*path", get(serve_archive))
+            .with_state(state);
+
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+
+        let (tx, rx) = oneshot::channel();
+        let server = axum::serve(listener, app)
+            .with_graceful_shutdown(async {
+                rx.await.ok();
+            })
+            .into_future();
+        tokio::spawn(server);
+
+        Ok(Self {
+            local_addr,
+            channels,
+            shutdown_sender: Some(tx),
+        })
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Returns the root url of the server.
+    pub fn url(&self) -> Url {
+        Url::parse(&format!("http://{}/", self.local_addr)).expect("socket address is a valid url")
+    }
+
+    /// Returns the url at which the server serves the archive located at
+    /// `url`. If `sha256` is provided the server verifies the hash of the
+    /// archive before it is served. Returns `None` if the url does not refer
+    /// to an archive of one of the channels of the server.
+    pub fn url_for(&self, url: &Url, sha256: Option<Sha256Hash>) -> Option<Url> {
+        if ArchiveType::try_from(url.path()).is_none() || !is_in_channels(&self.channels, url) {
+            return None;
+        }
+        let host = url.host_str()?;
+        let authority = match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        };
+        let mut served_url = self
+            .url()
+            .join(&format!("{}/{authority}{}", url.scheme(), url.path()))
+            .ok()?;
+        if let Some(sha256) = sha256 {
+            served_url.set_query(Some(&format!("sha256={sha256:x}")));
+        }
+        Some(served_url)
+    }
+}
+
+impl Drop for PackageCacheServer {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown_sender.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+/// Returns true if `url` refers to a file of one of the `channels`.
+fn is_in_channels(channels: &[Url], url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+        && channels
+            .iter()
+            .any(|channel| url.as_str().starts_with(channel.as_str()))
+}
+
+/// Serves the archive at `path`, downloading it first if it is not cached.
+async fn serve_archive(
+    State(state): State<Arc<ServerState>>,
+    UrlPath((scheme, path)): UrlPath<(String, String)>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, (StatusCode, String)> {
+    let not_found = || (StatusCode::NOT_FOUND, format!("{scheme}/{path} not found"));
+
+    // Only accept paths that refer to archives. Relative segments are
+    // rejected before the url is parsed because parsing would resolve them.
+    let segments = path.split('/').collect::<Vec<_>>();
+    if segments.len() < 2
+        || segments
+            .iter()
+            .any(|segment| matches!(*segment, "" | "." | ".."))
+    {
+        return Err(not_found());
+    }
+    let (Some(archive_type), Some(identifier)) = (
+        ArchiveType::try_from(&path),
+        ArchiveIdentifier::try_from_filename(segments[segments.len() - 1]),
+    ) else {
+        return Err(not_found());
+    };
+    let url = Url::parse(&format!("{scheme}://{path}")).map_err(|_| not_found())?;
+    if !is_in_channels(&state.channels, &url) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("{} is not part of a served channel", url.display_redacted()),
+        ));
+    }
+
+    let sha256 = match query.as_deref().map(parse_sha256_query).transpose() {
+        Ok(sha256) => sha256.flatten(),
+        Err(()) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "invalid sha256 query parameter".to_string(),
+            ))
+        }
+    };
+
+    // Reuse the archive from the cache if it is intact, otherwise download it.
+    let cache_key = CacheKey::from(identifier).with_opt_sha256(sha256);
+    let archive_path = state.cache.archive_path(&cache_key, archive_type);
+    let is_cached = archive_path.is_file()
+        && match sha256 {
+            Some(sha256) => file_has_sha256(&archive_path, sha256).await,
+            None => true,
+        };
+    if !is_cached {
+        tracing::debug!(
+            "downloading {} to {}",
+            url.display_redacted(),
+            archive_path.display()
+        );
+        state
+            .fetcher
+            .fetch_archive(&url, &archive_path, sha256, None)
+            .await
+            .map_err(|err| {
+                (
+                    StatusCode::BAD_GATEWAY,
+                    format!("failed to download {}: {err}", url.display_redacted()),
+                )
+            })?;
+    }
+
+    let file = tokio::fs::File::open(&archive_path)
+        .await
+        .map_err(internal_error)?;
+    let len = file.metadata().await.map_err(internal_error)?.len();
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CONTENT_LENGTH, len)
+        .body(Body::from_stream(ReaderStream::new(file)))
+        .map_err(internal_error)
+}
+
+/// Parses the `sha256` parameter from the query of a request. Returns an
+/// error if the parameter is not a valid sha256 hash.
+fn parse_sha256_query(query: &str) -> Result<Option<Sha256Hash>, ()> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "sha256")
+        .map(|(_, value)| rattler_digest::parse_digest_from_hex::<Sha256>(&value).ok_or(()))
+        .transpose()
+}
+
+fn internal_error(err: impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{future::IntoFuture, net::SocketAddr, path::Path};
+
+    use axum::routing::get_service;
+    use rattler_conda_types::Channel;
+    use rattler_digest::{compute_bytes_digest, Sha256};
+    use tempfile::tempdir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tower_http::services::ServeDir;
+    use url::Url;
+
+    use super::PackageCache;
+
+    #[tokio::test]
+    async fn test_serve_archives() {
+        let test_data = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data");
+
+        // Serve the test data as the upstream server.
+        let listener = tokio::net::TcpListener::bind(SocketAddr::new([127, 0, 0, 1].into(), 0))
+            .await
+            .unwrap();
+        let upstream = Url::parse(&format!(
+            "http://localhost:{}/",
+            listener.local_addr().unwrap().port()
+        ))
+        .unwrap();
+        let app = axum::Router::new().fallback_service(get_service(ServeDir::new(&test_data)));
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let cache_dir = tempdir().unwrap();
+        let cache = PackageCache::new(cache_dir.path());
+        let server = cache
+            .serve(
+                SocketAddr::new([127, 0, 0, 1].into(), 0),
+                reqwest::Client::default().into(),
+                [Channel::from_url(upstream.join("clobber/").unwrap())],
+            )
+            .await
+            .unwrap();
+
+        // The archive is downloaded and stored in the cache on the first request.
+        let archive = "clobber/clobber-python-0.1.0-cpython.conda";
+        let archive_bytes = std::fs::read(test_data.join(archive)).unwrap();
+        let sha256 = compute_bytes_digest::<Sha256>(&archive_bytes);
+        let archive_url = upstream.join(archive).unwrap();
+        let url = server.url_for(&archive_url, None).unwrap();
+        let bytes = reqwest::get(url.clone())
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(bytes, archive_bytes);
+        let cached = cache_dir.path().join("clobber-python-0.1.0-cpython.conda");
+        assert!(cached.is_file());
+
+        // Subsequent requests are served from the cache.
+        std::fs::write(&cached, b"cached").unwrap();
+        let bytes = reqwest::get(url).await.unwrap().bytes().await.unwrap();
+        assert_eq!(bytes.as_ref(), b"cached");
+
+        // If the hash is known a corrupt archive is downloaded again.
+        let url = server.url_for(&archive_url, Some(sha256)).unwrap();
+        let bytes = reqwest::get(url).await.unwrap().bytes().await.unwrap();
+        assert_eq!(bytes, archive_bytes);
+        assert_eq!(std::fs::read(&cached).unwrap(), archive_bytes);
+
+        // An archive that does not match the hash is not served.
+        let url = server
+            .url_for(
+                &upstream
+                    .join("clobber/clobber-1-0.1.0-h4616a5c_0.tar.bz2")
+                    .unwrap(),
+                Some(sha256),
+            )
+            .unwrap();
+        let response = reqwest::get(url).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_GATEWAY);
+
+        // Only archives of the configured channels are served.
+        assert!(server
+            .url_for(&upstream.join("clobber/repodata.json").unwrap(), None)
+            .is_none());
+        let other_channel = upstream.join("other/foo-1.0-0.conda").unwrap();
+        assert!(server.url_for(&other_channel, None).is_none());
+        let response = reqwest::get(
+            server
+                .url()
+                .join(&format!(
+                    "http/localhost:{}/other/foo-1.0-0.conda",
+                    upstream.port().unwrap()
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+        // Relative path segments are rejected. The request is sent over a raw
+        // connection because http clients resolve these segments themselves.
+        let mut stream = tokio::net::TcpStream::connect(server.local_addr())
+            .await
+            .unwrap();
+        stream
+            .write_all(
+                format!(
+                    "GET /http/localhost:{}/clobber/../secret/foo-1.0-0.conda HTTP/1.1\r\n\
+                     Host: localhost\r\nConnection: close\r\n\r\n",
+                    upstream.port().unwrap()
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 404"),
+            "unexpected response: {response}"
+        );
+    }
+}
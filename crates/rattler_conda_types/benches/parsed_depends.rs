@@ -0,0 +1,50 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rattler_conda_types::{MatchSpec, ParseStrictness, RepoData};
+
+fn conda_json_path() -> String {
+    format!(
+        "{}/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        "../../test-data/channels/conda-forge/linux-64/repodata.json"
+    )
+}
+
+/// Compares parsing the dependencies of all records, which is what every
+/// solve has to do without the cache, with reading the dependencies that are
+/// cached in the records, which is what every solve after the first one does
+/// when the records are kept in memory (e.g. by the gateway).
+fn criterion_benchmark(c: &mut Criterion) {
+    let repo_data: RepoData =
+        serde_json::from_str(&std::fs::read_to_string(conda_json_path()).unwrap()).unwrap();
+    let records = repo_data
+        .packages
+        .values()
+        .chain(repo_data.conda_packages.values())
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("depends");
+    group.sample_size(10);
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            for record in &records {
+                for dependency in &record.depends {
+                    black_box(MatchSpec::from_str(dependency, ParseStrictness::Lenient).ok());
+                }
+            }
+        });
+    });
+    group.bench_function("cached", |b| {
+        for record in &records {
+            record.parsed_depends();
+        }
+        b.iter(|| {
+            for record in &records {
+                black_box(record.parsed_depends());
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,143 @@
+//! Measures the memory footprint of parsed [`Version`]s.
+//!
+//! Loading repodata creates a [`Version`] for every record, the footprint of a single version
+//! therefore directly determines how much memory is required to load large channels. This
+//! benchmark reports the inline size of a [`Version`] and the heap memory that is allocated per
+//! version for a representative set of version strings. The same versions are also converted to
+//! the layout that was used before identifiers were stored behind a thin pointer to compare both.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rattler_conda_types::{Component, Version};
+use smallvec::SmallVec;
+
+/// An allocator that keeps track of the number of bytes currently allocated.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Version strings that resemble the distribution of versions in conda-forge.
+const VERSIONS: &[&str] = &[
+    "1.0",
+    "3.11.4",
+    "1.26.4",
+    "2.2.2",
+    "0.1.0",
+    "2024.2.2",
+    "10.0.22621.0",
+    "1.0.0rc1",
+    "2.0.0b3",
+    "4.5.0.post1",
+    "1.2.3.dev0",
+    "1!2.0",
+    "3.9.16+local.1",
+    "0.0.0a20240101",
+    "9e",
+    "1.1.1w",
+];
+
+const REPETITIONS: usize = 100_000;
+
+/// A component in the previous layout, identifiers were stored in a `Box<str>`.
+#[allow(dead_code)]
+enum PreviousComponent {
+    Numeral(u64),
+    Post,
+    Dev,
+    Iden(Box<str>),
+    UnderscoreOrDash { is_dash: bool },
+}
+
+/// A [`Version`] in the previous layout.
+#[allow(dead_code)]
+struct PreviousVersion {
+    components: SmallVec<[PreviousComponent; 3]>,
+    segments: SmallVec<[u16; 4]>,
+    flags: u8,
+}
+
+impl From<&Version> for PreviousVersion {
+    fn from(version: &Version) -> Self {
+        let mut components = SmallVec::new();
+        let mut segments = SmallVec::new();
+        if let Some(epoch) = version.epoch_opt() {
+            components.push(PreviousComponent::Numeral(epoch));
+        }
+        for segment in version.segments().chain(version.local_segments()) {
+            segments.push(u16::try_from(segment.component_count()).unwrap());
+            components.extend(segment.components().map(|component| match component {
+                Component::Numeral(value) => PreviousComponent::Numeral(*value),
+                Component::Post => PreviousComponent::Post,
+                Component::Dev => PreviousComponent::Dev,
+                Component::Iden(iden) => PreviousComponent::Iden(iden.as_str().into()),
+                Component::UnderscoreOrDash { is_dash } => {
+                    PreviousComponent::UnderscoreOrDash { is_dash: *is_dash }
+                }
+            }));
+        }
+        Self {
+            components,
+            segments,
+            flags: 0,
+        }
+    }
+}
+
+/// Calls `create` and returns its result together with the number of bytes that were allocated on
+/// the heap per element, excluding the allocation of the returned vector itself.
+fn with_heap_bytes_per_element<T>(create: impl FnOnce() -> Vec<T>) -> (Vec<T>, f64) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let elements = create();
+    let heap =
+        ALLOCATED.load(Ordering::Relaxed) - before - elements.capacity() * std::mem::size_of::<T>();
+    let heap = heap as f64 / elements.len() as f64;
+    (elements, heap)
+}
+
+fn print_footprint(name: &str, inline: usize, heap: f64) {
+    println!(
+        "{name:<8} inline bytes / version: {inline:>4}, heap bytes / version: {heap:>6.1}, total bytes / version: {:>6.1}",
+        inline as f64 + heap
+    );
+}
+
+fn main() {
+    let (versions, heap) = with_heap_bytes_per_element(|| {
+        (0..REPETITIONS)
+            .flat_map(|_| VERSIONS.iter())
+            .map(|version| version.parse::<Version>().unwrap())
+            .collect::<Vec<_>>()
+    });
+    let (_, previous_heap) = with_heap_bytes_per_element(|| {
+        versions
+            .iter()
+            .map(PreviousVersion::from)
+            .collect::<Vec<_>>()
+    });
+
+    println!("versions parsed: {}", versions.len());
+    print_footprint(
+        "before",
+        std::mem::size_of::<PreviousVersion>(),
+        previous_heap,
+    );
+    print_footprint("after", std::mem::size_of::<Version>(), heap);
+}
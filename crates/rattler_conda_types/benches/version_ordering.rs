@@ -0,0 +1,67 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rattler_conda_types::Version;
+
+/// Version strings that resemble the candidates of a package in conda-forge.
+const VERSIONS: &[&str] = &[
+    "1.0",
+    "1.0.1",
+    "1.1.0",
+    "1.2.3",
+    "1.10.0",
+    "1.26.4",
+    "2.0.0",
+    "2.2.2",
+    "3.11.4",
+    "2024.2.2",
+    "1.0.0rc1",
+    "2.0.0b3",
+    "4.5.0.post1",
+    "1.2.3.dev0",
+    "1!2.0",
+    "3.9.16+local.1",
+];
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let simple: Vec<Version> = VERSIONS[..10]
+        .iter()
+        .cycle()
+        .take(1000)
+        .map(|version| version.parse().unwrap())
+        .collect();
+    let mixed: Vec<Version> = VERSIONS
+        .iter()
+        .cycle()
+        .take(1000)
+        .map(|version| version.parse().unwrap())
+        .collect();
+
+    let a: Version = "3.11.4".parse().unwrap();
+    let b: Version = "3.11.5".parse().unwrap();
+    c.bench_function("compare simple versions", |bencher| {
+        bencher.iter(|| black_box(&a).cmp(black_box(&b)));
+    });
+
+    let a: Version = "1!1.0b2.post345.dev456+3.2.20.rc3".parse().unwrap();
+    let b: Version = "1!1.0b2.post345.dev456+3.2.20.rc4".parse().unwrap();
+    c.bench_function("compare complex versions", |bencher| {
+        bencher.iter(|| black_box(&a).cmp(black_box(&b)));
+    });
+
+    c.bench_function("sort simple versions", |bencher| {
+        bencher.iter(|| {
+            let mut versions = simple.clone();
+            versions.sort();
+            versions
+        });
+    });
+    c.bench_function("sort mixed versions", |bencher| {
+        bencher.iter(|| {
+            let mut versions = mixed.clone();
+            versions.sort();
+            versions
+        });
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
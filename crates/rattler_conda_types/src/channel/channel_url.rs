@@ -0,0 +1,131 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use url::Url;
+
+use super::Channel;
+use crate::Platform;
+
+/// The normalized base url of a channel.
+///
+/// Channels are often referred to by urls that differ only superficially,
+/// e.g. `https://conda.anaconda.org/conda-forge` and
+/// `https://conda.anaconda.org/conda-forge//`. A `ChannelUrl` normalizes
+/// these differences so that channels can be compared reliably:
+///
+/// * the path always ends with exactly one `/`,
+/// * the query and fragment are removed.
+///
+/// The scheme and host are normalized by the url parser itself.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct ChannelUrl(Url);
+
+impl ChannelUrl {
+    /// Constructs a new instance by normalizing the given url.
+    pub fn new(mut url: Url) -> Self {
+        let path = format!("{}/", url.path().trim_end_matches('/'));
+        url.set_path(&path);
+        url.set_query(None);
+        url.set_fragment(None);
+        Self(url)
+    }
+
+    /// Returns the normalized url.
+    pub fn url(&self) -> &Url {
+        &self.0
+    }
+
+    /// Returns the normalized url as a string.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Returns the url of the given platform of the channel.
+    pub fn platform_url(&self, platform: Platform) -> Url {
+        self.0
+            .join(&format!("{}/", platform.as_str()))
+            .expect("platform is a valid url fragment")
+    }
+}
+
+impl From<Url> for ChannelUrl {
+    fn from(url: Url) -> Self {
+        Self::new(url)
+    }
+}
+
+impl From<&Channel> for ChannelUrl {
+    fn from(channel: &Channel) -> Self {
+        Self::new(channel.base_url.clone())
+    }
+}
+
+impl From<ChannelUrl> for Url {
+    fn from(url: ChannelUrl) -> Self {
+        url.0
+    }
+}
+
+impl AsRef<Url> for ChannelUrl {
+    fn as_ref(&self) -> &Url {
+        &self.0
+    }
+}
+
+impl FromStr for ChannelUrl {
+    type Err = url::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Url::parse(s).map(Self::new)
+    }
+}
+
+impl Display for ChannelUrl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for ChannelUrl {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChannelUrl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Url::deserialize(deserializer).map(Self::new)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::ChannelUrl;
+    use crate::{Channel, ChannelConfig, Platform};
+
+    #[test]
+    fn test_normalization() {
+        let expected = ChannelUrl::from_str("https://conda.anaconda.org/conda-forge/").unwrap();
+        for url in [
+            "https://conda.anaconda.org/conda-forge",
+            "https://conda.anaconda.org/conda-forge//",
+            "HTTPS://Conda.Anaconda.org:443/conda-forge/?foo=bar#baz",
+        ] {
+            assert_eq!(ChannelUrl::from_str(url).unwrap(), expected, "{url}");
+        }
+        assert_eq!(expected.as_str(), "https://conda.anaconda.org/conda-forge/");
+        assert_eq!(
+            expected.platform_url(Platform::NoArch).as_str(),
+            "https://conda.anaconda.org/conda-forge/noarch/"
+        );
+
+        let config = ChannelConfig::default_with_root_dir(std::env::current_dir().unwrap());
+        let channel = Channel::from_str("conda-forge[linux-64]", &config).unwrap();
+        assert_eq!(channel.channel_url(), expected);
+    }
+}
@@ -18,6 +18,10 @@ use crate::utils::{
     url::{add_trailing_slash, parse_scheme},
 };
 
+mod channel_url;
+
+pub use channel_url::ChannelUrl;
+
 const DEFAULT_CHANNEL_ALIAS: &str = "https://conda.anaconda.org";
 
 /// The `ChannelConfig` describes properties that are required to resolve
@@ -341,6 +345,14 @@ impl Channel {
         &self.base_url
     }
 
+    /// Returns the normalized base url of the channel. Unlike the channel
+    /// itself, the [`ChannelUrl`] does not depend on the name or the
+    /// platforms of the channel, which makes it suitable to identify the
+    /// channel.
+    pub fn channel_url(&self) -> ChannelUrl {
+        ChannelUrl::from(self)
+    }
+
     /// Returns the Urls for the given platform
     pub fn platform_url(&self, platform: Platform) -> Url {
         self.base_url()
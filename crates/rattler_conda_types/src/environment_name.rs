@@ -0,0 +1,259 @@
+use std::{
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use thiserror::Error;
+
+/// The name of the directory inside a root directory that contains the named
+/// environments.
+pub const ENVS_DIR: &str = "envs";
+
+/// The name that refers to the root environment itself.
+pub const BASE_ENVIRONMENT_NAME: &str = "base";
+
+/// The name of a conda environment, e.g. the `myenv` in `conda activate
+/// myenv`.
+///
+/// A name always refers to a single directory inside the `envs` directory of
+/// an [`EnvironmentRegistry`]. Names that could refer to a path outside of
+/// that directory, e.g. because they contain a path separator or are `..`,
+/// are rejected.
+#[derive(
+    Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord, SerializeDisplay, DeserializeFromStr,
+)]
+pub struct EnvironmentName(String);
+
+/// An error that is returned when conversion from a string to an
+/// [`EnvironmentName`] fails.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum InvalidEnvironmentNameError {
+    /// The name is empty.
+    #[error("environment names cannot be empty")]
+    Empty,
+
+    /// The name contains a character that is not allowed.
+    #[error("'{0}' is not a valid environment name. Environment names cannot contain '/', '\\', ':', '#' or whitespace")]
+    InvalidCharacters(String),
+
+    /// The name refers to a relative directory, e.g. `..`.
+    #[error("'{0}' is not a valid environment name")]
+    RelativeDirectory(String),
+}
+
+impl EnvironmentName {
+    /// Returns the name as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns true if this is the name of the root environment.
+    pub fn is_base(&self) -> bool {
+        self.0 == BASE_ENVIRONMENT_NAME
+    }
+}
+
+impl TryFrom<String> for EnvironmentName {
+    type Error = InvalidEnvironmentNameError;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        if name.is_empty() {
+            return Err(InvalidEnvironmentNameError::Empty);
+        }
+        if name
+            .chars()
+            .any(|c| matches!(c, '/' | '\\' | ':' | '#') || c.is_whitespace() || c.is_control())
+        {
+            return Err(InvalidEnvironmentNameError::InvalidCharacters(name));
+        }
+        if name == "." || name == ".." {
+            return Err(InvalidEnvironmentNameError::RelativeDirectory(name));
+        }
+        Ok(Self(name))
+    }
+}
+
+impl<'a> TryFrom<&'a str> for EnvironmentName {
+    type Error = InvalidEnvironmentNameError;
+
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        value.to_owned().try_into()
+    }
+}
+
+impl FromStr for EnvironmentName {
+    type Err = InvalidEnvironmentNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.to_owned().try_into()
+    }
+}
+
+impl Display for EnvironmentName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for EnvironmentName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// A root directory that contains named environments, using the same layout
+/// as conda:
+///
+/// ```text
+/// <root>/              the root (or `base`) environment
+/// <root>/envs/<name>/  the environment named `<name>`
+/// ```
+///
+/// A directory is considered an environment if it contains a `conda-meta`
+/// directory.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EnvironmentRegistry {
+    root: PathBuf,
+}
+
+impl EnvironmentRegistry {
+    /// Constructs a new registry for the given root directory.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Returns the root directory. This is also the prefix of the `base`
+    /// environment.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the directory that contains the named environments.
+    pub fn envs_dir(&self) -> PathBuf {
+        self.root.join(ENVS_DIR)
+    }
+
+    /// Returns the prefix of the environment with the given name. The
+    /// environment does not have to exist.
+    pub fn prefix(&self, name: &EnvironmentName) -> PathBuf {
+        if name.is_base() {
+            self.root.clone()
+        } else {
+            self.envs_dir().join(name.as_str())
+        }
+    }
+
+    /// Returns true if the environment with the given name exists.
+    pub fn contains(&self, name: &EnvironmentName) -> bool {
+        is_environment(&self.prefix(name))
+    }
+
+    /// Returns the name of the environment at the given prefix, or `None` if
+    /// the prefix is not managed by this registry.
+    pub fn name_of(&self, prefix: &Path) -> Option<EnvironmentName> {
+        if prefix == self.root {
+            return Some(EnvironmentName(BASE_ENVIRONMENT_NAME.to_string()));
+        }
+        let relative = prefix.strip_prefix(self.envs_dir()).ok()?;
+        let mut components = relative.components();
+        let name = components.next()?.as_os_str().to_str()?;
+        if components.next().is_some() {
+            return None;
+        }
+        name.parse().ok()
+    }
+
+    /// Returns the names of all the environments in the registry, including
+    /// the `base` environment if it exists. The names are sorted.
+    pub fn environments(&self) -> Result<Vec<EnvironmentName>, std::io::Error> {
+        let mut names = Vec::new();
+        if is_environment(&self.root) {
+            names.push(EnvironmentName(BASE_ENVIRONMENT_NAME.to_string()));
+        }
+
+        let read_dir = match std::fs::read_dir(self.envs_dir()) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+            Err(e) => return Err(e),
+        };
+        for entry in read_dir {
+            let entry = entry?;
+            let Some(name) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| EnvironmentName::from_str(name).ok())
+            else {
+                continue;
+            };
+            if !name.is_base() && is_environment(&entry.path()) {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// Returns true if the directory is a conda environment.
+fn is_environment(prefix: &Path) -> bool {
+    prefix.join("conda-meta").is_dir()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, str::FromStr};
+
+    use super::{EnvironmentName, EnvironmentRegistry, InvalidEnvironmentNameError};
+
+    #[test]
+    fn test_environment_name() {
+        assert!(EnvironmentName::from_str("my-env_1.0").is_ok());
+        assert_eq!(
+            EnvironmentName::from_str(""),
+            Err(InvalidEnvironmentNameError::Empty)
+        );
+        for name in ["../foo", "foo/bar", "foo\\bar", "C:", "foo bar", "foo#bar"] {
+            assert!(
+                matches!(
+                    EnvironmentName::from_str(name),
+                    Err(InvalidEnvironmentNameError::InvalidCharacters(_))
+                ),
+                "{name}"
+            );
+        }
+        assert!(matches!(
+            EnvironmentName::from_str(".."),
+            Err(InvalidEnvironmentNameError::RelativeDirectory(_))
+        ));
+    }
+
+    #[test]
+    fn test_environment_registry() {
+        let root = tempfile::tempdir().unwrap();
+        let registry = EnvironmentRegistry::new(root.path());
+        assert!(registry.environments().unwrap().is_empty());
+
+        let base = EnvironmentName::from_str("base").unwrap();
+        let foo = EnvironmentName::from_str("foo").unwrap();
+        assert_eq!(registry.prefix(&base), root.path());
+        assert_eq!(registry.prefix(&foo), root.path().join("envs").join("foo"));
+        assert_eq!(registry.name_of(&registry.prefix(&foo)), Some(foo.clone()));
+        assert_eq!(registry.name_of(root.path()), Some(base.clone()));
+        assert_eq!(registry.name_of(Path::new("/somewhere/else")), None);
+        assert_eq!(
+            registry.name_of(&root.path().join("envs").join("foo").join("bin")),
+            None
+        );
+
+        for name in [&base, &foo] {
+            std::fs::create_dir_all(registry.prefix(name).join("conda-meta")).unwrap();
+        }
+        // Directories that are not environments are ignored.
+        std::fs::create_dir_all(root.path().join("envs").join("not-an-env")).unwrap();
+
+        assert!(registry.contains(&foo));
+        assert_eq!(registry.environments().unwrap(), [base, foo]);
+    }
+}
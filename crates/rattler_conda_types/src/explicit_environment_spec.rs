@@ -9,7 +9,11 @@
 //!
 //! To create an explicit environment file, you can use the `conda env export` command.
 
-use crate::{ParsePlatformError, Platform};
+use crate::{
+    package::{ArchiveIdentifier, IndexJson},
+    InvalidPackageNameError, PackageName, PackageRecord, ParsePlatformError, ParseVersionError,
+    Platform, RepoDataRecord, VersionWithSource,
+};
 use serde::{Deserialize, Serialize};
 use std::{fs, fs::File, io::Read, path::Path, str::FromStr};
 use url::Url;
@@ -106,6 +110,118 @@ impl ExplicitEnvironmentEntry {
     }
 }
 
+/// An error that can occur when converting an [`ExplicitEnvironmentEntry`] to a
+/// [`RepoDataRecord`].
+#[derive(Debug, thiserror::Error)]
+pub enum ExplicitEntryToRecordError {
+    /// The url does not refer to a package archive.
+    #[error("the url '{0}' does not refer to a package archive")]
+    InvalidArchiveUrl(Url),
+
+    /// The package name in the filename is invalid.
+    #[error("invalid package name in '{0}'")]
+    InvalidPackageName(String, #[source] InvalidPackageNameError),
+
+    /// The version in the filename is invalid.
+    #[error("invalid version in '{0}'")]
+    InvalidVersion(String, #[source] ParseVersionError),
+
+    /// The hash of the url is invalid.
+    #[error(transparent)]
+    InvalidHash(#[from] ParsePackageArchiveHashError),
+}
+
+impl ExplicitEnvironmentEntry {
+    /// Converts the entry to a [`RepoDataRecord`] that can be installed without
+    /// fetching repodata.
+    ///
+    /// The name, version and build string are derived from the filename of the
+    /// url, the subdirectory and channel from the path that precedes it. The
+    /// build number is derived from the build string (e.g. `0` for
+    /// `h4150a38_0`). If the url contains a hash it is stored in the record so
+    /// the package archive can be verified. Any other information, like the
+    /// dependencies, constraints and noarch type of the package, is not part
+    /// of an explicit environment and is left empty. Use
+    /// [`Self::to_repodata_record_with_index_json`] to fill in this
+    /// information from the package archive once it has been downloaded.
+    pub fn to_repodata_record(&self) -> Result<RepoDataRecord, ExplicitEntryToRecordError> {
+        let invalid_url = || ExplicitEntryToRecordError::InvalidArchiveUrl(self.url.clone());
+
+        let mut url = self.url.clone();
+        url.set_fragment(None);
+
+        let mut segments = url.path_segments().ok_or_else(invalid_url)?.rev();
+        let file_name = segments.next().ok_or_else(invalid_url)?.to_owned();
+        let subdir = segments
+            .next()
+            .filter(|subdir| !subdir.is_empty())
+            .ok_or_else(invalid_url)?
+            .to_owned();
+        let identifier =
+            ArchiveIdentifier::try_from_filename(&file_name).ok_or_else(invalid_url)?;
+
+        let mut channel = url.clone();
+        channel
+            .path_segments_mut()
+            .map_err(|()| invalid_url())?
+            .pop()
+            .pop()
+            .push("");
+
+        let name = PackageName::from_str(&identifier.name)
+            .map_err(|e| ExplicitEntryToRecordError::InvalidPackageName(file_name.clone(), e))?;
+        let version = VersionWithSource::from_str(&identifier.version)
+            .map_err(|e| ExplicitEntryToRecordError::InvalidVersion(file_name.clone(), e))?;
+        let mut package_record = PackageRecord::new(name, version, identifier.build_string);
+        package_record.build_number = package_record
+            .build
+            .rsplit('_')
+            .next()
+            .and_then(|build_number| build_number.parse().ok())
+            .unwrap_or(0);
+        package_record.subdir = subdir;
+        match self.package_archive_hash()? {
+            Some(PackageArchiveHash::Md5(md5)) => package_record.md5 = Some(md5),
+            Some(PackageArchiveHash::Sha256(sha256)) => package_record.sha256 = Some(sha256),
+            None => {}
+        }
+
+        Ok(RepoDataRecord {
+            package_record,
+            file_name,
+            url,
+            channel: channel.to_string(),
+        })
+    }
+
+    /// Converts the entry to a [`RepoDataRecord`] like
+    /// [`Self::to_repodata_record`], but takes the metadata of the package,
+    /// including its dependencies, constraints and noarch type, from the
+    /// `index.json` of the package archive.
+    ///
+    /// The url, channel, filename and hashes are still derived from the entry.
+    pub fn to_repodata_record_with_index_json(
+        &self,
+        mut index_json: IndexJson,
+    ) -> Result<RepoDataRecord, ExplicitEntryToRecordError> {
+        let record = self.to_repodata_record()?;
+        index_json
+            .subdir
+            .get_or_insert_with(|| record.package_record.subdir.clone());
+        let package_record = PackageRecord::from_index_json(
+            index_json,
+            None,
+            record.package_record.sha256,
+            record.package_record.md5,
+        )
+        .expect("the subdir is always known");
+        Ok(RepoDataRecord {
+            package_record,
+            ..record
+        })
+    }
+}
+
 impl From<Url> for ExplicitEnvironmentEntry {
     fn from(url: Url) -> Self {
         ExplicitEnvironmentEntry { url }
@@ -169,6 +285,16 @@ impl ExplicitEnvironmentSpec {
         s
     }
 
+    /// Converts all the packages of the environment to [`RepoDataRecord`]s in
+    /// the order in which they appear in the file. See
+    /// [`ExplicitEnvironmentEntry::to_repodata_record`].
+    pub fn to_repodata_records(&self) -> Result<Vec<RepoDataRecord>, ExplicitEntryToRecordError> {
+        self.packages
+            .iter()
+            .map(ExplicitEnvironmentEntry::to_repodata_record)
+            .collect()
+    }
+
     /// Writes an explicit environment spec to file
     pub fn to_path(&self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
         let s = self.to_spec_string();
@@ -222,8 +348,12 @@ impl FromStr for ExplicitEnvironmentSpec {
 mod test {
     use super::{ExplicitEnvironmentSpec, ParseExplicitEnvironmentSpecError};
     use crate::{
-        explicit_environment_spec::{PackageArchiveHash, ParsePackageArchiveHashError},
-        get_test_data_dir, ExplicitEnvironmentEntry,
+        explicit_environment_spec::{
+            ExplicitEntryToRecordError, PackageArchiveHash, ParsePackageArchiveHashError,
+        },
+        get_test_data_dir,
+        package::{IndexJson, PackageFile},
+        ExplicitEnvironmentEntry, NoArchType,
     };
     use assert_matches::assert_matches;
     use hex_literal::hex;
@@ -323,6 +453,78 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_to_repodata_records() {
+        let env = ExplicitEnvironmentSpec::from_path(
+            &get_test_data_dir().join("explicit-envs/xtensor_linux-64.txt"),
+        )
+        .unwrap();
+        let records = env.to_repodata_records().unwrap();
+        assert_eq!(records.len(), env.packages.len());
+
+        let record = &records[1];
+        assert_eq!(record.file_name, "libstdcxx-ng-9.3.0-h2ae2ef3_17.tar.bz2");
+        assert_eq!(
+            record.url.as_str(),
+            "https://conda.anaconda.org/conda-forge/linux-64/libstdcxx-ng-9.3.0-h2ae2ef3_17.tar.bz2"
+        );
+        assert_eq!(record.channel, "https://conda.anaconda.org/conda-forge/");
+        assert_eq!(record.package_record.name.as_normalized(), "libstdcxx-ng");
+        assert_eq!(record.package_record.version.as_str(), "9.3.0");
+        assert_eq!(record.package_record.build, "h2ae2ef3_17");
+        assert_eq!(record.package_record.build_number, 17);
+        assert_eq!(record.package_record.subdir, "linux-64");
+        assert_eq!(
+            record.package_record.md5,
+            Some(hex!("342f3c931d0a3a209ab09a522469d20c").into())
+        );
+
+        let entry: ExplicitEnvironmentEntry = Url::parse("https://example.com/not-a-package.txt")
+            .unwrap()
+            .into();
+        assert_matches!(
+            entry.to_repodata_record(),
+            Err(ExplicitEntryToRecordError::InvalidArchiveUrl(_))
+        );
+    }
+
+    #[test]
+    fn test_to_repodata_record_with_index_json() {
+        let entry: ExplicitEnvironmentEntry = Url::parse(
+            "https://conda.anaconda.org/conda-forge/noarch/six-1.16.0-pyh6c4a22f_0.tar.bz2#a9e4e3b5e0e6b5e8a7a7d2f6f3f0b6c1",
+        )
+        .unwrap()
+        .into();
+        let index_json = IndexJson::from_str(
+            r#"{
+                "name": "six",
+                "version": "1.16.0",
+                "build": "pyh6c4a22f_0",
+                "build_number": 0,
+                "depends": ["python"],
+                "constrains": ["pytest >=6"],
+                "noarch": "python",
+                "license": "MIT"
+            }"#,
+        )
+        .unwrap();
+
+        let record = entry
+            .to_repodata_record_with_index_json(index_json)
+            .unwrap();
+        assert_eq!(record.file_name, "six-1.16.0-pyh6c4a22f_0.tar.bz2");
+        assert_eq!(record.channel, "https://conda.anaconda.org/conda-forge/");
+        assert_eq!(record.package_record.subdir, "noarch");
+        assert_eq!(record.package_record.depends, ["python"]);
+        assert_eq!(record.package_record.constrains, ["pytest >=6"]);
+        assert_eq!(record.package_record.noarch, NoArchType::python());
+        assert_eq!(record.package_record.license.as_deref(), Some("MIT"));
+        assert_eq!(
+            record.package_record.md5,
+            Some(hex!("a9e4e3b5e0e6b5e8a7a7d2f6f3f0b6c1").into())
+        );
+    }
+
     #[test]
     fn test_entry_package_hash() {
         let entry: ExplicitEnvironmentEntry = Url::parse("https://repo.anaconda.com/pkgs/main/win-64/vs2015_runtime-14.16.27012-hf0eaf9b_3.conda#a98ea1e3abfdbbd201d60ff6b43ea7e4").unwrap().into();
@@ -18,22 +18,28 @@ mod utils;
 mod version;
 pub mod version_spec;
 
+mod environment_name;
 mod environment_yaml;
 mod generic_virtual_package;
 pub mod package;
 mod package_name;
 pub mod prefix_record;
+pub mod sbom;
 
 #[cfg(test)]
 use std::path::{Path, PathBuf};
 
 pub use build_spec::{BuildNumber, BuildNumberSpec, ParseBuildNumberSpecError};
-pub use channel::{Channel, ChannelConfig, NamedChannelOrUrl, ParseChannelError};
+pub use channel::{Channel, ChannelConfig, ChannelUrl, NamedChannelOrUrl, ParseChannelError};
 pub use channel_data::{ChannelData, ChannelDataPackage};
+pub use environment_name::{
+    EnvironmentName, EnvironmentRegistry, InvalidEnvironmentNameError, BASE_ENVIRONMENT_NAME,
+    ENVS_DIR,
+};
 pub use environment_yaml::EnvironmentYaml;
 pub use explicit_environment_spec::{
-    ExplicitEnvironmentEntry, ExplicitEnvironmentSpec, PackageArchiveHash,
-    ParseExplicitEnvironmentSpecError, ParsePackageArchiveHashError,
+    ExplicitEntryToRecordError, ExplicitEnvironmentEntry, ExplicitEnvironmentSpec,
+    PackageArchiveHash, ParseExplicitEnvironmentSpecError, ParsePackageArchiveHashError,
 };
 pub use generic_virtual_package::GenericVirtualPackage;
 pub use match_spec::{
@@ -49,14 +55,16 @@ pub use prefix_record::PrefixRecord;
 pub use repo_data::{
     compute_package_url,
     patches::{PackageRecordPatch, PatchInstructions, RepoDataPatch},
-    sharded::{Shard, ShardedRepodata, ShardedSubdirInfo},
-    ChannelInfo, ConvertSubdirError, PackageRecord, RepoData,
+    rewrite::{DependencyChange, DependencyKind, DependencyRewrite, RewrittenRecord},
+    sharded::{Shard, ShardDictionary, ShardedRepodata, ShardedSubdirInfo},
+    ChannelInfo, ConvertSubdirError, InvalidRecordError, PackageRecord, ParseRepoDataError,
+    RepoData, RepoDataParseReport, SkippedRecord,
 };
 pub use repo_data_record::RepoDataRecord;
 pub use run_export::RunExportKind;
 pub use version::{
-    Component, ParseVersionError, ParseVersionErrorKind, StrictVersion, Version, VersionBumpError,
-    VersionBumpType, VersionExtendError, VersionWithSource,
+    Component, Identifier, ParseVersionError, ParseVersionErrorKind, StrictVersion, Version,
+    VersionBumpError, VersionBumpType, VersionExtendError, VersionWithSource,
 };
 pub use version_spec::VersionSpec;
 
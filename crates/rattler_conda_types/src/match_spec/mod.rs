@@ -1,9 +1,14 @@
-use crate::{build_spec::BuildNumberSpec, PackageName, PackageRecord, RepoDataRecord, VersionSpec};
+use crate::{
+    build_spec::BuildNumberSpec, GenericVirtualPackage, PackageName, PackageRecord, RepoDataRecord,
+    VersionSpec,
+};
 use rattler_digest::{serde::SerializableHash, Md5Hash, Sha256Hash};
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_with::{serde_as, skip_serializing_none, DisplayFromStr};
+use std::collections::BTreeSet;
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::Hash;
+use std::path::PathBuf;
 use std::sync::Arc;
 use url::Url;
 
@@ -146,13 +151,59 @@ pub struct MatchSpec {
     pub sha256: Option<Sha256Hash>,
     /// The url of the package
     pub url: Option<Url>,
+    /// The track features of the package (e.g. `[track_features=mkl]`). A
+    /// package only matches if it has exactly these track features.
+    pub track_features: Option<Vec<String>>,
+    /// The optional dependency groups of the package that are requested (e.g.
+    /// `[extras=[bar]]`). A package only matches if it provides all of them,
+    /// see [`PackageRecord::extra_depends`].
+    pub extras: Option<Vec<String>>,
 }
 
 impl Display for MatchSpec {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with_channel_config(f, None)
+    }
+}
+
+impl MatchSpec {
+    /// Formats the spec like its [`Display`] implementation but refers to
+    /// channels under the channel alias of `channel_config` by their name.
+    /// The [`Display`] implementation uses the default channel alias.
+    pub fn to_string_with_channel_config(&self, channel_config: &ChannelConfig) -> String {
+        struct WithChannelConfig<'a>(&'a MatchSpec, &'a ChannelConfig);
+
+        impl Display for WithChannelConfig<'_> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                self.0.fmt_with_channel_config(f, Some(self.1))
+            }
+        }
+
+        WithChannelConfig(self, channel_config).to_string()
+    }
+
+    fn fmt_with_channel_config(
+        &self,
+        f: &mut Formatter<'_>,
+        channel_config: Option<&ChannelConfig>,
+    ) -> std::fmt::Result {
         if let Some(channel) = &self.channel {
-            let name = channel.name();
-            write!(f, "{name}")?;
+            // Only channels under the channel alias can be referred to by
+            // their name, other channels are written as urls.
+            let stripped = match channel_config {
+                Some(channel_config) => channel_config.strip_channel_alias(&channel.base_url),
+                None => ChannelConfig::default_with_root_dir(PathBuf::new())
+                    .strip_channel_alias(&channel.base_url),
+            };
+            match &channel.name {
+                Some(name)
+                    if stripped.as_deref().map(|rest| rest.trim_start_matches('/'))
+                        == Some(name.as_str()) =>
+                {
+                    write!(f, "{name}")?;
+                }
+                _ => write!(f, "{}", channel.base_url.as_str().trim_end_matches('/'))?,
+            }
 
             if let Some(subdir) = &self.subdir {
                 write!(f, "/{subdir}")?;
@@ -161,7 +212,7 @@ impl Display for MatchSpec {
 
         if let Some(namespace) = &self.namespace {
             write!(f, ":{namespace}:")?;
-        } else if self.channel.is_some() || self.subdir.is_some() {
+        } else if self.channel.is_some() {
             write!(f, "::")?;
         }
 
@@ -180,6 +231,23 @@ impl Display for MatchSpec {
 
         let mut keys = Vec::new();
 
+        if let Some(build_number) = &self.build_number {
+            keys.push(format!("build_number=\"{build_number}\""));
+        }
+
+        // Without a channel the subdir cannot be part of the channel prefix.
+        if let (None, Some(subdir)) = (&self.channel, &self.subdir) {
+            keys.push(format!("subdir={subdir}"));
+        }
+
+        if let Some(file_name) = &self.file_name {
+            keys.push(format!("fn=\"{file_name}\""));
+        }
+
+        if let Some(url) = &self.url {
+            keys.push(format!("url=\"{url}\""));
+        }
+
         if let Some(md5) = &self.md5 {
             keys.push(format!("md5={md5:x}"));
         }
@@ -188,15 +256,21 @@ impl Display for MatchSpec {
             keys.push(format!("sha256={sha256:x}"));
         }
 
+        if let Some(track_features) = &self.track_features {
+            keys.push(format!("track_features=\"{}\"", track_features.join(" ")));
+        }
+
+        if let Some(extras) = &self.extras {
+            keys.push(format!("extras=[{}]", extras.join(", ")));
+        }
+
         if !keys.is_empty() {
             write!(f, "[{}]", keys.join(", "))?;
         }
 
         Ok(())
     }
-}
 
-impl MatchSpec {
     /// Decomposes this instance into a [`NamelessMatchSpec`] and a name.
     pub fn into_nameless(self) -> (Option<PackageName>, NamelessMatchSpec) {
         (
@@ -212,9 +286,61 @@ impl MatchSpec {
                 md5: self.md5,
                 sha256: self.sha256,
                 url: self.url,
+                track_features: self.track_features,
+                extras: self.extras,
             },
         )
     }
+
+    /// Returns true if the package described by `record` that is located at
+    /// `url` satisfies this spec.
+    ///
+    /// Next to the fields that are matched against the [`PackageRecord`] this
+    /// also takes the fields into account that describe where a package is
+    /// located:
+    ///
+    /// * `url`: the url of the package must be identical, this is used by
+    ///   direct url specs. Any hashes in the spec must still match.
+    /// * `channel`: the package must be located inside the channel.
+    /// * `subdir`: the subdir of the package must be identical.
+    /// * `file_name`: the filename of the package must be identical. If the
+    ///   filename of the package is unknown it never matches.
+    ///
+    /// This is the logic used when matching specs against
+    /// [`RepoDataRecord`]s but it can also be used for other representations
+    /// of packages, like locked packages.
+    pub fn matches_package_at(
+        &self,
+        record: &PackageRecord,
+        url: &Url,
+        file_name: Option<&str>,
+    ) -> bool {
+        if let Some(url_spec) = self.url.as_ref() {
+            if url_spec != url {
+                return false;
+            }
+        }
+
+        if let Some(channel) = self.channel.as_ref() {
+            if !url.as_str().starts_with(channel.base_url.as_str()) {
+                return false;
+            }
+        }
+
+        if let Some(subdir) = self.subdir.as_ref() {
+            if subdir != &record.subdir {
+                return false;
+            }
+        }
+
+        if let Some(file_name_spec) = self.file_name.as_ref() {
+            if Some(file_name_spec.as_str()) != file_name {
+                return false;
+            }
+        }
+
+        self.matches(record)
+    }
 }
 
 // Enable constructing a match spec from a package name.
@@ -258,6 +384,13 @@ pub struct NamelessMatchSpec {
     pub sha256: Option<Sha256Hash>,
     /// The url of the package
     pub url: Option<Url>,
+    /// The track features of the package (e.g. `[track_features=mkl]`). A
+    /// package only matches if it has exactly these track features.
+    pub track_features: Option<Vec<String>>,
+    /// The optional dependency groups of the package that are requested (e.g.
+    /// `[extras=[bar]]`). A package only matches if it provides all of them,
+    /// see [`PackageRecord::extra_depends`].
+    pub extras: Option<Vec<String>>,
 }
 
 impl Display for NamelessMatchSpec {
@@ -281,6 +414,14 @@ impl Display for NamelessMatchSpec {
             keys.push(format!("sha256={sha256:x}"));
         }
 
+        if let Some(track_features) = &self.track_features {
+            keys.push(format!("track_features=\"{}\"", track_features.join(" ")));
+        }
+
+        if let Some(extras) = &self.extras {
+            keys.push(format!("extras=[{}]", extras.join(", ")));
+        }
+
         if !keys.is_empty() {
             write!(f, "[{}]", keys.join(", "))?;
         }
@@ -302,6 +443,8 @@ impl From<MatchSpec> for NamelessMatchSpec {
             md5: spec.md5,
             sha256: spec.sha256,
             url: spec.url,
+            track_features: spec.track_features,
+            extras: spec.extras,
         }
     }
 }
@@ -321,6 +464,8 @@ impl MatchSpec {
             md5: spec.md5,
             sha256: spec.sha256,
             url: spec.url,
+            track_features: spec.track_features,
+            extras: spec.extras,
         }
     }
 }
@@ -388,6 +533,21 @@ impl Matches<PackageRecord> for NamelessMatchSpec {
             }
         }
 
+        if let Some(track_features) = self.track_features.as_ref() {
+            if !track_features_match(track_features, &other.track_features) {
+                return false;
+            }
+        }
+
+        if let Some(extras) = self.extras.as_ref() {
+            if !extras
+                .iter()
+                .all(|extra| other.extra_depends.contains_key(extra))
+            {
+                return false;
+            }
+        }
+
         true
     }
 }
@@ -431,24 +591,141 @@ impl Matches<PackageRecord> for MatchSpec {
             }
         }
 
+        if let Some(track_features) = self.track_features.as_ref() {
+            if !track_features_match(track_features, &other.track_features) {
+                return false;
+            }
+        }
+
+        if let Some(extras) = self.extras.as_ref() {
+            if !extras
+                .iter()
+                .all(|extra| other.extra_depends.contains_key(extra))
+            {
+                return false;
+            }
+        }
+
         true
     }
 }
 
-impl Matches<RepoDataRecord> for MatchSpec {
-    /// Match a [`MatchSpec`] against a [`RepoDataRecord`]
-    fn matches(&self, other: &RepoDataRecord) -> bool {
-        if let Some(url_spec) = self.url.as_ref() {
-            if url_spec != &other.url {
+impl Matches<GenericVirtualPackage> for NamelessMatchSpec {
+    /// Match a [`NamelessMatchSpec`] against a [`GenericVirtualPackage`].
+    ///
+    /// Virtual packages always have build number `0`, no track features and
+    /// no extras.
+    /// They are not backed by an actual file so specs that refer to a
+    /// specific file never match.
+    fn matches(&self, other: &GenericVirtualPackage) -> bool {
+        if let Some(spec) = self.version.as_ref() {
+            if !spec.matches(&other.version) {
                 return false;
             }
         }
 
-        if !self.matches(&other.package_record) {
+        if let Some(build_string) = self.build.as_ref() {
+            if !build_string.matches(&other.build_string) {
+                return false;
+            }
+        }
+
+        if let Some(build_number) = self.build_number.as_ref() {
+            if !build_number.matches(&0) {
+                return false;
+            }
+        }
+
+        if let Some(track_features) = self.track_features.as_ref() {
+            if !track_features_match(track_features, &[]) {
+                return false;
+            }
+        }
+
+        if self
+            .extras
+            .as_ref()
+            .is_some_and(|extras| !extras.is_empty())
+        {
             return false;
         }
 
-        true
+        self.md5.is_none()
+            && self.sha256.is_none()
+            && self.url.is_none()
+            && self.file_name.is_none()
+    }
+}
+
+impl Matches<GenericVirtualPackage> for MatchSpec {
+    /// Match a [`MatchSpec`] against a [`GenericVirtualPackage`]. See the
+    /// implementation for [`NamelessMatchSpec`] for more information.
+    fn matches(&self, other: &GenericVirtualPackage) -> bool {
+        if let Some(name) = self.name.as_ref() {
+            if name != &other.name {
+                return false;
+            }
+        }
+
+        if let Some(spec) = self.version.as_ref() {
+            if !spec.matches(&other.version) {
+                return false;
+            }
+        }
+
+        if let Some(build_string) = self.build.as_ref() {
+            if !build_string.matches(&other.build_string) {
+                return false;
+            }
+        }
+
+        if let Some(build_number) = self.build_number.as_ref() {
+            if !build_number.matches(&0) {
+                return false;
+            }
+        }
+
+        if let Some(track_features) = self.track_features.as_ref() {
+            if !track_features_match(track_features, &[]) {
+                return false;
+            }
+        }
+
+        if self
+            .extras
+            .as_ref()
+            .is_some_and(|extras| !extras.is_empty())
+        {
+            return false;
+        }
+
+        self.md5.is_none()
+            && self.sha256.is_none()
+            && self.url.is_none()
+            && self.file_name.is_none()
+    }
+}
+
+/// Returns true if the track features of a spec match the track features of a
+/// record. Similar to conda, the sets of features have to be equal. Features
+/// can be separated by commas or whitespace.
+fn track_features_match(spec: &[String], record: &[String]) -> bool {
+    fn feature_set(features: &[String]) -> BTreeSet<&str> {
+        features
+            .iter()
+            .flat_map(|f| f.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|f| !f.is_empty())
+            .collect()
+    }
+
+    feature_set(spec) == feature_set(record)
+}
+
+impl Matches<RepoDataRecord> for MatchSpec {
+    /// Match a [`MatchSpec`] against a [`RepoDataRecord`]. See
+    /// [`MatchSpec::matches_package_at`].
+    fn matches(&self, other: &RepoDataRecord) -> bool {
+        self.matches_package_at(&other.package_record, &other.url, Some(&other.file_name))
     }
 }
 
@@ -471,13 +748,14 @@ impl Matches<RepoDataRecord> for NamelessMatchSpec {
 
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
+    use std::{str::FromStr, sync::Arc};
 
     use rattler_digest::{parse_digest_from_hex, Md5, Sha256};
+    use url::Url;
 
     use crate::{
-        match_spec::Matches, MatchSpec, NamelessMatchSpec, PackageName, PackageRecord,
-        ParseStrictness::*, RepoDataRecord, Version,
+        match_spec::Matches, Channel, ChannelConfig, GenericVirtualPackage, MatchSpec,
+        NamelessMatchSpec, PackageName, PackageRecord, ParseStrictness::*, RepoDataRecord, Version,
     };
     use insta::assert_snapshot;
     use std::hash::{Hash, Hasher};
@@ -491,6 +769,69 @@ mod tests {
         assert_eq!(spec, rebuild_spec);
     }
 
+    #[test]
+    fn test_channel_and_subdir_format_eq() {
+        for strictness in [Strict, Lenient] {
+            let spec = MatchSpec::from_str(
+                "conda-forge/linux-64::python=3.10[build=*cpython*]",
+                strictness,
+            )
+            .unwrap();
+            assert_eq!(spec.channel.as_ref().unwrap().name(), "conda-forge");
+            assert_eq!(spec.subdir.as_deref(), Some("linux-64"));
+            assert_eq!(spec.name, Some(PackageName::new_unchecked("python")));
+            assert_eq!(spec.version.as_ref().unwrap().to_string(), "3.10.*");
+            assert_eq!(spec.build.as_ref().unwrap().to_string(), "*cpython*");
+            assert_eq!(
+                spec.to_string(),
+                "conda-forge/linux-64::python 3.10.* *cpython*"
+            );
+            assert_eq!(
+                MatchSpec::from_str(&spec.to_string(), strictness).unwrap(),
+                spec
+            );
+        }
+
+        for spec in [
+            "https://c.com/p/conda/linux-64::python[version=3.9]",
+            "python[subdir=linux-64, build_number=\">=2\"]",
+            "python[fn=\"python-3.9.0-0.conda\"]",
+            "python[url=\"https://c.com/p/conda/linux-64/python-3.9.0-0.conda\"]",
+        ] {
+            let spec = MatchSpec::from_str(spec, Strict).unwrap();
+            assert_eq!(
+                MatchSpec::from_str(&spec.to_string(), Strict).unwrap(),
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_with_channel_config() {
+        let channel_config = ChannelConfig {
+            channel_alias: Url::parse("https://prefix.dev/").unwrap(),
+            ..ChannelConfig::default_with_root_dir(std::env::current_dir().unwrap())
+        };
+        let spec = MatchSpec {
+            channel: Some(Arc::new(
+                Channel::from_str("conda-forge", &channel_config).unwrap(),
+            )),
+            ..MatchSpec::from_str("python", Strict).unwrap()
+        };
+        assert_eq!(
+            spec.to_string_with_channel_config(&channel_config),
+            "conda-forge::python"
+        );
+        assert_eq!(spec.to_string(), "https://prefix.dev/conda-forge::python");
+
+        let spec = MatchSpec::from_str("conda-forge::python", Strict).unwrap();
+        assert_eq!(spec.to_string(), "conda-forge::python");
+        assert_eq!(
+            spec.to_string_with_channel_config(&channel_config),
+            "https://conda.anaconda.org/conda-forge::python"
+        );
+    }
+
     #[test]
     fn test_nameless_matchspec_format_eq() {
         let spec = NamelessMatchSpec::from_str("*[version==1.0, sha256=aaac4bc9c6916ecc0e33137431645b029ade22190c7144eead61446dcbcc6f97, md5=dede6252c964db3f3e41c7d30d07f6bf]", Strict).unwrap();
@@ -609,6 +950,61 @@ mod tests {
         assert!(nameless_spec.matches(&package_record));
     }
 
+    #[test]
+    fn test_matches_package_location() {
+        let mut package_record = PackageRecord::new(
+            PackageName::new_unchecked("mamba"),
+            Version::from_str("1.0").unwrap(),
+            String::from("py37_0"),
+        );
+        package_record.subdir = String::from("linux-64");
+        package_record.sha256 = parse_digest_from_hex::<Sha256>(
+            "f44c4bc9c6916ecc0e33137431645b029ade22190c7144eead61446dcbcc6f97",
+        );
+        let record = RepoDataRecord {
+            package_record,
+            file_name: String::from("mamba-1.0-py37_0.conda"),
+            url: url::Url::parse(
+                "https://conda.anaconda.org/conda-forge/linux-64/mamba-1.0-py37_0.conda",
+            )
+            .unwrap(),
+            channel: String::from("https://conda.anaconda.org/conda-forge/"),
+        };
+
+        for (spec, expected) in [
+            ("mamba", true),
+            ("conda-forge::mamba", true),
+            ("bioconda::mamba", false),
+            ("conda-forge/linux-64::mamba", true),
+            ("conda-forge/osx-64::mamba", false),
+            ("mamba[fn=mamba-1.0-py37_0.conda]", true),
+            ("mamba[fn=mamba-1.0-py37_0.tar.bz2]", false),
+            (
+                "https://conda.anaconda.org/conda-forge/linux-64/mamba-1.0-py37_0.conda",
+                true,
+            ),
+            (
+                "https://conda.anaconda.org/conda-forge/linux-64/mamba-1.0-py37_1.conda",
+                false,
+            ),
+            (
+                "mamba[url=https://conda.anaconda.org/conda-forge/linux-64/mamba-1.0-py37_0.conda, sha256=f44c4bc9c6916ecc0e33137431645b029ade22190c7144eead61446dcbcc6f97]",
+                true,
+            ),
+            (
+                "mamba[url=https://conda.anaconda.org/conda-forge/linux-64/mamba-1.0-py37_0.conda, sha256=aaac4bc9c6916ecc0e33137431645b029ade22190c7144eead61446dcbcc6f97]",
+                false,
+            ),
+        ] {
+            let spec = MatchSpec::from_str(spec, Lenient).unwrap();
+            assert_eq!(spec.matches(&record), expected, "{spec}");
+        }
+
+        // Packages with an unknown filename never match a filename spec.
+        let spec = MatchSpec::from_str("mamba[fn=mamba-1.0-py37_0.conda]", Lenient).unwrap();
+        assert!(!spec.matches_package_at(&record.package_record, &record.url, None));
+    }
+
     #[test]
     fn test_serialize_matchspec() {
         let specs = ["mamba 1.0 py37_0",
@@ -625,4 +1021,99 @@ mod tests {
             .collect::<Vec<String>>()
             .join("\n"));
     }
+
+    #[test]
+    fn test_virtual_package_matches() {
+        let package = GenericVirtualPackage {
+            name: PackageName::new_unchecked("__cuda"),
+            version: Version::from_str("12.2").unwrap(),
+            build_string: "cuda_0".to_string(),
+        };
+
+        for (spec, expected) in [
+            ("__cuda>=12", true),
+            ("__cuda >=12 cuda_*", true),
+            ("__cuda >=12 cpu_*", false),
+            ("__cuda[build_number=0]", true),
+            ("__cuda[build_number=1]", false),
+            ("__cuda[track_features=\"\"]", true),
+            ("__cuda[track_features=mkl]", false),
+            ("__cuda[extras=[foo]]", false),
+            ("__cuda[md5=dede6252c964db3f3e41c7d30d07f6bf]", false),
+            ("__glibc>=2.28", false),
+        ] {
+            let match_spec = MatchSpec::from_str(spec, Strict).unwrap();
+            let nameless_spec = match_spec.clone().into_nameless().1;
+            assert_eq!(match_spec.matches(&package), expected, "{spec}");
+            if match_spec.name.as_ref() == Some(&package.name) {
+                assert_eq!(nameless_spec.matches(&package), expected, "{spec}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_track_features_matches() {
+        let mut record = PackageRecord::new(
+            PackageName::new_unchecked("numpy"),
+            Version::from_str("1.26.0").unwrap(),
+            "mkl_0".to_string(),
+        );
+        record.track_features = vec!["mkl,blas".to_string()];
+
+        let spec = MatchSpec::from_str("numpy[track_features=\"blas mkl\"]", Strict).unwrap();
+        assert_eq!(
+            spec.track_features,
+            Some(vec!["blas".to_string(), "mkl".to_string()])
+        );
+        assert!(spec.matches(&record));
+        assert_eq!(spec.to_string(), "numpy[track_features=\"blas mkl\"]");
+        assert_eq!(
+            MatchSpec::from_str(&spec.to_string(), Strict).unwrap(),
+            spec
+        );
+        let nameless = NamelessMatchSpec::from(spec);
+        assert_eq!(
+            NamelessMatchSpec::from_str(&nameless.to_string(), Strict).unwrap(),
+            nameless
+        );
+        assert!(!MatchSpec::from_str("numpy[track_features=mkl]", Strict)
+            .unwrap()
+            .matches(&record));
+    }
+
+    #[test]
+    fn test_extras() {
+        let spec = MatchSpec::from_str("foo >=1.0[extras=[bar, \"baz\"]]", Strict).unwrap();
+        assert_eq!(
+            spec.extras,
+            Some(vec!["bar".to_string(), "baz".to_string()])
+        );
+        assert_eq!(spec.to_string(), "foo >=1.0[extras=[bar, baz]]");
+        assert_eq!(
+            MatchSpec::from_str(&spec.to_string(), Strict).unwrap(),
+            spec
+        );
+
+        let mut record = PackageRecord::new(
+            PackageName::new_unchecked("foo"),
+            Version::from_str("1.2").unwrap(),
+            "0".to_string(),
+        );
+        record.depends = vec!["python".to_string()];
+        record
+            .extra_depends
+            .insert("bar".to_string(), vec!["numpy".to_string()]);
+        assert!(!spec.matches(&record));
+
+        record
+            .extra_depends
+            .insert("baz".to_string(), vec!["scipy".to_string()]);
+        assert!(spec.matches(&record));
+        assert_eq!(
+            record
+                .depends_with_extras(spec.extras.as_deref().unwrap_or_default())
+                .collect::<Vec<_>>(),
+            ["python", "numpy", "scipy"]
+        );
+    }
 }
@@ -116,6 +116,29 @@ impl MatchSpec {
     ) -> Result<Self, ParseMatchSpecError> {
         matchspec_parser(source, strictness)
     }
+
+    /// Parses only the name of the package a match spec refers to, e.g.
+    /// `numpy` for `conda-forge::numpy>=1.20`. The version and build
+    /// constraints are not parsed, so this also succeeds for specs with
+    /// constraints that [`MatchSpec::from_str`] rejects.
+    pub fn name_from_str(source: &str) -> Result<PackageName, ParseMatchSpecError> {
+        let (input, _comment) = strip_comment(source);
+        let (input, _if_clause) = strip_if(input);
+        let input = input
+            .split_once('[')
+            .map_or(input, |(input, _)| input)
+            .trim();
+
+        if let Some(url) = parse_url_like(input)? {
+            return ArchiveIdentifier::try_from_url(&url)
+                .and_then(|archive| archive.try_into().ok())
+                .ok_or(ParseMatchSpecError::MissingPackageName);
+        }
+
+        let input = input.rsplitn(3, ':').next().unwrap_or("");
+        let (name, _rest) = strip_package_name(input)?;
+        Ok(name)
+    }
 }
 
 /// Strips a comment from a match spec. A comment is preceded by a '#' followed
@@ -172,6 +195,7 @@ fn parse_bracket_list(input: &str) -> Result<BracketVec<'_>, ParseMatchSpecError
         whitespace_enclosed(context(
             "value",
             alt((
+                delimited(char('['), take_until("]"), char(']')),
                 delimited(char('"'), take_until("\""), char('"')),
                 delimited(char('\''), take_until("'"), char('\'')),
                 take_till1(|c| c == ',' || c == ']' || c == '\'' || c == '"'),
@@ -203,19 +227,41 @@ fn parse_bracket_list(input: &str) -> Result<BracketVec<'_>, ParseMatchSpecError
 /// Strips the brackets part of the matchspec returning the rest of the
 /// matchspec and  the contents of the brackets as a `Vec<&str>`.
 fn strip_brackets(input: &str) -> Result<(Cow<'_, str>, BracketVec<'_>), ParseMatchSpecError> {
-    if let Some(matches) = lazy_regex::regex!(r#".*(?:(\[.*\]))$"#).captures(input) {
-        let bracket_str = matches.get(1).unwrap().as_str();
-        let bracket_contents = parse_bracket_list(bracket_str)?;
+    match trailing_bracket_start(input)? {
+        Some(start) => {
+            let (input, bracket_str) = input.split_at(start);
+            let bracket_contents = parse_bracket_list(bracket_str)?;
+            Ok((Cow::Borrowed(input), bracket_contents))
+        }
+        None => Ok((input.into(), SmallVec::new())),
+    }
+}
 
-        let input = if let Some(input) = input.strip_suffix(bracket_str) {
-            Cow::Borrowed(input)
-        } else {
-            Cow::Owned(input.replace(bracket_str, ""))
-        };
+/// Returns the index of the `[` that opens the bracket string at the end of
+/// the input. Brackets may be nested, e.g. `foo[extras=[bar, baz]]`.
+fn trailing_bracket_start(input: &str) -> Result<Option<usize>, ParseMatchSpecError> {
+    if !input.ends_with(']') {
+        return Ok(None);
+    }
+
+    let mut depth = 0usize;
+    for (idx, c) in input.char_indices().rev() {
+        match c {
+            ']' => depth += 1,
+            '[' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(Some(idx));
+                }
+            }
+            _ => {}
+        }
+    }
 
-        Ok((input, bracket_contents))
+    if input.contains('[') {
+        Err(ParseMatchSpecError::InvalidBracket)
     } else {
-        Ok((input.into(), SmallVec::new()))
+        Ok(None)
     }
 }
 
@@ -263,8 +309,27 @@ fn parse_bracket_vec_into_components(
                 match_spec.url = Some(url);
             }
             "subdir" => match_spec.subdir = Some(value.to_string()),
-            // TODO: Still need to add `track_features`, `features`, `license` and `license_family`
-            // to the match spec.
+            "track_features" => {
+                match_spec.track_features = Some(
+                    value
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|feature| !feature.is_empty())
+                        .map(ToOwned::to_owned)
+                        .collect(),
+                );
+            }
+            "extras" => {
+                match_spec.extras = Some(
+                    value
+                        .split(',')
+                        .map(|extra| extra.trim().trim_matches(|c| c == '"' || c == '\''))
+                        .filter(|extra| !extra.is_empty())
+                        .map(ToOwned::to_owned)
+                        .collect(),
+                );
+            }
+            // TODO: Still need to add `features`, `license` and `license_family` to the match
+            // spec.
             _ => Err(ParseMatchSpecError::InvalidBracketKey(key.to_owned()))?,
         }
     }
@@ -634,7 +699,8 @@ mod tests {
     };
     use crate::{
         match_spec::parse::parse_bracket_list, BuildNumberSpec, Channel, ChannelConfig,
-        NamelessMatchSpec, ParseChannelError, ParseStrictness, ParseStrictness::*, VersionSpec,
+        NamelessMatchSpec, PackageName, ParseChannelError, ParseStrictness, ParseStrictness::*,
+        VersionSpec,
     };
 
     fn channel_config() -> ChannelConfig {
@@ -669,6 +735,11 @@ mod tests {
         assert_eq!(result.0, "bla ");
         let expected: BracketVec<'_> = smallvec![("version", "1.2.3"), ("build_number", "1")];
         assert_eq!(result.1, expected);
+
+        let result = strip_brackets(r#"conda-forge[linux-64]::bla[extras=[foo, bar]]"#).unwrap();
+        assert_eq!(result.0, "conda-forge[linux-64]::bla");
+        let expected: BracketVec<'_> = smallvec![("extras", "foo, bar")];
+        assert_eq!(result.1, expected);
     }
 
     #[test]
@@ -1084,6 +1155,26 @@ mod tests {
         );
     }
 
+    #[rstest]
+    #[case("python", "python")]
+    #[case("python>=3.8", "python")]
+    #[case("python >=3.8,<4 *_cpython", "python")]
+    #[case("conda-forge::numpy 1.20.*", "numpy")]
+    #[case("conda-forge/linux-64::numpy", "numpy")]
+    #[case("foo[version=\"1.0.*\"]", "foo")]
+    #[case("__glibc >=2.17  # comment", "__glibc")]
+    #[case("foo >=1.0,<<2", "foo")]
+    #[case(
+        "https://conda.anaconda.org/conda-forge/linux-64/py-rattler-0.6.1-py39h8169da8_0.conda",
+        "py-rattler"
+    )]
+    fn test_name_from_str(#[case] spec: &str, #[case] name: &str) {
+        assert_eq!(
+            MatchSpec::name_from_str(spec).unwrap(),
+            PackageName::new_unchecked(name)
+        );
+    }
+
     #[test]
     fn test_invalid_channel_name() {
         let spec = MatchSpec::from_str("conda-forge::::foo[version=\"1.0.*\"]", Strict);
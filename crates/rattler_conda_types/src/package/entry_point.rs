@@ -3,6 +3,7 @@
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::Display;
 use std::str::FromStr;
+use thiserror::Error;
 
 /// A struct for a single Python entry point. An entry point is a command that
 /// runs a function in a Python module. For example, the entry point
@@ -17,8 +18,9 @@ use std::str::FromStr;
 ///
 /// The entry point is parsed from a string using the [`FromStr`] trait. The
 /// [`Display`] trait is implemented for the entry point to convert it back to a
-/// string.
-#[derive(Debug, Clone)]
+/// string. Parsing is lenient, use [`EntryPoint::validate`] to check that the
+/// command, module and function are valid.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct EntryPoint {
     /// The name of the command that will be available on the command line.
     pub command: String,
@@ -30,6 +32,27 @@ pub struct EntryPoint {
     pub function: String,
 }
 
+/// An error that is returned when validating python entry points, see
+/// [`EntryPoint::validate`] and [`super::PythonEntryPoints::validate`].
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum EntryPointValidationError {
+    /// The command is not a valid command name.
+    #[error("'{0}' is not a valid entry point command")]
+    InvalidCommand(String),
+
+    /// The module is not a valid Python module path.
+    #[error("'{0}' is not a valid python module")]
+    InvalidModule(String),
+
+    /// The function is not a valid Python object reference.
+    #[error("'{0}' is not a valid python function")]
+    InvalidFunction(String),
+
+    /// Multiple entry points create the same command.
+    #[error("the entry point command '{0}' is defined more than once")]
+    DuplicateCommand(String),
+}
+
 impl FromStr for EntryPoint {
     type Err = String;
 
@@ -48,6 +71,54 @@ impl FromStr for EntryPoint {
     }
 }
 
+impl EntryPoint {
+    /// Validates the entry point. The command must be a valid file name and
+    /// the module and function must be (dotted) Python identifiers.
+    ///
+    /// Parsing an entry point is lenient, entry points that are not valid can
+    /// still be parsed but may not work when they are installed.
+    pub fn validate(&self) -> Result<(), EntryPointValidationError> {
+        if !is_valid_command(&self.command) {
+            return Err(EntryPointValidationError::InvalidCommand(
+                self.command.clone(),
+            ));
+        }
+        if !is_dotted_identifier(&self.module) {
+            return Err(EntryPointValidationError::InvalidModule(
+                self.module.clone(),
+            ));
+        }
+        if !is_dotted_identifier(&self.function) {
+            return Err(EntryPointValidationError::InvalidFunction(
+                self.function.clone(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if the command can be used as the name of an executable.
+fn is_valid_command(command: &str) -> bool {
+    !command.is_empty()
+        && command != "."
+        && command != ".."
+        && !command
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '/' | '\\' | ':'))
+}
+
+/// Returns true if the string is a sequence of python identifiers separated by
+/// dots (e.g. `jupyterlab.labapp`).
+fn is_dotted_identifier(s: &str) -> bool {
+    s.split('.').all(|part| {
+        let mut chars = part.chars();
+        chars
+            .next()
+            .map_or(false, |c| c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
 impl Display for EntryPoint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{} = {}:{}", self.command, self.module, self.function)
@@ -75,7 +146,7 @@ impl Serialize for EntryPoint {
 
 #[cfg(test)]
 mod test {
-    use super::EntryPoint;
+    use super::{EntryPoint, EntryPointValidationError};
     use std::str::FromStr;
 
     #[test]
@@ -92,4 +163,48 @@ mod test {
 
         insta::assert_yaml_snapshot!(entry_point);
     }
+
+    #[test]
+    fn test_entry_point_dotted_function() {
+        let entry_point = EntryPoint::from_str("black = black:patched_main.run").unwrap();
+        assert_eq!(entry_point.module, "black");
+        assert_eq!(entry_point.function, "patched_main.run");
+    }
+
+    #[test]
+    fn test_invalid_entry_point() {
+        assert!(EntryPoint::from_str("jlpm jupyterlab.jlpmapp:main").is_err());
+        assert!(EntryPoint::from_str("jlpm = jupyterlab.jlpmapp.main").is_err());
+
+        let validate = |s: &str| EntryPoint::from_str(s).unwrap().validate();
+        assert_eq!(validate("jlpm = jupyterlab.jlpmapp:main"), Ok(()));
+        assert_eq!(
+            validate(" = jupyterlab.jlpmapp:main"),
+            Err(EntryPointValidationError::InvalidCommand(String::new()))
+        );
+        assert_eq!(
+            validate("bin/jlpm = jupyterlab.jlpmapp:main"),
+            Err(EntryPointValidationError::InvalidCommand(
+                "bin/jlpm".to_string()
+            ))
+        );
+        assert_eq!(
+            validate("jlpm = jupyterlab..jlpmapp:main"),
+            Err(EntryPointValidationError::InvalidModule(
+                "jupyterlab..jlpmapp".to_string()
+            ))
+        );
+        assert_eq!(
+            validate("jlpm = 1jupyterlab:main"),
+            Err(EntryPointValidationError::InvalidModule(
+                "1jupyterlab".to_string()
+            ))
+        );
+        assert_eq!(
+            validate("jlpm = jupyterlab:main()"),
+            Err(EntryPointValidationError::InvalidFunction(
+                "main()".to_string()
+            ))
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::{collections::BTreeMap, path::Path};
 
 use super::PackageFile;
 use crate::{NoArchType, PackageName, VersionWithSource};
@@ -33,6 +33,11 @@ pub struct IndexJson {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub depends: Vec<String>,
 
+    /// Optional groups of dependencies, keyed by the name of the extra. They
+    /// are only required when the extra is requested, e.g. `foo[extras=[bar]]`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra_depends: BTreeMap<String, Vec<String>>,
+
     /// Features are a deprecated way to specify different feature sets for the conda solver. This is not
     /// supported anymore and should not be used. Instead, `mutex` packages should be used to specify
     /// mutually exclusive features.
@@ -55,6 +60,11 @@ pub struct IndexJson {
     /// Optionally, the OS the package is build for.
     pub platform: Option<String>,
 
+    /// The path to the site-packages directory relative to the root of the
+    /// environment. Only set by python packages that do not use the default
+    /// location.
+    pub python_site_packages_path: Option<String>,
+
     /// The subdirectory that contains this package
     pub subdir: Option<String>,
 
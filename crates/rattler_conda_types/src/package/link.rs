@@ -1,10 +1,9 @@
-use std::path::Path;
+use std::{collections::HashSet, path::Path};
 
+use super::{EntryPoint, EntryPointValidationError, PackageFile};
 use rattler_macros::sorted;
 use serde::{Deserialize, Serialize};
 
-use super::{EntryPoint, PackageFile};
-
 /// Describes python noarch specific entry points
 #[derive(Serialize, Clone, Debug, Deserialize)]
 pub struct PythonEntryPoints {
@@ -13,6 +12,27 @@ pub struct PythonEntryPoints {
     pub entry_points: Vec<EntryPoint>,
 }
 
+impl PythonEntryPoints {
+    /// Validates the entry points of a package, see [`EntryPoint::validate`].
+    ///
+    /// Entry points must also create unique commands, otherwise they would
+    /// overwrite each other when they are installed.
+    pub fn validate(&self) -> Result<(), EntryPointValidationError> {
+        let mut commands = HashSet::new();
+        for entry_point in &self.entry_points {
+            entry_point.validate()?;
+            // Commands are compared case-insensitively because they would clash on
+            // case-insensitive filesystems.
+            if !commands.insert(entry_point.command.to_lowercase()) {
+                return Err(EntryPointValidationError::DuplicateCommand(
+                    entry_point.command.clone(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Links for specific types of noarch packages.
 #[derive(Serialize, Clone, Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
@@ -37,6 +57,24 @@ pub struct LinkJson {
     pub package_metadata_version: u64,
 }
 
+impl NoArchLinks {
+    /// Returns the python entry points or an empty slice if these are not
+    /// python links.
+    pub fn python_entry_points(&self) -> &[EntryPoint] {
+        match self {
+            NoArchLinks::Python(entry_points) => &entry_points.entry_points,
+            NoArchLinks::Generic => &[],
+        }
+    }
+}
+
+impl LinkJson {
+    /// Returns the python entry points defined in this file.
+    pub fn python_entry_points(&self) -> &[EntryPoint] {
+        self.noarch.python_entry_points()
+    }
+}
+
 impl PackageFile for LinkJson {
     fn package_path() -> &'static Path {
         Path::new("info/link.json")
@@ -49,7 +87,7 @@ impl PackageFile for LinkJson {
 
 #[cfg(test)]
 mod test {
-    use super::LinkJson;
+    use super::{EntryPointValidationError, LinkJson, NoArchLinks};
     use rstest::rstest;
 
     #[rstest]
@@ -62,4 +100,29 @@ mod test {
             serde_json::from_reader(std::fs::File::open(test_file).unwrap()).unwrap();
         insta::assert_yaml_snapshot!(path, link_json);
     }
+
+    #[test]
+    fn test_duplicate_entry_points() {
+        let link_json: LinkJson = serde_json::from_str(
+            r#"{
+                "noarch": {
+                    "type": "python",
+                    "entry_points": ["foo = foo.cli:main", "Foo = foo.other:main"]
+                },
+                "package_metadata_version": 1
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(link_json.python_entry_points().len(), 2);
+
+        let NoArchLinks::Python(entry_points) = &link_json.noarch else {
+            panic!("expected python links");
+        };
+        assert_eq!(
+            entry_points.validate(),
+            Err(EntryPointValidationError::DuplicateCommand(
+                "Foo".to_string()
+            ))
+        );
+    }
 }
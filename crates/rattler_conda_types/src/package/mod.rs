@@ -12,6 +12,7 @@ mod no_link;
 mod no_softlink;
 mod package_metadata;
 mod paths;
+mod recipe_metadata;
 mod run_exports;
 
 use std::fs::File;
@@ -21,7 +22,7 @@ pub use {
     about::AboutJson,
     archive_identifier::ArchiveIdentifier,
     archive_type::ArchiveType,
-    entry_point::EntryPoint,
+    entry_point::{EntryPoint, EntryPointValidationError},
     files::Files,
     has_prefix::HasPrefix,
     has_prefix::HasPrefixEntry,
@@ -31,6 +32,7 @@ pub use {
     no_softlink::NoSoftlink,
     package_metadata::PackageMetadata,
     paths::{FileMode, PathType, PathsEntry, PathsJson, PrefixPlaceholder},
+    recipe_metadata::RecipeMetadata,
     run_exports::RunExportsJson,
 };
 
@@ -0,0 +1,110 @@
+use std::{collections::BTreeMap, io::Error, path::Path};
+
+use crate::{package::PackageFile, utils::serde::LossyUrl};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, skip_serializing_none, OneOrMany};
+
+use url::Url;
+
+/// Metadata about the recipe that a package was built from.
+///
+/// Build tools store this information in the `extra` section of the `info/about.json` file (see
+/// [`super::AboutJson`]). For packages from conda-forge this contains the maintainers of the
+/// recipe and the feedstock and commit that the package was built from.
+///
+/// Like any other [`PackageFile`] this can be read from an extracted package with
+/// [`PackageFile::from_package_directory`] or directly from an archive with
+/// `rattler_package_streaming::seek::read_package_file`.
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct RecipeMetadata {
+    /// The maintainers of the recipe
+    #[serde(
+        rename = "recipe-maintainers",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[serde_as(as = "OneOrMany<_>")]
+    pub maintainers: Vec<String>,
+
+    /// The name of the feedstock that contains the recipe
+    #[serde(rename = "feedstock-name")]
+    pub feedstock_name: Option<String>,
+
+    /// The URL of the repository that contains the recipe
+    #[serde(default)]
+    #[serde_as(deserialize_as = "LossyUrl")]
+    pub remote_url: Option<Url>,
+
+    /// The commit of the repository that the package was built from
+    pub sha: Option<String>,
+
+    /// Any other information that was stored by the build tool
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+impl PackageFile for RecipeMetadata {
+    fn package_path() -> &'static Path {
+        Path::new("info/about.json")
+    }
+
+    fn from_str(str: &str) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct About {
+            #[serde(default)]
+            extra: Option<RecipeMetadata>,
+        }
+
+        let about: About = serde_json::from_str(str)?;
+        Ok(about.extra.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PackageFile, RecipeMetadata};
+
+    #[test]
+    fn test_recipe_metadata() {
+        let metadata = RecipeMetadata::from_str(
+            r#"{
+                "license": "MIT",
+                "extra": {
+                    "copy_test_source_files": true,
+                    "feedstock-name": "rattler-feedstock",
+                    "final": true,
+                    "recipe-maintainers": ["baszalmstra", "wolfv"],
+                    "remote_url": "https://github.com/conda-forge/rattler-feedstock",
+                    "sha": "0123456789abcdef"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(metadata.maintainers, ["baszalmstra", "wolfv"]);
+        assert_eq!(
+            metadata.feedstock_name.as_deref(),
+            Some("rattler-feedstock")
+        );
+        assert_eq!(
+            metadata.remote_url.unwrap().as_str(),
+            "https://github.com/conda-forge/rattler-feedstock"
+        );
+        assert_eq!(metadata.sha.as_deref(), Some("0123456789abcdef"));
+        assert_eq!(
+            metadata.other.keys().collect::<Vec<_>>(),
+            ["copy_test_source_files", "final"]
+        );
+
+        // Packages without recipe metadata.
+        assert_eq!(
+            RecipeMetadata::from_str(r#"{"license": "MIT"}"#).unwrap(),
+            RecipeMetadata::default()
+        );
+        assert_eq!(
+            RecipeMetadata::from_str(r#"{"extra": null}"#).unwrap(),
+            RecipeMetadata::default()
+        );
+    }
+}
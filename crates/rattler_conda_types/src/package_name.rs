@@ -45,6 +45,13 @@ impl PackageName {
     pub fn as_normalized(&self) -> &str {
         self.normalized.as_ref().unwrap_or(&self.source)
     }
+
+    /// Returns true if this is the name of a virtual package, e.g. `__glibc`.
+    /// Virtual packages describe the system and are never available from a
+    /// channel.
+    pub fn is_virtual(&self) -> bool {
+        self.as_normalized().starts_with("__")
+    }
 }
 
 /// An error that is returned when conversion from a string to a [`PackageName`] fails.
@@ -178,4 +185,10 @@ mod test {
 
         assert!(PackageName::try_from("invalid$").is_err());
     }
+
+    #[test]
+    fn test_is_virtual() {
+        assert!(PackageName::new_unchecked("__glibc").is_virtual());
+        assert!(!PackageName::new_unchecked("glibc").is_virtual());
+    }
 }
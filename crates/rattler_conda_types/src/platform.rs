@@ -31,6 +31,8 @@ pub enum Platform {
     Win64,
     WinArm64,
 
+    FreeBsd64,
+
     EmscriptenWasm32,
     WasiWasm32,
 
@@ -144,6 +146,15 @@ impl Platform {
             return Platform::OsxArm64;
         }
 
+        #[cfg(target_os = "freebsd")]
+        {
+            #[cfg(target_arch = "x86_64")]
+            return Platform::FreeBsd64;
+
+            #[cfg(not(target_arch = "x86_64"))]
+            compile_error!("unsupported freebsd architecture");
+        }
+
         #[cfg(target_os = "emscripten")]
         {
             #[cfg(target_arch = "wasm32")]
@@ -159,6 +170,7 @@ impl Platform {
         #[cfg(not(any(
             target_os = "linux",
             target_os = "macos",
+            target_os = "freebsd",
             target_os = "emscripten",
             target_os = "wasi",
             windows
@@ -185,7 +197,10 @@ impl Platform {
 
     /// Returns true if the platform is a unix based platform.
     pub const fn is_unix(self) -> bool {
-        self.is_linux() || self.is_osx() || matches!(self, Platform::EmscriptenWasm32)
+        self.is_linux()
+            || self.is_osx()
+            || self.is_freebsd()
+            || matches!(self, Platform::EmscriptenWasm32)
     }
 
     /// Returns true if the platform is a linux based platform.
@@ -210,6 +225,11 @@ impl Platform {
         matches!(self, Platform::Osx64 | Platform::OsxArm64)
     }
 
+    /// Returns true if the platform is a FreeBSD based platform.
+    pub const fn is_freebsd(self) -> bool {
+        matches!(self, Platform::FreeBsd64)
+    }
+
     /// Return only the platform (linux, win, or osx from the platform enum)
     pub fn only_platform(&self) -> Option<&str> {
         match self {
@@ -226,6 +246,7 @@ impl Platform {
             | Platform::LinuxRiscv64 => Some("linux"),
             Platform::Osx64 | Platform::OsxArm64 => Some("osx"),
             Platform::Win32 | Platform::Win64 | Platform::WinArm64 => Some("win"),
+            Platform::FreeBsd64 => Some("freebsd"),
             Platform::EmscriptenWasm32 => Some("emscripten"),
             Platform::WasiWasm32 => Some("wasi"),
             Platform::ZosZ => Some("zos"),
@@ -272,6 +293,7 @@ impl FromStr for Platform {
             "win-32" => Platform::Win32,
             "win-64" => Platform::Win64,
             "win-arm64" => Platform::WinArm64,
+            "freebsd-64" => Platform::FreeBsd64,
             "emscripten-wasm32" => Platform::EmscriptenWasm32,
             "wasi-wasm32" => Platform::WasiWasm32,
             "zos-z" => Platform::ZosZ,
@@ -303,6 +325,7 @@ impl From<Platform> for &'static str {
             Platform::Win32 => "win-32",
             Platform::Win64 => "win-64",
             Platform::WinArm64 => "win-arm64",
+            Platform::FreeBsd64 => "freebsd-64",
             Platform::EmscriptenWasm32 => "emscripten-wasm32",
             Platform::WasiWasm32 => "wasi-wasm32",
             Platform::ZosZ => "zos-z",
@@ -326,7 +349,9 @@ impl Platform {
             Platform::LinuxRiscv32 => Some(Arch::Riscv32),
             Platform::LinuxRiscv64 => Some(Arch::Riscv64),
             Platform::Linux32 | Platform::Win32 => Some(Arch::X86),
-            Platform::Linux64 | Platform::Win64 | Platform::Osx64 => Some(Arch::X86_64),
+            Platform::Linux64 | Platform::Win64 | Platform::Osx64 | Platform::FreeBsd64 => {
+                Some(Arch::X86_64)
+            }
             Platform::LinuxAarch64 => Some(Arch::Aarch64),
             Platform::WinArm64 | Platform::OsxArm64 => Some(Arch::Arm64),
             Platform::EmscriptenWasm32 | Platform::WasiWasm32 => Some(Arch::Wasm32),
@@ -482,6 +507,18 @@ mod tests {
         );
         assert_eq!("noarch".parse::<Platform>().unwrap(), Platform::NoArch);
         assert_eq!("zos-z".parse::<Platform>().unwrap(), Platform::ZosZ);
+        assert_eq!(
+            "freebsd-64".parse::<Platform>().unwrap(),
+            Platform::FreeBsd64
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        // `unknown` cannot be parsed
+        for platform in Platform::all().filter(|p| *p != Platform::Unknown) {
+            assert_eq!(platform.as_str().parse::<Platform>(), Ok(platform));
+        }
     }
 
     #[test]
@@ -519,5 +556,14 @@ mod tests {
         assert_eq!(Platform::WasiWasm32.arch(), Some(Arch::Wasm32));
         assert_eq!(Platform::NoArch.arch(), None);
         assert_eq!(Platform::ZosZ.arch(), Some(Arch::Z));
+        assert_eq!(Platform::FreeBsd64.arch(), Some(Arch::X86_64));
+    }
+
+    #[test]
+    fn test_freebsd() {
+        assert!(Platform::FreeBsd64.is_freebsd());
+        assert!(Platform::FreeBsd64.is_unix());
+        assert!(!Platform::FreeBsd64.is_linux());
+        assert_eq!(Platform::FreeBsd64.only_platform(), Some("freebsd"));
     }
 }
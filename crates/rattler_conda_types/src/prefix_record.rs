@@ -7,10 +7,21 @@ use rattler_digest::serde::SerializableHash;
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use serde_with::serde_as;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufWriter, Read};
+use std::io::{BufWriter, ErrorKind, Read};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use url::Url;
+
+/// The name of the file in the `conda-meta` directory that caches the records of all packages
+/// installed in the prefix, see [`PrefixRecord::collect_from_prefix_with_index`].
+pub const PREFIX_INDEX_FILE_NAME: &str = "index";
+
+/// The version of the format of the prefix index. Increment this whenever the format of the
+/// stored records changes.
+const PREFIX_INDEX_VERSION: u32 = 2;
 
 /// Information about every file installed with the package.
 ///
@@ -263,6 +274,218 @@ impl PrefixRecord {
         }
         Ok(records)
     }
+
+    /// Serializes this instance into the compact binary `MessagePack` format. Use
+    /// [`Self::from_msgpack`] to read the record back.
+    ///
+    /// The JSON files in the `conda-meta` directory remain the canonical representation of the
+    /// installed packages, other tools only understand that format.
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, std::io::Error> {
+        rmp_serde::to_vec_named(&CompactPrefixRecord::from(self.clone()))
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Parses a record that was serialized with [`Self::to_msgpack`].
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, std::io::Error> {
+        rmp_serde::from_slice::<CompactPrefixRecord>(bytes)
+            .map(Into::into)
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Collects all `PrefixRecord`s from the specified prefix like [`Self::collect_from_prefix`]
+    /// but uses the binary `$PREFIX/conda-meta/index` file to avoid parsing the JSON files of
+    /// all packages. This is much faster for environments with thousands of packages.
+    ///
+    /// The index stores the hash of every JSON file, only files that were added or modified
+    /// since the index was written are parsed. The index is only read, it is updated by
+    /// [`Self::write_prefix_index`].
+    pub fn collect_from_prefix_with_index(
+        prefix: &Path,
+    ) -> Result<Vec<PrefixRecord>, std::io::Error> {
+        let conda_meta_path = prefix.join("conda-meta");
+        if !conda_meta_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut cached = read_prefix_index(&conda_meta_path.join(PREFIX_INDEX_FILE_NAME));
+        Ok(scan_conda_meta(&conda_meta_path, &mut cached)?
+            .into_iter()
+            .map(|entry| entry.record.into())
+            .collect())
+    }
+
+    /// Writes the `$PREFIX/conda-meta/index` file that is read by
+    /// [`Self::collect_from_prefix_with_index`] for the records that are currently in the
+    /// `conda-meta` directory. The file is replaced atomically, so concurrent readers never
+    /// observe a partially written index.
+    ///
+    /// This should be called whenever the records of the prefix are modified. An outdated index
+    /// is still valid but does not speed up reading the modified records.
+    pub fn write_prefix_index(prefix: &Path) -> Result<(), std::io::Error> {
+        let conda_meta_path = prefix.join("conda-meta");
+        let index_path = conda_meta_path.join(PREFIX_INDEX_FILE_NAME);
+        let mut cached = read_prefix_index(&index_path);
+        let entries = scan_conda_meta(&conda_meta_path, &mut cached)?;
+        write_index_file(&index_path, &entries)
+    }
+}
+
+/// Reads the records of all the JSON files in the `conda-meta` directory. Records from the
+/// `cached` index are used for files that did not change.
+fn scan_conda_meta(
+    conda_meta_path: &Path,
+    cached: &mut HashMap<String, PrefixIndexEntry>,
+) -> Result<Vec<PrefixIndexEntry>, std::io::Error> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(conda_meta_path)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !entry.file_type()?.is_file() || !file_name.ends_with(".json") {
+            continue;
+        }
+
+        let mut contents = std::fs::read(entry.path())?;
+        let sha256 = rattler_digest::compute_bytes_digest::<rattler_digest::Sha256>(&contents);
+        let record = match cached.remove(&file_name) {
+            Some(cached) if cached.sha256 == sha256 => cached.record,
+            _ => CompactPrefixRecord::from(
+                simd_json::serde::from_slice::<PrefixRecord>(&mut contents)
+                    .map_err(std::io::Error::from)?,
+            ),
+        };
+        entries.push(PrefixIndexEntry {
+            file_name,
+            sha256,
+            record,
+        });
+    }
+    Ok(entries)
+}
+
+/// The representation of a [`PrefixRecord`] in binary formats.
+///
+/// [`PrefixRecord`] flattens the [`RepoDataRecord`] which is not supported by formats that
+/// are not human-readable, the hashes of the records would be read as hex strings.
+#[derive(Serialize, Deserialize)]
+struct CompactPrefixRecord {
+    package_record: PackageRecord,
+    file_name: String,
+    url: Url,
+    channel: String,
+    package_tarball_full_path: Option<PathBuf>,
+    extracted_package_dir: Option<PathBuf>,
+    files: Vec<PathBuf>,
+    paths_data: PrefixPaths,
+    link: Option<Link>,
+    requested_spec: Option<String>,
+}
+
+impl From<PrefixRecord> for CompactPrefixRecord {
+    fn from(record: PrefixRecord) -> Self {
+        Self {
+            package_record: record.repodata_record.package_record,
+            file_name: record.repodata_record.file_name,
+            url: record.repodata_record.url,
+            channel: record.repodata_record.channel,
+            package_tarball_full_path: record.package_tarball_full_path,
+            extracted_package_dir: record.extracted_package_dir,
+            files: record.files,
+            paths_data: record.paths_data,
+            link: record.link,
+            requested_spec: record.requested_spec,
+        }
+    }
+}
+
+impl From<CompactPrefixRecord> for PrefixRecord {
+    fn from(record: CompactPrefixRecord) -> Self {
+        Self {
+            repodata_record: RepoDataRecord {
+                package_record: record.package_record,
+                file_name: record.file_name,
+                url: record.url,
+                channel: record.channel,
+            },
+            package_tarball_full_path: record.package_tarball_full_path,
+            extracted_package_dir: record.extracted_package_dir,
+            files: record.files,
+            paths_data: record.paths_data,
+            link: record.link,
+            requested_spec: record.requested_spec,
+        }
+    }
+}
+
+/// The contents of the `conda-meta/index` file.
+#[derive(Deserialize)]
+struct PrefixIndex {
+    version: u32,
+    entries: Vec<PrefixIndexEntry>,
+}
+
+/// A record in the prefix index together with the hash of the JSON file it was read from.
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct PrefixIndexEntry {
+    file_name: String,
+    #[serde_as(as = "SerializableHash::<rattler_digest::Sha256>")]
+    sha256: rattler_digest::Sha256Hash,
+    record: CompactPrefixRecord,
+}
+
+/// Reads the entries of the prefix index by file name. Returns no entries if the index does not
+/// exist, is corrupted or was written in another format.
+fn read_prefix_index(path: &Path) -> HashMap<String, PrefixIndexEntry> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return HashMap::new(),
+        Err(e) => {
+            tracing::warn!("failed to read {}: {e}", path.display());
+            return HashMap::new();
+        }
+    };
+    match rmp_serde::from_slice::<PrefixIndex>(&bytes) {
+        Ok(index) if index.version == PREFIX_INDEX_VERSION => index
+            .entries
+            .into_iter()
+            .map(|entry| (entry.file_name.clone(), entry))
+            .collect(),
+        Ok(_) => HashMap::new(),
+        Err(e) => {
+            tracing::warn!("the prefix index at {} is corrupted: {e}", path.display());
+            HashMap::new()
+        }
+    }
+}
+
+/// Atomically replaces the prefix index by writing to a temporary file first.
+fn write_index_file(path: &Path, entries: &[PrefixIndexEntry]) -> Result<(), std::io::Error> {
+    // Distinguishes the temporary files of concurrent writers in the same process.
+    static WRITE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Serialize)]
+    struct PrefixIndexRef<'a> {
+        version: u32,
+        entries: &'a [PrefixIndexEntry],
+    }
+
+    let bytes = rmp_serde::to_vec_named(&PrefixIndexRef {
+        version: PREFIX_INDEX_VERSION,
+        entries,
+    })
+    .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+
+    let temp_path = path.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        WRITE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&temp_path, bytes)?;
+    if let Err(e) = std::fs::rename(&temp_path, path) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    Ok(())
 }
 
 impl FromStr for PrefixRecord {
@@ -323,6 +546,7 @@ impl AsRef<PackageRecord> for PrefixRecord {
 
 #[cfg(test)]
 mod test {
+    use super::{PrefixRecord, PREFIX_INDEX_FILE_NAME};
     use crate::get_test_data_dir;
     use rstest::rstest;
 
@@ -342,4 +566,72 @@ mod test {
         let prefix_record = super::PrefixRecord::from_path(path).unwrap();
         insta::assert_yaml_snapshot!(path_name.replace('.', "_"), prefix_record);
     }
+
+    #[rstest]
+    #[case::xz_5_2_6_h8d14728_0("xz-5.2.6-h8d14728_0.json")]
+    #[case::pip_23_0_pyhd8ed1ab_0_json("pip-23.0-pyhd8ed1ab_0.json")]
+    fn msgpack_roundtrip(#[case] path_name: &str) {
+        let path = get_test_data_dir().join("conda-meta").join(path_name);
+        let prefix_record = PrefixRecord::from_path(path).unwrap();
+        let bytes = prefix_record.to_msgpack().unwrap();
+        assert_eq!(PrefixRecord::from_msgpack(&bytes).unwrap(), prefix_record);
+    }
+
+    #[test]
+    fn collect_from_prefix_with_index() {
+        let prefix = tempfile::tempdir().unwrap();
+        let conda_meta = prefix.path().join("conda-meta");
+        std::fs::create_dir(&conda_meta).unwrap();
+        for name in ["xz-5.2.6-h8d14728_0.json", "pip-23.0-pyhd8ed1ab_0.json"] {
+            std::fs::copy(
+                get_test_data_dir().join("conda-meta").join(name),
+                conda_meta.join(name),
+            )
+            .unwrap();
+        }
+
+        let sorted = |mut records: Vec<PrefixRecord>| {
+            records.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+            records
+        };
+        let expected = sorted(PrefixRecord::collect_from_prefix(prefix.path()).unwrap());
+
+        // Reading the records does not write the index.
+        let records = PrefixRecord::collect_from_prefix_with_index(prefix.path()).unwrap();
+        assert_eq!(sorted(records), expected);
+        assert!(!conda_meta.join(PREFIX_INDEX_FILE_NAME).exists());
+
+        PrefixRecord::write_prefix_index(prefix.path()).unwrap();
+        assert!(conda_meta.join(PREFIX_INDEX_FILE_NAME).is_file());
+        let records = PrefixRecord::collect_from_prefix_with_index(prefix.path()).unwrap();
+        assert_eq!(sorted(records), expected);
+
+        // A record that is rewritten with the same length is parsed again.
+        let xz_path = conda_meta.join("xz-5.2.6-h8d14728_0.json");
+        let contents = std::fs::read_to_string(&xz_path).unwrap();
+        assert!(contents.contains("\"5.2.6\""));
+        std::fs::write(&xz_path, contents.replace("\"5.2.6\"", "\"5.2.7\"")).unwrap();
+        let records = PrefixRecord::collect_from_prefix_with_index(prefix.path()).unwrap();
+        let xz = records
+            .iter()
+            .find(|r| r.repodata_record.package_record.name.as_normalized() == "xz")
+            .unwrap();
+        assert_eq!(
+            xz.repodata_record.package_record.version.to_string(),
+            "5.2.7"
+        );
+
+        // Removed packages are no longer returned.
+        std::fs::remove_file(conda_meta.join("pip-23.0-pyhd8ed1ab_0.json")).unwrap();
+        let records = PrefixRecord::collect_from_prefix_with_index(prefix.path()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0]
+                .repodata_record
+                .package_record
+                .name
+                .as_normalized(),
+            "xz"
+        );
+    }
 }
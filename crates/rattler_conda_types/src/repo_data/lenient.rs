@@ -0,0 +1,238 @@
+//! Parsing of [`RepoData`] that can recover from records that fail to parse.
+//!
+//! Third-party channels sometimes contain a few records with invalid version
+//! strings or dependencies. Instead of rejecting the entire subdirectory, these
+//! records can be skipped and reported in a [`RepoDataParseReport`].
+
+use std::{collections::BTreeMap, path::Path};
+
+use fxhash::{FxHashMap, FxHashSet};
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::{ChannelInfo, PackageRecord, RepoData};
+use crate::{MatchSpec, ParseMatchSpecError, ParseStrictness};
+
+/// An error that occurred while parsing a single record of a [`RepoData`].
+#[derive(Debug, Error)]
+pub enum InvalidRecordError {
+    /// The record could not be deserialized, for instance because it contains
+    /// an invalid version string.
+    #[error(transparent)]
+    Deserialize(#[from] serde_json::Error),
+
+    /// One of the dependencies of the record is not a valid match spec.
+    #[error("invalid dependency '{0}'")]
+    InvalidDependency(String, #[source] ParseMatchSpecError),
+
+    /// One of the constraints of the record is not a valid match spec.
+    #[error("invalid constraint '{0}'")]
+    InvalidConstraint(String, #[source] ParseMatchSpecError),
+}
+
+/// An error that occurred while parsing a [`RepoData`] with
+/// [`RepoData::from_str_with_strictness`].
+#[derive(Debug, Error)]
+pub enum ParseRepoDataError {
+    /// The document itself is not valid repodata.
+    #[error(transparent)]
+    InvalidJson(#[from] serde_json::Error),
+
+    /// A record could not be parsed. This is only returned when parsing with
+    /// [`ParseStrictness::Strict`].
+    #[error("failed to parse the record of '{file_name}'")]
+    InvalidRecord {
+        /// The filename of the record.
+        file_name: String,
+
+        /// The reason the record could not be parsed.
+        #[source]
+        source: InvalidRecordError,
+    },
+}
+
+/// A record that was skipped because it could not be parsed.
+#[derive(Debug)]
+pub struct SkippedRecord {
+    /// The filename of the record.
+    pub file_name: String,
+
+    /// The reason the record could not be parsed.
+    pub error: InvalidRecordError,
+}
+
+/// Describes which records were skipped while parsing a [`RepoData`].
+#[derive(Debug, Default)]
+pub struct RepoDataParseReport {
+    /// The number of records that were parsed successfully.
+    pub parsed: usize,
+
+    /// The records that could not be parsed, sorted by filename.
+    pub skipped: Vec<SkippedRecord>,
+}
+
+impl RepoDataParseReport {
+    /// Returns the total number of records in the repodata.
+    pub fn total(&self) -> usize {
+        self.parsed + self.skipped.len()
+    }
+
+    /// Returns true if no records were skipped.
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+    }
+}
+
+/// The layout of a `repodata.json` file where the records have not been parsed
+/// yet.
+#[derive(Deserialize)]
+struct RawRepoData {
+    info: Option<ChannelInfo>,
+    #[serde(default)]
+    packages: BTreeMap<String, serde_json::Value>,
+    #[serde(default, rename = "packages.conda")]
+    conda_packages: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    removed: FxHashSet<String>,
+    #[serde(rename = "repodata_version")]
+    version: Option<u64>,
+}
+
+impl RepoData {
+    /// Parses [`RepoData`] from a JSON string and validates the dependencies
+    /// and constraints of every record.
+    ///
+    /// With [`ParseStrictness::Strict`] the first record that fails to parse
+    /// results in an error. With [`ParseStrictness::Lenient`] such records are
+    /// skipped and returned in the [`RepoDataParseReport`] instead.
+    pub fn from_str_with_strictness(
+        json: &str,
+        strictness: ParseStrictness,
+    ) -> Result<(Self, RepoDataParseReport), ParseRepoDataError> {
+        let raw: RawRepoData = serde_json::from_str(json)?;
+        let mut report = RepoDataParseReport::default();
+        let mut parse_records = |records: BTreeMap<String, serde_json::Value>| {
+            let mut parsed = FxHashMap::default();
+            for (file_name, value) in records {
+                match parse_record(value) {
+                    Ok(record) => {
+                        report.parsed += 1;
+                        parsed.insert(file_name, record);
+                    }
+                    Err(source) if strictness == ParseStrictness::Strict => {
+                        return Err(ParseRepoDataError::InvalidRecord { file_name, source });
+                    }
+                    Err(error) => report.skipped.push(SkippedRecord { file_name, error }),
+                }
+            }
+            Ok(parsed)
+        };
+
+        let packages = parse_records(raw.packages)?;
+        let conda_packages = parse_records(raw.conda_packages)?;
+        report.skipped.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+        Ok((
+            RepoData {
+                info: raw.info,
+                packages,
+                conda_packages,
+                removed: raw.removed,
+                version: raw.version,
+            },
+            report,
+        ))
+    }
+
+    /// Parses [`RepoData`] from a file. See [`RepoData::from_str_with_strictness`].
+    pub fn from_path_with_strictness(
+        path: impl AsRef<Path>,
+        strictness: ParseStrictness,
+    ) -> Result<(Self, RepoDataParseReport), std::io::Error> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::from_str_with_strictness(&contents, strictness)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Parses a single record and validates its dependencies and constraints.
+fn parse_record(value: serde_json::Value) -> Result<PackageRecord, InvalidRecordError> {
+    let record: PackageRecord = serde_json::from_value(value)?;
+    for depends in &record.depends {
+        MatchSpec::from_str(depends, ParseStrictness::Lenient)
+            .map_err(|e| InvalidRecordError::InvalidDependency(depends.clone(), e))?;
+    }
+    for constrains in &record.constrains {
+        MatchSpec::from_str(constrains, ParseStrictness::Lenient)
+            .map_err(|e| InvalidRecordError::InvalidConstraint(constrains.clone(), e))?;
+    }
+    Ok(record)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const REPODATA: &str = r#"{
+        "info": { "subdir": "linux-64" },
+        "packages": {
+            "foo-1.0-0.tar.bz2": {
+                "name": "foo", "version": "1.0", "build": "0", "build_number": 0,
+                "depends": ["bar >=1"], "subdir": "linux-64"
+            },
+            "foo-2.0-0.tar.bz2": {
+                "name": "foo", "version": "1@2", "build": "0", "build_number": 0,
+                "depends": [], "subdir": "linux-64"
+            }
+        },
+        "packages.conda": {
+            "bar-1.0-0.conda": {
+                "name": "bar", "version": "1.0", "build": "0", "build_number": 0,
+                "depends": ["baz[version="], "subdir": "linux-64"
+            }
+        },
+        "repodata_version": 1
+    }"#;
+
+    #[test]
+    fn test_lenient() {
+        let (repodata, report) =
+            RepoData::from_str_with_strictness(REPODATA, ParseStrictness::Lenient).unwrap();
+        assert_eq!(
+            repodata.packages.keys().collect::<Vec<_>>(),
+            vec!["foo-1.0-0.tar.bz2"]
+        );
+        assert!(repodata.conda_packages.is_empty());
+        assert_eq!(repodata.version, Some(1));
+
+        assert_eq!(report.parsed, 1);
+        assert_eq!(report.total(), 3);
+        assert!(!report.is_complete());
+        assert_eq!(
+            report
+                .skipped
+                .iter()
+                .map(|r| r.file_name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["bar-1.0-0.conda", "foo-2.0-0.tar.bz2"]
+        );
+        assert!(matches!(
+            report.skipped[0].error,
+            InvalidRecordError::InvalidDependency(..)
+        ));
+        assert!(matches!(
+            report.skipped[1].error,
+            InvalidRecordError::Deserialize(_)
+        ));
+    }
+
+    #[test]
+    fn test_strict() {
+        let err =
+            RepoData::from_str_with_strictness(REPODATA, ParseStrictness::Strict).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseRepoDataError::InvalidRecord { file_name, .. } if file_name == "foo-2.0-0.tar.bz2"
+        ));
+    }
+}
@@ -1,14 +1,18 @@
 //! Defines [`RepoData`]. `RepoData` stores information of all packages present
 //! in a subdirectory of a channel. It provides indexing functionality.
 
+mod lenient;
 pub mod patches;
+pub mod rewrite;
 pub mod sharded;
 mod topological_sort;
 
 use std::{
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
     fmt::{Display, Formatter},
+    hash::{Hash, Hasher},
     path::Path,
+    sync::{Arc, Mutex, PoisonError},
 };
 
 use fxhash::{FxHashMap, FxHashSet};
@@ -19,12 +23,15 @@ use serde_with::{serde_as, skip_serializing_none, OneOrMany};
 use thiserror::Error;
 use url::Url;
 
+pub use lenient::{InvalidRecordError, ParseRepoDataError, RepoDataParseReport, SkippedRecord};
+
 use crate::utils::url::add_trailing_slash;
 use crate::{
     build_spec::BuildNumber,
     package::{IndexJson, RunExportsJson},
     utils::serde::DeserializeFromStrUnchecked,
-    Channel, NoArchType, PackageName, PackageUrl, Platform, RepoDataRecord, VersionWithSource,
+    Channel, MatchSpec, NoArchType, PackageName, PackageUrl, ParseMatchSpecError, ParseStrictness,
+    Platform, RepoDataRecord, VersionWithSource,
 };
 
 /// [`RepoData`] is an index of package binaries available on in a subdirectory
@@ -100,9 +107,22 @@ pub struct PackageRecord {
     pub constrains: Vec<String>,
 
     /// Specification of packages this package depends on
+    ///
+    /// The parsed form of these specs is cached by
+    /// [`PackageRecord::parsed_depends`].
     #[serde(default)]
     pub depends: Vec<String>,
 
+    /// A cache of the parsed [`Self::depends`], this is not serialized.
+    #[serde(skip)]
+    depends_cache: DependsCache,
+
+    /// Optional groups of dependencies, keyed by the name of the extra. The
+    /// dependencies of an extra are only required when the extra is requested
+    /// with a match spec like `foo[extras=[bar]]`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra_depends: BTreeMap<String, Vec<String>>,
+
     /// Features are a deprecated way to specify different feature sets for the
     /// conda solver. This is not supported anymore and should not be used.
     /// Instead, `mutex` packages should be used to specify
@@ -150,6 +170,13 @@ pub struct PackageRecord {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub purls: Option<BTreeSet<PackageUrl>>,
 
+    /// The path to the site-packages directory of a python package relative
+    /// to the root of the environment. This is only set for `python` itself
+    /// and only if the location differs from the default, e.g. for free
+    /// threaded builds. Noarch python packages are installed into this
+    /// directory.
+    pub python_site_packages_path: Option<String>,
+
     /// Run exports that are specified in the package.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub run_exports: Option<RunExportsJson>,
@@ -239,6 +266,70 @@ impl RepoData {
         }
         records
     }
+
+    /// Returns a subset of this repodata that only contains the records of
+    /// the given packages and of all the packages they (transitively) depend
+    /// on, including the dependencies of their extras.
+    ///
+    /// This is useful to produce small repodata files, e.g. for test fixtures
+    /// or offline bundles. Dependencies that are not part of this repodata
+    /// (like virtual packages or packages from other subdirectories) are
+    /// ignored.
+    pub fn slice(&self, package_names: impl IntoIterator<Item = PackageName>) -> RepoData {
+        // Index the records by name so every package is only looked up once.
+        let mut records_by_name: FxHashMap<&PackageName, Vec<_>> = FxHashMap::default();
+        for (is_conda, records) in [(false, &self.packages), (true, &self.conda_packages)] {
+            for (file_name, record) in records {
+                records_by_name
+                    .entry(&record.name)
+                    .or_default()
+                    .push((is_conda, file_name, record));
+            }
+        }
+
+        let mut seen: HashSet<PackageName> = package_names.into_iter().collect();
+        let mut pending: VecDeque<_> = seen.iter().cloned().collect();
+        let mut packages = FxHashMap::default();
+        let mut conda_packages = FxHashMap::default();
+
+        while let Some(name) = pending.pop_front() {
+            let Some(records) = records_by_name.get(&name) else {
+                continue;
+            };
+            for &(is_conda, file_name, record) in records {
+                let dependencies = record
+                    .depends
+                    .iter()
+                    .chain(record.extra_depends.values().flatten());
+                for dependency in dependencies {
+                    let Some(dependency_name) =
+                        MatchSpec::from_str(dependency, ParseStrictness::Lenient)
+                            .ok()
+                            .and_then(|spec| spec.name)
+                    else {
+                        continue;
+                    };
+                    if seen.insert(dependency_name.clone()) {
+                        pending.push_back(dependency_name);
+                    }
+                }
+                let sliced = if is_conda {
+                    &mut conda_packages
+                } else {
+                    &mut packages
+                };
+                sliced.insert(file_name.clone(), record.clone());
+            }
+        }
+
+        RepoData {
+            info: self.info.clone(),
+            packages,
+            conda_packages,
+            removed: FxHashSet::default(),
+            version: self.version,
+        }
+    }
 }
 
 /// Computes the URL for a package.
@@ -284,6 +375,8 @@ impl PackageRecord {
             build_number: 0,
             constrains: vec![],
             depends: vec![],
+            depends_cache: DependsCache::default(),
+            extra_depends: BTreeMap::new(),
             features: None,
             legacy_bz2_md5: None,
             legacy_bz2_size: None,
@@ -300,10 +393,57 @@ impl PackageRecord {
             track_features: vec![],
             version: version.into(),
             purls: None,
+            python_site_packages_path: None,
             run_exports: None,
         }
     }
 
+    /// Returns the parsed [`Self::depends`], in the same order. Dependencies
+    /// that are not valid match specs are returned as errors.
+    ///
+    /// The specs are parsed leniently the first time this is called, after
+    /// that the cached result is returned for as long as [`Self::depends`]
+    /// does not change.
+    pub fn parsed_depends(&self) -> Arc<[Result<MatchSpec, ParseMatchSpecError>]> {
+        self.depends_cache.get_or_parse(&self.depends)
+    }
+
+    /// Returns the names of the packages this package depends on, in the
+    /// order of [`Self::depends`]. For dependencies that are not valid match
+    /// specs only the name is parsed with [`MatchSpec::name_from_str`],
+    /// dependencies without a valid name are skipped.
+    pub fn dependency_names(&self) -> impl Iterator<Item = PackageName> + '_ {
+        let parsed = self.parsed_depends();
+        self.depends
+            .iter()
+            .enumerate()
+            .filter_map(move |(idx, dependency)| match &parsed[idx] {
+                Ok(spec) => spec.name.clone(),
+                Err(_) => MatchSpec::name_from_str(dependency).ok(),
+            })
+    }
+
+    /// Replaces the dependencies of this package.
+    pub fn set_depends(&mut self, depends: Vec<String>) {
+        self.depends = depends;
+    }
+
+    /// Returns the dependencies of this package when the given extras are
+    /// requested. These are the regular dependencies followed by the
+    /// dependencies of each of the extras. Extras that the package does not
+    /// provide are ignored.
+    pub fn depends_with_extras<'a>(
+        &'a self,
+        extras: &'a [String],
+    ) -> impl Iterator<Item = &'a String> + 'a {
+        self.depends.iter().chain(
+            extras
+                .iter()
+                .filter_map(|extra| self.extra_depends.get(extra))
+                .flatten(),
+        )
+    }
+
     /// Sorts the records topologically.
     ///
     /// This function is deterministic, meaning that it will return the same
@@ -402,6 +542,8 @@ impl PackageRecord {
             build_number: index.build_number,
             constrains: index.constrains,
             depends: index.depends,
+            depends_cache: DependsCache::default(),
+            extra_depends: index.extra_depends,
             features: index.features,
             legacy_bz2_md5: None,
             legacy_bz2_size: None,
@@ -418,11 +560,76 @@ impl PackageRecord {
             track_features: index.track_features,
             version: index.version,
             purls: None,
+            python_site_packages_path: index.python_site_packages_path,
             run_exports: None,
         })
     }
 }
 
+/// The parsed dependencies of a record together with a hash of the
+/// dependencies they were parsed from.
+type ParsedDepends = (u64, Arc<[Result<MatchSpec, ParseMatchSpecError>]>);
+
+/// Caches the parsed [`PackageRecord::depends`] of a record, see
+/// [`PackageRecord::parsed_depends`].
+///
+/// The cached specs are keyed on a hash of the dependencies they were parsed
+/// from so modifying [`PackageRecord::depends`] directly never returns stale
+/// specs.
+///
+/// The cache is not part of the identity of a record: two caches always
+/// compare equal and the cache does not contribute to the hash of a record.
+#[derive(Default)]
+struct DependsCache(Mutex<Option<ParsedDepends>>);
+
+impl DependsCache {
+    fn get_or_parse(&self, depends: &[String]) -> Arc<[Result<MatchSpec, ParseMatchSpecError>]> {
+        let key = {
+            let mut hasher = fxhash::FxHasher64::default();
+            depends.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let mut cache = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        match cache.as_ref() {
+            Some((cached_key, parsed)) if *cached_key == key => parsed.clone(),
+            _ => {
+                let parsed: Arc<[_]> = depends
+                    .iter()
+                    .map(|dependency| MatchSpec::from_str(dependency, ParseStrictness::Lenient))
+                    .collect();
+                *cache = Some((key, parsed.clone()));
+                parsed
+            }
+        }
+    }
+}
+
+impl Clone for DependsCache {
+    fn clone(&self) -> Self {
+        let cache = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        Self(Mutex::new(cache.clone()))
+    }
+}
+
+impl std::fmt::Debug for DependsCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DependsCache").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for DependsCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for DependsCache {}
+
+impl Hash for DependsCache {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
 fn sort_map_alphabetically<T: Serialize, S: serde::Serializer>(
     value: &FxHashMap<String, T>,
     serializer: S,
@@ -442,11 +649,13 @@ fn sort_set_alphabetically<S: serde::Serializer>(
 
 #[cfg(test)]
 mod test {
+    use std::str::FromStr;
+
     use fxhash::FxHashMap;
 
     use crate::{
         repo_data::{compute_package_url, determine_subdir},
-        Channel, ChannelConfig, RepoData,
+        Channel, ChannelConfig, PackageName, PackageRecord, RepoData, Version,
     };
 
     // isl-0.12.2-1.tar.bz2
@@ -527,6 +736,87 @@ mod test {
         insta::assert_yaml_snapshot!(file_urls);
     }
 
+    #[test]
+    fn test_slice() {
+        let repodata = deserialize_json_from_test_data("channels/dummy/linux-64/repodata.json");
+        let sliced = repodata.slice([PackageName::new_unchecked("foobar")]);
+
+        let mut file_names = sliced
+            .packages
+            .keys()
+            .chain(sliced.conda_packages.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        file_names.sort();
+        assert_eq!(
+            file_names,
+            [
+                "bors-1.0-bla_1.tar.bz2",
+                "bors-1.1-bla_1.tar.bz2",
+                "bors-1.2.1-bla_1.tar.bz2",
+                "bors-2.0-bla_1.tar.bz2",
+                "bors-2.1-bla_1.tar.bz2",
+                "foobar-2.0-bla_1.tar.bz2",
+                "foobar-2.1-bla_1.tar.bz2",
+            ]
+        );
+        assert_eq!(sliced.info, repodata.info);
+
+        assert!(repodata
+            .slice([PackageName::new_unchecked("missing")])
+            .packages
+            .is_empty());
+    }
+
+    #[test]
+    fn test_parsed_depends() {
+        let mut record = PackageRecord::new(
+            PackageName::new_unchecked("foo"),
+            Version::from_str("1.0").unwrap(),
+            String::from("0"),
+        );
+        record.depends = vec![
+            String::from("python >=3.8"),
+            String::from(r#"bar [version="1.2.3", build_number=]"#),
+        ];
+
+        let parsed = record.parsed_depends();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(
+            parsed[0].as_ref().unwrap().name,
+            Some(PackageName::new_unchecked("python"))
+        );
+        assert!(parsed[1].is_err());
+        assert_eq!(
+            record.dependency_names().collect::<Vec<_>>(),
+            [
+                PackageName::new_unchecked("python"),
+                PackageName::new_unchecked("bar")
+            ]
+        );
+
+        // The cache does not affect the equality of records.
+        let mut unparsed = PackageRecord::new(
+            record.name.clone(),
+            record.version.clone(),
+            record.build.clone(),
+        );
+        unparsed.depends.clone_from(&record.depends);
+        assert_eq!(record, unparsed);
+
+        // Modifying the dependencies directly does not return stale specs.
+        record.depends = vec![String::from("numpy")];
+        assert_eq!(
+            record.dependency_names().collect::<Vec<_>>(),
+            [PackageName::new_unchecked("numpy")]
+        );
+        record.set_depends(vec![String::from("scipy")]);
+        assert_eq!(
+            record.dependency_names().collect::<Vec<_>>(),
+            [PackageName::new_unchecked("scipy")]
+        );
+    }
+
     #[test]
     fn test_base_url() {
         let channel = Channel::from_str(
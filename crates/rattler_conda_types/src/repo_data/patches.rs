@@ -7,7 +7,7 @@ use std::collections::BTreeSet;
 use std::io;
 use std::path::Path;
 
-use crate::{package::ArchiveType, PackageRecord, PackageUrl, RepoData, Shard};
+use crate::{package::ArchiveType, PackageRecord, PackageUrl, RepoData, RepoDataRecord, Shard};
 
 /// Represents a Conda repodata patch.
 ///
@@ -122,11 +122,52 @@ pub struct PatchInstructions {
     pub conda_packages: FxHashMap<String, PackageRecordPatch>,
 }
 
+impl PatchInstructions {
+    /// Applies the instructions to a list of records, e.g. the records of a single package that
+    /// were read from a subdirectory. Records that are removed by the instructions are dropped.
+    /// Patches and removals of `.tar.bz2` packages also apply to the equivalent `.conda` package.
+    pub fn apply_to_records(&self, records: Vec<RepoDataRecord>) -> Vec<RepoDataRecord> {
+        let mut packages = FxHashMap::default();
+        let mut conda_packages = FxHashMap::default();
+        let mut origins = Vec::with_capacity(records.len());
+        for record in records {
+            let archives = match ArchiveType::try_from(&record.file_name) {
+                Some(ArchiveType::Conda) => &mut conda_packages,
+                _ => &mut packages,
+            };
+            archives.insert(record.file_name.clone(), record.package_record);
+            origins.push((record.file_name, record.url, record.channel));
+        }
+
+        apply_patches_impl(
+            &mut packages,
+            &mut conda_packages,
+            &mut FxHashSet::default(),
+            self,
+        );
+
+        origins
+            .into_iter()
+            .filter_map(|(file_name, url, channel)| {
+                let package_record = packages
+                    .remove(&file_name)
+                    .or_else(|| conda_packages.remove(&file_name))?;
+                Some(RepoDataRecord {
+                    package_record,
+                    file_name,
+                    url,
+                    channel,
+                })
+            })
+            .collect()
+    }
+}
+
 impl PackageRecord {
     /// Apply a patch to a single package record
     pub fn apply_patch(&mut self, patch: &PackageRecordPatch) {
         if let Some(depends) = &patch.depends {
-            self.depends = depends.clone();
+            self.set_depends(depends.clone());
         }
         if let Some(constrains) = &patch.constrains {
             self.constrains = constrains.clone();
@@ -230,7 +271,11 @@ impl Shard {
 
 #[cfg(test)]
 mod test {
-    use crate::{PatchInstructions, RepoData};
+    use std::str::FromStr;
+
+    use url::Url;
+
+    use crate::{PackageName, PackageRecord, PatchInstructions, RepoData, RepoDataRecord, Version};
 
     #[test]
     fn test_null_values() {
@@ -297,6 +342,59 @@ mod test {
         insta::assert_yaml_snapshot!(repodata);
     }
 
+    #[test]
+    fn test_apply_to_records() {
+        let instructions: PatchInstructions = serde_json::from_str(
+            r#"{
+                "remove": ["foo-1.0-0.tar.bz2"],
+                "packages": {"bar-1.0-0.tar.bz2": {"depends": ["python"]}},
+                "packages.conda": {"bar-1.0-0.conda": {"constrains": ["numpy"]}}
+            }"#,
+        )
+        .unwrap();
+
+        let record = |file_name: &str| {
+            let name = file_name.split('-').next().unwrap();
+            RepoDataRecord {
+                package_record: PackageRecord::new(
+                    PackageName::new_unchecked(name),
+                    Version::from_str("1.0").unwrap(),
+                    String::from("0"),
+                ),
+                file_name: file_name.to_string(),
+                url: Url::parse(&format!("https://example.com/linux-64/{file_name}")).unwrap(),
+                channel: String::from("https://example.com"),
+            }
+        };
+        let records = [
+            "foo-1.0-0.tar.bz2",
+            "foo-1.0-0.conda",
+            "bar-1.0-0.tar.bz2",
+            "bar-1.0-0.conda",
+            "baz-1.0-0.conda",
+        ]
+        .map(record)
+        .to_vec();
+
+        let patched = instructions.apply_to_records(records);
+        let file_names = patched
+            .iter()
+            .map(|record| record.file_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            file_names,
+            ["bar-1.0-0.tar.bz2", "bar-1.0-0.conda", "baz-1.0-0.conda"]
+        );
+
+        // Patches of `.tar.bz2` packages also apply to the `.conda` package.
+        assert_eq!(patched[0].package_record.depends, ["python"]);
+        assert!(patched[0].package_record.constrains.is_empty());
+        assert_eq!(patched[1].package_record.depends, ["python"]);
+        assert_eq!(patched[1].package_record.constrains, ["numpy"]);
+        assert!(patched[2].package_record.depends.is_empty());
+        assert_eq!(patched[1].url, record("bar-1.0-0.conda").url);
+    }
+
     #[test]
     fn test_patch_purl() {
         // test data
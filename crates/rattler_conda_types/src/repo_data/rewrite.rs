@@ -0,0 +1,365 @@
+//! Utilities to rewrite the dependencies of [`PackageRecord`]s, the building block for hot-fixing
+//! repodata.
+
+use crate::{
+    MatchSpec, NoArchKind, PackageName, PackageRecord, PackageRecordPatch, ParseStrictness,
+};
+
+/// Whether a change applies to the `depends` or the `constrains` of a record.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DependencyKind {
+    /// The `depends` of a record.
+    Depends,
+
+    /// The `constrains` of a record.
+    Constrains,
+}
+
+/// A change that was made to the dependencies of a record by a [`DependencyRewrite`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum DependencyChange {
+    /// A dependency was added.
+    Added {
+        /// The list the dependency was added to.
+        kind: DependencyKind,
+        /// The dependency that was added.
+        spec: String,
+    },
+
+    /// A dependency was removed.
+    Removed {
+        /// The list the dependency was removed from.
+        kind: DependencyKind,
+        /// The dependency that was removed.
+        spec: String,
+    },
+
+    /// A dependency was replaced by another.
+    Replaced {
+        /// The list that contains the dependency.
+        kind: DependencyKind,
+        /// The dependency before it was replaced.
+        previous: String,
+        /// The dependency after it was replaced.
+        spec: String,
+    },
+}
+
+/// An operation of a [`DependencyRewrite`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Operation {
+    Add(DependencyKind, String),
+    Remove(DependencyKind, PackageName),
+    Replace(DependencyKind, PackageName, String),
+}
+
+/// A set of modifications of the `depends` and `constrains` of package records, e.g. to hot-fix
+/// the metadata of packages in a channel.
+///
+/// Dependencies are identified by the name of the package they refer to. The operations are
+/// applied in the order in which they were added.
+///
+/// ```rust
+/// # use rattler_conda_types::{PackageName, PackageRecord, Version};
+/// use rattler_conda_types::DependencyRewrite;
+///
+/// let mut record = PackageRecord::new(
+///     PackageName::new_unchecked("foo"),
+///     "1.0".parse::<Version>().unwrap(),
+///     String::from("0"),
+/// );
+/// record.depends = vec![String::from("openssl >=3")];
+///
+/// let rewritten = DependencyRewrite::new()
+///     .replace_depends(PackageName::new_unchecked("openssl"), "openssl >=3,<4")
+///     .apply(&record)
+///     .unwrap();
+/// assert_eq!(rewritten.record.depends, ["openssl >=3,<4"]);
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DependencyRewrite {
+    operations: Vec<Operation>,
+    noarch: Option<NoArchFilter>,
+}
+
+/// The [`NoArchKind`] of the records a [`DependencyRewrite`] applies to, `None` refers to
+/// architecture specific records.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct NoArchFilter(Option<NoArchKind>);
+
+/// The result of applying a [`DependencyRewrite`] to a record.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RewrittenRecord {
+    /// The record with the modified dependencies.
+    pub record: PackageRecord,
+
+    /// The changes that were made to the dependencies of the record.
+    pub changes: Vec<DependencyChange>,
+}
+
+impl DependencyRewrite {
+    /// Constructs a new rewrite that does not modify anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a dependency. Nothing is added if the record already has the exact same dependency.
+    #[must_use]
+    pub fn add_depends(self, spec: impl Into<String>) -> Self {
+        self.with_operation(Operation::Add(DependencyKind::Depends, spec.into()))
+    }
+
+    /// Removes all dependencies on the package with the given name.
+    #[must_use]
+    pub fn remove_depends(self, name: PackageName) -> Self {
+        self.with_operation(Operation::Remove(DependencyKind::Depends, name))
+    }
+
+    /// Replaces all dependencies on the package with the given name by `spec`. Nothing is changed
+    /// if the record does not depend on the package.
+    #[must_use]
+    pub fn replace_depends(self, name: PackageName, spec: impl Into<String>) -> Self {
+        self.with_operation(Operation::Replace(
+            DependencyKind::Depends,
+            name,
+            spec.into(),
+        ))
+    }
+
+    /// Adds a constraint. Nothing is added if the record already has the exact same constraint.
+    #[must_use]
+    pub fn add_constrains(self, spec: impl Into<String>) -> Self {
+        self.with_operation(Operation::Add(DependencyKind::Constrains, spec.into()))
+    }
+
+    /// Removes all constraints on the package with the given name.
+    #[must_use]
+    pub fn remove_constrains(self, name: PackageName) -> Self {
+        self.with_operation(Operation::Remove(DependencyKind::Constrains, name))
+    }
+
+    /// Replaces all constraints on the package with the given name by `spec`. Nothing is changed
+    /// if the record does not constrain the package.
+    #[must_use]
+    pub fn replace_constrains(self, name: PackageName, spec: impl Into<String>) -> Self {
+        self.with_operation(Operation::Replace(
+            DependencyKind::Constrains,
+            name,
+            spec.into(),
+        ))
+    }
+
+    /// Only rewrite records of which the [`crate::NoArchType::kind`] equals `kind`. Use `None` to
+    /// only rewrite architecture specific records, or e.g. `Some(NoArchKind::Python)` to only
+    /// rewrite `noarch: python` records.
+    #[must_use]
+    pub fn only_noarch(mut self, kind: Option<NoArchKind>) -> Self {
+        self.noarch = Some(NoArchFilter(kind));
+        self
+    }
+
+    fn with_operation(mut self, operation: Operation) -> Self {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Returns true if the rewrite applies to the given record.
+    pub fn applies_to(&self, record: &PackageRecord) -> bool {
+        self.noarch
+            .map_or(true, |NoArchFilter(kind)| record.noarch.kind() == kind)
+    }
+
+    /// Applies the rewrite to a record. Returns `None` if the rewrite does not apply to the record
+    /// or if the dependencies of the record were not modified.
+    pub fn apply(&self, record: &PackageRecord) -> Option<RewrittenRecord> {
+        if !self.applies_to(record) {
+            return None;
+        }
+
+        let mut record = record.clone();
+        let mut changes = Vec::new();
+        for operation in &self.operations {
+            match operation {
+                Operation::Add(kind, spec) => {
+                    let specs = specs_mut(&mut record, *kind);
+                    if !specs.contains(spec) {
+                        specs.push(spec.clone());
+                        changes.push(DependencyChange::Added {
+                            kind: *kind,
+                            spec: spec.clone(),
+                        });
+                    }
+                }
+                Operation::Remove(kind, name) => {
+                    specs_mut(&mut record, *kind).retain(|spec| {
+                        if refers_to(spec, name) {
+                            changes.push(DependencyChange::Removed {
+                                kind: *kind,
+                                spec: spec.clone(),
+                            });
+                            false
+                        } else {
+                            true
+                        }
+                    });
+                }
+                Operation::Replace(kind, name, replacement) => {
+                    for spec in specs_mut(&mut record, *kind) {
+                        if refers_to(spec, name) && spec != replacement {
+                            let previous = std::mem::replace(spec, replacement.clone());
+                            changes.push(DependencyChange::Replaced {
+                                kind: *kind,
+                                previous,
+                                spec: replacement.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        (!changes.is_empty()).then_some(RewrittenRecord { record, changes })
+    }
+}
+
+impl RewrittenRecord {
+    /// Returns the patch that turns the original record into the rewritten record. The patch can
+    /// be stored in [`crate::PatchInstructions`].
+    pub fn to_patch(&self) -> PackageRecordPatch {
+        let modified = |kind| {
+            self.changes.iter().any(|change| match change {
+                DependencyChange::Added { kind: k, .. }
+                | DependencyChange::Removed { kind: k, .. }
+                | DependencyChange::Replaced { kind: k, .. } => *k == kind,
+            })
+        };
+
+        PackageRecordPatch {
+            depends: modified(DependencyKind::Depends).then(|| self.record.depends.clone()),
+            constrains: modified(DependencyKind::Constrains)
+                .then(|| self.record.constrains.clone()),
+            track_features: None,
+            features: None,
+            license: None,
+            license_family: None,
+            purls: None,
+        }
+    }
+}
+
+fn specs_mut(record: &mut PackageRecord, kind: DependencyKind) -> &mut Vec<String> {
+    match kind {
+        DependencyKind::Depends => &mut record.depends,
+        DependencyKind::Constrains => &mut record.constrains,
+    }
+}
+
+/// Returns true if the dependency `spec` refers to the package with the given name, e.g.
+/// `numpy>=1.20` and `conda-forge::numpy` both refer to `numpy`. Specs that cannot be parsed are
+/// compared by their first word.
+fn refers_to(spec: &str, name: &PackageName) -> bool {
+    match MatchSpec::from_str(spec, ParseStrictness::Lenient) {
+        Ok(match_spec) => match_spec.name.as_ref() == Some(name),
+        Err(_) => spec
+            .split_whitespace()
+            .next()
+            .is_some_and(|spec_name| spec_name.eq_ignore_ascii_case(name.as_normalized())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DependencyChange, DependencyKind, DependencyRewrite};
+    use crate::{NoArchKind, NoArchType, PackageName, PackageRecord, Version};
+
+    fn record(noarch: NoArchType) -> PackageRecord {
+        let mut record = PackageRecord::new(
+            PackageName::new_unchecked("foo"),
+            "1.0".parse::<Version>().unwrap(),
+            String::from("0"),
+        );
+        record.noarch = noarch;
+        record.depends = vec![String::from("python >=3.8"), String::from("numpy")];
+        record.constrains = vec![String::from("scipy <2")];
+        record
+    }
+
+    #[test]
+    fn test_rewrite_dependencies() {
+        let rewrite = DependencyRewrite::new()
+            .replace_depends(PackageName::new_unchecked("python"), "python >=3.9")
+            .remove_depends(PackageName::new_unchecked("numpy"))
+            .add_depends("numpy >=1.20")
+            .add_constrains("scipy <2")
+            .remove_constrains(PackageName::new_unchecked("pandas"));
+
+        let rewritten = rewrite.apply(&record(NoArchType::none())).unwrap();
+        assert_eq!(rewritten.record.depends, ["python >=3.9", "numpy >=1.20"]);
+        assert_eq!(rewritten.record.constrains, ["scipy <2"]);
+        assert_eq!(
+            rewritten.changes,
+            [
+                DependencyChange::Replaced {
+                    kind: DependencyKind::Depends,
+                    previous: String::from("python >=3.8"),
+                    spec: String::from("python >=3.9"),
+                },
+                DependencyChange::Removed {
+                    kind: DependencyKind::Depends,
+                    spec: String::from("numpy"),
+                },
+                DependencyChange::Added {
+                    kind: DependencyKind::Depends,
+                    spec: String::from("numpy >=1.20"),
+                },
+            ]
+        );
+
+        // Only the modified dependencies are part of the patch.
+        let patch = rewritten.to_patch();
+        assert_eq!(patch.depends, Some(rewritten.record.depends.clone()));
+        assert_eq!(patch.constrains, None);
+
+        // Applying the patch to the original record results in the rewritten record.
+        let mut patched = record(NoArchType::none());
+        patched.apply_patch(&patch);
+        assert_eq!(patched, rewritten.record);
+    }
+
+    #[test]
+    fn test_rewrite_refers_to_name() {
+        let mut record = record(NoArchType::none());
+        record.depends = vec![
+            String::from("numpy>=1.20"),
+            String::from("conda-forge::numpy"),
+            String::from("numpy-base"),
+            String::from("NumPy 1.20.*"),
+        ];
+
+        let rewritten = DependencyRewrite::new()
+            .remove_depends(PackageName::new_unchecked("numpy"))
+            .apply(&record)
+            .unwrap();
+        assert_eq!(rewritten.record.depends, ["numpy-base"]);
+    }
+
+    #[test]
+    fn test_rewrite_noarch() {
+        let rewrite = DependencyRewrite::new()
+            .add_depends("python_abi")
+            .only_noarch(Some(NoArchKind::Python));
+        assert!(rewrite.apply(&record(NoArchType::python())).is_some());
+        assert!(rewrite.apply(&record(NoArchType::generic())).is_none());
+        assert!(rewrite.apply(&record(NoArchType::none())).is_none());
+
+        // Rewrites that do not modify a record return nothing.
+        assert!(DependencyRewrite::new()
+            .remove_depends(PackageName::new_unchecked("num"))
+            .apply(&record(NoArchType::none()))
+            .is_none());
+        assert!(DependencyRewrite::new()
+            .remove_depends(PackageName::new_unchecked("pandas"))
+            .apply(&record(NoArchType::none()))
+            .is_none());
+    }
+}
@@ -32,6 +32,22 @@ pub struct ShardedSubdirInfo {
     ///
     /// This is used to construct the full url of the shard.
     pub shards_base_url: String,
+
+    /// The zstd dictionary that was used to compress the individual shards.
+    /// If this is `None` the shards are compressed without a dictionary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dictionary: Option<ShardDictionary>,
+}
+
+/// Describes a zstd dictionary that can be used to decompress shards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardDictionary {
+    /// The url of the dictionary, relative to the index file.
+    pub url: String,
+
+    /// The SHA256 hash of the dictionary. This is used to verify the
+    /// dictionary and to key it in a cache.
+    pub sha256: Sha256Hash,
 }
 
 /// An individual shard that contains repodata for a single package name.
@@ -1,6 +1,9 @@
 //! Defines the `[RepoDataRecord]` struct.
 
-use crate::PackageRecord;
+use std::{fmt::Write, str::FromStr};
+
+use crate::{package::ArchiveType, ChannelUrl, PackageRecord, PackageUrl};
+use rattler_redaction::{redact_known_secrets_from_str, Redact, DEFAULT_REDACTION_STR};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
@@ -25,6 +28,65 @@ pub struct RepoDataRecord {
     pub channel: String,
 }
 
+impl RepoDataRecord {
+    /// Returns the normalized url of the channel the package comes from, or
+    /// `None` if [`Self::channel`] is not a url, e.g. when it is the name of
+    /// the channel.
+    ///
+    /// Use this instead of comparing [`Self::channel`] directly, the string
+    /// representation of a channel is not necessarily normalized.
+    pub fn channel_url(&self) -> Option<ChannelUrl> {
+        self.channel.parse().ok()
+    }
+
+    /// Returns the package url (purl) that identifies this conda package,
+    /// e.g. `pkg:conda/python@3.12.0?build=h1234_0&channel=...&subdir=linux-64&type=conda`.
+    ///
+    /// This differs from [`PackageRecord::purls`] which describes the packages
+    /// of other ecosystems (e.g. PyPI) that the conda package contains.
+    /// Known secrets in the channel, like tokens, are redacted. Returns `None`
+    /// if the record cannot be represented as a purl.
+    pub fn conda_purl(&self) -> Option<PackageUrl> {
+        let record = &self.package_record;
+        let mut purl = format!(
+            "pkg:conda/{}@{}?build={}",
+            percent_encode(record.name.as_normalized()),
+            percent_encode(&record.version.to_string()),
+            percent_encode(&record.build)
+        );
+        let channel = self.channel_url().map_or_else(
+            || redact_known_secrets_from_str(&self.channel, DEFAULT_REDACTION_STR).into_owned(),
+            |url| url.url().clone().redact().to_string(),
+        );
+        if !channel.is_empty() {
+            write!(purl, "&channel={}", percent_encode(&channel)).unwrap();
+        }
+        if !record.subdir.is_empty() {
+            write!(purl, "&subdir={}", percent_encode(&record.subdir)).unwrap();
+        }
+        match ArchiveType::split_str(&self.file_name) {
+            Some((_, ArchiveType::Conda)) => purl.push_str("&type=conda"),
+            Some((_, ArchiveType::TarBz2)) => purl.push_str("&type=tar.bz2"),
+            None => {}
+        }
+        PackageUrl::from_str(&purl).ok()
+    }
+}
+
+/// Percent-encodes all characters of a purl component except the unreserved
+/// ones.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'-' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            write!(encoded, "%{byte:02X}").unwrap();
+        }
+    }
+    encoded
+}
+
 impl AsRef<PackageRecord> for RepoDataRecord {
     fn as_ref(&self) -> &PackageRecord {
         &self.package_record
@@ -0,0 +1,413 @@
+//! Generation of software bills of materials (SBOMs) for conda environments.
+//!
+//! The functions in this module describe the packages that are installed in a
+//! prefix in the [CycloneDX](https://cyclonedx.org) and [SPDX](https://spdx.dev)
+//! JSON formats so they can be inventoried by security tooling. Every package
+//! is identified by its conda package url (see
+//! [`crate::RepoDataRecord::conda_purl`]), the package urls of other
+//! ecosystems that are recorded in the repodata (see
+//! [`crate::PackageRecord::purls`]) are included as well. Known secrets in the
+//! urls of packages, like channel tokens, are redacted.
+
+use chrono::{DateTime, Utc};
+use rattler_redaction::Redact;
+use serde_json::{json, Map, Value};
+
+use crate::PrefixRecord;
+
+/// The version of the CycloneDX specification of the generated documents.
+pub const CYCLONEDX_SPEC_VERSION: &str = "1.5";
+
+/// The version of the SPDX specification of the generated documents.
+pub const SPDX_VERSION: &str = "SPDX-2.3";
+
+/// Returns the CycloneDX component that describes an installed package.
+pub fn cyclonedx_component(record: &PrefixRecord) -> Value {
+    let repodata_record = &record.repodata_record;
+    let package_record = &repodata_record.package_record;
+
+    let mut component = Map::new();
+    component.insert("type".into(), json!("library"));
+    component.insert("name".into(), json!(package_record.name.as_normalized()));
+    component.insert("version".into(), json!(package_record.version.to_string()));
+    if let Some(purl) = repodata_record.conda_purl() {
+        component.insert("bom-ref".into(), json!(purl.to_string()));
+        component.insert("purl".into(), json!(purl.to_string()));
+    }
+
+    let mut hashes = Vec::new();
+    if let Some(sha256) = &package_record.sha256 {
+        hashes.push(json!({ "alg": "SHA-256", "content": format!("{sha256:x}") }));
+    }
+    if let Some(md5) = &package_record.md5 {
+        hashes.push(json!({ "alg": "MD5", "content": format!("{md5:x}") }));
+    }
+    if !hashes.is_empty() {
+        component.insert("hashes".into(), Value::Array(hashes));
+    }
+
+    // Licenses that are not valid SPDX expressions can only be stored by name.
+    if let Some(license) = &package_record.license {
+        let license = if is_spdx_expression(license) {
+            json!({ "expression": license })
+        } else {
+            json!({ "license": { "name": license } })
+        };
+        component.insert("licenses".into(), json!([license]));
+    }
+
+    let url = repodata_record.url.clone().redact();
+    component.insert(
+        "externalReferences".into(),
+        json!([{ "type": "distribution", "url": url.as_str() }]),
+    );
+
+    // CycloneDX only supports a single purl per component, the purls of
+    // other ecosystems are added as properties.
+    let properties = package_record
+        .purls
+        .iter()
+        .flatten()
+        .map(|purl| json!({ "name": "conda:purl", "value": purl.to_string() }))
+        .collect::<Vec<_>>();
+    if !properties.is_empty() {
+        component.insert("properties".into(), Value::Array(properties));
+    }
+
+    Value::Object(component)
+}
+
+/// Returns a CycloneDX document that describes the installed packages.
+pub fn to_cyclonedx<'a>(records: impl IntoIterator<Item = &'a PrefixRecord>) -> Value {
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": CYCLONEDX_SPEC_VERSION,
+        "version": 1,
+        "metadata": {
+            "tools": [{ "name": "rattler", "version": env!("CARGO_PKG_VERSION") }],
+        },
+        "components": records.into_iter().map(cyclonedx_component).collect::<Vec<_>>(),
+    })
+}
+
+/// Returns the SPDX package that describes an installed package. The `index`
+/// is used to make the identifier of the package unique within a document.
+pub fn spdx_package(record: &PrefixRecord, index: usize) -> Value {
+    let repodata_record = &record.repodata_record;
+    let package_record = &repodata_record.package_record;
+
+    let mut checksums = Vec::new();
+    if let Some(sha256) = &package_record.sha256 {
+        checksums.push(json!({ "algorithm": "SHA256", "checksumValue": format!("{sha256:x}") }));
+    }
+    if let Some(md5) = &package_record.md5 {
+        checksums.push(json!({ "algorithm": "MD5", "checksumValue": format!("{md5:x}") }));
+    }
+
+    let external_refs = repodata_record
+        .conda_purl()
+        .into_iter()
+        .chain(package_record.purls.iter().flatten().cloned())
+        .map(|purl| {
+            json!({
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": purl.to_string(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "SPDXID": spdx_package_id(package_record.name.as_normalized(), index),
+        "name": package_record.name.as_normalized(),
+        "versionInfo": package_record.version.to_string(),
+        "downloadLocation": repodata_record.url.clone().redact().as_str(),
+        "filesAnalyzed": false,
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": package_record
+            .license
+            .as_deref()
+            .filter(|license| is_spdx_expression(license))
+            .unwrap_or("NOASSERTION"),
+        "copyrightText": "NOASSERTION",
+        "checksums": checksums,
+        "externalRefs": external_refs,
+    })
+}
+
+/// Returns an SPDX document that describes the installed packages.
+///
+/// The `document_namespace` must be a unique uri for the document, e.g. a
+/// url that contains a uuid.
+pub fn to_spdx<'a>(
+    document_name: &str,
+    document_namespace: &str,
+    created: DateTime<Utc>,
+    records: impl IntoIterator<Item = &'a PrefixRecord>,
+) -> Value {
+    let packages = records
+        .into_iter()
+        .enumerate()
+        .map(|(index, record)| spdx_package(record, index))
+        .collect::<Vec<_>>();
+    let relationships = packages
+        .iter()
+        .map(|package| {
+            json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": package["SPDXID"],
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "spdxVersion": SPDX_VERSION,
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": document_name,
+        "documentNamespace": document_namespace,
+        "creationInfo": {
+            "created": created.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            "creators": [format!("Tool: rattler-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+/// Returns true if `license` is a syntactically valid SPDX license expression,
+/// e.g. `MIT`, `GPL-2.0-or-later WITH Classpath-exception-2.0` or
+/// `(MIT OR Apache-2.0) AND BSD-3-Clause`. Free form licenses that are common
+/// in repodata, like `BSD 3-Clause`, are rejected. The license identifiers
+/// themselves are not checked against the SPDX license list.
+fn is_spdx_expression(license: &str) -> bool {
+    fn is_license_id(token: &str) -> bool {
+        let id = token.strip_suffix('+').unwrap_or(token);
+        let id = match id.split_once(':') {
+            Some((document, license)) if document.starts_with("DocumentRef-") => {
+                if !license.starts_with("LicenseRef-") {
+                    return false;
+                }
+                license
+            }
+            Some(_) => return false,
+            None => id,
+        };
+        !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+            && !matches!(id, "AND" | "OR" | "WITH")
+    }
+
+    // Parses `term (AND|OR term)*` and returns the number of consumed tokens.
+    fn parse_expression(tokens: &[&str]) -> Option<usize> {
+        let mut consumed = parse_term(tokens)?;
+        while matches!(tokens.get(consumed), Some(&("AND" | "OR"))) {
+            consumed += 1 + parse_term(&tokens[consumed + 1..])?;
+        }
+        Some(consumed)
+    }
+
+    // Parses `( expression )` or `license [WITH exception]`.
+    fn parse_term(tokens: &[&str]) -> Option<usize> {
+        match tokens.first()? {
+            &"(" => {
+                let consumed = 1 + parse_expression(&tokens[1..])?;
+                (tokens.get(consumed) == Some(&")")).then_some(consumed + 1)
+            }
+            license if is_license_id(license) => match tokens.get(1) {
+                Some(&"WITH") => tokens
+                    .get(2)
+                    .filter(|exception| is_license_id(exception))
+                    .map(|_| 3),
+                _ => Some(1),
+            },
+            _ => None,
+        }
+    }
+
+    let spaced = license.replace('(', " ( ").replace(')', " ) ");
+    let tokens = spaced.split_whitespace().collect::<Vec<_>>();
+    parse_expression(&tokens) == Some(tokens.len())
+}
+
+/// Returns an SPDX identifier for a package. Identifiers may only contain
+/// letters, numbers, `.` and `-`.
+fn spdx_package_id(name: &str, index: usize) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    format!("SPDXRef-Package-{name}-{index}")
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeSet, str::FromStr};
+
+    use chrono::{TimeZone, Utc};
+    use rattler_digest::{parse_digest_from_hex, Sha256};
+
+    use super::{is_spdx_expression, to_cyclonedx, to_spdx};
+    use crate::{PackageName, PackageRecord, PackageUrl, PrefixRecord, RepoDataRecord, Version};
+
+    fn prefix_record() -> PrefixRecord {
+        let mut package_record = PackageRecord::new(
+            PackageName::new_unchecked("python_abi"),
+            Version::from_str("3.12").unwrap(),
+            String::from("4_cp312"),
+        );
+        package_record.subdir = String::from("linux-64");
+        package_record.license = Some(String::from("BSD-3-Clause"));
+        package_record.sha256 = parse_digest_from_hex::<Sha256>(
+            "9c51bbcbd7c4cfb8bb42dfb1df2a7b12e7e9d1d6b7a9d1b6a0de3e4b2bcd1f3a",
+        );
+        package_record.purls = Some(BTreeSet::from([PackageUrl::from_str(
+            "pkg:pypi/python-abi",
+        )
+        .unwrap()]));
+        let file_name = String::from("python_abi-3.12-4_cp312.conda");
+        PrefixRecord::from_repodata_record(
+            RepoDataRecord {
+                url: format!("https://conda.anaconda.org/conda-forge/linux-64/{file_name}")
+                    .parse()
+                    .unwrap(),
+                package_record,
+                file_name,
+                channel: String::from("https://conda.anaconda.org/conda-forge/"),
+            },
+            None,
+            None,
+            Vec::new(),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_conda_purl() {
+        let record = prefix_record();
+        assert_eq!(
+            record.repodata_record.conda_purl(),
+            Some(
+                PackageUrl::from_str(
+                    "pkg:conda/python_abi@3.12?build=4_cp312\
+                     &channel=https%3A%2F%2Fconda.anaconda.org%2Fconda-forge%2F\
+                     &subdir=linux-64&type=conda"
+                )
+                .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_cyclonedx() {
+        let record = prefix_record();
+        let bom = to_cyclonedx([&record]);
+        assert_eq!(bom["bomFormat"], "CycloneDX");
+        let component = &bom["components"][0];
+        assert_eq!(component["name"], "python_abi");
+        assert_eq!(component["version"], "3.12");
+        assert_eq!(component["hashes"][0]["alg"], "SHA-256");
+        assert_eq!(component["licenses"][0]["expression"], "BSD-3-Clause");
+        assert_eq!(component["properties"][0]["value"], "pkg:pypi/python-abi");
+        assert!(component["purl"]
+            .as_str()
+            .unwrap()
+            .starts_with("pkg:conda/python_abi@3.12?"));
+    }
+
+    #[test]
+    fn test_is_spdx_expression() {
+        for license in [
+            "MIT",
+            "BSD-3-Clause",
+            "GPL-2.0+",
+            "MIT OR Apache-2.0",
+            "(MIT OR Apache-2.0) AND BSD-3-Clause",
+            "GPL-2.0-or-later WITH Classpath-exception-2.0",
+            "LicenseRef-Proprietary",
+            "DocumentRef-spdx-tool-1.2:LicenseRef-MIT-Style-2",
+        ] {
+            assert!(is_spdx_expression(license), "{license}");
+        }
+        for license in [
+            "",
+            "BSD 3-Clause",
+            "Apache License 2.0",
+            "MIT/X11",
+            "MIT AND",
+            "(MIT OR Apache-2.0",
+            "MIT OR Apache-2.0)",
+            "GPL-2.0 WITH",
+        ] {
+            assert!(!is_spdx_expression(license), "{license}");
+        }
+    }
+
+    #[test]
+    fn test_free_form_license() {
+        let mut record = prefix_record();
+        record.repodata_record.package_record.license = Some(String::from("BSD 3-Clause"));
+
+        let bom = to_cyclonedx([&record]);
+        let license = &bom["components"][0]["licenses"][0];
+        assert_eq!(license["license"]["name"], "BSD 3-Clause");
+        assert!(license.get("expression").is_none());
+
+        let created = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let document = to_spdx("environment", "https://example.com", created, [&record]);
+        assert_eq!(document["packages"][0]["licenseDeclared"], "NOASSERTION");
+    }
+
+    #[test]
+    fn test_redact_secrets() {
+        let mut record = prefix_record();
+        record.repodata_record.url =
+            "https://conda.anaconda.org/t/secret-token/private/linux-64/python_abi-3.12-4_cp312.conda"
+                .parse()
+                .unwrap();
+        record.repodata_record.channel =
+            String::from("https://conda.anaconda.org/t/secret-token/private/");
+
+        let bom = to_cyclonedx([&record]);
+        let created = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let document = to_spdx("environment", "https://example.com", created, [&record]);
+        for rendered in [bom.to_string(), document.to_string()] {
+            assert!(!rendered.contains("secret-token"), "{rendered}");
+        }
+        assert_eq!(
+            bom["components"][0]["externalReferences"][0]["url"],
+            "https://conda.anaconda.org/t/********/private/linux-64/python_abi-3.12-4_cp312.conda"
+        );
+    }
+
+    #[test]
+    fn test_spdx() {
+        let record = prefix_record();
+        let created = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let document = to_spdx(
+            "environment",
+            "https://example.com/environment",
+            created,
+            [&record],
+        );
+        assert_eq!(document["creationInfo"]["created"], "2024-01-01T00:00:00Z");
+        let package = &document["packages"][0];
+        assert_eq!(package["SPDXID"], "SPDXRef-Package-python-abi-0");
+        assert_eq!(package["licenseDeclared"], "BSD-3-Clause");
+        assert_eq!(package["externalRefs"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            document["relationships"][0]["relatedSpdxElement"],
+            "SPDXRef-Package-python-abi-0"
+        );
+    }
+}
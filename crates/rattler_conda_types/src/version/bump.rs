@@ -69,11 +69,7 @@ impl Version {
             self.flags
         };
 
-        Cow::Owned(Version {
-            components,
-            segments: segments.into(),
-            flags,
-        })
+        Cow::Owned(Version::from_parts(components, segments.into(), flags))
     }
 
     /// Remove the local segment from the version if it exists.
@@ -88,11 +84,11 @@ impl Version {
             let mut components = self.components.clone();
             components.drain(components_offset..);
 
-            Cow::Owned(Version {
+            Cow::Owned(Version::from_parts(
                 components,
-                segments: segments.into(),
-                flags: self.flags.with_local_segment_index(0).unwrap(),
-            })
+                segments.into(),
+                self.flags.with_local_segment_index(0).unwrap(),
+            ))
         } else {
             return Cow::Borrowed(self);
         }
@@ -204,11 +200,7 @@ impl Version {
                 .expect("this should never fail because no new segments are added");
         }
 
-        Ok(Self {
-            components,
-            segments,
-            flags,
-        })
+        Ok(Self::from_parts(components, segments, flags))
     }
 }
 
@@ -1,32 +1,40 @@
 use std::fmt::{Debug, Formatter};
 
 /// Bitmask indicates if the first component stored in a [`super::Version`] refers to an epoch.
-const EPOCH_MASK: u8 = 0b1;
-const EPOCH_OFFSET: u8 = 0;
+const EPOCH_MASK: u16 = 0b1;
+const EPOCH_OFFSET: u16 = 0;
 
 /// Bitmask that indicates what the index is of the first segment that belongs to the local version
 /// part. E.g. the part after the '+' sign in `1.2.3+4.5.6`.
-const LOCAL_VERSION_MASK: u8 = (1 << 7) - 1;
-const LOCAL_VERSION_OFFSET: u8 = 1;
+const LOCAL_VERSION_MASK: u16 = (1 << 7) - 1;
+const LOCAL_VERSION_OFFSET: u16 = 1;
 
-/// Encodes several edge cases in a single byte.
+/// Bitmask of a single bit that indicates whether the version only consists of numbers.
+const PLAIN_NUMERIC_MASK: u16 = 0b1;
+const PLAIN_NUMERIC_OFFSET: u16 = 8;
+
+/// Encodes several edge cases in two bytes.
 ///
 /// The first bit is used to indicate whether or not there is an explicit epoch present in the
 /// version. If the flag is set it means the first entry in the [`Version::components`] array refers
 /// to the epoch instead of to the first component of the first segment.
 ///
-/// The remaining bits are used to encode the index of the first segment that belongs to the local
+/// The next seven bits are used to encode the index of the first segment that belongs to the local
 /// version part instead of to the common part. A value of `0` indicates that there is not local
 /// version part.
+///
+/// The ninth bit indicates whether the version is plain numeric, see
+/// [`super::Version::is_plain_numeric`].
 #[derive(Copy, Clone, Eq, PartialEq, Default)]
 #[repr(transparent)]
-pub struct Flags(pub(super) u8);
+pub struct Flags(pub(super) u16);
 
 impl Debug for Flags {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Flags")
             .field("has_epoch", &self.has_epoch())
             .field("local_segment_index", &self.local_segment_index())
+            .field("is_plain_numeric", &self.is_plain_numeric())
             .finish()
     }
 }
@@ -54,6 +62,7 @@ impl Flags {
     /// be stored.
     #[must_use]
     pub fn with_local_segment_index(self, index: u8) -> Option<Self> {
+        let index = u16::from(index);
         if index > LOCAL_VERSION_MASK {
             None
         } else {
@@ -66,7 +75,25 @@ impl Flags {
 
     /// Returns the index of the first segment that belongs to the local part of the version.
     pub fn local_segment_index(self) -> u8 {
-        (self.0 >> LOCAL_VERSION_OFFSET) & LOCAL_VERSION_MASK
+        ((self.0 >> LOCAL_VERSION_OFFSET) & LOCAL_VERSION_MASK) as u8
+    }
+
+    /// Sets whether or not the version only consists of numbers.
+    #[must_use]
+    pub fn with_plain_numeric(self, is_plain_numeric: bool) -> Self {
+        let flag = self.0 & !(PLAIN_NUMERIC_MASK << PLAIN_NUMERIC_OFFSET);
+        Self(
+            flag | if is_plain_numeric {
+                PLAIN_NUMERIC_MASK << PLAIN_NUMERIC_OFFSET
+            } else {
+                0
+            },
+        )
+    }
+
+    /// Returns true if this instance indicates that the version only consists of numbers.
+    pub fn is_plain_numeric(self) -> bool {
+        (self.0 >> PLAIN_NUMERIC_OFFSET) & PLAIN_NUMERIC_MASK != 0
     }
 }
 
@@ -104,14 +131,26 @@ mod test {
         assert_eq!(Flags::default().with_local_segment_index(128), None);
     }
 
+    #[test]
+    fn test_plain_numeric() {
+        assert!(!Flags::default().is_plain_numeric());
+        assert!(Flags::default().with_plain_numeric(true).is_plain_numeric());
+        assert!(!Flags::default()
+            .with_plain_numeric(true)
+            .with_plain_numeric(false)
+            .is_plain_numeric());
+    }
+
     #[test]
     fn test_all_elements() {
         let flags = Flags::default()
             .with_has_epoch(true)
             .with_local_segment_index(101)
-            .unwrap();
+            .unwrap()
+            .with_plain_numeric(true);
 
         assert!(flags.has_epoch());
         assert_eq!(flags.local_segment_index(), 101);
+        assert!(flags.is_plain_numeric());
     }
 }
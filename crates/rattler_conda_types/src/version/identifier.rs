@@ -0,0 +1,168 @@
+use std::{
+    borrow::Borrow,
+    fmt,
+    fmt::{Debug, Display, Formatter},
+    ops::Deref,
+    sync::{Arc, OnceLock},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Identifiers that occur frequently in versions. Parsing one of these does not allocate, instead
+/// all versions share the same instance. Must be sorted.
+const COMMON_IDENTIFIERS: [&str; 16] = [
+    "a", "alpha", "b", "beta", "c", "final", "g", "h", "p", "patch", "pre", "r", "rc", "rev", "v",
+    "vc",
+];
+
+/// A string identifier of a version, e.g. the `rc` in `1.0rc1`.
+///
+/// Identifiers are stored behind a thin reference counted pointer. This keeps a
+/// [`super::Component`] at 16 bytes which allows more components to be stored inline in a
+/// [`super::Version`]. Frequently used identifiers are interned and shared between all versions.
+#[derive(Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Identifier(Arc<Box<str>>);
+
+impl Identifier {
+    /// Constructs a new identifier, reusing an existing instance if the identifier is common.
+    pub fn new(value: &str) -> Self {
+        Self::common(value).unwrap_or_else(|| Self(Arc::new(value.into())))
+    }
+
+    /// Returns the identifier as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns a mutable reference to the identifier, copying it first if it
+    /// is shared with other versions.
+    pub(crate) fn make_mut(&mut self) -> &mut Box<str> {
+        Arc::make_mut(&mut self.0)
+    }
+
+    /// Returns the interned instance of `value` if it is a common identifier.
+    fn common(value: &str) -> Option<Self> {
+        static INTERNED: OnceLock<Vec<Identifier>> = OnceLock::new();
+        let index = COMMON_IDENTIFIERS.binary_search(&value).ok()?;
+        let interned = INTERNED.get_or_init(|| {
+            COMMON_IDENTIFIERS
+                .iter()
+                .map(|&iden| Self(Arc::new(iden.into())))
+                .collect()
+        });
+        Some(interned[index].clone())
+    }
+}
+
+impl Deref for Identifier {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Identifier {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for Identifier {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Identifier {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<String> for Identifier {
+    fn from(value: String) -> Self {
+        Self::common(&value).unwrap_or_else(|| Self(Arc::new(value.into_boxed_str())))
+    }
+}
+
+impl From<Box<str>> for Identifier {
+    fn from(value: Box<str>) -> Self {
+        Self::common(&value).unwrap_or_else(|| Self(Arc::new(value)))
+    }
+}
+
+impl From<Identifier> for Box<str> {
+    fn from(value: Identifier) -> Self {
+        Arc::try_unwrap(value.0).unwrap_or_else(|value| (*value).clone())
+    }
+}
+
+impl PartialEq<str> for Identifier {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Identifier {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+impl Debug for Identifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Identifier {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Identifier {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{Identifier, COMMON_IDENTIFIERS};
+
+    #[test]
+    fn test_common_identifiers_are_interned() {
+        assert!(COMMON_IDENTIFIERS.windows(2).all(|w| w[0] < w[1]));
+        for iden in COMMON_IDENTIFIERS {
+            assert!(Arc::ptr_eq(
+                &Identifier::new(iden).0,
+                &Identifier::from(iden.to_string()).0
+            ));
+        }
+        assert!(!Arc::ptr_eq(
+            &Identifier::new("foo").0,
+            &Identifier::new("foo").0
+        ));
+        assert_eq!(Identifier::new("foo"), Identifier::from("foo".to_string()));
+    }
+
+    #[test]
+    fn test_make_mut_copies_shared_identifiers() {
+        let shared = Identifier::new("rc");
+        let mut iden = shared.clone();
+        *iden.make_mut() = "dev".into();
+        assert_eq!(iden, "dev");
+        assert_eq!(shared, "rc");
+        assert_eq!(Identifier::new("rc"), "rc");
+    }
+}
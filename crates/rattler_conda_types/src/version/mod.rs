@@ -1,5 +1,6 @@
 use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::Bound;
 use std::hash::{Hash, Hasher};
 use std::ops::RangeBounds;
@@ -18,12 +19,14 @@ use smallvec::SmallVec;
 pub use parse::{ParseVersionError, ParseVersionErrorKind};
 
 mod flags;
+mod identifier;
 pub(crate) mod parse;
 mod segment;
 mod with_source;
 
 pub(crate) mod bump;
 pub use bump::{VersionBumpError, VersionBumpType};
+pub use identifier::Identifier;
 
 use flags::Flags;
 use segment::Segment;
@@ -139,7 +142,7 @@ pub struct Version {
     ///
     /// We store a maximum of 3 components on the stack. If a version consists of more components
     /// they are stored on the heap instead. We choose 3 here because most versions only consist of
-    /// 3 components.
+    /// 3 components. A [`Component`] is 16 bytes so the inline storage takes up 48 bytes.
     ///
     /// So for the version `1.2g.beta15.rc` this stores:
     ///
@@ -161,13 +164,21 @@ pub struct Version {
 
     /// Flags to indicate edge cases
     /// The first bit indicates whether or not this version has an epoch.
-    /// The rest of the bits indicate from which segment the local version starts or 0 if there is
-    /// no local version.
+    /// The next seven bits indicate from which segment the local version starts or 0 if there is
+    /// no local version. The ninth bit indicates whether the version is plain numeric.
     flags: Flags,
+
+    /// The hash of the version, computed once when the version is constructed. Versions are hashed
+    /// and compared for equality a lot while solving, the hash is stored in the padding after the
+    /// flags so it does not increase the size of a version.
+    hash: u32,
 }
 
 type ComponentVec = SmallVec<[Component; 3]>;
-type SegmentVec = SmallVec<[Segment; 4]>;
+
+/// A [`Segment`] is only 2 bytes, 8 of them take up the same space as the pointer and length of
+/// the heap allocation.
+type SegmentVec = SmallVec<[Segment; 8]>;
 
 /// Error that can occur when extending a version to a certain length.
 #[derive(Error, Debug, PartialEq)]
@@ -181,11 +192,28 @@ pub enum VersionExtendError {
 impl Version {
     /// Constructs a version with just a major component and no other components, e.g. "1".
     pub fn major(major: u64) -> Version {
-        Version {
-            components: smallvec::smallvec![Component::Numeral(major)],
-            segments: smallvec::smallvec![Segment::new(1).unwrap()],
-            flags: Flags(0),
-        }
+        Version::from_parts(
+            smallvec::smallvec![Component::Numeral(major)],
+            smallvec::smallvec![Segment::new(1).unwrap()],
+            Flags(0),
+        )
+    }
+
+    /// Constructs a version from its components, segments and flags. This computes the
+    /// information that is derived from the parts: whether the version is plain numeric and the
+    /// hash of the version. All versions must be constructed through this function.
+    fn from_parts(components: ComponentVec, segments: SegmentVec, flags: Flags) -> Version {
+        let mut version = Version {
+            components,
+            segments,
+            flags,
+            hash: 0,
+        };
+        version.flags = version
+            .flags
+            .with_plain_numeric(version.compute_is_plain_numeric());
+        version.hash = version.compute_hash();
+        version
     }
 
     /// Returns true if this version has an epoch.
@@ -193,6 +221,27 @@ impl Version {
         self.flags.has_epoch()
     }
 
+    /// Returns true if the version consists only of numbers separated by
+    /// single component segments (e.g. `1.2.3`) and has no epoch or local
+    /// version. Most versions are of this form and can be compared without
+    /// iterating over their segments.
+    fn is_plain_numeric(&self) -> bool {
+        self.flags.is_plain_numeric()
+    }
+
+    fn compute_is_plain_numeric(&self) -> bool {
+        !self.has_epoch()
+            && !self.has_local()
+            && self
+                .segments
+                .iter()
+                .all(|segment| segment.len() == 1 && !segment.has_implicit_default())
+            && self
+                .components
+                .iter()
+                .all(|component| matches!(component, Component::Numeral(_)))
+    }
+
     /// Returns true if this version has a local version defined
     pub fn has_local(&self) -> bool {
         self.flags.local_segment_index() > 0
@@ -354,8 +403,8 @@ impl Version {
             return None;
         }
 
-        let mut components = SmallVec::<[Component; 3]>::default();
-        let mut segments = SmallVec::<[Segment; 4]>::default();
+        let mut components = ComponentVec::default();
+        let mut segments = SegmentVec::default();
         let mut flags = Flags::default();
 
         // Copy the epoch
@@ -404,11 +453,7 @@ impl Version {
                 .expect("the number of segments must always be smaller so this should never fail");
         }
 
-        Some(Version {
-            components,
-            segments,
-            flags,
-        })
+        Some(Version::from_parts(components, segments, flags))
     }
 
     /// Pops the specified number of segments from the version. Returns `None` if the resulting
@@ -436,8 +481,8 @@ impl Version {
     /// removed.
     pub fn strip_local(&self) -> Cow<'_, Version> {
         if self.has_local() {
-            let mut components = SmallVec::<[Component; 3]>::default();
-            let mut segments = SmallVec::<[Segment; 4]>::default();
+            let mut components = ComponentVec::default();
+            let mut segments = SegmentVec::default();
             let mut flags = Flags::default();
 
             // Add the epoch
@@ -454,11 +499,7 @@ impl Version {
                 }
             }
 
-            Cow::Owned(Version {
-                components,
-                segments,
-                flags,
-            })
+            Cow::Owned(Version::from_parts(components, segments, flags))
         } else {
             Cow::Borrowed(self)
         }
@@ -502,11 +543,11 @@ impl Version {
             self.flags
         };
 
-        Ok(Cow::Owned(Version {
-            components: components.into(),
-            segments: segments.into(),
+        Ok(Cow::Owned(Version::from_parts(
+            components.into(),
+            segments.into(),
             flags,
-        }))
+        )))
     }
 }
 
@@ -571,7 +612,10 @@ impl PartialEq<Self> for Version {
             true
         }
 
-        self.epoch() == other.epoch()
+        // Equal versions always have the same hash, comparing the hashes first quickly rules out
+        // most versions that are not equal.
+        self.hash == other.hash
+            && self.epoch() == other.epoch()
             && segments_equal(self.segments(), other.segments())
             && segments_equal(self.local_segments(), other.local_segments())
     }
@@ -579,6 +623,13 @@ impl PartialEq<Self> for Version {
 
 impl Hash for Version {
     fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u32(self.hash);
+    }
+}
+
+impl Version {
+    /// Computes the hash that is cached in [`Version::hash`].
+    fn compute_hash(&self) -> u32 {
         fn hash_segments<'i, I: Iterator<Item = SegmentIter<'i>>, H: Hasher>(
             state: &mut H,
             segments: I,
@@ -596,9 +647,12 @@ impl Hash for Version {
             }
         }
 
-        self.epoch().hash(state);
-        hash_segments(state, self.segments());
-        hash_segments(state, self.local_segments());
+        // The default hasher uses fixed keys, so equal versions always get the same hash.
+        let mut state = DefaultHasher::new();
+        self.epoch().hash(&mut state);
+        hash_segments(&mut state, self.segments());
+        hash_segments(&mut state, self.local_segments());
+        state.finish() as u32
     }
 }
 
@@ -693,7 +747,7 @@ pub enum Component {
 
     /// A generic string identifier. Identifiers are compared lexicographically. They are always
     /// ordered less than numbers.
-    Iden(Box<str>),
+    Iden(Identifier),
 
     /// An underscore or dash.
     UnderscoreOrDash {
@@ -727,10 +781,11 @@ impl Component {
         }
     }
 
-    /// Returns a component as mutable iden value
+    /// Returns a component as mutable iden value. Identifiers can be shared
+    /// between versions, the identifier is copied if it is shared.
     pub fn as_iden_mut(&mut self) -> Option<&mut Box<str>> {
         match self {
-            Component::Iden(value) => Some(value),
+            Component::Iden(value) => Some(value.make_mut()),
             _ => None,
         }
     }
@@ -739,7 +794,7 @@ impl Component {
     #[allow(dead_code)]
     pub fn as_string(&self) -> Option<&str> {
         match self {
-            Component::Iden(value) => Some(value.as_ref()),
+            Component::Iden(value) => Some(value.as_str()),
             _ => None,
         }
     }
@@ -775,7 +830,7 @@ impl From<u64> for Component {
 
 impl From<String> for Component {
     fn from(other: String) -> Self {
-        Component::Iden(other.into_boxed_str())
+        Component::Iden(other.into())
     }
 }
 
@@ -854,35 +909,65 @@ impl Debug for Component {
 
 impl Ord for Version {
     fn cmp(&self, other: &Self) -> Ordering {
-        fn cmp_segments<'i, I: Iterator<Item = SegmentIter<'i>>>(a: I, b: I) -> Ordering {
-            for ranges in a.zip_longest(b) {
-                let (a_range, b_range) = ranges.map_any(Some, Some).or_default();
-                for components in a_range
-                    .iter()
-                    .flat_map(SegmentIter::components)
-                    .zip_longest(b_range.iter().flat_map(SegmentIter::components))
-                {
-                    let default = Component::default();
-                    let (a_component, b_component) = match components {
-                        EitherOrBoth::Left(l) => (l, &default),
-                        EitherOrBoth::Right(r) => (&default, r),
-                        EitherOrBoth::Both(l, r) => (l, r),
-                    };
-                    match a_component.cmp(b_component) {
-                        Ordering::Less => return Ordering::Less,
-                        Ordering::Equal => {}
-                        Ordering::Greater => return Ordering::Greater,
-                    }
+        if self.is_plain_numeric() && other.is_plain_numeric() {
+            cmp_plain_numeric(self, other)
+        } else {
+            cmp_segmented(self, other)
+        }
+    }
+}
+
+/// Compares two versions for which [`Version::is_plain_numeric`] holds. Every
+/// segment consists of a single number so the versions can be compared number
+/// by number, padding the shorter version with zeros.
+fn cmp_plain_numeric(a: &Version, b: &Version) -> Ordering {
+    let number = |component: &Component| match component {
+        Component::Numeral(number) => *number,
+        _ => unreachable!("a plain numeric version only contains numbers"),
+    };
+    for components in a.components.iter().zip_longest(b.components.iter()) {
+        let ordering = match components {
+            EitherOrBoth::Both(a, b) => number(a).cmp(&number(b)),
+            EitherOrBoth::Left(a) => number(a).cmp(&0),
+            EitherOrBoth::Right(b) => 0.cmp(&number(b)),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Compares two versions segment by segment.
+fn cmp_segmented(a: &Version, b: &Version) -> Ordering {
+    fn cmp_segments<'i, I: Iterator<Item = SegmentIter<'i>>>(a: I, b: I) -> Ordering {
+        for ranges in a.zip_longest(b) {
+            let (a_range, b_range) = ranges.map_any(Some, Some).or_default();
+            for components in a_range
+                .iter()
+                .flat_map(SegmentIter::components)
+                .zip_longest(b_range.iter().flat_map(SegmentIter::components))
+            {
+                let default = Component::default();
+                let (a_component, b_component) = match components {
+                    EitherOrBoth::Left(l) => (l, &default),
+                    EitherOrBoth::Right(r) => (&default, r),
+                    EitherOrBoth::Both(l, r) => (l, r),
+                };
+                match a_component.cmp(b_component) {
+                    Ordering::Less => return Ordering::Less,
+                    Ordering::Equal => {}
+                    Ordering::Greater => return Ordering::Greater,
                 }
             }
-            Ordering::Equal
         }
-
-        self.epoch()
-            .cmp(&other.epoch())
-            .then_with(|| cmp_segments(self.segments(), other.segments()))
-            .then_with(|| cmp_segments(self.local_segments(), other.local_segments()))
+        Ordering::Equal
     }
+
+    a.epoch()
+        .cmp(&b.epoch())
+        .then_with(|| cmp_segments(a.segments(), b.segments()))
+        .then_with(|| cmp_segments(a.local_segments(), b.local_segments()))
 }
 
 impl PartialOrd for Version {
@@ -1029,6 +1114,7 @@ mod test {
     use std::cmp::Ordering;
     use std::str::FromStr;
 
+    use std::collections::hash_map::DefaultHasher;
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
 
@@ -1037,7 +1123,83 @@ mod test {
 
     use crate::version::StrictVersion;
 
-    use super::Version;
+    use super::{Component, Version};
+
+    #[test]
+    fn test_plain_numeric_ordering() {
+        let versions = [
+            "0",
+            "0.0",
+            "0.1",
+            "1",
+            "1.0",
+            "1.0.0",
+            "1.0.1",
+            "1.1",
+            "1.2.3",
+            "1.10",
+            "2",
+            "10",
+            "2024.2.2",
+            "10.0.22621.0",
+            "1_2",
+            "1-2",
+        ]
+        .map(|version| Version::from_str(version).unwrap());
+        for a in &versions {
+            assert!(a.is_plain_numeric());
+            for b in &versions {
+                assert_eq!(
+                    super::cmp_plain_numeric(a, b),
+                    super::cmp_segmented(a, b),
+                    "{a} <=> {b}"
+                );
+            }
+        }
+
+        for version in ["1!1.0", "1.0+2", "1.0a", "1.0.post1", "1.0.dev0", "2.a"] {
+            assert!(!Version::from_str(version).unwrap().is_plain_numeric());
+        }
+
+        // The flag is also computed for versions that are derived from other versions.
+        let version = Version::from_str("1.2.3").unwrap();
+        assert!(!version.with_alpha().is_plain_numeric());
+        assert!(Version::from_str("1.2+3")
+            .unwrap()
+            .remove_local()
+            .is_plain_numeric());
+        assert!(Version::major(3).is_plain_numeric());
+    }
+
+    #[test]
+    fn test_cached_hash() {
+        for (a, b) in [
+            ("1.0", "1"),
+            ("1.2.0.0", "1.2"),
+            ("1.1.a1", "1.1.0a1"),
+            ("0!1.0+1", "1+1.0"),
+        ] {
+            let a = Version::from_str(a).unwrap();
+            let b = Version::from_str(b).unwrap();
+            assert_eq!(a, b);
+            assert_eq!(a.hash, b.hash, "{a} and {b} should have the same hash");
+        }
+        assert_ne!(
+            Version::from_str("1.2.3").unwrap(),
+            Version::from_str("1.2.4").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_memory_footprint() {
+        assert_eq!(std::mem::size_of::<Component>(), 16);
+
+        // Versions with up to 3 components and 8 segments are stored without heap allocations.
+        let version = Version::from_str("1.2.3").unwrap();
+        assert!(!version.components.spilled());
+        let version = Version::from_str("1!1.0b2.post345.dev456+3.2.20.rc3").unwrap();
+        assert!(!version.segments.spilled());
+    }
 
     // Tests are inspired by: https://github.com/conda/conda/blob/33a142c16530fcdada6c377486f1c1a385738a96/tests/models/test_version.py
 
@@ -1280,7 +1442,7 @@ mod test {
 
     #[test]
     fn size_of_version() {
-        assert_eq!(std::mem::size_of::<Version>(), 112);
+        assert_eq!(std::mem::size_of::<Version>(), 88);
     }
 
     #[test]
@@ -11,6 +11,7 @@ use nom::sequence::terminated;
 use nom::IResult;
 use smallvec::SmallVec;
 use std::{
+    cell::RefCell,
     convert::Into,
     default::Default,
     error::Error,
@@ -137,7 +138,7 @@ fn component_parser<'i>(input: &'i str) -> IResult<&'i str, Component, ParseVers
         value(Component::Dev, tag_no_case("dev")),
         // Parse an identifier
         map(alpha1, |alpha: &'i str| {
-            Component::Iden(alpha.to_lowercase().into_boxed_str())
+            Component::Iden(alpha.to_lowercase().into())
         }),
     ))(input)
 }
@@ -410,22 +411,37 @@ pub fn version_parser(input: &str) -> IResult<&str, Version, ParseVersionErrorKi
         rest
     };
 
-    Ok((
-        rest,
-        Version {
-            components,
-            segments,
-            flags,
-        },
-    ))
+    Ok((rest, Version::from_parts(components, segments, flags)))
+}
+
+thread_local! {
+    /// The version that was most recently parsed on this thread together with its source.
+    ///
+    /// The records in repodata are sorted by filename, so consecutive records often have the same
+    /// version. Reusing the previously parsed version avoids parsing the same string over and
+    /// over again and lets the versions share their identifiers.
+    static LAST_PARSED_VERSION: RefCell<Option<(Box<str>, Version)>> = const { RefCell::new(None) };
 }
 
 impl FromStr for Version {
     type Err = ParseVersionError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let interned = LAST_PARSED_VERSION.with(|last| match &*last.borrow() {
+            Some((source, version)) if source.as_ref() == s => Some(version.clone()),
+            _ => None,
+        });
+        if let Some(version) = interned {
+            return Ok(version);
+        }
+
         match version_parser(s) {
-            Ok(("", version)) => Ok(version),
+            Ok(("", version)) => {
+                LAST_PARSED_VERSION.with(|last| {
+                    *last.borrow_mut() = Some((s.into(), version.clone()));
+                });
+                Ok(version)
+            }
             Ok(_) => Err(ParseVersionError::new(
                 s,
                 ParseVersionErrorKind::ExpectedEof,
@@ -455,6 +471,17 @@ mod test {
     use std::path::Path;
     use std::str::FromStr;
 
+    #[test]
+    fn test_parse_repeated() {
+        // The second parse reuses the previously parsed version.
+        let a = Version::from_str("1.0foo").unwrap();
+        let b = Version::from_str("1.0foo").unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.to_string(), b.to_string());
+        assert_eq!(Version::from_str("1.0bar").unwrap().to_string(), "1.0bar");
+        assert!(Version::from_str("1.0foo!").is_err());
+    }
+
     #[test]
     fn test_parse_star() {
         assert_eq!(
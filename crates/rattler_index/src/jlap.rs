@@ -0,0 +1,97 @@
+//! Writing of `repodata.jlap` files.
+//!
+//! A JLAP file contains a chain of JSON patches that can be used by clients to incrementally
+//! update a cached `repodata.json`. See the
+//! [CEP](https://github.com/conda-incubator/ceps/pull/20/files) for more information.
+//!
+//! The first line of the file contains an initialization vector, followed by one line per patch,
+//! a footer that references the latest `repodata.json` and finally a checksum. The checksum is
+//! computed by hashing every line with the hash of the previous line as the key, starting with the
+//! initialization vector.
+
+use std::path::Path;
+
+use rattler_digest::{
+    digest::{FixedOutput, Update},
+    parse_digest_from_hex, Blake2b256, Blake2b256Hash, Blake2bMac256,
+};
+use serde_json::{json, Value};
+
+/// The name of the JLAP file next to the `repodata.json`.
+pub(crate) const JLAP_FILE_NAME: &str = "repodata.jlap";
+
+/// The initialization vector of a new JLAP file.
+const INITIAL_IV: [u8; 32] = [0; 32];
+
+/// Appends the patch between `previous` and `current` to the JLAP file at `path`, or creates a new
+/// file if it does not exist yet.
+///
+/// `previous` contains the bytes of the previous `repodata.json`, if any. The bytes are hashed as
+/// is because that is what clients will see when downloading the file.
+pub(crate) fn update_jlap(
+    path: &Path,
+    previous: Option<&[u8]>,
+    current: &[u8],
+) -> Result<(), std::io::Error> {
+    let current_hash = rattler_digest::compute_bytes_digest::<Blake2b256>(current);
+
+    // Strip the footer and checksum from the existing file.
+    let mut lines = match fs_err::read_to_string(path) {
+        Ok(contents) => {
+            let mut lines = contents
+                .lines()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            lines.truncate(lines.len().saturating_sub(2));
+            lines
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    if lines.is_empty() {
+        lines.push(hex::encode(INITIAL_IV));
+    }
+
+    if let Some(previous) = previous {
+        let previous_hash = rattler_digest::compute_bytes_digest::<Blake2b256>(previous);
+        if previous_hash != current_hash {
+            let previous_value: Value = serde_json::from_slice(previous)?;
+            let current_value: Value = serde_json::from_slice(current)?;
+            let patch = json_patch::diff(&previous_value, &current_value);
+            lines.push(serde_json::to_string(&json!({
+                "to": format!("{current_hash:x}"),
+                "from": format!("{previous_hash:x}"),
+                "patch": patch,
+            }))?);
+        }
+    }
+
+    lines.push(serde_json::to_string(&json!({
+        "url": "repodata.json",
+        "latest": format!("{current_hash:x}"),
+    }))?);
+
+    let checksum = checksum(&lines).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} has an invalid initialization vector", path.display()),
+        )
+    })?;
+    lines.push(format!("{checksum:x}"));
+
+    fs_err::write(path, lines.join("\n"))
+}
+
+/// Computes the checksum of the lines of a JLAP file. The first line must be the initialization
+/// vector. Returns `None` if the initialization vector is invalid.
+fn checksum(lines: &[String]) -> Option<Blake2b256Hash> {
+    let (iv, lines) = lines.split_first()?;
+    let mut hash = parse_digest_from_hex::<Blake2b256>(iv)?;
+    for line in lines {
+        let mut state = Blake2bMac256::new_with_salt_and_personal(hash.as_slice(), &[], &[])
+            .expect("a blake2b256 hash is a valid key");
+        state.update(line.as_bytes());
+        hash = state.finalize_fixed();
+    }
+    Some(hash)
+}
@@ -2,20 +2,28 @@
 #![deny(missing_docs)]
 
 use rattler_conda_types::{
-    package::ArchiveType, package::IndexJson, package::PackageFile, ChannelInfo, PackageRecord,
-    Platform, RepoData,
+    package::ArchiveType, package::IndexJson, package::PackageFile, ChannelInfo, MatchSpec,
+    Matches, PackageName, PackageRecord, ParseStrictness, PatchInstructions, Platform, RepoData,
+    RepoDataPatch,
 };
 use rattler_package_streaming::{read, seek};
+use rayon::prelude::*;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     ffi::OsStr,
     io::{Read, Write},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
 use fs_err::File;
 use walkdir::WalkDir;
 
+mod jlap;
+
 /// Extract the package record from an `index.json` file.
 pub fn package_record_from_index_json<T: Read>(
     file: &Path,
@@ -27,30 +35,24 @@ pub fn package_record_from_index_json<T: Read>(
     let md5_result = rattler_digest::compute_file_digest::<rattler_digest::Md5>(file)?;
     let size = std::fs::metadata(file)?.len();
 
-    let package_record = PackageRecord {
-        name: index.name,
-        version: index.version,
-        build: index.build,
-        build_number: index.build_number,
-        subdir: index.subdir.unwrap_or_else(|| "unknown".to_string()),
-        md5: Some(md5_result),
-        sha256: Some(sha256_result),
-        size: Some(size),
-        arch: index.arch,
-        platform: index.platform,
-        depends: index.depends,
-        constrains: index.constrains,
-        track_features: index.track_features,
-        features: index.features,
-        noarch: index.noarch,
-        license: index.license,
-        license_family: index.license_family,
-        timestamp: index.timestamp,
-        legacy_bz2_md5: None,
-        legacy_bz2_size: None,
-        purls: None,
-        run_exports: None,
-    };
+    let mut package_record = PackageRecord::new(index.name, index.version, index.build);
+    package_record.build_number = index.build_number;
+    package_record.subdir = index.subdir.unwrap_or_else(|| "unknown".to_string());
+    package_record.md5 = Some(md5_result);
+    package_record.sha256 = Some(sha256_result);
+    package_record.size = Some(size);
+    package_record.arch = index.arch;
+    package_record.platform = index.platform;
+    package_record.depends = index.depends;
+    package_record.extra_depends = index.extra_depends;
+    package_record.constrains = index.constrains;
+    package_record.track_features = index.track_features;
+    package_record.features = index.features;
+    package_record.noarch = index.noarch;
+    package_record.license = index.license;
+    package_record.license_family = index.license_family;
+    package_record.timestamp = index.timestamp;
+    package_record.python_site_packages_path = index.python_site_packages_path;
 
     Ok(package_record)
 }
@@ -94,13 +96,275 @@ pub fn package_record_from_conda(file: &Path) -> Result<PackageRecord, std::io::
     ))
 }
 
+/// Extract the package record from a package file based on its archive type.
+fn package_record_from_archive(
+    file: &Path,
+    archive_type: ArchiveType,
+) -> Result<PackageRecord, std::io::Error> {
+    match archive_type {
+        ArchiveType::TarBz2 => package_record_from_tar_bz2(file),
+        ArchiveType::Conda => package_record_from_conda(file),
+    }
+}
+
+/// A record in the repodata together with its filename.
+struct RepoDataEntry<'a> {
+    file_name: &'a str,
+    record: &'a PackageRecord,
+}
+
+/// Returns the entries with the highest version.
+fn newest_entries<'a, 'b>(
+    entries: impl Iterator<Item = &'b RepoDataEntry<'a>>,
+) -> Vec<&'b RepoDataEntry<'a>> {
+    let mut newest: Vec<&RepoDataEntry<'a>> = Vec::new();
+    for entry in entries {
+        match newest
+            .first()
+            .map(|n| entry.record.version.cmp(&n.record.version))
+        {
+            None | Some(std::cmp::Ordering::Equal) => newest.push(entry),
+            Some(std::cmp::Ordering::Greater) => newest = vec![entry],
+            Some(std::cmp::Ordering::Less) => {}
+        }
+    }
+    newest
+}
+
+/// Creates the content of a `current_repodata.json` file from the full repodata of a subdir.
+///
+/// Similar to `conda-index`, the current repodata only contains the newest version of every
+/// package (including all builds of that version). For every spec in `pins` the newest version
+/// that matches the spec is retained as well, e.g. to keep packages for older python versions
+/// available. Finally, the newest versions of dependencies that are not satisfied by the retained
+/// packages are added until all dependencies that can be satisfied from this subdir are.
+pub fn current_repodata(repodata: &RepoData, pins: &[MatchSpec]) -> RepoData {
+    // Group all records by name.
+    let mut entries_by_name: BTreeMap<&PackageName, Vec<RepoDataEntry<'_>>> = BTreeMap::new();
+    for (file_name, record) in repodata
+        .packages
+        .iter()
+        .chain(repodata.conda_packages.iter())
+    {
+        entries_by_name
+            .entry(&record.name)
+            .or_default()
+            .push(RepoDataEntry { file_name, record });
+    }
+
+    // Select the newest version of all packages and of all pins.
+    let mut selected = HashSet::new();
+    let mut queue = Vec::new();
+    for entries in entries_by_name.values() {
+        queue.extend(newest_entries(entries.iter()));
+    }
+    for pin in pins {
+        let Some(entries) = pin.name.as_ref().and_then(|name| entries_by_name.get(name)) else {
+            continue;
+        };
+        queue.extend(newest_entries(
+            entries.iter().filter(|entry| pin.matches(entry.record)),
+        ));
+    }
+    queue.retain(|entry| selected.insert(entry.file_name));
+
+    // Add the dependencies that are not satisfied by any of the selected records.
+    while let Some(entry) = queue.pop() {
+        for dependency in &entry.record.depends {
+            let Ok(spec) = MatchSpec::from_str(dependency, ParseStrictness::Lenient) else {
+                continue;
+            };
+            let Some(candidates) = spec
+                .name
+                .as_ref()
+                .and_then(|name| entries_by_name.get(name))
+            else {
+                continue;
+            };
+
+            if candidates
+                .iter()
+                .any(|c| selected.contains(c.file_name) && spec.matches(c.record))
+            {
+                continue;
+            }
+
+            for candidate in newest_entries(candidates.iter().filter(|c| spec.matches(c.record))) {
+                if selected.insert(candidate.file_name) {
+                    queue.push(candidate);
+                }
+            }
+        }
+    }
+
+    let retain_selected = |packages: &HashMap<String, PackageRecord, _>| {
+        packages
+            .iter()
+            .filter(|(file_name, _)| selected.contains(file_name.as_str()))
+            .map(|(file_name, record)| (file_name.clone(), record.clone()))
+            .collect()
+    };
+
+    RepoData {
+        info: repodata.info.clone(),
+        packages: retain_selected(&repodata.packages),
+        conda_packages: retain_selected(&repodata.conda_packages),
+        removed: repodata.removed.clone(),
+        version: repodata.version,
+    }
+}
+
+/// The name of the file that contains the repodata of a subdir before any patches are applied.
+pub const REPODATA_FROM_PACKAGES_FILE_NAME: &str = "repodata_from_packages.json";
+
+/// The name of the file in a subdir that stores the patches that were applied to its
+/// `repodata.json`. They are reapplied when a single package is added or removed.
+pub const PATCH_INSTRUCTIONS_FILE_NAME: &str = "patch_instructions.json";
+
 /// Create a new `repodata.json` for all packages in the given output folder. If `target_platform` is
 /// `Some`, only that specific subdir is indexed. Otherwise indexes all subdirs and creates a
 /// `repodata.json` for each.
+///
+/// Like `conda-index`, the repodata of the packages is written to `repodata_from_packages.json`
+/// and the `repodata.json` contains the same repodata with the patches of
+/// [`IndexOptions::patch_instructions_dir`] applied. Next to the `repodata.json` a
+/// `current_repodata.json` is written that only contains the newest versions of the packages. See
+/// [`current_repodata`].
 pub fn index(
     output_folder: &Path,
     target_platform: Option<&Platform>,
 ) -> Result<(), std::io::Error> {
+    index_with_pins(output_folder, target_platform, &[])
+}
+
+/// Same as [`index`] but additionally retains the newest packages that match the given `pins` in
+/// the `current_repodata.json`.
+pub fn index_with_pins(
+    output_folder: &Path,
+    target_platform: Option<&Platform>,
+    pins: &[MatchSpec],
+) -> Result<(), std::io::Error> {
+    index_with_options(
+        output_folder,
+        target_platform,
+        &IndexOptions {
+            pins: pins.to_vec(),
+            ..IndexOptions::default()
+        },
+    )
+}
+
+/// A trait that receives progress events while a channel is indexed.
+///
+/// The packages of a subdir are read concurrently, the methods are therefore
+/// called from multiple threads.
+pub trait IndexReporter: Send + Sync {
+    /// Called before the packages of a subdir are read.
+    fn on_subdir_start(&self, _subdir: &str, _total_packages: usize) {}
+
+    /// Called after the record of a package has been read. `indexed` is the
+    /// number of packages of the subdir that have been read so far, including
+    /// this one.
+    fn on_package_indexed(
+        &self,
+        _subdir: &str,
+        _package_path: &Path,
+        _result: Result<&PackageRecord, &std::io::Error>,
+        _indexed: usize,
+        _total_packages: usize,
+    ) {
+    }
+
+    /// Called after the repodata of a subdir has been written.
+    fn on_subdir_complete(&self, _subdir: &str) {}
+}
+
+/// Options that control how a channel is indexed. See [`index_with_options`].
+#[derive(Clone, Default)]
+pub struct IndexOptions {
+    /// The newest packages that match any of these specs are retained in the
+    /// `current_repodata.json`.
+    pub pins: Vec<MatchSpec>,
+
+    /// Whether to write a `repodata.jlap` file next to the `repodata.json`. If the subdir was
+    /// indexed before, a JSON patch between the previous and the new `repodata.json` is appended
+    /// to the file so clients can incrementally update their cached repodata.
+    ///
+    /// An existing `repodata.jlap` is always kept up to date, regardless of this option.
+    pub write_jlap: bool,
+
+    /// A directory with repodata patches (hotfixes) that are applied to the `repodata.json`, e.g.
+    /// an extracted `conda-forge-repodata-patches` package. The patches of a subdir are read from
+    /// `<dir>/<subdir>/patch_instructions.json`, subdirs without such a file are not patched.
+    pub patch_instructions_dir: Option<PathBuf>,
+
+    /// The maximum number of packages that are read concurrently. Defaults to the number of
+    /// available CPUs.
+    pub concurrency: Option<usize>,
+
+    /// Receives the progress of the indexing.
+    pub reporter: Option<Arc<dyn IndexReporter>>,
+}
+
+impl std::fmt::Debug for IndexOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IndexOptions")
+            .field("pins", &self.pins)
+            .field("write_jlap", &self.write_jlap)
+            .field("patch_instructions_dir", &self.patch_instructions_dir)
+            .field("concurrency", &self.concurrency)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reads the records of the packages of a subdir on the thread pool. The records are returned in
+/// the same order as the packages.
+fn read_package_records(
+    pool: &rayon::ThreadPool,
+    subdir: &str,
+    packages: &[(&PathBuf, ArchiveType)],
+    reporter: Option<&dyn IndexReporter>,
+) -> Vec<Result<PackageRecord, std::io::Error>> {
+    if let Some(reporter) = reporter {
+        reporter.on_subdir_start(subdir, packages.len());
+    }
+
+    let indexed = AtomicUsize::new(0);
+    pool.install(|| {
+        packages
+            .par_iter()
+            .map(|(path, archive_type)| {
+                let record = package_record_from_archive(path, *archive_type);
+                if let Some(reporter) = reporter {
+                    reporter.on_package_indexed(
+                        subdir,
+                        path,
+                        record.as_ref(),
+                        indexed.fetch_add(1, Ordering::Relaxed) + 1,
+                        packages.len(),
+                    );
+                }
+                record
+            })
+            .collect()
+    })
+}
+
+/// Same as [`index`] but with additional [`IndexOptions`].
+pub fn index_with_options(
+    output_folder: &Path,
+    target_platform: Option<&Platform>,
+    options: &IndexOptions,
+) -> Result<(), std::io::Error> {
+    let patches = match &options.patch_instructions_dir {
+        Some(dir) => RepoDataPatch::from_package(dir)?,
+        None => RepoDataPatch::default(),
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.concurrency.unwrap_or(0))
+        .build()
+        .map_err(std::io::Error::other)?;
+
     let entries = WalkDir::new(output_folder).into_iter();
     let entries: Vec<(PathBuf, ArchiveType)> = entries
         .filter_entry(|e| e.depth() <= 2)
@@ -166,23 +430,26 @@ pub fn index(
             version: Some(2),
         };
 
-        for (p, t) in entries.iter().filter_map(|(p, t)| {
-            p.parent().and_then(|parent| {
-                parent.file_name().and_then(|file_name| {
-                    if file_name == OsStr::new(&platform) {
-                        // If the file_name is the platform we're looking for, return Some((p, t))
-                        Some((p, t))
-                    } else {
-                        // Otherwise, we return None to filter out this item
-                        None
-                    }
+        let packages = entries
+            .iter()
+            .filter_map(|(p, t)| {
+                p.parent().and_then(|parent| {
+                    parent.file_name().and_then(|file_name| {
+                        if file_name == OsStr::new(&platform) {
+                            // If the file_name is the platform we're looking for, return Some((p, t))
+                            Some((p, *t))
+                        } else {
+                            // Otherwise, we return None to filter out this item
+                            None
+                        }
+                    })
                 })
             })
-        }) {
-            let record = match t {
-                ArchiveType::TarBz2 => package_record_from_tar_bz2(p),
-                ArchiveType::Conda => package_record_from_conda(p),
-            };
+            .collect::<Vec<_>>();
+        let records =
+            read_package_records(&pool, &platform, &packages, options.reporter.as_deref());
+
+        for ((p, t), record) in packages.iter().zip(records) {
             let (Ok(record), Some(file_name)) = (record, p.file_name()) else {
                 tracing::info!("Could not read package record from {:?}", p);
                 continue;
@@ -196,11 +463,219 @@ pub fn index(
                     .insert(file_name.to_string_lossy().to_string(), record),
             };
         }
-        let out_file = output_folder.join(platform).join("repodata.json");
-        File::create(&out_file)?.write_all(serde_json::to_string_pretty(&repodata)?.as_bytes())?;
+        write_repodata(
+            &output_folder.join(&platform),
+            &repodata,
+            patches.subdirs.get(&platform),
+            options,
+        )?;
+        if let Some(reporter) = &options.reporter {
+            reporter.on_subdir_complete(&platform);
+        }
     }
 
     Ok(())
 }
 
+/// Writes the `repodata_from_packages.json`, `repodata.json` and `current_repodata.json` files of
+/// a subdir. The patches are only applied to the latter two and are stored in the
+/// [`PATCH_INSTRUCTIONS_FILE_NAME`] file of the subdir. If requested, or if the subdir already
+/// contains a `repodata.jlap`, the changes to the `repodata.json` are also appended to the
+/// `repodata.jlap`.
+fn write_repodata(
+    subdir_folder: &Path,
+    repodata_from_packages: &RepoData,
+    patches: Option<&PatchInstructions>,
+    options: &IndexOptions,
+) -> Result<(), std::io::Error> {
+    let out_file = subdir_folder.join(REPODATA_FROM_PACKAGES_FILE_NAME);
+    File::create(&out_file)?
+        .write_all(serde_json::to_string_pretty(repodata_from_packages)?.as_bytes())?;
+
+    let mut repodata = repodata_from_packages.clone();
+    let patches_file = subdir_folder.join(PATCH_INSTRUCTIONS_FILE_NAME);
+    if let Some(patches) = patches {
+        repodata.apply_patches(patches);
+        File::create(&patches_file)?
+            .write_all(serde_json::to_string_pretty(patches)?.as_bytes())?;
+    } else if patches_file.is_file() {
+        fs_err::remove_file(&patches_file)?;
+    }
+
+    let out_file = subdir_folder.join("repodata.json");
+    let jlap_file = subdir_folder.join(jlap::JLAP_FILE_NAME);
+    let contents = serde_json::to_string_pretty(&repodata)?;
+    if options.write_jlap || jlap_file.is_file() {
+        let previous = if out_file.is_file() {
+            Some(fs_err::read(&out_file)?)
+        } else {
+            None
+        };
+        jlap::update_jlap(&jlap_file, previous.as_deref(), contents.as_bytes())?;
+    }
+    File::create(&out_file)?.write_all(contents.as_bytes())?;
+
+    let current_repodata = current_repodata(&repodata, &options.pins);
+    let out_file = subdir_folder.join("current_repodata.json");
+    File::create(&out_file)?
+        .write_all(serde_json::to_string_pretty(&current_repodata)?.as_bytes())?;
+
+    Ok(())
+}
+
+/// Reads the unpatched repodata of a subdir or returns empty repodata if the subdir has not been
+/// indexed yet.
+///
+/// Subdirs that only contain a `repodata.json` are rejected, it might contain patches that would
+/// otherwise end up in the `repodata_from_packages.json`.
+fn read_repodata(subdir_folder: &Path, subdir: &str) -> Result<RepoData, std::io::Error> {
+    let repodata_path = subdir_folder.join(REPODATA_FROM_PACKAGES_FILE_NAME);
+    if repodata_path.is_file() {
+        return RepoData::from_path(repodata_path);
+    }
+    if subdir_folder.join("repodata.json").is_file() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "{} does not contain a {REPODATA_FROM_PACKAGES_FILE_NAME}, reindex the channel first",
+                subdir_folder.display()
+            ),
+        ));
+    }
+
+    Ok(RepoData {
+        info: Some(ChannelInfo {
+            subdir: subdir.to_string(),
+            base_url: None,
+        }),
+        packages: HashMap::default(),
+        conda_packages: HashMap::default(),
+        removed: HashSet::default(),
+        version: Some(2),
+    })
+}
+
+/// Reads the patches that are applied to the `repodata.json` of a subdir. These are the patches of
+/// [`IndexOptions::patch_instructions_dir`] if it is set, or otherwise the patches that were
+/// applied when the subdir was indexed, see [`PATCH_INSTRUCTIONS_FILE_NAME`].
+fn read_patch_instructions(
+    subdir_folder: &Path,
+    subdir: &str,
+    options: &IndexOptions,
+) -> Result<Option<PatchInstructions>, std::io::Error> {
+    if let Some(dir) = &options.patch_instructions_dir {
+        return Ok(RepoDataPatch::from_package(dir)?.subdirs.remove(subdir));
+    }
+
+    let path = subdir_folder.join(PATCH_INSTRUCTIONS_FILE_NAME);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let contents = fs_err::read_to_string(path)?;
+    Ok(Some(serde_json::from_str(&contents)?))
+}
+
+/// Adds a single package to the `repodata.json` of the channel in `channel_dir` without
+/// reindexing the entire channel.
+///
+/// The package is copied into the subdir of the channel that is specified in its `index.json` if
+/// it is not already located there. The `repodata.json` and `current_repodata.json` of that subdir
+/// are updated in place, or created if the subdir was not indexed before. An existing entry with
+/// the same filename is replaced.
+///
+/// The repodata is updated from the `repodata_from_packages.json` of the subdir and the patches
+/// that were applied when the subdir was indexed with [`index_with_options`] are reapplied, unless
+/// [`IndexOptions::patch_instructions_dir`] is set. The `current_repodata.json` is written with
+/// the [`IndexOptions::pins`], pass the same options that were used to index the channel to keep
+/// the pinned packages.
+///
+/// Returns the record that was added to the repodata.
+pub fn add_package_to_repodata(
+    channel_dir: &Path,
+    package_path: &Path,
+    options: &IndexOptions,
+) -> Result<PackageRecord, std::io::Error> {
+    let (archive_type, file_name) = ArchiveType::try_from(package_path)
+        .zip(package_path.file_name())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not a conda package", package_path.display()),
+            )
+        })?;
+    let file_name = file_name.to_string_lossy().to_string();
+
+    let record = package_record_from_archive(package_path, archive_type)?;
+    if record.subdir == "unknown" {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "could not determine the subdir of {}",
+                package_path.display()
+            ),
+        ));
+    }
+
+    let subdir_folder = channel_dir.join(&record.subdir);
+    fs_err::create_dir_all(&subdir_folder)?;
+    let destination = subdir_folder.join(&file_name);
+    if !destination.exists() || !same_file(package_path, &destination)? {
+        fs_err::copy(package_path, &destination)?;
+    }
+
+    let mut repodata = read_repodata(&subdir_folder, &record.subdir)?;
+    let packages = match archive_type {
+        ArchiveType::TarBz2 => &mut repodata.packages,
+        ArchiveType::Conda => &mut repodata.conda_packages,
+    };
+    packages.insert(file_name.clone(), record.clone());
+    repodata.removed.remove(&file_name);
+
+    let patches = read_patch_instructions(&subdir_folder, &record.subdir, options)?;
+    write_repodata(&subdir_folder, &repodata, patches.as_ref(), options)?;
+
+    Ok(record)
+}
+
+/// Removes a single package from the `repodata.json` of the given subdir of the channel in
+/// `channel_dir` without reindexing the entire channel.
+///
+/// The package archive is deleted from the subdir if it exists, and the repodata files of the
+/// subdir are updated in place. Like [`add_package_to_repodata`], the patches of the subdir are
+/// reapplied and the `current_repodata.json` is written with the pins of the `options`.
+///
+/// Returns the record that was removed or `None` if the repodata did not contain the package.
+pub fn remove_package_from_repodata(
+    channel_dir: &Path,
+    platform: &Platform,
+    file_name: &str,
+    options: &IndexOptions,
+) -> Result<Option<PackageRecord>, std::io::Error> {
+    let subdir_folder = channel_dir.join(platform.as_str());
+    let mut repodata = read_repodata(&subdir_folder, platform.as_str())?;
+
+    let removed = match ArchiveType::try_from(file_name) {
+        Some(ArchiveType::TarBz2) => repodata.packages.remove(file_name),
+        Some(ArchiveType::Conda) => repodata.conda_packages.remove(file_name),
+        None => None,
+    };
+
+    let package_path = subdir_folder.join(file_name);
+    if package_path.is_file() {
+        fs_err::remove_file(package_path)?;
+    }
+
+    if removed.is_some() {
+        let patches = read_patch_instructions(&subdir_folder, platform.as_str(), options)?;
+        write_repodata(&subdir_folder, &repodata, patches.as_ref(), options)?;
+    }
+
+    Ok(removed)
+}
+
+/// Returns true if both paths refer to the same file.
+fn same_file(a: &Path, b: &Path) -> Result<bool, std::io::Error> {
+    Ok(fs_err::canonicalize(a)? == fs_err::canonicalize(b)?)
+}
+
 // TODO: write proper unit tests for above functions
@@ -1,11 +1,26 @@
 use std::{
+    collections::{HashMap, HashSet},
     fs,
     fs::File,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 
-use rattler_conda_types::Platform;
-use rattler_index::index;
+use rattler_conda_types::{
+    MatchSpec, PackageName, PackageRecord, ParseStrictness, Platform, RepoData, Version,
+};
+use rattler_digest::{
+    digest::{FixedOutput, Update},
+    Blake2b256, Blake2bMac256,
+};
+use rattler_index::{
+    add_package_to_repodata, current_repodata, index, index_with_options,
+    remove_package_from_repodata, IndexOptions, IndexReporter, PATCH_INSTRUCTIONS_FILE_NAME,
+    REPODATA_FROM_PACKAGES_FILE_NAME,
+};
 use serde_json::Value;
 
 fn test_data_dir() -> PathBuf {
@@ -82,6 +97,70 @@ fn test_index() {
             .unwrap(),
         &expected_repodata_entry
     );
+
+    // The current repodata should only contain the newest version.
+    let current_repodata_path = temp_dir
+        .path()
+        .join(subdir_path)
+        .join("current_repodata.json");
+    let current_repodata: RepoData =
+        serde_json::from_reader(File::open(current_repodata_path).unwrap()).unwrap();
+    assert!(current_repodata.packages.is_empty());
+    assert_eq!(
+        current_repodata.conda_packages.keys().collect::<Vec<_>>(),
+        vec!["conda-22.11.1-py38haa244fe_1.conda"]
+    );
+}
+
+fn record(name: &str, version: &str, build: &str, depends: &[&str]) -> (String, PackageRecord) {
+    let mut record = PackageRecord::new(
+        PackageName::new_unchecked(name),
+        version.parse::<Version>().unwrap(),
+        build.to_string(),
+    );
+    record.depends = depends.iter().map(ToString::to_string).collect();
+    (format!("{name}-{version}-{build}.conda"), record)
+}
+
+#[test]
+fn test_current_repodata() {
+    let repodata = RepoData {
+        info: None,
+        packages: HashMap::default(),
+        conda_packages: [
+            record("a", "1.0", "0", &[]),
+            record("a", "2.0", "0", &["b <2"]),
+            record("a", "2.0", "1", &["b <2"]),
+            record("b", "1.0", "0", &[]),
+            record("b", "1.5", "0", &[]),
+            record("b", "2.0", "0", &["c"]),
+            record("c", "1.0", "0", &[]),
+            record("python", "3.11.0", "0", &[]),
+            record("python", "3.12.0", "0", &[]),
+        ]
+        .into_iter()
+        .collect(),
+        removed: HashSet::default(),
+        version: Some(2),
+    };
+
+    let pins = [MatchSpec::from_str("python 3.11.*", ParseStrictness::Strict).unwrap()];
+    let current = current_repodata(&repodata, &pins);
+
+    let mut file_names = current.conda_packages.keys().cloned().collect::<Vec<_>>();
+    file_names.sort();
+    assert_eq!(
+        file_names,
+        vec![
+            "a-2.0-0.conda",
+            "a-2.0-1.conda",
+            "b-1.5-0.conda",
+            "b-2.0-0.conda",
+            "c-1.0-0.conda",
+            "python-3.11.0-0.conda",
+            "python-3.12.0-0.conda",
+        ]
+    );
 }
 
 #[test]
@@ -91,3 +170,422 @@ fn test_index_empty_directory() {
     assert!(res.is_ok());
     assert_eq!(fs::read_dir(temp_dir).unwrap().count(), 0);
 }
+
+#[test]
+fn test_add_and_remove_package() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let conda_file_path = tools::download_and_cache_file(
+        "https://conda.anaconda.org/conda-forge/win-64/conda-22.11.1-py38haa244fe_1.conda"
+            .parse()
+            .unwrap(),
+        "a8a44c5ff2b2f423546d49721ba2e3e632233c74a813c944adf8e5742834930e",
+    )
+    .unwrap();
+    let tar_bz2_file_path = tools::download_and_cache_file(
+        "https://conda.anaconda.org/conda-forge/win-64/conda-22.9.0-py38haa244fe_2.tar.bz2"
+            .parse()
+            .unwrap(),
+        "3c2c2e8e81bde5fb1ac4b014f51a62411feff004580c708c97a0ec2b7058cdc4",
+    )
+    .unwrap();
+    let subdir_path = temp_dir.path().join("win-64");
+    let read_repodata = |file_name: &str| -> RepoData {
+        serde_json::from_reader(File::open(subdir_path.join(file_name)).unwrap()).unwrap()
+    };
+
+    // Adding packages copies them into the channel and updates the repodata.
+    let record =
+        add_package_to_repodata(temp_dir.path(), &conda_file_path, &IndexOptions::default())
+            .unwrap();
+    assert_eq!(record.subdir, "win-64");
+    add_package_to_repodata(
+        temp_dir.path(),
+        &tar_bz2_file_path,
+        &IndexOptions::default(),
+    )
+    .unwrap();
+    assert!(subdir_path
+        .join("conda-22.11.1-py38haa244fe_1.conda")
+        .is_file());
+
+    let repodata = read_repodata("repodata.json");
+    assert_eq!(repodata.info.unwrap().subdir, "win-64");
+    assert!(repodata
+        .conda_packages
+        .contains_key("conda-22.11.1-py38haa244fe_1.conda"));
+    assert!(repodata
+        .packages
+        .contains_key("conda-22.9.0-py38haa244fe_2.tar.bz2"));
+    assert!(read_repodata("current_repodata.json").packages.is_empty());
+
+    // Adding a package that is already part of the channel is a no-op.
+    add_package_to_repodata(
+        temp_dir.path(),
+        &subdir_path.join("conda-22.9.0-py38haa244fe_2.tar.bz2"),
+        &IndexOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(read_repodata("repodata.json").packages.len(), 1);
+
+    // Removing the newest package removes it from the repodata and the channel.
+    let removed = remove_package_from_repodata(
+        temp_dir.path(),
+        &Platform::Win64,
+        "conda-22.11.1-py38haa244fe_1.conda",
+        &IndexOptions::default(),
+    )
+    .unwrap();
+    assert!(removed.is_some());
+    assert!(!subdir_path
+        .join("conda-22.11.1-py38haa244fe_1.conda")
+        .exists());
+    assert!(read_repodata("repodata.json").conda_packages.is_empty());
+    assert_eq!(
+        read_repodata("current_repodata.json")
+            .packages
+            .keys()
+            .collect::<Vec<_>>(),
+        vec!["conda-22.9.0-py38haa244fe_2.tar.bz2"]
+    );
+
+    // Removing a package that is not part of the repodata returns nothing.
+    assert!(remove_package_from_repodata(
+        temp_dir.path(),
+        &Platform::Win64,
+        "conda-22.11.1-py38haa244fe_1.conda",
+        &IndexOptions::default(),
+    )
+    .unwrap()
+    .is_none());
+}
+
+#[test]
+fn test_add_and_remove_package_keeps_pins() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let conda_file_path = tools::download_and_cache_file(
+        "https://conda.anaconda.org/conda-forge/win-64/conda-22.11.1-py38haa244fe_1.conda"
+            .parse()
+            .unwrap(),
+        "a8a44c5ff2b2f423546d49721ba2e3e632233c74a813c944adf8e5742834930e",
+    )
+    .unwrap();
+    let tar_bz2_file_path = tools::download_and_cache_file(
+        "https://conda.anaconda.org/conda-forge/win-64/conda-22.9.0-py38haa244fe_2.tar.bz2"
+            .parse()
+            .unwrap(),
+        "3c2c2e8e81bde5fb1ac4b014f51a62411feff004580c708c97a0ec2b7058cdc4",
+    )
+    .unwrap();
+    let options = IndexOptions {
+        pins: vec![MatchSpec::from_str("conda 22.9.*", ParseStrictness::Strict).unwrap()],
+        ..IndexOptions::default()
+    };
+    let current_file_names = || {
+        let repodata: RepoData = serde_json::from_reader(
+            File::open(temp_dir.path().join("win-64/current_repodata.json")).unwrap(),
+        )
+        .unwrap();
+        let mut file_names = repodata
+            .packages
+            .into_keys()
+            .chain(repodata.conda_packages.into_keys())
+            .collect::<Vec<_>>();
+        file_names.sort();
+        file_names
+    };
+
+    // The pinned older version is retained next to the newest version.
+    let copy_dir = tempfile::tempdir().unwrap();
+    let copy_path = copy_dir.path().join("conda-22.11.1-copy.conda");
+    fs::copy(&conda_file_path, &copy_path).unwrap();
+    add_package_to_repodata(temp_dir.path(), &tar_bz2_file_path, &options).unwrap();
+    add_package_to_repodata(temp_dir.path(), &conda_file_path, &options).unwrap();
+    add_package_to_repodata(temp_dir.path(), &copy_path, &options).unwrap();
+    assert_eq!(
+        current_file_names(),
+        vec![
+            "conda-22.11.1-copy.conda",
+            "conda-22.11.1-py38haa244fe_1.conda",
+            "conda-22.9.0-py38haa244fe_2.tar.bz2"
+        ]
+    );
+
+    // Removing a package also keeps the pinned version.
+    remove_package_from_repodata(
+        temp_dir.path(),
+        &Platform::Win64,
+        "conda-22.11.1-copy.conda",
+        &options,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        current_file_names(),
+        vec![
+            "conda-22.11.1-py38haa244fe_1.conda",
+            "conda-22.9.0-py38haa244fe_2.tar.bz2"
+        ]
+    );
+}
+
+#[test]
+fn test_index_writes_jlap() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let conda_file_path = tools::download_and_cache_file(
+        "https://conda.anaconda.org/conda-forge/win-64/conda-22.11.1-py38haa244fe_1.conda"
+            .parse()
+            .unwrap(),
+        "a8a44c5ff2b2f423546d49721ba2e3e632233c74a813c944adf8e5742834930e",
+    )
+    .unwrap();
+    let subdir_path = temp_dir.path().join("win-64");
+    let options = IndexOptions {
+        write_jlap: true,
+        ..IndexOptions::default()
+    };
+
+    // The first index only records the latest repodata.json.
+    index_with_options(temp_dir.path(), Some(&Platform::Win64), &options).unwrap();
+    let initial = fs::read(subdir_path.join("repodata.json")).unwrap();
+
+    // Adding a package to the channel appends a patch.
+    fs::copy(
+        &conda_file_path,
+        subdir_path.join("conda-22.11.1-py38haa244fe_1.conda"),
+    )
+    .unwrap();
+    index_with_options(temp_dir.path(), Some(&Platform::Win64), &options).unwrap();
+    let updated = fs::read(subdir_path.join("repodata.json")).unwrap();
+
+    // Reindexing without changes does not add another patch.
+    index_with_options(temp_dir.path(), Some(&Platform::Win64), &options).unwrap();
+
+    let jlap = fs::read_to_string(subdir_path.join("repodata.jlap")).unwrap();
+    let lines = jlap.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "0".repeat(64));
+
+    let blake2b = |bytes: &[u8]| {
+        format!(
+            "{:x}",
+            rattler_digest::compute_bytes_digest::<Blake2b256>(bytes)
+        )
+    };
+    let patch: Value = serde_json::from_str(lines[1]).unwrap();
+    assert_eq!(patch["from"], blake2b(&initial));
+    assert_eq!(patch["to"], blake2b(&updated));
+    let mut repodata: Value = serde_json::from_slice(&initial).unwrap();
+    let operations: json_patch::Patch = serde_json::from_value(patch["patch"].clone()).unwrap();
+    json_patch::patch(&mut repodata, &operations).unwrap();
+    assert_eq!(repodata, serde_json::from_slice::<Value>(&updated).unwrap());
+
+    let footer: Value = serde_json::from_str(lines[2]).unwrap();
+    assert_eq!(footer["url"], "repodata.json");
+    assert_eq!(footer["latest"], blake2b(&updated));
+
+    // The checksum chains the keyed hashes of all lines.
+    let mut checksum = hex::decode(lines[0]).unwrap();
+    for line in &lines[1..3] {
+        let mut state = Blake2bMac256::new_with_salt_and_personal(&checksum, &[], &[]).unwrap();
+        state.update(line.as_bytes());
+        checksum = state.finalize_fixed().to_vec();
+    }
+    assert_eq!(lines[3], hex::encode(checksum));
+
+    // Removing a package keeps an existing jlap file in sync.
+    remove_package_from_repodata(
+        temp_dir.path(),
+        &Platform::Win64,
+        "conda-22.11.1-py38haa244fe_1.conda",
+        &IndexOptions::default(),
+    )
+    .unwrap();
+    let jlap = fs::read_to_string(subdir_path.join("repodata.jlap")).unwrap();
+    assert_eq!(jlap.lines().count(), 5);
+}
+
+#[test]
+fn test_index_applies_patches() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let conda_file_path = tools::download_and_cache_file(
+        "https://conda.anaconda.org/conda-forge/win-64/conda-22.11.1-py38haa244fe_1.conda"
+            .parse()
+            .unwrap(),
+        "a8a44c5ff2b2f423546d49721ba2e3e632233c74a813c944adf8e5742834930e",
+    )
+    .unwrap();
+    let tar_bz2_file_path = tools::download_and_cache_file(
+        "https://conda.anaconda.org/conda-forge/win-64/conda-22.9.0-py38haa244fe_2.tar.bz2"
+            .parse()
+            .unwrap(),
+        "3c2c2e8e81bde5fb1ac4b014f51a62411feff004580c708c97a0ec2b7058cdc4",
+    )
+    .unwrap();
+    let channel_dir = temp_dir.path().join("channel");
+    let subdir_path = channel_dir.join("win-64");
+    fs::create_dir_all(&subdir_path).unwrap();
+    for path in [&conda_file_path, &tar_bz2_file_path] {
+        fs::copy(path, subdir_path.join(path.file_name().unwrap())).unwrap();
+    }
+
+    let patches_dir = temp_dir.path().join("patches");
+    fs::create_dir_all(patches_dir.join("win-64")).unwrap();
+    fs::write(
+        patches_dir.join("win-64").join("patch_instructions.json"),
+        r#"{
+            "remove": ["conda-22.9.0-py38haa244fe_2.tar.bz2"],
+            "packages.conda": {
+                "conda-22.11.1-py38haa244fe_1.conda": { "depends": ["python >=3.8"] }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    let options = IndexOptions {
+        patch_instructions_dir: Some(patches_dir),
+        ..IndexOptions::default()
+    };
+    index_with_options(&channel_dir, Some(&Platform::Win64), &options).unwrap();
+
+    // The repodata of the packages is left untouched.
+    let from_packages =
+        RepoData::from_path(subdir_path.join(REPODATA_FROM_PACKAGES_FILE_NAME)).unwrap();
+    assert!(from_packages
+        .packages
+        .contains_key("conda-22.9.0-py38haa244fe_2.tar.bz2"));
+    assert_ne!(
+        from_packages.conda_packages["conda-22.11.1-py38haa244fe_1.conda"].depends,
+        vec!["python >=3.8"]
+    );
+
+    // The patches are applied to the repodata.json.
+    let repodata = RepoData::from_path(subdir_path.join("repodata.json")).unwrap();
+    assert!(repodata.packages.is_empty());
+    assert!(repodata
+        .removed
+        .contains("conda-22.9.0-py38haa244fe_2.tar.bz2"));
+    assert_eq!(
+        repodata.conda_packages["conda-22.11.1-py38haa244fe_1.conda"].depends,
+        vec!["python >=3.8"]
+    );
+
+    // The patches are reapplied when a single package is removed.
+    remove_package_from_repodata(
+        &channel_dir,
+        &Platform::Win64,
+        "conda-22.9.0-py38haa244fe_2.tar.bz2",
+        &IndexOptions::default(),
+    )
+    .unwrap()
+    .unwrap();
+    let repodata = RepoData::from_path(subdir_path.join("repodata.json")).unwrap();
+    assert_eq!(
+        repodata.conda_packages["conda-22.11.1-py38haa244fe_1.conda"].depends,
+        vec!["python >=3.8"]
+    );
+
+    // The patches are dropped when the channel is reindexed without them.
+    index(&channel_dir, Some(&Platform::Win64)).unwrap();
+    assert!(!subdir_path.join(PATCH_INSTRUCTIONS_FILE_NAME).exists());
+    let repodata = RepoData::from_path(subdir_path.join("repodata.json")).unwrap();
+    assert_ne!(
+        repodata.conda_packages["conda-22.11.1-py38haa244fe_1.conda"].depends,
+        vec!["python >=3.8"]
+    );
+}
+
+#[test]
+fn test_add_package_requires_unpatched_repodata() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let conda_file_path = tools::download_and_cache_file(
+        "https://conda.anaconda.org/conda-forge/win-64/conda-22.11.1-py38haa244fe_1.conda"
+            .parse()
+            .unwrap(),
+        "a8a44c5ff2b2f423546d49721ba2e3e632233c74a813c944adf8e5742834930e",
+    )
+    .unwrap();
+    let subdir_path = temp_dir.path().join("win-64");
+    fs::create_dir_all(&subdir_path).unwrap();
+    fs::write(subdir_path.join("repodata.json"), "{}").unwrap();
+
+    let err = add_package_to_repodata(temp_dir.path(), &conda_file_path, &IndexOptions::default())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[derive(Default)]
+struct CountingReporter {
+    events: Mutex<Vec<String>>,
+    indexed: AtomicUsize,
+}
+
+impl IndexReporter for CountingReporter {
+    fn on_subdir_start(&self, subdir: &str, total_packages: usize) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("start {subdir} {total_packages}"));
+    }
+
+    fn on_package_indexed(
+        &self,
+        _subdir: &str,
+        _package_path: &Path,
+        result: Result<&PackageRecord, &std::io::Error>,
+        _indexed: usize,
+        _total_packages: usize,
+    ) {
+        assert!(result.is_ok());
+        self.indexed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_subdir_complete(&self, subdir: &str) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("complete {subdir}"));
+    }
+}
+
+#[test]
+fn test_index_reports_progress() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let subdir_path = temp_dir.path().join("win-64");
+    fs::create_dir_all(&subdir_path).unwrap();
+    for (url, sha256) in [
+        (
+            "https://conda.anaconda.org/conda-forge/win-64/conda-22.11.1-py38haa244fe_1.conda",
+            "a8a44c5ff2b2f423546d49721ba2e3e632233c74a813c944adf8e5742834930e",
+        ),
+        (
+            "https://conda.anaconda.org/conda-forge/win-64/conda-22.9.0-py38haa244fe_2.tar.bz2",
+            "3c2c2e8e81bde5fb1ac4b014f51a62411feff004580c708c97a0ec2b7058cdc4",
+        ),
+    ] {
+        let path = tools::download_and_cache_file(url.parse().unwrap(), sha256).unwrap();
+        fs::copy(&path, subdir_path.join(path.file_name().unwrap())).unwrap();
+    }
+
+    let reporter = Arc::new(CountingReporter::default());
+    let options = IndexOptions {
+        concurrency: Some(2),
+        reporter: Some(reporter.clone()),
+        ..IndexOptions::default()
+    };
+    index_with_options(temp_dir.path(), Some(&Platform::Win64), &options).unwrap();
+
+    // The empty noarch subdir is indexed as well.
+    assert_eq!(reporter.indexed.load(Ordering::SeqCst), 2);
+    let events = reporter.events.lock().unwrap();
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| event.ends_with("win-64") || event.contains("win-64 "))
+            .collect::<Vec<_>>(),
+        vec!["start win-64 2", "complete win-64"]
+    );
+    assert!(events.contains(&String::from("start noarch 0")));
+
+    let repodata = RepoData::from_path(subdir_path.join("repodata.json")).unwrap();
+    assert_eq!(repodata.packages.len() + repodata.conda_packages.len(), 2);
+}
@@ -12,8 +12,8 @@ use rattler_conda_types::Platform;
 
 use crate::{
     file_format_version::FileFormatVersion, Channel, CondaPackageData, EnvironmentData,
-    EnvironmentPackageData, LockFile, LockFileInner, Package, PypiIndexes, PypiPackageData,
-    PypiPackageEnvironmentData,
+    EnvironmentPackageData, LockFile, LockFileInner, Package, Provenance, PypiIndexes,
+    PypiPackageData, PypiPackageEnvironmentData,
 };
 
 /// A struct to incrementally build a lock-file.
@@ -46,11 +46,31 @@ impl LockFileBuilder {
                 channels: vec![],
                 packages: FxHashMap::default(),
                 indexes: None,
+                provenance: None,
             })
             .indexes = Some(indexes);
         self
     }
 
+    /// Sets the provenance of an environment, e.g. the solver that was used
+    /// to solve it.
+    pub fn set_provenance(
+        &mut self,
+        environment: impl Into<String>,
+        provenance: Provenance,
+    ) -> &mut Self {
+        self.environments
+            .entry(environment.into())
+            .or_insert_with(|| EnvironmentData {
+                channels: vec![],
+                packages: FxHashMap::default(),
+                indexes: None,
+                provenance: None,
+            })
+            .provenance = Some(provenance);
+        self
+    }
+
     /// Sets the metadata for an environment.
     pub fn set_channels(
         &mut self,
@@ -63,6 +83,7 @@ impl LockFileBuilder {
                 channels: vec![],
                 packages: FxHashMap::default(),
                 indexes: None,
+                provenance: None,
             })
             .channels = channels.into_iter().map(Into::into).collect();
         self
@@ -87,6 +108,7 @@ impl LockFileBuilder {
                 channels: vec![],
                 packages: HashMap::default(),
                 indexes: None,
+                provenance: None,
             });
 
         // Add the package to the list of packages.
@@ -122,6 +144,7 @@ impl LockFileBuilder {
                 channels: vec![],
                 packages: HashMap::default(),
                 indexes: None,
+                provenance: None,
             });
 
         // Add the package to the list of packages.
@@ -225,6 +248,19 @@ impl LockFileBuilder {
         self
     }
 
+    /// Sets the provenance of an environment.
+    ///
+    /// This function is similar to [`Self::set_provenance`] but consumes
+    /// `self`.
+    pub fn with_provenance(
+        mut self,
+        environment: impl Into<String>,
+        provenance: Provenance,
+    ) -> Self {
+        self.set_provenance(environment, provenance);
+        self
+    }
+
     /// Build a [`LockFile`]
     pub fn finish(self) -> LockFile {
         let (environment_lookup, environments) = self
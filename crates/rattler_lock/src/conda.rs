@@ -1,4 +1,6 @@
-use rattler_conda_types::{PackageRecord, RepoDataRecord};
+use rattler_conda_types::{
+    Channel, ChannelConfig, MatchSpec, Matches, PackageRecord, RepoDataRecord,
+};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, skip_serializing_none};
 use std::cmp::Ordering;
@@ -66,6 +68,15 @@ impl CondaPackageData {
     }
 }
 
+impl Matches<CondaPackageData> for MatchSpec {
+    /// Match a [`MatchSpec`] against a locked conda package. This uses the
+    /// same semantics as matching against a [`RepoDataRecord`], see
+    /// [`MatchSpec::matches_package_at`].
+    fn matches(&self, other: &CondaPackageData) -> bool {
+        self.matches_package_at(&other.package_record, &other.url, other.file_name())
+    }
+}
+
 impl From<RepoDataRecord> for CondaPackageData {
     fn from(value: RepoDataRecord) -> Self {
         let derived_file_name = file_name_from_url(&value.url);
@@ -75,13 +86,22 @@ impl From<RepoDataRecord> for CondaPackageData {
             Some(value.file_name)
         };
 
+        // The channel of the record is the logical channel, either as a url or as the name of a
+        // channel on the default channel alias. Only store it if it cannot be derived from the
+        // url, e.g. when the package was downloaded from a mirror.
+        let derived_channel = channel_from_url(&value.url);
+        let channel = logical_channel_url(&value.channel).filter(|channel| {
+            derived_channel
+                .as_ref()
+                .map(|url| url.as_str().trim_end_matches('/'))
+                != Some(channel.as_str().trim_end_matches('/'))
+        });
+
         Self {
             package_record: value.package_record,
             url: value.url,
             file_name,
-            // TODO: This is not entirely correct. It should be derived from the `channel` field in
-            //  the repodata record.
-            channel: None,
+            channel,
         }
     }
 }
@@ -144,9 +164,105 @@ fn channel_from_url(url: &Url) -> Option<Url> {
     Some(result)
 }
 
+/// Returns the url of the channel of a [`RepoDataRecord`]. The channel is either stored as a url
+/// or as the name of a channel, which is resolved against the default channel alias.
+fn logical_channel_url(channel: &str) -> Option<Url> {
+    if channel.is_empty() {
+        return None;
+    }
+    if let Ok(url) = Url::parse(channel) {
+        return Some(url);
+    }
+    let channel_config = ChannelConfig::default_with_root_dir(std::path::PathBuf::new());
+    Channel::from_str(channel, &channel_config)
+        .ok()
+        .map(|channel| channel.base_url)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use rattler_conda_types::{PackageName, ParseStrictness, Version};
+
+    #[test]
+    fn test_matches() {
+        let mut package_record = PackageRecord::new(
+            PackageName::new_unchecked("python"),
+            Version::major(3),
+            "h4150a38_1_cpython".to_string(),
+        );
+        package_record.subdir = "osx-64".to_string();
+        let url = "https://conda.anaconda.org/conda-forge/osx-64/python-3-h4150a38_1_cpython.conda";
+        let package = CondaPackageData::from(RepoDataRecord {
+            package_record,
+            file_name: "python-3-h4150a38_1_cpython.conda".to_string(),
+            url: Url::parse(url).unwrap(),
+            channel: "https://conda.anaconda.org/conda-forge/".to_string(),
+        });
+
+        for (spec, expected) in [
+            ("python >=3", true),
+            ("python <3", false),
+            ("conda-forge::python", true),
+            ("bioconda::python", false),
+            ("conda-forge/linux-64::python", false),
+            ("python[fn=python-3-h4150a38_1_cpython.conda]", true),
+            (url, true),
+            (
+                "https://conda.anaconda.org/conda-forge/osx-64/python-3-h4150a38_0_cpython.conda",
+                false,
+            ),
+        ] {
+            let spec = MatchSpec::from_str(spec, ParseStrictness::Lenient).unwrap();
+            assert_eq!(spec.matches(&package), expected, "{spec}");
+        }
+    }
+
+    #[test]
+    fn test_channel_from_repodata_record() {
+        let record = |url: &str, channel: &str| RepoDataRecord {
+            package_record: PackageRecord::new(
+                PackageName::new_unchecked("foo"),
+                Version::major(1),
+                "0".to_string(),
+            ),
+            file_name: "foo-1-0.conda".to_string(),
+            url: Url::parse(url).unwrap(),
+            channel: channel.to_string(),
+        };
+
+        // The channel is derived from the url.
+        let package = CondaPackageData::from(record(
+            "https://conda.anaconda.org/conda-forge/noarch/foo-1-0.conda",
+            "https://conda.anaconda.org/conda-forge/",
+        ));
+        assert_eq!(package.channel, None);
+        assert_eq!(
+            package.channel().unwrap().as_str(),
+            "https://conda.anaconda.org/conda-forge"
+        );
+
+        // The package was downloaded from a mirror of the channel.
+        let package = CondaPackageData::from(record(
+            "https://mirror.example.com/conda-forge/noarch/foo-1-0.conda",
+            "https://conda.anaconda.org/conda-forge/",
+        ));
+        assert_eq!(
+            package.channel().unwrap().as_str(),
+            "https://conda.anaconda.org/conda-forge/"
+        );
+
+        // The channel of the record is a name instead of a url, the mirror should still not be
+        // used as the channel.
+        let package = CondaPackageData::from(record(
+            "https://mirror.example.com/conda-forge/noarch/foo-1-0.conda",
+            "conda-forge",
+        ));
+        assert_eq!(
+            package.channel().unwrap().as_str(),
+            "https://conda.anaconda.org/conda-forge/"
+        );
+    }
 
     #[test]
     fn test_channel_from_url() {
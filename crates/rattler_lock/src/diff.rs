@@ -0,0 +1,214 @@
+//! Computing the differences between two versions of a lock-file. See
+//! [`LockFileDiff`].
+
+use std::collections::BTreeMap;
+
+use indexmap::IndexMap;
+use itertools::Itertools;
+use rattler_conda_types::Platform;
+
+use crate::{Environment, LockFile, Package};
+
+/// The differences between two versions of a lock-file.
+///
+/// Packages are identified by their kind (conda or pypi) and their name. A
+/// package is considered changed if it is locked to a different url or path.
+#[derive(Clone, Default)]
+pub struct LockFileDiff {
+    /// The differences per environment, sorted by name. Environments that only
+    /// exist in one of the two lock-files are included as well.
+    pub environments: IndexMap<String, EnvironmentDiff>,
+}
+
+/// The differences between two versions of an [`Environment`].
+#[derive(Clone, Default)]
+pub struct EnvironmentDiff {
+    /// The differences per platform, sorted by platform name.
+    pub platforms: IndexMap<Platform, PlatformDiff>,
+}
+
+/// The differences between the packages of an environment for a single
+/// platform.
+#[derive(Clone, Default)]
+pub struct PlatformDiff {
+    /// Packages that are only present in the current version.
+    pub added: Vec<Package>,
+
+    /// Packages that are only present in the previous version.
+    pub removed: Vec<Package>,
+
+    /// Packages that are present in both versions but were locked differently.
+    /// The first element is the previous package, the second the current one.
+    pub changed: Vec<(Package, Package)>,
+}
+
+/// Identifies a package independent of the version it is locked to.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PackageKind {
+    Conda,
+    Pypi,
+}
+
+impl LockFileDiff {
+    /// Computes the differences between the `previous` and `current` version
+    /// of a lock-file. Environments and platforms without any changes are
+    /// omitted.
+    pub fn from_lock_files(previous: &LockFile, current: &LockFile) -> Self {
+        let names = previous
+            .environments()
+            .chain(current.environments())
+            .map(|(name, _)| name.to_string())
+            .sorted()
+            .dedup()
+            .collect::<Vec<_>>();
+
+        let environments = names
+            .into_iter()
+            .filter_map(|name| {
+                let diff = EnvironmentDiff::from_environments(
+                    previous.environment(&name).as_ref(),
+                    current.environment(&name).as_ref(),
+                );
+                (!diff.is_empty()).then_some((name, diff))
+            })
+            .collect();
+
+        Self { environments }
+    }
+
+    /// Returns true if there are no differences between the lock-files.
+    pub fn is_empty(&self) -> bool {
+        self.environments.values().all(EnvironmentDiff::is_empty)
+    }
+}
+
+impl EnvironmentDiff {
+    /// Computes the differences between two versions of an environment. If an
+    /// environment is `None` it is treated as an environment without any
+    /// packages.
+    pub fn from_environments(
+        previous: Option<&Environment>,
+        current: Option<&Environment>,
+    ) -> Self {
+        let platforms = previous
+            .into_iter()
+            .flat_map(Environment::platforms)
+            .chain(current.into_iter().flat_map(Environment::platforms))
+            .sorted_by_key(|platform| platform.as_str())
+            .dedup()
+            .collect::<Vec<_>>();
+
+        let platforms = platforms
+            .into_iter()
+            .filter_map(|platform| {
+                let diff = PlatformDiff::from_packages(
+                    previous
+                        .and_then(|env| env.packages(platform))
+                        .into_iter()
+                        .flatten(),
+                    current
+                        .and_then(|env| env.packages(platform))
+                        .into_iter()
+                        .flatten(),
+                );
+                (!diff.is_empty()).then_some((platform, diff))
+            })
+            .collect();
+
+        Self { platforms }
+    }
+
+    /// Returns true if there are no differences between the environments.
+    pub fn is_empty(&self) -> bool {
+        self.platforms.values().all(PlatformDiff::is_empty)
+    }
+}
+
+impl PlatformDiff {
+    /// Computes the differences between two sets of packages. The resulting
+    /// packages are sorted by name.
+    pub fn from_packages(
+        previous: impl IntoIterator<Item = Package>,
+        current: impl IntoIterator<Item = Package>,
+    ) -> Self {
+        let mut previous = previous
+            .into_iter()
+            .map(|package| (package_key(&package), package))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut diff = Self::default();
+        for package in current {
+            match previous.remove(&package_key(&package)) {
+                None => diff.added.push(package),
+                Some(previous) if previous.url_or_path() != package.url_or_path() => {
+                    diff.changed.push((previous, package));
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed.extend(previous.into_values());
+
+        diff.added.sort_by_key(package_key);
+        diff.changed
+            .sort_by_key(|(_, package)| package_key(package));
+        diff
+    }
+
+    /// Returns true if there are no differences between the packages.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Returns the key that identifies a package in a [`PlatformDiff`].
+fn package_key(package: &Package) -> (String, PackageKind) {
+    let kind = if package.is_conda() {
+        PackageKind::Conda
+    } else {
+        PackageKind::Pypi
+    };
+    (package.name().into_owned(), kind)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use rattler_conda_types::Platform;
+
+    use super::LockFileDiff;
+    use crate::{LockFile, DEFAULT_ENVIRONMENT_NAME};
+
+    fn lock_file(file_name: &str) -> LockFile {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock")
+            .join(file_name);
+        LockFile::from_path(&path).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let numpy = lock_file("v4/numpy-lock.yml");
+        let python = lock_file("v4/python-lock.yml");
+
+        assert!(LockFileDiff::from_lock_files(&numpy, &numpy).is_empty());
+
+        let diff = LockFileDiff::from_lock_files(&python, &numpy);
+        assert!(!diff.is_empty());
+        let linux = &diff.environments[DEFAULT_ENVIRONMENT_NAME].platforms[&Platform::Linux64];
+        assert!(linux.added.iter().any(|p| p.name() == "numpy"));
+        assert!(linux.removed.iter().all(|p| p.name() != "numpy"));
+        assert!(linux
+            .changed
+            .iter()
+            .any(|(previous, current)| previous.name() == "python" && current.name() == "python"));
+
+        // Reversing the order of the lock-files swaps added and removed packages.
+        let reversed = LockFileDiff::from_lock_files(&numpy, &python);
+        let reversed_linux =
+            &reversed.environments[DEFAULT_ENVIRONMENT_NAME].platforms[&Platform::Linux64];
+        assert_eq!(linux.added.len(), reversed_linux.removed.len());
+        assert_eq!(linux.removed.len(), reversed_linux.added.len());
+        assert_eq!(linux.changed.len(), reversed_linux.changed.len());
+    }
+}
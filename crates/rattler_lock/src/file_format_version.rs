@@ -23,6 +23,11 @@ pub enum FileFormatVersion {
 
     /// pypi indexes should be part of the file now.
     V5 = 5,
+
+    /// Conda packages can list the dependencies of their extras, pypi
+    /// packages can record the source they are built from and environments
+    /// can record their provenance.
+    V6 = 6,
 }
 
 impl Display for FileFormatVersion {
@@ -33,13 +38,26 @@ impl Display for FileFormatVersion {
 
 impl FileFormatVersion {
     /// The latest version this crate supports.
-    pub const LATEST: Self = FileFormatVersion::V5;
+    pub const LATEST: Self = FileFormatVersion::V6;
 
     /// Returns true if the pypi indexes should be present in the lock file if
     /// there are pypi packages present.
     pub fn should_pypi_indexes_be_present(self) -> bool {
         self >= FileFormatVersion::V5
     }
+
+    /// Returns the version that directly follows this version or `None` if
+    /// this is the latest version.
+    pub fn next(self) -> Option<Self> {
+        match self {
+            FileFormatVersion::V1 => Some(FileFormatVersion::V2),
+            FileFormatVersion::V2 => Some(FileFormatVersion::V3),
+            FileFormatVersion::V3 => Some(FileFormatVersion::V4),
+            FileFormatVersion::V4 => Some(FileFormatVersion::V5),
+            FileFormatVersion::V5 => Some(FileFormatVersion::V6),
+            FileFormatVersion::V6 => None,
+        }
+    }
 }
 
 impl Default for FileFormatVersion {
@@ -63,6 +81,7 @@ impl TryFrom<u64> for FileFormatVersion {
             3 => Self::V3,
             4 => Self::V4,
             5 => Self::V5,
+            6 => Self::V6,
             _ => {
                 return Err(ParseCondaLockError::IncompatibleVersion {
                     lock_file_version: value,
@@ -87,29 +87,39 @@ use std::{
 
 use fxhash::FxHashMap;
 use pep508_rs::{ExtraName, Requirement};
-use rattler_conda_types::{MatchSpec, PackageRecord, Platform, RepoDataRecord};
+use rattler_conda_types::{MatchSpec, Matches, PackageRecord, Platform, RepoDataRecord};
 use url::Url;
 
 mod builder;
 mod channel;
 mod conda;
+mod diff;
 mod file_format_version;
 mod hash;
 mod parse;
+mod provenance;
 mod pypi;
 mod pypi_indexes;
+mod python_interpreter;
+mod report;
 mod url_or_path;
 mod utils;
 
 pub use builder::LockFileBuilder;
 pub use channel::Channel;
 pub use conda::{CondaPackageData, ConversionError};
+pub use diff::{EnvironmentDiff, LockFileDiff, PlatformDiff};
 pub use file_format_version::FileFormatVersion;
 pub use hash::PackageHashes;
-pub use parse::ParseCondaLockError;
-pub use pypi::{PypiPackageData, PypiPackageEnvironmentData, PypiSourceTreeHashable};
+pub use parse::{Migration, MigrationReport, ParseCondaLockError, WriteLockFileError};
+pub use provenance::Provenance;
+pub use pypi::{
+    PypiPackageData, PypiPackageEnvironmentData, PypiPackageSource, PypiSourceTreeHashable,
+};
 pub use pypi_indexes::{FindLinksUrlOrPath, PypiIndexes};
+pub use python_interpreter::{PythonImplementation, PythonInterpreter};
 pub use rattler_conda_types::Matches;
+pub use report::{render_diff_report, render_environment_report, ReportFormat};
 pub use url_or_path::UrlOrPath;
 
 /// The name of the default environment in a [`LockFile`]. This is the
@@ -166,6 +176,9 @@ struct EnvironmentData {
     /// The pypi indexes used to solve the environment.
     indexes: Option<PypiIndexes>,
 
+    /// Information about how the environment was solved.
+    provenance: Option<Provenance>,
+
     /// For each individual platform this environment supports we store the
     /// package identifiers associated with the environment.
     packages: FxHashMap<Platform, Vec<EnvironmentPackageData>>,
@@ -191,11 +204,91 @@ impl LockFile {
         Self::from_str(&source)
     }
 
-    /// Writes the conda lock to a file
+    /// Writes the conda lock to a file.
+    ///
+    /// The output is deterministic: environments, platforms and packages are
+    /// always written in the same order. If the file already has exactly the
+    /// same content it is not touched.
+    ///
+    /// The lock-file is written in the oldest format version that can
+    /// represent its content, use [`LockFile::to_path_with_version`] to write
+    /// a specific version.
     pub fn to_path(&self, path: &Path) -> Result<(), std::io::Error> {
-        let file = std::fs::File::create(path)?;
-        serde_yaml::to_writer(file, self)
-            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+        let rendered = serde_yaml::to_string(self)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        if std::fs::read_to_string(path).is_ok_and(|existing| existing == rendered) {
+            return Ok(());
+        }
+        std::fs::write(path, rendered)
+    }
+
+    /// Writes the lock-file to a file while preserving as much as possible of
+    /// the lock-file that already exists at that location.
+    ///
+    /// If the existing file locks exactly the same content it is left
+    /// untouched, including its formatting and any comments. Otherwise the
+    /// lock-file is written in the format version of the existing file if it
+    /// can be represented in that version, or in the oldest version that can
+    /// represent it if it cannot. This avoids noisy diffs when re-locking.
+    ///
+    /// Returns `true` if the file was written.
+    pub fn to_path_preserving(&self, path: &Path) -> Result<bool, WriteLockFileError> {
+        let existing = match std::fs::read_to_string(path) {
+            Ok(existing) => Some(existing),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        let existing_lock_file = existing
+            .as_deref()
+            .and_then(|existing| Self::from_str(existing).ok());
+
+        let version = existing_lock_file
+            .as_ref()
+            .map(LockFile::version)
+            .filter(|version| self.check_writable_as(*version).is_ok())
+            .unwrap_or_else(|| self.default_write_version());
+        let rendered = self.render_to_string_with_version(version)?;
+
+        if let Some(existing_lock_file) = existing_lock_file {
+            if existing_lock_file
+                .render_to_string_with_version(version)
+                .is_ok_and(|existing| existing == rendered)
+            {
+                return Ok(false);
+            }
+        }
+
+        std::fs::write(path, rendered)?;
+        Ok(true)
+    }
+
+    /// Renders the lock-file as a string in the given format version.
+    ///
+    /// Returns an error if the lock-file cannot be represented in the
+    /// requested version without losing information.
+    pub fn render_to_string_with_version(
+        &self,
+        version: FileFormatVersion,
+    ) -> Result<String, WriteLockFileError> {
+        self.check_writable_as(version)?;
+        Ok(serde_yaml::to_string(&parse::VersionedLockFile {
+            lock_file: self,
+            version,
+        })?)
+    }
+
+    /// Writes the lock-file to a file in the given format version.
+    ///
+    /// This can be used to keep lock-files readable by older tools when the
+    /// content of the lock-file allows it.
+    pub fn to_path_with_version(
+        &self,
+        path: &Path,
+        version: FileFormatVersion,
+    ) -> Result<(), WriteLockFileError> {
+        let rendered = self.render_to_string_with_version(version)?;
+        std::fs::write(path, rendered)?;
+        Ok(())
     }
 
     /// Returns the environment with the given name.
@@ -272,6 +365,12 @@ impl Environment {
         self.data().indexes.as_ref()
     }
 
+    /// Returns information about how this environment was solved, e.g. which
+    /// solver was used and when.
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.data().provenance.as_ref()
+    }
+
     /// Returns all the packages for a specific platform in this environment.
     pub fn packages(
         &self,
@@ -404,6 +503,19 @@ impl Environment {
         )
     }
 
+    /// Returns the pypi packages and their associated environment data that
+    /// apply to the given interpreter when the `requested` packages are
+    /// installed, see [`PythonInterpreter::filter_packages`]. Returns `None`
+    /// if the platform of the interpreter is not defined for this environment.
+    pub fn pypi_packages_for_interpreter(
+        &self,
+        interpreter: &PythonInterpreter,
+        requested: impl IntoIterator<Item = pep508_rs::PackageName>,
+    ) -> Option<Vec<(PypiPackageData, PypiPackageEnvironmentData)>> {
+        self.pypi_packages_for_platform(interpreter.platform())
+            .map(|packages| interpreter.filter_packages(packages, requested))
+    }
+
     /// Returns the version of the lock-file that contained this environment.
     pub fn version(&self) -> FileFormatVersion {
         self.inner.version
@@ -551,21 +663,35 @@ impl CondaPackage {
         self.package_data().channel()
     }
 
+    /// Returns the subdirectory of the channel that the package originates
+    /// from.
+    pub fn subdir(&self) -> &str {
+        &self.package_record().subdir
+    }
+
     /// Returns true if this package satisfies the given `spec`.
+    ///
+    /// See [`MatchSpec::matches_package_at`] for the exact semantics.
     pub fn satisfies(&self, spec: &MatchSpec) -> bool {
-        // Check the data in the package record
-        if !spec.matches(self.package_record()) {
-            return false;
-        }
+        spec.matches(self.package_data())
+    }
+}
 
-        // Check the the channel
-        if let Some(channel) = &spec.channel {
-            if !self.url().as_str().starts_with(channel.base_url.as_str()) {
-                return false;
-            }
-        }
+impl Matches<CondaPackage> for MatchSpec {
+    /// Match a [`MatchSpec`] against a locked conda package.
+    fn matches(&self, other: &CondaPackage) -> bool {
+        self.matches(other.package_data())
+    }
+}
 
-        true
+impl Matches<Package> for MatchSpec {
+    /// Match a [`MatchSpec`] against a locked package. Pypi packages never
+    /// match.
+    fn matches(&self, other: &Package) -> bool {
+        match other {
+            Package::Conda(package) => self.matches(package),
+            Package::Pypi(_) => false,
+        }
     }
 }
 
@@ -629,6 +755,12 @@ impl PypiPackage {
     pub fn is_editable(&self) -> bool {
         self.package_data().editable
     }
+
+    /// Returns the source the package is built from, or `None` if the
+    /// package is a distribution from a package index.
+    pub fn source(&self) -> Option<&PypiPackageSource> {
+        self.package_data().source.as_ref()
+    }
 }
 
 /// A helper struct to group package and environment data together.
@@ -644,12 +776,15 @@ pub struct PypiPackageDataRef<'p> {
 
 #[cfg(test)]
 mod test {
-    use std::path::Path;
+    use std::{path::Path, str::FromStr};
 
     use rattler_conda_types::Platform;
     use rstest::*;
 
-    use super::{LockFile, DEFAULT_ENVIRONMENT_NAME};
+    use super::{
+        FileFormatVersion, LockFile, PypiPackageData, PypiPackageEnvironmentData,
+        PypiPackageSource, UrlOrPath, WriteLockFileError, DEFAULT_ENVIRONMENT_NAME,
+    };
 
     #[rstest]
     #[case("v0/numpy-conda-lock.yml")]
@@ -670,6 +805,89 @@ mod test {
         insta::assert_yaml_snapshot!(file_name, conda_lock);
     }
 
+    #[test]
+    fn test_pypi_source_roundtrip() {
+        let package = PypiPackageData {
+            name: "flask".parse().unwrap(),
+            version: "3.0.0".parse().unwrap(),
+            url_or_path: UrlOrPath::Url(
+                "git+https://github.com/pallets/flask@b90a4f1f4a370e92054b9cc9db0efcb864f87ebe"
+                    .parse()
+                    .unwrap(),
+            ),
+            hash: None,
+            requires_dist: Vec::new(),
+            requires_python: None,
+            editable: true,
+            source: Some(PypiPackageSource::Git {
+                rev: Some("main".to_string()),
+                commit: "b90a4f1f4a370e92054b9cc9db0efcb864f87ebe".to_string(),
+                subdirectory: Some("src".to_string()),
+            }),
+        };
+        let lock_file = LockFile::builder()
+            .with_pypi_package(
+                DEFAULT_ENVIRONMENT_NAME,
+                Platform::Linux64,
+                package.clone(),
+                PypiPackageEnvironmentData::default(),
+            )
+            .finish();
+
+        // The source requires version 6 of the format, which is picked
+        // automatically.
+        let rendered = serde_yaml::to_string(&lock_file).unwrap();
+        assert!(rendered.starts_with("version: 6\n"), "{rendered}");
+        assert!(rendered.contains("kind: git"), "{rendered}");
+
+        let parsed = LockFile::from_str(&rendered).unwrap();
+        let packages = parsed
+            .environment(DEFAULT_ENVIRONMENT_NAME)
+            .unwrap()
+            .pypi_packages_for_platform(Platform::Linux64)
+            .unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].0, package);
+        assert!(packages[0].0.is_source());
+
+        // Older versions of the format cannot store the source.
+        assert!(matches!(
+            lock_file.render_to_string_with_version(FileFormatVersion::V5),
+            Err(WriteLockFileError::IncompatibleData { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_path_preserving() {
+        let test_data = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data/conda-lock");
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("pixi.lock");
+
+        // An existing file with the same content is left untouched.
+        let existing = format!(
+            "# a comment\n{}",
+            std::fs::read_to_string(test_data.join("v4/numpy-lock.yml")).unwrap()
+        );
+        std::fs::write(&path, &existing).unwrap();
+        let lock_file = LockFile::from_path(&path).unwrap();
+        assert!(!lock_file.to_path_preserving(&path).unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), existing);
+
+        // Different content is written in the version of the existing file.
+        let lock_file = LockFile::from_path(&test_data.join("v4/python-lock.yml")).unwrap();
+        assert!(lock_file.to_path_preserving(&path).unwrap());
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with("version: 4\n"), "{written}");
+        assert!(!lock_file.to_path_preserving(&path).unwrap());
+
+        // New files are written in the oldest version that can represent the
+        // content.
+        let path = temp_dir.path().join("new.lock");
+        assert!(lock_file.to_path_preserving(&path).unwrap());
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with("version: 5\n"), "{written}");
+    }
+
     /// Absolute paths on Windows are not properly parsed.
     /// See: <https://github.com/conda-incubator/rattler/issues/615>
     #[test]
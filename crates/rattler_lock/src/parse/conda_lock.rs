@@ -0,0 +1,235 @@
+//! Writing of lock-files in the format used by conda-lock (`conda-lock.yml`).
+//!
+//! conda-lock files are lock-file version 1 and can be read with
+//! [`LockFile::from_path`] like any other lock-file.
+
+use std::{borrow::Cow, collections::BTreeMap, path::Path};
+
+use itertools::Itertools;
+use pep508_rs::VersionOrUrl;
+use rattler_conda_types::Platform;
+use rattler_digest::{compute_bytes_digest, Sha256};
+use serde::Serialize;
+use url::Url;
+
+use super::WriteLockFileError;
+use crate::{
+    file_format_version::FileFormatVersion, Channel, LockFile, Package, PackageHashes, UrlOrPath,
+};
+
+#[derive(Serialize)]
+struct CondaLockFile<'a> {
+    version: FileFormatVersion,
+    metadata: CondaLockMetadata<'a>,
+    package: Vec<CondaLockPackage<'a>>,
+}
+
+#[derive(Serialize)]
+struct CondaLockMetadata<'a> {
+    channels: &'a [Channel],
+    content_hash: BTreeMap<Platform, String>,
+    platforms: Vec<Platform>,
+    sources: Vec<String>,
+}
+
+/// A locked package, the fields are ordered the same way conda-lock orders
+/// them.
+#[derive(Serialize)]
+struct CondaLockPackage<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build: Option<&'a str>,
+    category: &'static str,
+    dependencies: BTreeMap<String, String>,
+    hash: PackageHashes,
+    manager: &'static str,
+    name: Cow<'a, str>,
+    optional: bool,
+    platform: Platform,
+    url: Url,
+    version: String,
+}
+
+impl LockFile {
+    /// Renders the environment with the given name in the format used by
+    /// conda-lock (`conda-lock.yml`). conda-lock files only contain a single
+    /// environment.
+    ///
+    /// The markers and extras of pypi dependencies cannot be represented in
+    /// this format and are omitted. An error is returned if the environment
+    /// contains packages without a hash or packages that are not referenced
+    /// by a url.
+    pub fn render_to_conda_lock_string(
+        &self,
+        environment_name: &str,
+    ) -> Result<String, WriteLockFileError> {
+        let environment = self
+            .environment(environment_name)
+            .ok_or_else(|| WriteLockFileError::MissingEnvironment(environment_name.to_string()))?;
+        let incompatible = |reason| WriteLockFileError::IncompatibleData {
+            requested: FileFormatVersion::V1,
+            environment: environment_name.to_string(),
+            reason,
+        };
+
+        let platforms = environment
+            .platforms()
+            .sorted_by_key(|platform| platform.as_str())
+            .collect::<Vec<_>>();
+        let mut packages = Vec::new();
+        let mut content_hash = BTreeMap::new();
+        for &platform in &platforms {
+            let mut platform_packages = environment
+                .packages(platform)
+                .into_iter()
+                .flatten()
+                .map(|package| conda_lock_package(&package, platform))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| incompatible("contains packages without a hash or a url"))?;
+            platform_packages.sort_by(|a, b| a.name.cmp(&b.name).then(a.manager.cmp(&b.manager)));
+
+            // conda-lock uses the content hash to detect whether the lock-file
+            // is outdated compared to its sources. The sources are not known so
+            // instead the hash is derived from the locked packages.
+            let urls = platform_packages
+                .iter()
+                .map(|package| package.url.as_str())
+                .join("\n");
+            content_hash.insert(
+                platform,
+                format!("{:x}", compute_bytes_digest::<Sha256>(urls)),
+            );
+
+            packages.extend(platform_packages);
+        }
+
+        Ok(serde_yaml::to_string(&CondaLockFile {
+            version: FileFormatVersion::V1,
+            metadata: CondaLockMetadata {
+                channels: environment.channels(),
+                content_hash,
+                platforms,
+                sources: Vec::new(),
+            },
+            package: packages,
+        })?)
+    }
+
+    /// Writes the environment with the given name to a file in the format used
+    /// by conda-lock (`conda-lock.yml`). See
+    /// [`LockFile::render_to_conda_lock_string`].
+    pub fn to_conda_lock_path(
+        &self,
+        path: &Path,
+        environment_name: &str,
+    ) -> Result<(), WriteLockFileError> {
+        let rendered = self.render_to_conda_lock_string(environment_name)?;
+        std::fs::write(path, rendered)?;
+        Ok(())
+    }
+}
+
+/// Converts a package to the conda-lock representation. Returns `None` if the
+/// package cannot be represented.
+fn conda_lock_package(package: &Package, platform: Platform) -> Option<CondaLockPackage<'_>> {
+    match package {
+        Package::Conda(conda) => {
+            let record = conda.package_record();
+            let dependencies = record
+                .depends
+                .iter()
+                .map(|dependency| match dependency.split_once(' ') {
+                    Some((name, spec)) => (name.to_string(), spec.trim().to_string()),
+                    None => (dependency.clone(), String::from("*")),
+                })
+                .collect();
+            Some(CondaLockPackage {
+                build: Some(&record.build),
+                category: "main",
+                dependencies,
+                hash: PackageHashes::from_hashes(record.md5, record.sha256)?,
+                manager: "conda",
+                name: Cow::Borrowed(record.name.as_normalized()),
+                optional: false,
+                platform,
+                url: conda.url().clone(),
+                version: record.version.to_string(),
+            })
+        }
+        Package::Pypi(pypi) => {
+            let data = pypi.data().package;
+            let UrlOrPath::Url(url) = &data.url_or_path else {
+                return None;
+            };
+            let dependencies = data
+                .requires_dist
+                .iter()
+                .map(|requirement| {
+                    let spec = match &requirement.version_or_url {
+                        Some(VersionOrUrl::VersionSpecifier(specifiers)) => specifiers.to_string(),
+                        _ => String::new(),
+                    };
+                    (requirement.name.to_string(), spec)
+                })
+                .collect();
+            Some(CondaLockPackage {
+                build: None,
+                category: "main",
+                dependencies,
+                hash: data.hash.clone()?,
+                manager: "pip",
+                name: package.name(),
+                optional: false,
+                platform,
+                url: url.clone(),
+                version: data.version.to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, str::FromStr};
+
+    use rattler_conda_types::Platform;
+
+    use crate::{LockFile, WriteLockFileError, DEFAULT_ENVIRONMENT_NAME};
+
+    #[test]
+    fn test_conda_lock_roundtrip() {
+        let lock_file = LockFile::from_path(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../test-data/conda-lock/v4/pypi-matplotlib-lock.yml"),
+        )
+        .unwrap();
+
+        let rendered = lock_file
+            .render_to_conda_lock_string(DEFAULT_ENVIRONMENT_NAME)
+            .unwrap();
+        assert!(rendered.starts_with("version: 1\n"));
+
+        // Reading the rendered file back results in the same packages.
+        let reparsed = LockFile::from_str(&rendered).unwrap();
+        let original = lock_file.default_environment().unwrap();
+        let reparsed = reparsed.default_environment().unwrap();
+        assert_eq!(original.channels(), reparsed.channels());
+        let urls = |environment: &crate::Environment, platform| {
+            let mut urls = environment
+                .packages(platform)
+                .unwrap()
+                .map(|package| package.url_or_path().into_owned())
+                .collect::<Vec<_>>();
+            urls.sort_by_key(ToString::to_string);
+            urls
+        };
+        assert_eq!(
+            urls(&original, Platform::Linux64),
+            urls(&reparsed, Platform::Linux64)
+        );
+
+        assert!(matches!(
+            lock_file.render_to_conda_lock_string("missing"),
+            Err(WriteLockFileError::MissingEnvironment(_))
+        ));
+    }
+}
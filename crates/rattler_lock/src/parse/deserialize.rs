@@ -2,7 +2,8 @@ use crate::file_format_version::FileFormatVersion;
 use crate::utils::serde::RawCondaPackageData;
 use crate::{
     Channel, CondaPackageData, EnvironmentData, EnvironmentPackageData, LockFile, LockFileInner,
-    ParseCondaLockError, PypiIndexes, PypiPackageData, PypiPackageEnvironmentData, UrlOrPath,
+    ParseCondaLockError, Provenance, PypiIndexes, PypiPackageData, PypiPackageEnvironmentData,
+    UrlOrPath,
 };
 use fxhash::FxHashMap;
 use indexmap::IndexSet;
@@ -26,6 +27,8 @@ struct DeserializableEnvironment {
     channels: Vec<Channel>,
     #[serde(flatten)]
     indexes: Option<PypiIndexes>,
+    #[serde(default)]
+    provenance: Option<Provenance>,
     packages: BTreeMap<Platform, Vec<DeserializablePackageSelector>>,
 }
 
@@ -100,6 +103,7 @@ pub fn parse_from_document(
                 EnvironmentData {
                     channels: env.channels,
                     indexes: env.indexes,
+                    provenance: env.provenance,
                     packages: env
                         .packages
                         .into_iter()
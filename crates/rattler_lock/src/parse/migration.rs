@@ -0,0 +1,103 @@
+//! Describes the transformations that are applied when a lock-file in an older
+//! format is loaded.
+//!
+//! Every format version has its own parser (see the sibling modules). Older
+//! versions are parsed directly into the in-memory representation of the latest
+//! version. The [`MigrationReport`] describes which upgrade steps were
+//! performed to get there so tools can inform users about what changed.
+
+use crate::FileFormatVersion;
+
+/// A single upgrade step from one lock-file format version to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    /// The version before the migration.
+    pub from: FileFormatVersion,
+
+    /// The version after the migration.
+    pub to: FileFormatVersion,
+
+    /// A human readable description of the transformation.
+    pub description: &'static str,
+}
+
+/// A report of all the migrations that were applied when loading a lock-file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// The version of the lock-file before it was upgraded.
+    pub original_version: FileFormatVersion,
+
+    /// The migrations that were applied in order.
+    pub migrations: Vec<Migration>,
+}
+
+impl MigrationReport {
+    /// Constructs the report of upgrading a lock-file with the given version to
+    /// the latest version.
+    pub(crate) fn from_version(original_version: FileFormatVersion) -> Self {
+        let mut migrations = Vec::new();
+        let mut version = original_version;
+        while let Some(next) = version.next() {
+            migrations.push(Migration {
+                from: version,
+                to: next,
+                description: migration_description(next),
+            });
+            version = next;
+        }
+
+        Self {
+            original_version,
+            migrations,
+        }
+    }
+
+    /// Returns true if the lock-file was already in the latest format.
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty()
+    }
+}
+
+/// Returns a description of the transformation required to upgrade to the
+/// given version from its predecessor.
+fn migration_description(to: FileFormatVersion) -> &'static str {
+    match to {
+        FileFormatVersion::V1 => "initial version",
+        FileFormatVersion::V2 => "converted dependency maps to arrays",
+        FileFormatVersion::V3 => "renamed the `pip` package manager to `pypi`",
+        FileFormatVersion::V4 => {
+            "converted to the multi-environment format, all packages are placed in the `default` environment"
+        }
+        FileFormatVersion::V5 => {
+            "added support for pypi indexes, environments without indexes are left unchanged"
+        }
+        FileFormatVersion::V6 => {
+            "added support for the dependencies of extras of conda packages, the source of pypi packages and the provenance of environments"
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_report_from_version() {
+        let report = MigrationReport::from_version(FileFormatVersion::V3);
+        assert_eq!(report.original_version, FileFormatVersion::V3);
+        assert_eq!(
+            report
+                .migrations
+                .iter()
+                .map(|m| (m.from, m.to))
+                .collect::<Vec<_>>(),
+            vec![
+                (FileFormatVersion::V3, FileFormatVersion::V4),
+                (FileFormatVersion::V4, FileFormatVersion::V5),
+                (FileFormatVersion::V5, FileFormatVersion::V6)
+            ]
+        );
+
+        assert!(MigrationReport::from_version(FileFormatVersion::LATEST).is_empty());
+    }
+}
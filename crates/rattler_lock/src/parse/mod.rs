@@ -1,8 +1,13 @@
+mod conda_lock;
 mod deserialize;
+mod migration;
 mod serialize;
 mod v3;
 
-use super::{LockFile, UrlOrPath};
+pub use migration::{Migration, MigrationReport};
+pub(crate) use serialize::VersionedLockFile;
+
+use super::{LockFile, Package, UrlOrPath};
 use crate::file_format_version::FileFormatVersion;
 use rattler_conda_types::Platform;
 use serde::de::Error;
@@ -32,10 +37,53 @@ pub enum ParseCondaLockError {
     InvalidPypiPackageName(#[from] pep508_rs::InvalidNameError),
 }
 
-impl FromStr for LockFile {
-    type Err = ParseCondaLockError;
+/// An error that can occur when writing a lock-file in a specific format
+/// version.
+#[derive(Debug, thiserror::Error)]
+pub enum WriteLockFileError {
+    /// Writing lock-files older than the multi-environment format is not
+    /// supported.
+    #[error("writing lock-files with version {requested} is not supported, the oldest supported version is {}", FileFormatVersion::V4)]
+    UnsupportedVersion {
+        /// The version that was requested.
+        requested: FileFormatVersion,
+    },
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+    /// The lock-file contains information that cannot be represented in the
+    /// requested version.
+    #[error(
+        "environment '{environment}' cannot be written as version {requested} because it {reason}"
+    )]
+    IncompatibleData {
+        /// The version that was requested.
+        requested: FileFormatVersion,
+
+        /// The name of the environment that cannot be represented.
+        environment: String,
+
+        /// A description of the data that cannot be represented.
+        reason: &'static str,
+    },
+
+    /// The requested environment does not exist in the lock-file.
+    #[error("the lock-file does not contain an environment named '{0}'")]
+    MissingEnvironment(String),
+
+    /// An IO error occurred while writing the lock-file.
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    /// The lock-file could not be serialized.
+    #[error(transparent)]
+    SerializeError(#[from] serde_yaml::Error),
+}
+
+impl LockFile {
+    /// Parses a lock-file from a string and returns a report of the migrations
+    /// that were applied to upgrade the lock-file to the latest format version.
+    pub fn from_str_with_migration_report(
+        s: &str,
+    ) -> Result<(Self, MigrationReport), ParseCondaLockError> {
         // First parse the document to a `serde_yaml::Value`.
         let document: Value = serde_yaml::from_str(s).map_err(ParseCondaLockError::ParseError)?;
 
@@ -57,14 +105,100 @@ impl FromStr for LockFile {
                 FileFormatVersion::try_from(v)
             })?;
 
-        if version <= FileFormatVersion::V3 {
+        let lock_file = if version <= FileFormatVersion::V3 {
             parse_v3_or_lower(document, version)
         } else {
             deserialize::parse_from_document(document, version)
+        }?;
+
+        Ok((lock_file, MigrationReport::from_version(version)))
+    }
+
+    /// Checks whether this lock-file can be written in the given format
+    /// version without losing information.
+    pub(crate) fn check_writable_as(
+        &self,
+        version: FileFormatVersion,
+    ) -> Result<(), WriteLockFileError> {
+        if version < FileFormatVersion::V4 {
+            return Err(WriteLockFileError::UnsupportedVersion { requested: version });
+        }
+
+        if !version.should_pypi_indexes_be_present() {
+            if let Some((name, _)) = self
+                .environments()
+                .find(|(_, env)| env.pypi_indexes().is_some())
+            {
+                return Err(WriteLockFileError::IncompatibleData {
+                    requested: version,
+                    environment: name.to_string(),
+                    reason: "contains pypi indexes",
+                });
+            }
+        }
+
+        if version < FileFormatVersion::V6 {
+            if let Some((name, _)) = self
+                .environments()
+                .find(|(_, env)| env.provenance().is_some())
+            {
+                return Err(WriteLockFileError::IncompatibleData {
+                    requested: version,
+                    environment: name.to_string(),
+                    reason: "contains the provenance of the environment",
+                });
+            }
+
+            for (name, env) in self.environments() {
+                for package in env
+                    .packages_by_platform()
+                    .flat_map(|(_, packages)| packages)
+                {
+                    let reason = match &package {
+                        Package::Conda(package)
+                            if !package.package_record().extra_depends.is_empty() =>
+                        {
+                            "contains conda packages with dependencies of extras"
+                        }
+                        Package::Pypi(package) if package.source().is_some() => {
+                            "contains pypi packages that are built from source"
+                        }
+                        _ => continue,
+                    };
+                    return Err(WriteLockFileError::IncompatibleData {
+                        requested: version,
+                        environment: name.to_string(),
+                        reason,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the version this lock-file is written in by default: the
+    /// oldest version that can represent it without losing information. This
+    /// keeps lock-files readable by older tools unless they actually contain
+    /// data that requires a newer version. Version 5 is the oldest version
+    /// that is written by default.
+    pub(crate) fn default_write_version(&self) -> FileFormatVersion {
+        if self.check_writable_as(FileFormatVersion::V5).is_ok() {
+            FileFormatVersion::V5
+        } else {
+            FileFormatVersion::LATEST
         }
     }
 }
 
+impl FromStr for LockFile {
+    type Err = ParseCondaLockError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_str_with_migration_report(s).map(|(lock_file, _)| lock_file)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -79,7 +213,37 @@ mod test {
         .err()
         .unwrap();
 
-        insta::assert_snapshot!(format!("{}", err), @"found newer lockfile format version 1000, but only up to including version 5 is supported");
+        insta::assert_snapshot!(format!("{}", err), @"found newer lockfile format version 1000, but only up to including version 6 is supported");
+    }
+
+    #[test]
+    fn test_provenance_roundtrip() {
+        let provenance = crate::Provenance {
+            solver: Some(String::from("resolvo")),
+            solver_version: Some(String::from("0.8.0")),
+            solved_at: Some("2024-06-01T12:00:00Z".parse().unwrap()),
+        };
+        let lock_file = LockFile::builder()
+            .with_channels("default", ["https://conda.anaconda.org/conda-forge/"])
+            .with_provenance("default", provenance.clone())
+            .with_channels("other", ["https://conda.anaconda.org/conda-forge/"])
+            .finish();
+
+        let rendered = serde_yaml::to_string(&lock_file).unwrap();
+        assert!(rendered.contains("solver: resolvo"), "{rendered}");
+
+        let reparsed = LockFile::from_str(&rendered).unwrap();
+        assert_eq!(
+            reparsed.environment("default").unwrap().provenance(),
+            Some(&provenance)
+        );
+        assert_eq!(reparsed.environment("other").unwrap().provenance(), None);
+
+        // Older versions of the format cannot store the provenance.
+        assert!(matches!(
+            lock_file.render_to_string_with_version(FileFormatVersion::V5),
+            Err(WriteLockFileError::IncompatibleData { .. })
+        ));
     }
 
     // This test verifies the deterministic ordering of lock files. It does so by comparing the serialized
@@ -106,4 +270,75 @@ mod test {
 
         assert_eq!(output_original, output_shuffled);
     }
+
+    #[test]
+    fn test_migration_report() {
+        let source = std::fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../test-data/conda-lock/v3/robostack-turtlesim-conda-lock.yml"),
+        )
+        .unwrap();
+        let (_, report) = LockFile::from_str_with_migration_report(&source).unwrap();
+        assert_eq!(report.original_version, FileFormatVersion::V3);
+        assert_eq!(
+            report.migrations.iter().map(|m| m.to).collect::<Vec<_>>(),
+            vec![
+                FileFormatVersion::V4,
+                FileFormatVersion::V5,
+                FileFormatVersion::V6
+            ]
+        );
+
+        let source = std::fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../test-data/conda-lock/v5/flat-index-lock.yml"),
+        )
+        .unwrap();
+        let (_, report) = LockFile::from_str_with_migration_report(&source).unwrap();
+        assert_eq!(
+            report.migrations.iter().map(|m| m.to).collect::<Vec<_>>(),
+            vec![FileFormatVersion::V6]
+        );
+    }
+
+    #[test]
+    fn test_write_older_version() {
+        let lock_file = LockFile::from_path(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../test-data/conda-lock/v4/numpy-lock.yml"),
+        )
+        .unwrap();
+
+        let rendered = lock_file
+            .render_to_string_with_version(FileFormatVersion::V4)
+            .unwrap();
+        assert!(rendered.starts_with("version: 4\n"));
+
+        // The rendered lock-file should be readable and identical to the original.
+        let reparsed = LockFile::from_str(&rendered).unwrap();
+        assert_eq!(reparsed.version(), FileFormatVersion::V4);
+        assert_eq!(
+            serde_yaml::to_string(&reparsed).unwrap(),
+            serde_yaml::to_string(&lock_file).unwrap()
+        );
+
+        assert!(matches!(
+            lock_file.render_to_string_with_version(FileFormatVersion::V3),
+            Err(WriteLockFileError::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_write_older_version_with_indexes() {
+        let lock_file = LockFile::from_path(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../test-data/conda-lock/v5/flat-index-lock.yml"),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            lock_file.render_to_string_with_version(FileFormatVersion::V4),
+            Err(WriteLockFileError::IncompatibleData { .. })
+        ));
+    }
 }
@@ -12,7 +12,7 @@ use url::Url;
 
 use crate::{
     file_format_version::FileFormatVersion, utils::serde::RawCondaPackageData, Channel,
-    CondaPackage, EnvironmentPackageData, LockFile, Package, PypiIndexes, PypiPackage,
+    CondaPackage, EnvironmentPackageData, LockFile, Package, Provenance, PypiIndexes, PypiPackage,
     PypiPackageData, UrlOrPath,
 };
 
@@ -28,6 +28,8 @@ struct SerializableEnvironment<'a> {
     channels: &'a [Channel],
     #[serde(flatten)]
     indexes: Option<&'a PypiIndexes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provenance: Option<&'a Provenance>,
     packages: BTreeMap<Platform, Vec<SerializablePackageSelector<'a>>>,
 }
 
@@ -195,12 +197,34 @@ impl Ord for SerializablePackageData<'_> {
     }
 }
 
+/// A lock-file together with the format version it should be serialized as.
+///
+/// The caller is responsible for ensuring that the lock-file can be represented
+/// in the requested version, see [`LockFile::render_to_string_with_version`].
+pub(crate) struct VersionedLockFile<'a> {
+    pub lock_file: &'a LockFile,
+    pub version: FileFormatVersion,
+}
+
 impl Serialize for LockFile {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let inner = self.inner.as_ref();
+        VersionedLockFile {
+            lock_file: self,
+            version: self.default_write_version(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Serialize for VersionedLockFile<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let inner = self.lock_file.inner.as_ref();
 
         // Get all packages.
         let mut packages = inner
@@ -226,7 +250,11 @@ impl Serialize for LockFile {
                     name,
                     SerializableEnvironment {
                         channels: &env_data.channels,
-                        indexes: env_data.indexes.as_ref(),
+                        indexes: env_data
+                            .indexes
+                            .as_ref()
+                            .filter(|_| self.version.should_pypi_indexes_be_present()),
+                        provenance: env_data.provenance.as_ref(),
                         packages: env_data
                             .packages
                             .iter()
@@ -256,6 +284,7 @@ impl Serialize for LockFile {
                                             }
                                         })
                                         .sorted()
+                                        .dedup()
                                         .collect(),
                                 )
                             })
@@ -283,7 +312,7 @@ impl Serialize for LockFile {
         packages.sort();
 
         let raw = SerializableLockFile {
-            version: FileFormatVersion::LATEST,
+            version: self.version,
             environments,
             packages,
         };
@@ -150,29 +150,29 @@ pub fn parse_v3_or_lower(
 
                 let deduplicated_idx = conda_packages
                     .insert_full(CondaPackageData {
-                        package_record: PackageRecord {
-                            arch: value.arch,
-                            build: value.build,
-                            build_number: value.build_number.unwrap_or(0),
-                            constrains: value.constrains,
-                            depends: value.dependencies,
-                            features: value.features,
-                            legacy_bz2_md5: None,
-                            legacy_bz2_size: None,
-                            license: value.license,
-                            license_family: value.license_family,
-                            md5,
-                            name: PackageName::new_unchecked(value.name),
-                            noarch: value.noarch,
-                            platform: platform.only_platform().map(ToString::to_string),
-                            sha256,
-                            size: value.size,
-                            subdir: value.subdir.unwrap_or(platform.to_string()),
-                            timestamp: value.timestamp,
-                            track_features: value.track_features,
-                            version: value.version,
-                            purls: value.purls.is_empty().not().then_some(value.purls),
-                            run_exports: None,
+                        package_record: {
+                            let mut record = PackageRecord::new(
+                                PackageName::new_unchecked(value.name),
+                                value.version,
+                                value.build,
+                            );
+                            record.arch = value.arch;
+                            record.build_number = value.build_number.unwrap_or(0);
+                            record.constrains = value.constrains;
+                            record.depends = value.dependencies;
+                            record.features = value.features;
+                            record.license = value.license;
+                            record.license_family = value.license_family;
+                            record.md5 = md5;
+                            record.noarch = value.noarch;
+                            record.platform = platform.only_platform().map(ToString::to_string);
+                            record.sha256 = sha256;
+                            record.size = value.size;
+                            record.subdir = value.subdir.unwrap_or(platform.to_string());
+                            record.timestamp = value.timestamp;
+                            record.track_features = value.track_features;
+                            record.purls = value.purls.is_empty().not().then_some(value.purls);
+                            record
                         },
                         url: value.url,
                         file_name: None,
@@ -192,6 +192,7 @@ pub fn parse_v3_or_lower(
                         url_or_path: UrlOrPath::Url(pkg.url),
                         hash: pkg.hash,
                         editable: false,
+                        source: None,
                     })
                     .0;
                 EnvironmentPackageData::Pypi(
@@ -208,6 +209,7 @@ pub fn parse_v3_or_lower(
     let default_environment = EnvironmentData {
         channels: lock_file.metadata.channels,
         indexes: None,
+        provenance: None,
         packages: per_platform,
     };
 
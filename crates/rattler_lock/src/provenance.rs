@@ -0,0 +1,22 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+
+/// Describes how the packages of an environment were determined. Audit
+/// tooling can use this to trace back how an environment came to be.
+///
+/// The channel and subdirectory that an individual conda package originates
+/// from are stored with the package itself, see
+/// [`crate::CondaPackage::channel`] and [`crate::CondaPackage::subdir`].
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Provenance {
+    /// The name of the solver backend that was used, e.g. `resolvo`.
+    pub solver: Option<String>,
+
+    /// The version of the solver backend that was used.
+    pub solver_version: Option<String>,
+
+    /// The moment at which the environment was solved.
+    pub solved_at: Option<DateTime<Utc>>,
+}
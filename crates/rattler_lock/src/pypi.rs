@@ -38,6 +38,43 @@ pub struct PypiPackageData {
     /// Whether the projects should be installed in editable mode or not.
     #[serde(default, skip_serializing_if = "should_skip_serializing_editable")]
     pub editable: bool,
+
+    /// Describes the source the package is built from, or `None` if the
+    /// package is a distribution from a package index.
+    pub source: Option<PypiPackageSource>,
+}
+
+/// Describes the source of a pypi package that is built from source instead
+/// of installed from a distribution on a package index.
+///
+/// The location of the source itself is stored in
+/// [`PypiPackageData::url_or_path`].
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, Hash, PartialOrd, Ord)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum PypiPackageSource {
+    /// The package is built from a git repository.
+    Git {
+        /// The branch, tag or commit that was requested, if any.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rev: Option<String>,
+
+        /// The commit the requested revision resolved to.
+        commit: String,
+
+        /// The directory inside the repository that contains the project.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        subdirectory: Option<String>,
+    },
+
+    /// The package is built from a source archive (sdist) at a direct url.
+    DirectUrl {
+        /// The directory inside the archive that contains the project.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        subdirectory: Option<String>,
+    },
+
+    /// The package is built from a local source tree.
+    SourceTree,
 }
 
 /// Additional runtime configuration of a package. Multiple environments/platforms might refer to
@@ -61,10 +98,17 @@ impl Ord for PypiPackageData {
             .then_with(|| self.version.cmp(&other.version))
             .then_with(|| self.url_or_path.cmp(&other.url_or_path))
             .then_with(|| self.hash.cmp(&other.hash))
+            .then_with(|| self.source.cmp(&other.source))
     }
 }
 
 impl PypiPackageData {
+    /// Returns true if this package is built from source instead of
+    /// installed from a distribution on a package index.
+    pub fn is_source(&self) -> bool {
+        self.source.is_some()
+    }
+
     /// Returns true if this package satisfies the given `spec`.
     pub fn satisfies(&self, spec: &Requirement) -> bool {
         // Check if the name matches
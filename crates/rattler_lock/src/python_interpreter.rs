@@ -0,0 +1,595 @@
+use std::collections::{HashMap, HashSet};
+
+use pep440_rs::Version;
+use pep508_rs::{ExtraName, MarkerEnvironment, MarkerEnvironmentBuilder, PackageName};
+use rattler_conda_types::{Arch, Platform};
+
+use crate::{PypiPackageData, PypiPackageEnvironmentData, UrlOrPath};
+
+/// The implementation of a python interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PythonImplementation {
+    /// The reference implementation of python.
+    CPython,
+
+    /// The `PyPy` implementation of python.
+    PyPy,
+}
+
+impl PythonImplementation {
+    /// Returns the value of the `implementation_name` environment marker.
+    fn implementation_name(self) -> &'static str {
+        match self {
+            PythonImplementation::CPython => "cpython",
+            PythonImplementation::PyPy => "pypy",
+        }
+    }
+
+    /// Returns the value of the `platform_python_implementation` environment
+    /// marker.
+    fn platform_python_implementation(self) -> &'static str {
+        match self {
+            PythonImplementation::CPython => "CPython",
+            PythonImplementation::PyPy => "PyPy",
+        }
+    }
+
+    /// Returns the prefix of the python tag of wheels built specifically for
+    /// this implementation.
+    fn wheel_tag_prefix(self) -> &'static str {
+        match self {
+            PythonImplementation::CPython => "cp",
+            PythonImplementation::PyPy => "pp",
+        }
+    }
+}
+
+/// Describes the python interpreter of a target machine onto which the pypi
+/// packages of a lock-file are installed.
+///
+/// The pypi packages of an environment are locked per conda platform, but not
+/// every locked package is applicable to every interpreter of that platform.
+/// The interpreter is used to filter the locked packages based on the python
+/// version they require, the tags of their wheels and the environment markers
+/// of the dependencies that pull them in. See
+/// [`PythonInterpreter::filter_packages`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PythonInterpreter {
+    platform: Platform,
+    version: Version,
+    implementation: PythonImplementation,
+    musl: bool,
+}
+
+impl PythonInterpreter {
+    /// Constructs a new interpreter with the full version of python (e.g.
+    /// `3.11.4`) that runs on the given platform.
+    pub fn new(platform: Platform, version: Version, implementation: PythonImplementation) -> Self {
+        Self {
+            platform,
+            version,
+            implementation,
+            musl: false,
+        }
+    }
+
+    /// Sets whether the interpreter is linked against musl instead of glibc.
+    /// This determines whether `musllinux` or `manylinux` wheels can be
+    /// installed on linux platforms.
+    #[must_use]
+    pub fn with_musl(self, musl: bool) -> Self {
+        Self { musl, ..self }
+    }
+
+    /// Returns the platform the interpreter runs on.
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Returns the full version of the interpreter.
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    /// Returns the implementation of the interpreter.
+    pub fn implementation(&self) -> PythonImplementation {
+        self.implementation
+    }
+
+    /// Returns true if the interpreter is linked against musl.
+    pub fn is_musl(&self) -> bool {
+        self.musl
+    }
+
+    /// Returns the major and minor version of the interpreter.
+    fn major_minor(&self) -> (u64, u64) {
+        let release = self.version.release();
+        (
+            release.first().copied().unwrap_or(0),
+            release.get(1).copied().unwrap_or(0),
+        )
+    }
+
+    /// Returns the environment markers of the interpreter.
+    ///
+    /// Markers that cannot be derived from the platform and the version, like
+    /// `platform_release` and `platform_version`, are empty.
+    pub fn marker_environment(&self) -> MarkerEnvironment {
+        let (major, minor) = self.major_minor();
+        let python_version = format!("{major}.{minor}");
+        let python_full_version = self.version.to_string();
+
+        let (os_name, sys_platform, platform_system) = if self.platform.is_windows() {
+            ("nt", "win32", "Windows")
+        } else if self.platform.is_osx() {
+            ("posix", "darwin", "Darwin")
+        } else if self.platform.is_linux() {
+            ("posix", "linux", "Linux")
+        } else if self.platform.is_freebsd() {
+            ("posix", "freebsd", "FreeBSD")
+        } else {
+            ("", "", "")
+        };
+
+        MarkerEnvironment::try_from(MarkerEnvironmentBuilder {
+            implementation_name: self.implementation.implementation_name(),
+            implementation_version: &python_full_version,
+            os_name,
+            platform_machine: self.platform_machine(),
+            platform_python_implementation: self.implementation.platform_python_implementation(),
+            platform_release: "",
+            platform_system,
+            platform_version: "",
+            python_full_version: &python_full_version,
+            python_version: &python_version,
+            sys_platform,
+        })
+        .expect("the versions are derived from a valid version")
+    }
+
+    /// Returns the value of the `platform_machine` environment marker.
+    fn platform_machine(&self) -> &'static str {
+        match (self.platform.is_windows(), self.platform.arch()) {
+            (true, Some(Arch::X86_64)) => "AMD64",
+            (true, Some(Arch::X86)) => "x86",
+            (true, Some(Arch::Arm64)) => "ARM64",
+            (_, Some(Arch::X86)) => "i686",
+            (_, Some(Arch::X86_64)) => "x86_64",
+            (_, Some(Arch::Aarch64)) => "aarch64",
+            (_, Some(Arch::Arm64)) => "arm64",
+            (_, Some(Arch::ArmV6l)) => "armv6l",
+            (_, Some(Arch::ArmV7l)) => "armv7l",
+            (_, Some(Arch::Ppc64le)) => "ppc64le",
+            (_, Some(Arch::Ppc64)) => "ppc64",
+            (_, Some(Arch::S390X)) => "s390x",
+            _ => "",
+        }
+    }
+
+    /// Returns true if a wheel with the given file name can be installed for
+    /// this interpreter. Returns true for files that are not wheels.
+    pub fn is_compatible_wheel(&self, file_name: &str) -> bool {
+        let Some(stem) = file_name.strip_suffix(".whl") else {
+            return true;
+        };
+
+        // {distribution}-{version}(-{build tag})?-{python tag}-{abi tag}-{platform tag}
+        let parts = stem.split('-').collect::<Vec<_>>();
+        let [.., python_tags, abi_tag, platform_tags] = parts[..] else {
+            return false;
+        };
+        if parts.len() < 5 {
+            return false;
+        }
+
+        python_tags
+            .split('.')
+            .any(|python_tag| self.is_compatible_python_tag(python_tag, abi_tag))
+            && platform_tags
+                .split('.')
+                .any(|platform_tag| self.is_compatible_platform_tag(platform_tag))
+    }
+
+    fn is_compatible_python_tag(&self, python_tag: &str, abi_tag: &str) -> bool {
+        let (major, minor) = self.major_minor();
+        let parse_version = |version: &str| -> Option<(u64, Option<u64>)> {
+            let major_digit = version.get(..1)?.parse().ok()?;
+            let minor = match version.get(1..) {
+                Some("") | None => None,
+                Some(minor) => Some(minor.parse().ok()?),
+            };
+            Some((major_digit, minor))
+        };
+
+        // Generic python tags are compatible with every later minor version.
+        if let Some(version) = python_tag.strip_prefix("py") {
+            return match parse_version(version) {
+                Some((tag_major, tag_minor)) => {
+                    tag_major == major && tag_minor.map_or(true, |tag_minor| tag_minor <= minor)
+                }
+                None => false,
+            };
+        }
+
+        // Implementation specific tags are only compatible with the same
+        // minor version, unless the wheel uses the stable abi.
+        let Some(version) = python_tag.strip_prefix(self.implementation.wheel_tag_prefix()) else {
+            return false;
+        };
+        match parse_version(version) {
+            Some((tag_major, Some(tag_minor))) if tag_major == major => {
+                tag_minor == minor || (abi_tag == "abi3" && tag_minor <= minor)
+            }
+            _ => false,
+        }
+    }
+
+    fn is_compatible_platform_tag(&self, platform_tag: &str) -> bool {
+        if platform_tag == "any" {
+            return true;
+        }
+
+        let platform = self.platform;
+        if platform.is_windows() {
+            return match platform.arch() {
+                Some(Arch::X86_64) => platform_tag == "win_amd64",
+                Some(Arch::X86) => platform_tag == "win32",
+                Some(Arch::Arm64) => platform_tag == "win_arm64",
+                _ => false,
+            };
+        }
+
+        if platform.is_osx() {
+            let Some(rest) = platform_tag.strip_prefix("macosx_") else {
+                return false;
+            };
+            // macosx_{major}_{minor}_{arch}
+            let arch = rest.splitn(3, '_').nth(2).unwrap_or_default();
+            return match platform.arch() {
+                Some(Arch::X86_64) => {
+                    matches!(arch, "x86_64" | "intel" | "universal" | "universal2")
+                }
+                Some(Arch::Arm64) => matches!(arch, "arm64" | "universal2"),
+                _ => false,
+            };
+        }
+
+        if platform.is_linux() {
+            // Wheels for musl and glibc based distributions are not
+            // interchangeable.
+            let libc_prefix = if self.musl { "musllinux" } else { "manylinux" };
+            let is_linux_tag =
+                platform_tag.starts_with("linux_") || platform_tag.starts_with(libc_prefix);
+            return is_linux_tag
+                && platform_tag
+                    .strip_suffix(self.platform_machine())
+                    .is_some_and(|rest| rest.ends_with('_'));
+        }
+
+        false
+    }
+
+    /// Returns true if the package can be installed for this interpreter,
+    /// based on the python version it requires and the tags of its wheel.
+    ///
+    /// This does not take the environment markers of the requirements on the
+    /// package into account, see [`PythonInterpreter::filter_packages`].
+    pub fn is_compatible(&self, package: &PypiPackageData) -> bool {
+        if let Some(requires_python) = &package.requires_python {
+            if !requires_python.contains(&self.version) {
+                return false;
+            }
+        }
+
+        let file_name = match &package.url_or_path {
+            UrlOrPath::Url(url) => url
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .map(ToString::to_string),
+            UrlOrPath::Path(path) => path
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned()),
+        };
+        file_name.map_or(true, |file_name| self.is_compatible_wheel(&file_name))
+    }
+
+    /// Filters the locked pypi packages of a platform down to the packages
+    /// that apply to this interpreter.
+    ///
+    /// The packages that apply are the `requested` packages and the packages
+    /// they transitively require through requirements whose environment
+    /// markers evaluate to true for this interpreter. Packages that are not
+    /// required by any other locked package are treated as requested as well.
+    /// Packages that are not compatible with the interpreter (see
+    /// [`PythonInterpreter::is_compatible`]) are always excluded.
+    pub fn filter_packages(
+        &self,
+        packages: impl IntoIterator<Item = (PypiPackageData, PypiPackageEnvironmentData)>,
+        requested: impl IntoIterator<Item = PackageName>,
+    ) -> Vec<(PypiPackageData, PypiPackageEnvironmentData)> {
+        let packages = packages
+            .into_iter()
+            .filter(|(package, _)| self.is_compatible(package))
+            .collect::<Vec<_>>();
+
+        let mut by_name = HashMap::<&PackageName, Vec<usize>>::new();
+        for (idx, (package, _)) in packages.iter().enumerate() {
+            by_name.entry(&package.name).or_default().push(idx);
+        }
+
+        let required = packages
+            .iter()
+            .flat_map(|(package, _)| package.requires_dist.iter())
+            .map(|requirement| &requirement.name)
+            .collect::<HashSet<_>>();
+
+        // Walk the requirements that are active for this interpreter, starting
+        // from the requested packages. Packages are only visited once, so
+        // cycles in the requirements do not keep packages alive on their own.
+        let mut included = requested.into_iter().collect::<HashSet<_>>();
+        included.extend(
+            packages
+                .iter()
+                .map(|(package, _)| &package.name)
+                .filter(|name| !required.contains(name))
+                .cloned(),
+        );
+        let mut queue = included.iter().cloned().collect::<Vec<_>>();
+        let marker_environment = self.marker_environment();
+        while let Some(name) = queue.pop() {
+            for &idx in by_name.get(&name).into_iter().flatten() {
+                let (package, environment) = &packages[idx];
+                let extras = environment
+                    .extras
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<ExtraName>>();
+                for requirement in &package.requires_dist {
+                    if requirement.evaluate_markers(&marker_environment, &extras)
+                        && included.insert(requirement.name.clone())
+                    {
+                        queue.push(requirement.name.clone());
+                    }
+                }
+            }
+        }
+
+        packages
+            .into_iter()
+            .filter(|(package, _)| included.contains(&package.name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use pep508_rs::Requirement;
+    use rattler_conda_types::Platform;
+    use rstest::rstest;
+
+    use super::{PythonImplementation, PythonInterpreter};
+    use crate::{PypiPackageData, PypiPackageEnvironmentData, UrlOrPath};
+
+    fn interpreter(platform: Platform, version: &str) -> PythonInterpreter {
+        PythonInterpreter::new(
+            platform,
+            version.parse().unwrap(),
+            PythonImplementation::CPython,
+        )
+    }
+
+    fn package(name: &str, file_name: &str, requires_dist: &[&str]) -> PypiPackageData {
+        PypiPackageData {
+            name: name.parse().unwrap(),
+            version: "1.0".parse().unwrap(),
+            url_or_path: UrlOrPath::Url(
+                format!("https://files.pythonhosted.org/packages/{file_name}")
+                    .parse()
+                    .unwrap(),
+            ),
+            hash: None,
+            requires_dist: requires_dist
+                .iter()
+                .map(|requirement| Requirement::from_str(requirement).unwrap())
+                .collect(),
+            requires_python: None,
+            editable: false,
+            source: None,
+        }
+    }
+
+    #[rstest]
+    #[case(Platform::Linux64, "foo-1.0-py3-none-any.whl", true)]
+    #[case(Platform::Linux64, "foo-1.0.tar.gz", true)]
+    #[case(Platform::Linux64, "foo-1.0-py2-none-any.whl", false)]
+    #[case(Platform::Linux64, "foo-1.0-py2.py3-none-any.whl", true)]
+    #[case(Platform::Linux64, "foo-1.0-py312-none-any.whl", false)]
+    #[case(
+        Platform::Linux64,
+        "foo-1.0-cp311-cp311-manylinux_2_17_x86_64.manylinux2014_x86_64.whl",
+        true
+    )]
+    #[case(
+        Platform::Linux64,
+        "foo-1.0-cp310-cp310-manylinux_2_17_x86_64.whl",
+        false
+    )]
+    #[case(Platform::Linux64, "foo-1.0-cp38-abi3-manylinux_2_17_x86_64.whl", true)]
+    #[case(
+        Platform::Linux64,
+        "foo-1.0-cp311-cp311-manylinux_2_17_aarch64.whl",
+        false
+    )]
+    #[case(
+        Platform::Linux64,
+        "foo-1.0-cp311-cp311-musllinux_1_1_x86_64.whl",
+        false
+    )]
+    #[case(
+        Platform::Linux64,
+        "foo-1.0-pp311-pypy311_pp73-manylinux_2_17_x86_64.whl",
+        false
+    )]
+    #[case(
+        Platform::LinuxAarch64,
+        "foo-1.0-cp311-cp311-manylinux_2_17_aarch64.whl",
+        true
+    )]
+    #[case(Platform::OsxArm64, "foo-1.0-cp311-cp311-macosx_11_0_arm64.whl", true)]
+    #[case(
+        Platform::OsxArm64,
+        "foo-1.0-cp311-cp311-macosx_10_9_universal2.whl",
+        true
+    )]
+    #[case(
+        Platform::OsxArm64,
+        "foo-1.0-cp311-cp311-macosx_10_9_x86_64.whl",
+        false
+    )]
+    #[case(Platform::Osx64, "foo-1.0-cp311-cp311-macosx_10_9_x86_64.whl", true)]
+    #[case(Platform::Win64, "foo-1.0-cp311-cp311-win_amd64.whl", true)]
+    #[case(Platform::Win64, "foo-1.0-cp311-cp311-win32.whl", false)]
+    #[case(Platform::Win64, "foo-1.0-1-cp311-cp311-win_amd64.whl", true)]
+    #[case(Platform::Win64, "foo-1.0.whl", false)]
+    fn test_is_compatible_wheel(
+        #[case] platform: Platform,
+        #[case] file_name: &str,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(
+            interpreter(platform, "3.11.4").is_compatible_wheel(file_name),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_is_compatible_wheel_musl() {
+        let interpreter = interpreter(Platform::Linux64, "3.11.4").with_musl(true);
+        assert!(interpreter.is_compatible_wheel("foo-1.0-cp311-cp311-musllinux_1_1_x86_64.whl"));
+        assert!(!interpreter.is_compatible_wheel("foo-1.0-cp311-cp311-manylinux_2_17_x86_64.whl"));
+        assert!(interpreter.is_compatible_wheel("foo-1.0-py3-none-any.whl"));
+    }
+
+    #[test]
+    fn test_is_compatible_requires_python() {
+        let mut package = package("foo", "foo-1.0-py3-none-any.whl", &[]);
+        package.requires_python = Some(">=3.12".parse().unwrap());
+        assert!(!interpreter(Platform::Linux64, "3.11.4").is_compatible(&package));
+        assert!(interpreter(Platform::Linux64, "3.12.0").is_compatible(&package));
+    }
+
+    #[test]
+    fn test_filter_packages() {
+        let packages = [
+            package(
+                "app",
+                "app-1.0-py3-none-any.whl",
+                &[
+                    "colorama; sys_platform == 'win32'",
+                    "uvloop; sys_platform != 'win32'",
+                    "tomli; python_version < '3.11'",
+                    "rich",
+                ],
+            ),
+            package("colorama", "colorama-1.0-py2.py3-none-any.whl", &[]),
+            package(
+                "uvloop",
+                "uvloop-1.0-cp311-cp311-manylinux_2_17_x86_64.whl",
+                &[],
+            ),
+            package("tomli", "tomli-1.0-py3-none-any.whl", &[]),
+            package(
+                "rich",
+                "rich-1.0-py3-none-any.whl",
+                &["pygments", "typing-extensions; extra == 'typing'"],
+            ),
+            package("pygments", "pygments-1.0-py3-none-any.whl", &[]),
+            package(
+                "typing-extensions",
+                "typing_extensions-1.0-py3-none-any.whl",
+                &[],
+            ),
+        ]
+        .map(|package| (package, PypiPackageEnvironmentData::default()));
+
+        let names = |platform: Platform, version: &str| {
+            interpreter(platform, version)
+                .filter_packages(packages.iter().cloned(), [])
+                .into_iter()
+                .map(|(package, _)| package.name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(Platform::Linux64, "3.11.4"),
+            ["app", "uvloop", "rich", "pygments"]
+        );
+        assert_eq!(
+            names(Platform::Win64, "3.10.0"),
+            ["app", "colorama", "tomli", "rich", "pygments"]
+        );
+
+        // Extras of a package enable the requirements of that extra.
+        let mut packages = packages.to_vec();
+        packages[4].1.extras.insert("typing".parse().unwrap());
+        let names = interpreter(Platform::Linux64, "3.11.4")
+            .filter_packages(packages, [])
+            .into_iter()
+            .map(|(package, _)| package.name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["app", "uvloop", "rich", "pygments", "typing-extensions"]
+        );
+    }
+
+    #[test]
+    fn test_filter_packages_cycle() {
+        // `a` and `b` require each other, but are only required by `app` on
+        // windows.
+        let packages = [
+            package(
+                "app",
+                "app-1.0-py3-none-any.whl",
+                &["a; sys_platform == 'win32'"],
+            ),
+            package("a", "a-1.0-py3-none-any.whl", &["b"]),
+            package("b", "b-1.0-py3-none-any.whl", &["a"]),
+        ]
+        .map(|package| (package, PypiPackageEnvironmentData::default()));
+
+        let names = |platform: Platform| {
+            interpreter(platform, "3.11.4")
+                .filter_packages(packages.iter().cloned(), [])
+                .into_iter()
+                .map(|(package, _)| package.name.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(Platform::Linux64), ["app"]);
+        assert_eq!(names(Platform::Win64), ["app", "a", "b"]);
+    }
+
+    #[test]
+    fn test_filter_packages_requested() {
+        // `colorama` is requested directly, but is also required by `app` on
+        // windows only.
+        let packages = [
+            package(
+                "app",
+                "app-1.0-py3-none-any.whl",
+                &["colorama; sys_platform == 'win32'"],
+            ),
+            package("colorama", "colorama-1.0-py2.py3-none-any.whl", &[]),
+        ]
+        .map(|package| (package, PypiPackageEnvironmentData::default()));
+
+        let names = interpreter(Platform::Linux64, "3.11.4")
+            .filter_packages(packages, ["colorama".parse().unwrap()])
+            .into_iter()
+            .map(|(package, _)| package.name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["app", "colorama"]);
+    }
+}
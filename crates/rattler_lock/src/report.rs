@@ -0,0 +1,373 @@
+//! Rendering of human-readable reports of lock-files.
+//!
+//! Reports summarize an [`Environment`] or a [`LockFileDiff`] as Markdown or
+//! HTML, e.g. to attach them to release notes or pull request comments.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use itertools::Itertools;
+
+use crate::{Environment, LockFileDiff, Package};
+
+/// The format in which a report is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    /// GitHub flavored Markdown.
+    #[default]
+    Markdown,
+
+    /// An HTML fragment that can be embedded in a larger document.
+    Html,
+}
+
+/// Renders a report of the environment with the given name. The report lists
+/// the channels of the environment, a table of the packages of every platform
+/// and a summary of the licenses of all packages.
+pub fn render_environment_report(
+    name: &str,
+    environment: &Environment,
+    format: ReportFormat,
+) -> String {
+    let mut document = Document::new(format);
+    document.heading(1, &format!("Environment {name}"));
+
+    document.heading(2, "Channels");
+    document.list(
+        environment
+            .channels()
+            .iter()
+            .map(|channel| channel.url.clone()),
+    );
+    if let Some(indexes) = environment.pypi_indexes() {
+        document.heading(2, "PyPI indexes");
+        document.list(indexes.indexes.iter().map(ToString::to_string));
+    }
+
+    let mut licenses = BTreeMap::<String, BTreeSet<String>>::new();
+    for platform in environment
+        .platforms()
+        .sorted_by_key(|platform| platform.as_str())
+    {
+        let packages = environment
+            .packages(platform)
+            .into_iter()
+            .flatten()
+            .sorted_by(|a, b| a.name().cmp(&b.name()))
+            .collect::<Vec<_>>();
+
+        document.heading(2, platform.as_str());
+        document.table(
+            &["Package", "Version", "Build", "Channel", "License"],
+            packages.iter().map(|package| {
+                vec![
+                    package.name().into_owned(),
+                    package.version().into_owned(),
+                    build_string(package),
+                    channel(package),
+                    license(package),
+                ]
+            }),
+        );
+
+        for package in &packages {
+            licenses
+                .entry(license(package))
+                .or_default()
+                .insert(package.name().into_owned());
+        }
+    }
+
+    document.heading(2, "Licenses");
+    document.table(
+        &["License", "Packages"],
+        licenses
+            .into_iter()
+            .map(|(license, packages)| vec![license, packages.len().to_string()]),
+    );
+
+    document.finish()
+}
+
+/// Renders a report of the differences between two versions of a lock-file.
+/// For every environment and platform that changed, the report contains a
+/// table of the added, removed and changed packages.
+pub fn render_diff_report(diff: &LockFileDiff, format: ReportFormat) -> String {
+    let mut document = Document::new(format);
+    document.heading(1, "Lock-file changes");
+
+    if diff.is_empty() {
+        document.paragraph("No packages changed.");
+        return document.finish();
+    }
+
+    for (name, environment) in &diff.environments {
+        document.heading(2, &format!("Environment {name}"));
+        for (platform, platform_diff) in &environment.platforms {
+            document.heading(3, platform.as_str());
+
+            let added = platform_diff
+                .added
+                .iter()
+                .map(|package| (package, None, Some(package)));
+            let removed = platform_diff
+                .removed
+                .iter()
+                .map(|package| (package, Some(package), None));
+            let changed = platform_diff
+                .changed
+                .iter()
+                .map(|(previous, current)| (current, Some(previous), Some(current)));
+
+            document.table(
+                &["Package", "Before", "After", "Channel", "License"],
+                added
+                    .chain(removed)
+                    .chain(changed)
+                    .sorted_by(|(a, ..), (b, ..)| a.name().cmp(&b.name()))
+                    .map(|(package, previous, current)| {
+                        vec![
+                            package.name().into_owned(),
+                            previous.map_or_else(String::new, version_string),
+                            current.map_or_else(String::new, version_string),
+                            channel(package),
+                            license(package),
+                        ]
+                    }),
+            );
+        }
+    }
+
+    document.finish()
+}
+
+/// Returns the version and the build string of a package.
+fn version_string(package: &Package) -> String {
+    match package.as_conda() {
+        Some(conda) => format!("{} ({})", package.version(), conda.package_record().build),
+        None => package.version().into_owned(),
+    }
+}
+
+/// Returns the build string of a package, pypi packages do not have one.
+fn build_string(package: &Package) -> String {
+    package
+        .as_conda()
+        .map(|conda| conda.package_record().build.clone())
+        .unwrap_or_default()
+}
+
+/// Returns where a package originates from.
+fn channel(package: &Package) -> String {
+    match package.as_conda() {
+        Some(conda) => conda.channel().as_ref().unwrap_or(conda.url()).to_string(),
+        None => String::from("pypi"),
+    }
+}
+
+/// Returns the license of a package or `unknown` if it is not known.
+fn license(package: &Package) -> String {
+    package
+        .as_conda()
+        .and_then(|conda| conda.package_record().license.clone())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+/// A minimal document builder that renders either Markdown or HTML.
+struct Document {
+    format: ReportFormat,
+    output: String,
+}
+
+impl Document {
+    fn new(format: ReportFormat) -> Self {
+        Self {
+            format,
+            output: String::new(),
+        }
+    }
+
+    fn escape(&self, text: &str) -> String {
+        match self.format {
+            // Line breaks would end a table row or list item, they are replaced
+            // by spaces. `<` and `&` are escaped so they are not interpreted as
+            // HTML.
+            ReportFormat::Markdown => {
+                let mut escaped = String::with_capacity(text.len());
+                for (idx, line) in text.lines().enumerate() {
+                    if idx > 0 {
+                        escaped.push(' ');
+                    }
+                    for c in line.chars() {
+                        if matches!(c, '\\' | '|' | '<' | '>' | '&') {
+                            escaped.push('\\');
+                        }
+                        escaped.push(c);
+                    }
+                }
+                escaped
+            }
+            ReportFormat::Html => text
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;"),
+        }
+    }
+
+    fn heading(&mut self, level: usize, text: &str) {
+        let text = self.escape(text);
+        match self.format {
+            ReportFormat::Markdown => {
+                self.output
+                    .push_str(&format!("{} {text}\n\n", "#".repeat(level)));
+            }
+            ReportFormat::Html => {
+                self.output
+                    .push_str(&format!("<h{level}>{text}</h{level}>\n"));
+            }
+        }
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        let text = self.escape(text);
+        match self.format {
+            ReportFormat::Markdown => self.output.push_str(&format!("{text}\n\n")),
+            ReportFormat::Html => self.output.push_str(&format!("<p>{text}</p>\n")),
+        }
+    }
+
+    fn list(&mut self, items: impl IntoIterator<Item = String>) {
+        let items = items
+            .into_iter()
+            .map(|item| self.escape(&item))
+            .collect::<Vec<_>>();
+        match self.format {
+            ReportFormat::Markdown => {
+                for item in items {
+                    self.output.push_str(&format!("- {item}\n"));
+                }
+                self.output.push('\n');
+            }
+            ReportFormat::Html => {
+                self.output.push_str("<ul>\n");
+                for item in items {
+                    self.output.push_str(&format!("<li>{item}</li>\n"));
+                }
+                self.output.push_str("</ul>\n");
+            }
+        }
+    }
+
+    fn table(&mut self, header: &[&str], rows: impl IntoIterator<Item = Vec<String>>) {
+        let rows = rows
+            .into_iter()
+            .map(|row| row.iter().map(|cell| self.escape(cell)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        match self.format {
+            ReportFormat::Markdown => {
+                self.output
+                    .push_str(&format!("| {} |\n", header.iter().join(" | ")));
+                self.output
+                    .push_str(&format!("|{}\n", "---|".repeat(header.len())));
+                for row in rows {
+                    self.output.push_str(&format!("| {} |\n", row.join(" | ")));
+                }
+                self.output.push('\n');
+            }
+            ReportFormat::Html => {
+                self.output.push_str("<table>\n<thead>\n<tr>");
+                for cell in header {
+                    self.output.push_str(&format!("<th>{cell}</th>"));
+                }
+                self.output.push_str("</tr>\n</thead>\n<tbody>\n");
+                for row in rows {
+                    self.output.push_str("<tr>");
+                    for cell in row {
+                        self.output.push_str(&format!("<td>{cell}</td>"));
+                    }
+                    self.output.push_str("</tr>\n");
+                }
+                self.output.push_str("</tbody>\n</table>\n");
+            }
+        }
+    }
+
+    fn finish(self) -> String {
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{render_diff_report, render_environment_report, Document, ReportFormat};
+    use crate::{LockFile, LockFileDiff, DEFAULT_ENVIRONMENT_NAME};
+
+    fn lock_file(file_name: &str) -> LockFile {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test-data/conda-lock")
+            .join(file_name);
+        LockFile::from_path(&path).unwrap()
+    }
+
+    #[test]
+    fn test_environment_report() {
+        let lock_file = lock_file("v4/python-lock.yml");
+        let environment = lock_file.default_environment().unwrap();
+
+        let markdown = render_environment_report(
+            DEFAULT_ENVIRONMENT_NAME,
+            &environment,
+            ReportFormat::Markdown,
+        );
+        assert!(markdown.starts_with("# Environment default\n"));
+        assert!(markdown.contains("- conda-forge\n"));
+        assert!(markdown.contains("## linux-64\n"));
+        assert!(markdown.contains(
+            "| python | 3.11.0 | he550d4f_1_cpython | https://conda.anaconda.org/conda-forge"
+        ));
+        assert!(markdown.contains("## Licenses\n"));
+
+        let html =
+            render_environment_report(DEFAULT_ENVIRONMENT_NAME, &environment, ReportFormat::Html);
+        assert!(html.starts_with("<h1>Environment default</h1>\n"));
+        assert!(html.contains("<td>python</td><td>3.11.0</td><td>he550d4f_1_cpython</td>"));
+    }
+
+    #[test]
+    fn test_diff_report() {
+        let numpy = lock_file("v4/numpy-lock.yml");
+        let python = lock_file("v4/python-lock.yml");
+
+        let report = render_diff_report(
+            &LockFileDiff::from_lock_files(&numpy, &numpy),
+            ReportFormat::Markdown,
+        );
+        assert!(report.contains("No packages changed."));
+
+        let report = render_diff_report(
+            &LockFileDiff::from_lock_files(&python, &numpy),
+            ReportFormat::Markdown,
+        );
+        assert!(report.contains("## Environment default\n"));
+        assert!(report
+            .contains("| python | 3.11.0 (he550d4f_1_cpython) | 3.9.16 (h2782a2a_0_cpython) |"));
+        assert!(report.contains("| numpy |  | 1.24.2 (py39h7360e5f_0) |"));
+    }
+
+    #[test]
+    fn test_escape() {
+        let markdown = Document::new(ReportFormat::Markdown);
+        assert_eq!(
+            markdown.escape("BSD-3-Clause AND\nMIT | <script>&amp;\r\nfoo\\bar"),
+            "BSD-3-Clause AND MIT \\| \\<script\\>\\&amp; foo\\\\bar"
+        );
+
+        let html = Document::new(ReportFormat::Html);
+        assert_eq!(
+            html.escape("<a href=\"x\">&</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use url::Url;
 
 fn is_default<T: Default + Eq>(value: &T) -> bool {
@@ -65,6 +65,8 @@ pub(crate) struct RawCondaPackageData<'a> {
     pub depends: Cow<'a, Vec<String>>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub constrains: Cow<'a, Vec<String>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra_depends: Cow<'a, BTreeMap<String, Vec<String>>>,
 
     // Additional properties (in semi alphabetic order but grouped by commonality)
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -89,6 +91,8 @@ pub(crate) struct RawCondaPackageData<'a> {
     pub license_family: Cow<'a, Option<String>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub purls: Cow<'a, Option<BTreeSet<PackageUrl>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub python_site_packages_path: Cow<'a, Option<String>>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub size: Cow<'a, Option<u64>>,
@@ -103,29 +107,33 @@ pub(crate) struct RawCondaPackageData<'a> {
 impl<'a> From<RawCondaPackageData<'a>> for CondaPackageData {
     fn from(value: RawCondaPackageData<'a>) -> Self {
         Self {
-            package_record: PackageRecord {
-                arch: value.arch.into_owned(),
-                build: value.build.into_owned(),
-                build_number: value.build_number,
-                constrains: value.constrains.into_owned(),
-                depends: value.depends.into_owned(),
-                features: value.features.into_owned(),
-                legacy_bz2_md5: value.legacy_bz2_md5,
-                legacy_bz2_size: value.legacy_bz2_size.into_owned(),
-                license: value.license.into_owned(),
-                license_family: value.license_family.into_owned(),
-                md5: value.md5,
-                name: value.name.into_owned(),
-                noarch: value.noarch.into_owned(),
-                platform: value.platform.into_owned(),
-                purls: value.purls.into_owned(),
-                sha256: value.sha256,
-                size: value.size.into_owned(),
-                subdir: value.subdir.into_owned(),
-                timestamp: value.timestamp,
-                track_features: value.track_features.into_owned(),
-                version: value.version.into_owned(),
-                run_exports: None,
+            package_record: {
+                let mut record = PackageRecord::new(
+                    value.name.into_owned(),
+                    value.version.into_owned(),
+                    value.build.into_owned(),
+                );
+                record.arch = value.arch.into_owned();
+                record.build_number = value.build_number;
+                record.constrains = value.constrains.into_owned();
+                record.depends = value.depends.into_owned();
+                record.extra_depends = value.extra_depends.into_owned();
+                record.features = value.features.into_owned();
+                record.legacy_bz2_md5 = value.legacy_bz2_md5;
+                record.legacy_bz2_size = value.legacy_bz2_size.into_owned();
+                record.license = value.license.into_owned();
+                record.license_family = value.license_family.into_owned();
+                record.md5 = value.md5;
+                record.noarch = value.noarch.into_owned();
+                record.platform = value.platform.into_owned();
+                record.purls = value.purls.into_owned();
+                record.python_site_packages_path = value.python_site_packages_path.into_owned();
+                record.sha256 = value.sha256;
+                record.size = value.size.into_owned();
+                record.subdir = value.subdir.into_owned();
+                record.timestamp = value.timestamp;
+                record.track_features = value.track_features.into_owned();
+                record
             },
             url: value.url.into_owned(),
             file_name: value.file_name.into_owned(),
@@ -147,8 +155,12 @@ impl<'a> From<&'a CondaPackageData> for RawCondaPackageData<'a> {
             channel: Cow::Borrowed(&value.channel),
             file_name: Cow::Borrowed(&value.file_name),
             purls: Cow::Borrowed(&value.package_record.purls),
+            python_site_packages_path: Cow::Borrowed(
+                &value.package_record.python_site_packages_path,
+            ),
             depends: Cow::Borrowed(&value.package_record.depends),
             constrains: Cow::Borrowed(&value.package_record.constrains),
+            extra_depends: Cow::Borrowed(&value.package_record.extra_depends),
             platform: Cow::Borrowed(&value.package_record.platform),
             arch: Cow::Borrowed(&value.package_record.arch),
             md5: value.package_record.md5,
@@ -13,6 +13,7 @@ use url::Url;
 #[derive(Clone, Default)]
 pub struct AuthenticationMiddleware {
     auth_storage: AuthenticationStorage,
+    url_credentials: Vec<(Url, Authentication)>,
 }
 
 #[async_trait]
@@ -29,7 +30,11 @@ impl Middleware for AuthenticationMiddleware {
         }
 
         let url = req.url().clone();
-        match self.auth_storage.get_by_url(url) {
+        let credentials = match self.url_credentials(&url) {
+            Some(auth) => Ok((url, Some(auth.clone()))),
+            None => self.auth_storage.get_by_url(url),
+        };
+        match credentials {
             Err(_) => {
                 // Forward error to caller (invalid URL)
                 next.run(req, extensions).await
@@ -50,7 +55,40 @@ impl Middleware for AuthenticationMiddleware {
 impl AuthenticationMiddleware {
     /// Create a new authentication middleware with the given authentication storage
     pub fn new(auth_storage: AuthenticationStorage) -> Self {
-        Self { auth_storage }
+        Self {
+            auth_storage,
+            url_credentials: Vec::new(),
+        }
+    }
+
+    /// Adds credentials that are used to authenticate requests to urls that
+    /// start with `base_url`, e.g. the base url of a channel. These take
+    /// precedence over the credentials of the authentication storage. If the
+    /// base urls of multiple credentials match a request, the credentials
+    /// with the longest base url are used.
+    #[must_use]
+    pub fn with_url_credentials(mut self, base_url: Url, authentication: Authentication) -> Self {
+        self.url_credentials.push((base_url, authentication));
+        self
+    }
+
+    /// Returns the credentials with the longest base url that matches the
+    /// given url.
+    fn url_credentials(&self, url: &Url) -> Option<&Authentication> {
+        self.url_credentials
+            .iter()
+            .filter(|(base_url, _)| {
+                let base_path = base_url.path().trim_end_matches('/');
+                base_url.scheme() == url.scheme()
+                    && base_url.host_str() == url.host_str()
+                    && base_url.port_or_known_default() == url.port_or_known_default()
+                    && url
+                        .path()
+                        .strip_prefix(base_path)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(base_url, _)| base_url.path().trim_end_matches('/').len())
+            .map(|(_, authentication)| authentication)
     }
 
     /// Authenticate the given URL with the given authentication information
@@ -405,6 +443,54 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_netrc_default_fallback() -> anyhow::Result<()> {
+        use crate::authentication_storage::{backends::netrc::NetRcStorage, StorageBackend};
+
+        let tdir = tempdir()?;
+        let netrc_path = tdir.path().join(".netrc");
+        std::fs::write(&netrc_path, "default\nlogin anonymous\npassword secret\n")?;
+
+        let wildcard = Authentication::BearerToken("testtoken".to_string());
+        let file_storage = FileStorage::new(tdir.path().join("auth.json"))?;
+        file_storage.store("*.prefix.dev", &wildcard)?;
+
+        let storage_with_netrc = |use_default_machine: bool| -> anyhow::Result<_> {
+            let mut storage = AuthenticationStorage::new();
+            storage.add_backend(Arc::from(file_storage.clone()));
+            storage.add_backend(Arc::from(
+                NetRcStorage::from_path(&netrc_path)?.with_default_machine(use_default_machine),
+            ));
+            Ok(storage)
+        };
+
+        // The wildcard credentials take precedence over the default entry.
+        let storage = storage_with_netrc(true)?;
+        let (_, credentials) =
+            storage.get_by_url("https://repo.prefix.dev/conda-forge/noarch/repodata.json")?;
+        assert_eq!(credentials, Some(wildcard.clone()));
+
+        // Other hosts use the default entry.
+        let (_, credentials) = storage.get_by_url("https://example.com/channel/")?;
+        assert_eq!(
+            credentials,
+            Some(Authentication::BasicHTTP {
+                username: "anonymous".to_string(),
+                password: "secret".to_string(),
+            })
+        );
+
+        // Unless the default entry is not enabled.
+        let storage = storage_with_netrc(false)?;
+        let (_, credentials) = storage.get_by_url("https://example.com/channel/")?;
+        assert_eq!(credentials, None);
+        let (_, credentials) =
+            storage.get_by_url("https://repo.prefix.dev/conda-forge/noarch/repodata.json")?;
+        assert_eq!(credentials, Some(wildcard));
+
+        Ok(())
+    }
+
     #[test]
     fn test_rattler_auth_file_env_var_handling() -> anyhow::Result<()> {
         let tdir = tempdir()?;
@@ -0,0 +1,78 @@
+//! In-memory storage for credentials.
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+
+use crate::{authentication_storage::StorageBackend, Authentication};
+
+/// A struct that implements storage and access of authentication
+/// information in memory. Credentials are not persisted, which makes this
+/// backend useful to provide credentials that are configured by an
+/// application.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    credentials: Arc<RwLock<HashMap<String, Authentication>>>,
+}
+
+impl MemoryStorage {
+    /// Create a new empty memory storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<H: Into<String>> FromIterator<(H, Authentication)> for MemoryStorage {
+    fn from_iter<T: IntoIterator<Item = (H, Authentication)>>(iter: T) -> Self {
+        Self {
+            credentials: Arc::new(RwLock::new(
+                iter.into_iter()
+                    .map(|(host, authentication)| (host.into(), authentication))
+                    .collect(),
+            )),
+        }
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn store(&self, host: &str, authentication: &Authentication) -> Result<()> {
+        self.credentials
+            .write()
+            .unwrap()
+            .insert(host.to_string(), authentication.clone());
+        Ok(())
+    }
+
+    fn get(&self, host: &str) -> Result<Option<Authentication>> {
+        Ok(self.credentials.read().unwrap().get(host).cloned())
+    }
+
+    fn delete(&self, host: &str) -> Result<()> {
+        self.credentials.write().unwrap().remove(host);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryStorage;
+    use crate::{authentication_storage::StorageBackend, Authentication};
+
+    #[test]
+    fn test_memory_storage() {
+        let storage = MemoryStorage::from_iter([(
+            "repo.prefix.dev",
+            Authentication::BearerToken(String::from("token")),
+        )]);
+        assert_eq!(
+            storage.get("repo.prefix.dev").unwrap(),
+            Some(Authentication::BearerToken(String::from("token")))
+        );
+        assert_eq!(storage.get("conda.anaconda.org").unwrap(), None);
+
+        storage.delete("repo.prefix.dev").unwrap();
+        assert_eq!(storage.get("repo.prefix.dev").unwrap(), None);
+    }
+}
@@ -10,6 +10,12 @@ use std::{collections::HashMap, env, io::ErrorKind, path::Path, path::PathBuf};
 pub struct NetRcStorage {
     /// The netrc file contents
     machines: HashMap<String, Machine>,
+
+    /// The `default` entry of the netrc file
+    default: Option<Machine>,
+
+    /// Whether the `default` entry is used for hosts without credentials
+    use_default_machine: bool,
 }
 
 /// An error that can occur when accessing the fallback storage
@@ -63,24 +69,49 @@ impl NetRcStorage {
     pub fn from_path(path: &Path) -> Result<Self, NetRcStorageError> {
         let content = std::fs::read_to_string(path)?;
         let netrc = Netrc::parse(content, false).map_err(NetRcStorageError::ParseError)?;
-        let machines = netrc
-            .machines
-            .into_iter()
-            .map(|m| (m.name.clone(), m))
-            .filter_map(|(name, value)| name.map(|n| (n, value)))
-            .collect();
-        Ok(Self { machines })
+        let mut machines = HashMap::new();
+        let mut default = None;
+        for machine in netrc.machines {
+            match machine.name.clone() {
+                Some(name) => {
+                    machines.insert(name, machine);
+                }
+                // Like curl, only the first `default` entry is used
+                None => {
+                    default.get_or_insert(machine);
+                }
+            }
+        }
+        Ok(Self {
+            machines,
+            default,
+            use_default_machine: false,
+        })
+    }
+
+    /// Sets whether the credentials of the `default` entry of the netrc file
+    /// are used for hosts that have no credentials in any storage backend
+    /// (defaults to false). Note that these credentials are then sent to every
+    /// host without credentials, including mirrors and package hosts.
+    #[must_use]
+    pub fn with_default_machine(self, use_default_machine: bool) -> Self {
+        Self {
+            use_default_machine,
+            ..self
+        }
     }
 
     /// Retrieve the authentication information for the given host
     pub fn get_password(&self, host: &str) -> Result<Option<Authentication>, NetRcStorageError> {
-        match self.machines.get(host) {
-            Some(machine) => Ok(Some(Authentication::BasicHTTP {
-                username: machine.login.clone().unwrap_or_default(),
-                password: machine.password.clone().unwrap_or_default(),
-            })),
-            None => Ok(None),
-        }
+        Ok(self.machines.get(host).map(machine_authentication))
+    }
+}
+
+/// Returns the credentials of a netrc entry.
+fn machine_authentication(machine: &Machine) -> Authentication {
+    Authentication::BasicHTTP {
+        username: machine.login.clone().unwrap_or_default(),
+        password: machine.password.clone().unwrap_or_default(),
     }
 }
 
@@ -100,6 +131,13 @@ impl StorageBackend for NetRcStorage {
             Err(err) => Err(anyhow::Error::new(err)),
         }
     }
+
+    fn get_fallback(&self) -> anyhow::Result<Option<Authentication>> {
+        if !self.use_default_machine {
+            return Ok(None);
+        }
+        Ok(self.default.as_ref().map(machine_authentication))
+    }
 }
 
 #[cfg(test)]
@@ -131,6 +169,44 @@ mod tests {
         assert_eq!(storage.get("test_unknown").unwrap(), None);
     }
 
+    #[test]
+    fn test_default_machine() {
+        let file = tempdir().unwrap();
+        let path = file.path().join(".testnetrc3");
+
+        let mut netrc = std::fs::File::create(&path).unwrap();
+        netrc
+            .write_all(
+                b"machine mainmachine\nlogin test\npassword password\n\
+                  default\nlogin anonymous\npassword secret\n",
+            )
+            .unwrap();
+        netrc.flush().unwrap();
+
+        let storage = NetRcStorage::from_path(path.as_path()).unwrap();
+        assert_eq!(
+            storage.get("mainmachine").unwrap(),
+            Some(Authentication::BasicHTTP {
+                username: "test".to_string(),
+                password: "password".to_string(),
+            })
+        );
+
+        // The default entry is never returned for a specific host and is only
+        // used as a fallback when enabled.
+        assert_eq!(storage.get("othermachine").unwrap(), None);
+        assert_eq!(storage.get_fallback().unwrap(), None);
+        let storage = storage.with_default_machine(true);
+        assert_eq!(storage.get("othermachine").unwrap(), None);
+        assert_eq!(
+            storage.get_fallback().unwrap(),
+            Some(Authentication::BasicHTTP {
+                username: "anonymous".to_string(),
+                password: "secret".to_string(),
+            })
+        );
+    }
+
     #[test]
     fn test_file_storage_from_env() {
         let file = tempdir().unwrap();
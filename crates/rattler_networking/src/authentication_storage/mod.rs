@@ -14,6 +14,13 @@ pub trait StorageBackend: std::fmt::Debug {
     /// Retrieve the authentication information for the given host
     fn get(&self, host: &str) -> Result<Option<Authentication>>;
 
+    /// Retrieve the authentication information that is used for hosts that
+    /// have no credentials in any backend, e.g. the `default` entry of a netrc
+    /// file. By default backends have no fallback credentials.
+    fn get_fallback(&self) -> Result<Option<Authentication>> {
+        Ok(None)
+    }
+
     /// Delete the authentication information for the given host
     fn delete(&self, host: &str) -> Result<()>;
 }
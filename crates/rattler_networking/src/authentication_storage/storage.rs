@@ -91,6 +91,12 @@ impl AuthenticationStorage {
         self.backends.push(backend);
     }
 
+    /// Add a new storage backend that takes precedence over all backends that
+    /// were added before
+    pub fn prepend_backend(&mut self, backend: Arc<dyn StorageBackend + Send + Sync>) {
+        self.backends.insert(0, backend);
+    }
+
     /// Store the given authentication information for the given host
     pub fn store(&self, host: &str, authentication: &Authentication) -> Result<()> {
         {
@@ -144,6 +150,10 @@ impl AuthenticationStorage {
     /// E.g. if credentials are stored for `*.prefix.dev` and the
     /// given URL is `https://repo.prefix.dev`, the credentials
     /// for `*.prefix.dev` will be returned.
+    ///
+    /// If no credentials are found for the host or any of the wildcard
+    /// hosts, the fallback credentials of the backends are returned (see
+    /// [`StorageBackend::get_fallback`]).
     pub fn get_by_url<U: IntoUrl>(
         &self,
         url: U,
@@ -161,7 +171,8 @@ impl AuthenticationStorage {
 
         // Check for credentials under e.g. `*.prefix.dev`
         let Some(mut domain) = url.domain() else {
-            return Ok((url, None));
+            let credentials = self.get_fallback();
+            return Ok((url, credentials));
         };
 
         loop {
@@ -181,11 +192,29 @@ impl AuthenticationStorage {
                 Some(rest) => {
                     domain = rest;
                 }
-                _ => return Ok((url, None)), // No more subdomains to check
+                // No more subdomains to check
+                _ => {
+                    let credentials = self.get_fallback();
+                    return Ok((url, credentials));
+                }
             }
         }
     }
 
+    /// Returns the fallback credentials of the first backend that has them,
+    /// see [`StorageBackend::get_fallback`].
+    fn get_fallback(&self) -> Option<Authentication> {
+        self.backends
+            .iter()
+            .find_map(|backend| match backend.get_fallback() {
+                Ok(credentials) => credentials,
+                Err(e) => {
+                    tracing::warn!("Error retrieving fallback credentials from backend: {}", e);
+                    None
+                }
+            })
+    }
+
     /// Delete the authentication information for the given host
     pub fn delete(&self, host: &str) -> Result<()> {
         {
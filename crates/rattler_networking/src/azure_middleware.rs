@@ -0,0 +1,448 @@
+//! Middleware to authenticate requests to channels hosted on Azure Blob
+//! storage, including `az://` URLs.
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use http::{header::AUTHORIZATION, Extensions, HeaderValue};
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next, Result as MiddlewareResult};
+use serde::Deserialize;
+use url::Url;
+
+/// The resource (or scope) to request tokens for.
+const STORAGE_RESOURCE: &str = "https://storage.azure.com/";
+
+/// The version of the Blob storage REST API. Authenticating with a bearer
+/// token requires a version to be specified.
+const STORAGE_API_VERSION: &str = "2021-08-06";
+
+/// The endpoint of the instance metadata service used for managed identities.
+const IMDS_ENDPOINT: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// Tokens are refreshed this long before they actually expire.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// After failing to obtain a token, no new attempt is made for this long.
+const FAILURE_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(thiserror::Error, Debug)]
+enum AzureMiddlewareError {
+    #[error("failed to request an access token: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error("the access token is not a valid header value")]
+    InvalidToken(#[from] http::header::InvalidHeaderValue),
+
+    #[error("invalid Azure storage url: {0}")]
+    InvalidUrl(String),
+
+    #[error("no access token is available, the last attempt failed recently")]
+    RecentlyFailed,
+}
+
+/// The credentials that are used to authenticate requests.
+#[derive(Clone, PartialEq, Eq)]
+enum AzureCredential {
+    /// A shared access signature that is appended to the query of the url.
+    SasToken(String),
+
+    /// A service principal that authenticates with a client secret.
+    ClientSecret {
+        tenant_id: String,
+        client_id: String,
+        client_secret: String,
+    },
+
+    /// The managed identity of the Azure resource the process is running on.
+    ManagedIdentity { client_id: Option<String> },
+}
+
+impl AzureCredential {
+    /// Determines the credentials from the environment, similar to the
+    /// default credential chain of the Azure SDKs:
+    ///
+    /// 1. A shared access signature in `AZURE_STORAGE_SAS_TOKEN`.
+    /// 2. A service principal described by `AZURE_TENANT_ID`,
+    ///    `AZURE_CLIENT_ID` and `AZURE_CLIENT_SECRET`.
+    /// 3. The managed identity of the host, optionally selected with
+    ///    `AZURE_CLIENT_ID`.
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        if let Some(sas_token) = var("AZURE_STORAGE_SAS_TOKEN") {
+            return Self::SasToken(sas_token.trim_start_matches('?').to_string());
+        }
+        let client_id = var("AZURE_CLIENT_ID");
+        if let (Some(tenant_id), Some(client_id), Some(client_secret)) = (
+            var("AZURE_TENANT_ID"),
+            client_id.clone(),
+            var("AZURE_CLIENT_SECRET"),
+        ) {
+            return Self::ClientSecret {
+                tenant_id,
+                client_id,
+                client_secret,
+            };
+        }
+        Self::ManagedIdentity { client_id }
+    }
+}
+
+impl std::fmt::Debug for AzureCredential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print secrets.
+        match self {
+            Self::SasToken(_) => f.debug_tuple("SasToken").field(&"<redacted>").finish(),
+            Self::ClientSecret {
+                tenant_id,
+                client_id,
+                ..
+            } => f
+                .debug_struct("ClientSecret")
+                .field("tenant_id", tenant_id)
+                .field("client_id", client_id)
+                .finish_non_exhaustive(),
+            Self::ManagedIdentity { client_id } => f
+                .debug_struct("ManagedIdentity")
+                .field("client_id", client_id)
+                .finish(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(deserialize_with = "deserialize_seconds")]
+    expires_in: u64,
+}
+
+/// The token endpoints return the expiry either as a number or as a string.
+fn deserialize_seconds<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(u64),
+        String(String),
+    }
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(seconds) => Ok(seconds),
+        NumberOrString::String(seconds) => seconds.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AccessToken {
+    token: String,
+    expires_at: SystemTime,
+}
+
+/// Middleware to authenticate requests to Azure Blob storage.
+///
+/// Requests to `az://<account>/<container>/<path>` urls are rewritten to
+/// `https://<account>.blob.core.windows.net/<container>/<path>` and
+/// authenticated with the credentials found in the environment, see
+/// [`AzureMiddleware::from_env`]. With
+/// [`AzureMiddleware::with_authenticate_https`], requests to
+/// `*.blob.core.windows.net` that do not already carry credentials are
+/// authenticated as well. If no credentials are available the request is
+/// sent unauthenticated, which works for public containers.
+///
+/// Access tokens are reused until they expire. If no token can be obtained,
+/// no new attempt is made for a minute.
+#[derive(Debug, Clone)]
+pub struct AzureMiddleware {
+    credential: AzureCredential,
+    client: reqwest::Client,
+    authenticate_https: bool,
+    token: Arc<Mutex<Option<AccessToken>>>,
+    last_failure: Arc<Mutex<Option<SystemTime>>>,
+}
+
+impl Default for AzureMiddleware {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+impl AzureMiddleware {
+    /// Constructs a new middleware that uses the application-default
+    /// credentials found in the environment. In order of precedence these
+    /// are:
+    ///
+    /// 1. A shared access signature in `AZURE_STORAGE_SAS_TOKEN`.
+    /// 2. A service principal described by `AZURE_TENANT_ID`,
+    ///    `AZURE_CLIENT_ID` and `AZURE_CLIENT_SECRET`.
+    /// 3. The managed identity of the Azure resource the process is running
+    ///    on, optionally selected with `AZURE_CLIENT_ID`.
+    pub fn from_env() -> Self {
+        Self::with_credential(AzureCredential::from_env(|key| std::env::var(key).ok()))
+    }
+
+    fn with_credential(credential: AzureCredential) -> Self {
+        Self {
+            credential,
+            client: reqwest::Client::new(),
+            authenticate_https: false,
+            token: Arc::default(),
+            last_failure: Arc::default(),
+        }
+    }
+
+    /// Sets whether requests to `*.blob.core.windows.net` are authenticated
+    /// as well. Defaults to `false`.
+    #[must_use]
+    pub fn with_authenticate_https(self, authenticate_https: bool) -> Self {
+        Self {
+            authenticate_https,
+            ..self
+        }
+    }
+
+    /// Returns a valid access token, requesting a new one if the cached token
+    /// expired.
+    async fn access_token(&self) -> Result<String, AzureMiddlewareError> {
+        let now = SystemTime::now();
+        if let Some(token) = self.token.lock().unwrap().as_ref() {
+            if token.expires_at > now + EXPIRY_MARGIN {
+                return Ok(token.token.clone());
+            }
+        }
+        if let Some(last_failure) = *self.last_failure.lock().unwrap() {
+            if last_failure + FAILURE_BACKOFF > now {
+                return Err(AzureMiddlewareError::RecentlyFailed);
+            }
+        }
+
+        let result = self.request_access_token(now).await;
+        *self.last_failure.lock().unwrap() = result.is_err().then_some(now);
+        result
+    }
+
+    /// Requests a new access token with the credentials.
+    async fn request_access_token(&self, now: SystemTime) -> Result<String, AzureMiddlewareError> {
+        let request = match &self.credential {
+            AzureCredential::SasToken(_) => unreachable!("sas tokens do not require a token"),
+            AzureCredential::ClientSecret {
+                tenant_id,
+                client_id,
+                client_secret,
+            } => {
+                let scope = format!("{STORAGE_RESOURCE}.default");
+                self.client
+                    .post(format!(
+                        "https://login.microsoftonline.com/{tenant_id}/oauth2/v2.0/token"
+                    ))
+                    .form(&[
+                        ("grant_type", "client_credentials"),
+                        ("client_id", client_id.as_str()),
+                        ("client_secret", client_secret.as_str()),
+                        ("scope", scope.as_str()),
+                    ])
+            }
+            AzureCredential::ManagedIdentity { client_id } => {
+                let mut query = vec![
+                    ("api-version", "2018-02-01"),
+                    ("resource", STORAGE_RESOURCE),
+                ];
+                if let Some(client_id) = client_id {
+                    query.push(("client_id", client_id.as_str()));
+                }
+                self.client
+                    .get(IMDS_ENDPOINT)
+                    .query(&query)
+                    .header("Metadata", "true")
+            }
+        };
+
+        // The metadata service is not reachable outside of Azure, don't wait
+        // for it too long.
+        #[cfg(not(target_arch = "wasm32"))]
+        let request = if matches!(self.credential, AzureCredential::ManagedIdentity { .. }) {
+            request.timeout(Duration::from_secs(2))
+        } else {
+            request
+        };
+
+        let response: TokenResponse = request.send().await?.error_for_status()?.json().await?;
+        let token = AccessToken {
+            token: response.access_token,
+            expires_at: now + Duration::from_secs(response.expires_in),
+        };
+        *self.token.lock().unwrap() = Some(token.clone());
+        Ok(token.token)
+    }
+
+    /// Adds the credentials to the request.
+    async fn authenticate(&self, req: &mut Request) -> Result<(), AzureMiddlewareError> {
+        if let AzureCredential::SasToken(sas_token) = &self.credential {
+            let query = match req.url().query() {
+                Some(query) if !query.is_empty() => format!("{query}&{sas_token}"),
+                _ => sas_token.clone(),
+            };
+            req.url_mut().set_query(Some(&query));
+            return Ok(());
+        }
+
+        let token = self.access_token().await?;
+        req.headers_mut().insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {token}"))?,
+        );
+        req.headers_mut().insert(
+            "x-ms-version",
+            HeaderValue::from_static(STORAGE_API_VERSION),
+        );
+        Ok(())
+    }
+}
+
+/// Converts an `az://<account>/<container>/<path>` url to the equivalent
+/// `https://` url. Returns `None` if the url does not use the `az` scheme.
+fn az_url_to_https(url: &Url) -> Option<Result<Url, AzureMiddlewareError>> {
+    if url.scheme() != "az" {
+        return None;
+    }
+    let Some(account) = url.host_str() else {
+        return Some(Err(AzureMiddlewareError::InvalidUrl(url.to_string())));
+    };
+    let mut new_url = match Url::parse(&format!(
+        "https://{account}.blob.core.windows.net{}",
+        url.path()
+    )) {
+        Ok(new_url) => new_url,
+        Err(_) => return Some(Err(AzureMiddlewareError::InvalidUrl(url.to_string()))),
+    };
+    new_url.set_query(url.query());
+    Some(Ok(new_url))
+}
+
+/// Returns true if the request targets Azure Blob storage over https without
+/// credentials. Urls that contain a shared access signature carry their
+/// credentials in the query string.
+fn is_unauthenticated_storage_request(req: &Request) -> bool {
+    req.url().scheme() == "https"
+        && req
+            .url()
+            .host_str()
+            .is_some_and(|host| host.ends_with(".blob.core.windows.net"))
+        && !req.headers().contains_key(AUTHORIZATION)
+        && !req.url().query_pairs().any(|(key, _)| key == "sig")
+}
+
+#[async_trait]
+impl Middleware for AzureMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> MiddlewareResult<Response> {
+        let rewritten = match az_url_to_https(req.url()) {
+            Some(url) => {
+                *req.url_mut() = url.map_err(reqwest_middleware::Error::middleware)?;
+                true
+            }
+            None => false,
+        };
+
+        if (rewritten || self.authenticate_https) && is_unauthenticated_storage_request(&req) {
+            if let Err(e) = self.authenticate(&mut req).await {
+                tracing::debug!("failed to authenticate request to Azure Blob storage: {e}");
+            }
+        }
+
+        next.run(req, extensions).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use url::Url;
+
+    use super::{
+        az_url_to_https, is_unauthenticated_storage_request, AzureCredential, AzureMiddlewareError,
+    };
+    use crate::AzureMiddleware;
+
+    fn request(url: &str) -> reqwest::Request {
+        reqwest::Request::new(reqwest::Method::GET, Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_az_url_to_https() {
+        let url = Url::parse("az://account/container/noarch/repodata.json").unwrap();
+        assert_eq!(
+            az_url_to_https(&url).unwrap().unwrap().as_str(),
+            "https://account.blob.core.windows.net/container/noarch/repodata.json"
+        );
+        assert!(az_url_to_https(&Url::parse("https://example.com/foo").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_credential_from_env() {
+        let env = |vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> = vars
+                .iter()
+                .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+                .collect();
+            AzureCredential::from_env(move |key| vars.get(key).cloned())
+        };
+
+        assert_eq!(
+            env(&[("AZURE_STORAGE_SAS_TOKEN", "?sv=2022&sig=abc")]),
+            AzureCredential::SasToken(String::from("sv=2022&sig=abc"))
+        );
+        assert_eq!(
+            env(&[
+                ("AZURE_TENANT_ID", "tenant"),
+                ("AZURE_CLIENT_ID", "client"),
+                ("AZURE_CLIENT_SECRET", "secret")
+            ]),
+            AzureCredential::ClientSecret {
+                tenant_id: String::from("tenant"),
+                client_id: String::from("client"),
+                client_secret: String::from("secret"),
+            }
+        );
+        assert_eq!(
+            env(&[("AZURE_CLIENT_ID", "client")]),
+            AzureCredential::ManagedIdentity {
+                client_id: Some(String::from("client"))
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sas_token_is_appended() {
+        let middleware =
+            AzureMiddleware::with_credential(AzureCredential::SasToken(String::from("sig=abc")));
+
+        let mut req = request("https://account.blob.core.windows.net/container/noarch/foo.conda");
+        assert!(is_unauthenticated_storage_request(&req));
+        middleware.authenticate(&mut req).await.unwrap();
+        assert_eq!(req.url().query(), Some("sig=abc"));
+
+        // Requests that are already signed are left alone.
+        assert!(!is_unauthenticated_storage_request(&req));
+        assert!(!is_unauthenticated_storage_request(&request(
+            "https://example.com/container/noarch/foo.conda"
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_cached() {
+        let middleware =
+            AzureMiddleware::with_credential(AzureCredential::ManagedIdentity { client_id: None });
+        *middleware.last_failure.lock().unwrap() = Some(std::time::SystemTime::now());
+
+        // No request is made to the metadata service while backing off.
+        assert!(matches!(
+            middleware.access_token().await,
+            Err(AzureMiddlewareError::RecentlyFailed)
+        ));
+    }
+}
@@ -0,0 +1,341 @@
+//! Middleware that tracks failures per host and temporarily stops sending
+//! requests to hosts that keep failing.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use http::{Extensions, StatusCode};
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next, Result};
+use tokio::time::Instant;
+use url::Url;
+
+/// The error that is returned for requests to a host whose circuit is open.
+#[derive(Debug, thiserror::Error)]
+#[error("requests to {0} are temporarily blocked because of repeated failures")]
+pub struct CircuitOpenError(pub String);
+
+#[derive(Debug, Default)]
+struct HostState {
+    consecutive_failures: usize,
+    open_until: Option<Instant>,
+    /// Whether a request is being sent to probe if the host has recovered.
+    probing: bool,
+}
+
+impl HostState {
+    /// Returns true if requests to the host are currently blocked.
+    fn is_open(&self, now: Instant) -> bool {
+        self.open_until
+            .is_some_and(|open_until| open_until > now || self.probing)
+    }
+}
+
+/// A circuit breaker for HTTP requests.
+///
+/// The middleware counts the consecutive failures (connection errors, server
+/// errors and `429 Too Many Requests` responses) of every host. When the
+/// number of failures of a host reaches the failure threshold the circuit of
+/// the host opens and all requests to the host fail immediately with a
+/// [`CircuitOpenError`] for the open duration. After that the circuit is
+/// half-open: a single request is let through to probe the host while other
+/// requests are still blocked. If the probe fails the circuit opens again, if
+/// it succeeds the circuit closes.
+///
+/// The state is shared between clones of the middleware. Use the same
+/// instance for the clients that fetch repodata and packages so that a
+/// failing host is avoided by both. When combined with the
+/// [`crate::MirrorMiddleware`] this middleware must come after it, so that it
+/// sees the url of the selected mirror. Pass the same instance to
+/// [`crate::MirrorMiddleware::with_circuit_breaker`] to skip mirrors whose
+/// circuit is open.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerMiddleware {
+    failure_threshold: usize,
+    open_duration: Duration,
+    hosts: Arc<Mutex<HashMap<String, HostState>>>,
+}
+
+impl Default for CircuitBreakerMiddleware {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(30),
+            hosts: Arc::default(),
+        }
+    }
+}
+
+impl CircuitBreakerMiddleware {
+    /// Creates a new circuit breaker that opens after 5 consecutive failures
+    /// for 30 seconds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of consecutive failures after which the circuit of a
+    /// host opens.
+    #[must_use]
+    pub fn with_failure_threshold(mut self, failure_threshold: usize) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /// Sets the duration for which requests to a failing host are blocked.
+    #[must_use]
+    pub fn with_open_duration(mut self, open_duration: Duration) -> Self {
+        self.open_duration = open_duration;
+        self
+    }
+
+    /// Returns the number of consecutive failures of the host of the url.
+    pub fn failures(&self, url: &Url) -> usize {
+        self.hosts
+            .lock()
+            .unwrap()
+            .get(&host_key(url))
+            .map_or(0, |state| state.consecutive_failures)
+    }
+
+    /// Returns true if requests to the host of the url are currently blocked,
+    /// either because the circuit is open or because a request is probing if
+    /// the host has recovered.
+    pub fn is_open(&self, url: &Url) -> bool {
+        self.hosts
+            .lock()
+            .unwrap()
+            .get(&host_key(url))
+            .is_some_and(|state| state.is_open(Instant::now()))
+    }
+
+    /// Returns whether a request to the host may be sent. If the open
+    /// duration of the circuit has passed, the request becomes the probe of
+    /// the host.
+    fn try_acquire(&self, host: &str) -> Option<ProbeGuard<'_>> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let Some(state) = hosts.get_mut(host) else {
+            return Some(ProbeGuard::none());
+        };
+        if state.is_open(Instant::now()) {
+            return None;
+        }
+        if state.open_until.is_none() {
+            return Some(ProbeGuard::none());
+        }
+        state.probing = true;
+        Some(ProbeGuard {
+            circuit_breaker: Some(self),
+            host,
+        })
+    }
+
+    fn record_success(&self, host: &str) {
+        self.hosts.lock().unwrap().remove(host);
+    }
+
+    fn record_failure(&self, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = hosts.entry(host.to_string()).or_default();
+        state.consecutive_failures += 1;
+        state.probing = false;
+        if state.consecutive_failures >= self.failure_threshold {
+            tracing::warn!(
+                "blocking requests to {host} for {}s after {} consecutive failures",
+                self.open_duration.as_secs_f32(),
+                state.consecutive_failures
+            );
+            state.open_until = Some(Instant::now() + self.open_duration);
+        }
+    }
+}
+
+/// Ends the probe of a host if the request is dropped before it completes, so
+/// that another request can probe the host.
+struct ProbeGuard<'a> {
+    circuit_breaker: Option<&'a CircuitBreakerMiddleware>,
+    host: &'a str,
+}
+
+impl ProbeGuard<'_> {
+    fn none() -> Self {
+        Self {
+            circuit_breaker: None,
+            host: "",
+        }
+    }
+
+    fn complete(mut self) {
+        self.circuit_breaker = None;
+    }
+}
+
+impl Drop for ProbeGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(circuit_breaker) = self.circuit_breaker {
+            if let Some(state) = circuit_breaker.hosts.lock().unwrap().get_mut(self.host) {
+                state.probing = false;
+            }
+        }
+    }
+}
+
+/// Returns the key by which the state of a host is tracked.
+fn host_key(url: &Url) -> String {
+    format!(
+        "{}:{}",
+        url.host_str().unwrap_or_default(),
+        url.port_or_known_default().unwrap_or_default()
+    )
+}
+
+fn is_failure(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+#[async_trait::async_trait]
+impl Middleware for CircuitBreakerMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        // Local files cannot fail in a way that recovers over time.
+        if req.url().host_str().is_none() {
+            return next.run(req, extensions).await;
+        }
+
+        let host = host_key(req.url());
+        let Some(probe) = self.try_acquire(&host) else {
+            return Err(reqwest_middleware::Error::middleware(CircuitOpenError(
+                host,
+            )));
+        };
+
+        let res = next.run(req, extensions).await;
+        match &res {
+            Ok(response) if is_failure(response.status()) => self.record_failure(&host),
+            Err(_) => self.record_failure(&host),
+            Ok(_) => self.record_success(&host),
+        }
+        probe.complete();
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+    use std::time::Duration;
+
+    use http::{Extensions, StatusCode};
+    use reqwest::{Request, Response};
+    use reqwest_middleware::{ClientWithMiddleware, Middleware, Next};
+    use tokio::sync::Semaphore;
+    use url::Url;
+
+    use super::{CircuitBreakerMiddleware, CircuitOpenError};
+
+    /// Responds to requests with a configurable status instead of sending
+    /// them. Every request waits for a permit of the gate.
+    #[derive(Clone)]
+    struct Server {
+        requests: Arc<AtomicUsize>,
+        status: Arc<Mutex<StatusCode>>,
+        gate: Arc<Semaphore>,
+    }
+
+    #[async_trait::async_trait]
+    impl Middleware for Server {
+        async fn handle(
+            &self,
+            _req: Request,
+            _extensions: &mut Extensions,
+            _next: Next<'_>,
+        ) -> reqwest_middleware::Result<Response> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            self.gate.acquire().await.unwrap().forget();
+            let status = *self.status.lock().unwrap();
+            Ok(http::Response::builder()
+                .status(status)
+                .body("")
+                .unwrap()
+                .into())
+        }
+    }
+
+    fn is_circuit_open_error(err: reqwest_middleware::Error) -> bool {
+        let reqwest_middleware::Error::Middleware(err) = err else {
+            return false;
+        };
+        err.downcast_ref::<CircuitOpenError>().is_some()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_circuit_breaker() {
+        let server = Server {
+            requests: Arc::default(),
+            status: Arc::new(Mutex::new(StatusCode::SERVICE_UNAVAILABLE)),
+            gate: Arc::new(Semaphore::new(2)),
+        };
+        let circuit_breaker = CircuitBreakerMiddleware::new()
+            .with_failure_threshold(2)
+            .with_open_duration(Duration::from_secs(30));
+        let client: ClientWithMiddleware =
+            reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+                .with(circuit_breaker.clone())
+                .with(server.clone())
+                .build();
+        let url = Url::parse("https://example.com/broken").unwrap();
+
+        // The circuit opens after two failures.
+        for _ in 0..2 {
+            let response = client.get(url.clone()).send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+        assert_eq!(circuit_breaker.failures(&url), 2);
+        assert!(circuit_breaker.is_open(&url));
+
+        // Requests to the host fail without reaching the server.
+        let err = client.get(url.clone()).send().await.unwrap_err();
+        assert!(is_circuit_open_error(err));
+        assert_eq!(server.requests.load(Ordering::SeqCst), 2);
+
+        // After the open duration a single request is let through to probe
+        // the host, other requests are blocked until the probe completes.
+        tokio::time::advance(Duration::from_secs(31)).await;
+        assert!(!circuit_breaker.is_open(&url));
+        let probe = tokio::spawn({
+            let client = client.clone();
+            let url = url.clone();
+            async move { client.get(url).send().await.unwrap().status() }
+        });
+        while server.requests.load(Ordering::SeqCst) < 3 {
+            tokio::task::yield_now().await;
+        }
+        assert!(circuit_breaker.is_open(&url));
+        let err = client.get(url.clone()).send().await.unwrap_err();
+        assert!(is_circuit_open_error(err));
+
+        // A failing probe opens the circuit again.
+        server.gate.add_permits(1);
+        assert_eq!(probe.await.unwrap(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(circuit_breaker.is_open(&url));
+        assert_eq!(server.requests.load(Ordering::SeqCst), 3);
+
+        // A successful probe closes the circuit.
+        tokio::time::advance(Duration::from_secs(31)).await;
+        *server.status.lock().unwrap() = StatusCode::OK;
+        server.gate.add_permits(2);
+        client.get(url.clone()).send().await.unwrap();
+        assert!(!circuit_breaker.is_open(&url));
+        assert_eq!(circuit_breaker.failures(&url), 0);
+        client.get(url.clone()).send().await.unwrap();
+        assert_eq!(server.requests.load(Ordering::SeqCst), 5);
+    }
+}
@@ -1,12 +1,110 @@
 //! Middleware to handle `gcs://` URLs to pull artifacts from an GCS
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use async_trait::async_trait;
 use google_cloud_auth::project::{create_token_source, Config};
-use reqwest::{Request, Response};
+use reqwest::{header::HeaderValue, Request, Response};
 use reqwest_middleware::{Middleware, Next, Result as MiddlewareResult};
 use url::Url;
 
-/// GCS middleware to authenticate requests
-pub struct GCSMiddleware;
+/// Tokens are refreshed this long before they actually expire.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Tokens without an expiry are reused for this long.
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(300);
+
+/// After failing to obtain a token, no new attempt is made for this long.
+const FAILURE_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(thiserror::Error, Debug)]
+enum GCSMiddlewareError {
+    #[error("invalid GCS url: {0}")]
+    InvalidUrl(String),
+
+    #[error("failed to obtain an access token for GCS")]
+    Token(#[source] Arc<google_cloud_auth::error::Error>),
+
+    #[error("no access token for GCS is available, the last attempt failed recently")]
+    RecentlyFailed,
+
+    #[error("the access token is not a valid header value")]
+    InvalidToken(#[from] http::header::InvalidHeaderValue),
+}
+
+/// The result of the last attempt to obtain an access token.
+#[derive(Debug, Clone)]
+enum TokenState {
+    Valid {
+        header: HeaderValue,
+        expires_at: SystemTime,
+    },
+    Failed {
+        at: SystemTime,
+    },
+}
+
+/// GCS middleware to authenticate requests using the application-default
+/// credentials of Google Cloud.
+///
+/// Requests to `gcs://bucket/path` and `gs://bucket/path` urls are rewritten
+/// to `https://storage.googleapis.com/bucket/path` and authenticated. With
+/// [`GCSMiddleware::with_authenticate_https`], requests that directly target
+/// `https://storage.googleapis.com` are authenticated as well unless they
+/// already carry credentials, e.g. because the url is a signed url.
+///
+/// Access tokens are reused until they expire. If no token can be obtained,
+/// no new attempt is made for a minute.
+#[derive(Debug, Clone, Default)]
+pub struct GCSMiddleware {
+    authenticate_https: bool,
+    token: Arc<Mutex<Option<TokenState>>>,
+}
+
+impl GCSMiddleware {
+    /// Constructs a new middleware that only authenticates `gcs://` and
+    /// `gs://` urls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether requests to `https://storage.googleapis.com` are
+    /// authenticated as well. Defaults to `false`.
+    #[must_use]
+    pub fn with_authenticate_https(self, authenticate_https: bool) -> Self {
+        Self {
+            authenticate_https,
+            ..self
+        }
+    }
+
+    /// Returns the value of the authorization header, reusing the cached
+    /// token if it did not expire yet.
+    async fn authorization(&self) -> Result<HeaderValue, GCSMiddlewareError> {
+        let now = SystemTime::now();
+        match self.token.lock().unwrap().as_ref() {
+            Some(TokenState::Valid { header, expires_at }) if *expires_at > now + EXPIRY_MARGIN => {
+                return Ok(header.clone());
+            }
+            Some(TokenState::Failed { at }) if *at + FAILURE_BACKOFF > now => {
+                return Err(GCSMiddlewareError::RecentlyFailed);
+            }
+            _ => {}
+        }
+
+        let result = google_cloud_authorization().await;
+        *self.token.lock().unwrap() = Some(match &result {
+            Ok((header, expires_at)) => TokenState::Valid {
+                header: header.clone(),
+                expires_at: expires_at.unwrap_or(now + DEFAULT_TOKEN_LIFETIME),
+            },
+            Err(_) => TokenState::Failed { at: now },
+        });
+        result.map(|(header, _)| header)
+    }
+}
 
 #[async_trait]
 impl Middleware for GCSMiddleware {
@@ -17,47 +115,128 @@ impl Middleware for GCSMiddleware {
         extensions: &mut http::Extensions,
         next: Next<'_>,
     ) -> MiddlewareResult<Response> {
-        if req.url().scheme() == "gcs" {
-            let mut url = req.url().clone();
-            let bucket_name = url.host_str().expect("Host should be present in GCS URL");
-            let new_url = format!(
-                "https://storage.googleapis.com/{}{}",
-                bucket_name,
-                url.path()
-            );
-            url = Url::parse(&new_url).expect("Failed to parse URL");
-            *req.url_mut() = url;
-            req = authenticate_with_google_cloud(req).await?;
+        if let Some(url) = gcs_url_to_https(req.url()) {
+            *req.url_mut() = url.map_err(reqwest_middleware::Error::middleware)?;
+            let header_value = self
+                .authorization()
+                .await
+                .map_err(reqwest_middleware::Error::middleware)?;
+            req.headers_mut()
+                .insert(reqwest::header::AUTHORIZATION, header_value);
+        } else if self.authenticate_https && is_unauthenticated_storage_request(&req) {
+            match self.authorization().await {
+                Ok(header_value) => {
+                    req.headers_mut()
+                        .insert(reqwest::header::AUTHORIZATION, header_value);
+                }
+                Err(e) => tracing::debug!("failed to authenticate request to GCS: {e}"),
+            }
         }
         next.run(req, extensions).await
     }
 }
 
-/// Auth to GCS
-async fn authenticate_with_google_cloud(mut req: Request) -> MiddlewareResult<Request> {
+/// Converts a `gcs://` or `gs://` url to the equivalent `https://` url.
+/// Returns `None` if the url does not use one of these schemes.
+fn gcs_url_to_https(url: &Url) -> Option<Result<Url, GCSMiddlewareError>> {
+    if !matches!(url.scheme(), "gcs" | "gs") {
+        return None;
+    }
+    let Some(bucket_name) = url.host_str() else {
+        return Some(Err(GCSMiddlewareError::InvalidUrl(url.to_string())));
+    };
+    let mut new_url = match Url::parse(&format!(
+        "https://storage.googleapis.com/{}{}",
+        bucket_name,
+        url.path()
+    )) {
+        Ok(new_url) => new_url,
+        Err(_) => return Some(Err(GCSMiddlewareError::InvalidUrl(url.to_string()))),
+    };
+    new_url.set_query(url.query());
+    Some(Ok(new_url))
+}
+
+/// Returns true if the request targets GCS over https without credentials.
+/// Signed urls carry their credentials in the query string.
+fn is_unauthenticated_storage_request(req: &Request) -> bool {
+    req.url().scheme() == "https"
+        && req.url().host_str() == Some("storage.googleapis.com")
+        && !req.headers().contains_key(reqwest::header::AUTHORIZATION)
+        && !req
+            .url()
+            .query_pairs()
+            .any(|(key, _)| key.eq_ignore_ascii_case("x-goog-signature"))
+}
+
+/// Auth to GCS, returns the value of the authorization header and the time
+/// at which the token expires, if known.
+async fn google_cloud_authorization(
+) -> Result<(HeaderValue, Option<SystemTime>), GCSMiddlewareError> {
     let audience = "https://storage.googleapis.com/";
     let scopes = [
         "https://www.googleapis.com/auth/cloud-platform",
         "https://www.googleapis.com/auth/devstorage.read_only",
     ];
-    match create_token_source(Config {
+    let token_source = create_token_source(Config {
         audience: Some(audience),
         scopes: Some(&scopes),
         sub: None,
     })
     .await
-    {
-        Ok(ts) => match ts.token().await {
-            Ok(token) => {
-                let bearer_auth = format!("Bearer {}", token.access_token);
-                let header_value = reqwest::header::HeaderValue::from_str(&bearer_auth)
-                    .map_err(reqwest_middleware::Error::middleware)?;
-                req.headers_mut()
-                    .insert(reqwest::header::AUTHORIZATION, header_value);
-                Ok(req)
-            }
-            Err(e) => Err(reqwest_middleware::Error::Middleware(anyhow::Error::new(e))),
-        },
-        Err(e) => Err(reqwest_middleware::Error::Middleware(anyhow::Error::new(e))),
+    .map_err(|e| GCSMiddlewareError::Token(Arc::new(e)))?;
+    let token = token_source
+        .token()
+        .await
+        .map_err(|e| GCSMiddlewareError::Token(Arc::new(e)))?;
+
+    let mut header = HeaderValue::from_str(&format!("Bearer {}", token.access_token))?;
+    header.set_sensitive(true);
+    let expires_at = token.expiry.and_then(|expiry| {
+        let seconds = u64::try_from(expiry.unix_timestamp()).ok()?;
+        Some(UNIX_EPOCH + Duration::from_secs(seconds))
+    });
+    Ok((header, expires_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{gcs_url_to_https, is_unauthenticated_storage_request};
+
+    #[test]
+    fn test_gcs_url_to_https() {
+        for url in [
+            "gcs://bucket/channel/noarch/repodata.json",
+            "gs://bucket/channel/noarch/repodata.json",
+        ] {
+            assert_eq!(
+                gcs_url_to_https(&Url::parse(url).unwrap())
+                    .unwrap()
+                    .unwrap()
+                    .as_str(),
+                "https://storage.googleapis.com/bucket/channel/noarch/repodata.json"
+            );
+        }
+        assert!(gcs_url_to_https(&Url::parse("https://example.com/foo").unwrap()).is_none());
+        assert!(gcs_url_to_https(&Url::parse("gs:channel/noarch").unwrap())
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_signed_urls_are_not_authenticated() {
+        let request =
+            |url: &str| reqwest::Request::new(reqwest::Method::GET, Url::parse(url).unwrap());
+        assert!(is_unauthenticated_storage_request(&request(
+            "https://storage.googleapis.com/bucket/noarch/repodata.json"
+        )));
+        assert!(!is_unauthenticated_storage_request(&request(
+            "https://storage.googleapis.com/bucket/noarch/repodata.json?X-Goog-Signature=abc"
+        )));
+        assert!(!is_unauthenticated_storage_request(&request(
+            "https://example.com/bucket/noarch/repodata.json"
+        )));
     }
 }
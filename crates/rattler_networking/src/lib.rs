@@ -3,6 +3,8 @@
 //! Networking utilities for Rattler, specifically authenticating requests
 pub use authentication_middleware::AuthenticationMiddleware;
 pub use authentication_storage::{authentication::Authentication, storage::AuthenticationStorage};
+pub use azure_middleware::AzureMiddleware;
+pub use circuit_breaker_middleware::CircuitBreakerMiddleware;
 pub use mirror_middleware::MirrorMiddleware;
 pub use oci_middleware::OciMiddleware;
 
@@ -13,6 +15,8 @@ pub use gcs_middleware::GCSMiddleware;
 
 pub mod authentication_middleware;
 pub mod authentication_storage;
+pub mod azure_middleware;
+pub mod circuit_breaker_middleware;
 
 pub mod mirror_middleware;
 pub mod oci_middleware;
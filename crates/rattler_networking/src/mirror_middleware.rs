@@ -10,6 +10,8 @@ use reqwest::{Request, Response, ResponseBuilderExt};
 use reqwest_middleware::{Middleware, Next, Result};
 use url::Url;
 
+use crate::CircuitBreakerMiddleware;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 /// Settings for the specific mirror (e.g. no zstd or bz2 support)
 pub struct Mirror {
@@ -41,6 +43,7 @@ impl MirrorState {
 pub struct MirrorMiddleware {
     mirror_map: HashMap<Url, Vec<MirrorState>>,
     sorted_keys: Vec<(String, Url)>,
+    circuit_breaker: Option<CircuitBreakerMiddleware>,
 }
 
 impl MirrorMiddleware {
@@ -70,9 +73,19 @@ impl MirrorMiddleware {
         Self {
             mirror_map,
             sorted_keys,
+            circuit_breaker: None,
         }
     }
 
+    /// Skip mirrors whose circuit is open in the given circuit breaker. The
+    /// circuit breaker must also be added to the client after this
+    /// middleware, so that it records the failures of the mirrors.
+    #[must_use]
+    pub fn with_circuit_breaker(mut self, circuit_breaker: CircuitBreakerMiddleware) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
     /// Get sorted keys. The keys are sorted by length of the path,
     /// so the longest path comes first.
     pub fn keys(&self) -> &[(String, Url)] {
@@ -80,11 +93,23 @@ impl MirrorMiddleware {
     }
 }
 
-fn select_mirror(mirrors: &[MirrorState]) -> Option<&MirrorState> {
+fn select_mirror<'a>(
+    mirrors: &'a [MirrorState],
+    circuit_breaker: Option<&CircuitBreakerMiddleware>,
+) -> Option<&'a MirrorState> {
+    // Skip the mirrors whose circuit is open. If the circuit of every mirror
+    // is open the circuit breaker rejects the request.
+    let is_open =
+        |mirror: &MirrorState| circuit_breaker.is_some_and(|cb| cb.is_open(&mirror.mirror.url));
+    let all_open = mirrors.iter().all(is_open);
+
     let mut min_failures = usize::MAX;
     let mut min_failures_index = usize::MAX;
 
     for (i, mirror) in mirrors.iter().enumerate() {
+        if !all_open && is_open(mirror) {
+            continue;
+        }
         let failures = mirror.failures.load(atomic::Ordering::Relaxed);
         if failures < min_failures
             && mirror
@@ -117,7 +142,7 @@ impl Middleware for MirrorMiddleware {
                 let url_rest = url_rest.trim_start_matches('/');
                 // replace the key with the mirror
                 let mirrors = self.mirror_map.get(url).unwrap();
-                let selected_mirror = select_mirror(mirrors);
+                let selected_mirror = select_mirror(mirrors, self.circuit_breaker.as_ref());
 
                 let Some(selected_mirror) = selected_mirror else {
                     return Ok(create_404_response(req.url(), "All mirrors are dead"));
@@ -183,7 +208,7 @@ mod test {
     use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
     use url::Url;
 
-    use crate::MirrorMiddleware;
+    use crate::{CircuitBreakerMiddleware, MirrorMiddleware};
 
     use super::Mirror;
 
@@ -290,6 +315,39 @@ mod test {
         assert!(res.text().await.unwrap() == "Hi from counter: server 2");
     }
 
+    #[tokio::test]
+    async fn test_mirror_middleware_circuit_breaker() {
+        let addr_1 = test_server("server 1", true).await;
+        let addr_2 = test_server("server 2", false).await;
+
+        // Open the circuit of the first mirror.
+        let circuit_breaker = CircuitBreakerMiddleware::new().with_failure_threshold(1);
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(circuit_breaker.clone())
+            .build();
+        let res = client.get(addr_1.join("count").unwrap()).send().await;
+        assert!(res.unwrap().status().is_server_error());
+        assert!(circuit_breaker.is_open(&addr_1));
+
+        // The mirror with the open circuit is skipped even though it has not
+        // failed for the mirror middleware.
+        let mut mirror_map = std::collections::HashMap::new();
+        mirror_map.insert(
+            "http://bla.com".parse().unwrap(),
+            vec![mirror_setting(addr_1), mirror_setting(addr_2)],
+        );
+        let middleware =
+            MirrorMiddleware::from_map(mirror_map).with_circuit_breaker(circuit_breaker.clone());
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(middleware)
+            .with(circuit_breaker)
+            .build();
+
+        let res = client.get("http://bla.com/count").send().await.unwrap();
+        assert!(res.status().is_success());
+        assert_eq!(res.text().await.unwrap(), "Hi from counter: server 2");
+    }
+
     #[test]
     fn test_mirror_sort() {
         let keys: Vec<Url> = vec![
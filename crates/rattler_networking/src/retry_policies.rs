@@ -2,10 +2,10 @@
 //! implementations.
 //!
 //! This module also provides the [`DoNotRetryPolicy`] which is useful if you do not want to retry
-//! anything.
+//! anything, and the [`RetryConfig`] to configure an exponential backoff with jitter.
 
 pub use retry_policies::{policies::*, Jitter, RetryDecision, RetryPolicy};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 /// A simple [`RetryPolicy`] that just never retries.
 pub struct DoNotRetryPolicy;
@@ -20,5 +20,80 @@ impl RetryPolicy for DoNotRetryPolicy {
 /// This is useful if you just do not care about a retry policy and you just want something
 /// sensible. Note that the behavior of what is "sensible" might change over time.
 pub fn default_retry_policy() -> ExponentialBackoff {
-    ExponentialBackoff::builder().build_with_max_retries(3)
+    RetryConfig::default().build()
+}
+
+/// Configuration of an [`ExponentialBackoff`] retry policy.
+///
+/// The interval before retry `n` is `min_retry_interval * base^n`, capped at
+/// `max_retry_interval`. The jitter randomizes the interval so that clients
+/// that failed at the same time do not all retry at the same time.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryConfig {
+    /// The maximum number of times a request is retried.
+    pub max_retries: u32,
+
+    /// The interval before the first retry.
+    pub min_retry_interval: Duration,
+
+    /// The maximum interval between two retries.
+    pub max_retry_interval: Duration,
+
+    /// The factor by which the interval grows with every retry.
+    pub base: u32,
+
+    /// How the intervals are randomized.
+    pub jitter: Jitter,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            min_retry_interval: Duration::from_secs(1),
+            max_retry_interval: Duration::from_secs(30 * 60),
+            base: 2,
+            jitter: Jitter::Full,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Sets the maximum number of times a request is retried.
+    #[must_use]
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the minimum and maximum interval between two retries.
+    #[must_use]
+    pub fn with_retry_bounds(mut self, min: Duration, max: Duration) -> Self {
+        self.min_retry_interval = min;
+        self.max_retry_interval = max.max(min);
+        self
+    }
+
+    /// Sets the factor by which the interval grows with every retry.
+    #[must_use]
+    pub fn with_base(mut self, base: u32) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// Sets how the intervals are randomized.
+    #[must_use]
+    pub fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Constructs the retry policy.
+    pub fn build(&self) -> ExponentialBackoff {
+        ExponentialBackoff::builder()
+            .retry_bounds(self.min_retry_interval, self.max_retry_interval)
+            .base(self.base)
+            .jitter(self.jitter)
+            .build_with_max_retries(self.max_retries)
+    }
 }
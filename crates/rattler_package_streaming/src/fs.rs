@@ -1,6 +1,6 @@
 //! Functions to extracting or stream a Conda package from a file on disk.
 
-use crate::{ExtractError, ExtractResult};
+use crate::{ExtractError, ExtractOptions, ExtractResult};
 use rattler_conda_types::package::ArchiveType;
 use std::fs::File;
 use std::path::Path;
@@ -35,6 +35,17 @@ pub fn extract_conda(archive: &Path, destination: &Path) -> Result<ExtractResult
     crate::read::extract_conda_via_streaming(file, destination)
 }
 
+/// Extracts the contents a `.conda` package archive at the specified path to a directory using
+/// the given options.
+pub fn extract_conda_with_options(
+    archive: &Path,
+    destination: &Path,
+    options: &ExtractOptions,
+) -> Result<ExtractResult, ExtractError> {
+    let file = File::open(archive)?;
+    crate::read::extract_conda_via_streaming_with_options(file, destination, options)
+}
+
 /// Extracts the contents a package archive at the specified path to a directory. The type of
 /// package is determined based on the file extension of the archive path.
 ///
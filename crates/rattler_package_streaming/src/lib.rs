@@ -79,6 +79,45 @@ pub struct ExtractResult {
     pub md5: Md5Hash,
 }
 
+/// Options that tune the extraction of `.conda` package archives.
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractOptions {
+    /// The base 2 logarithm of the largest window the zstd decoder accepts.
+    ///
+    /// Archives that were compressed with long distance matching (`zstd
+    /// --long`) require a larger window than zstd accepts by default. Memory
+    /// is only allocated for the window size that an archive actually uses.
+    /// Defaults to 27 (128 MiB), the default limit of zstd. Larger values
+    /// must be opted into and are limited to 31 on 64-bit targets and to 30
+    /// on 32-bit targets.
+    pub zstd_window_log_max: u32,
+
+    /// Whether large components of an archive are decompressed on a separate
+    /// thread while the files are written to disk on the current thread.
+    ///
+    /// zstd itself decompresses a single frame sequentially. Overlapping
+    /// decompression with writing the extracted files can speed up the
+    /// extraction of large packages on fast disks. Components smaller than
+    /// [`Self::multithreaded_threshold`] are always extracted on the current
+    /// thread. Defaults to `false`.
+    pub multithreaded: bool,
+
+    /// The compressed size in bytes from which components are decompressed on
+    /// a separate thread if [`Self::multithreaded`] is enabled. Defaults to
+    /// 1 MiB.
+    pub multithreaded_threshold: u64,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            zstd_window_log_max: 27,
+            multithreaded: false,
+            multithreaded_threshold: 1024 * 1024,
+        }
+    }
+}
+
 /// A trait that can be implemented to report download progress.
 pub trait DownloadReporter: Send + Sync {
     /// Called when the download starts.
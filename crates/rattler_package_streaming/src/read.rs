@@ -1,10 +1,11 @@
 //! Functions that enable extracting or streaming a Conda package for objects that implement the
 //! [`std::io::Read`] trait.
 
-use super::{ExtractError, ExtractResult};
+use super::{ExtractError, ExtractOptions, ExtractResult};
 use rattler_digest::HashingReader;
 use std::io::{copy, Seek, SeekFrom};
 use std::mem::ManuallyDrop;
+use std::sync::mpsc::{sync_channel, Receiver};
 use std::{ffi::OsStr, io::Read, path::Path};
 use tempfile::SpooledTempFile;
 use zip::read::{read_zipfile_from_stream, ZipArchive, ZipFile};
@@ -20,7 +21,20 @@ pub fn stream_tar_bz2(reader: impl Read) -> tar::Archive<impl Read + Sized> {
 pub(crate) fn stream_tar_zst(
     reader: impl Read,
 ) -> Result<tar::Archive<impl Read + Sized>, ExtractError> {
-    Ok(tar::Archive::new(zstd::stream::read::Decoder::new(reader)?))
+    Ok(tar::Archive::new(zstd_decoder(
+        reader,
+        &ExtractOptions::default(),
+    )?))
+}
+
+/// Returns a zstd decoder for the given reader configured with the options.
+fn zstd_decoder(
+    reader: impl Read,
+    options: &ExtractOptions,
+) -> Result<impl Read + Sized, ExtractError> {
+    let mut decoder = zstd::stream::read::Decoder::new(reader)?;
+    decoder.window_log_max(options.zstd_window_log_max)?;
+    Ok(decoder)
 }
 
 /// Extracts the contents a `.tar.bz2` package archive.
@@ -50,6 +64,16 @@ pub fn extract_tar_bz2(
 pub fn extract_conda_via_streaming(
     reader: impl Read,
     destination: &Path,
+) -> Result<ExtractResult, ExtractError> {
+    extract_conda_via_streaming_with_options(reader, destination, &ExtractOptions::default())
+}
+
+/// Extracts the contents of a `.conda` package archive using the given
+/// options.
+pub fn extract_conda_via_streaming_with_options(
+    reader: impl Read,
+    destination: &Path,
+    options: &ExtractOptions,
 ) -> Result<ExtractResult, ExtractError> {
     // Construct the destination path if it doesnt exist yet
     std::fs::create_dir_all(destination).map_err(ExtractError::CouldNotCreateDestination)?;
@@ -62,7 +86,7 @@ pub fn extract_conda_via_streaming(
 
     // Iterate over all entries in the zip-file and extract them one-by-one
     while let Some(file) = read_zipfile_from_stream(&mut md5_reader)? {
-        extract_zipfile(file, destination)?;
+        extract_zipfile(file, destination, options)?;
     }
     compute_hashes(md5_reader)
 }
@@ -71,6 +95,16 @@ pub fn extract_conda_via_streaming(
 pub fn extract_conda_via_buffering(
     reader: impl Read,
     destination: &Path,
+) -> Result<ExtractResult, ExtractError> {
+    extract_conda_via_buffering_with_options(reader, destination, &ExtractOptions::default())
+}
+
+/// Extracts the contents of a .conda package archive by fully reading the
+/// stream and then decompressing using the given options.
+pub fn extract_conda_via_buffering_with_options(
+    reader: impl Read,
+    destination: &Path,
+    options: &ExtractOptions,
 ) -> Result<ExtractResult, ExtractError> {
     // delete destination first, as this method is usually used as a fallback from a failed streaming decompression
     if destination.exists() {
@@ -90,7 +124,7 @@ pub fn extract_conda_via_buffering(
 
     for i in 0..archive.len() {
         let file = archive.by_index(i)?;
-        extract_zipfile(file, destination)?;
+        extract_zipfile(file, destination, options)?;
     }
     // Read the file to the end to make sure the hash is properly computed.
     std::io::copy(&mut md5_reader, &mut std::io::sink())?;
@@ -98,7 +132,11 @@ pub fn extract_conda_via_buffering(
     compute_hashes(md5_reader)
 }
 
-fn extract_zipfile(zip_file: ZipFile<'_>, destination: &Path) -> Result<(), ExtractError> {
+fn extract_zipfile(
+    zip_file: ZipFile<'_>,
+    destination: &Path,
+    options: &ExtractOptions,
+) -> Result<(), ExtractError> {
     // If an error occurs while we are reading the contents of the zip we don't want to
     // seek to the end of the file. Using [`ManuallyDrop`] we prevent `drop` to be called on
     // the `file` in case the stack unwinds.
@@ -110,7 +148,14 @@ fn extract_zipfile(zip_file: ZipFile<'_>, destination: &Path) -> Result<(), Extr
         .map(OsStr::to_string_lossy)
         .map_or(false, |file_name| file_name.ends_with(".tar.zst"))
     {
-        stream_tar_zst(&mut *file)?.unpack(destination)?;
+        let multithreaded =
+            options.multithreaded && file.compressed_size() >= options.multithreaded_threshold;
+        let decoder = zstd_decoder(&mut *file, options)?;
+        if multithreaded {
+            unpack_on_separate_thread(decoder, destination)?;
+        } else {
+            tar::Archive::new(decoder).unpack(destination)?;
+        }
     } else {
         // Manually read to the end of the stream if that didn't happen.
         std::io::copy(&mut *file, &mut std::io::sink())?;
@@ -122,6 +167,97 @@ fn extract_zipfile(zip_file: ZipFile<'_>, destination: &Path) -> Result<(), Extr
     Ok(())
 }
 
+/// The size of the chunks that are sent from the decompressing thread to the
+/// thread that writes the files.
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// The number of chunks that can be buffered between the threads.
+const CHUNK_BUFFER: usize = 16;
+
+/// Unpacks the tar archive that is read from `decoder` into `destination`.
+/// The files are written on a separate thread while the current thread reads
+/// and decompresses the archive.
+fn unpack_on_separate_thread(
+    mut decoder: impl Read,
+    destination: &Path,
+) -> Result<(), ExtractError> {
+    let (sender, receiver) = sync_channel::<Vec<u8>>(CHUNK_BUFFER);
+    std::thread::scope(|scope| {
+        let unpack = scope.spawn(move || {
+            let mut archive = tar::Archive::new(ChannelReader::new(receiver));
+            archive.unpack(destination)?;
+            // Drain the remainder of the stream, e.g. the padding at the end
+            // of the archive.
+            std::io::copy(&mut archive.into_inner(), &mut std::io::sink())?;
+            Ok::<_, ExtractError>(())
+        });
+
+        let mut decompress_result = Ok(());
+        loop {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            match decoder.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => {
+                    chunk.truncate(len);
+                    if sender.send(chunk).is_err() {
+                        // The unpacking thread stopped, its result contains the
+                        // reason.
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    decompress_result = Err(ExtractError::from(e));
+                    break;
+                }
+            }
+        }
+        drop(sender);
+
+        let unpack_result = match unpack.join() {
+            Ok(result) => result,
+            Err(payload) => std::panic::resume_unwind(payload),
+        };
+        decompress_result.and(unpack_result)
+    })
+}
+
+/// A reader that reads the chunks that are received from a channel.
+struct ChannelReader {
+    receiver: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl ChannelReader {
+    fn new(receiver: Receiver<Vec<u8>>) -> Self {
+        Self {
+            receiver,
+            chunk: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position >= self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                // The sender was dropped, this is the end of the stream.
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.position);
+        buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
 fn compute_hashes<R: Read>(
     mut md5_reader: HashingReader<HashingReader<R, rattler_digest::Sha256>, rattler_digest::Md5>,
 ) -> Result<ExtractResult, ExtractError> {
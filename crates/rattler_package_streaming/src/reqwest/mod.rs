@@ -1,2 +1,3 @@
 //! Functionality to stream and extract packages directly from a [`reqwest::Url`].
+pub mod range;
 pub mod tokio;
@@ -0,0 +1,550 @@
+//! Functionality to read the `info` section of a remote `.conda` package archive with HTTP range
+//! requests.
+//!
+//! A `.conda` archive is a zip file that stores the metadata of a package in a separate
+//! `info-*.tar.zst` member. Instead of downloading the entire package, the functions in this module
+//! only download the zip central directory and the `info` member. This is useful when only the
+//! metadata of a package is required, e.g. when indexing a channel.
+//!
+//! If the server does not support range requests the entire archive is downloaded instead.
+
+use std::{
+    io::{Cursor, Read, SeekFrom},
+    path::Path,
+};
+
+use rattler_conda_types::package::{ArchiveType, PackageFile};
+use reqwest::{header, StatusCode};
+use reqwest_middleware::ClientWithMiddleware;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use url::Url;
+use zip::{result::ZipError, CompressionMethod};
+
+use crate::{read::stream_tar_zst, seek::get_file_from_archive, ExtractError};
+
+/// The number of bytes that are initially requested from the end of the archive. This is large
+/// enough to contain the central directory of a `.conda` archive, which only has a few entries.
+const TAIL_SIZE: u64 = 16 * 1024;
+
+/// The signature of the zip end of central directory record.
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+
+/// The size of the zip end of central directory record without the trailing comment.
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+
+/// The signature of a zip central directory file header.
+const CENTRAL_DIRECTORY_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x01, 0x02];
+
+/// The size of a zip central directory file header without the variable length fields.
+const CENTRAL_DIRECTORY_HEADER_SIZE: usize = 46;
+
+/// The signature of a zip local file header.
+const LOCAL_HEADER_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x03, 0x04];
+
+/// The size of a zip local file header without the variable length fields.
+const LOCAL_HEADER_SIZE: usize = 30;
+
+/// Streams the info section of a remote `.conda` package as a tar archive. Only the parts of the
+/// archive that are required to read the info section are downloaded.
+///
+/// ```rust,no_run
+/// # #[tokio::main]
+/// # async fn main() {
+/// use rattler_package_streaming::reqwest::range::stream_conda_info;
+/// use reqwest::Client;
+/// use reqwest_middleware::ClientWithMiddleware;
+/// use url::Url;
+/// let mut info = stream_conda_info(
+///     ClientWithMiddleware::from(Client::new()),
+///     Url::parse("https://conda.anaconda.org/conda-forge/linux-64/python-3.10.8-h4a9ceb5_0_cpython.conda").unwrap())
+///     .await
+///     .unwrap();
+/// info.unpack("/tmp/python-info").unwrap();
+/// # }
+/// ```
+pub async fn stream_conda_info(
+    client: ClientWithMiddleware,
+    url: Url,
+) -> Result<tar::Archive<impl Read + Sized>, ExtractError> {
+    if ArchiveType::try_from(Path::new(url.path())) != Some(ArchiveType::Conda) {
+        return Err(ExtractError::UnsupportedArchiveType);
+    }
+
+    let info = RangeReader { client, url }.read_info_member().await?;
+    stream_tar_zst(Cursor::new(info))
+}
+
+/// Reads a package file from the info section of a remote `.conda` package. Only the parts of the
+/// archive that are required to read the info section are downloaded.
+///
+/// ```rust,no_run
+/// # #[tokio::main]
+/// # async fn main() {
+/// use rattler_conda_types::package::IndexJson;
+/// use rattler_package_streaming::reqwest::range::read_package_file;
+/// use reqwest::Client;
+/// use reqwest_middleware::ClientWithMiddleware;
+/// use url::Url;
+/// let index_json = read_package_file::<IndexJson>(
+///     ClientWithMiddleware::from(Client::new()),
+///     Url::parse("https://conda.anaconda.org/conda-forge/linux-64/python-3.10.8-h4a9ceb5_0_cpython.conda").unwrap())
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub async fn read_package_file<P: PackageFile>(
+    client: ClientWithMiddleware,
+    url: Url,
+) -> Result<P, ExtractError> {
+    let mut archive = stream_conda_info(client, url).await?;
+    let buf = get_file_from_archive(&mut archive, P::package_path())?;
+    P::from_str(&String::from_utf8_lossy(&buf))
+        .map_err(|e| ExtractError::ArchiveMemberParseError(P::package_path().to_owned(), e))
+}
+
+/// A range of bytes of an archive.
+enum ByteRange {
+    /// The last `n` bytes of the archive.
+    Suffix(u64),
+
+    /// `len` bytes starting at `start`.
+    Bounded { start: u64, len: u64 },
+}
+
+/// The bytes returned for a [`ByteRange`].
+enum RangeResponse {
+    /// The requested range, `start` is the offset of the first byte in the archive.
+    Partial { start: u64, bytes: Vec<u8> },
+
+    /// The server ignored the range and returned the entire archive.
+    Full(Vec<u8>),
+}
+
+/// The parts of a zip central directory entry that are required to locate the data of the entry.
+struct CentralDirectoryEntry {
+    name: String,
+    compression: u16,
+    compressed_size: u64,
+    local_header_offset: u64,
+}
+
+/// Reads ranges of bytes from a remote archive.
+struct RangeReader {
+    client: ClientWithMiddleware,
+    url: Url,
+}
+
+impl RangeReader {
+    /// Returns the compressed bytes of the `info-*.tar.zst` member of the archive.
+    async fn read_info_member(&self) -> Result<Vec<u8>, ExtractError> {
+        let (tail_start, tail) = match self.read_range(ByteRange::Suffix(TAIL_SIZE)).await? {
+            RangeResponse::Partial { start, bytes } => (start, bytes),
+            RangeResponse::Full(archive) => return info_member_from_archive(archive),
+        };
+
+        let Some((directory_size, directory_offset)) = find_central_directory(&tail) else {
+            // The central directory could not be located (e.g. because the archive uses zip64
+            // extensions), fall back to reading the whole archive.
+            return self.read_entire_archive().await;
+        };
+
+        let directory = if directory_offset >= tail_start {
+            let start = usize::try_from(directory_offset - tail_start).unwrap_or(usize::MAX);
+            let end = start.saturating_add(usize::try_from(directory_size).unwrap_or(usize::MAX));
+            tail.get(start..end)
+                .ok_or(ZipError::InvalidArchive("invalid central directory"))?
+                .to_vec()
+        } else {
+            self.read_partial(directory_offset, directory_size).await?
+        };
+
+        let entry = parse_central_directory(&directory)?
+            .into_iter()
+            .find(|entry| entry.name.starts_with("info-") && entry.name.ends_with(".tar.zst"))
+            .ok_or(ExtractError::MissingComponent)?;
+        // The data can only be read directly if it is stored without compression.
+        if entry.compression != 0 {
+            return Err(ExtractError::UnsupportedCompressionMethod);
+        }
+
+        // The local header has its own variable length fields which may differ from the ones in
+        // the central directory, so they are read first to determine where the data starts.
+        let local_header = self
+            .read_partial(entry.local_header_offset, LOCAL_HEADER_SIZE as u64)
+            .await?;
+        if local_header.len() != LOCAL_HEADER_SIZE || local_header[..4] != LOCAL_HEADER_SIGNATURE {
+            return Err(ZipError::InvalidArchive("invalid local file header").into());
+        }
+        let data_offset = entry.local_header_offset
+            + LOCAL_HEADER_SIZE as u64
+            + u64::from(read_u16(&local_header, 26))
+            + u64::from(read_u16(&local_header, 28));
+
+        self.read_partial(data_offset, entry.compressed_size).await
+    }
+
+    /// Reads exactly `len` bytes starting at `start`.
+    async fn read_partial(&self, start: u64, len: u64) -> Result<Vec<u8>, ExtractError> {
+        match self.read_range(ByteRange::Bounded { start, len }).await? {
+            RangeResponse::Partial { bytes, .. } if bytes.len() as u64 == len => Ok(bytes),
+            RangeResponse::Partial { .. } => {
+                Err(ZipError::InvalidArchive("unexpected end of archive").into())
+            }
+            RangeResponse::Full(archive) => {
+                let start = usize::try_from(start).unwrap_or(usize::MAX);
+                let end = start.saturating_add(usize::try_from(len).unwrap_or(usize::MAX));
+                Ok(archive
+                    .get(start..end)
+                    .ok_or(ZipError::InvalidArchive("unexpected end of archive"))?
+                    .to_vec())
+            }
+        }
+    }
+
+    /// Downloads the entire archive and extracts the info member from it.
+    async fn read_entire_archive(&self) -> Result<Vec<u8>, ExtractError> {
+        let archive = match self.url.scheme() {
+            "file" => tokio::fs::read(self.file_path()?).await?,
+            _ => self
+                .client
+                .get(self.url.clone())
+                .send()
+                .await
+                .and_then(error_for_status)?
+                .bytes()
+                .await
+                .map_err(reqwest_middleware::Error::Reqwest)?
+                .to_vec(),
+        };
+        info_member_from_archive(archive)
+    }
+
+    /// Reads a range of bytes from the archive.
+    async fn read_range(&self, range: ByteRange) -> Result<RangeResponse, ExtractError> {
+        if self.url.scheme() == "file" {
+            return self.read_file_range(range).await;
+        }
+
+        let range_header = match range {
+            ByteRange::Suffix(len) => format!("bytes=-{len}"),
+            ByteRange::Bounded { start, len } => {
+                format!("bytes={start}-{}", start + len.max(1) - 1)
+            }
+        };
+
+        let response = self
+            .client
+            .get(self.url.clone())
+            .header(header::RANGE, range_header)
+            .send()
+            .await
+            .and_then(error_for_status)?;
+
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            tracing::debug!(
+                "server does not support range requests, downloading the entire archive"
+            );
+            let bytes = response
+                .bytes()
+                .await
+                .map_err(reqwest_middleware::Error::Reqwest)?;
+            return Ok(RangeResponse::Full(bytes.to_vec()));
+        }
+
+        let start = response
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_content_range_start)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "missing or invalid Content-Range header",
+                )
+            })?;
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(reqwest_middleware::Error::Reqwest)?;
+
+        Ok(RangeResponse::Partial {
+            start,
+            bytes: bytes.to_vec(),
+        })
+    }
+
+    /// Reads a range of bytes from a local archive.
+    async fn read_file_range(&self, range: ByteRange) -> Result<RangeResponse, ExtractError> {
+        let mut file = tokio::fs::File::open(self.file_path()?).await?;
+        let file_len = file.metadata().await?.len();
+        let (start, len) = match range {
+            ByteRange::Suffix(len) => (file_len.saturating_sub(len), len.min(file_len)),
+            ByteRange::Bounded { start, len } => (start, len.min(file_len.saturating_sub(start))),
+        };
+
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut bytes = Vec::with_capacity(usize::try_from(len).unwrap_or_default());
+        file.take(len).read_to_end(&mut bytes).await?;
+        Ok(RangeResponse::Partial { start, bytes })
+    }
+
+    fn file_path(&self) -> Result<std::path::PathBuf, ExtractError> {
+        self.url.to_file_path().map_err(|()| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid file url").into()
+        })
+    }
+}
+
+fn error_for_status(response: reqwest::Response) -> reqwest_middleware::Result<reqwest::Response> {
+    response
+        .error_for_status()
+        .map_err(reqwest_middleware::Error::Reqwest)
+}
+
+/// Extracts the compressed bytes of the `info-*.tar.zst` member from an entire archive.
+fn info_member_from_archive(archive: Vec<u8>) -> Result<Vec<u8>, ExtractError> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive.as_slice()))?;
+    let file_name = zip
+        .file_names()
+        .find(|file_name| file_name.starts_with("info-") && file_name.ends_with(".tar.zst"))
+        .ok_or(ExtractError::MissingComponent)?
+        .to_owned();
+
+    let entry = zip.by_name(&file_name)?;
+    if entry.compression() != CompressionMethod::Stored {
+        return Err(ExtractError::UnsupportedCompressionMethod);
+    }
+    let start = usize::try_from(entry.data_start()).unwrap_or(usize::MAX);
+    let end = start.saturating_add(usize::try_from(entry.compressed_size()).unwrap_or(usize::MAX));
+    Ok(archive
+        .get(start..end)
+        .ok_or(ZipError::InvalidArchive("unexpected end of archive"))?
+        .to_vec())
+}
+
+/// Locates the end of central directory record in the tail of a zip archive and returns the size
+/// and offset of the central directory. Returns `None` if the record cannot be found or if the
+/// archive uses zip64 extensions.
+fn find_central_directory(tail: &[u8]) -> Option<(u64, u64)> {
+    let position = tail
+        .windows(END_OF_CENTRAL_DIRECTORY_SIGNATURE.len())
+        .rposition(|window| window == END_OF_CENTRAL_DIRECTORY_SIGNATURE)?;
+    let record = tail.get(position..position + END_OF_CENTRAL_DIRECTORY_SIZE)?;
+    let size = read_u32(record, 12);
+    let offset = read_u32(record, 16);
+    if size == u32::MAX || offset == u32::MAX {
+        return None;
+    }
+    Some((u64::from(size), u64::from(offset)))
+}
+
+/// Parses the entries of a zip central directory.
+fn parse_central_directory(mut directory: &[u8]) -> Result<Vec<CentralDirectoryEntry>, ZipError> {
+    let mut entries = Vec::new();
+    while directory.len() >= CENTRAL_DIRECTORY_HEADER_SIZE
+        && directory[..4] == CENTRAL_DIRECTORY_SIGNATURE
+    {
+        let name_len = usize::from(read_u16(directory, 28));
+        let extra_len = usize::from(read_u16(directory, 30));
+        let comment_len = usize::from(read_u16(directory, 32));
+        let name = directory
+            .get(CENTRAL_DIRECTORY_HEADER_SIZE..CENTRAL_DIRECTORY_HEADER_SIZE + name_len)
+            .ok_or(ZipError::InvalidArchive("invalid central directory"))?;
+
+        entries.push(CentralDirectoryEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            compression: read_u16(directory, 10),
+            compressed_size: u64::from(read_u32(directory, 20)),
+            local_header_offset: u64::from(read_u32(directory, 42)),
+        });
+
+        let entry_len = CENTRAL_DIRECTORY_HEADER_SIZE + name_len + extra_len + comment_len;
+        directory = directory.get(entry_len..).unwrap_or_default();
+    }
+    Ok(entries)
+}
+
+/// Parses the start offset from a `Content-Range` header, e.g. `bytes 100-199/1000`.
+fn parse_content_range_start(value: &str) -> Option<u64> {
+    let (start, _) = value.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::IntoFuture,
+        net::SocketAddr,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::Body,
+        extract::State,
+        http::{header, Request, StatusCode},
+        middleware,
+        middleware::Next,
+        response::Response,
+        routing::get_service,
+        Router,
+    };
+    use rattler_conda_types::package::IndexJson;
+    use reqwest::Client;
+    use reqwest_middleware::ClientWithMiddleware;
+    use rstest::rstest;
+    use tower_http::services::ServeDir;
+    use url::Url;
+
+    use super::{parse_content_range_start, read_package_file, TAIL_SIZE};
+    use crate::write::{write_conda_package, CompressionLevel};
+
+    const CLOBBER_PYTHON: &str = "clobber/clobber-python-0.1.0-cpython.conda";
+
+    fn test_data_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data")
+    }
+
+    /// Writes a copy of the `clobber-python` package to `dir` with an
+    /// additional incompressible file, so the archive is larger than the tail
+    /// that is read initially.
+    fn write_large_conda_package(dir: &Path) -> PathBuf {
+        let package_dir = dir.join("package");
+        crate::fs::extract(&test_data_dir().join(CLOBBER_PYTHON), &package_dir).unwrap();
+
+        // Pseudo-random bytes are not compressed by zstd.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let data = (0..4 * TAIL_SIZE)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state.to_le_bytes()[0]
+            })
+            .collect::<Vec<u8>>();
+        std::fs::create_dir_all(package_dir.join("lib")).unwrap();
+        std::fs::write(package_dir.join("lib/data.bin"), data).unwrap();
+
+        let paths = walkdir::WalkDir::new(&package_dir)
+            .into_iter()
+            .map(Result::unwrap)
+            .filter(|entry| entry.file_type().is_file())
+            .map(walkdir::DirEntry::into_path)
+            .collect::<Vec<_>>();
+        let archive_path = dir.join("clobber-python-0.1.0-cpython.conda");
+        write_conda_package(
+            std::fs::File::create(&archive_path).unwrap(),
+            &package_dir,
+            &paths,
+            CompressionLevel::Default,
+            None,
+            "clobber-python-0.1.0-cpython",
+            None,
+            None,
+        )
+        .unwrap();
+        archive_path
+    }
+
+    type Statuses = Arc<Mutex<Vec<StatusCode>>>;
+
+    /// Records the status of every response. If range requests are not
+    /// supported the `Range` header is removed so the entire file is returned.
+    async fn record_status(
+        State((support_ranges, statuses)): State<(bool, Statuses)>,
+        mut req: Request<Body>,
+        next: Next,
+    ) -> Response {
+        if !support_ranges {
+            req.headers_mut().remove(header::RANGE);
+        }
+        let response = next.run(req).await;
+        statuses.lock().unwrap().push(response.status());
+        response
+    }
+
+    /// Serves the files in `dir` on a random port and returns the url of the
+    /// server and the statuses of the responses it sends.
+    async fn serve_dir(dir: &Path, support_ranges: bool) -> (Url, Statuses) {
+        let statuses = Statuses::default();
+        let router = Router::new()
+            .fallback_service(get_service(ServeDir::new(dir)))
+            .layer(middleware::from_fn_with_state(
+                (support_ranges, statuses.clone()),
+                record_status,
+            ));
+
+        let addr = SocketAddr::new([127, 0, 0, 1].into(), 0);
+        let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router).into_future());
+
+        let url = Url::parse(&format!("http://localhost:{}", addr.port())).unwrap();
+        (url, statuses)
+    }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(parse_content_range_start("bytes 100-199/1000"), Some(100));
+        assert_eq!(parse_content_range_start("bytes 0-0/*"), Some(0));
+        assert_eq!(parse_content_range_start("bytes */1000"), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_package_file_from_file_url() {
+        let path = test_data_dir().join(CLOBBER_PYTHON);
+        let expected: IndexJson = crate::seek::read_package_file(&path).unwrap();
+
+        let index_json: IndexJson = read_package_file(
+            ClientWithMiddleware::from(Client::new()),
+            Url::from_file_path(path.canonicalize().unwrap()).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(index_json, expected);
+    }
+
+    #[rstest]
+    #[case::partial_content(true, StatusCode::PARTIAL_CONTENT)]
+    #[case::full_content(false, StatusCode::OK)]
+    #[tokio::test]
+    async fn test_read_package_file_from_server(
+        #[case] support_ranges: bool,
+        #[case] expected_status: StatusCode,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_large_conda_package(dir.path());
+        assert!(std::fs::metadata(&path).unwrap().len() > TAIL_SIZE);
+        let expected: IndexJson = crate::seek::read_package_file(&path).unwrap();
+
+        let (server_url, statuses) = serve_dir(dir.path(), support_ranges).await;
+        let index_json: IndexJson = read_package_file(
+            ClientWithMiddleware::from(Client::new()),
+            server_url
+                .join("clobber-python-0.1.0-cpython.conda")
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(index_json, expected);
+
+        // Without range support the archive is downloaded once, otherwise only
+        // the tail, the local header and the info member are requested.
+        let statuses = statuses.lock().unwrap().clone();
+        assert!(statuses.iter().all(|status| *status == expected_status));
+        assert_eq!(statuses.len(), if support_ranges { 3 } else { 1 });
+    }
+}
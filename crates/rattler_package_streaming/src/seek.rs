@@ -4,7 +4,7 @@
 use crate::read::{stream_tar_bz2, stream_tar_zst};
 use crate::ExtractError;
 use rattler_conda_types::package::ArchiveType;
-use rattler_conda_types::package::PackageFile;
+use rattler_conda_types::package::{IndexJson, LinkJson, PackageFile, PathsJson};
 use std::fs::File;
 use std::{
     io::{Read, Seek, SeekFrom},
@@ -69,7 +69,7 @@ pub fn stream_conda_content<'a>(
     stream_conda_zip_entry(archive, &file_name)
 }
 
-fn get_file_from_archive(
+pub(crate) fn get_file_from_archive(
     archive: &mut Archive<impl Read>,
     file_name: &Path,
 ) -> Result<Vec<u8>, ExtractError> {
@@ -118,3 +118,98 @@ pub fn read_package_file<P: PackageFile>(path: impl AsRef<Path>) -> Result<P, Ex
         }
     };
 }
+
+/// The parsed metadata files from the `info/` section of a package archive.
+/// See [`read_package_metadata`].
+#[derive(Debug, Clone)]
+pub struct PackageMetadata {
+    /// The `info/index.json` file of the package.
+    pub index_json: IndexJson,
+
+    /// The `info/paths.json` file of the package or `None` if the package
+    /// does not contain the file, e.g. because it is a very old package.
+    pub paths_json: Option<PathsJson>,
+
+    /// The `info/link.json` file of the package or `None` if the package does
+    /// not contain the file. Only noarch python packages contain this file.
+    pub link_json: Option<LinkJson>,
+}
+
+/// Reads the `index.json`, `paths.json` and `link.json` files of a package
+/// archive in a single pass over the archive.
+///
+/// This is faster than calling [`read_package_file`] for every file because
+/// the archive is only opened and decompressed once. Packages store the
+/// `info/` directory before the rest of the files, so reading stops at the
+/// first entry after the `info/` directory. Returns
+/// [`ExtractError::MissingComponent`] if the archive does not contain an
+/// `index.json` file.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rattler_package_streaming::seek::read_package_metadata;
+///
+/// let metadata = read_package_metadata("conda-forge/noarch/mock-5.0.0-pyhd8ed1ab_0.conda").unwrap();
+/// println!("{}", metadata.index_json.name.as_normalized());
+/// ```
+pub fn read_package_metadata(path: impl AsRef<Path>) -> Result<PackageMetadata, ExtractError> {
+    let file = File::open(&path)?;
+    match ArchiveType::try_from(&path).ok_or(ExtractError::UnsupportedArchiveType)? {
+        ArchiveType::TarBz2 => read_metadata_from_archive(&mut stream_tar_bz2(file)),
+        ArchiveType::Conda => read_metadata_from_archive(&mut stream_conda_info(file)?),
+    }
+}
+
+fn read_metadata_from_archive(
+    archive: &mut Archive<impl Read>,
+) -> Result<PackageMetadata, ExtractError> {
+    fn parse<P: PackageFile>(buf: &[u8]) -> Result<P, ExtractError> {
+        P::from_str(&String::from_utf8_lossy(buf))
+            .map_err(|e| ExtractError::ArchiveMemberParseError(P::package_path().to_owned(), e))
+    }
+
+    let mut index_json = None;
+    let mut paths_json = None;
+    let mut link_json = None;
+    let mut seen_info = false;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        // Stop once the entries of the `info/` directory have been read, the
+        // remaining entries are the (potentially large) files of the package.
+        if !path.starts_with("info") {
+            if seen_info {
+                break;
+            }
+            continue;
+        }
+        seen_info = true;
+
+        let target = if path == IndexJson::package_path() {
+            &mut index_json
+        } else if path == PathsJson::package_path() {
+            &mut paths_json
+        } else if path == LinkJson::package_path() {
+            &mut link_json
+        } else {
+            continue;
+        };
+
+        let mut buf = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut buf)?;
+        *target = Some(buf);
+
+        // Stop early once all files have been found.
+        if index_json.is_some() && paths_json.is_some() && link_json.is_some() {
+            break;
+        }
+    }
+
+    Ok(PackageMetadata {
+        index_json: parse(&index_json.ok_or(ExtractError::MissingComponent)?)?,
+        paths_json: paths_json.as_deref().map(parse).transpose()?,
+        link_json: link_json.as_deref().map(parse).transpose()?,
+    })
+}
@@ -6,8 +6,11 @@ use std::{
 
 use rattler_conda_types::package::IndexJson;
 use rattler_package_streaming::{
-    read::{extract_conda_via_buffering, extract_conda_via_streaming, extract_tar_bz2},
-    ExtractError,
+    read::{
+        extract_conda_via_buffering, extract_conda_via_streaming,
+        extract_conda_via_streaming_with_options, extract_tar_bz2,
+    },
+    ExtractError, ExtractOptions,
 };
 use rstest::rstest;
 use rstest_reuse::{self, apply, template};
@@ -122,6 +125,53 @@ fn test_extract_conda(#[case] input: Url, #[case] sha256: &str, #[case] md5: &st
     assert_eq!(&format!("{:x}", result.md5), md5);
 }
 
+#[apply(conda_archives)]
+fn test_extract_conda_multithreaded(#[case] input: Url, #[case] sha256: &str, #[case] md5: &str) {
+    let temp_dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let file_path = tools::download_and_cache_file(input, sha256).unwrap();
+    let name = file_path.file_stem().unwrap().to_string_lossy();
+
+    let mut extracted = Vec::new();
+    for multithreaded in [false, true] {
+        let target_dir = temp_dir.join(format!("{name}-multithreaded-{multithreaded}"));
+        let result = extract_conda_via_streaming_with_options(
+            File::open(&file_path).unwrap(),
+            &target_dir,
+            &ExtractOptions {
+                multithreaded,
+                multithreaded_threshold: 0,
+                ..ExtractOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(&format!("{:x}", result.sha256), sha256);
+        assert_eq!(&format!("{:x}", result.md5), md5);
+
+        let mut files = walkdir::WalkDir::new(&target_dir)
+            .sort_by_file_name()
+            .into_iter()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let contents = entry
+                    .file_type()
+                    .is_file()
+                    .then(|| std::fs::read(entry.path()).unwrap());
+                (
+                    entry
+                        .path()
+                        .strip_prefix(&target_dir)
+                        .unwrap()
+                        .to_path_buf(),
+                    contents,
+                )
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        extracted.push(files);
+    }
+    assert_eq!(extracted[0], extracted[1]);
+}
+
 #[apply(conda_archives)]
 fn test_stream_info(#[case] input: Url, #[case] sha256: &str, #[case] _md5: &str) {
     let temp_dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
@@ -326,3 +376,47 @@ impl<R: Read> Read for FlakyReader<R> {
         Ok(bytes_read)
     }
 }
+
+#[apply(conda_archives)]
+fn read_package_metadata(#[case] input: Url, #[case] sha256: &str, #[case] _md5: &str) {
+    let file_path = tools::download_and_cache_file(input, sha256).unwrap();
+    let metadata = rattler_package_streaming::seek::read_package_metadata(&file_path).unwrap();
+
+    let index_json: IndexJson =
+        rattler_package_streaming::seek::read_package_file(&file_path).unwrap();
+    assert_eq!(metadata.index_json.name, index_json.name);
+    assert_eq!(metadata.index_json.version, index_json.version);
+    assert!(metadata.paths_json.is_some());
+    if metadata.index_json.noarch.is_python() {
+        assert!(metadata.link_json.is_some());
+    }
+}
+
+#[test]
+fn read_package_metadata_stops_after_info() {
+    // A package with an `info/` file after the files of the package.
+    let file_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("stray-info-1.0-0.tar.bz2");
+    let mut builder = tar::Builder::new(bzip2::write::BzEncoder::new(
+        File::create(&file_path).unwrap(),
+        bzip2::Compression::default(),
+    ));
+    let mut append = |path: &str, contents: &str| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, path, contents.as_bytes())
+            .unwrap();
+    };
+    append(
+        "info/index.json",
+        r#"{"name": "stray-info", "version": "1.0", "build": "0", "build_number": 0}"#,
+    );
+    append("lib/libfoo.so", "foo");
+    append("info/link.json", "not json");
+    builder.into_inner().unwrap().finish().unwrap();
+
+    let metadata = rattler_package_streaming::seek::read_package_metadata(&file_path).unwrap();
+    assert_eq!(metadata.index_json.name.as_normalized(), "stray-info");
+    assert!(metadata.link_json.is_none());
+}
@@ -0,0 +1,178 @@
+//! Benchmarks of fetching, parsing and querying repodata with synthetic
+//! channels of different sizes.
+//!
+//! The channels are generated with [`SyntheticChannel`] so the numbers are
+//! comparable between runs and machines. Remote channels are served from a
+//! local HTTP server to exclude network latency.
+
+use std::{future::IntoFuture, net::SocketAddr, path::Path};
+
+use axum::routing::get_service;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, SamplingMode};
+use rattler_conda_types::{Channel, RepoData};
+use rattler_repodata_gateway::{
+    fetch::{CacheAction, CacheRefreshPolicy},
+    sparse::SparseRepoData,
+    synthetic_channel::SyntheticChannel,
+    ChannelConfig, Gateway, SourceConfig,
+};
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+use tower_http::services::ServeDir;
+use url::Url;
+
+/// The channel configurations that are benchmarked.
+fn channels() -> [(&'static str, SyntheticChannel); 2] {
+    [
+        ("small", SyntheticChannel::new().with_package_count(500)),
+        ("large", SyntheticChannel::new().with_package_count(5000)),
+    ]
+}
+
+/// Serves the directory at `path` over HTTP and returns the url of the server.
+async fn serve(path: &Path) -> Url {
+    let app = axum::Router::new().fallback_service(get_service(ServeDir::new(path)));
+    let listener = tokio::net::TcpListener::bind(SocketAddr::new([127, 0, 0, 1].into(), 0))
+        .await
+        .unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(axum::serve(listener, app).into_future());
+    Url::parse(&format!("http://localhost:{port}")).unwrap()
+}
+
+/// Constructs a gateway that stores its cache in `cache_dir` and uses the
+/// given cache settings for all channels.
+fn gateway(cache_dir: &Path, cache_action: CacheAction) -> Gateway {
+    Gateway::builder()
+        .with_cache_dir(cache_dir)
+        .with_channel_config(ChannelConfig {
+            default: SourceConfig {
+                cache_action,
+                refresh_policy: CacheRefreshPolicy::Immutable,
+                ..SourceConfig::default()
+            },
+            ..ChannelConfig::default()
+        })
+        .finish()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.sampling_mode(SamplingMode::Flat);
+    group.sample_size(20);
+
+    for (name, synthetic) in channels() {
+        let channel_dir = TempDir::new().unwrap();
+        synthetic.write(channel_dir.path()).unwrap();
+        let repodata_path = channel_dir.path().join("linux-64/repodata.json");
+
+        group.bench_function(format!("{name}/repodata"), |b| {
+            b.iter(|| RepoData::from_path(&repodata_path).unwrap());
+        });
+
+        let channel = Channel::from_url(Url::from_directory_path(channel_dir.path()).unwrap());
+        group.bench_function(format!("{name}/sparse"), |b| {
+            b.iter(|| {
+                let sparse = synthetic
+                    .subdirs()
+                    .iter()
+                    .map(|subdir| {
+                        SparseRepoData::new(
+                            channel.clone(),
+                            subdir.as_str(),
+                            channel_dir
+                                .path()
+                                .join(subdir.as_str())
+                                .join("repodata.json"),
+                            None,
+                        )
+                        .unwrap()
+                    })
+                    .collect::<Vec<_>>();
+                SparseRepoData::load_records_recursive(
+                    &sparse,
+                    [synthetic.root_package_name()],
+                    None,
+                )
+                .unwrap()
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_query(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query");
+    group.sampling_mode(SamplingMode::Flat);
+    group.sample_size(10);
+
+    let runtime = Runtime::new().unwrap();
+    for (name, synthetic) in channels() {
+        let channel_dir = TempDir::new().unwrap();
+        synthetic.write(channel_dir.path()).unwrap();
+        let platforms = synthetic.subdirs().to_vec();
+        let query = |gateway: &Gateway, channel: &Channel| {
+            runtime
+                .block_on(
+                    gateway
+                        .query(
+                            [channel.clone()],
+                            platforms.iter().copied(),
+                            [synthetic.root_package_name()],
+                        )
+                        .recursive(true)
+                        .into_future(),
+                )
+                .unwrap()
+        };
+
+        // A channel on the local filesystem, this is never cached on disk.
+        let local_channel =
+            Channel::from_url(Url::from_directory_path(channel_dir.path()).unwrap());
+        let cache_dir = TempDir::new().unwrap();
+        group.bench_function(format!("{name}/local/cold"), |b| {
+            b.iter_batched(
+                || gateway(cache_dir.path(), CacheAction::default()),
+                |gateway| query(&gateway, &local_channel),
+                BatchSize::PerIteration,
+            );
+        });
+        let warm_gateway = gateway(cache_dir.path(), CacheAction::default());
+        query(&warm_gateway, &local_channel);
+        group.bench_function(format!("{name}/local/in-memory"), |b| {
+            b.iter(|| query(&warm_gateway, &local_channel));
+        });
+
+        // A remote channel that is fetched over HTTP.
+        let remote_channel = Channel::from_url(runtime.block_on(serve(channel_dir.path())));
+        group.bench_function(format!("{name}/remote/no-cache"), |b| {
+            b.iter_batched(
+                || gateway(cache_dir.path(), CacheAction::NoCache),
+                |gateway| query(&gateway, &remote_channel),
+                BatchSize::PerIteration,
+            );
+        });
+        query(
+            &gateway(cache_dir.path(), CacheAction::default()),
+            &remote_channel,
+        );
+        group.bench_function(format!("{name}/remote/disk-cache"), |b| {
+            b.iter_batched(
+                || gateway(cache_dir.path(), CacheAction::ForceCacheOnly),
+                |gateway| query(&gateway, &remote_channel),
+                BatchSize::PerIteration,
+            );
+        });
+        let warm_gateway = gateway(cache_dir.path(), CacheAction::default());
+        query(&warm_gateway, &remote_channel);
+        group.bench_function(format!("{name}/remote/in-memory"), |b| {
+            b.iter(|| query(&warm_gateway, &remote_channel));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_query);
+criterion_main!(benches);
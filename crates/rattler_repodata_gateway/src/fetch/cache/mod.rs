@@ -61,6 +61,10 @@ pub struct RepoDataState {
 
     /// State information related to JLAP
     pub jlap: Option<JLAPState>,
+
+    /// The content trust metadata that was used to verify the signatures of the repodata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_trust: Option<ContentTrustState>,
 }
 
 impl RepoDataState {
@@ -114,6 +118,22 @@ pub struct JLAPFooter {
     pub latest: blake2::digest::Output<Blake2b256>,
 }
 
+/// Used inside of the `RepoDataState` to store the content trust metadata that was verified
+/// together with the repodata, see [`super::content_trust`].
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentTrustState {
+    /// The newest verified version of the root metadata.
+    pub root: String,
+
+    /// The verified key manager metadata.
+    pub key_mgr: String,
+
+    /// blake2b hash of the `repodata.json` file whose signatures were verified
+    #[serde_as(as = "SerializableHash::<rattler_digest::Blake2b256>")]
+    pub verified_blake2_hash: blake2::digest::Output<Blake2b256>,
+}
+
 /// Represents a value and when the value was last checked.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Expiring<T> {
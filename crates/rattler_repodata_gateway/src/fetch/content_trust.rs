@@ -0,0 +1,878 @@
+//! Verification of signed repodata following conda's content trust model.
+//!
+//! A channel that supports content trust signs the records in its `repodata.json`. The keys that
+//! are allowed to sign the records are delegated through a chain of signed metadata files:
+//!
+//! - `root.json` is the root of trust. The initial version is provided by the user as a
+//!   [`TrustedRoot`]. Newer versions are published by the channel as `<version>.root.json` and
+//!   must be signed by the keys of both the previous and the new version.
+//! - `key_mgr.json` is signed by the `key_mgr` keys that are delegated by the root and delegates
+//!   the `pkg_mgr` keys.
+//! - The `signatures` of the repodata contain the signatures of every record by the `pkg_mgr`
+//!   keys.
+//!
+//! Only ed25519 signatures are supported, signatures with OpenPGP headers are ignored.
+
+use std::{collections::HashMap, fmt::Write, path::Path};
+
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use rattler_digest::Blake2b256;
+use rattler_redaction::{DisplayRedacted, Redact};
+use reqwest::StatusCode;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::Deserialize;
+use serde_json::Value;
+use simple_spawn_blocking::{tokio::run_blocking_task, Cancelled};
+use url::Url;
+
+use super::ContentTrustState;
+
+/// Determines how the signatures of repodata are verified.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SignaturePolicy {
+    /// The signatures are not verified.
+    #[default]
+    Disabled,
+
+    /// The signatures are verified but a failed verification only results in a warning.
+    Warn,
+
+    /// The signatures are verified and a failed verification results in an error.
+    Enforce,
+}
+
+/// An error that occurred while verifying signed metadata or repodata.
+#[derive(Debug, thiserror::Error)]
+pub enum ContentTrustError {
+    /// No trusted root metadata was configured to verify the signatures with.
+    #[error("no trusted root metadata is configured")]
+    NoTrustedRoot,
+
+    /// The metadata could not be parsed.
+    #[error("failed to parse the {0} metadata")]
+    InvalidMetadata(String, #[source] serde_json::Error),
+
+    /// The metadata is of a different type than expected.
+    #[error("expected {expected} metadata but found {found} metadata")]
+    UnexpectedType {
+        /// The expected type
+        expected: String,
+        /// The type of the metadata
+        found: String,
+    },
+
+    /// The root metadata does not have the version that follows the trusted root.
+    #[error("expected version {expected} of the root metadata but found version {found}")]
+    UnexpectedVersion {
+        /// The expected version
+        expected: u64,
+        /// The version of the metadata
+        found: u64,
+    },
+
+    /// The metadata does not delegate a role that is required.
+    #[error("the {0} role is not delegated")]
+    MissingDelegation(String),
+
+    /// The metadata is not signed by enough of the delegated keys.
+    #[error("the {role} metadata has {found} valid signatures but requires {threshold}")]
+    NotEnoughSignatures {
+        /// The role of the metadata
+        role: String,
+        /// The number of valid signatures
+        found: usize,
+        /// The number of required signatures
+        threshold: usize,
+    },
+
+    /// The metadata has expired.
+    #[error("the {0} metadata expired at {1}")]
+    Expired(String, DateTime<Utc>),
+
+    /// The records of some packages are not signed correctly.
+    #[error("the signatures of {} packages could not be verified, e.g. '{}'", .0.len(), .0[0])]
+    InvalidPackageSignatures(Vec<String>),
+
+    /// The repodata could not be parsed.
+    #[error("failed to parse the repodata")]
+    InvalidRepoData(#[source] serde_json::Error),
+
+    /// The metadata could not be fetched.
+    #[error(transparent)]
+    HttpError(reqwest_middleware::Error),
+
+    /// The repodata could not be read.
+    #[error(transparent)]
+    IoError(std::io::Error),
+
+    /// The metadata has to be fetched but network requests are not allowed.
+    #[error("the content trust metadata is not cached and cannot be fetched")]
+    MetadataNotCached,
+
+    /// The signatures of sharded repodata cannot be verified.
+    #[error("the signatures of sharded repodata cannot be verified")]
+    ShardedRepoData,
+
+    /// The operation was cancelled
+    #[error("the operation was cancelled")]
+    Cancelled,
+}
+
+impl From<Cancelled> for ContentTrustError {
+    fn from(_: Cancelled) -> Self {
+        ContentTrustError::Cancelled
+    }
+}
+
+impl From<reqwest_middleware::Error> for ContentTrustError {
+    fn from(err: reqwest_middleware::Error) -> Self {
+        Self::HttpError(err.redact())
+    }
+}
+
+impl From<reqwest::Error> for ContentTrustError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::HttpError(err.redact().into())
+    }
+}
+
+/// The keys that are allowed to sign the metadata of a role.
+#[derive(Debug, Clone, Deserialize)]
+struct Delegation {
+    pubkeys: Vec<String>,
+    threshold: usize,
+}
+
+/// The part of a metadata file that is signed.
+#[derive(Debug, Clone, Deserialize)]
+struct RoleMetadata {
+    #[serde(rename = "type")]
+    role_type: String,
+    version: u64,
+    expiration: DateTime<Utc>,
+    #[serde(default)]
+    delegations: HashMap<String, Delegation>,
+}
+
+impl RoleMetadata {
+    fn delegation(&self, role: &str) -> Result<&Delegation, ContentTrustError> {
+        self.delegations
+            .get(role)
+            .ok_or_else(|| ContentTrustError::MissingDelegation(role.to_string()))
+    }
+
+    fn check_expiration(&self) -> Result<(), ContentTrustError> {
+        if self.expiration <= Utc::now() {
+            return Err(ContentTrustError::Expired(
+                self.role_type.clone(),
+                self.expiration,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A signature of a metadata file or record.
+#[derive(Debug, Deserialize)]
+struct SignatureEntry {
+    signature: String,
+    #[serde(default)]
+    other_headers: Option<String>,
+}
+
+/// A metadata file with its signatures.
+#[derive(Debug, Deserialize)]
+struct SignedMetadata {
+    #[serde(default)]
+    signatures: HashMap<String, SignatureEntry>,
+    signed: Value,
+}
+
+/// Parses a metadata file and checks that it is of the expected type.
+fn parse_metadata(
+    json: &str,
+    expected_type: &str,
+) -> Result<(SignedMetadata, RoleMetadata), ContentTrustError> {
+    let invalid = |err| ContentTrustError::InvalidMetadata(expected_type.to_string(), err);
+    let signed: SignedMetadata = serde_json::from_str(json).map_err(invalid)?;
+    let metadata = RoleMetadata::deserialize(&signed.signed).map_err(invalid)?;
+    if metadata.role_type != expected_type {
+        return Err(ContentTrustError::UnexpectedType {
+            expected: expected_type.to_string(),
+            found: metadata.role_type,
+        });
+    }
+    Ok((signed, metadata))
+}
+
+/// The root metadata of a channel that is trusted to delegate the keys of the other roles.
+#[derive(Debug, Clone)]
+pub struct TrustedRoot {
+    metadata: RoleMetadata,
+    json: String,
+}
+
+impl TrustedRoot {
+    /// Constructs the trusted root from the contents of a `root.json` file. The file itself is
+    /// trusted as is, it should therefore come from a trusted source, e.g. be shipped with the
+    /// application.
+    pub fn from_json(json: &str) -> Result<Self, ContentTrustError> {
+        let (_, metadata) = parse_metadata(json, "root")?;
+        Ok(Self {
+            metadata,
+            json: json.to_string(),
+        })
+    }
+
+    /// Returns the version of the root metadata.
+    pub fn version(&self) -> u64 {
+        self.metadata.version
+    }
+
+    /// Returns the time at which the root metadata expires.
+    pub fn expiration(&self) -> DateTime<Utc> {
+        self.metadata.expiration
+    }
+
+    /// Verifies the next version of the root metadata. The new version must be signed by the
+    /// root keys of both this and the new version.
+    pub fn update(&self, json: &str) -> Result<Self, ContentTrustError> {
+        let (signed, metadata) = parse_metadata(json, "root")?;
+        if metadata.version != self.metadata.version + 1 {
+            return Err(ContentTrustError::UnexpectedVersion {
+                expected: self.metadata.version + 1,
+                found: metadata.version,
+            });
+        }
+
+        let message = canonical_json(&signed.signed);
+        for delegation in [
+            self.metadata.delegation("root")?,
+            metadata.delegation("root")?,
+        ] {
+            verify_threshold("root", &message, &signed.signatures, delegation)?;
+        }
+
+        Ok(Self {
+            metadata,
+            json: json.to_string(),
+        })
+    }
+
+    /// Verifies the contents of a `key_mgr.json` file with the keys delegated by the root.
+    pub fn verify_key_mgr(&self, json: &str) -> Result<KeyManager, ContentTrustError> {
+        let (signed, metadata) = parse_metadata(json, "key_mgr")?;
+        verify_threshold(
+            "key_mgr",
+            &canonical_json(&signed.signed),
+            &signed.signatures,
+            self.metadata.delegation("key_mgr")?,
+        )?;
+        metadata.check_expiration()?;
+        Ok(KeyManager {
+            metadata,
+            json: json.to_string(),
+        })
+    }
+}
+
+/// Verified key manager metadata which delegates the keys that sign the records of packages.
+#[derive(Debug, Clone)]
+pub struct KeyManager {
+    metadata: RoleMetadata,
+    json: String,
+}
+
+impl KeyManager {
+    /// Returns true if the record of a package is signed by the delegated package keys.
+    /// `signatures` is the entry of the package in the `signatures` of the repodata.
+    pub fn verify_package(&self, record: &Value, signatures: &Value) -> bool {
+        let Ok(delegation) = self.metadata.delegation("pkg_mgr") else {
+            return false;
+        };
+        let Ok(signatures) = HashMap::<String, SignatureEntry>::deserialize(signatures) else {
+            return false;
+        };
+        count_valid_signatures(&canonical_json(record), &signatures, delegation)
+            >= delegation.threshold.max(1)
+    }
+
+    /// Verifies the signatures of all the records in the repodata.
+    pub fn verify_repo_data(&self, repo_data: &Value) -> Result<(), ContentTrustError> {
+        self.metadata.delegation("pkg_mgr")?;
+
+        let signatures = repo_data.get("signatures");
+        let mut invalid = Vec::new();
+        for key in ["packages", "packages.conda"] {
+            let Some(packages) = repo_data.get(key).and_then(Value::as_object) else {
+                continue;
+            };
+            for (file_name, record) in packages {
+                let verified = signatures
+                    .and_then(|signatures| signatures.get(file_name))
+                    .is_some_and(|signatures| self.verify_package(record, signatures));
+                if !verified {
+                    invalid.push(file_name.clone());
+                }
+            }
+        }
+
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(ContentTrustError::InvalidPackageSignatures(invalid))
+        }
+    }
+}
+
+/// Verifies the signatures of the repodata of a subdirectory that is stored at
+/// `repo_data_json_path` according to the policy.
+///
+/// Newer versions of the root metadata and the key manager metadata are fetched from the channel
+/// that contains the subdirectory, or taken from the `cached` state if `offline` is true. Nothing
+/// is verified if the `cached` state shows that the same repodata was verified before.
+///
+/// Returns the state that should be stored in the cache, or `None` if it did not change.
+pub(crate) async fn verify_repo_data(
+    client: &ClientWithMiddleware,
+    subdir_url: &Url,
+    trusted_root: Option<&TrustedRoot>,
+    repo_data_json_path: &Path,
+    cached: Option<&ContentTrustState>,
+    offline: bool,
+    policy: SignaturePolicy,
+) -> Result<Option<ContentTrustState>, ContentTrustError> {
+    if policy == SignaturePolicy::Disabled {
+        return Ok(None);
+    }
+
+    let result: Result<Option<ContentTrustState>, ContentTrustError> = async {
+        let trusted_root = trusted_root.ok_or(ContentTrustError::NoTrustedRoot)?;
+
+        let repo_data_json_path = repo_data_json_path.to_path_buf();
+        let (bytes, hash) = run_blocking_task(move || {
+            let bytes = std::fs::read(repo_data_json_path).map_err(ContentTrustError::IoError)?;
+            let hash = rattler_digest::compute_bytes_digest::<Blake2b256>(&bytes);
+            Ok((bytes, hash))
+        })
+        .await?;
+
+        // The cached root is only used if it is not older than the configured root.
+        let cached = cached.and_then(|cached| {
+            let root = TrustedRoot::from_json(&cached.root).ok()?;
+            (root.version() >= trusted_root.version()).then_some((cached, root))
+        });
+        if let Some((cached, _)) = &cached {
+            if cached.verified_blake2_hash == hash {
+                return Ok(None);
+            }
+        }
+
+        let (root, key_mgr) = if offline {
+            let (cached, root) = cached.ok_or(ContentTrustError::MetadataNotCached)?;
+            root.metadata.check_expiration()?;
+            let key_mgr = root.verify_key_mgr(&cached.key_mgr)?;
+            (root, key_mgr)
+        } else {
+            let channel_url = subdir_url
+                .join("..")
+                .expect("subdir url must have a parent");
+            let root = cached.map_or_else(|| trusted_root.clone(), |(_, root)| root);
+            let root = update_root(client, &channel_url, root).await?;
+
+            let key_mgr_json = client
+                .get(channel_url.join("key_mgr.json").expect("valid file name"))
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            let key_mgr = root.verify_key_mgr(&key_mgr_json)?;
+            (root, key_mgr)
+        };
+
+        let state = ContentTrustState {
+            root: root.json,
+            key_mgr: key_mgr.json.clone(),
+            verified_blake2_hash: hash,
+        };
+        run_blocking_task(move || {
+            let repo_data: Value =
+                serde_json::from_slice(&bytes).map_err(ContentTrustError::InvalidRepoData)?;
+            key_mgr.verify_repo_data(&repo_data)
+        })
+        .await?;
+        Ok(Some(state))
+    }
+    .await;
+
+    match result {
+        Err(err) if policy == SignaturePolicy::Warn => {
+            tracing::warn!(
+                "failed to verify the signatures of the repodata of {}: {err}",
+                subdir_url.display_redacted()
+            );
+            Ok(None)
+        }
+        result => result,
+    }
+}
+
+/// Updates the trusted root with the newer versions that are published by the channel. The
+/// resulting root must not be expired.
+async fn update_root(
+    client: &ClientWithMiddleware,
+    channel_url: &Url,
+    mut root: TrustedRoot,
+) -> Result<TrustedRoot, ContentTrustError> {
+    loop {
+        let url = channel_url
+            .join(&format!("{}.root.json", root.version() + 1))
+            .expect("valid file name");
+        let response = client.get(url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            break;
+        }
+        let json = response.error_for_status()?.text().await?;
+        root = root.update(&json)?;
+        tracing::debug!("updated the trusted root to version {}", root.version());
+    }
+
+    root.metadata.check_expiration()?;
+    Ok(root)
+}
+
+/// Returns an error if the message is not signed by at least the threshold of the delegated
+/// keys.
+fn verify_threshold(
+    role: &str,
+    message: &[u8],
+    signatures: &HashMap<String, SignatureEntry>,
+    delegation: &Delegation,
+) -> Result<(), ContentTrustError> {
+    let found = count_valid_signatures(message, signatures, delegation);
+    let threshold = delegation.threshold.max(1);
+    if found < threshold {
+        return Err(ContentTrustError::NotEnoughSignatures {
+            role: role.to_string(),
+            found,
+            threshold,
+        });
+    }
+    Ok(())
+}
+
+/// Returns the number of delegated keys that signed the message.
+fn count_valid_signatures(
+    message: &[u8],
+    signatures: &HashMap<String, SignatureEntry>,
+    delegation: &Delegation,
+) -> usize {
+    let mut keys = delegation
+        .pubkeys
+        .iter()
+        .map(|key| key.to_lowercase())
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    keys.iter()
+        .filter(|key| {
+            signatures.get(key.as_str()).is_some_and(|entry| {
+                entry.other_headers.is_none() && verify_signature(key, &entry.signature, message)
+            })
+        })
+        .count()
+}
+
+/// Verifies an ed25519 signature of the message. The key and signature are hex encoded.
+fn verify_signature(public_key: &str, signature: &str, message: &[u8]) -> bool {
+    let (Ok(public_key), Ok(signature)) = (hex::decode(public_key), hex::decode(signature)) else {
+        return false;
+    };
+    let Ok(public_key) = <[u8; 32]>::try_from(public_key.as_slice()) else {
+        return false;
+    };
+    let (Ok(public_key), Ok(signature)) = (
+        VerifyingKey::from_bytes(&public_key),
+        Signature::from_slice(&signature),
+    ) else {
+        return false;
+    };
+    public_key.verify(message, &signature).is_ok()
+}
+
+/// Serializes the value the way conda-content-trust does before it is signed: with sorted keys,
+/// an indentation of two spaces and all non-ASCII characters escaped.
+fn canonical_json(value: &Value) -> Vec<u8> {
+    let mut out = String::new();
+    write_canonical_json(value, 0, &mut out);
+    out.into_bytes()
+}
+
+fn write_canonical_json(value: &Value, depth: usize, out: &mut String) {
+    let newline = |depth: usize, out: &mut String| {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    };
+
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+        Value::Number(value) => out.push_str(&value.to_string()),
+        Value::String(value) => write_canonical_string(value, out),
+        Value::Array(items) if items.is_empty() => out.push_str("[]"),
+        Value::Array(items) => {
+            out.push('[');
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                newline(depth + 1, out);
+                write_canonical_json(item, depth + 1, out);
+            }
+            newline(depth, out);
+            out.push(']');
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+            out.push('{');
+            for (idx, (key, item)) in entries.into_iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                newline(depth + 1, out);
+                write_canonical_string(key, out);
+                out.push_str(": ");
+                write_canonical_json(item, depth + 1, out);
+            }
+            newline(depth, out);
+            out.push('}');
+        }
+    }
+}
+
+fn write_canonical_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            ' '..='~' => out.push(c),
+            c => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    write!(out, "\\u{unit:04x}").expect("writing to a string cannot fail");
+                }
+            }
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use ed25519_dalek::{Signer, SigningKey};
+    use reqwest::Client;
+    use reqwest_middleware::ClientWithMiddleware;
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+
+    use super::{canonical_json, ContentTrustError, SignaturePolicy, TrustedRoot};
+    use crate::{
+        fetch::{fetch_repo_data, CacheAction, FetchRepoDataError, FetchRepoDataOptions},
+        utils::simple_channel_server::SimpleChannelServer,
+    };
+
+    fn public_key(key: &SigningKey) -> String {
+        hex::encode(key.verifying_key().to_bytes())
+    }
+
+    fn sign(keys: &[&SigningKey], value: &Value) -> Value {
+        let message = canonical_json(value);
+        let signatures = keys
+            .iter()
+            .map(|key| {
+                (
+                    public_key(key),
+                    json!({ "signature": hex::encode(key.sign(&message).to_bytes()) }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>();
+        Value::Object(signatures)
+    }
+
+    fn metadata(role_type: &str, version: u64, delegations: &[(&str, &SigningKey)]) -> Value {
+        let delegations = delegations
+            .iter()
+            .map(|(role, key)| {
+                (
+                    role.to_string(),
+                    json!({ "pubkeys": [public_key(key)], "threshold": 1 }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>();
+        json!({
+            "delegations": delegations,
+            "expiration": "2100-01-01T00:00:00Z",
+            "metadata_spec_version": "0.6.0",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "type": role_type,
+            "version": version,
+        })
+    }
+
+    fn signed_metadata(keys: &[&SigningKey], signed: Value) -> String {
+        json!({ "signatures": sign(keys, &signed), "signed": signed }).to_string()
+    }
+
+    struct Keys {
+        root: SigningKey,
+        new_root: SigningKey,
+        key_mgr: SigningKey,
+        pkg_mgr: SigningKey,
+    }
+
+    impl Keys {
+        fn new() -> Self {
+            Self {
+                root: SigningKey::from_bytes(&[1; 32]),
+                new_root: SigningKey::from_bytes(&[2; 32]),
+                key_mgr: SigningKey::from_bytes(&[3; 32]),
+                pkg_mgr: SigningKey::from_bytes(&[4; 32]),
+            }
+        }
+
+        fn root(&self) -> TrustedRoot {
+            TrustedRoot::from_json(&signed_metadata(
+                &[&self.root],
+                metadata("root", 1, &[("root", &self.root), ("key_mgr", &self.root)]),
+            ))
+            .unwrap()
+        }
+
+        fn repo_data(&self) -> Value {
+            let foo = json!({ "name": "foo", "version": "1.0", "build": "0", "depends": [] });
+            let bar = json!({ "name": "bar", "version": "2.0", "build": "0", "depends": ["foo"] });
+            json!({
+                "info": { "subdir": "noarch" },
+                "packages": { "foo-1.0-0.tar.bz2": foo },
+                "packages.conda": { "bar-2.0-0.conda": bar },
+                "signatures": {
+                    "foo-1.0-0.tar.bz2": sign(&[&self.pkg_mgr], &foo),
+                    "bar-2.0-0.conda": sign(&[&self.pkg_mgr], &bar),
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        let value = json!({ "b": [1, { "é": "x\n" }], "a": {}, "c": [] });
+        assert_eq!(
+            String::from_utf8(canonical_json(&value)).unwrap(),
+            "{\n  \"a\": {},\n  \"b\": [\n    1,\n    {\n      \"\\u00e9\": \"x\\n\"\n    }\n  ],\n  \"c\": []\n}"
+        );
+    }
+
+    #[test]
+    fn test_update_root() {
+        let keys = Keys::new();
+        let root = keys.root();
+
+        // The new version must be signed by the old and the new root keys.
+        let v2 = metadata("root", 2, &[("root", &keys.new_root)]);
+        let updated = root
+            .update(&signed_metadata(&[&keys.root, &keys.new_root], v2.clone()))
+            .unwrap();
+        assert_eq!(updated.version(), 2);
+        assert_matches!(
+            root.update(&signed_metadata(&[&keys.new_root], v2)),
+            Err(ContentTrustError::NotEnoughSignatures { found: 0, .. })
+        );
+
+        // Versions cannot be skipped.
+        let v3 = metadata("root", 3, &[("root", &keys.root)]);
+        assert_matches!(
+            root.update(&signed_metadata(&[&keys.root], v3)),
+            Err(ContentTrustError::UnexpectedVersion {
+                expected: 2,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_repo_data() {
+        let keys = Keys::new();
+        let key_mgr = keys
+            .root()
+            .verify_key_mgr(&signed_metadata(
+                &[&keys.root],
+                metadata("key_mgr", 1, &[("pkg_mgr", &keys.pkg_mgr)]),
+            ))
+            .unwrap();
+
+        let mut repo_data = keys.repo_data();
+        key_mgr.verify_repo_data(&repo_data).unwrap();
+
+        // Modifying a record invalidates its signature.
+        repo_data["packages.conda"]["bar-2.0-0.conda"]["depends"] = json!([]);
+        assert_matches!(
+            key_mgr.verify_repo_data(&repo_data),
+            Err(ContentTrustError::InvalidPackageSignatures(packages)) if packages == ["bar-2.0-0.conda"]
+        );
+
+        // The key manager must be signed by the delegated keys.
+        assert_matches!(
+            keys.root().verify_key_mgr(&signed_metadata(
+                &[&keys.pkg_mgr],
+                metadata("key_mgr", 1, &[("pkg_mgr", &keys.pkg_mgr)]),
+            )),
+            Err(ContentTrustError::NotEnoughSignatures { .. })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_signed_repo_data() {
+        let keys = Keys::new();
+        let channel_dir = TempDir::new().unwrap();
+        std::fs::create_dir(channel_dir.path().join("noarch")).unwrap();
+        std::fs::write(
+            channel_dir.path().join("2.root.json"),
+            signed_metadata(
+                &[&keys.root, &keys.new_root],
+                metadata(
+                    "root",
+                    2,
+                    &[("root", &keys.new_root), ("key_mgr", &keys.key_mgr)],
+                ),
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            channel_dir.path().join("key_mgr.json"),
+            signed_metadata(
+                &[&keys.key_mgr],
+                metadata("key_mgr", 1, &[("pkg_mgr", &keys.pkg_mgr)]),
+            ),
+        )
+        .unwrap();
+        let server = SimpleChannelServer::new(channel_dir.path()).await;
+
+        let fetch = |policy| {
+            let cache_dir = TempDir::new().unwrap();
+            let server_url = server.url();
+            let root = keys.root();
+            async move {
+                fetch_repo_data(
+                    server_url.join("noarch/").unwrap(),
+                    ClientWithMiddleware::from(Client::new()),
+                    cache_dir.path().to_owned(),
+                    FetchRepoDataOptions {
+                        signature_policy: policy,
+                        trusted_root: Some(root),
+                        ..FetchRepoDataOptions::default()
+                    },
+                    None,
+                )
+                .await
+            }
+        };
+
+        let mut repo_data = keys.repo_data();
+        let repo_data_path = channel_dir.path().join("noarch/repodata.json");
+        std::fs::write(&repo_data_path, repo_data.to_string()).unwrap();
+        fetch(SignaturePolicy::Enforce).await.unwrap();
+
+        // A tampered record is rejected, unless the policy only warns.
+        repo_data["packages"]["foo-1.0-0.tar.bz2"]["version"] = json!("1.1");
+        std::fs::write(&repo_data_path, repo_data.to_string()).unwrap();
+        assert_matches!(
+            fetch(SignaturePolicy::Enforce).await,
+            Err(FetchRepoDataError::ContentTrust(
+                ContentTrustError::InvalidPackageSignatures(_)
+            ))
+        );
+        fetch(SignaturePolicy::Warn).await.unwrap();
+        fetch(SignaturePolicy::Disabled).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verified_repo_data_is_cached() {
+        let keys = Keys::new();
+        let channel_dir = TempDir::new().unwrap();
+        std::fs::create_dir(channel_dir.path().join("noarch")).unwrap();
+        let root_path = channel_dir.path().join("2.root.json");
+        std::fs::write(
+            &root_path,
+            signed_metadata(
+                &[&keys.root, &keys.new_root],
+                metadata(
+                    "root",
+                    2,
+                    &[("root", &keys.new_root), ("key_mgr", &keys.key_mgr)],
+                ),
+            ),
+        )
+        .unwrap();
+        let key_mgr_path = channel_dir.path().join("key_mgr.json");
+        std::fs::write(
+            &key_mgr_path,
+            signed_metadata(
+                &[&keys.key_mgr],
+                metadata("key_mgr", 1, &[("pkg_mgr", &keys.pkg_mgr)]),
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            channel_dir.path().join("noarch/repodata.json"),
+            keys.repo_data().to_string(),
+        )
+        .unwrap();
+        let server = SimpleChannelServer::new(channel_dir.path()).await;
+
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = |cache_action| {
+            fetch_repo_data(
+                server.url().join("noarch/").unwrap(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    cache_action,
+                    signature_policy: SignaturePolicy::Enforce,
+                    trusted_root: Some(keys.root()),
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        // The updated root is stored in the cache.
+        let result = fetch(CacheAction::CacheOrFetch).await.unwrap();
+        let content_trust = result.cache_state.content_trust.clone().unwrap();
+        assert_eq!(
+            TrustedRoot::from_json(&content_trust.root)
+                .unwrap()
+                .version(),
+            2
+        );
+        drop(result);
+
+        // Repodata that was verified before is not verified again, so the
+        // metadata is not fetched.
+        std::fs::remove_file(&root_path).unwrap();
+        std::fs::remove_file(&key_mgr_path).unwrap();
+        fetch(CacheAction::CacheOrFetch).await.unwrap();
+        fetch(CacheAction::ForceCacheOnly).await.unwrap();
+    }
+}
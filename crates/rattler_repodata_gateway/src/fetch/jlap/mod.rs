@@ -71,6 +71,7 @@
 //!         subdir_url,
 //!         repo_data_state,
 //!         &current_repo_data,
+//!         None,
 //!         None
 //!     ).await.unwrap();
 //!
@@ -81,10 +82,11 @@
 
 use blake2::digest::Output;
 use blake2::digest::{FixedOutput, Update};
+use json_patch::PatchOperation;
 use rattler_digest::{
     parse_digest_from_hex, serde::SerializableHash, Blake2b256, Blake2b256Hash, Blake2bMac256,
 };
-use rattler_redaction::Redact;
+use rattler_redaction::{DisplayRedacted, Redact};
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     Response, StatusCode,
@@ -93,6 +95,7 @@ use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_with::serde_as;
+use std::collections::BTreeMap;
 use std::io::Write;
 use std::iter::Iterator;
 use std::path::Path;
@@ -122,6 +125,9 @@ pub const JLAP_START_POSITION: u64 = 0;
 /// Default initialization vector for JLAP requests
 pub const JLAP_START_INITIALIZATION_VECTOR: &[u8] = &[0; 32];
 
+/// Number of consecutive patches that are merged into a single patch before they are applied
+const JLAP_PATCH_BATCH_SIZE: usize = 64;
+
 /// Represents the variety of errors that we come across while processing JLAP files
 #[derive(Debug, thiserror::Error)]
 pub enum JLAPError {
@@ -157,6 +163,11 @@ pub enum JLAPError {
     /// This should be seldom and might indicate an error on the server.
     ChecksumParse,
 
+    #[error("{0} JLAP patches have to be applied which exceeds the maximum of {1}")]
+    /// Error returned when more patches have to be applied than allowed. Applying a lot of
+    /// patches can be slower than downloading the complete `repodata.json` again.
+    TooManyPatches(usize, usize),
+
     #[error("The JLAP response was empty and we unable to parse it")]
     /// Error return if we cannot find anything inside the actual JLAP response.
     /// This indicates that we need to reset the values for JLAP in our cache.
@@ -309,10 +320,14 @@ impl<'a> JLAPResponse<'a> {
 
     /// Applies patches to a `repo_data_json_path` file provided using the `hash` value to
     /// find the correct ones to apply.
+    ///
+    /// If more than `max_patches` patches would have to be applied, no patches are applied and
+    /// [`JLAPError::TooManyPatches`] is returned instead.
     pub async fn apply(
         &self,
         repo_data_json_path: &Path,
         hash: Output<Blake2b256>,
+        max_patches: Option<usize>,
         reporter: Option<Arc<dyn Reporter>>,
     ) -> Result<Blake2b256Hash, JLAPError> {
         // We use the current hash to find which patches we need to apply
@@ -321,6 +336,11 @@ impl<'a> JLAPResponse<'a> {
             return Err(JLAPError::NoHashFound);
         };
 
+        let patch_count = self.patches.len() - idx;
+        if let Some(max_patches) = max_patches.filter(|&max_patches| patch_count > max_patches) {
+            return Err(JLAPError::TooManyPatches(patch_count, max_patches));
+        }
+
         // Apply the patches on a blocking thread. Applying the patches is a relatively CPU intense
         // operation and we don't want to block the tokio runtime.
         let repo_data_path = self.patches.clone();
@@ -408,12 +428,17 @@ fn get_bytes_offset(lines: &Vec<&str>) -> u64 {
 /// At the end, we compare the new `blake2b` hash with what was listed in the JLAP metadata to
 /// ensure the file is correct.
 ///
+/// If the local file is more than `max_patches` patches behind, the file is left untouched and
+/// [`JLAPError::TooManyPatches`] is returned, in which case the caller should download the
+/// complete `repodata.json` instead.
+///
 /// The return value is the updated [`JLAPState`] and the Blake2b256 hash of the new file.
 pub async fn patch_repo_data(
     client: &ClientWithMiddleware,
     subdir_url: Url,
     repo_data_state: RepoDataState,
     repo_data_json_path: &Path,
+    max_patches: Option<usize>,
     reporter: Option<Arc<dyn Reporter>>,
 ) -> Result<(JLAPState, Blake2b256Hash), JLAPError> {
     // Determine what we should use as our starting state
@@ -457,7 +482,9 @@ pub async fn patch_repo_data(
     }
 
     // Applies patches and returns early if an error is encountered
-    let hash = jlap.apply(repo_data_json_path, hash, reporter).await?;
+    let hash = jlap
+        .apply(repo_data_json_path, hash, max_patches, reporter)
+        .await?;
 
     // Patches were applied successfully, so we need to update the position
     Ok((jlap.get_state(jlap.new_position, new_iv), hash))
@@ -493,7 +520,10 @@ async fn fetch_jlap_with_retry(
     client: &ClientWithMiddleware,
     position: u64,
 ) -> Result<(Response, u64), JLAPError> {
-    tracing::info!("fetching JLAP state from {url} (bytes={position}-)");
+    tracing::info!(
+        "fetching JLAP state from {} (bytes={position}-)",
+        url.display_redacted()
+    );
     let range = format!("bytes={position}-");
 
     match fetch_jlap(url, client, &range).await {
@@ -519,7 +549,8 @@ async fn fetch_jlap_with_retry(
 /// This is a multi-step process that involves:
 ///
 /// 1. Opening and parsing the current repodata file
-/// 2. Applying patches to this repodata file
+/// 2. Applying patches to this repodata file, consecutive patches are merged and compacted with
+///    [`compact_patches`] before they are applied
 /// 3. Re-ordering the repo data
 /// 4. Saving this repodata file to disk
 fn apply_jlap_patches(
@@ -555,11 +586,15 @@ fn apply_jlap_patches(
         start_index + 1,
         patches.len()
     );
-    for (patch_index, patch) in patches[start_index..].iter().enumerate() {
+    for (batch_index, batch) in patches[start_index..]
+        .chunks(JLAP_PATCH_BATCH_SIZE)
+        .enumerate()
+    {
         if let Some((reporter, index)) = report {
-            reporter.on_jlap_apply_patch(index, patch_index, patches.len());
+            reporter.on_jlap_apply_patch(index, batch_index * JLAP_PATCH_BATCH_SIZE, patches.len());
         }
-        if let Err(error) = json_patch::patch_unsafe(&mut repo_data, &patch.patch) {
+        let patch = compact_patches(batch);
+        if let Err(error) = json_patch::patch_unsafe(&mut repo_data, &patch) {
             return Err(JLAPError::JSONPatch(error));
         }
     }
@@ -596,6 +631,89 @@ fn apply_jlap_patches(
     Ok(hash)
 }
 
+/// Merges consecutive patches into a single patch.
+///
+/// An operation that sets the member of an object is dropped if a later operation of the same
+/// kind sets the same member again and the value is not touched in between, because the later
+/// operation overwrites its effect anyway. This is common when a package is updated a couple of
+/// times while the local file is behind. All other operations are kept as is, so applying the
+/// merged patch fails in exactly the same cases as applying the patches one by one.
+fn compact_patches(patches: &[Patch]) -> json_patch::Patch {
+    let mut operations = patches
+        .iter()
+        .flat_map(|patch| patch.patch.0.iter().cloned().map(Some))
+        .collect::<Vec<_>>();
+
+    // The last operation that set the value of a path and whether it was an `add` operation, for
+    // all the paths whose value has not been touched since.
+    let mut last_set = BTreeMap::<String, (usize, bool)>::new();
+    for index in 0..operations.len() {
+        let (path, from, is_add) = match operations[index]
+            .as_ref()
+            .expect("only previous operations are dropped")
+        {
+            PatchOperation::Add(op) => (op.path.to_string(), None, Some(true)),
+            PatchOperation::Replace(op) => (op.path.to_string(), None, Some(false)),
+            PatchOperation::Remove(op) => (op.path.to_string(), None, None),
+            PatchOperation::Move(op) => (op.path.to_string(), Some(op.from.to_string()), None),
+            PatchOperation::Copy(op) => (op.path.to_string(), Some(op.from.to_string()), None),
+            PatchOperation::Test(op) => (op.path.to_string(), None, None),
+        };
+
+        if let Some(from) = from {
+            forget_related_paths(&mut last_set, &from);
+            last_set.remove(&from);
+        }
+        forget_related_paths(&mut last_set, &path);
+        let previous = last_set.remove(&path);
+
+        let Some(is_add) = is_add.filter(|_| is_object_member(&path)) else {
+            continue;
+        };
+
+        // An `add` overwrites a previous `add` and a `replace` overwrites a previous `replace`.
+        // Other combinations are kept because the operations fail in different cases.
+        if let Some((previous_index, previous_is_add)) = previous {
+            if previous_is_add == is_add {
+                operations[previous_index] = None;
+            }
+        }
+        last_set.insert(path, (index, is_add));
+    }
+
+    json_patch::Patch(operations.into_iter().flatten().collect())
+}
+
+/// Forgets the values that were set at the ancestors and the descendants of the path, because an
+/// operation on the path reads or modifies them.
+fn forget_related_paths(last_set: &mut BTreeMap<String, (usize, bool)>, path: &str) {
+    let mut ancestor = path;
+    while let Some(end) = ancestor.rfind('/') {
+        ancestor = &ancestor[..end];
+        last_set.remove(ancestor);
+    }
+
+    let prefix = format!("{path}/");
+    let descendants = last_set
+        .range(prefix.clone()..)
+        .take_while(|(descendant, _)| descendant.starts_with(&prefix))
+        .map(|(descendant, _)| descendant.clone())
+        .collect::<Vec<_>>();
+    for descendant in descendants {
+        last_set.remove(&descendant);
+    }
+}
+
+/// Returns true if the last token of the JSON pointer refers to the member of an object. Tokens
+/// that could refer to an array element are excluded because adding an element to an array
+/// inserts it instead of overwriting the existing element.
+fn is_object_member(path: &str) -> bool {
+    match path.rsplit_once('/') {
+        Some((_, token)) => token != "-" && !token.bytes().all(|b| b.is_ascii_digit()),
+        None => false,
+    }
+}
+
 /// Retrieves the correct values for `position` and `initialization_vector` from a `JLAPState` object
 ///
 /// If we cannot find the correct values, we provide defaults from this module.
@@ -625,12 +743,14 @@ fn blake2b_256_hash_with_key(data: &[u8], key: &[u8]) -> Output<Blake2bMac256> {
 
 #[cfg(test)]
 mod test {
-    use super::patch_repo_data;
+    use super::{compact_patches, patch_repo_data, JLAPError, Patch};
     use std::path::PathBuf;
+    use std::str::FromStr;
 
     use crate::fetch::cache::RepoDataState;
     use crate::utils::simple_channel_server::SimpleChannelServer;
 
+    use assert_matches::assert_matches;
     use rattler_digest::{parse_digest_from_hex, Blake2b256};
     use reqwest_middleware::ClientWithMiddleware;
     use rstest::rstest;
@@ -949,6 +1069,7 @@ mod test {
             test_env.repo_data_state,
             &test_env.cache_repo_data,
             None,
+            None,
         )
         .await
         .unwrap();
@@ -972,4 +1093,89 @@ mod test {
             parse_digest_from_hex::<Blake2b256>(expected_hash).unwrap()
         );
     }
+
+    #[tokio::test]
+    pub async fn test_patch_repo_data_with_too_many_patches() {
+        let test_env = TestEnvironment::new(
+            FAKE_REPO_DATA_INITIAL,
+            FAKE_JLAP_DATA_UPDATE_ONE,
+            FAKE_STATE_DATA_INITIAL,
+        )
+        .await;
+
+        let result = patch_repo_data(
+            &test_env.client,
+            test_env.server_url,
+            test_env.repo_data_state,
+            &test_env.cache_repo_data,
+            Some(1),
+            None,
+        )
+        .await;
+        assert_matches!(result, Err(JLAPError::TooManyPatches(2, 1)));
+
+        // The cached repodata is left untouched.
+        let repo_data = tokio::fs::read_to_string(test_env.cache_repo_data)
+            .await
+            .unwrap();
+        assert_eq!(repo_data, FAKE_REPO_DATA_INITIAL);
+    }
+
+    #[test]
+    pub fn test_compact_patches() {
+        let patches = [
+            r#"[
+                {"op": "add", "path": "/packages/a", "value": 1},
+                {"op": "add", "path": "/packages/b", "value": {}},
+                {"op": "replace", "path": "/info/version", "value": 1}
+            ]"#,
+            r#"[
+                {"op": "add", "path": "/packages/a", "value": 2},
+                {"op": "add", "path": "/packages/b/depends", "value": []},
+                {"op": "add", "path": "/packages/b", "value": {"name": "b"}},
+                {"op": "add", "path": "/removed/0", "value": "a"},
+                {"op": "add", "path": "/removed/0", "value": "b"}
+            ]"#,
+            r#"[
+                {"op": "add", "path": "/packages/a", "value": 3},
+                {"op": "replace", "path": "/info/version", "value": 2},
+                {"op": "remove", "path": "/packages/b"}
+            ]"#,
+        ]
+        .map(|patch| {
+            let hash = "0".repeat(64);
+            Patch::from_str(&format!(
+                r#"{{"from": "{hash}", "to": "{hash}", "patch": {patch}}}"#
+            ))
+            .unwrap()
+        });
+
+        let compacted = compact_patches(&patches);
+
+        // Overwritten members are dropped, members that are touched in between and array
+        // elements are kept.
+        assert_eq!(
+            serde_json::to_value(&compacted).unwrap(),
+            serde_json::json!([
+                {"op": "add", "path": "/packages/b", "value": {}},
+                {"op": "add", "path": "/packages/b/depends", "value": []},
+                {"op": "add", "path": "/packages/b", "value": {"name": "b"}},
+                {"op": "add", "path": "/removed/0", "value": "a"},
+                {"op": "add", "path": "/removed/0", "value": "b"},
+                {"op": "add", "path": "/packages/a", "value": 3},
+                {"op": "replace", "path": "/info/version", "value": 2},
+                {"op": "remove", "path": "/packages/b"}
+            ])
+        );
+
+        // Applying the compacted patch results in the same document.
+        let document = serde_json::json!({"info": {"version": 0}, "packages": {}, "removed": []});
+        let mut expected = document.clone();
+        for patch in &patches {
+            json_patch::patch_unsafe(&mut expected, &patch.patch).unwrap();
+        }
+        let mut actual = document;
+        json_patch::patch_unsafe(&mut actual, &compacted).unwrap();
+        assert_eq!(actual, expected);
+    }
 }
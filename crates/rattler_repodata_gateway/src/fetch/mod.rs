@@ -3,12 +3,14 @@
 use crate::reporter::ResponseReporterExt;
 use crate::utils::{AsyncEncoding, Encoding, LockedFile};
 use crate::Reporter;
-use cache::{CacheHeaders, Expiring, RepoDataState};
+use cache::Expiring;
+pub use cache::{CacheHeaders, ContentTrustState, RepoDataState};
 use cache_control::{Cachability, CacheControl};
+use content_trust::{ContentTrustError, SignaturePolicy, TrustedRoot};
 use futures::{future::ready, FutureExt, TryStreamExt};
 use humansize::{SizeFormatter, DECIMAL};
 use rattler_digest::{compute_file_digest, Blake2b256, HashingWriter};
-use rattler_redaction::Redact;
+use rattler_redaction::{DisplayRedacted, Redact};
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     Response, StatusCode,
@@ -17,7 +19,7 @@ use std::sync::Arc;
 use std::{
     io::ErrorKind,
     path::{Path, PathBuf},
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 use tempfile::NamedTempFile;
 use tokio_util::io::StreamReader;
@@ -25,6 +27,7 @@ use tracing::instrument;
 use url::Url;
 
 mod cache;
+pub mod content_trust;
 pub mod jlap;
 
 /// `RepoData` could not be found for given channel and platform
@@ -72,6 +75,9 @@ pub enum FetchRepoDataError {
     #[error("there is no cache available")]
     NoCacheAvailable,
 
+    #[error("failed to verify the signatures of the repodata")]
+    ContentTrust(#[from] ContentTrustError),
+
     #[error("the operation was cancelled")]
     Cancelled,
 }
@@ -123,6 +129,31 @@ pub enum CacheAction {
     NoCache,
 }
 
+/// Defines when cached repodata is considered fresh enough to be used without
+/// contacting the server. This is applied on top of the cache-control headers
+/// that were returned by the server when the data was fetched.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CacheRefreshPolicy {
+    /// Follow the cache-control headers of the server.
+    #[default]
+    FollowServer,
+
+    /// Use cached data without revalidating it, as long as it has not been
+    /// stale for longer than the given duration. This trades freshness for
+    /// fewer requests.
+    MaxStaleness(Duration),
+
+    /// Always revalidate cached data with the server, even if the server
+    /// indicated that it is still fresh. The cached data is only downloaded
+    /// again if the server reports that it has changed (e.g. based on its
+    /// `ETag`).
+    ForceRevalidate,
+
+    /// Treat cached data as immutable and never revalidate it. Data is only
+    /// downloaded if it is not cached yet.
+    Immutable,
+}
+
 /// Defines which type of repodata.json file to download. Usually you want to use the
 /// [`Variant::AfterPatches`] variant because that reflects the repodata with any patches applied.
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
@@ -172,11 +203,27 @@ pub struct FetchRepoDataOptions {
     /// When enabled repodata can be fetched incrementally using JLAP
     pub jlap_enabled: bool,
 
+    /// The maximum number of JLAP patches that are applied to the cached repodata. If the cache
+    /// is further behind, the complete repodata is downloaded instead. `None` means no limit.
+    pub jlap_max_patches: Option<usize>,
+
     /// When enabled, the zstd variant will be used if available
     pub zstd_enabled: bool,
 
     /// When enabled, the bz2 variant will be used if available
     pub bz2_enabled: bool,
+
+    /// Determines when the cache is revalidated with the server. See
+    /// [`CacheRefreshPolicy`] for more information.
+    pub refresh_policy: CacheRefreshPolicy,
+
+    /// Determines whether the signatures of the records in the repodata are verified. See
+    /// [`SignaturePolicy`] for more information. Repodata from local channels is never verified.
+    pub signature_policy: SignaturePolicy,
+
+    /// The root metadata that is trusted to verify the signatures of the repodata. This is
+    /// required unless the `signature_policy` is [`SignaturePolicy::Disabled`].
+    pub trusted_root: Option<TrustedRoot>,
 }
 
 impl Default for FetchRepoDataOptions {
@@ -185,8 +232,12 @@ impl Default for FetchRepoDataOptions {
             cache_action: CacheAction::default(),
             variant: Variant::default(),
             jlap_enabled: true,
+            jlap_max_patches: None,
             zstd_enabled: true,
             bz2_enabled: true,
+            refresh_policy: CacheRefreshPolicy::default(),
+            signature_policy: SignaturePolicy::default(),
+            trusted_root: None,
         }
     }
 }
@@ -205,6 +256,10 @@ pub struct CachedRepoData {
 
     /// How the cache was used for this request.
     pub cache_result: CacheResult,
+
+    /// Whether updating the cached repodata with JLAP patches succeeded, or
+    /// `None` if no JLAP patches were requested.
+    pub jlap_patched: Option<bool>,
 }
 
 /// Indicates whether or not the repodata.json cache was up-to-date or not.
@@ -260,6 +315,7 @@ async fn repodata_from_file(
         has_bz2: None,
         has_jlap: None,
         jlap: None,
+        content_trust: None,
     };
 
     // write the cache state
@@ -276,6 +332,7 @@ async fn repodata_from_file(
         repo_data_json_path: out_path.clone(),
         cache_state: new_cache_state,
         cache_result: CacheResult::CacheHit,
+        jlap_patched: None,
     })
 }
 
@@ -298,6 +355,13 @@ async fn repodata_from_file(
 ///
 /// The checks to see if a `.zst` and/or `.bz2` file exist are performed by doing a HEAD request to
 /// the respective URLs. The result of these are cached.
+///
+/// If a [`SignaturePolicy`] is configured the signatures of the records in the repodata are
+/// verified with the [`TrustedRoot`] after the repodata has been fetched. The verified metadata
+/// is stored in the cache, repodata that was verified before is not verified again.
+///
+/// When the [`CacheAction`] does not allow network requests the repodata is verified with the
+/// cached metadata.
 #[instrument(err, skip_all, fields(subdir_url, cache_path = % cache_path.display()))]
 pub async fn fetch_repo_data(
     subdir_url: Url,
@@ -307,13 +371,69 @@ pub async fn fetch_repo_data(
     reporter: Option<Arc<dyn Reporter>>,
 ) -> Result<CachedRepoData, FetchRepoDataError> {
     let subdir_url = normalize_subdir_url(subdir_url);
+    let signature_policy = options.signature_policy;
+    let trusted_root = options.trusted_root.clone();
+    let offline = matches!(
+        options.cache_action,
+        CacheAction::UseCacheOnly | CacheAction::ForceCacheOnly
+    );
+    let cache_state_path = cache_path.join(format!(
+        "{}.info.json",
+        cache_key(&subdir_url, options.variant)
+    ));
+
+    let mut result = fetch_unverified_repo_data(
+        subdir_url.clone(),
+        client.clone(),
+        cache_path,
+        options,
+        reporter,
+    )
+    .await?;
 
-    // Compute the cache key from the url
-    let cache_key = crate::utils::url_to_cache_filename(
+    if signature_policy != SignaturePolicy::Disabled && subdir_url.scheme() != "file" {
+        let content_trust = content_trust::verify_repo_data(
+            &client,
+            &subdir_url,
+            trusted_root.as_ref(),
+            &result.repo_data_json_path,
+            result.cache_state.content_trust.as_ref(),
+            offline,
+            signature_policy,
+        )
+        .await?;
+
+        // Store the verified metadata while we still hold the lock on the cache.
+        if let Some(content_trust) = content_trust {
+            result.cache_state.content_trust = Some(content_trust);
+            let cache_state = result.cache_state.clone();
+            tokio::task::spawn_blocking(move || cache_state.to_path(&cache_state_path))
+                .await?
+                .map_err(FetchRepoDataError::FailedToWriteCacheState)?;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Returns the key that identifies the files of the repodata of a subdir in the cache.
+fn cache_key(subdir_url: &Url, variant: Variant) -> String {
+    crate::utils::url_to_cache_filename(
         &subdir_url
-            .join(options.variant.file_name())
+            .join(variant.file_name())
             .expect("file name is valid"),
-    );
+    )
+}
+
+async fn fetch_unverified_repo_data(
+    subdir_url: Url,
+    client: reqwest_middleware::ClientWithMiddleware,
+    cache_path: PathBuf,
+    options: FetchRepoDataOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<CachedRepoData, FetchRepoDataError> {
+    // Compute the cache key from the url
+    let cache_key = cache_key(&subdir_url, options.variant);
     let repo_data_json_path = cache_path.join(format!("{cache_key}.json"));
     let cache_state_path = cache_path.join(format!("{cache_key}.info.json"));
 
@@ -344,8 +464,14 @@ pub async fn fetch_repo_data(
         let owned_subdir_url = subdir_url.clone();
         let owned_cache_path = cache_path.clone();
         let owned_cache_key = cache_key.clone();
+        let refresh_policy = options.refresh_policy;
         let cache_state = tokio::task::spawn_blocking(move || {
-            validate_cached_state(&owned_cache_path, &owned_subdir_url, &owned_cache_key)
+            validate_cached_state(
+                &owned_cache_path,
+                &owned_subdir_url,
+                &owned_cache_key,
+                refresh_policy,
+            )
         })
         .await?;
         match (cache_state, options.cache_action) {
@@ -358,6 +484,7 @@ pub async fn fetch_repo_data(
                     repo_data_json_path,
                     cache_state,
                     cache_result: CacheResult::CacheHit,
+                    jlap_patched: None,
                 });
             }
             (ValidatedCacheState::OutOfDate(_), CacheAction::UseCacheOnly)
@@ -405,6 +532,7 @@ pub async fn fetch_repo_data(
 
     // We first attempt to make a JLAP request; if it fails for any reason, we continue on with
     // a normal request.
+    let mut jlap_patched = None;
     let jlap_state = if has_jlap && cache_state.is_some() {
         let repo_data_state = cache_state.as_ref().unwrap();
         match jlap::patch_repo_data(
@@ -412,6 +540,7 @@ pub async fn fetch_repo_data(
             subdir_url.clone(),
             repo_data_state.clone(),
             &repo_data_json_path,
+            options.jlap_max_patches,
             reporter.clone(),
         )
         .await
@@ -441,10 +570,12 @@ pub async fn fetch_repo_data(
                     repo_data_json_path,
                     cache_state,
                     cache_result: CacheResult::CacheOutdated,
+                    jlap_patched: Some(true),
                 });
             }
             Err(error) => {
                 tracing::warn!("Error during JLAP request: {}", error);
+                jlap_patched = Some(false);
                 None
             }
         }
@@ -466,7 +597,7 @@ pub async fn fetch_repo_data(
     };
 
     // Construct the HTTP request
-    tracing::debug!("fetching '{}'", &repo_data_url);
+    tracing::debug!("fetching '{}'", repo_data_url.display_redacted());
     let request_builder = client.get(repo_data_url.clone());
 
     let mut headers = HeaderMap::default();
@@ -531,6 +662,7 @@ pub async fn fetch_repo_data(
             repo_data_json_path,
             cache_state,
             cache_result: CacheResult::CacheHitAfterFetch,
+            jlap_patched,
         });
     }
 
@@ -572,8 +704,10 @@ pub async fn fetch_repo_data(
     })
     .await??;
 
-    // Update the cache on disk.
+    // Update the cache on disk. The content trust metadata is kept so the root metadata does
+    // not have to be updated from the initial trusted root again.
     let had_cache = cache_state.is_some();
+    let content_trust = cache_state.and_then(|state| state.content_trust);
     let new_cache_state = RepoDataState {
         url: repo_data_url,
         cache_headers,
@@ -587,6 +721,7 @@ pub async fn fetch_repo_data(
         has_bz2: variant_availability.has_bz2,
         has_jlap: variant_availability.has_jlap,
         jlap: jlap_state,
+        content_trust,
     };
 
     let new_cache_state = tokio::task::spawn_blocking(move || {
@@ -606,6 +741,7 @@ pub async fn fetch_repo_data(
         } else {
             CacheResult::CacheNotPresent
         },
+        jlap_patched,
     })
 }
 
@@ -794,14 +930,15 @@ async fn check_valid_download_target(
     url: &Url,
     client: &reqwest_middleware::ClientWithMiddleware,
 ) -> bool {
-    tracing::debug!("checking availability of '{url}'");
+    let redacted_url = url.display_redacted();
+    tracing::debug!("checking availability of '{redacted_url}'");
 
     if url.scheme() == "file" {
         // If the url is a file url we can simply check if the file exists.
         let path = url.to_file_path().unwrap();
         let exists = tokio::fs::metadata(path).await.is_ok();
         tracing::debug!(
-            "'{url}' seems to be {}",
+            "'{redacted_url}' seems to be {}",
             if exists { "available" } else { "unavailable" }
         );
         exists
@@ -810,10 +947,10 @@ async fn check_valid_download_target(
         match client.head(url.clone()).send().await {
             Ok(response) => {
                 if response.status().is_success() {
-                    tracing::debug!("'{url}' seems to be available");
+                    tracing::debug!("'{redacted_url}' seems to be available");
                     true
                 } else {
-                    tracing::debug!("'{url}' seems to be unavailable");
+                    tracing::debug!("'{redacted_url}' seems to be unavailable");
                     false
                 }
             }
@@ -856,7 +993,8 @@ enum ValidatedCacheState {
 }
 
 /// Tries to determine if the cache state for the repodata.json for the given `subdir_url` is
-/// considered to be up-to-date.
+/// considered to be up-to-date. Whether the cache is up-to-date is determined by the cache-control
+/// headers of the server and the `refresh_policy`.
 ///
 /// This functions reads multiple files from the `cache_path`, it is left up to the user to ensure
 /// that these files stay synchronized during the execution of this function.
@@ -864,6 +1002,7 @@ fn validate_cached_state(
     cache_path: &Path,
     subdir_url: &Url,
     cache_key: &str,
+    refresh_policy: CacheRefreshPolicy,
 ) -> ValidatedCacheState {
     let repo_data_json_path = cache_path.join(format!("{cache_key}.json"));
     let cache_state_path = cache_path.join(format!("{cache_key}.info.json"));
@@ -965,8 +1104,8 @@ fn validate_cached_state(
         }
     };
 
-    // Parse the cache control header, and determine if the cache is out of date or not.
-    if let Some(cache_control) = cache_state.cache_headers.cache_control.as_deref() {
+    // Parse the cache control header, and determine how old the cache may be.
+    let max_age = if let Some(cache_control) = cache_state.cache_headers.cache_control.as_deref() {
         match CacheControl::from_value(cache_control) {
             None => {
                 tracing::warn!(
@@ -978,38 +1117,58 @@ fn validate_cached_state(
                 cachability: Some(Cachability::Public),
                 max_age: Some(duration),
                 ..
-            }) => {
-                if cache_age > duration {
-                    tracing::debug!(
-                        "Cache is {} old but can at most be {} old. Assuming out of date...",
-                        humantime::format_duration(cache_age),
-                        humantime::format_duration(duration),
-                    );
-                    return ValidatedCacheState::OutOfDate(cache_state);
-                }
-            }
+            }) => Some(duration),
             Some(_) => {
                 tracing::debug!(
                     "Unsupported cache-control value '{}'. Assuming out of date...",
                     cache_control
                 );
-                return ValidatedCacheState::OutOfDate(cache_state);
+                None
             }
         }
     } else {
         tracing::warn!(
             "previous cache state does not contain cache_control header. Assuming out of date..."
         );
-        return ValidatedCacheState::OutOfDate(cache_state);
-    }
+        None
+    };
+
+    // Determine if the cache is out of date or not.
+    let up_to_date = match max_age {
+        Some(duration) if cache_age > duration => {
+            tracing::debug!(
+                "Cache is {} old but can at most be {} old. Assuming out of date...",
+                humantime::format_duration(cache_age),
+                humantime::format_duration(duration),
+            );
+            false
+        }
+        Some(_) => true,
+        None => false,
+    };
 
-    // Well then! If we get here, it means the cache must be up to date!
-    ValidatedCacheState::UpToDate(cache_state)
+    let up_to_date = match refresh_policy {
+        CacheRefreshPolicy::FollowServer => up_to_date,
+        CacheRefreshPolicy::MaxStaleness(max_staleness) => {
+            up_to_date || cache_age.saturating_sub(max_age.unwrap_or_default()) <= max_staleness
+        }
+        CacheRefreshPolicy::ForceRevalidate => false,
+        CacheRefreshPolicy::Immutable => true,
+    };
+
+    if up_to_date {
+        // Well then! If we get here, it means the cache must be up to date!
+        ValidatedCacheState::UpToDate(cache_state)
+    } else {
+        ValidatedCacheState::OutOfDate(cache_state)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{fetch_repo_data, CacheResult, CachedRepoData, FetchRepoDataOptions};
+    use super::{
+        fetch_repo_data, CacheRefreshPolicy, CacheResult, CachedRepoData, FetchRepoDataOptions,
+    };
     use crate::fetch::{FetchRepoDataError, RepoDataNotFoundError};
     use crate::utils::simple_channel_server::SimpleChannelServer;
     use crate::utils::Encoding;
@@ -1022,6 +1181,7 @@ mod test {
     use std::path::Path;
     use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
+    use std::time::Duration;
     use tempfile::TempDir;
     use tokio::io::AsyncWriteExt;
     use url::Url;
@@ -1191,6 +1351,62 @@ mod test {
         assert_matches!(cache_result, CacheResult::CacheOutdated);
     }
 
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    pub async fn test_refresh_policy() {
+        // Create a directory with some repodata.
+        let subdir_path = TempDir::new().unwrap();
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+        let server = SimpleChannelServer::new(subdir_path.path()).await;
+
+        let cache_dir = TempDir::new().unwrap();
+        let fetch = |refresh_policy| {
+            fetch_repo_data(
+                server.url(),
+                ClientWithMiddleware::from(Client::new()),
+                cache_dir.path().to_owned(),
+                FetchRepoDataOptions {
+                    refresh_policy,
+                    ..FetchRepoDataOptions::default()
+                },
+                None,
+            )
+        };
+
+        // Fill the cache.
+        let CachedRepoData { cache_result, .. } =
+            fetch(CacheRefreshPolicy::FollowServer).await.unwrap();
+        assert_matches!(cache_result, CacheResult::CacheNotPresent);
+
+        // The cache is revalidated with the server even though it was just filled.
+        let CachedRepoData { cache_result, .. } =
+            fetch(CacheRefreshPolicy::ForceRevalidate).await.unwrap();
+        assert_matches!(
+            cache_result,
+            CacheResult::CacheHitAfterFetch | CacheResult::CacheOutdated
+        );
+
+        // See `test_cache_works` for why this is needed.
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        std::fs::write(subdir_path.path().join("repodata.json"), FAKE_REPO_DATA).unwrap();
+
+        // An immutable cache is never revalidated.
+        let CachedRepoData { cache_result, .. } =
+            fetch(CacheRefreshPolicy::Immutable).await.unwrap();
+        assert_matches!(cache_result, CacheResult::CacheHit);
+
+        // The cache is just created, so it is not stale for longer than an hour.
+        let CachedRepoData { cache_result, .. } =
+            fetch(CacheRefreshPolicy::MaxStaleness(Duration::from_secs(3600)))
+                .await
+                .unwrap();
+        assert_matches!(cache_result, CacheResult::CacheHit);
+
+        let CachedRepoData { cache_result, .. } =
+            fetch(CacheRefreshPolicy::ForceRevalidate).await.unwrap();
+        assert_matches!(cache_result, CacheResult::CacheOutdated);
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     pub async fn test_zst_works() {
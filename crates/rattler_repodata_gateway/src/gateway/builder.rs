@@ -1,11 +1,27 @@
-use crate::gateway::GatewayInner;
+use crate::gateway::{GatewayInner, GatewayMetrics};
+use crate::transport::{Transport, TransportMiddleware};
 use crate::{ChannelConfig, Gateway};
 use dashmap::DashMap;
 use rattler_cache::package_cache::PackageCache;
+use rattler_conda_types::Channel;
+use rattler_networking::{
+    Authentication, AuthenticationMiddleware, AuthenticationStorage, AzureMiddleware,
+    CircuitBreakerMiddleware,
+};
 use reqwest::Client;
-use reqwest_middleware::ClientWithMiddleware;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use std::path::PathBuf;
 use std::sync::Arc;
+use url::Url;
+
+/// An error that can occur when constructing a [`Gateway`] with
+/// [`GatewayBuilder::try_finish`].
+#[derive(Debug, thiserror::Error)]
+pub enum GatewayBuilderError {
+    /// Failed to construct the default HTTP client.
+    #[error("failed to construct the HTTP client")]
+    Client(#[source] reqwest::Error),
+}
 
 /// A builder for constructing a [`Gateway`].
 #[derive(Default)]
@@ -15,6 +31,14 @@ pub struct GatewayBuilder {
     cache: Option<PathBuf>,
     package_cache: Option<PackageCache>,
     max_concurrent_requests: Option<usize>,
+    transport: Option<Arc<dyn Transport>>,
+    authentication_storage: Option<AuthenticationStorage>,
+    credentials: Vec<(Url, Authentication)>,
+    metrics: Option<Arc<dyn GatewayMetrics>>,
+    circuit_breaker: Option<CircuitBreakerMiddleware>,
+    include_noarch: bool,
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    tls_config: Option<crate::gateway::TlsConfig>,
 }
 
 impl GatewayBuilder {
@@ -36,6 +60,87 @@ impl GatewayBuilder {
         self
     }
 
+    /// Set a custom transport that is used to execute HTTP requests instead
+    /// of the [`reqwest::Client`] of the client. Any middleware of the client
+    /// is still applied before the request is handed to the transport.
+    #[must_use]
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.set_transport(transport);
+        self
+    }
+
+    /// Set a custom transport that is used to execute HTTP requests instead
+    /// of the [`reqwest::Client`] of the client. Any middleware of the client
+    /// is still applied before the request is handed to the transport.
+    pub fn set_transport<T: Transport + 'static>(&mut self, transport: T) -> &mut Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Set the TLS configuration of the client that is constructed when no
+    /// client was specified with [`Self::with_client`], e.g. to trust the
+    /// certificate authority of a proxy or to present a client certificate.
+    /// The configuration is ignored if a client is specified.
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    #[must_use]
+    pub fn with_tls_config(mut self, tls_config: crate::gateway::TlsConfig) -> Self {
+        self.set_tls_config(tls_config);
+        self
+    }
+
+    /// Set the TLS configuration of the client that is constructed when no
+    /// client was specified with [`Self::with_client`].
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    pub fn set_tls_config(&mut self, tls_config: crate::gateway::TlsConfig) -> &mut Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
+
+    /// Set the storage from which credentials are read to authenticate
+    /// requests. If no storage is set, the storage is constructed from the
+    /// environment with [`AuthenticationStorage::from_env`].
+    #[must_use]
+    pub fn with_authentication_storage(mut self, storage: AuthenticationStorage) -> Self {
+        self.set_authentication_storage(storage);
+        self
+    }
+
+    /// Set the storage from which credentials are read to authenticate
+    /// requests. If no storage is set, the storage is constructed from the
+    /// environment with [`AuthenticationStorage::from_env`].
+    pub fn set_authentication_storage(&mut self, storage: AuthenticationStorage) -> &mut Self {
+        self.authentication_storage = Some(storage);
+        self
+    }
+
+    /// Add credentials that are used to authenticate requests to the given
+    /// channel, i.e. to all urls that start with the base url of the channel.
+    /// Other channels on the same host are not affected. If the base urls of
+    /// multiple channels match a request, e.g. because one channel is nested
+    /// in another, the credentials of the longest base url are used. They take
+    /// precedence over the credentials of the authentication storage.
+    #[must_use]
+    pub fn with_channel_credentials(
+        mut self,
+        channel: &Channel,
+        authentication: Authentication,
+    ) -> Self {
+        self.add_channel_credentials(channel, authentication);
+        self
+    }
+
+    /// Add credentials that are used to authenticate requests to the given
+    /// channel. See [`Self::with_channel_credentials`].
+    pub fn add_channel_credentials(
+        &mut self,
+        channel: &Channel,
+        authentication: Authentication,
+    ) -> &mut Self {
+        self.credentials
+            .push((channel.base_url.clone(), authentication));
+        self
+    }
+
     /// Set the channel configuration to use for fetching repodata.
     #[must_use]
     pub fn with_channel_config(mut self, channel_config: ChannelConfig) -> Self {
@@ -87,11 +192,133 @@ impl GatewayBuilder {
         self
     }
 
+    /// Set the metrics that are notified about the repodata that is fetched
+    /// by the gateway, e.g. to monitor cache hits and downloaded bytes.
+    #[must_use]
+    pub fn with_metrics<M: GatewayMetrics + 'static>(mut self, metrics: M) -> Self {
+        self.set_metrics(metrics);
+        self
+    }
+
+    /// Set the metrics that are notified about the repodata that is fetched
+    /// by the gateway, e.g. to monitor cache hits and downloaded bytes.
+    pub fn set_metrics<M: GatewayMetrics + 'static>(&mut self, metrics: M) -> &mut Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// Set a circuit breaker that stops sending requests to hosts that keep
+    /// failing. Use the same instance for the client that downloads packages
+    /// so that a failing host is avoided by both.
+    #[must_use]
+    pub fn with_circuit_breaker(mut self, circuit_breaker: CircuitBreakerMiddleware) -> Self {
+        self.set_circuit_breaker(circuit_breaker);
+        self
+    }
+
+    /// Set a circuit breaker that stops sending requests to hosts that keep
+    /// failing.
+    pub fn set_circuit_breaker(&mut self, circuit_breaker: CircuitBreakerMiddleware) -> &mut Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    /// Sets whether queries also fetch the records of the `noarch` platform
+    /// when it is not part of the platforms of the query. Most environments
+    /// require `noarch` packages, so without it a query for e.g.
+    /// `linux-64` only returns part of the available records. This can still
+    /// be overridden per query with [`crate::GatewayQuery::include_noarch`].
+    ///
+    /// Defaults to `false`.
+    #[must_use]
+    pub fn with_include_noarch(mut self, include_noarch: bool) -> Self {
+        self.set_include_noarch(include_noarch);
+        self
+    }
+
+    /// Sets whether queries also fetch the records of the `noarch` platform
+    /// when it is not part of the platforms of the query.
+    pub fn set_include_noarch(&mut self, include_noarch: bool) -> &mut Self {
+        self.include_noarch = include_noarch;
+        self
+    }
+
     /// Finish the construction of the gateway returning a constructed gateway.
+    ///
+    /// Requests are authenticated with the credentials of the authentication
+    /// storage, unless a client was specified with [`Self::with_client`]
+    /// without configuring any credentials. In that case the client is
+    /// expected to take care of authentication itself. The default client
+    /// also supports channels on Azure Blob storage through
+    /// [`AzureMiddleware`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the default client cannot be constructed, e.g. because the
+    /// TLS configuration cannot be applied. Use [`Self::try_finish`] to handle
+    /// this case.
     pub fn finish(self) -> Gateway {
-        let client = self
-            .client
-            .unwrap_or_else(|| ClientWithMiddleware::from(Client::new()));
+        self.try_finish()
+            .expect("failed to construct the client of the gateway")
+    }
+
+    /// Finish the construction of the gateway, returning an error if the
+    /// default client cannot be constructed.
+    ///
+    /// See [`Self::finish`] for how requests are authenticated.
+    pub fn try_finish(self) -> Result<Gateway, GatewayBuilderError> {
+        let authenticate = self.client.is_none()
+            || self.authentication_storage.is_some()
+            || !self.credentials.is_empty();
+        let client = match self.client {
+            Some(client) => client,
+            None => {
+                let builder = Client::builder();
+                #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+                let builder = match self.tls_config {
+                    Some(tls_config) => tls_config.apply(builder),
+                    None => builder,
+                };
+                // Rewrites and authenticates `az://` urls before the other
+                // middleware sees them.
+                ClientBuilder::new(builder.build().map_err(GatewayBuilderError::Client)?)
+                    .with(AzureMiddleware::from_env())
+                    .build()
+            }
+        };
+
+        let client = if authenticate {
+            let storage = self.authentication_storage.unwrap_or_else(|| {
+                AuthenticationStorage::from_env().unwrap_or_else(|err| {
+                    tracing::warn!("failed to read the authentication storage: {err}");
+                    AuthenticationStorage::default()
+                })
+            });
+            let middleware = self.credentials.into_iter().fold(
+                AuthenticationMiddleware::new(storage),
+                |middleware, (base_url, authentication)| {
+                    middleware.with_url_credentials(base_url, authentication)
+                },
+            );
+            ClientBuilder::from_client(client).with(middleware).build()
+        } else {
+            client
+        };
+
+        let client = match self.circuit_breaker {
+            Some(circuit_breaker) => ClientBuilder::from_client(client)
+                .with(circuit_breaker)
+                .build(),
+            None => client,
+        };
+
+        // Route all requests through the custom transport if one was specified.
+        let client = match self.transport {
+            Some(transport) => ClientBuilder::from_client(client)
+                .with(TransportMiddleware::from_arc(transport))
+                .build(),
+            None => client,
+        };
 
         let cache = self.cache.unwrap_or_else(|| {
             dirs::cache_dir()
@@ -104,7 +331,7 @@ impl GatewayBuilder {
         ));
 
         let max_concurrent_requests = self.max_concurrent_requests.unwrap_or(100);
-        Gateway {
+        Ok(Gateway {
             inner: Arc::new(GatewayInner {
                 subdirs: DashMap::default(),
                 client,
@@ -114,7 +341,92 @@ impl GatewayBuilder {
                 concurrent_requests_semaphore: Arc::new(tokio::sync::Semaphore::new(
                     max_concurrent_requests,
                 )),
+                metrics: self.metrics,
+                include_noarch: self.include_noarch,
             }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bytes::Bytes;
+    use rattler_conda_types::{Channel, ChannelConfig};
+    use rattler_networking::{Authentication, AuthenticationStorage};
+
+    use super::GatewayBuilder;
+    use crate::transport::Transport;
+
+    /// A transport that responds to every request with its `Authorization`
+    /// header.
+    struct AuthorizationEchoTransport;
+
+    #[async_trait::async_trait]
+    impl Transport for AuthorizationEchoTransport {
+        async fn execute(
+            &self,
+            request: http::Request<reqwest::Body>,
+        ) -> anyhow::Result<http::Response<reqwest::Body>> {
+            let authorization = request
+                .headers()
+                .get(http::header::AUTHORIZATION)
+                .map(|value| value.to_str().unwrap().to_string())
+                .unwrap_or_default();
+            Ok(http::Response::builder()
+                .status(200)
+                .body(Bytes::from(authorization).into())?)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_channel_credentials() {
+        let channel_config = ChannelConfig::default_with_root_dir(std::env::current_dir().unwrap());
+        let channel = |url: &str| Channel::from_str(url, &channel_config).unwrap();
+        let gateway = GatewayBuilder::new()
+            .with_authentication_storage(AuthenticationStorage::new())
+            .with_channel_credentials(
+                &channel("https://repo.example.com/private"),
+                Authentication::BearerToken(String::from("secret")),
+            )
+            .with_channel_credentials(
+                &channel("https://repo.example.com/private/nested"),
+                Authentication::BearerToken(String::from("nested-secret")),
+            )
+            .with_channel_credentials(
+                &channel("https://repo.example.com/other"),
+                Authentication::BearerToken(String::from("other-secret")),
+            )
+            .with_transport(AuthorizationEchoTransport)
+            .finish();
+
+        // Credentials are selected by the longest matching base url of a
+        // channel, channels without credentials on the same host are not
+        // authenticated.
+        for (channel_url, expected) in [
+            ("https://repo.example.com/private", "Bearer secret"),
+            (
+                "https://repo.example.com/private/nested",
+                "Bearer nested-secret",
+            ),
+            ("https://repo.example.com/other", "Bearer other-secret"),
+            ("https://repo.example.com/private-public", ""),
+            ("https://repo.example.com/public", ""),
+        ] {
+            let response = gateway
+                .inner
+                .client
+                .get(
+                    channel(channel_url)
+                        .base_url
+                        .join("noarch/repodata.json")
+                        .unwrap(),
+                )
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.text().await.unwrap(), expected, "{channel_url}");
         }
     }
 }
@@ -1,6 +1,11 @@
-use crate::fetch::CacheAction;
+use crate::fetch::{
+    content_trust::{SignaturePolicy, TrustedRoot},
+    CacheAction, CacheRefreshPolicy,
+};
 use rattler_conda_types::Channel;
-use std::collections::HashMap;
+use reqwest::header::{HeaderMap, HeaderValue};
+use std::{collections::HashMap, time::Duration};
+use url::Url;
 
 /// Describes additional properties that influence how the gateway fetches repodata for a specific
 /// channel.
@@ -9,6 +14,10 @@ pub struct SourceConfig {
     /// When enabled repodata can be fetched incrementally using JLAP (defaults to true)
     pub jlap_enabled: bool,
 
+    /// The maximum number of JLAP patches that are applied to cached repodata. If the cache is
+    /// further behind, the complete repodata is downloaded instead (defaults to no limit)
+    pub jlap_max_patches: Option<usize>,
+
     /// When enabled, the zstd variant will be used if available (defaults to true)
     pub zstd_enabled: bool,
 
@@ -18,15 +27,78 @@ pub struct SourceConfig {
     /// Describes fetching repodata from a channel should interact with any
     /// caches.
     pub cache_action: CacheAction,
+
+    /// Determines when cached repodata and shard indices are revalidated with
+    /// the server (defaults to following the cache-control headers of the
+    /// server).
+    pub refresh_policy: CacheRefreshPolicy,
+
+    /// When enabled, parsed records are stored in a second level on-disk cache
+    /// so they don't have to be parsed from the `repodata.json` again after a
+    /// restart (defaults to false)
+    pub parsed_records_cache_enabled: bool,
+
+    /// When enabled, the `patch_instructions.json` of a subdirectory is
+    /// fetched and applied on top of the repodata. This is how channels like
+    /// anaconda.org hotfix the metadata of published packages. The
+    /// instructions are also applied to sharded repodata. If the server fails
+    /// to provide the instructions, the cached instructions or the unpatched
+    /// repodata are used instead (defaults to false)
+    pub patch_instructions_enabled: bool,
+
+    /// The base urls of channels whose records are merged on top of the
+    /// records of this channel, e.g. a small live channel with recent
+    /// packages on top of an air-gapped snapshot. If an overlay contains a
+    /// package with the same filename as the channel, the record of the
+    /// overlay is used. Later overlays take precedence over earlier ones.
+    /// Subdirectories that are missing from an overlay are ignored (defaults
+    /// to no overlays)
+    pub overlays: Vec<Url>,
+
+    /// The interval at which [`super::Gateway::watch`] revalidates the
+    /// repodata (defaults to 5 minutes)
+    pub refresh_interval: Duration,
+
+    /// Determines whether the signatures of the records in the repodata are
+    /// verified (defaults to [`SignaturePolicy::Disabled`]). Only the
+    /// `repodata.json` of remote channels is verified, local channels are
+    /// not. Sharded repodata cannot be verified, it results in an error if
+    /// the policy is [`SignaturePolicy::Enforce`].
+    pub signature_policy: SignaturePolicy,
+
+    /// The root metadata that is trusted to verify the signatures of the
+    /// repodata. Required unless the signature policy is disabled (defaults
+    /// to none)
+    pub trusted_root: Option<TrustedRoot>,
+
+    /// Additional headers that are added to every request for the repodata,
+    /// shards and JLAP patches of the channel, e.g. tokens for a private CDN.
+    /// The headers replace headers with the same name that are set by the
+    /// client (defaults to no headers)
+    pub headers: HeaderMap,
+
+    /// The user-agent that is used for the requests of the channel instead
+    /// of the user-agent of the client (defaults to none)
+    pub user_agent: Option<HeaderValue>,
 }
 
 impl Default for SourceConfig {
     fn default() -> Self {
         Self {
             jlap_enabled: true,
+            jlap_max_patches: None,
             zstd_enabled: true,
             bz2_enabled: true,
             cache_action: CacheAction::default(),
+            refresh_policy: CacheRefreshPolicy::default(),
+            parsed_records_cache_enabled: false,
+            patch_instructions_enabled: false,
+            overlays: Vec::new(),
+            refresh_interval: Duration::from_secs(5 * 60),
+            signature_policy: SignaturePolicy::default(),
+            trusted_root: None,
+            headers: HeaderMap::new(),
+            user_agent: None,
         }
     }
 }
@@ -45,7 +117,16 @@ pub struct ChannelConfig {
 impl ChannelConfig {
     /// Returns the source configuration for the given channel. If the channel does not have a
     /// specific source configuration the default source configuration will be returned.
+    ///
+    /// Channels are matched by their normalized url, see [`Channel::channel_url`].
     pub fn get(&self, channel: &Channel) -> &SourceConfig {
-        self.per_channel.get(channel).unwrap_or(&self.default)
+        if let Some(config) = self.per_channel.get(channel) {
+            return config;
+        }
+        let channel_url = channel.channel_url();
+        self.per_channel
+            .iter()
+            .find(|(candidate, _)| candidate.channel_url() == channel_url)
+            .map_or(&self.default, |(_, config)| config)
     }
 }
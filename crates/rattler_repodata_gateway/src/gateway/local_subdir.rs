@@ -13,6 +13,7 @@ use std::sync::Arc;
 /// Use the [`LocalSubdirClient::from_directory`] function to create a new instance of this client.
 pub struct LocalSubdirClient {
     sparse: Arc<SparseRepoData>,
+    revision: Option<String>,
 }
 
 impl LocalSubdirClient {
@@ -21,6 +22,20 @@ impl LocalSubdirClient {
         channel: Channel,
         subdir: &str,
     ) -> Result<Self, GatewayError> {
+        // The file is replaced when it changes, its modification time and
+        // size identify the content.
+        let revision = tokio::fs::metadata(repodata_path)
+            .await
+            .ok()
+            .and_then(|metadata| {
+                let modified = metadata
+                    .modified()
+                    .ok()?
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .ok()?;
+                Some(format!("{}-{}", modified.as_nanos(), metadata.len()))
+            });
+
         let repodata_path = repodata_path.to_path_buf();
         let subdir = subdir.to_string();
         let sparse = run_blocking_task(move || {
@@ -42,6 +57,7 @@ impl LocalSubdirClient {
 
         Ok(Self {
             sparse: Arc::new(sparse),
+            revision,
         })
     }
 }
@@ -64,4 +80,15 @@ impl SubdirClient for LocalSubdirClient {
         })
         .await
     }
+
+    fn package_names(&self) -> Vec<PackageName> {
+        self.sparse
+            .package_names()
+            .filter_map(|name| PackageName::try_from(name).ok())
+            .collect()
+    }
+
+    fn revision(&self) -> Option<String> {
+        self.revision.clone()
+    }
 }
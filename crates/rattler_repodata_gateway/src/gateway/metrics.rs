@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use url::Url;
+
+use crate::{fetch::CacheResult, Reporter};
+
+/// A trait that enables collecting metrics about the repodata that is fetched
+/// by a [`super::Gateway`].
+///
+/// Services that embed the gateway can implement this trait to forward the
+/// events to their metrics system, e.g. as counters for cache hits and misses
+/// and as a histogram of the downloaded bytes. Metrics are registered with
+/// [`super::GatewayBuilder::with_metrics`].
+///
+/// All methods have a default implementation that does nothing. The methods
+/// are called from the tasks that fetch the repodata and should therefore
+/// return quickly.
+pub trait GatewayMetrics: Send + Sync {
+    /// Called when the repodata or the shard index of a remote subdirectory
+    /// has been fetched. The `cache_result` describes how the cache was used.
+    fn on_subdir_fetched(&self, _url: &Url, _cache_result: CacheResult) {}
+
+    /// Called when the records of a single shard of a sharded subdirectory
+    /// have been fetched. `cache_hit` is `true` if the shard was read from the
+    /// cache instead of being downloaded.
+    fn on_shard_fetched(&self, _url: &Url, _cache_hit: bool) {}
+
+    /// Called after an attempt to update the cached repodata of a subdirectory
+    /// with JLAP patches. If the attempt failed the complete repodata is
+    /// downloaded instead.
+    fn on_jlap_patch(&self, _url: &Url, _success: bool) {}
+
+    /// Called when a download finished with the number of bytes that were
+    /// transferred.
+    fn on_bytes_downloaded(&self, _url: &Url, _bytes: usize) {}
+}
+
+/// A download that is in progress.
+struct Download {
+    /// The index of the download in the wrapped reporter.
+    reporter_index: Option<usize>,
+
+    /// The number of bytes downloaded so far.
+    bytes: usize,
+}
+
+/// A [`Reporter`] that counts the downloaded bytes for the [`GatewayMetrics`]
+/// and forwards all events to the reporter of the query, if any.
+pub(crate) struct MetricsReporter {
+    metrics: Arc<dyn GatewayMetrics>,
+    reporter: Option<Arc<dyn Reporter>>,
+    next_index: AtomicUsize,
+    downloads: Mutex<HashMap<usize, Download>>,
+}
+
+impl MetricsReporter {
+    pub fn new(metrics: Arc<dyn GatewayMetrics>, reporter: Option<Arc<dyn Reporter>>) -> Self {
+        Self {
+            metrics,
+            reporter,
+            next_index: AtomicUsize::new(0),
+            downloads: Mutex::default(),
+        }
+    }
+}
+
+impl Reporter for MetricsReporter {
+    fn on_download_start(&self, url: &Url) -> usize {
+        let reporter_index = self
+            .reporter
+            .as_ref()
+            .map(|reporter| reporter.on_download_start(url));
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        self.downloads.lock().unwrap().insert(
+            index,
+            Download {
+                reporter_index,
+                bytes: 0,
+            },
+        );
+        index
+    }
+
+    fn on_download_progress(
+        &self,
+        url: &Url,
+        index: usize,
+        bytes_downloaded: usize,
+        total_bytes: Option<usize>,
+    ) {
+        let reporter_index = {
+            let mut downloads = self.downloads.lock().unwrap();
+            let Some(download) = downloads.get_mut(&index) else {
+                return;
+            };
+            download.bytes = bytes_downloaded;
+            download.reporter_index
+        };
+        if let (Some(reporter), Some(reporter_index)) = (&self.reporter, reporter_index) {
+            reporter.on_download_progress(url, reporter_index, bytes_downloaded, total_bytes);
+        }
+    }
+
+    fn on_download_complete(&self, url: &Url, index: usize) {
+        let Some(download) = self.downloads.lock().unwrap().remove(&index) else {
+            return;
+        };
+        self.metrics.on_bytes_downloaded(url, download.bytes);
+        if let (Some(reporter), Some(reporter_index)) = (&self.reporter, download.reporter_index) {
+            reporter.on_download_complete(url, reporter_index);
+        }
+    }
+
+    fn on_jlap_start(&self) -> usize {
+        self.reporter
+            .as_ref()
+            .map_or(0, |reporter| reporter.on_jlap_start())
+    }
+
+    fn on_jlap_decode_start(&self, index: usize) {
+        if let Some(reporter) = &self.reporter {
+            reporter.on_jlap_decode_start(index);
+        }
+    }
+
+    fn on_jlap_decode_completed(&self, index: usize) {
+        if let Some(reporter) = &self.reporter {
+            reporter.on_jlap_decode_completed(index);
+        }
+    }
+
+    fn on_jlap_apply_patch(&self, index: usize, patch_index: usize, total: usize) {
+        if let Some(reporter) = &self.reporter {
+            reporter.on_jlap_apply_patch(index, patch_index, total);
+        }
+    }
+
+    fn on_jlap_apply_patches_completed(&self, index: usize) {
+        if let Some(reporter) = &self.reporter {
+            reporter.on_jlap_apply_patches_completed(index);
+        }
+    }
+
+    fn on_jlap_encode_start(&self, index: usize) {
+        if let Some(reporter) = &self.reporter {
+            reporter.on_jlap_encode_start(index);
+        }
+    }
+
+    fn on_jlap_encode_completed(&self, index: usize) {
+        if let Some(reporter) = &self.reporter {
+            reporter.on_jlap_encode_completed(index);
+        }
+    }
+
+    fn on_jlap_completed(&self, index: usize) {
+        if let Some(reporter) = &self.reporter {
+            reporter.on_jlap_completed(index);
+        }
+    }
+}
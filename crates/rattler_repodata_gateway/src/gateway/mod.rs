@@ -4,11 +4,19 @@ mod channel_config;
 mod direct_url_query;
 mod error;
 mod local_subdir;
+mod metrics;
+mod overlay_subdir;
+mod patch_instructions;
 mod query;
+mod records_cache;
 mod remote_subdir;
 mod repo_data;
+mod request_headers;
 mod sharded_subdir;
 mod subdir;
+#[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+mod tls;
+mod watch;
 
 use std::{
     collections::HashSet,
@@ -17,22 +25,38 @@ use std::{
 };
 
 pub use barrier_cell::BarrierCell;
-pub use builder::GatewayBuilder;
+pub use builder::{GatewayBuilder, GatewayBuilderError};
 pub use channel_config::{ChannelConfig, SourceConfig};
 use dashmap::{mapref::entry::Entry, DashMap};
 pub use error::GatewayError;
 use file_url::url_to_path;
+use futures::Stream;
 use local_subdir::LocalSubdirClient;
-pub use query::GatewayQuery;
+pub use metrics::GatewayMetrics;
+use metrics::MetricsReporter;
+use overlay_subdir::OverlaySubdirClient;
+use patch_instructions::{PatchedSubdirClient, SubdirPatchInstructions};
+pub use query::{GatewayQuery, RecordFilter};
 use rattler_cache::package_cache::PackageCache;
-use rattler_conda_types::{Channel, MatchSpec, Platform};
+use rattler_conda_types::{Channel, ChannelUrl, MatchSpec, Platform, RepoDataRecord};
+use rattler_redaction::DisplayRedacted;
 pub use repo_data::RepoData;
 use reqwest_middleware::ClientWithMiddleware;
-use subdir::{Subdir, SubdirData};
+use subdir::{Subdir, SubdirClient, SubdirData};
+#[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+pub use tls::{TlsConfig, TlsConfigError};
 use tokio::sync::broadcast;
 use tracing::instrument;
-
-use crate::{fetch::FetchRepoDataError, gateway::error::SubdirNotFoundError, Reporter};
+pub use watch::RepoDataUpdate;
+
+use crate::{
+    fetch::{
+        content_trust::{ContentTrustError, SignaturePolicy},
+        FetchRepoDataError,
+    },
+    gateway::error::SubdirNotFoundError,
+    Reporter,
+};
 
 /// Central access point for high level queries about
 /// [`rattler_conda_types::RepoDataRecord`]s from different channels.
@@ -116,21 +140,81 @@ impl Gateway {
         )
     }
 
+    /// Recursively fetches the records of the given specs and their
+    /// dependencies and returns them as a stream as soon as they become
+    /// available. This is a shorthand for a recursive [`Gateway::query`] that
+    /// is executed with [`GatewayQuery::execute_stream`].
+    pub fn stream_records_recursive<
+        AsChannel,
+        ChannelIter,
+        PlatformIter,
+        PackageNameIter,
+        IntoMatchSpec,
+    >(
+        &self,
+        channels: ChannelIter,
+        platforms: PlatformIter,
+        specs: PackageNameIter,
+    ) -> impl Stream<Item = Result<RepoDataRecord, GatewayError>>
+    where
+        AsChannel: Into<Channel>,
+        ChannelIter: IntoIterator<Item = AsChannel>,
+        PlatformIter: IntoIterator<Item = Platform>,
+        <PlatformIter as IntoIterator>::IntoIter: Clone,
+        PackageNameIter: IntoIterator<Item = IntoMatchSpec>,
+        IntoMatchSpec: Into<MatchSpec>,
+    {
+        self.query(channels, platforms, specs)
+            .recursive(true)
+            .execute_stream()
+    }
+
     /// Clears any in-memory cache for the given channel.
     ///
     /// Any subsequent query will re-fetch any required data from the source.
     ///
     /// This method does not clear any on-disk cache.
     pub fn clear_repodata_cache(&self, channel: &Channel, subdirs: SubdirSelection) {
+        let channel_url = channel.channel_url();
         self.inner
             .subdirs
-            .retain(|key, _| key.0 != *channel || !subdirs.contains(key.1.as_str()));
+            .retain(|key, _| key.0 != channel_url || !subdirs.contains(key.1.as_str()));
+    }
+
+    /// Watches the repodata of a subdirectory of a channel for changes.
+    ///
+    /// A background task revalidates the repodata at the
+    /// [`SourceConfig::refresh_interval`] of the channel. Revalidation follows
+    /// the cache policy of the channel, so unchanged repodata is not
+    /// downloaded again and incremental updates through JLAP are used when
+    /// available. When packages are added or the records of any package that
+    /// was previously requested from the gateway change, the in-memory cache
+    /// of the gateway is updated and the returned receiver is notified. The
+    /// records are only compared if the content of the subdirectory changed.
+    ///
+    /// The task stops when all receivers or the gateway are dropped. This
+    /// function must be called from within a tokio runtime.
+    pub fn watch(
+        &self,
+        channel: &Channel,
+        platform: Platform,
+    ) -> tokio::sync::watch::Receiver<RepoDataUpdate> {
+        let (sender, receiver) = tokio::sync::watch::channel(RepoDataUpdate::default());
+        tokio::spawn(watch::watch_subdir(
+            Arc::downgrade(&self.inner),
+            channel.clone(),
+            platform,
+            sender,
+        ));
+        receiver
     }
 }
 
 struct GatewayInner {
-    /// A map of subdirectories for each channel and platform.
-    subdirs: DashMap<(Channel, Platform), PendingOrFetched<Arc<Subdir>>>,
+    /// A map of subdirectories for each channel and platform. Channels are
+    /// identified by their normalized url, the same channel referred to by a
+    /// different name or with different platforms shares the same entries.
+    subdirs: DashMap<(ChannelUrl, Platform), PendingOrFetched<Arc<Subdir>>>,
 
     /// The client to use to fetch repodata.
     client: ClientWithMiddleware,
@@ -146,9 +230,24 @@ struct GatewayInner {
 
     /// A semaphore to limit the number of concurrent requests.
     concurrent_requests_semaphore: Arc<tokio::sync::Semaphore>,
+
+    /// The metrics that are notified about the fetched repodata.
+    metrics: Option<Arc<dyn GatewayMetrics>>,
+
+    /// Whether queries include the `noarch` platform by default.
+    include_noarch: bool,
 }
 
 impl GatewayInner {
+    /// Returns the reporter to use for fetching repodata. If metrics are
+    /// configured the reporter is wrapped to count the downloaded bytes.
+    fn reporter(&self, reporter: Option<Arc<dyn Reporter>>) -> Option<Arc<dyn Reporter>> {
+        match &self.metrics {
+            Some(metrics) => Some(Arc::new(MetricsReporter::new(metrics.clone(), reporter))),
+            None => reporter,
+        }
+    }
+
     /// Returns the [`Subdir`] for the given channel and platform. This
     /// function will create the [`Subdir`] if it does not exist yet, otherwise
     /// it will return the previously created subdir.
@@ -164,7 +263,7 @@ impl GatewayInner {
         platform: Platform,
         reporter: Option<Arc<dyn Reporter>>,
     ) -> Result<Arc<Subdir>, GatewayError> {
-        let sender = match self.subdirs.entry((channel.clone(), platform)) {
+        let sender = match self.subdirs.entry((channel.channel_url(), platform)) {
             Entry::Vacant(entry) => {
                 // Construct a sender so other tasks can subscribe
                 let (sender, _) = broadcast::channel(1);
@@ -234,7 +333,7 @@ impl GatewayInner {
 
         // Store the fetched files in the entry.
         self.subdirs.insert(
-            (channel.clone(), platform),
+            (channel.channel_url(), platform),
             PendingOrFetched::Fetched(subdir.clone()),
         );
 
@@ -251,46 +350,153 @@ impl GatewayInner {
         platform: Platform,
         reporter: Option<Arc<dyn Reporter>>,
     ) -> Result<Subdir, GatewayError> {
+        let base = match self
+            .create_subdir_client(channel, platform, reporter.clone())
+            .await
+        {
+            Ok(client) => Some(client),
+            Err(GatewayError::SubdirNotFoundError(err)) if platform != Platform::NoArch => {
+                // If the subdir was not found and the platform is not `noarch` we assume its
+                // just empty.
+                tracing::info!(
+                    "subdir {} of channel {} was not found, ignoring",
+                    err.subdir,
+                    err.channel.canonical_name()
+                );
+                None
+            }
+            Err(GatewayError::FetchRepoDataError(FetchRepoDataError::NotFound(err))) => {
+                return Err(SubdirNotFoundError {
+                    subdir: platform.to_string(),
+                    channel: channel.clone(),
+                    source: err.into(),
+                }
+                .into())
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut overlays = Vec::new();
+        for overlay in &self.channel_config.get(channel).overlays {
+            let overlay = Channel::from_url(overlay.clone());
+            match self
+                .create_subdir_client(&overlay, platform, reporter.clone())
+                .await
+            {
+                Ok(client) => overlays.push(client),
+                Err(
+                    GatewayError::SubdirNotFoundError(_)
+                    | GatewayError::FetchRepoDataError(FetchRepoDataError::NotFound(_)),
+                ) => {
+                    tracing::debug!(
+                        "subdir {} of overlay {} was not found, ignoring",
+                        platform,
+                        overlay.canonical_name()
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        match (base, overlays.is_empty()) {
+            (None, true) => Ok(Subdir::NotFound),
+            (Some(base), true) => Ok(Subdir::Found(SubdirData::from_client(base))),
+            (base, false) => Ok(Subdir::Found(SubdirData::from_client(Arc::new(
+                OverlaySubdirClient::new(channel.canonical_name(), base, overlays),
+            )))),
+        }
+    }
+
+    /// Constructs the client that reads the records of a single subdir of a
+    /// channel.
+    async fn create_subdir_client(
+        &self,
+        channel: &Channel,
+        platform: Platform,
+        reporter: Option<Arc<dyn Reporter>>,
+    ) -> Result<Arc<dyn SubdirClient>, GatewayError> {
         let url = channel.platform_url(platform);
-        let subdir_data = if url.scheme() == "file" {
+        let client: Arc<dyn SubdirClient> = if url.scheme() == "file" {
             if let Some(path) = url_to_path(&url) {
-                LocalSubdirClient::from_channel_subdir(
-                    &path.join("repodata.json"),
-                    channel.clone(),
-                    platform.as_str(),
+                Arc::new(
+                    LocalSubdirClient::from_channel_subdir(
+                        &path.join("repodata.json"),
+                        channel.clone(),
+                        platform.as_str(),
+                    )
+                    .await?,
                 )
-                .await
-                .map(SubdirData::from_client)
             } else {
                 return Err(GatewayError::UnsupportedUrl(
                     "unsupported file based url".to_string(),
                 ));
             }
         } else if url.scheme() == "http" || url.scheme() == "https" {
+            let source_config = self.channel_config.get(channel);
+            let client = request_headers::client_for_source(&self.client, source_config);
             if url.host_str() == Some("fast.prefiks.dev")
                 || url.host_str() == Some("fast.prefix.dev")
             {
-                sharded_subdir::ShardedSubdir::new(
-                    channel.clone(),
-                    platform.to_string(),
-                    self.client.clone(),
-                    self.cache.clone(),
-                    self.concurrent_requests_semaphore.clone(),
-                    reporter.as_deref(),
-                )
-                .await
-                .map(SubdirData::from_client)
+                // The sharded index does not contain the signatures of the records.
+                match source_config.signature_policy {
+                    SignaturePolicy::Disabled => {}
+                    SignaturePolicy::Warn => tracing::warn!(
+                        "the signatures of the sharded repodata of {} cannot be verified",
+                        url.display_redacted()
+                    ),
+                    SignaturePolicy::Enforce => {
+                        return Err(FetchRepoDataError::ContentTrust(
+                            ContentTrustError::ShardedRepoData,
+                        )
+                        .into())
+                    }
+                }
+                let sharded: Arc<dyn SubdirClient> = Arc::new(
+                    sharded_subdir::ShardedSubdir::new(
+                        channel.clone(),
+                        platform.to_string(),
+                        client.clone(),
+                        self.cache.clone(),
+                        self.concurrent_requests_semaphore.clone(),
+                        source_config.refresh_policy,
+                        self.metrics.clone(),
+                        reporter.as_deref(),
+                    )
+                    .await?,
+                );
+
+                // The shards do not contain the patches of the channel, they
+                // are applied on top like for the full repodata.
+                let patch_instructions = if source_config.patch_instructions_enabled {
+                    SubdirPatchInstructions::fetch(
+                        &client,
+                        &url,
+                        &self.cache,
+                        source_config.cache_action,
+                    )
+                    .await?
+                } else {
+                    None
+                };
+                match patch_instructions {
+                    Some(patch_instructions) => {
+                        Arc::new(PatchedSubdirClient::new(sharded, patch_instructions))
+                    }
+                    None => sharded,
+                }
             } else {
-                remote_subdir::RemoteSubdirClient::new(
-                    channel.clone(),
-                    platform,
-                    self.client.clone(),
-                    self.cache.clone(),
-                    self.channel_config.get(channel).clone(),
-                    reporter,
+                Arc::new(
+                    remote_subdir::RemoteSubdirClient::new(
+                        channel.clone(),
+                        platform,
+                        client,
+                        self.cache.clone(),
+                        source_config.clone(),
+                        self.metrics.clone(),
+                        reporter,
+                    )
+                    .await?,
                 )
-                .await
-                .map(SubdirData::from_client)
             }
         } else {
             return Err(GatewayError::UnsupportedUrl(format!(
@@ -299,35 +505,16 @@ impl GatewayInner {
             )));
         };
 
-        match subdir_data {
-            Ok(client) => Ok(Subdir::Found(client)),
-            Err(GatewayError::SubdirNotFoundError(err)) if platform != Platform::NoArch => {
-                // If the subdir was not found and the platform is not `noarch` we assume its
-                // just empty.
-                tracing::info!(
-                    "subdir {} of channel {} was not found, ignoring",
-                    err.subdir,
-                    err.channel.canonical_name()
-                );
-                Ok(Subdir::NotFound)
-            }
-            Err(GatewayError::FetchRepoDataError(FetchRepoDataError::NotFound(err))) => {
-                Err(SubdirNotFoundError {
-                    subdir: platform.to_string(),
-                    channel: channel.clone(),
-                    source: err.into(),
-                }
-                .into())
-            }
-            Err(err) => Err(err),
-        }
+        Ok(client)
     }
 }
 
-/// A record that is either pending or has been fetched.
+/// A record that is either pending or has been fetched. The sender of a
+/// pending record sends `S` to the waiting tasks, which defaults to the
+/// record itself.
 #[derive(Clone)]
-enum PendingOrFetched<T> {
-    Pending(Weak<broadcast::Sender<T>>),
+enum PendingOrFetched<T, S = T> {
+    Pending(Weak<broadcast::Sender<S>>),
     Fetched(T),
 }
 
@@ -337,11 +524,15 @@ mod test {
     use std::{
         path::{Path, PathBuf},
         str::FromStr,
-        sync::Arc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
         time::Instant,
     };
 
-    use dashmap::DashSet;
+    use dashmap::{DashMap, DashSet};
+    use futures::StreamExt;
     use rattler_cache::default_cache_dir;
     use rattler_cache::package_cache::PackageCache;
     use rattler_conda_types::{
@@ -352,10 +543,10 @@ mod test {
     use url::Url;
 
     use crate::{
-        fetch::CacheAction,
+        fetch::{CacheAction, CacheResult},
         gateway::Gateway,
         utils::{simple_channel_server::SimpleChannelServer, test::fetch_repo_data},
-        GatewayError, RepoData, Reporter, SourceConfig, SubdirSelection,
+        GatewayError, GatewayMetrics, RepoData, Reporter, SourceConfig, SubdirSelection,
     };
 
     async fn local_conda_forge() -> Channel {
@@ -391,6 +582,25 @@ mod test {
         assert_eq!(total_records, 45060);
     }
 
+    #[tokio::test]
+    async fn test_stream_records() {
+        let gateway = Gateway::new();
+
+        let records = gateway
+            .stream_records_recursive(
+                vec![local_conda_forge().await],
+                vec![Platform::Linux64, Platform::NoArch],
+                vec![PackageName::from_str("rubin-env").unwrap()].into_iter(),
+            )
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(records.len(), 45060);
+    }
+
     #[tokio::test]
     async fn test_remote_gateway() {
         let gateway = Gateway::new();
@@ -611,6 +821,102 @@ mod test {
         assert!(total_records == 49);
     }
 
+    #[tokio::test]
+    async fn test_query_overrides() {
+        let gateway = Gateway::new();
+        let index = local_conda_forge().await;
+
+        // Override the platforms and specs of a query.
+        let query = gateway.query(
+            vec![index.clone()],
+            vec![Platform::Win64],
+            vec![PackageName::from_str("python").unwrap()],
+        );
+        let records = query
+            .clone()
+            .with_platforms([Platform::Linux64])
+            .with_specs([MatchSpec::from_str("openssl=3", Lenient).unwrap()])
+            .await
+            .unwrap();
+        let total_records: usize = records.iter().map(RepoData::len).sum();
+        assert_eq!(total_records, 9);
+
+        // Apply a record filter.
+        let records = query
+            .clone()
+            .with_platforms([Platform::Linux64])
+            .with_specs([MatchSpec::from_str("openssl=3", Lenient).unwrap()])
+            .with_record_filter(|record| record.package_record.build.ends_with("_1"))
+            .await
+            .unwrap();
+        let total_records: usize = records.iter().map(RepoData::len).sum();
+        assert_eq!(total_records, 3);
+
+        // Include the noarch subdir.
+        let records = query
+            .clone()
+            .with_platforms([Platform::Linux64])
+            .include_noarch(true)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+
+        // With strict channel priority the records of the second channel are
+        // dropped.
+        let query = query
+            .with_channels([index.clone(), index])
+            .with_platforms([Platform::Linux64])
+            .with_specs([MatchSpec::from_str("openssl=3", Lenient).unwrap()]);
+        let records = query.clone().await.unwrap();
+        let total_records: usize = records.iter().map(RepoData::len).sum();
+        assert_eq!(total_records, 18);
+
+        let query = query.with_strict_channel_priority(true);
+        let records = query.clone().await.unwrap();
+        assert_eq!(records[0].len(), 9);
+        assert!(records[1].is_empty());
+
+        // The same records are streamed with strict channel priority.
+        let streamed = query
+            .execute_stream()
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(streamed.len(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_query_platforms() {
+        let index = local_conda_forge().await;
+
+        // The noarch platform is included by default if configured on the
+        // gateway, duplicate platforms are ignored.
+        let gateway = Gateway::builder().with_include_noarch(true).finish();
+        let query = gateway.query(
+            vec![index.clone()],
+            vec![Platform::Linux64, Platform::Linux64],
+            vec![PackageName::from_str("python").unwrap()],
+        );
+        let records = query.clone().await.unwrap();
+        assert_eq!(records.len(), 2);
+
+        // It can still be disabled for a single query.
+        let records = query.include_noarch(false).await.unwrap();
+        assert_eq!(records.len(), 1);
+
+        // The unknown platform cannot be queried.
+        let result = gateway
+            .query(
+                vec![index],
+                vec![Platform::Unknown],
+                vec![PackageName::from_str("python").unwrap()],
+            )
+            .await;
+        assert_matches!(result, Err(GatewayError::UnsupportedPlatform(_)));
+    }
+
     #[tokio::test]
     async fn test_nameless_matchspec_error() {
         let gateway = Gateway::new();
@@ -747,4 +1053,218 @@ mod test {
             "after clearing the cache there should be new urls fetched"
         );
     }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        #[derive(Default)]
+        struct Metrics {
+            subdirs: DashMap<Url, CacheResult>,
+            bytes_downloaded: AtomicUsize,
+        }
+        impl GatewayMetrics for Arc<Metrics> {
+            fn on_subdir_fetched(&self, url: &Url, cache_result: CacheResult) {
+                self.subdirs.insert(url.clone(), cache_result);
+            }
+
+            fn on_bytes_downloaded(&self, _url: &Url, bytes: usize) {
+                self.bytes_downloaded.fetch_add(bytes, Ordering::Relaxed);
+            }
+        }
+
+        #[derive(Default)]
+        struct Downloads {
+            urls: DashSet<Url>,
+        }
+        impl Reporter for Arc<Downloads> {
+            fn on_download_complete(&self, url: &Url, _index: usize) {
+                self.urls.insert(url.clone());
+            }
+        }
+
+        let local_channel = remote_conda_forge().await;
+        let cache_dir = tempfile::tempdir().unwrap();
+        let metrics = Arc::new(Metrics::default());
+        let gateway = Gateway::builder()
+            .with_cache_dir(cache_dir.path())
+            .with_metrics(metrics.clone())
+            .finish();
+
+        let downloads = Arc::new(Downloads::default());
+        gateway
+            .query(
+                vec![local_channel.channel()],
+                vec![Platform::Linux64, Platform::NoArch],
+                vec![PackageName::from_str("python").unwrap()].into_iter(),
+            )
+            .with_reporter(downloads.clone())
+            .await
+            .unwrap();
+
+        // Both subdirectories were fetched without a cache.
+        let channel = local_channel.channel();
+        for platform in [Platform::Linux64, Platform::NoArch] {
+            assert_eq!(
+                metrics
+                    .subdirs
+                    .get(&channel.platform_url(platform))
+                    .map(|entry| *entry),
+                Some(CacheResult::CacheNotPresent)
+            );
+        }
+        assert!(metrics.bytes_downloaded.load(Ordering::Relaxed) > 0);
+
+        // The reporter of the query is still notified.
+        assert!(!downloads.urls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_overlay() {
+        let channel = Channel::from_directory(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data/channels/dummy"),
+        );
+
+        // An overlay that replaces an existing record and adds a new one.
+        let overlay_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(overlay_dir.path().join("linux-64")).unwrap();
+        std::fs::write(
+            overlay_dir.path().join("linux-64/repodata.json"),
+            r#"{
+                "info": { "subdir": "linux-64" },
+                "packages": {
+                    "foo-3.0.2-py36h1af98f8_1.tar.bz2": {
+                        "build": "py36h1af98f8_1", "build_number": 1, "depends": ["bar"],
+                        "name": "foo", "subdir": "linux-64", "version": "3.0.2"
+                    },
+                    "foo-5.0.0-0.tar.bz2": {
+                        "build": "0", "build_number": 0, "depends": [],
+                        "name": "foo", "subdir": "linux-64", "version": "5.0.0"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let overlay = Channel::from_directory(overlay_dir.path());
+
+        let gateway = Gateway::builder()
+            .with_channel_config(super::ChannelConfig {
+                per_channel: [(
+                    channel.clone(),
+                    SourceConfig {
+                        overlays: vec![overlay.base_url.clone()],
+                        ..SourceConfig::default()
+                    },
+                )]
+                .into_iter()
+                .collect(),
+                ..super::ChannelConfig::default()
+            })
+            .finish();
+
+        let records = gateway
+            .query(
+                vec![channel.clone()],
+                vec![Platform::Linux64],
+                vec![PackageName::from_str("foo").unwrap()].into_iter(),
+            )
+            .await
+            .unwrap();
+        let records = records[0].iter().collect::<Vec<_>>();
+
+        assert_eq!(records.len(), 5);
+        assert!(records
+            .iter()
+            .all(|record| record.channel == channel.canonical_name()));
+        let replaced = records
+            .iter()
+            .find(|record| record.file_name == "foo-3.0.2-py36h1af98f8_1.tar.bz2")
+            .unwrap();
+        assert_eq!(replaced.package_record.depends, vec!["bar"]);
+        assert!(records
+            .iter()
+            .any(|record| record.file_name == "foo-5.0.0-0.tar.bz2"));
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let channel_dir = tempfile::tempdir().unwrap();
+        let repodata_path = channel_dir.path().join("linux-64/repodata.json");
+        std::fs::create_dir(channel_dir.path().join("linux-64")).unwrap();
+        std::fs::copy(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../test-data/channels/dummy/linux-64/repodata.json"),
+            &repodata_path,
+        )
+        .unwrap();
+        let channel = Channel::from_directory(channel_dir.path());
+
+        let gateway = Gateway::builder()
+            .with_channel_config(super::ChannelConfig {
+                default: SourceConfig {
+                    refresh_interval: std::time::Duration::from_millis(10),
+                    ..SourceConfig::default()
+                },
+                ..super::ChannelConfig::default()
+            })
+            .finish();
+
+        let foo = PackageName::from_str("foo").unwrap();
+        let records = gateway
+            .query(
+                vec![channel.clone()],
+                vec![Platform::Linux64],
+                vec![foo.clone()],
+            )
+            .await
+            .unwrap();
+        assert!(!records[0].is_empty());
+
+        let mut receiver = gateway.watch(&channel, Platform::Linux64);
+
+        // Remove all the records of foo from the channel. The file is replaced
+        // instead of overwritten because the previous repodata is memory mapped.
+        let mut repodata: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&repodata_path).unwrap()).unwrap();
+        repodata["packages"]
+            .as_object_mut()
+            .unwrap()
+            .retain(|_, record| record["name"] != "foo");
+        let updated_path = channel_dir.path().join("repodata.json.tmp");
+        std::fs::write(&updated_path, repodata.to_string()).unwrap();
+        std::fs::rename(&updated_path, &repodata_path).unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(10), receiver.changed())
+            .await
+            .expect("the update should be received in time")
+            .unwrap();
+        let update = receiver.borrow_and_update().clone();
+        assert_eq!(update.revision, 1);
+        assert_eq!(update.changed_packages, vec![foo.clone()]);
+
+        let records = gateway
+            .query(vec![channel.clone()], vec![Platform::Linux64], vec![foo])
+            .await
+            .unwrap();
+        assert!(records[0].is_empty());
+
+        // Packages that are added to the channel are reported as well.
+        let mut record = repodata["packages"]["bors-1.0-bla_1.tar.bz2"].clone();
+        record["name"] = "qux".into();
+        repodata["packages"]
+            .as_object_mut()
+            .unwrap()
+            .insert("qux-1.0-bla_1.tar.bz2".to_string(), record);
+        std::fs::write(&updated_path, repodata.to_string()).unwrap();
+        std::fs::rename(&updated_path, &repodata_path).unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(10), receiver.changed())
+            .await
+            .expect("the update should be received in time")
+            .unwrap();
+        let update = receiver.borrow_and_update().clone();
+        assert_eq!(update.revision, 2);
+        assert_eq!(
+            update.changed_packages,
+            vec![PackageName::from_str("qux").unwrap()]
+        );
+    }
 }
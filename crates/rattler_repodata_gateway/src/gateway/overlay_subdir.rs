@@ -0,0 +1,96 @@
+use std::{collections::HashSet, sync::Arc};
+
+use futures::future::try_join_all;
+use rattler_conda_types::{PackageName, RepoDataRecord};
+
+use super::{subdir::SubdirClient, GatewayError};
+use crate::Reporter;
+
+/// A client that merges the records of a subdirectory from multiple sources.
+///
+/// The records of the overlays take precedence over the records of the base
+/// if they refer to a package with the same filename. Later overlays take
+/// precedence over earlier ones. The records of the overlays are attributed to
+/// the channel of the base.
+pub struct OverlaySubdirClient {
+    channel_name: String,
+    base: Option<Arc<dyn SubdirClient>>,
+    overlays: Vec<Arc<dyn SubdirClient>>,
+}
+
+impl OverlaySubdirClient {
+    pub fn new(
+        channel_name: String,
+        base: Option<Arc<dyn SubdirClient>>,
+        overlays: Vec<Arc<dyn SubdirClient>>,
+    ) -> Self {
+        Self {
+            channel_name,
+            base,
+            overlays,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SubdirClient for OverlaySubdirClient {
+    async fn fetch_package_records(
+        &self,
+        name: &PackageName,
+        reporter: Option<&dyn Reporter>,
+    ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
+        let (base, overlays) = futures::try_join!(
+            async {
+                match &self.base {
+                    Some(client) => client.fetch_package_records(name, reporter).await.map(Some),
+                    None => Ok(None),
+                }
+            },
+            try_join_all(
+                self.overlays
+                    .iter()
+                    .map(|client| client.fetch_package_records(name, reporter)),
+            )
+        )?;
+
+        let mut seen = HashSet::new();
+        let mut records = Vec::new();
+        for record in overlays.iter().rev().flat_map(|records| records.iter()) {
+            if seen.insert(record.file_name.as_str()) {
+                records.push(RepoDataRecord {
+                    channel: self.channel_name.clone(),
+                    ..record.clone()
+                });
+            }
+        }
+        for record in base.iter().flat_map(|records| records.iter()) {
+            if seen.insert(record.file_name.as_str()) {
+                records.push(record.clone());
+            }
+        }
+
+        Ok(records.into())
+    }
+
+    fn package_names(&self) -> Vec<PackageName> {
+        let mut names = self
+            .base
+            .iter()
+            .chain(&self.overlays)
+            .flat_map(|client| client.package_names())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn revision(&self) -> Option<String> {
+        let revisions = self
+            .base
+            .iter()
+            .chain(&self.overlays)
+            .map(|client| client.revision())
+            .collect::<Option<Vec<_>>>()?;
+        Some(revisions.join("-"))
+    }
+}
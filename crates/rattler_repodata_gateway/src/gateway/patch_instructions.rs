@@ -0,0 +1,332 @@
+//! Support for the `patch_instructions.json` file that some channels (e.g.
+//! anaconda.org) publish next to the `repodata.json` of a subdirectory. The
+//! instructions "hotfix" the metadata of packages after they have been
+//! published, for instance to tighten the dependencies of a package.
+//!
+//! The instructions are cached on disk next to the repodata. The `ETag` of the
+//! response is stored alongside so the instructions can be revalidated with
+//! the server.
+
+use std::{path::Path, sync::Arc};
+
+use http::{header::ETAG, header::IF_NONE_MATCH, StatusCode};
+use rattler_conda_types::{PackageName, PatchInstructions, RepoDataRecord};
+use rattler_digest::{compute_bytes_digest, Sha256};
+use rattler_redaction::DisplayRedacted;
+use reqwest_middleware::ClientWithMiddleware;
+use url::Url;
+
+use super::subdir::SubdirClient;
+use crate::{fetch::CacheAction, utils::url_to_cache_filename, GatewayError, Reporter};
+
+/// The name of the directory that contains all cached patch instructions.
+const PATCH_INSTRUCTIONS_CACHE_DIR: &str = "patch-instructions-v1";
+
+/// The name of the file that contains the patch instructions of a subdirectory.
+const PATCH_INSTRUCTIONS_FILENAME: &str = "patch_instructions.json";
+
+/// The patch instructions of a single subdirectory.
+pub struct SubdirPatchInstructions {
+    instructions: PatchInstructions,
+
+    /// The hash of the instructions, used to invalidate caches of records that
+    /// were patched with a different version of the instructions.
+    hash: String,
+}
+
+impl SubdirPatchInstructions {
+    /// Fetches the patch instructions of the subdirectory at `subdir_url`.
+    /// Returns `None` if the subdirectory does not provide patch instructions.
+    pub async fn fetch(
+        client: &ClientWithMiddleware,
+        subdir_url: &Url,
+        cache_dir: &Path,
+        cache_action: CacheAction,
+    ) -> Result<Option<Self>, GatewayError> {
+        let cache_path = cache_dir
+            .join(PATCH_INSTRUCTIONS_CACHE_DIR)
+            .join(format!("{}.json", url_to_cache_filename(subdir_url)));
+        let etag_path = cache_path.with_extension("etag");
+
+        let url = subdir_url
+            .join(PATCH_INSTRUCTIONS_FILENAME)
+            .expect("invalid patch instructions url");
+        let from_bytes = |bytes| Self::from_bytes(bytes, &url);
+
+        let cached_bytes = tokio::fs::read(&cache_path).await.ok();
+        if matches!(
+            cache_action,
+            CacheAction::UseCacheOnly | CacheAction::ForceCacheOnly
+        ) {
+            return cached_bytes.map(from_bytes).transpose();
+        }
+
+        let mut request = client.get(url.clone());
+        if cached_bytes.is_some() && cache_action != CacheAction::NoCache {
+            if let Ok(etag) = tokio::fs::read_to_string(&etag_path).await {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                return match cached_bytes {
+                    Some(bytes) => {
+                        tracing::warn!(
+                            "failed to fetch {}, using cached patch instructions instead: {e}",
+                            url.display_redacted()
+                        );
+                        from_bytes(bytes).map(Some)
+                    }
+                    None => Err(e.into()),
+                }
+            }
+        };
+
+        match response.status() {
+            StatusCode::NOT_MODIFIED if cached_bytes.is_some() => {
+                return cached_bytes.map(from_bytes).transpose();
+            }
+            StatusCode::NOT_FOUND => {
+                remove_cache_file(&cache_path).await?;
+                remove_cache_file(&etag_path).await?;
+                return Ok(None);
+            }
+            // A server that is temporarily unavailable should not prevent the
+            // repodata from being used.
+            status if status.is_server_error() => {
+                return match cached_bytes {
+                    Some(bytes) => {
+                        tracing::warn!(
+                            "failed to fetch {} ({status}), using cached patch instructions instead",
+                            url.display_redacted()
+                        );
+                        from_bytes(bytes).map(Some)
+                    }
+                    None => {
+                        tracing::warn!(
+                            "failed to fetch {} ({status}), the repodata is used without patches",
+                            url.display_redacted()
+                        );
+                        Ok(None)
+                    }
+                };
+            }
+            _ => {}
+        }
+
+        let response = response.error_for_status()?;
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+        let bytes = response.bytes().await?.to_vec();
+        let instructions = from_bytes(bytes.clone())?;
+
+        if let Err(e) = write_cache(&cache_path, &bytes, &etag_path, etag.as_deref()).await {
+            tracing::warn!("failed to cache patch instructions of {subdir_url}: {e}");
+        }
+
+        Ok(Some(instructions))
+    }
+
+    fn from_bytes(bytes: Vec<u8>, url: &Url) -> Result<Self, GatewayError> {
+        let instructions = serde_json::from_slice(&bytes).map_err(|e| {
+            GatewayError::InvalidPatchInstructions(url.display_redacted().to_string(), e)
+        })?;
+        Ok(Self {
+            instructions,
+            hash: format!("{:x}", compute_bytes_digest::<Sha256>(&bytes)),
+        })
+    }
+
+    /// Returns a hash that uniquely identifies these instructions.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Applies the instructions to the given records. Records that are
+    /// removed by the instructions are dropped.
+    pub fn apply(&self, records: Vec<RepoDataRecord>) -> Vec<RepoDataRecord> {
+        self.instructions.apply_to_records(records)
+    }
+}
+
+/// A client that applies the patch instructions of a subdirectory to the
+/// records of another client, e.g. of a sharded subdirectory.
+pub struct PatchedSubdirClient {
+    inner: Arc<dyn SubdirClient>,
+    patch_instructions: SubdirPatchInstructions,
+}
+
+impl PatchedSubdirClient {
+    /// Constructs a client that patches the records of `inner`.
+    pub fn new(inner: Arc<dyn SubdirClient>, patch_instructions: SubdirPatchInstructions) -> Self {
+        Self {
+            inner,
+            patch_instructions,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SubdirClient for PatchedSubdirClient {
+    async fn fetch_package_records(
+        &self,
+        name: &PackageName,
+        reporter: Option<&dyn Reporter>,
+    ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
+        let records = self.inner.fetch_package_records(name, reporter).await?;
+        Ok(self.patch_instructions.apply(records.to_vec()).into())
+    }
+
+    fn package_names(&self) -> Vec<PackageName> {
+        self.inner.package_names()
+    }
+
+    fn revision(&self) -> Option<String> {
+        let revision = self.inner.revision()?;
+        Some(format!("{revision}-{}", self.patch_instructions.hash()))
+    }
+}
+
+async fn remove_cache_file(path: &Path) -> Result<(), GatewayError> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(GatewayError::IoError(
+            format!("failed to remove {}", path.display()),
+            e,
+        )),
+    }
+}
+
+async fn write_cache(
+    cache_path: &Path,
+    bytes: &[u8],
+    etag_path: &Path,
+    etag: Option<&str>,
+) -> std::io::Result<()> {
+    if let Some(parent) = cache_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(cache_path, bytes).await?;
+    match etag {
+        Some(etag) => tokio::fs::write(etag_path, etag).await,
+        None => match tokio::fs::remove_file(etag_path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use rattler_conda_types::{Channel, RepoData};
+    use url::Url;
+
+    use super::SubdirPatchInstructions;
+    use crate::fetch::CacheAction;
+
+    #[test]
+    fn test_apply_patch_instructions() {
+        let subdir =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test-data/channels/patch/linux-64");
+        let repodata: RepoData = serde_json::from_slice(
+            &std::fs::read(subdir.join("repodata_from_packages.json")).unwrap(),
+        )
+        .unwrap();
+        let records = repodata.into_repo_data_records(&Channel::from_url(
+            Url::parse("https://example.com/patch").unwrap(),
+        ));
+
+        let instructions = SubdirPatchInstructions::from_bytes(
+            std::fs::read(subdir.join("patch_instructions.json")).unwrap(),
+            &Url::parse("https://example.com/patch/linux-64/patch_instructions.json").unwrap(),
+        )
+        .unwrap();
+        let patched = instructions.apply(records);
+
+        // Patches of `.tar.bz2` packages also apply to the equivalent `.conda` package.
+        for file_name in [
+            "cross-python_emscripten-32-3.10.1-h60d57d3_8.tar.bz2",
+            "cross-python_emscripten-32-3.10.1-h60d57d3_8.conda",
+        ] {
+            let record = patched
+                .iter()
+                .find(|record| record.file_name == file_name)
+                .unwrap();
+            assert_eq!(
+                record.package_record.license.as_deref(),
+                Some("WOLF LICENSE")
+            );
+            assert!(record
+                .package_record
+                .depends
+                .iter()
+                .any(|spec| spec == "wolfssl 12.0"));
+        }
+
+        let record = patched
+            .iter()
+            .find(|record| record.file_name == "cross-python_emscripten-32-3.12.1-h60d57d3_8.conda")
+            .unwrap();
+        assert_eq!(
+            record.package_record.license.as_deref(),
+            Some("WOLF LICENSE II")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_missing_patch_instructions_from_cache() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let instructions = SubdirPatchInstructions::fetch(
+            &reqwest_middleware::ClientWithMiddleware::from(reqwest::Client::new()),
+            &Url::parse("https://example.com/channel/linux-64/").unwrap(),
+            cache_dir.path(),
+            CacheAction::ForceCacheOnly,
+        )
+        .await
+        .unwrap();
+        assert!(instructions.is_none());
+    }
+
+    /// Responds to every request with a server error.
+    struct Unavailable;
+
+    #[async_trait::async_trait]
+    impl reqwest_middleware::Middleware for Unavailable {
+        async fn handle(
+            &self,
+            _req: reqwest::Request,
+            _extensions: &mut http::Extensions,
+            _next: reqwest_middleware::Next<'_>,
+        ) -> reqwest_middleware::Result<reqwest::Response> {
+            Ok(http::Response::builder()
+                .status(503)
+                .body("")
+                .unwrap()
+                .into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_patch_instructions() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let instructions = SubdirPatchInstructions::fetch(
+            &reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+                .with(Unavailable)
+                .build(),
+            &Url::parse("https://example.com/channel/linux-64/").unwrap(),
+            cache_dir.path(),
+            CacheAction::CacheOrFetch,
+        )
+        .await
+        .unwrap();
+        assert!(instructions.is_none());
+    }
+}
@@ -4,13 +4,18 @@ use std::{
     sync::Arc,
 };
 
-use futures::{select_biased, stream::FuturesUnordered, FutureExt, StreamExt};
+use futures::{select_biased, stream::FuturesUnordered, FutureExt, Stream, StreamExt};
 use itertools::Itertools;
-use rattler_conda_types::{Channel, MatchSpec, Matches, PackageName, Platform};
+use rattler_conda_types::{Channel, MatchSpec, Matches, PackageName, Platform, RepoDataRecord};
+use rattler_redaction::DisplayRedacted;
 
 use super::{subdir::Subdir, BarrierCell, GatewayError, GatewayInner, RepoData};
 use crate::{gateway::direct_url_query::DirectUrlQuery, Reporter};
 
+/// A predicate that decides whether a record should be part of the result of a
+/// [`GatewayQuery`].
+pub type RecordFilter = Arc<dyn Fn(&RepoDataRecord) -> bool + Send + Sync>;
+
 /// Represents a query to execute with a [`Gateway`].
 ///
 /// When executed the query will asynchronously load the repodata from all
@@ -22,6 +27,10 @@ use crate::{gateway::direct_url_query::DirectUrlQuery, Reporter};
 /// Repodata is cached by the [`Gateway`] so executing the same query twice
 /// with the same channels will not result in the repodata being fetched
 /// twice.
+///
+/// The channels, platforms and specs passed to [`Gateway::query`] can be
+/// overridden per query. This allows reusing a single [`Gateway`] (and its
+/// caches) for queries with different settings.
 #[derive(Clone)]
 pub struct GatewayQuery {
     /// The gateway that manages all resources
@@ -39,6 +48,16 @@ pub struct GatewayQuery {
     /// Whether to recursively fetch dependencies
     recursive: bool,
 
+    /// Whether to add the `noarch` platform if it is not already present.
+    include_noarch: bool,
+
+    /// Whether records for a package should only be returned from the first
+    /// channel that contains the package.
+    strict_channel_priority: bool,
+
+    /// An optional filter that records must pass to be part of the result.
+    record_filter: Option<RecordFilter>,
+
     /// The reporter to use by the query.
     reporter: Option<Arc<dyn Reporter>>,
 }
@@ -52,6 +71,7 @@ impl GatewayQuery {
         platforms: Vec<Platform>,
         specs: Vec<MatchSpec>,
     ) -> Self {
+        let include_noarch = gateway.include_noarch;
         Self {
             gateway,
             channels,
@@ -59,6 +79,9 @@ impl GatewayQuery {
             specs,
 
             recursive: false,
+            include_noarch,
+            strict_channel_priority: false,
+            record_filter: None,
             reporter: None,
         }
     }
@@ -74,6 +97,82 @@ impl GatewayQuery {
         Self { recursive, ..self }
     }
 
+    /// Overrides the channels to query. The order of the channels determines
+    /// their priority.
+    #[must_use]
+    pub fn with_channels<C>(self, channels: C) -> Self
+    where
+        C: IntoIterator,
+        C::Item: Into<Channel>,
+    {
+        Self {
+            channels: channels.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Overrides the platforms to query.
+    #[must_use]
+    pub fn with_platforms(self, platforms: impl IntoIterator<Item = Platform>) -> Self {
+        Self {
+            platforms: platforms.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Overrides the specs to fetch records for.
+    #[must_use]
+    pub fn with_specs<S>(self, specs: S) -> Self
+    where
+        S: IntoIterator,
+        S::Item: Into<MatchSpec>,
+    {
+        Self {
+            specs: specs.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Sets whether the `noarch` platform should also be queried, even if it
+    /// is not part of the platforms of the query. Defaults to the value
+    /// configured with [`crate::GatewayBuilder::with_include_noarch`].
+    #[must_use]
+    pub fn include_noarch(self, include_noarch: bool) -> Self {
+        Self {
+            include_noarch,
+            ..self
+        }
+    }
+
+    /// Sets whether strict channel priority is used for this query.
+    ///
+    /// With strict channel priority the records of a package are only returned
+    /// from the first channel (in the order of the query) that contains the
+    /// package. Records that are fetched through a direct url always take
+    /// precedence. By default, records from all channels are returned.
+    #[must_use]
+    pub fn with_strict_channel_priority(self, strict_channel_priority: bool) -> Self {
+        Self {
+            strict_channel_priority,
+            ..self
+        }
+    }
+
+    /// Sets a filter that records must pass to be part of the result.
+    ///
+    /// Records that are rejected by the filter are also not considered when
+    /// recursively fetching dependencies.
+    #[must_use]
+    pub fn with_record_filter(
+        self,
+        filter: impl Fn(&RepoDataRecord) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            record_filter: Some(Arc::new(filter)),
+            ..self
+        }
+    }
+
     /// Sets the reporter to use for this query.
     ///
     /// The reporter is notified of important evens during the execution of the
@@ -87,13 +186,114 @@ impl GatewayQuery {
 
     /// Execute the query and return the resulting repodata records.
     pub async fn execute(self) -> Result<Vec<RepoData>, GatewayError> {
+        let strict_channel_priority = self.strict_channel_priority;
+        let mut result: Vec<RepoData> = Vec::new();
+        let layout = self
+            .run(|_, result_idx, _, records| {
+                if records.is_empty() {
+                    return;
+                }
+                if result.len() <= result_idx {
+                    result.resize_with(result_idx + 1, RepoData::default);
+                }
+                let result = &mut result[result_idx];
+                result.len += records.len();
+                result
+                    .shards
+                    .push(records.into_iter().cloned().collect::<Vec<_>>().into());
+            })
+            .await?;
+        result.resize_with(layout.len, RepoData::default);
+
+        if strict_channel_priority {
+            apply_strict_channel_priority(
+                &mut result,
+                layout.direct_url_offset,
+                layout.platform_count,
+            );
+        }
+
+        Ok(result)
+    }
+
+    /// Execute the query and return a stream of the resulting records as
+    /// soon as they become available. This allows processing records before
+    /// the repodata of all subdirectories has been fetched.
+    ///
+    /// The records are not returned in any particular order. With strict
+    /// channel priority (see [`GatewayQuery::with_strict_channel_priority`])
+    /// the records of a package are held back until the records of the
+    /// package from all higher priority channels are known.
+    ///
+    /// If an error occurs it is returned as the last item of the stream.
+    pub fn execute_stream(self) -> impl Stream<Item = Result<RepoDataRecord, GatewayError>> {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let strict_channel_priority = self.strict_channel_priority;
+        let direct_url_names: HashSet<PackageName> = self
+            .specs
+            .iter()
+            .filter(|spec| spec.url.is_some())
+            .filter_map(|spec| spec.name.clone())
+            .collect();
+        let query = async move {
+            let mut priorities = HashMap::new();
+            let result = self
+                .run(|layout, result_idx, name, records| {
+                    let records = if strict_channel_priority {
+                        priorities
+                            .entry(name.clone())
+                            .or_insert_with(|| {
+                                PackagePriority::new(layout, direct_url_names.contains(name))
+                            })
+                            .add(layout, result_idx, records)
+                    } else {
+                        records.into_iter().cloned().collect()
+                    };
+
+                    // The receiver is only dropped when the stream is dropped, in
+                    // which case nobody is interested in the records anymore.
+                    for record in records {
+                        let _ = sender.unbounded_send(Ok(record));
+                    }
+                })
+                .await;
+            if let Err(e) = result {
+                let _ = sender.unbounded_send(Err(e));
+            }
+        };
+
+        // Drive the query as part of the stream so no task has to be spawned.
+        // The receiver ends once the query has completed and the sender is
+        // dropped.
+        let query = futures::stream::once(query).filter_map(|()| futures::future::ready(None));
+        futures::stream::select(query, receiver)
+    }
+
+    /// Executes the query and calls `on_records` with the index of the result,
+    /// the name of the package and the records that are part of the result
+    /// every time the records of a package have been fetched from a
+    /// subdirectory or a direct url, even if there are no such records.
+    async fn run(
+        self,
+        mut on_records: impl FnMut(&ResultLayout, usize, &PackageName, Vec<&RepoDataRecord>),
+    ) -> Result<ResultLayout, GatewayError> {
+        let platforms = normalize_platforms(self.platforms, self.include_noarch)?;
+        let platform_count = platforms.len();
+        let reporter = self.gateway.reporter(self.reporter.clone());
+
         // Collect all the channels and platforms together
         let channels_and_platforms = self
             .channels
             .iter()
-            .cartesian_product(self.platforms.into_iter())
+            .cartesian_product(platforms.into_iter())
             .collect_vec();
 
+        let record_filter = self.record_filter;
+        let passes_filter = |record: &RepoDataRecord| match &record_filter {
+            Some(filter) => filter(record),
+            None => true,
+        };
+
         // Collect all the specs that have a direct url and the ones that have a name.
         let mut seen = HashSet::new();
         let mut pending_package_specs = HashMap::new();
@@ -117,6 +317,11 @@ impl GatewayQuery {
 
         // Result offset for direct url queries.
         let direct_url_offset = usize::from(!direct_url_specs.is_empty());
+        let layout = ResultLayout {
+            len: channels_and_platforms.len() + direct_url_offset,
+            direct_url_offset,
+            platform_count,
+        };
 
         // Create barrier cells for each subdirectory.
         // This can be used to wait until the subdir becomes available.
@@ -129,7 +334,7 @@ impl GatewayQuery {
             subdirs.push((subdir_idx + direct_url_offset, barrier.clone()));
 
             let inner = self.gateway.clone();
-            let reporter = self.reporter.clone();
+            let reporter = reporter.clone();
             pending_subdirs.push(async move {
                 match inner
                     .get_or_create_subdir(channel, platform, reporter)
@@ -160,10 +365,9 @@ impl GatewayQuery {
                         spec.sha256,
                     );
 
-                    let record = query
-                        .execute()
-                        .await
-                        .map_err(|e| GatewayError::DirectUrlQueryError(url.to_string(), e))?;
+                    let record = query.execute().await.map_err(|e| {
+                        GatewayError::DirectUrlQueryError(url.display_redacted().to_string(), e)
+                    })?;
 
                     // Check if record actually has the same name
                     if let Some(record) = record.first() {
@@ -176,15 +380,12 @@ impl GatewayQuery {
                         }
                     }
                     // Push the direct url in the first subdir result for channel priority logic.
-                    Ok((0, vec![spec], record))
+                    Ok((0, name, vec![spec], record))
                 }
                 .boxed(),
             );
         }
 
-        let len = subdirs.len() + direct_url_offset;
-        let mut result = vec![RepoData::default(); len];
-
         // Loop until all pending package names have been fetched.
         loop {
             // Iterate over all pending package names and create futures to fetch them from
@@ -193,7 +394,7 @@ impl GatewayQuery {
                 for (subdir_idx, subdir) in subdirs.iter().cloned() {
                     let specs = specs.clone();
                     let package_name = package_name.clone();
-                    let reporter = self.reporter.clone();
+                    let reporter = reporter.clone();
                     pending_records.push(
                         async move {
                             let barrier_cell = subdir.clone();
@@ -202,9 +403,9 @@ impl GatewayQuery {
                                 Subdir::Found(subdir) => subdir
                                     .get_or_fetch_package_records(&package_name, reporter)
                                     .await
-                                    .map(|records| (subdir_idx, specs, records)),
+                                    .map(|records| (subdir_idx, package_name, specs, records)),
                                 Subdir::NotFound => {
-                                    Ok((subdir_idx + direct_url_offset, specs, Arc::from(vec![])))
+                                    Ok((subdir_idx, package_name, specs, Arc::from(vec![])))
                                 }
                             }
                         }
@@ -222,21 +423,22 @@ impl GatewayQuery {
 
                 // Handle any records that were fetched
                 records = pending_records.select_next_some() => {
-                    let (result_idx, request_specs, records) = records?;
+                    let (result_idx, package_name, request_specs, records) = records?;
 
                     if self.recursive {
                         // Extract the dependencies from the records and recursively add them to the
                         // list of package names that we need to fetch.
                         for record in records.iter() {
-                            if !request_specs.iter().any(|spec| spec.matches(record)) {
+                            if !request_specs.iter().any(|spec| spec.matches(record)) || !passes_filter(record) {
                                 // Do not recurse into records that do not match to root spec.
                                 continue;
                             }
-                            for dependency in &record.package_record.depends {
+                            for dependency_name in record.package_record.dependency_names() {
+                                // Virtual packages are provided by the system, not by a channel.
+                                if dependency_name.is_virtual() {
+                                    continue;
+                                }
                                 // Use only the name for transitive dependencies.
-                                let dependency_name = PackageName::new_unchecked(
-                                    dependency.split_once(' ').unwrap_or((dependency, "")).0,
-                                );
                                 if seen.insert(dependency_name.clone()) {
                                     pending_package_specs.insert(dependency_name.clone(), vec![dependency_name.into()]);
                                 }
@@ -245,18 +447,15 @@ impl GatewayQuery {
                     }
 
                     // Add the records to the result
-                    if records.len() > 0 {
-                        let result = &mut result[result_idx];
-
-                        for record in records.iter() {
-                            if !self.recursive && !request_specs.iter().any(|spec| spec.matches(record)) {
-                                // Do not return records that do not match to root spec.
-                                continue;
-                            }
-                            result.len += 1;
-                            result.shards.push(Arc::new([record.clone()]));
-                        }
-                    }
+                    let records = records
+                        .iter()
+                        .filter(|record| {
+                            // Do not return records that do not match to root spec.
+                            (self.recursive || request_specs.iter().any(|spec| spec.matches(record)))
+                                && passes_filter(record)
+                        })
+                        .collect();
+                    on_records(&layout, result_idx, &package_name, records);
                 }
 
                 // All futures have been handled, all subdirectories have been loaded and all
@@ -267,7 +466,134 @@ impl GatewayQuery {
             }
         }
 
-        Ok(result)
+        Ok(layout)
+    }
+}
+
+/// Describes how the results of a query are ordered.
+struct ResultLayout {
+    /// The total number of results.
+    len: usize,
+
+    /// The number of results of direct url queries that precede the results
+    /// of the subdirectories.
+    direct_url_offset: usize,
+
+    /// The number of platforms per channel.
+    platform_count: usize,
+}
+
+/// Tracks the records of a package that are streamed by
+/// [`GatewayQuery::execute_stream`] with strict channel priority.
+///
+/// The priorities are the same as in [`apply_strict_channel_priority`]: the
+/// direct url of the package comes first, followed by the channels in order.
+struct PackagePriority {
+    /// The number of results that are still expected for each priority.
+    remaining: Vec<usize>,
+
+    /// The records of each priority that are held back until it is known
+    /// that no higher priority contains the package.
+    pending: Vec<Vec<RepoDataRecord>>,
+
+    /// The priority from which the records are returned, once it is known.
+    selected: Option<usize>,
+}
+
+impl PackagePriority {
+    fn new(layout: &ResultLayout, has_direct_url: bool) -> Self {
+        let channel_count = (layout.len - layout.direct_url_offset) / layout.platform_count.max(1);
+        let mut remaining = vec![layout.platform_count; channel_count + 1];
+        remaining[0] = usize::from(has_direct_url);
+        Self {
+            remaining,
+            pending: vec![Vec::new(); channel_count + 1],
+            selected: None,
+        }
+    }
+
+    /// Adds the records of the package that were fetched for the result with
+    /// the given index and returns the records that can be returned.
+    fn add(
+        &mut self,
+        layout: &ResultLayout,
+        result_idx: usize,
+        records: Vec<&RepoDataRecord>,
+    ) -> Vec<RepoDataRecord> {
+        let priority = if result_idx < layout.direct_url_offset {
+            0
+        } else {
+            1 + (result_idx - layout.direct_url_offset) / layout.platform_count.max(1)
+        };
+
+        if let Some(selected) = self.selected {
+            return if selected == priority {
+                records.into_iter().cloned().collect()
+            } else {
+                Vec::new()
+            };
+        }
+
+        self.remaining[priority] = self.remaining[priority].saturating_sub(1);
+        self.pending[priority].extend(records.into_iter().cloned());
+
+        // The records are returned from the highest priority that contains the
+        // package, but only once all higher priorities are known to not
+        // contain it.
+        for priority in 0..self.pending.len() {
+            if !self.pending[priority].is_empty() {
+                self.selected = Some(priority);
+                let records = std::mem::take(&mut self.pending[priority]);
+                self.pending = Vec::new();
+                return records;
+            }
+            if self.remaining[priority] > 0 {
+                break;
+            }
+        }
+        Vec::new()
+    }
+}
+
+/// Removes all records of a package from the result that do not originate from
+/// the highest priority channel that contains the package.
+///
+/// The result is ordered by channel and then by platform, optionally prefixed by
+/// the results of direct url queries which have the highest priority.
+fn apply_strict_channel_priority(
+    result: &mut [RepoData],
+    direct_url_offset: usize,
+    platform_count: usize,
+) {
+    let priority = |result_idx: usize| {
+        if result_idx < direct_url_offset {
+            0
+        } else {
+            1 + (result_idx - direct_url_offset) / platform_count.max(1)
+        }
+    };
+
+    // Determine the highest priority for every package name.
+    let mut highest_priority = HashMap::new();
+    for (result_idx, repo_data) in result.iter().enumerate() {
+        for record in repo_data.iter() {
+            highest_priority
+                .entry(record.package_record.name.clone())
+                .or_insert(priority(result_idx));
+        }
+    }
+
+    for (result_idx, repo_data) in result.iter_mut().enumerate() {
+        let priority = priority(result_idx);
+        let records = repo_data
+            .iter()
+            .filter(|record| highest_priority.get(&record.package_record.name) == Some(&priority))
+            .cloned()
+            .collect_vec();
+        *repo_data = RepoData {
+            len: records.len(),
+            shards: vec![Arc::from(records)],
+        };
     }
 }
 
@@ -279,3 +605,26 @@ impl IntoFuture for GatewayQuery {
         self.execute().boxed()
     }
 }
+
+/// Removes duplicate platforms while retaining the order in which they were
+/// specified and adds the `noarch` platform if requested. Returns an error if
+/// a platform is specified for which no repodata exists.
+fn normalize_platforms(
+    platforms: Vec<Platform>,
+    include_noarch: bool,
+) -> Result<Vec<Platform>, GatewayError> {
+    let mut seen = HashSet::new();
+    let mut normalized = Vec::with_capacity(platforms.len() + 1);
+    for platform in platforms {
+        if platform == Platform::Unknown {
+            return Err(GatewayError::UnsupportedPlatform(platform));
+        }
+        if seen.insert(platform) {
+            normalized.push(platform);
+        }
+    }
+    if include_noarch && seen.insert(Platform::NoArch) {
+        normalized.push(Platform::NoArch);
+    }
+    Ok(normalized)
+}
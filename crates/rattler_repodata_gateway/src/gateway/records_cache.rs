@@ -0,0 +1,353 @@
+//! A second level on-disk cache that stores parsed [`RepoDataRecord`]s per
+//! package name. This avoids having to re-parse the (potentially very large)
+//! `repodata.json` file after a process restart.
+//!
+//! The cache is stored in a directory per subdirectory. The directory contains
+//! a key file that identifies the version of the upstream repodata the records
+//! were parsed from (e.g. the `ETag`). When the upstream repodata changes the
+//! key no longer matches and the whole directory is invalidated.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use rattler_conda_types::{PackageName, PackageRecord, RepoDataRecord};
+use serde::{Deserialize, Serialize};
+use simple_spawn_blocking::tokio::run_blocking_task;
+use url::Url;
+
+use crate::{fetch::RepoDataState, utils::url_to_cache_filename, GatewayError};
+
+/// The version of the format of the cache. Increment this whenever the format
+/// of the stored records changes.
+const RECORDS_CACHE_VERSION: u32 = 2;
+
+/// The name of the file that stores the key of the cache.
+const CACHE_KEY_FILENAME: &str = ".cache-key.json";
+
+/// The name of the directory that contains all parsed record caches.
+const RECORDS_CACHE_DIR: &str = "records-v1";
+
+/// Identifies the upstream repodata the cached records were parsed from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheKey {
+    version: u32,
+    key: String,
+}
+
+/// The on-disk representation of a single record.
+#[derive(Serialize, Deserialize)]
+struct CachedRecord {
+    package_record: PackageRecord,
+    file_name: String,
+    url: Url,
+    channel: String,
+}
+
+/// An on-disk cache of parsed records for a single subdirectory.
+pub struct RecordsCache {
+    cache_dir: PathBuf,
+}
+
+impl RecordsCache {
+    /// Opens the records cache for the subdirectory described by the given
+    /// repodata state. If the cached records were parsed from a different
+    /// version of the repodata the cache is cleared.
+    ///
+    /// `patch_hash` identifies the patch instructions that were applied to the
+    /// records, if any.
+    ///
+    /// Returns `None` if the repodata state does not contain enough information
+    /// to uniquely identify the upstream repodata.
+    pub async fn open(
+        cache_dir: &Path,
+        subdir_url: &Url,
+        state: &RepoDataState,
+        patch_hash: Option<&str>,
+    ) -> Result<Option<Self>, GatewayError> {
+        let Some(mut key) = cache_key(state) else {
+            return Ok(None);
+        };
+        if let Some(patch_hash) = patch_hash {
+            key = format!("{key}:patches:{patch_hash}");
+        }
+
+        let cache_dir = cache_dir
+            .join(RECORDS_CACHE_DIR)
+            .join(url_to_cache_filename(subdir_url));
+        run_blocking_task(move || {
+            let key = CacheKey {
+                version: RECORDS_CACHE_VERSION,
+                key,
+            };
+            let key_path = cache_dir.join(CACHE_KEY_FILENAME);
+            let existing_key = std::fs::read(&key_path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<CacheKey>(&bytes).ok());
+
+            if existing_key.as_ref() != Some(&key) {
+                if existing_key.is_some() {
+                    tracing::debug!(
+                        "parsed records cache at {} is outdated, removing",
+                        cache_dir.display()
+                    );
+                }
+                match std::fs::remove_dir_all(&cache_dir) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(GatewayError::IoError(
+                            format!(
+                                "failed to remove outdated records cache at {}",
+                                cache_dir.display()
+                            ),
+                            e,
+                        ))
+                    }
+                }
+                std::fs::create_dir_all(&cache_dir).map_err(|e| {
+                    GatewayError::IoError(
+                        format!(
+                            "failed to create records cache directory at {}",
+                            cache_dir.display()
+                        ),
+                        e,
+                    )
+                })?;
+                let key_bytes = serde_json::to_vec(&key).expect("failed to serialize cache key");
+                write_atomically(&key_path, &key_bytes)?;
+            }
+
+            Ok(Some(Self { cache_dir }))
+        })
+        .await
+    }
+
+    /// Returns the records for the given package from the cache or `None` if
+    /// the records have not been cached yet.
+    pub async fn read(&self, name: &PackageName) -> Option<Vec<RepoDataRecord>> {
+        let path = self.record_path(name);
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                tracing::warn!("failed to read cached records from {}: {e}", path.display());
+                return None;
+            }
+        };
+
+        let result = run_blocking_task(move || {
+            rmp_serde::from_slice::<Vec<CachedRecord>>(&bytes).map_err(|e| {
+                GatewayError::IoError(
+                    "failed to parse cached records".to_string(),
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()),
+                )
+            })
+        })
+        .await;
+
+        match result {
+            Ok(records) => Some(
+                records
+                    .into_iter()
+                    .map(|record| RepoDataRecord {
+                        package_record: record.package_record,
+                        file_name: record.file_name,
+                        url: record.url,
+                        channel: record.channel,
+                    })
+                    .collect(),
+            ),
+            Err(e) => {
+                tracing::warn!(
+                    "the cached records at {} are corrupted: {e}",
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Stores the records of a package in the cache.
+    pub async fn write(
+        &self,
+        name: &PackageName,
+        records: &[RepoDataRecord],
+    ) -> Result<(), GatewayError> {
+        let path = self.record_path(name);
+        let records = records
+            .iter()
+            .map(|record| CachedRecord {
+                package_record: record.package_record.clone(),
+                file_name: record.file_name.clone(),
+                url: record.url.clone(),
+                channel: record.channel.clone(),
+            })
+            .collect::<Vec<_>>();
+        run_blocking_task(move || {
+            // Records are stored with their field names because `PackageRecord` skips
+            // fields that are `None`, which would shift the fields of a positional
+            // encoding.
+            let bytes = rmp_serde::to_vec_named(&records).map_err(|e| {
+                GatewayError::IoError(
+                    "failed to serialize records".to_string(),
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()),
+                )
+            })?;
+            write_atomically(&path, &bytes)
+        })
+        .await
+    }
+
+    fn record_path(&self, name: &PackageName) -> PathBuf {
+        self.cache_dir
+            .join(format!("{}.msgpack", name.as_normalized()))
+    }
+}
+
+/// Determines a key that uniquely identifies the upstream repodata.
+fn cache_key(state: &RepoDataState) -> Option<String> {
+    if let Some(etag) = &state.cache_headers.etag {
+        return Some(format!("etag:{etag}"));
+    }
+    if let Some(hash) = &state.blake2_hash {
+        return Some(format!("blake2:{hash:x}"));
+    }
+    state
+        .cache_headers
+        .last_modified
+        .as_ref()
+        .map(|last_modified| format!("last-modified:{last_modified}:{}", state.cache_size))
+}
+
+/// Atomically writes bytes to a file by writing to a temporary file first.
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<(), GatewayError> {
+    let parent = path.parent().expect("file path must have a parent");
+    let mut temp_file = tempfile::Builder::new().tempfile_in(parent).map_err(|e| {
+        GatewayError::IoError(
+            format!("failed to create temporary file in {}", parent.display()),
+            e,
+        )
+    })?;
+    temp_file.write_all(bytes).map_err(|e| {
+        GatewayError::IoError(
+            format!("failed to write temporary file in {}", parent.display()),
+            e,
+        )
+    })?;
+    temp_file.persist(path).map_err(|e| {
+        GatewayError::IoError(format!("failed to persist {}", path.display()), e.error)
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{str::FromStr, time::SystemTime};
+
+    use rattler_conda_types::{PackageName, PackageRecord, RepoDataRecord, Version};
+    use rattler_digest::{parse_digest_from_hex, Sha256};
+    use url::Url;
+
+    use super::RecordsCache;
+    use crate::fetch::{CacheHeaders, RepoDataState};
+
+    fn state_with_etag(etag: &str) -> RepoDataState {
+        RepoDataState {
+            url: Url::parse("https://example.com/channel/noarch/repodata.json").unwrap(),
+            cache_headers: CacheHeaders {
+                etag: Some(etag.to_string()),
+                last_modified: None,
+                cache_control: None,
+            },
+            cache_last_modified: SystemTime::now(),
+            cache_size: 0,
+            blake2_hash: None,
+            blake2_hash_nominal: None,
+            has_zst: None,
+            has_bz2: None,
+            has_jlap: None,
+            jlap: None,
+            content_trust: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_cache_invalidation() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let subdir_url = Url::parse("https://example.com/channel/noarch/").unwrap();
+        let name = PackageName::from_str("foo").unwrap();
+        let record = RepoDataRecord {
+            package_record: PackageRecord::new(
+                name.clone(),
+                Version::from_str("1.0").unwrap(),
+                "0".to_string(),
+            ),
+            file_name: "foo-1.0-0.tar.bz2".to_string(),
+            url: subdir_url.join("foo-1.0-0.tar.bz2").unwrap(),
+            channel: "channel".to_string(),
+        };
+
+        // Write the record and read it back
+        let cache = RecordsCache::open(cache_dir.path(), &subdir_url, &state_with_etag("a"), None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(cache.read(&name).await.is_none());
+        cache.write(&name, &[record.clone()]).await.unwrap();
+        let records = cache.read(&name).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].file_name, record.file_name);
+        assert_eq!(records[0].package_record, record.package_record);
+
+        // Opening with the same etag keeps the cache.
+        let cache = RecordsCache::open(cache_dir.path(), &subdir_url, &state_with_etag("a"), None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(cache.read(&name).await.is_some());
+
+        // Opening with a different etag invalidates the cache.
+        let cache = RecordsCache::open(cache_dir.path(), &subdir_url, &state_with_etag("b"), None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(cache.read(&name).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_records_cache_roundtrip() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let subdir_url = Url::parse("https://example.com/channel/noarch/").unwrap();
+        let name = PackageName::from_str("foo").unwrap();
+
+        // A record where some optional fields are set and others are not, the
+        // fields that are skipped must not shift the fields that follow them.
+        let mut package_record = PackageRecord::new(
+            name.clone(),
+            Version::from_str("1.0").unwrap(),
+            "0".to_string(),
+        );
+        package_record.license = Some("MIT".to_string());
+        package_record.sha256 = parse_digest_from_hex::<Sha256>(
+            "0101010101010101010101010101010101010101010101010101010101010101",
+        );
+        package_record.md5 = None;
+        package_record.timestamp = None;
+        package_record.depends = vec!["bar >=1".to_string()];
+        let record = RepoDataRecord {
+            package_record,
+            file_name: "foo-1.0-0.tar.bz2".to_string(),
+            url: subdir_url.join("foo-1.0-0.tar.bz2").unwrap(),
+            channel: "channel".to_string(),
+        };
+
+        let cache = RecordsCache::open(cache_dir.path(), &subdir_url, &state_with_etag("a"), None)
+            .await
+            .unwrap()
+            .unwrap();
+        cache.write(&name, &[record.clone()]).await.unwrap();
+        assert_eq!(cache.read(&name).await.unwrap(), vec![record]);
+    }
+}
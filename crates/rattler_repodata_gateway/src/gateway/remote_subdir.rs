@@ -1,4 +1,8 @@
-use super::{local_subdir::LocalSubdirClient, GatewayError, SourceConfig};
+use super::{
+    local_subdir::LocalSubdirClient, metrics::GatewayMetrics,
+    patch_instructions::SubdirPatchInstructions, records_cache::RecordsCache, GatewayError,
+    SourceConfig,
+};
 use crate::fetch::{fetch_repo_data, FetchRepoDataError, FetchRepoDataOptions, Variant};
 use crate::gateway::error::SubdirNotFoundError;
 use crate::gateway::subdir::SubdirClient;
@@ -9,6 +13,9 @@ use std::{path::PathBuf, sync::Arc};
 
 pub struct RemoteSubdirClient {
     sparse: LocalSubdirClient,
+    records_cache: Option<RecordsCache>,
+    patch_instructions: Option<SubdirPatchInstructions>,
+    repodata_hash: Option<String>,
 }
 
 impl RemoteSubdirClient {
@@ -18,21 +25,26 @@ impl RemoteSubdirClient {
         client: ClientWithMiddleware,
         cache_dir: PathBuf,
         source_config: SourceConfig,
+        metrics: Option<Arc<dyn GatewayMetrics>>,
         reporter: Option<Arc<dyn Reporter>>,
     ) -> Result<Self, GatewayError> {
         let subdir_url = channel.platform_url(platform);
 
         // Fetch the repodata from the remote server
         let repodata = fetch_repo_data(
-            subdir_url,
-            client,
-            cache_dir,
+            subdir_url.clone(),
+            client.clone(),
+            cache_dir.clone(),
             FetchRepoDataOptions {
                 cache_action: source_config.cache_action,
                 variant: Variant::default(),
                 jlap_enabled: source_config.jlap_enabled,
+                jlap_max_patches: source_config.jlap_max_patches,
                 zstd_enabled: source_config.zstd_enabled,
                 bz2_enabled: source_config.bz2_enabled,
+                refresh_policy: source_config.refresh_policy,
+                signature_policy: source_config.signature_policy,
+                trusted_root: source_config.trusted_root.clone(),
             },
             reporter,
         )
@@ -48,6 +60,13 @@ impl RemoteSubdirClient {
             e => GatewayError::FetchRepoDataError(e),
         })?;
 
+        if let Some(metrics) = &metrics {
+            metrics.on_subdir_fetched(&subdir_url, repodata.cache_result);
+            if let Some(success) = repodata.jlap_patched {
+                metrics.on_jlap_patch(&subdir_url, success);
+            }
+        }
+
         // Create a new sparse repodata client that can be used to read records from the repodata.
         let sparse = LocalSubdirClient::from_channel_subdir(
             &repodata.repo_data_json_path,
@@ -56,7 +75,58 @@ impl RemoteSubdirClient {
         )
         .await?;
 
-        Ok(Self { sparse })
+        // Fetch the instructions to hotfix the repodata if enabled.
+        let patch_instructions = if source_config.patch_instructions_enabled {
+            SubdirPatchInstructions::fetch(
+                &client,
+                &subdir_url,
+                &cache_dir,
+                source_config.cache_action,
+            )
+            .await?
+        } else {
+            None
+        };
+
+        // Open the cache of previously parsed records if enabled. The records
+        // are stored after patching so the cache also depends on the patches.
+        let records_cache = if source_config.parsed_records_cache_enabled {
+            RecordsCache::open(
+                &cache_dir,
+                &subdir_url,
+                &repodata.cache_state,
+                patch_instructions
+                    .as_ref()
+                    .map(SubdirPatchInstructions::hash),
+            )
+            .await?
+        } else {
+            None
+        };
+
+        Ok(Self {
+            sparse,
+            records_cache,
+            patch_instructions,
+            repodata_hash: repodata
+                .cache_state
+                .blake2_hash
+                .map(|hash| format!("{hash:x}")),
+        })
+    }
+
+    /// Reads the records of a package from the repodata and applies the patch
+    /// instructions of the subdirectory, if any.
+    async fn read_patched_records(
+        &self,
+        name: &PackageName,
+        reporter: Option<&dyn Reporter>,
+    ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
+        let records = self.sparse.fetch_package_records(name, reporter).await?;
+        Ok(match &self.patch_instructions {
+            Some(patch_instructions) => patch_instructions.apply(records.to_vec()).into(),
+            None => records,
+        })
     }
 }
 
@@ -67,6 +137,30 @@ impl SubdirClient for RemoteSubdirClient {
         name: &PackageName,
         reporter: Option<&dyn Reporter>,
     ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
-        self.sparse.fetch_package_records(name, reporter).await
+        let Some(records_cache) = &self.records_cache else {
+            return self.read_patched_records(name, reporter).await;
+        };
+
+        if let Some(records) = records_cache.read(name).await {
+            return Ok(records.into());
+        }
+
+        let records = self.read_patched_records(name, reporter).await?;
+        if let Err(e) = records_cache.write(name, &records).await {
+            tracing::warn!("failed to write parsed records to cache: {e}");
+        }
+        Ok(records)
+    }
+
+    fn package_names(&self) -> Vec<PackageName> {
+        self.sparse.package_names()
+    }
+
+    fn revision(&self) -> Option<String> {
+        let repodata_hash = self.repodata_hash.as_ref()?;
+        Some(match &self.patch_instructions {
+            Some(patch_instructions) => format!("{repodata_hash}-{}", patch_instructions.hash()),
+            None => repodata_hash.clone(),
+        })
     }
 }
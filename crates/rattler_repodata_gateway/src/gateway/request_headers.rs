@@ -0,0 +1,123 @@
+//! Adds the headers that are configured for a channel to its requests.
+
+use http::Extensions;
+use reqwest::{
+    header::{HeaderMap, USER_AGENT},
+    Request, Response,
+};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+
+use super::SourceConfig;
+
+/// A middleware that adds headers to every request, replacing headers with
+/// the same name.
+struct RequestHeadersMiddleware {
+    headers: HeaderMap,
+}
+
+#[async_trait::async_trait]
+impl Middleware for RequestHeadersMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let headers = req.headers_mut();
+        for name in self.headers.keys() {
+            headers.remove(name);
+        }
+        for (name, value) in &self.headers {
+            headers.append(name, value.clone());
+        }
+        next.run(req, extensions).await
+    }
+}
+
+/// Returns a client that adds the headers and the user-agent of the source
+/// configuration to its requests. The client is returned unchanged if the
+/// configuration does not specify any headers.
+pub(crate) fn client_for_source(
+    client: &ClientWithMiddleware,
+    source_config: &SourceConfig,
+) -> ClientWithMiddleware {
+    let mut headers = source_config.headers.clone();
+    if let Some(user_agent) = &source_config.user_agent {
+        headers.insert(USER_AGENT, user_agent.clone());
+    }
+    if headers.is_empty() {
+        return client.clone();
+    }
+
+    // The middleware is added after the existing middleware so the headers
+    // also apply to requests that are modified by them, e.g. by mirrors.
+    ClientBuilder::from_client(client.clone())
+        .with(RequestHeadersMiddleware { headers })
+        .build()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use http::Extensions;
+    use reqwest::{
+        header::{HeaderMap, HeaderValue, USER_AGENT},
+        Request, Response,
+    };
+    use reqwest_middleware::{ClientBuilder, Middleware, Next};
+
+    use super::client_for_source;
+    use crate::gateway::SourceConfig;
+
+    /// Captures the headers of a request instead of sending it.
+    #[derive(Default, Clone)]
+    struct CaptureHeaders(Arc<Mutex<Option<HeaderMap>>>);
+
+    #[async_trait::async_trait]
+    impl Middleware for CaptureHeaders {
+        async fn handle(
+            &self,
+            req: Request,
+            _extensions: &mut Extensions,
+            _next: Next<'_>,
+        ) -> reqwest_middleware::Result<Response> {
+            *self.0.lock().unwrap() = Some(req.headers().clone());
+            Ok(http::Response::builder()
+                .status(200)
+                .body("")
+                .unwrap()
+                .into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_source_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-trace-id", HeaderValue::from_static("1234"));
+        headers.insert("x-cdn-token", HeaderValue::from_static("token"));
+        let source_config = SourceConfig {
+            headers,
+            user_agent: Some(HeaderValue::from_static("my-tool/1.0")),
+            ..SourceConfig::default()
+        };
+
+        let capture = CaptureHeaders::default();
+        let client = ClientBuilder::new(reqwest::Client::new()).build();
+        let client = ClientBuilder::from_client(client_for_source(&client, &source_config))
+            .with(capture.clone())
+            .build();
+
+        client
+            .get("https://example.com/linux-64/repodata.json")
+            .header("x-trace-id", "replaced")
+            .send()
+            .await
+            .unwrap();
+
+        let headers = capture.0.lock().unwrap().take().unwrap();
+        assert_eq!(headers["x-trace-id"], "1234");
+        assert_eq!(headers["x-cdn-token"], "token");
+        assert_eq!(headers[USER_AGENT], "my-tool/1.0");
+    }
+}
@@ -1,9 +1,10 @@
 use super::{token::TokenClient, ShardedRepodata};
+use crate::fetch::{CacheRefreshPolicy, CacheResult};
 use crate::reporter::ResponseReporterExt;
 use crate::{utils::url_to_cache_filename, GatewayError, Reporter};
 use bytes::Bytes;
 use futures::{FutureExt, TryFutureExt};
-use http::{HeaderMap, Method, Uri};
+use http::{header::CACHE_CONTROL, HeaderMap, HeaderValue, Method, Uri};
 use http_cache_semantics::{AfterResponse, BeforeRequest, CachePolicy, RequestLike};
 use reqwest::Response;
 use reqwest_middleware::ClientWithMiddleware;
@@ -21,15 +22,17 @@ const MAGIC_NUMBER: &[u8] = b"SHARD-CACHE-V1";
 
 const REPODATA_SHARDS_FILENAME: &str = "repodata_shards.msgpack.zst";
 
-// Fetches the shard index from the url or read it from the cache.
+// Fetches the shard index from the url or read it from the cache. Also returns
+// how the cache was used.
 pub async fn fetch_index(
     client: ClientWithMiddleware,
     channel_base_url: &Url,
     token_client: &TokenClient,
     cache_dir: &Path,
     concurrent_requests_semaphore: Arc<tokio::sync::Semaphore>,
+    refresh_policy: CacheRefreshPolicy,
     reporter: Option<&dyn Reporter>,
-) -> Result<ShardedRepodata, GatewayError> {
+) -> Result<(ShardedRepodata, CacheResult), GatewayError> {
     async fn from_response(
         cache_path: &Path,
         policy: CachePolicy,
@@ -124,22 +127,31 @@ pub async fn fetch_index(
 
     let canonical_request = SimpleRequest::get(&canonical_shards_url);
 
+    // The request that is used to determine whether the cached index can be used.
+    let cache_request =
+        SimpleRequest::get(&canonical_shards_url).with_refresh_policy(refresh_policy);
+
     // Try reading the cached file
     if let Ok((cache_header, file)) = read_cached_index(&cache_path).await {
-        match cache_header
-            .policy
-            .before_request(&canonical_request, SystemTime::now())
-        {
-            BeforeRequest::Fresh(_) => {
+        match (
+            refresh_policy,
+            cache_header
+                .policy
+                .before_request(&cache_request, SystemTime::now()),
+        ) {
+            (CacheRefreshPolicy::Immutable, _) | (_, BeforeRequest::Fresh(_)) => {
                 if let Ok(shard_index) = read_shard_index_from_reader(file).await {
                     tracing::debug!("shard index cache hit");
-                    return Ok(shard_index);
+                    return Ok((shard_index, CacheResult::CacheHit));
                 }
             }
-            BeforeRequest::Stale {
-                request: state_request,
-                ..
-            } => {
+            (
+                _,
+                BeforeRequest::Stale {
+                    request: state_request,
+                    ..
+                },
+            ) => {
                 // Get the token from the token client
                 let token = token_client.get_token(reporter).await?;
 
@@ -177,7 +189,7 @@ pub async fn fetch_index(
                             Ok(shard_index) => {
                                 tracing::debug!("shard index cache was not modified");
                                 // If reading the file failed for some reason we'll just fetch it again.
-                                return Ok(shard_index);
+                                return Ok((shard_index, CacheResult::CacheHitAfterFetch));
                             }
                             Err(e) => {
                                 tracing::warn!("the cached shard index has been corrupted: {e}");
@@ -193,7 +205,8 @@ pub async fn fetch_index(
 
                         tracing::debug!("shard index cache has become stale");
                         return from_response(&cache_path, policy, response, download_reporter)
-                            .await;
+                            .await
+                            .map(|shard_index| (shard_index, CacheResult::CacheOutdated));
                     }
                 }
             }
@@ -234,7 +247,9 @@ pub async fn fetch_index(
         .await?;
 
     let policy = CachePolicy::new(&canonical_request, &response);
-    from_response(&cache_path, policy, response, reporter).await
+    from_response(&cache_path, policy, response, reporter)
+        .await
+        .map(|shard_index| (shard_index, CacheResult::CacheNotPresent))
 }
 
 /// Writes the shard index cache to disk.
@@ -340,6 +355,24 @@ impl SimpleRequest {
             headers: HeaderMap::default(),
         }
     }
+
+    /// Adds the request cache-control directives that correspond with the
+    /// given refresh policy. These are taken into account when determining
+    /// whether a cached response can be used.
+    pub fn with_refresh_policy(mut self, refresh_policy: CacheRefreshPolicy) -> Self {
+        let directive = match refresh_policy {
+            CacheRefreshPolicy::FollowServer | CacheRefreshPolicy::Immutable => return self,
+            CacheRefreshPolicy::MaxStaleness(max_staleness) => {
+                format!("max-stale={}", max_staleness.as_secs())
+            }
+            CacheRefreshPolicy::ForceRevalidate => String::from("no-cache"),
+        };
+        self.headers.insert(
+            CACHE_CONTROL,
+            HeaderValue::from_str(&directive).expect("cache-control directive is a valid header"),
+        );
+        self
+    }
 }
 
 impl RequestLike for SimpleRequest {
@@ -1,15 +1,23 @@
-use std::{borrow::Cow, io::Write, path::PathBuf, sync::Arc};
+use std::{
+    borrow::Cow,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use http::{header::CACHE_CONTROL, HeaderValue, StatusCode};
-use rattler_conda_types::{Channel, PackageName, RepoDataRecord, Shard, ShardedRepodata};
+use rattler_conda_types::{
+    Channel, PackageName, RepoDataRecord, Shard, ShardDictionary, ShardedRepodata,
+};
+use rattler_digest::{compute_bytes_digest, Sha256};
 use reqwest_middleware::ClientWithMiddleware;
 use simple_spawn_blocking::tokio::run_blocking_task;
 use token::TokenClient;
 use url::Url;
 
 use crate::{
-    fetch::FetchRepoDataError,
-    gateway::{error::SubdirNotFoundError, subdir::SubdirClient},
+    fetch::{CacheRefreshPolicy, FetchRepoDataError},
+    gateway::{error::SubdirNotFoundError, metrics::GatewayMetrics, subdir::SubdirClient},
     reporter::ResponseReporterExt,
     GatewayError, Reporter,
 };
@@ -26,6 +34,10 @@ pub struct ShardedSubdir {
     sharded_repodata: ShardedRepodata,
     cache_dir: PathBuf,
     concurrent_requests_semaphore: Arc<tokio::sync::Semaphore>,
+    metrics: Option<Arc<dyn GatewayMetrics>>,
+
+    /// The zstd dictionary that was used to compress the shards, if any.
+    dictionary: Option<Arc<[u8]>>,
 }
 
 impl ShardedSubdir {
@@ -35,6 +47,8 @@ impl ShardedSubdir {
         client: ClientWithMiddleware,
         cache_dir: PathBuf,
         concurrent_requests_semaphore: Arc<tokio::sync::Semaphore>,
+        refresh_policy: CacheRefreshPolicy,
+        metrics: Option<Arc<dyn GatewayMetrics>>,
         reporter: Option<&dyn Reporter>,
     ) -> Result<Self, GatewayError> {
         // Construct the base url for the shards (e.g. `<channel>/<subdir>`).
@@ -50,12 +64,13 @@ impl ShardedSubdir {
         );
 
         // Fetch the shard index
-        let sharded_repodata = index::fetch_index(
+        let (sharded_repodata, cache_result) = index::fetch_index(
             client.clone(),
             &index_base_url,
             &token_client,
             &cache_dir,
             concurrent_requests_semaphore.clone(),
+            refresh_policy,
             reporter,
         )
         .await
@@ -70,6 +85,10 @@ impl ShardedSubdir {
             e => e,
         })?;
 
+        if let Some(metrics) = &metrics {
+            metrics.on_subdir_fetched(&index_base_url, cache_result);
+        }
+
         // Convert the URLs
         let shards_base_url = Url::options()
             .base_url(Some(&index_base_url))
@@ -96,6 +115,26 @@ impl ShardedSubdir {
             .await
             .map_err(FetchRepoDataError::IoError)?;
 
+        // If the shards are compressed with a dictionary, fetch it. Older indices
+        // do not specify a dictionary in which case the shards are decoded
+        // without one.
+        let dictionary = match &sharded_repodata.info.dictionary {
+            Some(dictionary) => Some(
+                fetch_dictionary(
+                    &client,
+                    &index_base_url,
+                    dictionary,
+                    &token_client,
+                    &cache_dir,
+                    &concurrent_requests_semaphore,
+                    reporter,
+                )
+                .await?
+                .into(),
+            ),
+            None => None,
+        };
+
         Ok(Self {
             channel,
             client,
@@ -105,6 +144,8 @@ impl ShardedSubdir {
             sharded_repodata,
             cache_dir,
             concurrent_requests_semaphore,
+            metrics,
+            dictionary,
         })
     }
 }
@@ -123,10 +164,18 @@ impl SubdirClient for ShardedSubdir {
 
         // Check if we already have the shard in the cache.
         let shard_cache_path = self.cache_dir.join(format!("{shard:x}.msgpack"));
+        let shard_url = self
+            .shards_base_url
+            .join(&format!("{shard:x}.msgpack.zst"))
+            .expect("invalid shard url");
 
         // Read the cached shard
         match tokio::fs::read(&shard_cache_path).await {
             Ok(cached_bytes) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.on_shard_fetched(&shard_url, true);
+                }
+
                 // Decode the cached shard
                 return parse_records(
                     cached_bytes,
@@ -146,11 +195,6 @@ impl SubdirClient for ShardedSubdir {
         let token = self.token_client.get_token(reporter).await?;
 
         // Download the shard
-        let shard_url = self
-            .shards_base_url
-            .join(&format!("{shard:x}.msgpack.zst"))
-            .expect("invalid shard url");
-
         let mut shard_request = self
             .client
             .get(shard_url.clone())
@@ -181,7 +225,16 @@ impl SubdirClient for ShardedSubdir {
             bytes
         };
 
-        let shard_bytes = decode_zst_bytes_async(shard_bytes).await?;
+        if let Some(metrics) = &self.metrics {
+            metrics.on_shard_fetched(&shard_url, false);
+        }
+
+        let shard_bytes = match &self.dictionary {
+            Some(dictionary) => {
+                decode_zst_bytes_with_dictionary_async(shard_bytes, dictionary.clone()).await?
+            }
+            None => decode_zst_bytes_async(shard_bytes).await?,
+        };
 
         // Create a future to write the cached bytes to disk
         let write_to_cache_fut = write_shard_to_cache(shard_cache_path, shard_bytes.clone());
@@ -198,6 +251,32 @@ impl SubdirClient for ShardedSubdir {
 
         Ok(records.into())
     }
+
+    fn package_names(&self) -> Vec<PackageName> {
+        self.sharded_repodata
+            .shards
+            .keys()
+            .filter_map(|name| PackageName::try_from(name.as_str()).ok())
+            .collect()
+    }
+
+    fn revision(&self) -> Option<String> {
+        // The shards are content addressed, the index identifies the records.
+        let mut shards = self
+            .sharded_repodata
+            .shards
+            .iter()
+            .map(|(name, hash)| format!("{name}={hash:x}\n"))
+            .collect::<Vec<_>>();
+        shards.sort();
+        let index = format!(
+            "{}\n{}\n{}",
+            self.shards_base_url,
+            self.package_base_url,
+            shards.concat()
+        );
+        Some(format!("{:x}", compute_bytes_digest::<Sha256>(index)))
+    }
 }
 
 /// Atomically writes the shard bytes to the cache.
@@ -261,6 +340,117 @@ async fn decode_zst_bytes_async<R: AsRef<[u8]> + Send + 'static>(
     .await
 }
 
+/// Decodes zstd compressed bytes that may have been compressed with the given
+/// dictionary. If decoding with the dictionary fails the bytes are decoded
+/// without it, servers are allowed to serve shards that were compressed without
+/// the dictionary.
+async fn decode_zst_bytes_with_dictionary_async<R: AsRef<[u8]> + Send + 'static>(
+    bytes: R,
+    dictionary: Arc<[u8]>,
+) -> Result<Vec<u8>, GatewayError> {
+    run_blocking_task(move || {
+        let decode_with_dictionary = || -> std::io::Result<Vec<u8>> {
+            let mut decoder =
+                zstd::stream::read::Decoder::with_dictionary(bytes.as_ref(), &dictionary)?;
+            let mut decoded = Vec::new();
+            decoder.read_to_end(&mut decoded)?;
+            Ok(decoded)
+        };
+
+        match decode_with_dictionary() {
+            Ok(decoded) => Ok(decoded),
+            Err(err) => {
+                tracing::debug!("failed to decode shard with dictionary, retrying without: {err}");
+                zstd::decode_all(bytes.as_ref()).map_err(|err| {
+                    GatewayError::IoError("failed to decode zstd shard".to_string(), err)
+                })
+            }
+        }
+    })
+    .await
+}
+
+/// Fetches the zstd dictionary that was used to compress the shards. The
+/// dictionary is cached on disk keyed by its hash.
+async fn fetch_dictionary(
+    client: &ClientWithMiddleware,
+    index_base_url: &Url,
+    dictionary: &ShardDictionary,
+    token_client: &TokenClient,
+    cache_dir: &Path,
+    concurrent_requests_semaphore: &tokio::sync::Semaphore,
+    reporter: Option<&dyn Reporter>,
+) -> Result<Vec<u8>, GatewayError> {
+    let cache_path = cache_dir.join(format!("{:x}.zst-dict", dictionary.sha256));
+
+    // Read the cached dictionary
+    match tokio::fs::read(&cache_path).await {
+        Ok(cached_bytes) if compute_bytes_digest::<Sha256>(&cached_bytes) == dictionary.sha256 => {
+            return Ok(cached_bytes);
+        }
+        Ok(_) => {
+            tracing::warn!(
+                "the cached shard dictionary at {} is corrupted",
+                cache_path.display()
+            );
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            // The file is missing from the cache, we need to download it.
+        }
+        Err(err) => return Err(FetchRepoDataError::IoError(err).into()),
+    }
+
+    let dictionary_url = Url::options()
+        .base_url(Some(index_base_url))
+        .parse(&dictionary.url)
+        .map_err(|_e| {
+            GatewayError::Generic(format!(
+                "shard index contains invalid dictionary url: {}",
+                &dictionary.url
+            ))
+        })?;
+
+    // Get the token
+    let token = token_client.get_token(reporter).await?;
+
+    let mut request = client
+        .get(dictionary_url.clone())
+        .build()
+        .expect("failed to build dictionary request");
+    token.add_to_headers(request.headers_mut());
+
+    let dictionary_bytes = {
+        let _permit = concurrent_requests_semaphore.acquire().await;
+        let reporter = reporter.map(|r| (r, r.on_download_start(&dictionary_url)));
+        let response = client
+            .execute(request)
+            .await
+            .and_then(|r| r.error_for_status().map_err(Into::into))
+            .map_err(FetchRepoDataError::from)?;
+
+        let bytes = response
+            .bytes_with_progress(reporter)
+            .await
+            .map_err(FetchRepoDataError::from)?;
+
+        if let Some((reporter, index)) = reporter {
+            reporter.on_download_complete(&dictionary_url, index);
+        }
+
+        bytes
+    };
+
+    if compute_bytes_digest::<Sha256>(&dictionary_bytes) != dictionary.sha256 {
+        return Err(GatewayError::Generic(format!(
+            "the shard dictionary at {dictionary_url} does not match the expected hash"
+        )));
+    }
+
+    write_shard_to_cache(cache_path, dictionary_bytes.clone()).await?;
+
+    Ok(dictionary_bytes)
+}
+
 async fn parse_records<R: AsRef<[u8]> + Send + 'static>(
     bytes: R,
     channel_name: String,
@@ -301,3 +491,35 @@ fn add_trailing_slash(url: &Url) -> Cow<'_, Url> {
         Cow::Owned(url)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::decode_zst_bytes_with_dictionary_async;
+
+    #[tokio::test]
+    async fn test_decode_with_dictionary() {
+        let dictionary: Arc<[u8]> = b"{\"depends\": [\"python >=3.8\"], \"license\": \"MIT\"}"
+            .repeat(16)
+            .into();
+        let content = b"{\"depends\": [\"python >=3.8\"], \"license\": \"BSD\"}".to_vec();
+
+        // Compressed with the dictionary
+        let compressed = zstd::bulk::Compressor::with_dictionary(3, &dictionary)
+            .unwrap()
+            .compress(&content)
+            .unwrap();
+        let decoded = decode_zst_bytes_with_dictionary_async(compressed, dictionary.clone())
+            .await
+            .unwrap();
+        assert_eq!(decoded, content);
+
+        // Compressed without the dictionary should still decode.
+        let compressed = zstd::encode_all(content.as_slice(), 3).unwrap();
+        let decoded = decode_zst_bytes_with_dictionary_async(compressed, dictionary)
+            .await
+            .unwrap();
+        assert_eq!(decoded, content);
+    }
+}
@@ -6,6 +6,7 @@ use http::header::CACHE_CONTROL;
 use http::HeaderValue;
 use itertools::Either;
 use parking_lot::Mutex;
+use rattler_redaction::DisplayRedacted;
 use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Serialize};
 use std::ops::Add;
@@ -87,7 +88,7 @@ impl TokenClient {
             .token_base_url
             .join("token")
             .expect("invalid token url");
-        tracing::debug!("fetching token from {}", &token_url);
+        tracing::debug!("fetching token from {}", token_url.display_redacted());
 
         // Fetch the token
         let token = {
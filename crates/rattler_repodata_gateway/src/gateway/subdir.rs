@@ -21,87 +21,140 @@ pub struct SubdirData {
     client: Arc<dyn SubdirClient>,
 
     /// Previously fetched or currently pending records.
-    records: DashMap<PackageName, PendingOrFetched<Arc<[RepoDataRecord]>>>,
+    records: DashMap<PackageName, PendingOrFetched<Arc<[RepoDataRecord]>, FetchResult>>,
 }
 
+/// The result of fetching the records of a package that is sent to the tasks
+/// that wait for it.
+type FetchResult = Result<Arc<[RepoDataRecord]>, Arc<GatewayError>>;
+
 impl SubdirData {
-    pub fn from_client<C: SubdirClient + 'static>(client: C) -> Self {
+    pub fn from_client(client: Arc<dyn SubdirClient>) -> Self {
         Self {
-            client: Arc::new(client),
+            client,
             records: DashMap::default(),
         }
     }
 
+    /// Returns the names of all the packages in the subdirectory.
+    pub fn package_names(&self) -> Vec<PackageName> {
+        self.client.package_names()
+    }
+
+    /// Returns a value that identifies the content of the subdirectory, see
+    /// [`SubdirClient::revision`].
+    pub fn revision(&self) -> Option<String> {
+        self.client.revision()
+    }
+
+    /// Returns the records of all the packages that have been fetched so far.
+    pub fn fetched_records(&self) -> Vec<(PackageName, Arc<[RepoDataRecord]>)> {
+        self.records
+            .iter()
+            .filter_map(|entry| match entry.value() {
+                PendingOrFetched::Fetched(records) => Some((entry.key().clone(), records.clone())),
+                PendingOrFetched::Pending(_) => None,
+            })
+            .collect()
+    }
+
+    /// Returns the records of the package with the given name, fetching them
+    /// if they have not been fetched before.
+    ///
+    /// Concurrent requests for the same package are coalesced: only a single
+    /// task fetches the records and all other tasks wait for the result. If
+    /// the fetch fails all waiting tasks receive the error. If the task that
+    /// fetches the records is cancelled before the records are available, one
+    /// of the waiting tasks takes over the fetch.
     pub async fn get_or_fetch_package_records(
         &self,
         name: &PackageName,
         reporter: Option<Arc<dyn Reporter>>,
     ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
-        let sender = match self.records.entry(name.clone()) {
-            Entry::Vacant(entry) => {
-                // Construct a sender so other tasks can subscribe
-                let (sender, _) = broadcast::channel(1);
-                let sender = Arc::new(sender);
+        loop {
+            let sender = match self.records.entry(name.clone()) {
+                Entry::Vacant(entry) => {
+                    // Construct a sender so other tasks can subscribe
+                    let (sender, _) = broadcast::channel(1);
+                    let sender = Arc::new(sender);
 
-                // Modify the current entry to the pending entry, this is an atomic operation
-                // because who holds the entry holds mutable access.
-                entry.insert(PendingOrFetched::Pending(Arc::downgrade(&sender)));
+                    // Modify the current entry to the pending entry, this is an atomic operation
+                    // because who holds the entry holds mutable access.
+                    entry.insert(PendingOrFetched::Pending(Arc::downgrade(&sender)));
 
-                sender
-            }
-            Entry::Occupied(mut entry) => {
-                let records = entry.get();
-                match records {
-                    PendingOrFetched::Pending(sender) => {
-                        let sender = sender.upgrade();
-
-                        if let Some(sender) = sender {
-                            // Create a receiver before we drop the entry. While we hold on to
-                            // the entry we have exclusive access to it, this means the task
-                            // currently fetching the package will not be able to store a value
-                            // until we drop the entry.
-                            // By creating the receiver here we ensure that we are subscribed
-                            // before the other tasks sends a value over the channel.
-                            let mut receiver = sender.subscribe();
-
-                            // Explicitly drop the entry, so we don't block any other tasks.
-                            drop(entry);
-
-                            // The sender is still active, so we can wait for the records to be
-                            // fetched.
-                            return match receiver.recv().await {
-                                Ok(records) => Ok(records),
-                                Err(_) => {
-                                    // If this happens the sender was dropped. We simply have to
-                                    // retry.
-                                    Err(GatewayError::IoError(
-                                        "a coalesced request failed".to_string(),
-                                        std::io::ErrorKind::Other.into(),
-                                    ))
+                    sender
+                }
+                Entry::Occupied(mut entry) => {
+                    let records = entry.get();
+                    match records {
+                        PendingOrFetched::Pending(sender) => {
+                            let sender = sender.upgrade();
+
+                            if let Some(sender) = sender {
+                                // Create a receiver before we drop the entry. While we hold on to
+                                // the entry we have exclusive access to it, this means the task
+                                // currently fetching the package will not be able to store a
+                                // value until we drop the entry.
+                                // By creating the receiver here we ensure that we are subscribed
+                                // before the other tasks sends a value over the channel.
+                                let mut receiver = sender.subscribe();
+
+                                // Explicitly drop the entry, so we don't block any other tasks.
+                                drop(entry);
+
+                                // The sender is still active, so we can wait for the records to
+                                // be fetched.
+                                match receiver.recv().await {
+                                    Ok(Ok(records)) => return Ok(records),
+                                    Ok(Err(err)) => {
+                                        return Err(GatewayError::CoalescedRequestFailed(err))
+                                    }
+                                    Err(_) => {
+                                        // If this happens the task that fetches the records
+                                        // was cancelled. We simply have to retry, either by
+                                        // waiting for another task or by fetching the records
+                                        // ourselves.
+                                        continue;
+                                    }
                                 }
-                            };
-                        } else {
-                            // Construct a sender so other tasks can subscribe
-                            let (sender, _) = broadcast::channel(1);
-                            let sender = Arc::new(sender);
+                            } else {
+                                // Construct a sender so other tasks can subscribe
+                                let (sender, _) = broadcast::channel(1);
+                                let sender = Arc::new(sender);
 
-                            // Modify the current entry to the pending entry, this is an atomic
-                            // operation because who holds the entry holds mutable access.
-                            entry.insert(PendingOrFetched::Pending(Arc::downgrade(&sender)));
+                                // Modify the current entry to the pending entry, this is an
+                                // atomic operation because who holds the entry holds mutable
+                                // access.
+                                entry.insert(PendingOrFetched::Pending(Arc::downgrade(&sender)));
 
-                            sender
+                                sender
+                            }
                         }
+                        PendingOrFetched::Fetched(records) => return Ok(records.clone()),
                     }
-                    PendingOrFetched::Fetched(records) => return Ok(records.clone()),
                 }
-            }
-        };
+            };
 
+            return self
+                .fetch_and_store_package_records(name, sender, reporter)
+                .await;
+        }
+    }
+
+    /// Fetches the records of a package for which the caller holds the
+    /// pending entry and sends them to all waiting tasks.
+    async fn fetch_and_store_package_records(
+        &self,
+        name: &PackageName,
+        sender: Arc<broadcast::Sender<FetchResult>>,
+        reporter: Option<Arc<dyn Reporter>>,
+    ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
         // At this point we have exclusive write access to this specific entry. All other tasks
         // will find a pending entry and will wait for the records to become available.
         //
-        // Let's start by fetching the records. If an error occurs we immediately return the error.
-        // This will drop the sender and all other waiting tasks will receive an error.
+        // Let's start by fetching the records. If an error occurs it is sent to all waiting tasks.
+        // If the fetch is cancelled, the sender is dropped without sending anything and one of
+        // the waiting tasks will retry the fetch.
         let records = match tokio::spawn({
             let client = self.client.clone();
             let name = name.clone();
@@ -115,7 +168,17 @@ impl SubdirData {
         .map_err(JoinError::try_into_panic)
         {
             Ok(Ok(records)) => records,
-            Ok(Err(err)) => return Err(err),
+            Ok(Err(GatewayError::Cancelled)) => return Err(GatewayError::Cancelled),
+            Ok(Err(err)) => {
+                // The pending entry is left in place, once the sender is dropped the next
+                // request fetches the records again.
+                let err = Arc::new(err);
+                let _ = sender.send(Err(err.clone()));
+                drop(sender);
+                return Err(
+                    Arc::try_unwrap(err).unwrap_or_else(GatewayError::CoalescedRequestFailed)
+                );
+            }
             Err(Ok(panic)) => std::panic::resume_unwind(panic),
             Err(Err(_)) => {
                 return Err(GatewayError::IoError(
@@ -131,7 +194,7 @@ impl SubdirData {
 
         // Send the records to all waiting tasks. We don't care if there are no receivers so we
         // drop the error.
-        let _ = sender.send(records.clone());
+        let _ = sender.send(Ok(records.clone()));
 
         Ok(records)
     }
@@ -146,4 +209,157 @@ pub trait SubdirClient: Send + Sync {
         name: &PackageName,
         reporter: Option<&dyn Reporter>,
     ) -> Result<Arc<[RepoDataRecord]>, GatewayError>;
+
+    /// Returns the names of all the packages in the channel subdirectory.
+    fn package_names(&self) -> Vec<PackageName>;
+
+    /// Returns a value that identifies the content of the channel
+    /// subdirectory, e.g. a hash of the repodata. Clients with the same
+    /// revision return the same records. Returns `None` if the content cannot
+    /// be identified.
+    fn revision(&self) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use assert_matches::assert_matches;
+    use rattler_conda_types::{PackageName, RepoDataRecord};
+    use tokio::sync::{Notify, Semaphore};
+
+    use super::{SubdirClient, SubdirData};
+    use crate::{GatewayError, Reporter};
+
+    /// A client that counts the number of times records are fetched. Every
+    /// fetch notifies `started` and only completes once the test adds a permit
+    /// to `release`.
+    struct ControlledClient {
+        fetches: AtomicUsize,
+        started: Notify,
+        release: Semaphore,
+        fail: bool,
+    }
+
+    impl ControlledClient {
+        fn new(fail: bool) -> Self {
+            Self {
+                fetches: AtomicUsize::new(0),
+                started: Notify::new(),
+                release: Semaphore::new(0),
+                fail,
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl SubdirClient for ControlledClient {
+        async fn fetch_package_records(
+            &self,
+            _name: &PackageName,
+            _reporter: Option<&dyn Reporter>,
+        ) -> Result<Arc<[RepoDataRecord]>, GatewayError> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            self.started.notify_one();
+            self.release.acquire().await.unwrap().forget();
+            if self.fail {
+                Err(GatewayError::Generic("failed to fetch".to_string()))
+            } else {
+                Ok(Arc::from(vec![]))
+            }
+        }
+
+        fn package_names(&self) -> Vec<PackageName> {
+            Vec::new()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_fetches_are_coalesced() {
+        let client = Arc::new(ControlledClient::new(false));
+        let subdir = SubdirData::from_client(client.clone());
+        let name = PackageName::new_unchecked("foo");
+
+        // All requests are waiting before the fetch is allowed to complete.
+        let requests = futures::future::join_all(
+            (0..10).map(|_| subdir.get_or_fetch_package_records(&name, None)),
+        );
+        let release = async {
+            client.started.notified().await;
+            client.release.add_permits(1);
+        };
+        let (results, ()) = tokio::join!(requests, release);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(client.fetches.load(Ordering::SeqCst), 1);
+
+        // Fetched records are reused.
+        subdir
+            .get_or_fetch_package_records(&name, None)
+            .await
+            .unwrap();
+        assert_eq!(client.fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_waiting_fetch_receives_error() {
+        let client = Arc::new(ControlledClient::new(true));
+        let subdir = SubdirData::from_client(client.clone());
+        let name = PackageName::new_unchecked("foo");
+
+        let requests = futures::future::join_all(
+            (0..3).map(|_| subdir.get_or_fetch_package_records(&name, None)),
+        );
+        let release = async {
+            client.started.notified().await;
+            client.release.add_permits(1);
+        };
+        let (results, ()) = tokio::join!(requests, release);
+
+        // The waiting requests receive the error instead of retrying.
+        assert_matches!(results[0], Err(GatewayError::Generic(_)));
+        for result in &results[1..] {
+            assert_matches!(result, Err(GatewayError::CoalescedRequestFailed(_)));
+        }
+        assert_eq!(client.fetches.load(Ordering::SeqCst), 1);
+
+        // A later request tries again.
+        client.release.add_permits(1);
+        assert!(subdir
+            .get_or_fetch_package_records(&name, None)
+            .await
+            .is_err());
+        assert_eq!(client.fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_waiting_fetch_retries_when_cancelled() {
+        let client = Arc::new(ControlledClient::new(false));
+        let subdir = Arc::new(SubdirData::from_client(client.clone()));
+        let name = PackageName::new_unchecked("foo");
+
+        // Start a request that is cancelled before the records are fetched.
+        let cancelled = tokio::spawn({
+            let subdir = subdir.clone();
+            let name = name.clone();
+            async move { subdir.get_or_fetch_package_records(&name, None).await }
+        });
+        client.started.notified().await;
+
+        // Start a request that waits for the first one.
+        let waiting = subdir.get_or_fetch_package_records(&name, None);
+        tokio::pin!(waiting);
+        assert!(futures::poll!(&mut waiting).is_pending());
+
+        // The waiting request should take over instead of failing.
+        cancelled.abort();
+        assert!(cancelled.await.unwrap_err().is_cancelled());
+        client.release.add_permits(2);
+        assert!(waiting.await.is_ok());
+        assert_eq!(client.fetches.load(Ordering::SeqCst), 2);
+    }
 }
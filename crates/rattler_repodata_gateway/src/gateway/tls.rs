@@ -0,0 +1,213 @@
+use std::{any::Any, path::Path, sync::Arc};
+
+use reqwest::{Certificate, Identity};
+
+type ConfigureFn = dyn Fn(reqwest::ClientBuilder) -> reqwest::ClientBuilder + Send + Sync;
+
+/// An error that can occur while reading certificates for a [`TlsConfig`].
+#[derive(Debug, thiserror::Error)]
+pub enum TlsConfigError {
+    /// Failed to read the certificate file.
+    #[error("failed to read {0}")]
+    Io(String, #[source] std::io::Error),
+
+    /// The certificate file does not contain valid PEM encoded certificates.
+    #[error("failed to parse the certificates in {0}")]
+    InvalidCertificate(String, #[source] reqwest::Error),
+}
+
+/// The TLS configuration of the client that a [`crate::Gateway`] constructs
+/// when no client is specified explicitly with
+/// [`super::GatewayBuilder::with_client`].
+///
+/// This is useful in environments where TLS traffic is intercepted by a
+/// proxy that uses a custom certificate authority, or where servers require
+/// client certificates.
+#[derive(Clone)]
+pub struct TlsConfig {
+    root_certificates: Vec<Certificate>,
+    built_in_root_certificates: bool,
+    identity: Option<Identity>,
+    accept_invalid_certificates: bool,
+    preconfigured: Option<Arc<ConfigureFn>>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            root_certificates: Vec::new(),
+            built_in_root_certificates: true,
+            identity: None,
+            accept_invalid_certificates: false,
+            preconfigured: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("root_certificates", &self.root_certificates.len())
+            .field(
+                "built_in_root_certificates",
+                &self.built_in_root_certificates,
+            )
+            .field("identity", &self.identity.is_some())
+            .field(
+                "accept_invalid_certificates",
+                &self.accept_invalid_certificates,
+            )
+            .field("preconfigured", &self.preconfigured.is_some())
+            .finish()
+    }
+}
+
+impl TlsConfig {
+    /// Constructs a new configuration that uses the default root
+    /// certificates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a certificate authority that is trusted in addition to the
+    /// built-in root certificates.
+    #[must_use]
+    pub fn with_root_certificate(mut self, certificate: Certificate) -> Self {
+        self.add_root_certificate(certificate);
+        self
+    }
+
+    /// Adds a certificate authority that is trusted in addition to the
+    /// built-in root certificates.
+    pub fn add_root_certificate(&mut self, certificate: Certificate) -> &mut Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Adds all the certificates in the PEM encoded CA bundle at the given
+    /// path as trusted certificate authorities. This is the format of the
+    /// file that `SSL_CERT_FILE` or conda's `ssl_verify` point to.
+    pub fn with_ca_bundle(mut self, path: impl AsRef<Path>) -> Result<Self, TlsConfigError> {
+        self.add_ca_bundle(path)?;
+        Ok(self)
+    }
+
+    /// Adds all the certificates in the PEM encoded CA bundle at the given
+    /// path as trusted certificate authorities.
+    pub fn add_ca_bundle(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, TlsConfigError> {
+        let path = path.as_ref();
+        let contents =
+            std::fs::read(path).map_err(|e| TlsConfigError::Io(path.display().to_string(), e))?;
+        let certificates = Certificate::from_pem_bundle(&contents)
+            .map_err(|e| TlsConfigError::InvalidCertificate(path.display().to_string(), e))?;
+        self.root_certificates.extend(certificates);
+        Ok(self)
+    }
+
+    /// Sets whether the built-in root certificates are trusted. Defaults to
+    /// `true`. Disable this to only trust the certificates that were added
+    /// explicitly.
+    #[must_use]
+    pub fn with_built_in_root_certificates(mut self, enabled: bool) -> Self {
+        self.set_built_in_root_certificates(enabled);
+        self
+    }
+
+    /// Sets whether the built-in root certificates are trusted.
+    pub fn set_built_in_root_certificates(&mut self, enabled: bool) -> &mut Self {
+        self.built_in_root_certificates = enabled;
+        self
+    }
+
+    /// Sets the client certificate that is presented to servers that require
+    /// client authentication. How the identity is constructed depends on the
+    /// TLS backend, e.g. `Identity::from_pem` for rustls or
+    /// `Identity::from_pkcs8_pem` for native-tls.
+    #[must_use]
+    pub fn with_client_identity(mut self, identity: Identity) -> Self {
+        self.set_client_identity(identity);
+        self
+    }
+
+    /// Sets the client certificate that is presented to servers that require
+    /// client authentication.
+    pub fn set_client_identity(&mut self, identity: Identity) -> &mut Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Disables the verification of server certificates.
+    ///
+    /// This is dangerous: any server can impersonate the channel. Only use
+    /// this as a last resort, prefer adding the certificate of the proxy with
+    /// [`Self::with_ca_bundle`] instead.
+    #[must_use]
+    pub fn with_accept_invalid_certificates(mut self, accept: bool) -> Self {
+        self.set_accept_invalid_certificates(accept);
+        self
+    }
+
+    /// Disables the verification of server certificates. See
+    /// [`Self::with_accept_invalid_certificates`].
+    pub fn set_accept_invalid_certificates(&mut self, accept: bool) -> &mut Self {
+        self.accept_invalid_certificates = accept;
+        self
+    }
+
+    /// Uses a fully configured TLS backend, e.g. a `rustls::ClientConfig` or
+    /// a `native_tls::TlsConnector`. The type must match the TLS backend
+    /// reqwest was compiled with, see
+    /// [`reqwest::ClientBuilder::use_preconfigured_tls`]. All other options
+    /// of this configuration are ignored by the backend in that case.
+    #[must_use]
+    pub fn with_preconfigured_tls<T: Any + Clone + Send + Sync>(mut self, tls: T) -> Self {
+        self.set_preconfigured_tls(tls);
+        self
+    }
+
+    /// Uses a fully configured TLS backend. See
+    /// [`Self::with_preconfigured_tls`].
+    pub fn set_preconfigured_tls<T: Any + Clone + Send + Sync>(&mut self, tls: T) -> &mut Self {
+        self.preconfigured = Some(Arc::new(move |builder: reqwest::ClientBuilder| {
+            builder.use_preconfigured_tls(tls.clone())
+        }));
+        self
+    }
+
+    /// Applies the configuration to a client builder.
+    pub fn apply(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        for certificate in &self.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        builder = builder.tls_built_in_root_certs(self.built_in_root_certificates);
+        if let Some(identity) = &self.identity {
+            builder = builder.identity(identity.clone());
+        }
+        if self.accept_invalid_certificates {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        if let Some(configure) = &self.preconfigured {
+            builder = configure(builder);
+        }
+        builder
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TlsConfig, TlsConfigError};
+
+    #[test]
+    fn test_tls_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.pem");
+
+        assert!(matches!(
+            TlsConfig::new().with_ca_bundle(&path),
+            Err(TlsConfigError::Io(..))
+        ));
+
+        let config = TlsConfig::new().with_built_in_root_certificates(true);
+        assert!(config.apply(reqwest::Client::builder()).build().is_ok());
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Weak},
+};
+
+use rattler_conda_types::{Channel, PackageName, Platform};
+use tokio::{sync::watch, time::MissedTickBehavior};
+
+use super::{subdir::Subdir, GatewayError, GatewayInner, PendingOrFetched};
+
+/// Describes the latest change to the records of a subdirectory that is
+/// watched with [`super::Gateway::watch`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepoDataUpdate {
+    /// The number of times the records changed since the subdirectory is
+    /// watched.
+    pub revision: u64,
+
+    /// The names of the packages whose records changed in the latest update.
+    /// This includes packages that were added to the subdirectory.
+    pub changed_packages: Vec<PackageName>,
+}
+
+/// Revalidates the repodata of a subdirectory until the gateway or all the
+/// receivers are dropped.
+pub(crate) async fn watch_subdir(
+    gateway: Weak<GatewayInner>,
+    channel: Channel,
+    platform: Platform,
+    sender: watch::Sender<RepoDataUpdate>,
+) {
+    let Some(period) = gateway
+        .upgrade()
+        .map(|inner| inner.channel_config.get(&channel).refresh_interval)
+    else {
+        return;
+    };
+
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut current = None;
+    loop {
+        tokio::select! {
+            () = sender.closed() => return,
+            _ = interval.tick() => {}
+        }
+
+        let Some(inner) = gateway.upgrade() else {
+            return;
+        };
+
+        let Some(previous) = current.take() else {
+            // The first tick completes immediately, use the subdir that is
+            // already known to the gateway as the starting point.
+            match inner
+                .get_or_create_subdir(&channel, platform, inner.reporter(None))
+                .await
+            {
+                Ok(subdir) => current = Some(subdir),
+                Err(err) => tracing::warn!(
+                    "failed to fetch {}/{platform}: {err}",
+                    channel.canonical_name()
+                ),
+            }
+            continue;
+        };
+
+        match refresh_subdir(&inner, &channel, platform, &previous).await {
+            Ok((subdir, changed_packages)) => {
+                if !changed_packages.is_empty() {
+                    tracing::debug!(
+                        "the records of {} packages in {}/{platform} changed",
+                        changed_packages.len(),
+                        channel.canonical_name()
+                    );
+                    sender.send_modify(|update| {
+                        update.revision += 1;
+                        update.changed_packages = changed_packages;
+                    });
+                }
+                current = Some(subdir);
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "failed to refresh {}/{platform}: {err}",
+                    channel.canonical_name()
+                );
+                current = Some(previous);
+            }
+        }
+    }
+}
+
+/// Fetches the subdirectory again and replaces it in the gateway. Returns the
+/// new subdirectory and the names of the packages that were added and of the
+/// previously fetched packages whose records changed.
+///
+/// If the content of the subdirectory did not change the previous
+/// subdirectory, including the records that were fetched from it, is kept.
+async fn refresh_subdir(
+    inner: &GatewayInner,
+    channel: &Channel,
+    platform: Platform,
+    previous: &Arc<Subdir>,
+) -> Result<(Arc<Subdir>, Vec<PackageName>), GatewayError> {
+    let reporter = inner.reporter(None);
+    let subdir = Arc::new(
+        inner
+            .create_subdir(channel, platform, reporter.clone())
+            .await?,
+    );
+
+    let mut changed_packages = Vec::new();
+    match (previous.as_ref(), subdir.as_ref()) {
+        (Subdir::NotFound, Subdir::NotFound) => return Ok((previous.clone(), changed_packages)),
+        (Subdir::Found(previous_data), Subdir::Found(subdir))
+            if previous_data
+                .revision()
+                .is_some_and(|revision| Some(revision) == subdir.revision()) =>
+        {
+            return Ok((previous.clone(), changed_packages));
+        }
+        (Subdir::Found(previous), subdir) => {
+            for (name, previous_records) in previous.fetched_records() {
+                let records = match subdir {
+                    Subdir::Found(subdir) => {
+                        subdir
+                            .get_or_fetch_package_records(&name, reporter.clone())
+                            .await?
+                    }
+                    Subdir::NotFound => Arc::from([]),
+                };
+                if records != previous_records {
+                    changed_packages.push(name);
+                }
+            }
+        }
+        (Subdir::NotFound, Subdir::Found(_)) => {}
+    }
+
+    // Packages that were added are changed as well.
+    if let Subdir::Found(subdir) = subdir.as_ref() {
+        let previous_names = match previous.as_ref() {
+            Subdir::Found(previous) => previous.package_names().into_iter().collect(),
+            Subdir::NotFound => HashSet::new(),
+        };
+        changed_packages.extend(
+            subdir
+                .package_names()
+                .into_iter()
+                .filter(|name| !previous_names.contains(name)),
+        );
+    }
+    changed_packages.sort();
+    changed_packages.dedup();
+
+    inner.subdirs.insert(
+        (channel.channel_url(), platform),
+        PendingOrFetched::Fetched(subdir.clone()),
+    );
+
+    Ok((subdir, changed_packages))
+}
@@ -64,6 +64,10 @@ pub mod fetch;
 mod reporter;
 #[cfg(feature = "sparse")]
 pub mod sparse;
+#[cfg(feature = "sparse")]
+pub mod synthetic_channel;
+#[cfg(feature = "gateway")]
+pub mod transport;
 mod utils;
 pub use reporter::Reporter;
 
@@ -72,5 +76,11 @@ mod gateway;
 
 #[cfg(feature = "gateway")]
 pub use gateway::{
-    ChannelConfig, Gateway, GatewayBuilder, GatewayError, RepoData, SourceConfig, SubdirSelection,
+    ChannelConfig, Gateway, GatewayBuilder, GatewayBuilderError, GatewayError, GatewayMetrics,
+    GatewayQuery, RecordFilter, RepoData, RepoDataUpdate, SourceConfig, SubdirSelection,
 };
+#[cfg(all(
+    feature = "gateway",
+    any(feature = "native-tls", feature = "rustls-tls")
+))]
+pub use gateway::{TlsConfig, TlsConfigError};
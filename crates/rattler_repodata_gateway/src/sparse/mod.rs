@@ -14,7 +14,7 @@ use bytes::Bytes;
 use futures::{stream, StreamExt, TryFutureExt, TryStreamExt};
 use itertools::Itertools;
 use rattler_conda_types::{
-    compute_package_url, Channel, ChannelInfo, PackageName, PackageRecord, RepoDataRecord,
+    compute_package_url, Channel, ChannelInfo, PackageName, PackageRecord, RepoData, RepoDataRecord,
 };
 use serde::{
     de::{Error, MapAccess, Visitor},
@@ -142,6 +142,22 @@ impl SparseRepoData {
         })
     }
 
+    /// Construct an instance of self from an in-memory [`RepoData`] and a
+    /// [`Channel`].
+    ///
+    /// The repodata is serialized so that records can be loaded lazily in the
+    /// same way as from a file. Use [`RepoData::slice`] beforehand to only
+    /// retain the records of a subset of the packages.
+    pub fn from_repo_data(
+        channel: Channel,
+        subdir: impl Into<String>,
+        repo_data: &RepoData,
+        patch_function: Option<fn(&mut PackageRecord)>,
+    ) -> Result<Self, serde_json::Error> {
+        let bytes = serde_json::to_vec(repo_data)?;
+        Self::from_bytes(channel, subdir, bytes.into(), patch_function)
+    }
+
     /// Returns an iterator over all package names in this repodata file.
     ///
     /// This works by iterating over all elements in the `packages` and
@@ -234,10 +250,11 @@ impl SparseRepoData {
 
                 // Iterate over all packages to find recursive dependencies.
                 for record in records.iter() {
-                    for dependency in &record.package_record.depends {
-                        let dependency_name = PackageName::new_unchecked(
-                            dependency.split_once(' ').unwrap_or((dependency, "")).0,
-                        );
+                    for dependency_name in record.package_record.dependency_names() {
+                        // Virtual packages are provided by the system, not by a channel.
+                        if dependency_name.is_virtual() {
+                            continue;
+                        }
                         if !seen.contains(&dependency_name) {
                             pending.push_back(dependency_name.clone());
                             seen.insert(dependency_name);
@@ -671,6 +688,75 @@ mod test {
         assert_eq!(total_records, 367596);
     }
 
+    #[test]
+    fn test_from_repo_data() {
+        let repo_data =
+            RepoData::from_path(test_dir().join("channels/dummy/linux-64/repodata.json")).unwrap();
+        let channel = Channel::from_str(
+            "dummy",
+            &ChannelConfig::default_with_root_dir(std::env::current_dir().unwrap()),
+        )
+        .unwrap();
+
+        let sliced = repo_data.slice([PackageName::new_unchecked("foobar")]);
+        let sparse = SparseRepoData::from_repo_data(channel, "linux-64", &sliced, None).unwrap();
+        assert_eq!(
+            sparse.package_names().sorted().collect::<Vec<_>>(),
+            ["bors", "foobar"]
+        );
+
+        let records = SparseRepoData::load_records_recursive(
+            [&sparse],
+            [PackageName::new_unchecked("foobar")],
+            None,
+        )
+        .unwrap();
+        assert_eq!(records[0].len(), 7);
+    }
+
+    #[test]
+    fn test_recursive_dependency_names() {
+        let repo_data: RepoData = serde_json::from_str(
+            r#"{
+                "info": { "subdir": "linux-64" },
+                "packages": {
+                    "foo-1.0-0.tar.bz2": {
+                        "name": "foo", "version": "1.0", "build": "0", "build_number": 0,
+                        "depends": ["bar>=1.0", "conda-forge::baz", "__glibc >=2.17"]
+                    },
+                    "bar-1.0-0.tar.bz2": { "name": "bar", "version": "1.0", "build": "0", "build_number": 0 },
+                    "baz-1.0-0.tar.bz2": { "name": "baz", "version": "1.0", "build": "0", "build_number": 0 },
+                    "__glibc-2.17-0.tar.bz2": { "name": "__glibc", "version": "2.17", "build": "0", "build_number": 0 }
+                }
+            }"#,
+        )
+        .unwrap();
+        let channel = Channel::from_str(
+            "dummy",
+            &ChannelConfig::default_with_root_dir(std::env::current_dir().unwrap()),
+        )
+        .unwrap();
+        let sparse = SparseRepoData::from_repo_data(channel, "linux-64", &repo_data, None).unwrap();
+
+        let records = SparseRepoData::load_records_recursive(
+            [&sparse],
+            [PackageName::new_unchecked("foo")],
+            None,
+        )
+        .unwrap();
+
+        // Dependencies without a space before the version and with a channel
+        // are followed, virtual packages are not.
+        assert_eq!(
+            records[0]
+                .iter()
+                .map(|record| record.package_record.name.as_normalized())
+                .sorted()
+                .collect::<Vec<_>>(),
+            ["bar", "baz", "foo"]
+        );
+    }
+
     #[rstest]
     #[case("clang-format-13.0.1-root_62800_h69bbbaa_1.conda", "clang-format")]
     #[case("clang-format-13-13.0.1-default_he082bbe_0.tar.bz2", "clang-format-13")]
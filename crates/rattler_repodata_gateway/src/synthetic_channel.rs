@@ -0,0 +1,267 @@
+//! Generation of synthetic channels.
+//!
+//! A [`SyntheticChannel`] describes a channel of configurable size that is
+//! generated deterministically from a seed. The benchmarks of this crate use
+//! it to measure the performance of fetching, parsing and querying repodata,
+//! but it can also be used to profile other configurations of the gateway
+//! without depending on the contents of a real channel.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use rattler_conda_types::{ChannelInfo, PackageName, PackageRecord, Platform, RepoData, Version};
+use rattler_digest::{compute_bytes_digest, Md5, Sha256};
+
+/// Describes a synthetic channel. See the [module level documentation](self)
+/// for more information.
+#[derive(Debug, Clone)]
+pub struct SyntheticChannel {
+    subdirs: Vec<Platform>,
+    package_count: usize,
+    versions_per_package: usize,
+    builds_per_version: usize,
+    dependencies_per_record: usize,
+    seed: u64,
+}
+
+impl Default for SyntheticChannel {
+    fn default() -> Self {
+        Self {
+            subdirs: vec![Platform::Linux64, Platform::NoArch],
+            package_count: 1000,
+            versions_per_package: 5,
+            builds_per_version: 2,
+            dependencies_per_record: 4,
+            seed: 0,
+        }
+    }
+}
+
+impl SyntheticChannel {
+    /// Constructs a new synthetic channel with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the subdirectories of the channel. The packages are distributed
+    /// evenly over the subdirectories.
+    #[must_use]
+    pub fn with_subdirs(self, subdirs: impl IntoIterator<Item = Platform>) -> Self {
+        Self {
+            subdirs: subdirs.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Sets the number of distinct package names in the channel.
+    #[must_use]
+    pub fn with_package_count(self, package_count: usize) -> Self {
+        Self {
+            package_count,
+            ..self
+        }
+    }
+
+    /// Sets the number of versions of every package.
+    #[must_use]
+    pub fn with_versions_per_package(self, versions_per_package: usize) -> Self {
+        Self {
+            versions_per_package,
+            ..self
+        }
+    }
+
+    /// Sets the number of builds of every version of a package.
+    #[must_use]
+    pub fn with_builds_per_version(self, builds_per_version: usize) -> Self {
+        Self {
+            builds_per_version,
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of dependencies of every record. A package only
+    /// depends on packages that were generated before it, so the dependency
+    /// graph never contains cycles.
+    #[must_use]
+    pub fn with_dependencies_per_record(self, dependencies_per_record: usize) -> Self {
+        Self {
+            dependencies_per_record,
+            ..self
+        }
+    }
+
+    /// Sets the seed that is used to generate the channel. The same seed
+    /// always results in the same channel.
+    #[must_use]
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Returns the subdirectories of the channel.
+    pub fn subdirs(&self) -> &[Platform] {
+        &self.subdirs
+    }
+
+    /// Returns the total number of records in the channel.
+    pub fn record_count(&self) -> usize {
+        self.package_count * self.versions_per_package * self.builds_per_version
+    }
+
+    /// Returns the name of the package with the given index.
+    pub fn package_name(index: usize) -> PackageName {
+        PackageName::new_unchecked(format!("pkg-{index:06}"))
+    }
+
+    /// Returns the name of the package with the most transitive
+    /// dependencies. Querying this package recursively touches a large part
+    /// of the channel.
+    pub fn root_package_name(&self) -> PackageName {
+        Self::package_name(self.package_count.saturating_sub(1))
+    }
+
+    /// Generates the repodata of a single subdirectory of the channel.
+    pub fn repodata(&self, subdir: Platform) -> RepoData {
+        let mut repodata = RepoData {
+            info: Some(ChannelInfo {
+                subdir: subdir.to_string(),
+                base_url: None,
+            }),
+            packages: HashMap::default(),
+            conda_packages: HashMap::default(),
+            removed: HashSet::default(),
+            version: Some(2),
+        };
+
+        let Some(subdir_index) = self.subdirs.iter().position(|s| s == &subdir) else {
+            return repodata;
+        };
+
+        for package_index in (subdir_index..self.package_count).step_by(self.subdirs.len()) {
+            let name = Self::package_name(package_index);
+            let mut rng = SplitMix64::new(self.seed ^ package_index as u64);
+            for version_index in 0..self.versions_per_package {
+                let version = format!("{}.{}.0", 1 + version_index / 10, version_index % 10);
+                for build_number in 0..self.builds_per_version {
+                    let build = format!("h{:08x}_{build_number}", rng.next_u64() as u32);
+                    let file_name = format!("{}-{version}-{build}.conda", name.as_normalized());
+
+                    let mut record = PackageRecord::new(
+                        name.clone(),
+                        version
+                            .parse::<Version>()
+                            .expect("generated versions are valid"),
+                        build,
+                    );
+                    record.build_number = build_number as u64;
+                    record.subdir = subdir.to_string();
+                    record.size = Some(rng.next_u64() % 10_000_000);
+                    record.md5 = Some(compute_bytes_digest::<Md5>(file_name.as_bytes()));
+                    record.sha256 = Some(compute_bytes_digest::<Sha256>(file_name.as_bytes()));
+                    record.depends = self.dependencies(package_index, &mut rng);
+
+                    repodata.conda_packages.insert(file_name, record);
+                }
+            }
+        }
+
+        repodata
+    }
+
+    /// Writes the `repodata.json` of every subdirectory of the channel to
+    /// `root`.
+    pub fn write(&self, root: &Path) -> Result<(), std::io::Error> {
+        for &subdir in &self.subdirs {
+            let subdir_path = root.join(subdir.as_str());
+            std::fs::create_dir_all(&subdir_path)?;
+            std::fs::write(
+                subdir_path.join("repodata.json"),
+                serde_json::to_vec(&self.repodata(subdir))?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Generates the dependencies of a record of the package with the given
+    /// index.
+    fn dependencies(&self, package_index: usize, rng: &mut SplitMix64) -> Vec<String> {
+        let count = self.dependencies_per_record.min(package_index);
+        let mut dependencies: Vec<usize> = Vec::with_capacity(count);
+        while dependencies.len() < count {
+            let dependency = (rng.next_u64() % package_index as u64) as usize;
+            if !dependencies.contains(&dependency) {
+                dependencies.push(dependency);
+            }
+        }
+
+        dependencies
+            .into_iter()
+            .map(|dependency| {
+                let min_version = rng.next_u64() as usize % self.versions_per_package.max(1);
+                format!(
+                    "{} >={}.{}",
+                    Self::package_name(dependency).as_normalized(),
+                    1 + min_version / 10,
+                    min_version % 10
+                )
+            })
+            .collect()
+    }
+}
+
+/// A small and fast pseudo random number generator. It is used instead of a
+/// dependency on `rand` to guarantee that the generated channels never change.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_synthetic_channel() {
+        let channel = SyntheticChannel::new()
+            .with_package_count(10)
+            .with_versions_per_package(3)
+            .with_builds_per_version(2);
+        assert_eq!(channel.record_count(), 60);
+
+        let linux = channel.repodata(Platform::Linux64);
+        let noarch = channel.repodata(Platform::NoArch);
+        assert_eq!(
+            linux.conda_packages.len() + noarch.conda_packages.len(),
+            channel.record_count()
+        );
+        assert!(channel.repodata(Platform::Win64).conda_packages.is_empty());
+
+        // The same configuration always results in the same channel.
+        assert_eq!(linux, channel.repodata(Platform::Linux64));
+        assert_ne!(
+            linux,
+            channel.clone().with_seed(1).repodata(Platform::Linux64)
+        );
+
+        // Packages only depend on packages that were generated before them.
+        for record in linux.conda_packages.values() {
+            for dependency in &record.depends {
+                let (name, _) = dependency.split_once(' ').unwrap();
+                assert!(name < record.name.as_normalized());
+            }
+        }
+    }
+}
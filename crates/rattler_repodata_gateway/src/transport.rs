@@ -0,0 +1,152 @@
+//! Defines the [`Transport`] trait which abstracts the HTTP layer that is used
+//! to fetch repodata.
+//!
+//! By default, repodata is fetched using [`reqwest`]. Embedders that need to
+//! route requests through their own stack (e.g. a proxy that speaks a different
+//! protocol, a unix socket, or a corporate agent) can implement [`Transport`]
+//! and pass it to [`crate::GatewayBuilder::with_transport`]. For the lower
+//! level [`crate::fetch`] functions the [`TransportMiddleware`] can be added to
+//! a [`reqwest_middleware::ClientWithMiddleware`] directly.
+
+use std::sync::Arc;
+
+use http::Extensions;
+use reqwest::{Request, Response, ResponseBuilderExt};
+use reqwest_middleware::{Middleware, Next};
+
+/// A transport executes HTTP requests on behalf of the gateway.
+///
+/// The body of the response can be streamed by constructing it with
+/// [`reqwest::Body::wrap_stream`]. The transport is responsible for decoding
+/// any `Content-Encoding` of the response.
+#[async_trait::async_trait]
+pub trait Transport: Send + Sync {
+    /// Executes the request and returns the response.
+    async fn execute(
+        &self,
+        request: http::Request<reqwest::Body>,
+    ) -> anyhow::Result<http::Response<reqwest::Body>>;
+}
+
+/// The default [`Transport`] which uses a [`reqwest::Client`] to execute the
+/// requests.
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    /// Constructs a new transport from the given client.
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for ReqwestTransport {
+    async fn execute(
+        &self,
+        request: http::Request<reqwest::Body>,
+    ) -> anyhow::Result<http::Response<reqwest::Body>> {
+        let request = Request::try_from(request)?;
+        let response = self.client.execute(request).await?;
+        Ok(response.into())
+    }
+}
+
+/// A middleware that executes all requests through a [`Transport`] instead of
+/// the underlying [`reqwest::Client`].
+///
+/// This middleware terminates the middleware chain so it should be added as
+/// the last middleware.
+#[derive(Clone)]
+pub struct TransportMiddleware {
+    transport: Arc<dyn Transport>,
+}
+
+impl TransportMiddleware {
+    /// Constructs a new middleware from the given transport.
+    pub fn new<T: Transport + 'static>(transport: T) -> Self {
+        Self {
+            transport: Arc::new(transport),
+        }
+    }
+
+    /// Constructs a new middleware from a shared transport.
+    pub fn from_arc(transport: Arc<dyn Transport>) -> Self {
+        Self { transport }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for TransportMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        _extensions: &mut Extensions,
+        _next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let url = req.url().clone();
+        let request = http::Request::try_from(req)?;
+        let response = self
+            .transport
+            .execute(request)
+            .await
+            .map_err(reqwest_middleware::Error::Middleware)?;
+
+        // Reconstruct the response with the url of the request attached.
+        let (parts, body) = response.into_parts();
+        let mut builder = http::Response::builder()
+            .status(parts.status)
+            .version(parts.version)
+            .url(url);
+        if let Some(headers) = builder.headers_mut() {
+            *headers = parts.headers;
+        }
+        let response = builder
+            .body(body)
+            .map_err(|e| reqwest_middleware::Error::Middleware(e.into()))?;
+
+        Ok(Response::from(response))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use reqwest::Client;
+    use reqwest_middleware::ClientBuilder;
+    use url::Url;
+
+    use super::{Transport, TransportMiddleware};
+
+    /// A transport that responds to every request with the path of the url.
+    struct EchoTransport;
+
+    #[async_trait::async_trait]
+    impl Transport for EchoTransport {
+        async fn execute(
+            &self,
+            request: http::Request<reqwest::Body>,
+        ) -> anyhow::Result<http::Response<reqwest::Body>> {
+            Ok(http::Response::builder()
+                .status(200)
+                .body(Bytes::from(request.uri().path().to_string()).into())?)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transport_middleware() {
+        let client = ClientBuilder::new(Client::new())
+            .with(TransportMiddleware::new(EchoTransport))
+            .build();
+
+        let url = Url::parse("https://example.invalid/conda-forge/noarch/repodata.json").unwrap();
+        let response = client.get(url.clone()).send().await.unwrap();
+        assert_eq!(response.url(), &url);
+        assert_eq!(
+            response.text().await.unwrap(),
+            "/conda-forge/noarch/repodata.json"
+        );
+    }
+}
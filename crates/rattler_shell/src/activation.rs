@@ -18,6 +18,10 @@ use crate::shell::{Shell, ShellScript};
 
 const ENV_START_SEPERATOR: &str = "____RATTLER_ENV_START____";
 
+/// The value that conda writes to the `state` file to indicate that an
+/// environment variable should be unset when the environment is activated.
+pub const CONDA_ENV_VARS_UNSET_VAR: &str = "***unset***";
+
 /// Type of modification done to the `PATH` variable
 #[derive(Default, Clone)]
 pub enum PathModificationBehavior {
@@ -77,18 +81,26 @@ pub struct Activator<T: Shell + 'static> {
     /// A list of scripts to run when activating the environment
     pub activation_scripts: Vec<PathBuf>,
 
-    /// A list of scripts to run when deactivating the environment
+    /// A list of scripts to run when deactivating the environment. The
+    /// scripts are stored in the order in which they are executed, which is
+    /// the reverse of the order of the activation scripts.
     pub deactivation_scripts: Vec<PathBuf>,
 
     /// A list of environment variables to set when activating the environment
     pub env_vars: IndexMap<String, String>,
 
+    /// A list of environment variables to unset when activating the
+    /// environment. These are the variables that have the value
+    /// [`CONDA_ENV_VARS_UNSET_VAR`] in the `conda-meta/state` file.
+    pub unset_env_vars: Vec<String>,
+
     /// The platform for which to generate the Activator
     pub platform: Platform,
 }
 
 /// Collect all script files that match a certain shell type from a given path.
-/// The files are sorted by their filename.
+/// The files are sorted by their filename so the order is deterministic and
+/// independent of the filesystem.
 /// If the path does not exist, an empty vector is returned.
 /// If the path is not a directory, an error is returned.
 ///
@@ -119,7 +131,7 @@ fn collect_scripts<T: Shell>(path: &Path, shell_type: &T) -> Result<Vec<PathBuf>
         .filter(|path| shell_type.can_run_script(path))
         .collect::<Vec<_>>();
 
-    scripts.sort();
+    scripts.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
 
     Ok(scripts)
 }
@@ -188,6 +200,9 @@ pub enum ActivationError {
 ///
 /// If the `state` file or the `env_vars.d` directory cannot be read, an error
 /// is returned.
+///
+/// Variables that are unset in the `state` file have the value
+/// [`CONDA_ENV_VARS_UNSET_VAR`].
 fn collect_env_vars(prefix: &Path) -> Result<IndexMap<String, String>, ActivationError> {
     let state_file = prefix.join("conda-meta/state");
     let pkg_env_var_dir = prefix.join("etc/conda/env_vars.d");
@@ -218,7 +233,7 @@ fn collect_env_vars(prefix: &Path) -> Result<IndexMap<String, String>, Activatio
         for (env_var_json, env_var_file) in env_var_json_files.iter().zip(env_var_files.iter()) {
             let env_var_json = env_var_json.as_object().ok_or_else(|| {
                 ActivationError::InvalidEnvVarFileJsonNoObject {
-                    file: pkg_env_var_dir.clone(),
+                    file: env_var_file.clone(),
                 }
             })?;
 
@@ -248,7 +263,7 @@ fn collect_env_vars(prefix: &Path) -> Result<IndexMap<String, String>, Activatio
         })?;
 
         for (key, value) in state_env_vars {
-            if state_env_vars.contains_key(key) {
+            if env_vars.contains_key(&key.to_uppercase()) {
                 tracing::warn!(
                     "WARNING: environment variable {key} already defined in packages (path: {state_file:?})");
             }
@@ -334,10 +349,17 @@ impl<T: Shell + Clone> Activator<T> {
     ) -> Result<Activator<T>, ActivationError> {
         let activation_scripts = collect_scripts(&path.join("etc/conda/activate.d"), &shell_type)?;
 
-        let deactivation_scripts =
+        // Like conda, deactivation scripts are run in reverse order so that the
+        // deactivation of a package is undone in the opposite order of its activation.
+        let mut deactivation_scripts =
             collect_scripts(&path.join("etc/conda/deactivate.d"), &shell_type)?;
+        deactivation_scripts.reverse();
 
-        let env_vars = collect_env_vars(path)?;
+        // Variables marked as unset in the `state` file are unset instead of set.
+        let (unset_env_vars, env_vars): (IndexMap<_, _>, IndexMap<_, _>) = collect_env_vars(path)?
+            .into_iter()
+            .partition(|(_, value)| value == CONDA_ENV_VARS_UNSET_VAR);
+        let unset_env_vars = unset_env_vars.into_keys().collect();
 
         let paths = prefix_path_entries(path, &platform);
 
@@ -348,6 +370,7 @@ impl<T: Shell + Clone> Activator<T> {
             activation_scripts,
             deactivation_scripts,
             env_vars,
+            unset_env_vars,
             platform,
         })
     }
@@ -369,14 +392,16 @@ impl<T: Shell + Clone> Activator<T> {
                 self.platform,
             )?;
 
-            for (key, _) in &deactivate.env_vars {
-                script.unset_env_var(key)?;
-            }
-
+            // Run the deactivation scripts before unsetting the environment variables
+            // because the scripts might depend on them.
             for deactivation_script in &deactivate.deactivation_scripts {
                 script.run_script(deactivation_script)?;
             }
 
+            for (key, _) in &deactivate.env_vars {
+                script.unset_env_var(key)?;
+            }
+
             path.retain(|x| !deactivate.paths.contains(x));
         }
 
@@ -389,6 +414,10 @@ impl<T: Shell + Clone> Activator<T> {
         // this point
         script.set_env_var("CONDA_PREFIX", &self.target_prefix.to_string_lossy())?;
 
+        for key in &self.unset_env_vars {
+            script.unset_env_var(key)?;
+        }
+
         for (key, value) in &self.env_vars {
             script.set_env_var(key, value)?;
         }
@@ -522,6 +551,50 @@ mod tests {
         assert_eq!(activator.activation_scripts[2], script3);
     }
 
+    #[test]
+    fn test_deactivation_scripts_order() {
+        let old_env = TempDir::new("old").unwrap();
+        let new_env = TempDir::new("new").unwrap();
+
+        let path = old_env.path().join("etc/conda/deactivate.d/");
+        fs::create_dir_all(&path).unwrap();
+        let script1 = path.join("aaa.sh");
+        let script2 = path.join("bbb.sh");
+        fs::write(&script1, "").unwrap();
+        fs::write(&script2, "").unwrap();
+        fs::write(path.join("ccc.fish"), "").unwrap();
+
+        let state_path = old_env.path().join("conda-meta/state");
+        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+        fs::write(&state_path, r#"{"env_vars": {"OLD_VAR": "old"}}"#).unwrap();
+
+        let old_activator =
+            Activator::from_path(old_env.path(), shell::Bash, Platform::Linux64).unwrap();
+        assert_eq!(
+            old_activator.deactivation_scripts,
+            vec![script2.clone(), script1.clone()]
+        );
+
+        let activator =
+            Activator::from_path(new_env.path(), shell::Bash, Platform::Linux64).unwrap();
+        let script = activator
+            .activation(ActivationVariables {
+                conda_prefix: Some(old_env.path().to_path_buf()),
+                ..ActivationVariables::default()
+            })
+            .unwrap()
+            .script
+            .contents()
+            .unwrap();
+
+        let position = |needle: &str| script.find(needle).unwrap();
+        let script1 = script1.to_string_lossy();
+        let script2 = script2.to_string_lossy();
+        assert!(position(&script2) < position(&script1));
+        assert!(position(&script1) < position("unset OLD_VAR"));
+        assert!(!script.contains("ccc.fish"));
+    }
+
     #[test]
     fn test_collect_env_vars() {
         let tdir = TempDir::new("test").unwrap();
@@ -582,6 +655,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_unset_env_vars_from_state() {
+        let tdir = TempDir::new("test").unwrap();
+        let state_path = tdir.path().join("conda-meta/state");
+        fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+
+        let env_var_d = tdir.path().join("etc/conda/env_vars.d");
+        fs::create_dir_all(&env_var_d).unwrap();
+        fs::write(
+            env_var_d.join("pkg1.json"),
+            r#"{"PKG_VAR": "pkg", "REMOVED": "pkg"}"#,
+        )
+        .unwrap();
+
+        let state = r#"{"env_vars": {"removed": "***unset***", "STATE": "state"}}"#;
+        fs::write(&state_path, state).unwrap();
+
+        let activator = Activator::from_path(tdir.path(), shell::Bash, Platform::Linux64).unwrap();
+        assert_eq!(activator.unset_env_vars, vec!["REMOVED".to_string()]);
+        assert_eq!(activator.env_vars.len(), 2);
+        assert_eq!(activator.env_vars["PKG_VAR"], "pkg");
+        assert_eq!(activator.env_vars["STATE"], "state");
+
+        let script = activator
+            .activation(ActivationVariables::default())
+            .unwrap()
+            .script
+            .contents()
+            .unwrap();
+        assert!(script.lines().any(|line| line == "unset REMOVED"));
+        assert!(!script
+            .lines()
+            .any(|line| line.starts_with("export REMOVED=")));
+    }
+
     #[test]
     fn test_add_to_path() {
         let prefix = PathBuf::from_str("/opt/conda").unwrap();
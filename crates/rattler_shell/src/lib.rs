@@ -5,4 +5,5 @@
 pub mod activation;
 pub mod run;
 pub mod shell;
+pub mod variables;
 pub use run::run_in_environment;
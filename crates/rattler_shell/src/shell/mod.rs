@@ -16,7 +16,10 @@ use itertools::Itertools;
 use rattler_conda_types::Platform;
 use thiserror::Error;
 
-use crate::activation::PathModificationBehavior;
+use crate::{
+    activation::PathModificationBehavior,
+    variables::{path_separator, EnvValue, EnvValueSegment},
+};
 
 /// A trait for generating shell scripts.
 /// The trait is implemented for each shell individually.
@@ -45,6 +48,17 @@ pub trait Shell {
     /// Set an env var by `export`-ing it.
     fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> std::fmt::Result;
 
+    /// Set an env var to a value that can reference other env vars. The
+    /// references are formatted with [`Self::format_env_var`].
+    fn set_env_value(
+        &self,
+        f: &mut impl Write,
+        env_var: &str,
+        value: &EnvValue,
+    ) -> std::fmt::Result {
+        self.set_env_var(f, env_var, &value.format(self))
+    }
+
     /// Unset an env var by `unset`-ing it.
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result;
 
@@ -108,11 +122,7 @@ pub trait Shell {
 
     /// Path seperator
     fn path_seperator(&self, platform: &Platform) -> &str {
-        if platform.is_unix() {
-            ":"
-        } else {
-            ";"
-        }
+        path_separator(*platform)
     }
 
     /// Returns the name of the PATH variable for the given platform. On
@@ -131,6 +141,12 @@ pub trait Shell {
         format!("${{{var_name}}}")
     }
 
+    /// Escapes a literal string so it is not interpreted by the shell when it
+    /// is part of the value passed to [`Self::set_env_var`].
+    fn escape_literal<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(value)
+    }
+
     /// Emits echoing certain text to stdout.
     fn echo(&self, f: &mut impl Write, text: &str) -> std::fmt::Result {
         writeln!(f, "echo {}", shlex::try_quote(text).unwrap_or_default())
@@ -204,6 +220,10 @@ impl Shell for Bash {
         writeln!(f, "export {env_var}=\"{value}\"")
     }
 
+    fn escape_literal<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        escape_chars(value, &['\\', '"', '$', '`'], '\\')
+    }
+
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result {
         writeln!(f, "unset {env_var}")
     }
@@ -286,6 +306,10 @@ impl Shell for Zsh {
         writeln!(f, "export {env_var}=\"{value}\"")
     }
 
+    fn escape_literal<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        escape_chars(value, &['\\', '"', '$', '`'], '\\')
+    }
+
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result {
         writeln!(f, "unset {env_var}")
     }
@@ -318,6 +342,10 @@ impl Shell for Xonsh {
         writeln!(f, "${env_var} = \"{value}\"")
     }
 
+    fn escape_literal<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        escape_chars(value, &['\\', '"'], '\\')
+    }
+
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result {
         writeln!(f, "del ${env_var}")
     }
@@ -367,6 +395,10 @@ impl Shell for CmdExe {
         writeln!(f, "@SET \"{env_var}={value}\"")
     }
 
+    fn escape_literal<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        escape_chars(value, &['%'], '%')
+    }
+
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result {
         writeln!(f, "@SET {env_var}=")
     }
@@ -428,6 +460,10 @@ impl Shell for CmdExe {
 }
 
 /// A [`Shell`] implementation for `PowerShell`.
+///
+/// The scripts are run with the modern `pwsh` if it is available and with
+/// Windows `PowerShell` (`powershell.exe`) otherwise. Use [`PowerShellCore`]
+/// to generate scripts that always use `pwsh` and never expand values.
 #[derive(Debug, Clone)]
 pub struct PowerShell {
     executable_path: String,
@@ -436,8 +472,7 @@ pub struct PowerShell {
 impl Default for PowerShell {
     fn default() -> Self {
         // Check if the modern "pwsh" PowerShell Core is available
-        let test_powershell = Command::new("pwsh").arg("-v").output().is_ok();
-        let exe = if test_powershell {
+        let exe = if PowerShellCore::is_available() {
             "pwsh"
         } else {
             // Fall back to older "Windows PowerShell"
@@ -460,6 +495,10 @@ impl Shell for PowerShell {
         writeln!(f, "${{Env:{env_var}}} = \"{value}\"")
     }
 
+    fn escape_literal<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        escape_chars(value, &['`', '"', '$'], '`')
+    }
+
     fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result {
         writeln!(f, "${{Env:{env_var}}}=\"\"")
     }
@@ -492,6 +531,152 @@ impl Shell for PowerShell {
     }
 }
 
+/// A [`Shell`] implementation for the cross-platform `PowerShell` (`pwsh`),
+/// also known as `PowerShell` Core.
+///
+/// Values and paths are written as single-quoted strings, so they are never
+/// expanded by the shell. This makes the scripts work with paths that contain
+/// spaces, non-ASCII characters or characters like `$` and `` ` ``.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PowerShellCore;
+
+impl PowerShellCore {
+    /// Returns true if the `pwsh` executable can be found.
+    pub fn is_available() -> bool {
+        Command::new("pwsh").arg("-Version").output().is_ok()
+    }
+}
+
+/// Quotes a string as a verbatim (single-quoted) `PowerShell` string. Besides
+/// the ASCII single quote `PowerShell` also treats the typographic single
+/// quotes as quotes, these are escaped by doubling them.
+fn powershell_quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('\'');
+    for c in s.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+impl Shell for PowerShellCore {
+    fn force_utf8(&self, f: &mut impl Write) -> std::fmt::Result {
+        writeln!(f, "$OutputEncoding = [System.Console]::OutputEncoding = [System.Console]::InputEncoding = [System.Text.Encoding]::UTF8")
+    }
+
+    fn set_env_var(&self, f: &mut impl Write, env_var: &str, value: &str) -> std::fmt::Result {
+        writeln!(f, "${{Env:{env_var}}} = {}", powershell_quote(value))
+    }
+
+    fn set_env_value(
+        &self,
+        f: &mut impl Write,
+        env_var: &str,
+        value: &EnvValue,
+    ) -> std::fmt::Result {
+        // Concatenate the literals and the references instead of relying on
+        // string interpolation.
+        let value = value
+            .segments()
+            .iter()
+            .map(|segment| match segment {
+                EnvValueSegment::Literal(literal) => powershell_quote(literal),
+                EnvValueSegment::Variable(name) => self.format_env_var(name),
+            })
+            .join(" + ");
+        if value.is_empty() {
+            writeln!(f, "${{Env:{env_var}}} = ''")
+        } else {
+            writeln!(f, "${{Env:{env_var}}} = {value}")
+        }
+    }
+
+    fn unset_env_var(&self, f: &mut impl Write, env_var: &str) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Remove-Item -LiteralPath {} -ErrorAction SilentlyContinue",
+            powershell_quote(&format!("Env:{env_var}"))
+        )
+    }
+
+    fn run_script(&self, f: &mut impl Write, path: &Path) -> std::fmt::Result {
+        writeln!(f, ". {}", powershell_quote(&path.to_string_lossy()))
+    }
+
+    fn run_command<'a>(
+        &self,
+        f: &mut impl Write,
+        command: impl IntoIterator<Item = &'a str> + 'a,
+    ) -> std::fmt::Result {
+        let mut command = command.into_iter();
+        let Some(executable) = command.next() else {
+            return Ok(());
+        };
+        // The call operator is required to invoke a quoted executable.
+        write!(f, "& {}", powershell_quote(executable))?;
+        for arg in command {
+            write!(f, " {}", powershell_quote(arg))?;
+        }
+        writeln!(f)
+    }
+
+    fn set_path(
+        &self,
+        f: &mut impl Write,
+        paths: &[PathBuf],
+        modification_behavior: PathModificationBehavior,
+        platform: &Platform,
+    ) -> std::fmt::Result {
+        let path_var = self.path_var(platform);
+        let value = match modification_behavior {
+            PathModificationBehavior::Replace => EnvValue::literal(
+                paths
+                    .iter()
+                    .map(|path| path.to_string_lossy())
+                    .join(self.path_seperator(platform)),
+            ),
+            PathModificationBehavior::Append => {
+                EnvValue::append_paths(path_var, paths.iter().cloned(), *platform)
+            }
+            PathModificationBehavior::Prepend => {
+                EnvValue::prepend_paths(path_var, paths.iter().cloned(), *platform)
+            }
+        };
+        self.set_env_value(f, path_var, &value)
+    }
+
+    fn extension(&self) -> &str {
+        "ps1"
+    }
+
+    fn executable(&self) -> &str {
+        "pwsh"
+    }
+
+    fn create_run_script_command(&self, path: &Path) -> Command {
+        let mut cmd = Command::new(self.executable());
+        cmd.arg("-NoLogo").arg("-File").arg(path);
+        cmd
+    }
+
+    fn format_env_var(&self, var_name: &str) -> String {
+        format!("${{Env:{var_name}}}")
+    }
+
+    fn echo(&self, f: &mut impl Write, text: &str) -> std::fmt::Result {
+        writeln!(f, "Write-Output {}", powershell_quote(text))
+    }
+
+    /// Emits writing all current environment variables to stdout.
+    fn print_env(&self, f: &mut impl Write) -> std::fmt::Result {
+        writeln!(f, r##"dir env: | %{{"{{0}}={{1}}" -f $_.Name,$_.Value}}"##)
+    }
+}
+
 /// A [`Shell`] implementation for the Fish shell.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Fish;
@@ -501,6 +686,10 @@ impl Shell for Fish {
         writeln!(f, "set -gx {env_var} \"{value}\"")
     }
 
+    fn escape_literal<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        escape_chars(value, &['\\', '"', '$'], '\\')
+    }
+
     fn format_env_var(&self, var_name: &str) -> String {
         // Fish doesnt want the extra brackets '{}'
         format!("${var_name}")
@@ -529,6 +718,21 @@ impl Shell for Fish {
     }
 }
 
+/// Prefixes every occurrence of one of `chars` in `s` with `escape`.
+fn escape_chars<'a>(s: &'a str, chars: &[char], escape: char) -> Cow<'a, str> {
+    if !s.contains(chars) {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::with_capacity(s.len() + 1);
+    for c in s.chars() {
+        if chars.contains(&c) {
+            escaped.push(escape);
+        }
+        escaped.push(c);
+    }
+    Cow::Owned(escaped)
+}
+
 fn escape_backslashes(s: &str) -> String {
     s.replace('\\', "\\\\")
 }
@@ -615,6 +819,7 @@ pub enum ShellEnum {
     Xonsh,
     CmdExe,
     PowerShell,
+    PowerShellCore,
     Fish,
     NuShell,
 }
@@ -647,7 +852,11 @@ impl ShellEnum {
         if let Some(env_shell) = std::env::var_os("SHELL") {
             Self::from_shell_path(env_shell)
         } else if cfg!(windows) {
-            Some(PowerShell::default().into())
+            if PowerShellCore::is_available() {
+                Some(PowerShellCore.into())
+            } else {
+                Some(PowerShell::default().into())
+            }
         } else {
             None
         }
@@ -690,9 +899,9 @@ impl ShellEnum {
                 Some(Fish.into())
             } else if parent_process_name.contains("nu") {
                 Some(NuShell.into())
-            } else if parent_process_name.contains("powershell")
-                || parent_process_name.contains("pwsh")
-            {
+            } else if parent_process_name.contains("pwsh") {
+                Some(PowerShellCore.into())
+            } else if parent_process_name.contains("powershell") {
                 Some(
                     PowerShell {
                         executable_path: parent_process_name.clone(),
@@ -739,6 +948,7 @@ impl FromStr for ShellEnum {
             "cmd" => Ok(CmdExe.into()),
             "nu" | "nushell" => Ok(NuShell.into()),
             "powershell" | "powershell_ise" => Ok(PowerShell::default().into()),
+            "pwsh" => Ok(PowerShellCore.into()),
             _ => Err(ParseShellEnumError(format!(
                 "'{s}' is an unknown shell variant"
             ))),
@@ -904,6 +1114,45 @@ mod tests {
         insta::assert_snapshot!(script.contents);
     }
 
+    #[test]
+    fn test_powershell_core() {
+        let mut script = ShellScript::new(PowerShellCore, Platform::Win64);
+
+        script
+            .set_env_var("FOO", "it's $5 `n")
+            .unwrap()
+            .unset_env_var("FOO")
+            .unwrap()
+            .set_path(
+                &[PathBuf::from(r"C:\Program Files\envs\🦀")],
+                PathModificationBehavior::Prepend,
+            )
+            .unwrap()
+            .run_script(&PathBuf::from(r"C:\Program Files\envs\🦀\activate.ps1"))
+            .unwrap();
+
+        assert_eq!(
+            script.contents,
+            [
+                "${Env:FOO} = 'it''s $5 `n'",
+                "Remove-Item -LiteralPath 'Env:FOO' -ErrorAction SilentlyContinue",
+                r"${Env:Path} = 'C:\Program Files\envs\🦀;' + ${Env:Path}",
+                r". 'C:\Program Files\envs\🦀\activate.ps1'",
+                ""
+            ]
+            .join("\n")
+        );
+
+        assert!(matches!(
+            ShellEnum::from_str("pwsh"),
+            Ok(ShellEnum::PowerShellCore(_))
+        ));
+        assert!(matches!(
+            ShellEnum::from_str("powershell"),
+            Ok(ShellEnum::PowerShell(_))
+        ));
+    }
+
     #[cfg(feature = "sysinfo")]
     #[test]
     fn test_from_parent_process_doenst_crash() {
@@ -0,0 +1,351 @@
+//! Typed building blocks for the environment variables that are set during
+//! activation. Front-ends can use these to construct the value of variables
+//! programmatically instead of formatting shell snippets by hand.
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::PathBuf;
+//! use rattler_conda_types::Platform;
+//! use rattler_shell::{
+//!     shell::{Bash, Shell},
+//!     variables::{EnvValue, PathList},
+//! };
+//!
+//! // Manipulate a concrete PATH value.
+//! let mut path = PathList::parse("/usr/bin:/bin", Platform::Linux64);
+//! path.prepend_unique("/opt/env/bin");
+//! assert_eq!(path.to_value(Platform::Linux64), "/opt/env/bin:/usr/bin:/bin");
+//!
+//! // Or reference the value of PATH at the time the script is executed.
+//! let bin = PathBuf::from("/opt/env/bin");
+//! let value = EnvValue::prepend_paths("PATH", [bin], Platform::Linux64);
+//! let mut script = String::new();
+//! Bash.set_env_value(&mut script, "PATH", &value).unwrap();
+//! assert_eq!(script, "export PATH=\"/opt/env/bin:${PATH}\"\n");
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use rattler_conda_types::Platform;
+
+use crate::shell::Shell;
+
+/// Returns the separator that is used to separate the entries of PATH-like
+/// variables on the given platform, see [`Shell::path_seperator`].
+pub(crate) fn path_separator(platform: Platform) -> &'static str {
+    if platform.is_unix() {
+        ":"
+    } else {
+        ";"
+    }
+}
+
+/// The entries of a PATH-like environment variable, e.g. `PATH` or
+/// `PYTHONPATH`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathList {
+    entries: Vec<PathBuf>,
+}
+
+impl PathList {
+    /// Constructs an empty list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Splits the value of a PATH-like variable into its entries using the
+    /// separator of the given platform. Empty entries are ignored.
+    pub fn parse(value: &str, platform: Platform) -> Self {
+        value
+            .split(path_separator(platform))
+            .filter(|entry| !entry.is_empty())
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// Reads the entries of the variable with the given name from the
+    /// environment of the current process. Returns an empty list if the
+    /// variable is not set.
+    pub fn from_env(name: &str) -> Self {
+        std::env::var_os(name)
+            .map(|value| std::env::split_paths(&value).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the entries of the list.
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    /// Returns true if the list contains the given path.
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        self.entries.iter().any(|entry| entry == path)
+    }
+
+    /// Adds a path to the front of the list.
+    pub fn prepend(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.entries.insert(0, path.into());
+        self
+    }
+
+    /// Adds a path to the back of the list.
+    pub fn append(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.entries.push(path.into());
+        self
+    }
+
+    /// Adds a path to the front of the list, removing any other occurrence of
+    /// the path. This ensures the path takes precedence over all other
+    /// entries without growing the list when activating multiple times.
+    pub fn prepend_unique(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        let path = path.into();
+        self.remove(&path);
+        self.prepend(path)
+    }
+
+    /// Adds a path to the back of the list unless the list already contains
+    /// the path.
+    pub fn append_unique(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        let path = path.into();
+        if !self.contains(&path) {
+            self.entries.push(path);
+        }
+        self
+    }
+
+    /// Removes all occurrences of the given path.
+    pub fn remove(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        let path = path.as_ref();
+        self.entries.retain(|entry| entry != path);
+        self
+    }
+
+    /// Joins the entries with the separator of the given platform.
+    pub fn to_value(&self, platform: Platform) -> String {
+        self.entries
+            .iter()
+            .map(|entry| entry.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(path_separator(platform))
+    }
+}
+
+impl<P: Into<PathBuf>> FromIterator<P> for PathList {
+    fn from_iter<T: IntoIterator<Item = P>>(iter: T) -> Self {
+        Self {
+            entries: iter.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// A part of an [`EnvValue`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvValueSegment {
+    /// A literal string.
+    Literal(String),
+
+    /// A reference to the value of another environment variable.
+    Variable(String),
+}
+
+/// The value of an environment variable that can contain references to other
+/// environment variables, e.g. `/opt/env/bin:$PATH`.
+///
+/// References are formatted with the syntax of the shell the value is written
+/// for, see [`EnvValue::format`], or expanded directly with
+/// [`EnvValue::expand`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvValue {
+    segments: Vec<EnvValueSegment>,
+}
+
+impl EnvValue {
+    /// Constructs an empty value.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a value that only consists of a literal string.
+    pub fn literal(value: impl Into<String>) -> Self {
+        Self::new().with_literal(value)
+    }
+
+    /// Constructs a value that references another variable.
+    pub fn variable(name: impl Into<String>) -> Self {
+        Self::new().with_variable(name)
+    }
+
+    /// Constructs the value of a PATH-like variable that puts `paths` in front
+    /// of the current value of the variable `name`.
+    pub fn prepend_paths(
+        name: &str,
+        paths: impl IntoIterator<Item = PathBuf>,
+        platform: Platform,
+    ) -> Self {
+        let separator = path_separator(platform);
+        let mut value = Self::new();
+        for path in paths {
+            value.push_literal(format!("{}{separator}", path.to_string_lossy()));
+        }
+        value.with_variable(name)
+    }
+
+    /// Constructs the value of a PATH-like variable that puts `paths` after
+    /// the current value of the variable `name`.
+    pub fn append_paths(
+        name: &str,
+        paths: impl IntoIterator<Item = PathBuf>,
+        platform: Platform,
+    ) -> Self {
+        let separator = path_separator(platform);
+        let mut value = Self::variable(name);
+        for path in paths {
+            value.push_literal(format!("{separator}{}", path.to_string_lossy()));
+        }
+        value
+    }
+
+    /// Appends a literal string to the value.
+    #[must_use]
+    pub fn with_literal(mut self, value: impl Into<String>) -> Self {
+        self.push_literal(value);
+        self
+    }
+
+    /// Appends a reference to another variable to the value.
+    #[must_use]
+    pub fn with_variable(mut self, name: impl Into<String>) -> Self {
+        self.push_variable(name);
+        self
+    }
+
+    /// Appends a literal string to the value.
+    pub fn push_literal(&mut self, value: impl Into<String>) -> &mut Self {
+        let value = value.into();
+        match self.segments.last_mut() {
+            Some(EnvValueSegment::Literal(last)) => last.push_str(&value),
+            _ => self.segments.push(EnvValueSegment::Literal(value)),
+        }
+        self
+    }
+
+    /// Appends a reference to another variable to the value.
+    pub fn push_variable(&mut self, name: impl Into<String>) -> &mut Self {
+        self.segments.push(EnvValueSegment::Variable(name.into()));
+        self
+    }
+
+    /// Returns the segments of the value.
+    pub fn segments(&self) -> &[EnvValueSegment] {
+        &self.segments
+    }
+
+    /// Formats the value for the given shell. Literals are escaped with
+    /// [`Shell::escape_literal`] and references to other variables use the
+    /// syntax of the shell, see [`Shell::format_env_var`].
+    pub fn format(&self, shell: &(impl Shell + ?Sized)) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                EnvValueSegment::Literal(value) => shell.escape_literal(value).into_owned(),
+                EnvValueSegment::Variable(name) => shell.format_env_var(name),
+            })
+            .collect()
+    }
+
+    /// Expands the references to other variables using `lookup`. References
+    /// to variables for which `lookup` returns `None` expand to an empty
+    /// string.
+    pub fn expand(&self, lookup: impl Fn(&str) -> Option<String>) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                EnvValueSegment::Literal(value) => value.clone(),
+                EnvValueSegment::Variable(name) => lookup(name).unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+impl From<&str> for EnvValue {
+    fn from(value: &str) -> Self {
+        Self::literal(value)
+    }
+}
+
+impl From<String> for EnvValue {
+    fn from(value: String) -> Self {
+        Self::literal(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rattler_conda_types::Platform;
+
+    use super::{EnvValue, PathList};
+    use crate::shell::{Bash, CmdExe, Fish, PowerShell, Shell};
+
+    #[test]
+    fn test_path_list() {
+        let mut path = PathList::parse("C:\\bin;;C:\\env\\Scripts", Platform::Win64);
+        assert_eq!(path.entries().len(), 2);
+
+        path.prepend_unique("C:\\env\\Scripts")
+            .append_unique("C:\\bin")
+            .append("C:\\tools");
+        assert_eq!(
+            path.to_value(Platform::Win64),
+            "C:\\env\\Scripts;C:\\bin;C:\\tools"
+        );
+
+        path.remove("C:\\bin");
+        assert!(!path.contains("C:\\bin"));
+        assert_eq!(
+            path.to_value(Platform::Linux64),
+            "C:\\env\\Scripts:C:\\tools"
+        );
+    }
+
+    #[test]
+    fn test_env_value_per_shell() {
+        let value = EnvValue::prepend_paths(
+            "PATH",
+            [PathBuf::from("/env/bin"), PathBuf::from("/env/sbin")],
+            Platform::Linux64,
+        );
+        assert_eq!(value.format(&Bash), "/env/bin:/env/sbin:${PATH}");
+        assert_eq!(value.format(&Fish), "/env/bin:/env/sbin:$PATH");
+
+        let value = EnvValue::append_paths("Path", [PathBuf::from("C:\\env")], Platform::Win64);
+        assert_eq!(value.format(&CmdExe), "%Path%;C:\\env");
+        assert_eq!(value.format(&PowerShell::default()), "$Env:Path;C:\\env");
+
+        let value = EnvValue::variable("CONDA_PREFIX").with_literal("/lib");
+        assert_eq!(
+            value.expand(|name| (name == "CONDA_PREFIX").then(|| String::from("/env"))),
+            "/env/lib"
+        );
+        assert_eq!(value.expand(|_| None), "/lib");
+
+        let mut script = String::new();
+        Bash.set_env_value(&mut script, "FOO", &value).unwrap();
+        assert_eq!(script, "export FOO=\"${CONDA_PREFIX}/lib\"\n");
+    }
+
+    #[test]
+    fn test_env_value_escapes_literals() {
+        let value = EnvValue::literal("a \"$b\" `c` 100%").with_variable("PATH");
+        assert_eq!(value.format(&Bash), "a \\\"\\$b\\\" \\`c\\` 100%${PATH}");
+        assert_eq!(value.format(&Fish), "a \\\"\\$b\\\" `c` 100%$PATH");
+        assert_eq!(value.format(&CmdExe), "a \"$b\" `c` 100%%%PATH%");
+        assert_eq!(
+            value.format(&PowerShell::default()),
+            "a `\"`$b`\" ``c`` 100%$Env:PATH"
+        );
+    }
+}
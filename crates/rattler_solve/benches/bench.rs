@@ -1,4 +1,4 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion, SamplingMode};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, SamplingMode};
 use rattler_conda_types::ParseStrictness::Strict;
 use rattler_conda_types::{Channel, ChannelConfig, MatchSpec};
 use rattler_repodata_gateway::sparse::SparseRepoData;
@@ -54,9 +54,15 @@ fn bench_solve_environment(c: &mut Criterion, specs: Vec<&str>) {
         read_sparse_repodata(&json_file_noarch),
     ];
 
-    let names = specs.iter().map(|s| s.name.clone().unwrap());
-    let available_packages =
-        SparseRepoData::load_records_recursive(&sparse_repo_datas, names, None).unwrap();
+    let names = specs
+        .iter()
+        .map(|s| s.name.clone().unwrap())
+        .collect::<Vec<_>>();
+    let load_records = || {
+        SparseRepoData::load_records_recursive(&sparse_repo_datas, names.iter().cloned(), None)
+            .unwrap()
+    };
+    let available_packages = load_records();
 
     #[cfg(feature = "libsolv_c")]
     group.bench_function("libsolv_c", |b| {
@@ -82,6 +88,24 @@ fn bench_solve_environment(c: &mut Criterion, specs: Vec<&str>) {
         });
     });
 
+    // The records cache their parsed dependencies, this measures a solve with
+    // records whose match specs have not been parsed yet.
+    #[cfg(feature = "resolvo")]
+    group.bench_function("resolvo (unparsed records)", |b| {
+        b.iter_batched(
+            load_records,
+            |available_packages| {
+                rattler_solve::resolvo::Solver
+                    .solve(black_box(SolverTask {
+                        specs: specs.clone(),
+                        ..SolverTask::from_iter(&available_packages)
+                    }))
+                    .unwrap()
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
     group.finish();
 }
 
@@ -1,6 +1,12 @@
 //! `rattler_solve` is a crate that provides functionality to solve Conda
 //! environments. It currently exposes the functionality through the
 //! [`SolverImpl::solve`] function.
+//!
+//! Solves are deterministic: solving the same [`SolverTask`] with the same
+//! backend always results in the same records in the same order, on every
+//! platform. The order of the records within a single repodata does not
+//! influence the result, the order of the repodata themselves does because it
+//! determines the channel priority.
 
 #![deny(missing_docs)]
 
@@ -9,10 +15,13 @@ pub mod libsolv_c;
 #[cfg(feature = "resolvo")]
 pub mod resolvo;
 
-use std::fmt;
+use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
 
 use chrono::{DateTime, Utc};
-use rattler_conda_types::{GenericVirtualPackage, MatchSpec, RepoDataRecord};
+use rattler_conda_types::{
+    ChannelUrl, GenericVirtualPackage, MatchSpec, Matches, PackageName, PackageRecord,
+    PrefixRecord, RepoDataRecord,
+};
 
 /// Represents a solver implementation, capable of solving [`SolverTask`]s
 pub trait SolverImpl {
@@ -29,6 +38,163 @@ pub trait SolverImpl {
         &mut self,
         task: SolverTask<TAvailablePackagesIterator>,
     ) -> Result<Vec<RepoDataRecord>, SolveError>;
+
+    /// Resolve the dependencies like [`SolverImpl::solve`] but also return
+    /// statistics about the performance of the solve.
+    ///
+    /// The default implementation only measures the total time spent in
+    /// [`SolverImpl::solve`], backends override this to report more detailed
+    /// statistics.
+    fn solve_with_statistics<
+        'a,
+        R: IntoRepoData<'a, Self::RepoData<'a>>,
+        TAvailablePackagesIterator: IntoIterator<Item = R>,
+    >(
+        &mut self,
+        task: SolverTask<TAvailablePackagesIterator>,
+    ) -> Result<SolverResult, SolveError> {
+        let start = std::time::Instant::now();
+        let records = self.solve(task)?;
+        Ok(SolverResult {
+            records,
+            statistics: SolveStatistics {
+                solve_duration: start.elapsed(),
+                ..SolveStatistics::default()
+            },
+            quality: SolveQuality::Optimal,
+        })
+    }
+}
+
+/// The result of a successful solve.
+///
+/// The records are owned [`RepoDataRecord`]s by default. Backends that can
+/// share the records with the caller, like the `solve_shared` method of the
+/// resolvo solver, return `Arc<RepoDataRecord>`s instead.
+#[derive(Debug, Clone)]
+pub struct SolverResult<R = RepoDataRecord> {
+    /// The records that should be present in the environment.
+    pub records: Vec<R>,
+
+    /// Statistics about the performance of the solve.
+    pub statistics: SolveStatistics,
+
+    /// Describes whether the solution is optimal or what was compromised to
+    /// find a solution before the timeout was reached.
+    pub quality: SolveQuality,
+}
+
+/// Describes how good a solution returned by the solver is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SolveQuality {
+    /// The solution was found using the full candidate ordering of the solve
+    /// strategy.
+    #[default]
+    Optimal,
+
+    /// The solver was unable to find a solution with the full candidate
+    /// ordering before the timeout was reached and instead returned the best
+    /// solution it could find with a cheaper ordering. See
+    /// [`SolverTask::best_effort`].
+    BestEffort {
+        /// What was given up to find the solution in time.
+        compromises: Vec<SolveCompromise>,
+
+        /// The state of the solver when the attempt to find an optimal
+        /// solution was cancelled.
+        optimal_attempt: CancellationDiagnostics,
+    },
+}
+
+impl SolveQuality {
+    /// Returns true if the solution is optimal.
+    pub fn is_optimal(&self) -> bool {
+        matches!(self, SolveQuality::Optimal)
+    }
+}
+
+/// Something that was given up to find a solution before the timeout was
+/// reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolveCompromise {
+    /// Variants of a package with the same version and build number were not
+    /// ordered by the versions of their dependencies. The solution might
+    /// therefore contain lower versions of dependencies than an optimal
+    /// solution would.
+    DependencyAwareOrdering,
+}
+
+impl fmt::Display for SolveCompromise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveCompromise::DependencyAwareOrdering => write!(
+                f,
+                "variants of a package were not ordered by the versions of their dependencies"
+            ),
+        }
+    }
+}
+
+/// Machine-readable statistics about the performance of a single solve.
+///
+/// Not every solver backend is able to report every counter. Counters that are
+/// not supported by the backend are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub struct SolveStatistics {
+    /// The number of candidates that were considered by the solver.
+    ///
+    /// For resolvo these are the candidates that were requested for the
+    /// package names the solver encountered. Libsolv loads all available
+    /// packages up front, so for libsolv this is the number of packages that
+    /// were loaded into the pool.
+    pub candidates_considered: u64,
+
+    /// The number of decisions that were made by the solver.
+    pub decisions: Option<u64>,
+
+    /// The number of packages for which the solver requested the
+    /// dependencies.
+    pub dependencies_requested: Option<u64>,
+
+    /// The number of clauses that were learned from conflicts.
+    pub clauses_learned: Option<u64>,
+
+    /// The number of times the solver restarted.
+    pub restarts: Option<u64>,
+
+    /// The time it took to load the available packages into the solver.
+    pub load_duration: Duration,
+
+    /// The time it took to solve the problem.
+    pub solve_duration: Duration,
+
+    /// The time it took to extract the records from the solution.
+    pub extract_duration: Duration,
+
+    /// The time spent ordering the candidates of packages. This is included
+    /// in the `solve_duration`.
+    pub sort_duration: Option<Duration>,
+
+    /// The number of distinct match specs that were parsed from the
+    /// dependencies of packages.
+    pub match_specs_parsed: Option<u64>,
+
+    /// The number of times a match spec was reused instead of parsing it
+    /// again.
+    pub match_spec_cache_hits: Option<u64>,
+}
+
+impl SolveStatistics {
+    /// Returns the fraction of match specs that were reused instead of
+    /// parsed, or `None` if the backend does not report this or no match
+    /// specs were parsed.
+    pub fn match_spec_cache_hit_rate(&self) -> Option<f64> {
+        let hits = self.match_spec_cache_hits?;
+        let total = hits + self.match_specs_parsed?;
+        (total > 0).then(|| hits as f64 / total as f64)
+    }
 }
 
 /// Represents an error when solving the dependencies for a given environment
@@ -49,8 +215,56 @@ pub enum SolveError {
     /// Encountered duplicate records in the available packages.
     DuplicateRecords(String),
 
-    /// To support Resolvo cancellation
-    Cancelled,
+    /// The solver was cancelled before a solution was found, for instance
+    /// because the timeout was reached. Contains best-effort information about
+    /// the state of the solver at the moment it was cancelled.
+    Cancelled(CancellationDiagnostics),
+
+    /// A pinned package was uploaded after the cutoff date of
+    /// [`SolverTask::exclude_newer`]. Only returned if
+    /// [`SolverTask::exclude_newer_applies_to_locked`] is set.
+    PinnedPackageExcludedByDate {
+        /// The filename of the pinned package.
+        package: String,
+
+        /// The cutoff date.
+        exclude_newer: DateTime<Utc>,
+    },
+
+    /// A constraint of the task on a virtual package is not satisfied by the
+    /// virtual package that was provided.
+    VirtualPackageConstraintViolated {
+        /// The constraint that is violated.
+        constraint: String,
+
+        /// The virtual package that was provided.
+        virtual_package: String,
+    },
+}
+
+/// Best-effort information about the state of the solver at the moment it was
+/// cancelled.
+///
+/// This information can be used to figure out which specs make the problem
+/// hard to solve so they can be tuned.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CancellationDiagnostics {
+    /// True if the solver was cancelled because the timeout was reached.
+    pub timed_out: bool,
+
+    /// The time the solver spent solving before it was cancelled.
+    pub elapsed: Duration,
+
+    /// The number of candidates that were considered by the solver.
+    pub candidates_considered: u64,
+
+    /// The number of packages for which the solver requested the
+    /// dependencies.
+    pub dependencies_requested: u64,
+
+    /// The most recent specs for which none of the candidates matched. The
+    /// most recent spec is last.
+    pub last_conflicting_specs: Vec<String>,
 }
 
 impl fmt::Display for SolveError {
@@ -69,12 +283,50 @@ impl fmt::Display for SolveError {
             SolveError::ParseMatchSpecError(e) => {
                 write!(f, "Error parsing match spec: {e}")
             }
-            SolveError::Cancelled => {
-                write!(f, "Solve operation has been cancelled")
+            SolveError::Cancelled(diagnostics) => {
+                write!(f, "Solve operation has been cancelled")?;
+                if diagnostics.timed_out {
+                    write!(
+                        f,
+                        " because the timeout was reached after {:.2?}",
+                        diagnostics.elapsed
+                    )?;
+                }
+                write!(
+                    f,
+                    " ({} dependencies requested, {} candidates considered)",
+                    diagnostics.dependencies_requested, diagnostics.candidates_considered
+                )?;
+                if !diagnostics.last_conflicting_specs.is_empty() {
+                    write!(
+                        f,
+                        ", last conflicting specs: {}",
+                        diagnostics.last_conflicting_specs.join(", ")
+                    )?;
+                }
+                Ok(())
             }
             SolveError::DuplicateRecords(filename) => {
                 write!(f, "encountered duplicate records for {filename}")
             }
+            SolveError::PinnedPackageExcludedByDate {
+                package,
+                exclude_newer,
+            } => {
+                write!(
+                    f,
+                    "the pinned package {package} is uploaded after the cutoff date of {exclude_newer}"
+                )
+            }
+            SolveError::VirtualPackageConstraintViolated {
+                constraint,
+                virtual_package,
+            } => {
+                write!(
+                    f,
+                    "the constraint '{constraint}' is not satisfied by the virtual package {virtual_package} of the system"
+                )
+            }
         }
     }
 }
@@ -98,10 +350,37 @@ pub enum ChannelPriority {
     Disabled,
 }
 
+/// A channel policy for a single package that takes precedence over the
+/// [`SolverTask::channel_priority`] for that package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum PackageChannelPolicy {
+    /// Only candidates from the given channel are considered. The channel is
+    /// either the url of the channel (e.g.
+    /// `https://conda.anaconda.org/pytorch/`) or its name (e.g. `pytorch`).
+    Only(String),
+
+    /// Candidates are selected with the given channel priority instead of
+    /// the channel priority of the task.
+    Priority(ChannelPriority),
+}
+
+/// A function that reorders the candidates of a package. It is called with
+/// the candidates in the order determined by the built-in ordering of the
+/// solver, the first candidate is the one the solver tries first.
+///
+/// The function can reorder the candidates in any way, e.g. to prefer internal
+/// rebuilds of packages or to penalize packages that are older than a certain
+/// date. Any other modification of the candidates is ignored.
+pub type CandidateOrdering = Arc<dyn Fn(&mut [&RepoDataRecord]) + Send + Sync>;
+
 /// Represents a dependency resolution task, to be solved by one of the backends
 /// (currently only libsolv is supported)
 pub struct SolverTask<TAvailablePackagesIterator> {
-    /// An iterator over all available packages
+    /// An iterator over all available packages, grouped by repodata. The
+    /// repodata should be ordered by channel priority, the records within a
+    /// repodata can be in any order.
     pub available_packages: TAvailablePackagesIterator,
 
     /// Records of packages that are previously selected.
@@ -113,7 +392,8 @@ pub struct SolverTask<TAvailablePackagesIterator> {
     /// packages that are updated when installing new packages.
     ///
     /// Usually you add the currently installed packages or packages from a
-    /// lock-file here.
+    /// lock-file here. Use [`SolverTask::with_installed_packages`] to add the
+    /// packages that are installed in a prefix.
     pub locked_packages: Vec<RepoDataRecord>,
 
     /// Records of packages that are previously selected and CANNOT be changed.
@@ -129,26 +409,84 @@ pub struct SolverTask<TAvailablePackagesIterator> {
     pub virtual_packages: Vec<GenericVirtualPackage>,
 
     /// The specs we want to solve
+    ///
+    /// The libsolv backend returns [`SolveError::UnsupportedOperations`] if
+    /// any spec or constraint requires a subdir.
     pub specs: Vec<MatchSpec>,
 
     /// Additional constraints that should be satisfied by the solver.
     /// Packages included in the `constraints` are not necessarily
     /// installed, but they must be satisfied by the solution.
+    ///
+    /// Constraints can also refer to virtual packages, e.g. `__glibc<2.35`,
+    /// in which case they are checked against the [`Self::virtual_packages`].
+    /// Constraints on virtual packages that are not provided are ignored.
     pub constraints: Vec<MatchSpec>,
 
     /// The timeout after which the solver should stop
     pub timeout: Option<std::time::Duration>,
 
+    /// When `true` and a `timeout` is set, the solver returns the best
+    /// solution it can find before the timeout instead of failing with
+    /// [`SolveError::Cancelled`] when an optimal solution could not be found
+    /// in time. The [`SolverResult::quality`] describes what was compromised.
+    ///
+    /// Half of the timeout is spent trying to find an optimal solution, the
+    /// other half is used to find a solution with a cheaper candidate
+    /// ordering. Not all backends support this, in which case the option is
+    /// ignored.
+    pub best_effort: bool,
+
     /// The channel priority to solve with, either [`ChannelPriority::Strict`]
     /// or [`ChannelPriority::Disabled`]
     pub channel_priority: ChannelPriority,
 
+    /// Channel policies for specific packages that take precedence over the
+    /// `channel_priority`, e.g. to only use `pytorch` from the `pytorch`
+    /// channel while all other packages follow strict channel priority. Not
+    /// all backends support this, the libsolv backend returns
+    /// [`SolveError::UnsupportedOperations`] if any policy is specified.
+    pub channel_policies: HashMap<PackageName, PackageChannelPolicy>,
+
     /// Exclude any package that has a timestamp newer than the specified
     /// timestamp.
     pub exclude_newer: Option<DateTime<Utc>>,
 
+    /// When `true`, `exclude_newer` also applies to the `locked_packages` and
+    /// the `pinned_packages`. Locked packages that are newer than the cutoff
+    /// are ignored and a pinned package that is newer than the cutoff results
+    /// in [`SolveError::PinnedPackageExcludedByDate`]. This makes it possible
+    /// to reproduce a solve at a point in time regardless of what is
+    /// currently installed.
+    ///
+    /// By default the cutoff only applies to the available packages.
+    pub exclude_newer_applies_to_locked: bool,
+
     /// The solve strategy.
     pub strategy: SolveStrategy,
+
+    /// An optional function that reorders the candidates of a package after
+    /// the built-in ordering has been applied. See [`CandidateOrdering`]. Not
+    /// all backends support this, in which case the option is ignored.
+    pub candidate_ordering: Option<CandidateOrdering>,
+
+    /// Determines how strongly the `track_features` of packages
+    /// de-prioritize them. See [`TrackFeaturesPolicy`]. Not all backends
+    /// support this, in which case the option is ignored and packages with
+    /// track features are always de-prioritized.
+    pub track_features: TrackFeaturesPolicy,
+
+    /// When `true`, the dependencies of packages are ignored, like
+    /// `conda install --no-deps`. The solution only contains the packages
+    /// that are required by the `specs` (and the `pinned_packages`). The
+    /// constraints of these packages and the `constraints` of the task are
+    /// still checked, so conflicts between the requested packages are still
+    /// reported.
+    ///
+    /// The packages in the solution are not guaranteed to be usable on their
+    /// own. To install them into an existing environment, the installed
+    /// packages that are not part of the solution have to be kept.
+    pub no_deps: bool,
 }
 
 impl<'r, I: IntoIterator<Item = &'r RepoDataRecord>> FromIterator<I>
@@ -163,10 +501,137 @@ impl<'r, I: IntoIterator<Item = &'r RepoDataRecord>> FromIterator<I>
             specs: Vec::new(),
             constraints: Vec::new(),
             timeout: None,
+            best_effort: false,
             channel_priority: ChannelPriority::default(),
+            channel_policies: HashMap::new(),
             exclude_newer: None,
+            exclude_newer_applies_to_locked: false,
             strategy: SolveStrategy::default(),
+            candidate_ordering: None,
+            track_features: TrackFeaturesPolicy::default(),
+            no_deps: false,
+        }
+    }
+}
+
+impl<TAvailablePackagesIterator> SolverTask<TAvailablePackagesIterator> {
+    /// Favors the installed packages of a prefix to keep the changes to the
+    /// environment minimal, like conda does when installing new packages.
+    ///
+    /// The installed packages are added to the [`Self::locked_packages`],
+    /// except for the packages in `update` which are free to be updated.
+    /// Packages that are already locked or pinned are left untouched.
+    #[must_use]
+    pub fn with_installed_packages(
+        mut self,
+        installed: impl IntoIterator<Item = PrefixRecord>,
+        update: &[PackageName],
+    ) -> Self {
+        for record in installed {
+            let name = &record.repodata_record.package_record.name;
+            let already_selected = self
+                .locked_packages
+                .iter()
+                .chain(&self.pinned_packages)
+                .any(|locked| &locked.package_record.name == name);
+            if !already_selected && !update.contains(name) {
+                self.locked_packages.push(record.repodata_record);
+            }
+        }
+        self
+    }
+
+    /// Converts the available packages of the task with `f`, keeping all
+    /// other options of the task.
+    pub fn map_available_packages<U>(
+        self,
+        f: impl FnOnce(TAvailablePackagesIterator) -> U,
+    ) -> SolverTask<U> {
+        SolverTask {
+            available_packages: f(self.available_packages),
+            locked_packages: self.locked_packages,
+            pinned_packages: self.pinned_packages,
+            virtual_packages: self.virtual_packages,
+            specs: self.specs,
+            constraints: self.constraints,
+            timeout: self.timeout,
+            best_effort: self.best_effort,
+            channel_priority: self.channel_priority,
+            channel_policies: self.channel_policies,
+            exclude_newer: self.exclude_newer,
+            exclude_newer_applies_to_locked: self.exclude_newer_applies_to_locked,
+            strategy: self.strategy,
+            candidate_ordering: self.candidate_ordering,
+            track_features: self.track_features,
+            no_deps: self.no_deps,
+        }
+    }
+
+    /// Applies `exclude_newer` to the locked and pinned packages if
+    /// `exclude_newer_applies_to_locked` is set. Locked packages that are
+    /// newer than the cutoff are removed from the task.
+    pub(crate) fn apply_exclude_newer_to_locked(&mut self) -> Result<(), SolveError> {
+        let Some(exclude_newer) = self
+            .exclude_newer
+            .filter(|_| self.exclude_newer_applies_to_locked)
+        else {
+            return Ok(());
+        };
+        let is_excluded = |record: &RepoDataRecord| {
+            record
+                .package_record
+                .timestamp
+                .is_some_and(|timestamp| timestamp > exclude_newer)
+        };
+
+        if let Some(record) = self
+            .pinned_packages
+            .iter()
+            .find(|record| is_excluded(record))
+        {
+            return Err(SolveError::PinnedPackageExcludedByDate {
+                package: record.file_name.clone(),
+                exclude_newer,
+            });
+        }
+
+        self.locked_packages.retain(|record| {
+            let excluded = is_excluded(record);
+            if excluded {
+                tracing::debug!(
+                    "Ignoring locked package '{}' because it is uploaded after the cutoff date of {exclude_newer}.",
+                    record.file_name
+                );
+            }
+            !excluded
+        });
+
+        Ok(())
+    }
+
+    /// Verifies that the constraints on virtual packages are satisfied by the
+    /// provided virtual packages. The solvers would also reject these, but
+    /// checking them upfront results in a more helpful error.
+    pub(crate) fn check_virtual_package_constraints(&self) -> Result<(), SolveError> {
+        for constraint in &self.constraints {
+            let Some(name) = &constraint.name else {
+                continue;
+            };
+            let Some(virtual_package) = self
+                .virtual_packages
+                .iter()
+                .find(|virtual_package| &virtual_package.name == name)
+            else {
+                continue;
+            };
+            if !constraint.matches(virtual_package) {
+                return Err(SolveError::VirtualPackageConstraintViolated {
+                    constraint: constraint.to_string(),
+                    virtual_package: virtual_package.to_string(),
+                });
+            }
         }
+        Ok(())
     }
 }
 
@@ -192,6 +657,31 @@ pub enum SolveStrategy {
     LowestVersionDirect,
 }
 
+/// Determines how the `track_features` of packages affect the order in which
+/// the solver considers the candidates of a package.
+///
+/// Track features are used to de-prioritize variants of a package, e.g. a
+/// debug build. Some channels misuse them, in which case the default
+/// de-prioritization results in unexpected solutions.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum TrackFeaturesPolicy {
+    /// Candidates with track features are considered after all candidates
+    /// without track features, regardless of their version. This is the
+    /// behavior of conda.
+    #[default]
+    Strict,
+
+    /// Track features only de-prioritize candidates with the same version. A
+    /// higher version with track features is still preferred over a lower
+    /// version without track features.
+    SameVersion,
+
+    /// Track features do not affect the order of the candidates.
+    Ignore,
+}
+
 /// A representation of a collection of [`RepoDataRecord`] usable by a
 /// [`SolverImpl`] implementation.
 ///
@@ -241,3 +731,118 @@ impl<'a, T: IntoIterator<Item = &'a RepoDataRecord>, S: SolverRepoData<'a>> Into
         self.0.into_iter().collect()
     }
 }
+
+/// Returns a normalized representation of the channel of a record, see
+/// [`rattler_conda_types::ChannelUrl`]. Channels that are not a url, e.g.
+/// channel names, are returned as is.
+pub(crate) fn normalized_channel(channel: &str) -> String {
+    channel
+        .parse::<ChannelUrl>()
+        .map_or_else(|_| channel.to_string(), |url| url.to_string())
+}
+
+/// Sorts the records of a single repodata so that the order in which they are
+/// passed to a solver does not depend on the order in which they were read.
+///
+/// Records are grouped by channel, in the order in which the channels first
+/// appear, because the channel order determines the channel priority. Within
+/// a channel the records are sorted by package name and url.
+pub(crate) fn normalize_record_order(records: &mut [&RepoDataRecord]) {
+    let mut channel_order = HashMap::new();
+    for &record in records.iter() {
+        let next = channel_order.len();
+        channel_order.entry(record.channel.as_str()).or_insert(next);
+    }
+    records.sort_by(|a, b| {
+        channel_order[a.channel.as_str()]
+            .cmp(&channel_order[b.channel.as_str()])
+            .then_with(|| a.package_record.name.cmp(&b.package_record.name))
+            .then_with(|| a.url.cmp(&b.url))
+    });
+}
+
+/// Returns the dependencies of a record, including the ones that are implied
+/// by the record.
+///
+/// A `noarch: python` package is installed into the site-packages directory
+/// of the python in the environment and can therefore not be installed
+/// without it. Older packages do not always declare this dependency, in which
+/// case an unconstrained dependency on `python` is added.
+pub(crate) fn record_dependencies(record: &PackageRecord) -> impl Iterator<Item = &str> {
+    let implicit_python = (record.noarch.is_python()
+        && !record
+            .dependency_names()
+            .any(|name| name.as_normalized() == "python"))
+    .then_some("python");
+    record
+        .depends
+        .iter()
+        .map(String::as_str)
+        .chain(implicit_python)
+}
+
+#[cfg(test)]
+mod test {
+    use rattler_conda_types::{NoArchType, PackageName, PackageRecord, Version};
+
+    use super::record_dependencies;
+
+    #[test]
+    fn test_record_dependencies() {
+        let record = |noarch: NoArchType, depends: &[&str]| {
+            let mut record = PackageRecord::new(
+                PackageName::new_unchecked("foo"),
+                Version::major(1),
+                String::from("0"),
+            );
+            record.noarch = noarch;
+            record.depends = depends.iter().map(ToString::to_string).collect();
+            record
+        };
+
+        // Only noarch python packages without a dependency on python depend on
+        // python implicitly.
+        for (noarch, depends, expected) in [
+            (NoArchType::none(), &[][..], &[][..]),
+            (NoArchType::generic(), &["six"][..], &["six"][..]),
+            (NoArchType::python(), &["six"][..], &["six", "python"][..]),
+            (
+                NoArchType::python(),
+                &["python-dateutil", "python3"][..],
+                &["python-dateutil", "python3", "python"][..],
+            ),
+            (
+                NoArchType::python(),
+                &["python_abi 3.12.*"][..],
+                &["python_abi 3.12.*", "python"][..],
+            ),
+            (
+                NoArchType::python(),
+                &["python >=3.8"][..],
+                &["python >=3.8"][..],
+            ),
+            (
+                NoArchType::python(),
+                &["python>=3.8"][..],
+                &["python>=3.8"][..],
+            ),
+            (
+                NoArchType::python(),
+                &["conda-forge::python 3.12.*"][..],
+                &["conda-forge::python 3.12.*"][..],
+            ),
+            (
+                NoArchType::python(),
+                &["python[version='>=3.8']"][..],
+                &["python[version='>=3.8']"][..],
+            ),
+        ] {
+            let record = record(noarch, depends);
+            assert_eq!(
+                record_dependencies(&record).collect::<Vec<_>>(),
+                expected,
+                "{depends:?}"
+            );
+        }
+    }
+}
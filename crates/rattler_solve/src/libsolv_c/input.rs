@@ -47,7 +47,8 @@ pub fn add_solv_file(pool: &Pool, repo: &Repo<'_>, solv_bytes: &LibcByteSlice) {
     unsafe { libc::fclose(file) };
 }
 
-/// Adds [`RepoDataRecord`] to `repo`
+/// Adds [`RepoDataRecord`] to `repo`. If `no_deps` is `true` the dependencies
+/// of the records are not added, only their constraints.
 ///
 /// Panics if the repo does not belong to the pool
 pub fn add_repodata_records<'a>(
@@ -55,6 +56,7 @@ pub fn add_repodata_records<'a>(
     repo: &Repo<'_>,
     repo_datas: impl IntoIterator<Item = &'a RepoDataRecord>,
     exclude_newer: Option<&DateTime<Utc>>,
+    no_deps: bool,
 ) -> Result<Vec<SolvableId>, SolveError> {
     // Sanity check
     repo.ensure_belongs_to_pool(pool);
@@ -121,7 +123,7 @@ pub fn add_repodata_records<'a>(
         );
 
         // Dependencies
-        for match_spec in record.depends.iter() {
+        for match_spec in crate::record_dependencies(record).filter(|_| !no_deps) {
             // Create a reldep id from a matchspec
             let match_spec_id = pool.conda_matchspec(&c_string(match_spec));
 
@@ -324,7 +326,7 @@ pub fn cache_repodata(
     // Add repodata to a new pool + repo
     let pool = Pool::default();
     let repo = Repo::new(&pool, url, channel_priority.unwrap_or(0));
-    add_repodata_records(&pool, &repo, data, None)?;
+    add_repodata_records(&pool, &repo, data, None, false)?;
 
     // Export repo to .solv in memory
     let mut stream_ptr = std::ptr::null_mut();
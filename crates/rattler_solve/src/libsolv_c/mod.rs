@@ -4,6 +4,7 @@ use std::{
     collections::{HashMap, HashSet},
     ffi::CString,
     mem::ManuallyDrop,
+    time::Instant,
 };
 
 pub use input::cache_repodata;
@@ -11,6 +12,7 @@ use input::{add_repodata_records, add_solv_file, add_virtual_packages};
 pub use libc_byte_slice::LibcByteSlice;
 use output::get_required_packages;
 use rattler_conda_types::{MatchSpec, NamelessMatchSpec, RepoDataRecord};
+use tracing::instrument;
 use wrapper::{
     flags::SolverFlag,
     pool::{Pool, Verbosity},
@@ -18,7 +20,10 @@ use wrapper::{
     solve_goal::SolveGoal,
 };
 
-use crate::{ChannelPriority, IntoRepoData, SolveError, SolveStrategy, SolverRepoData, SolverTask};
+use crate::{
+    ChannelPriority, IntoRepoData, SolveError, SolveQuality, SolveStatistics, SolveStrategy,
+    SolverRepoData, SolverResult, SolverTask,
+};
 
 mod input;
 mod libc_byte_slice;
@@ -95,6 +100,19 @@ impl super::SolverImpl for Solver {
         &mut self,
         task: SolverTask<TAvailablePackagesIterator>,
     ) -> Result<Vec<RepoDataRecord>, SolveError> {
+        self.solve_with_statistics(task)
+            .map(|result| result.records)
+    }
+
+    #[instrument(name = "solve", skip_all, fields(backend = "libsolv_c", specs = task.specs.len()))]
+    fn solve_with_statistics<
+        'a,
+        R: IntoRepoData<'a, Self::RepoData<'a>>,
+        TAvailablePackagesIterator: IntoIterator<Item = R>,
+    >(
+        &mut self,
+        mut task: SolverTask<TAvailablePackagesIterator>,
+    ) -> Result<SolverResult, SolveError> {
         if task.timeout.is_some() {
             return Err(SolveError::UnsupportedOperations(vec![
                 "timeout".to_string()
@@ -107,6 +125,31 @@ impl super::SolverImpl for Solver {
             ]));
         }
 
+        if !task.channel_policies.is_empty() {
+            return Err(SolveError::UnsupportedOperations(vec![
+                "channel_policies".to_string()
+            ]));
+        }
+
+        // libsolv does not know the subdir of a spec, the candidates would not
+        // be restricted to it.
+        if task
+            .specs
+            .iter()
+            .chain(&task.constraints)
+            .any(|spec| spec.subdir.is_some())
+        {
+            return Err(SolveError::UnsupportedOperations(
+                vec!["subdir".to_string()],
+            ));
+        }
+
+        task.apply_exclude_newer_to_locked()?;
+        task.check_virtual_package_constraints()?;
+
+        let load_start = Instant::now();
+        let load_span = tracing::debug_span!("construct_pool").entered();
+
         // Construct a default libsolv pool
         let pool = Pool::default();
 
@@ -116,12 +159,21 @@ impl super::SolverImpl for Solver {
         });
         pool.set_debug_level(Verbosity::Low);
 
-        let repodatas: Vec<Self::RepoData<'_>> = task
+        let mut repodatas: Vec<Self::RepoData<'_>> = task
             .available_packages
             .into_iter()
             .map(IntoRepoData::into)
             .collect();
 
+        // The records of a cached .solv file must stay in the order in which
+        // the file was created.
+        for repodata in repodatas
+            .iter_mut()
+            .filter(|repodata| repodata.solv_file.is_none())
+        {
+            crate::normalize_record_order(&mut repodata.records);
+        }
+
         // Determine the channel priority for each channel in the repodata in the order
         // in which the repodatas are passed, where the first channel will have
         // the highest priority value and each successive channel will descend
@@ -136,7 +188,7 @@ impl super::SolverImpl for Solver {
                 for channel in repodatas
                     .iter()
                     .filter(|&r| !r.records.is_empty())
-                    .map(|r| r.records[0].channel.clone())
+                    .map(|r| crate::normalized_channel(&r.records[0].channel))
                 {
                     if !seen_channels.contains(&channel) {
                         channel_order.push(channel.clone());
@@ -174,21 +226,26 @@ impl super::SolverImpl for Solver {
 
             // We dont want to drop the Repo, its stored in the pool anyway.
             let priority: i32 = if task.channel_priority == ChannelPriority::Strict {
-                *channel_priority.get(channel_name).unwrap()
+                *channel_priority
+                    .get(&crate::normalized_channel(channel_name))
+                    .unwrap()
             } else {
                 0
             };
             let repo = ManuallyDrop::new(Repo::new(&pool, channel_name, priority));
 
-            if let Some(solv_file) = repodata.solv_file {
-                add_solv_file(&pool, &repo, solv_file);
-            } else {
-                add_repodata_records(
-                    &pool,
-                    &repo,
-                    repodata.records.iter().copied(),
-                    task.exclude_newer.as_ref(),
-                )?;
+            // The cached solv file contains the dependencies of the records.
+            match repodata.solv_file {
+                Some(solv_file) if !task.no_deps => add_solv_file(&pool, &repo, solv_file),
+                _ => {
+                    add_repodata_records(
+                        &pool,
+                        &repo,
+                        repodata.records.iter().copied(),
+                        task.exclude_newer.as_ref(),
+                        task.no_deps,
+                    )?;
+                }
             }
 
             // Keep our own info about repodata_records
@@ -198,7 +255,8 @@ impl super::SolverImpl for Solver {
 
         // Create a special pool for records that are already installed or locked.
         let repo = Repo::new(&pool, "locked", highest_priority);
-        let installed_solvables = add_repodata_records(&pool, &repo, &task.locked_packages, None)?;
+        let installed_solvables =
+            add_repodata_records(&pool, &repo, &task.locked_packages, None, task.no_deps)?;
 
         // Also add the installed records to the repodata
         repo_mapping.insert(repo.id(), repo_mapping.len());
@@ -206,7 +264,8 @@ impl super::SolverImpl for Solver {
 
         // Create a special pool for records that are pinned and cannot be changed.
         let repo = Repo::new(&pool, "pinned", highest_priority);
-        let pinned_solvables = add_repodata_records(&pool, &repo, &task.pinned_packages, None)?;
+        let pinned_solvables =
+            add_repodata_records(&pool, &repo, &task.pinned_packages, None, task.no_deps)?;
 
         // Also add the installed records to the repodata
         repo_mapping.insert(repo.id(), repo_mapping.len());
@@ -250,7 +309,21 @@ impl super::SolverImpl for Solver {
             goal.install(id, false);
         }
 
+        // The first two solvables are reserved by libsolv. All other solvables
+        // are the packages that were loaded into the pool, which libsolv
+        // considers as candidates.
+        let candidates_considered = u64::try_from(pool.as_ref().nsolvables - 2).unwrap_or(0);
+        tracing::debug!(
+            repos = repo_mapping.len(),
+            solvables = candidates_considered,
+            "constructed the libsolv pool"
+        );
+        let load_duration = load_start.elapsed();
+        drop(load_span);
+
         // Construct a solver and solve the problems in the queue
+        let solve_start = Instant::now();
+        let solve_span = tracing::debug_span!("run_solver").entered();
         let mut solver = pool.create_solver();
         solver.set_flag(SolverFlag::allow_uninstall(), true);
         solver.set_flag(SolverFlag::allow_downgrade(), true);
@@ -260,7 +333,12 @@ impl super::SolverImpl for Solver {
         );
 
         let transaction = solver.solve(&mut goal).map_err(SolveError::Unsolvable)?;
+        tracing::debug!(decisions = solver.decision_count(), "solved the goal");
+        let solve_duration = solve_start.elapsed();
+        drop(solve_span);
 
+        let extract_start = Instant::now();
+        let extract_span = tracing::debug_span!("extract_result").entered();
         let required_records = get_required_packages(
             &pool,
             &repo_mapping,
@@ -275,8 +353,30 @@ impl super::SolverImpl for Solver {
                     .collect(),
             )
         })?;
-
-        Ok(required_records)
+        drop(transaction);
+        tracing::debug!(records = required_records.len(), "extracted the solution");
+        drop(extract_span);
+
+        let statistics = SolveStatistics {
+            candidates_considered,
+            decisions: Some(solver.decision_count() as u64),
+            dependencies_requested: None,
+            clauses_learned: None,
+            restarts: None,
+            load_duration,
+            solve_duration,
+            extract_duration: extract_start.elapsed(),
+            sort_duration: None,
+            match_specs_parsed: None,
+            match_spec_cache_hits: None,
+        };
+        tracing::debug!("solve statistics: {statistics:?}");
+
+        Ok(SolverResult {
+            records: required_records,
+            statistics,
+            quality: SolveQuality::Optimal,
+        })
     }
 }
 
@@ -124,6 +124,13 @@ impl<'pool> Solver<'pool> {
         problems
     }
 
+    /// Returns the number of decisions the solver made during the last solve.
+    pub fn decision_count(&self) -> usize {
+        let mut decisions = Queue::<ffi::Id>::default();
+        unsafe { ffi::solver_get_decisionqueue(self.raw_ptr(), decisions.raw_ptr()) };
+        decisions.id_iter().count()
+    }
+
     /// Sets a solver flag
     pub fn set_flag(&self, flag: SolverFlag, value: bool) {
         unsafe { ffi::solver_set_flag(self.raw_ptr(), flag.inner(), i32::from(value)) };
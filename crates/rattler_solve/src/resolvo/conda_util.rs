@@ -4,7 +4,7 @@ use futures::future::FutureExt;
 use rattler_conda_types::Version;
 use resolvo::{Dependencies, SolvableId, SolverCache, VersionSetId};
 
-use crate::resolvo::CondaDependencyProvider;
+use crate::{resolvo::CondaDependencyProvider, TrackFeaturesPolicy};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub(super) enum CompareStrategy {
@@ -23,6 +23,8 @@ pub(super) fn compare_candidates(
         Option<(rattler_conda_types::Version, bool)>,
     >,
     strategy: CompareStrategy,
+    track_features: TrackFeaturesPolicy,
+    dependency_aware: bool,
 ) -> Ordering {
     let pool = &solver.provider().pool;
 
@@ -36,11 +38,13 @@ pub(super) fn compare_candidates(
     // feature it is sorted below the one that doesn't have the tracked feature.
     let a_has_tracked_features = !a_record.track_features().is_empty();
     let b_has_tracked_features = !b_record.track_features().is_empty();
-    match a_has_tracked_features.cmp(&b_has_tracked_features) {
-        Ordering::Less => return Ordering::Less,
-        Ordering::Greater => return Ordering::Greater,
-        Ordering::Equal => {}
-    };
+    if track_features == TrackFeaturesPolicy::Strict {
+        match a_has_tracked_features.cmp(&b_has_tracked_features) {
+            Ordering::Less => return Ordering::Less,
+            Ordering::Greater => return Ordering::Greater,
+            Ordering::Equal => {}
+        };
+    }
 
     // Otherwise, select the variant with the highest version
     match (strategy, a_record.version().cmp(b_record.version())) {
@@ -51,6 +55,16 @@ pub(super) fn compare_candidates(
         (_, Ordering::Equal) => {}
     };
 
+    // If the policy only applies tracked features to variants with the same
+    // version, compare them now.
+    if track_features == TrackFeaturesPolicy::SameVersion {
+        match a_has_tracked_features.cmp(&b_has_tracked_features) {
+            Ordering::Less => return Ordering::Less,
+            Ordering::Greater => return Ordering::Greater,
+            Ordering::Equal => {}
+        };
+    }
+
     // Otherwise, select the variant with the highest build number
     match a_record.build_number().cmp(&b_record.build_number()) {
         Ordering::Less => return Ordering::Greater,
@@ -58,7 +72,9 @@ pub(super) fn compare_candidates(
         Ordering::Equal => {}
     };
 
-    // return Ordering::Equal;
+    if !dependency_aware {
+        return Ordering::Equal;
+    }
 
     // Otherwise, compare the dependencies of the variants. If there are similar
     // dependencies select the variant that selects the highest version of the
@@ -129,18 +145,22 @@ pub(super) fn compare_candidates(
 
             // If one of the dependencies only selects versions with tracked features, down-
             // weigh that variant.
-            if let Some(score) = match a_tracked_features.cmp(&b_tracked_features) {
-                Ordering::Less => Some(-100),
-                Ordering::Greater => Some(100),
-                Ordering::Equal => None,
-            } {
-                total_score += score;
+            let tracked_features_score = match a_tracked_features.cmp(&b_tracked_features) {
+                Ordering::Less => -1,
+                Ordering::Greater => 1,
+                Ordering::Equal => 0,
+            };
+            if track_features == TrackFeaturesPolicy::Strict && tracked_features_score != 0 {
+                total_score += 100 * tracked_features_score;
                 continue;
             }
 
             // Otherwise, down-weigh the version with the lowest selected version.
             total_score += match a_version.cmp(&b_version) {
                 Ordering::Less => 1,
+                Ordering::Equal if track_features == TrackFeaturesPolicy::SameVersion => {
+                    tracked_features_score
+                }
                 Ordering::Equal => 0,
                 Ordering::Greater => -1,
             };
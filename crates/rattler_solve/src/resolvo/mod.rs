@@ -1,20 +1,24 @@
 //! Provides an solver implementation based on the [`resolvo`] crate.
 
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     cmp::Ordering,
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     fmt::{Display, Formatter},
     marker::PhantomData,
     ops::Deref,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use chrono::{DateTime, Utc};
 use itertools::Itertools;
 use rattler_conda_types::{
-    package::ArchiveType, GenericVirtualPackage, MatchSpec, Matches, NamelessMatchSpec,
-    PackageName, PackageRecord, ParseMatchSpecError, ParseStrictness, RepoDataRecord,
+    package::ArchiveType, version_spec::EqualityOperator, ChannelUrl, GenericVirtualPackage,
+    MatchSpec, Matches, NamelessMatchSpec, PackageName, PackageRecord, ParseMatchSpecError,
+    ParseStrictness, RepoDataRecord, StringMatcher, VersionSpec,
 };
+use rayon::prelude::*;
 use resolvo::{
     utils::{Pool, VersionSet},
     Candidates, Dependencies, DependencyProvider, Interner, KnownDependencies, NameId, SolvableId,
@@ -22,9 +26,11 @@ use resolvo::{
 };
 
 use crate::{
-    resolvo::conda_util::CompareStrategy, ChannelPriority, IntoRepoData, SolveError, SolveStrategy,
-    SolverRepoData, SolverTask,
+    resolvo::conda_util::CompareStrategy, CancellationDiagnostics, CandidateOrdering,
+    ChannelPriority, IntoRepoData, PackageChannelPolicy, SolveCompromise, SolveError, SolveQuality,
+    SolveStatistics, SolveStrategy, SolverRepoData, SolverResult, SolverTask, TrackFeaturesPolicy,
 };
+use tracing::instrument;
 
 mod conda_util;
 
@@ -85,6 +91,8 @@ impl<'a> VersionSet for SolverMatchSpec<'a> {
 #[derive(Eq, PartialEq)]
 enum SolverPackageRecord<'a> {
     Record(&'a RepoDataRecord),
+    /// An extra of a record, see [`add_extra_candidates`].
+    Extra(&'a RepoDataRecord, &'a str),
     VirtualPackage(&'a GenericVirtualPackage),
 }
 
@@ -107,14 +115,18 @@ impl<'a> Ord for SolverPackageRecord<'a> {
 impl<'a> SolverPackageRecord<'a> {
     fn name(&self) -> &PackageName {
         match self {
-            SolverPackageRecord::Record(rec) => &rec.package_record.name,
+            SolverPackageRecord::Record(rec) | SolverPackageRecord::Extra(rec, _) => {
+                &rec.package_record.name
+            }
             SolverPackageRecord::VirtualPackage(rec) => &rec.name,
         }
     }
 
     fn version(&self) -> &rattler_conda_types::Version {
         match self {
-            SolverPackageRecord::Record(rec) => rec.package_record.version.version(),
+            SolverPackageRecord::Record(rec) | SolverPackageRecord::Extra(rec, _) => {
+                rec.package_record.version.version()
+            }
             SolverPackageRecord::VirtualPackage(rec) => &rec.version,
         }
     }
@@ -122,21 +134,27 @@ impl<'a> SolverPackageRecord<'a> {
     fn track_features(&self) -> &[String] {
         const EMPTY: [String; 0] = [];
         match self {
-            SolverPackageRecord::Record(rec) => &rec.package_record.track_features,
+            SolverPackageRecord::Record(rec) | SolverPackageRecord::Extra(rec, _) => {
+                &rec.package_record.track_features
+            }
             SolverPackageRecord::VirtualPackage(_rec) => &EMPTY,
         }
     }
 
     fn build_number(&self) -> u64 {
         match self {
-            SolverPackageRecord::Record(rec) => rec.package_record.build_number,
+            SolverPackageRecord::Record(rec) | SolverPackageRecord::Extra(rec, _) => {
+                rec.package_record.build_number
+            }
             SolverPackageRecord::VirtualPackage(_rec) => 0,
         }
     }
 
     fn timestamp(&self) -> Option<&chrono::DateTime<chrono::Utc>> {
         match self {
-            SolverPackageRecord::Record(rec) => rec.package_record.timestamp.as_ref(),
+            SolverPackageRecord::Record(rec) | SolverPackageRecord::Extra(rec, _) => {
+                rec.package_record.timestamp.as_ref()
+            }
             SolverPackageRecord::VirtualPackage(_rec) => None,
         }
     }
@@ -148,6 +166,9 @@ impl<'a> Display for SolverPackageRecord<'a> {
             SolverPackageRecord::Record(rec) => {
                 write!(f, "{}", &rec.package_record)
             }
+            SolverPackageRecord::Extra(rec, extra) => {
+                write!(f, "{}[{extra}]", &rec.package_record)
+            }
             SolverPackageRecord::VirtualPackage(rec) => {
                 write!(f, "{rec}")
             }
@@ -158,6 +179,10 @@ impl<'a> Display for SolverPackageRecord<'a> {
 /// An implement of [`resolvo::DependencyProvider`] that implements the
 /// ecosystem behavior for conda. This allows resolvo to solve for conda
 /// packages.
+///
+/// The provider borrows the records of the [`RepoData`] it is constructed
+/// from, the pool only stores references to them. Use
+/// [`Solver::solve_shared`] to also avoid cloning the records of the solution.
 #[derive(Default)]
 pub struct CondaDependencyProvider<'a> {
     pool: Pool<SolverMatchSpec<'a>, String>,
@@ -169,16 +194,66 @@ pub struct CondaDependencyProvider<'a> {
 
     parse_match_spec_cache: RefCell<HashMap<&'a str, VersionSetId>>,
 
+    /// The match specs of the dependencies and constraints of all candidates,
+    /// parsed up front in parallel. A spec is moved into the pool the first
+    /// time the solver requests it.
+    pre_parsed_match_specs: RefCell<HashMap<&'a str, Result<MatchSpec, ParseMatchSpecError>>>,
+
+    /// The result of sorting candidates, keyed by package name and the order
+    /// of the candidates before sorting. The solver requests the sorted
+    /// candidates for every version set, and different version sets often
+    /// match the same candidates.
+    sorted_candidates_cache: RefCell<HashMap<NameId, HashMap<Vec<SolvableId>, Vec<SolvableId>>>>,
+
+    /// The number of times a match spec was found in the
+    /// `parse_match_spec_cache`.
+    parse_match_spec_cache_hits: Cell<u64>,
+
     stop_time: Option<std::time::SystemTime>,
 
     strategy: SolveStrategy,
 
+    /// Whether candidates with the same version and build number are ordered
+    /// by the versions of their dependencies.
+    dependency_aware_sorting: bool,
+
+    /// A user provided function that reorders the candidates after they have
+    /// been sorted.
+    candidate_ordering: Option<CandidateOrdering>,
+
+    /// Determines how the track features of candidates affect their order.
+    track_features: TrackFeaturesPolicy,
+
+    /// When `true` the dependencies of candidates are ignored, only their
+    /// constraints are taken into account.
+    no_deps: bool,
+
     direct_dependencies: HashSet<NameId>,
+
+    /// The total number of candidates that have been handed to the solver.
+    candidates_considered: Cell<u64>,
+
+    /// The number of solvables for which the solver requested the
+    /// dependencies. The solver only does this for solvables it decided to
+    /// install.
+    dependencies_requested: Cell<u64>,
+
+    /// The total time spent sorting candidates.
+    sort_duration: Cell<Duration>,
+
+    /// The most recent version sets that did not match any of the candidates,
+    /// used to report diagnostics when the solver is cancelled.
+    recent_conflicting_specs: RefCell<VecDeque<VersionSetId>>,
 }
 
+/// The maximum number of conflicting specs that are remembered for
+/// [`CancellationDiagnostics`].
+const MAX_RECENT_CONFLICTING_SPECS: usize = 5;
+
 impl<'a> CondaDependencyProvider<'a> {
     /// Constructs a new provider.
     #[allow(clippy::too_many_arguments)]
+    #[instrument(name = "construct_pool", skip_all)]
     pub fn new(
         repodata: impl IntoIterator<Item = RepoData<'a>>,
         favored_records: &'a [RepoDataRecord],
@@ -187,6 +262,7 @@ impl<'a> CondaDependencyProvider<'a> {
         match_specs: &[MatchSpec],
         stop_time: Option<std::time::SystemTime>,
         channel_priority: ChannelPriority,
+        channel_policies: &HashMap<PackageName, PackageChannelPolicy>,
         exclude_newer: Option<DateTime<Utc>>,
         strategy: SolveStrategy,
     ) -> Result<Self, SolveError> {
@@ -211,11 +287,16 @@ impl<'a> CondaDependencyProvider<'a> {
         // TODO: Normalize these channel names to urls so we can compare them correctly.
         let channel_specific_specs = match_specs
             .iter()
-            .filter(|spec| spec.channel.is_some())
+            .filter(|spec| spec.channel.is_some() || spec.subdir.is_some())
             .collect::<Vec<_>>();
 
         // Hashmap that maps the package name to the channel it was first found in.
-        let mut package_name_found_in_channel = HashMap::<String, &String>::new();
+        let mut package_name_found_in_channel = HashMap::<String, usize>::new();
+        let mut channel_ids = ChannelIds::default();
+
+        // All records that the solver might consider, their match specs are
+        // parsed upfront.
+        let mut records_to_parse = Vec::new();
 
         // Add additional records
         for repo_datas in repodata {
@@ -230,6 +311,8 @@ impl<'a> CondaDependencyProvider<'a> {
             // runtime so instead we store the values in a Vec as we iterate over the
             // records. This guarentees that the order of records remains the same over
             // runs.
+            let dedup_span =
+                tracing::trace_span!("dedup_records", records = repo_datas.records.len()).entered();
             let mut ordered_repodata = Vec::with_capacity(repo_datas.records.len());
             let mut package_to_type: HashMap<&str, (ArchiveType, usize, bool)> =
                 HashMap::with_capacity(repo_datas.records.len());
@@ -285,6 +368,8 @@ impl<'a> CondaDependencyProvider<'a> {
                     }
                 }
             }
+            tracing::trace!(records = ordered_repodata.len(), "deduplicated records");
+            drop(dedup_span);
 
             for record in ordered_repodata {
                 let package_name =
@@ -307,18 +392,16 @@ impl<'a> CondaDependencyProvider<'a> {
                     _ => {}
                 }
 
-                // Add to excluded when package is not in the specified channel.
+                // Add to excluded when package is not in the specified channel or subdir.
                 if !channel_specific_specs.is_empty() {
                     if let Some(spec) = channel_specific_specs.iter().find(|&&spec| {
-                        spec.name
-                            .as_ref()
-                            .expect("expecting a name")
-                            .as_normalized()
-                            == record.package_record.name.as_normalized()
+                        spec.name.as_ref().is_some_and(|name| {
+                            name.as_normalized() == record.package_record.name.as_normalized()
+                        })
                     }) {
                         // Check if the spec has a channel, and compare it to the repodata channel
                         if let Some(spec_channel) = &spec.channel {
-                            if record.channel != spec_channel.base_url.to_string() {
+                            if record.channel_url() != Some(spec_channel.channel_url()) {
                                 tracing::debug!("Ignoring {} from {} because it was not requested from that channel.", &record.package_record.name.as_normalized(), &record.channel);
                                 // Add record to the excluded with reason of being in the non
                                 // requested channel.
@@ -335,19 +418,59 @@ impl<'a> CondaDependencyProvider<'a> {
                                 continue;
                             }
                         }
+
+                        // Check if the spec has a subdir, and compare it to the subdir of the
+                        // record.
+                        if let Some(spec_subdir) = &spec.subdir {
+                            if &record.package_record.subdir != spec_subdir {
+                                tracing::debug!("Ignoring {} from {}/{} because it was not requested from that subdir.", &record.package_record.name.as_normalized(), &record.channel, &record.package_record.subdir);
+                                let message =
+                                    format!("candidate not in requested subdir: '{spec_subdir}'");
+                                candidates
+                                    .excluded
+                                    .push((solvable_id, pool.intern_string(message)));
+                                continue;
+                            }
+                        }
                     }
                 }
 
+                // Add to excluded when the package is pinned to a different channel.
+                let policy = channel_policies.get(&record.package_record.name);
+                if let Some(PackageChannelPolicy::Only(channel)) = policy {
+                    if !channel_matches(&record.channel, channel) {
+                        tracing::debug!(
+                            "Ignoring '{}' from '{}' because it is pinned to '{}'.",
+                            &record.package_record.name.as_normalized(),
+                            &record.channel,
+                            channel
+                        );
+                        candidates.excluded.push((
+                            solvable_id,
+                            pool.intern_string(format!(
+                                "candidate not in the channel '{channel}' that '{}' is pinned to",
+                                record.package_record.name.as_normalized()
+                            )),
+                        ));
+                        continue;
+                    }
+                }
+                let channel_priority = match policy {
+                    Some(PackageChannelPolicy::Priority(priority)) => *priority,
+                    _ => channel_priority,
+                };
+
                 // Enforce channel priority
                 // This function makes the assumption that the records are given in order of the
                 // channels.
+                let channel_id = channel_ids.id(&record.channel);
                 if let (Some(first_channel), ChannelPriority::Strict) = (
                     package_name_found_in_channel
                         .get(&record.package_record.name.as_normalized().to_string()),
                     channel_priority,
                 ) {
                     // Add the record to the excluded list when it is from a different channel.
-                    if first_channel != &&record.channel {
+                    if *first_channel != channel_id {
                         tracing::debug!(
                             "Ignoring '{}' from '{}' because of strict channel priority.",
                             &record.package_record.name.as_normalized(),
@@ -365,11 +488,13 @@ impl<'a> CondaDependencyProvider<'a> {
                 } else {
                     package_name_found_in_channel.insert(
                         record.package_record.name.as_normalized().to_string(),
-                        &record.channel,
+                        channel_id,
                     );
                 }
 
+                records_to_parse.push(&record.package_record);
                 candidates.hint_dependencies_available.push(solvable_id);
+                add_extra_candidates(&pool, &mut records, record);
             }
         }
 
@@ -380,6 +505,8 @@ impl<'a> CondaDependencyProvider<'a> {
             let candidates = records.entry(name).or_default();
             candidates.candidates.push(solvable);
             candidates.favored = Some(solvable);
+            records_to_parse.push(&favored_record.package_record);
+            add_extra_candidates(&pool, &mut records, favored_record);
         }
 
         for locked_record in locked_records {
@@ -388,16 +515,36 @@ impl<'a> CondaDependencyProvider<'a> {
             let candidates = records.entry(name).or_default();
             candidates.candidates.push(solvable);
             candidates.locked = Some(solvable);
+            records_to_parse.push(&locked_record.package_record);
+            add_extra_candidates(&pool, &mut records, locked_record);
         }
 
+        tracing::debug!(
+            names = records.len(),
+            solvables = records.values().map(|c| c.candidates.len()).sum::<usize>(),
+            excluded = records.values().map(|c| c.excluded.len()).sum::<usize>(),
+            "constructed the solver pool"
+        );
+
         Ok(Self {
             pool,
             records,
             matchspec_to_highest_version: RefCell::default(),
             parse_match_spec_cache: RefCell::default(),
+            pre_parsed_match_specs: RefCell::new(parse_match_specs(&records_to_parse, stop_time)),
+            sorted_candidates_cache: RefCell::default(),
+            parse_match_spec_cache_hits: Cell::new(0),
             stop_time,
             strategy,
+            dependency_aware_sorting: true,
+            candidate_ordering: None,
+            track_features: TrackFeaturesPolicy::default(),
+            no_deps: false,
             direct_dependencies,
+            candidates_considered: Cell::new(0),
+            dependencies_requested: Cell::new(0),
+            sort_duration: Cell::new(Duration::ZERO),
+            recent_conflicting_specs: RefCell::default(),
         })
     }
 
@@ -405,6 +552,149 @@ impl<'a> CondaDependencyProvider<'a> {
     pub fn package_names(&self) -> impl Iterator<Item = NameId> + '_ {
         self.records.keys().copied()
     }
+
+    /// Lets the user provided [`CandidateOrdering`] reorder the candidates. The
+    /// order is left untouched if the candidates contain virtual packages or if
+    /// the function did not return a permutation of the candidates.
+    fn apply_candidate_ordering(
+        &self,
+        candidate_ordering: &CandidateOrdering,
+        solvables: &mut [SolvableId],
+    ) {
+        let mut records = Vec::with_capacity(solvables.len());
+        for &solvable in solvables.iter() {
+            match self.pool.resolve_solvable(solvable).record {
+                SolverPackageRecord::Record(record) => records.push(record),
+                SolverPackageRecord::Extra(..) | SolverPackageRecord::VirtualPackage(_) => return,
+            }
+        }
+
+        let solvable_by_record: HashMap<*const RepoDataRecord, SolvableId> = records
+            .iter()
+            .map(|&record| record as *const RepoDataRecord)
+            .zip(solvables.iter().copied())
+            .collect();
+
+        candidate_ordering(&mut records);
+
+        let reordered = records
+            .iter()
+            .map(|&record| {
+                solvable_by_record
+                    .get(&(record as *const RepoDataRecord))
+                    .copied()
+            })
+            .collect::<Option<Vec<_>>>();
+        match reordered {
+            Some(reordered) if reordered.iter().collect::<HashSet<_>>().len() == solvables.len() => {
+                solvables.copy_from_slice(&reordered);
+            }
+            _ => tracing::warn!(
+                "the custom candidate ordering did not return a permutation of the candidates, ignoring it"
+            ),
+        }
+    }
+
+    /// Returns the dependencies of an extra of a record: the record itself and
+    /// the dependencies of the extra.
+    fn extra_dependencies(&self, rec: &'a RepoDataRecord, extra: &'a str) -> Dependencies {
+        let mut dependencies = KnownDependencies::default();
+
+        // Pin the record the extra belongs to.
+        let record = &rec.package_record;
+        let name_id = self.pool.intern_package_name(record.name.as_normalized());
+        let record_spec = NamelessMatchSpec {
+            version: Some(VersionSpec::Exact(
+                EqualityOperator::Equals,
+                record.version.version().clone(),
+            )),
+            build: Some(StringMatcher::Exact(record.build.clone())),
+            subdir: Some(record.subdir.clone()),
+            md5: record.md5,
+            sha256: record.sha256,
+            ..NamelessMatchSpec::default()
+        };
+        dependencies
+            .requirements
+            .push(self.pool.intern_version_set(name_id, record_spec.into()));
+
+        if self.no_deps {
+            return Dependencies::Known(dependencies);
+        }
+
+        let mut parse_match_spec_cache = self.parse_match_spec_cache.borrow_mut();
+        let mut pre_parsed_match_specs = self.pre_parsed_match_specs.borrow_mut();
+        for depends in record.extra_depends.get(extra).into_iter().flatten() {
+            let version_set_id = match parse_match_spec(
+                &self.pool,
+                depends,
+                None,
+                &mut parse_match_spec_cache,
+                &mut pre_parsed_match_specs,
+                &self.parse_match_spec_cache_hits,
+            ) {
+                Ok(version_set_id) => version_set_id,
+                Err(e) => {
+                    let reason = self.pool.intern_string(format!(
+                        "the dependency '{depends}' of the extra '{extra}' failed to parse: {e}",
+                    ));
+
+                    return Dependencies::Unknown(reason);
+                }
+            };
+            dependencies.requirements.push(version_set_id);
+            dependencies
+                .requirements
+                .extend(extra_requirements(&self.pool, version_set_id));
+        }
+
+        Dependencies::Known(dependencies)
+    }
+
+    /// Remembers that none of the candidates matched the given version set.
+    fn record_conflicting_spec(&self, version_set: VersionSetId) {
+        let mut recent = self.recent_conflicting_specs.borrow_mut();
+        if recent.back() == Some(&version_set) {
+            return;
+        }
+        recent.retain(|&id| id != version_set);
+        if recent.len() == MAX_RECENT_CONFLICTING_SPECS {
+            recent.pop_front();
+        }
+        recent.push_back(version_set);
+    }
+
+    /// Returns the diagnostics that describe the state of the solver when it
+    /// was cancelled.
+    fn cancellation_diagnostics(
+        &self,
+        reason: &dyn std::any::Any,
+        elapsed: std::time::Duration,
+    ) -> CancellationDiagnostics {
+        let timed_out = match reason.downcast_ref::<CancelReason>() {
+            Some(CancelReason::Timeout) => true,
+            None => false,
+        };
+
+        CancellationDiagnostics {
+            timed_out,
+            elapsed,
+            candidates_considered: self.candidates_considered.get(),
+            dependencies_requested: self.dependencies_requested.get(),
+            last_conflicting_specs: self
+                .recent_conflicting_specs
+                .borrow()
+                .iter()
+                .map(|&version_set| {
+                    format!(
+                        "{} {}",
+                        self.display_name(self.version_set_name(version_set)),
+                        self.display_version_set(version_set)
+                    )
+                })
+                .collect(),
+        }
+    }
 }
 
 /// The reason why the solver was cancelled
@@ -461,65 +751,137 @@ impl<'a> DependencyProvider for CondaDependencyProvider<'a> {
             return;
         }
 
-        let mut highest_version_spec = self.matchspec_to_highest_version.borrow_mut();
-
-        let strategy = match self.strategy {
-            SolveStrategy::Highest => CompareStrategy::Default,
-            SolveStrategy::LowestVersion => CompareStrategy::LowestVersion,
-            SolveStrategy::LowestVersionDirect => {
-                if self
-                    .direct_dependencies
-                    .contains(&self.pool.resolve_solvable(solvables[0]).name)
-                {
-                    CompareStrategy::LowestVersion
-                } else {
-                    CompareStrategy::Default
+        let sort_start = Instant::now();
+        let name = self.pool.resolve_solvable(solvables[0]).name;
+        let _span = tracing::trace_span!(
+            "sort_candidates",
+            name = %self.pool.resolve_package_name(name),
+            candidates = solvables.len()
+        )
+        .entered();
+
+        let cached = self
+            .sorted_candidates_cache
+            .borrow()
+            .get(&name)
+            .and_then(|sorted| sorted.get(&*solvables).cloned());
+        if let Some(sorted) = cached {
+            solvables.copy_from_slice(&sorted);
+        } else {
+            let unsorted = solvables.to_vec();
+            let mut highest_version_spec = self.matchspec_to_highest_version.borrow_mut();
+
+            let strategy = match self.strategy {
+                SolveStrategy::Highest => CompareStrategy::Default,
+                SolveStrategy::LowestVersion => CompareStrategy::LowestVersion,
+                SolveStrategy::LowestVersionDirect => {
+                    if self.direct_dependencies.contains(&name) {
+                        CompareStrategy::LowestVersion
+                    } else {
+                        CompareStrategy::Default
+                    }
                 }
-            }
-        };
-        solvables.sort_by(|&p1, &p2| {
-            conda_util::compare_candidates(p1, p2, solver, &mut highest_version_spec, strategy)
-        });
+            };
+            solvables.sort_by(|&p1, &p2| {
+                conda_util::compare_candidates(
+                    p1,
+                    p2,
+                    solver,
+                    &mut highest_version_spec,
+                    strategy,
+                    self.track_features,
+                    self.dependency_aware_sorting,
+                )
+            });
+
+            self.sorted_candidates_cache
+                .borrow_mut()
+                .entry(name)
+                .or_default()
+                .insert(unsorted, solvables.to_vec());
+        }
+
+        if let Some(candidate_ordering) = &self.candidate_ordering {
+            self.apply_candidate_ordering(candidate_ordering, solvables);
+        }
+
+        self.sort_duration
+            .set(self.sort_duration.get() + sort_start.elapsed());
     }
 
     async fn get_candidates(&self, name: NameId) -> Option<Candidates> {
-        self.records.get(&name).cloned()
+        let candidates = self.records.get(&name).cloned()?;
+        self.candidates_considered
+            .set(self.candidates_considered.get() + candidates.candidates.len() as u64);
+        Some(candidates)
     }
 
     async fn get_dependencies(&self, solvable: SolvableId) -> Dependencies {
+        self.dependencies_requested
+            .set(self.dependencies_requested.get() + 1);
+
         let mut dependencies = KnownDependencies::default();
-        let SolverPackageRecord::Record(rec) = self.pool.resolve_solvable(solvable).record else {
-            return Dependencies::Known(dependencies);
+        let rec = match self.pool.resolve_solvable(solvable).record {
+            SolverPackageRecord::Record(rec) => rec,
+            SolverPackageRecord::Extra(rec, extra) => return self.extra_dependencies(rec, extra),
+            SolverPackageRecord::VirtualPackage(_) => return Dependencies::Known(dependencies),
         };
 
         let mut parse_match_spec_cache = self.parse_match_spec_cache.borrow_mut();
-        for depends in rec.package_record.depends.iter() {
-            let version_set_id =
-                match parse_match_spec(&self.pool, depends, &mut parse_match_spec_cache) {
-                    Ok(version_set_id) => version_set_id,
-                    Err(e) => {
-                        let reason = self.pool.intern_string(format!(
-                            "the dependency '{depends}' failed to parse: {e}",
-                        ));
-
-                        return Dependencies::Unknown(reason);
-                    }
-                };
+        let mut pre_parsed_match_specs = self.pre_parsed_match_specs.borrow_mut();
+        // The implicit dependencies follow the declared ones, those are not
+        // parsed yet. Without dependencies only the constraints are added.
+        let parsed_depends = rec.package_record.parsed_depends();
+        let depends = crate::record_dependencies(&rec.package_record)
+            .zip(
+                parsed_depends
+                    .iter()
+                    .map(Some)
+                    .chain(std::iter::repeat(None)),
+            )
+            .filter(|_| !self.no_deps);
+        for (depends, parsed) in depends {
+            let version_set_id = match parse_match_spec(
+                &self.pool,
+                depends,
+                parsed,
+                &mut parse_match_spec_cache,
+                &mut pre_parsed_match_specs,
+                &self.parse_match_spec_cache_hits,
+            ) {
+                Ok(version_set_id) => version_set_id,
+                Err(e) => {
+                    let reason = self
+                        .pool
+                        .intern_string(format!("the dependency '{depends}' failed to parse: {e}",));
+
+                    return Dependencies::Unknown(reason);
+                }
+            };
             dependencies.requirements.push(version_set_id);
+            dependencies
+                .requirements
+                .extend(extra_requirements(&self.pool, version_set_id));
         }
 
         for constrains in rec.package_record.constrains.iter() {
-            let version_set_id =
-                match parse_match_spec(&self.pool, constrains, &mut parse_match_spec_cache) {
-                    Ok(version_set_id) => version_set_id,
-                    Err(e) => {
-                        let reason = self.pool.intern_string(format!(
-                            "the constrains '{constrains}' failed to parse: {e}",
-                        ));
-
-                        return Dependencies::Unknown(reason);
-                    }
-                };
+            let version_set_id = match parse_match_spec(
+                &self.pool,
+                constrains,
+                None,
+                &mut parse_match_spec_cache,
+                &mut pre_parsed_match_specs,
+                &self.parse_match_spec_cache_hits,
+            ) {
+                Ok(version_set_id) => version_set_id,
+                Err(e) => {
+                    let reason = self.pool.intern_string(format!(
+                        "the constrains '{constrains}' failed to parse: {e}",
+                    ));
+
+                    return Dependencies::Unknown(reason);
+                }
+            };
             dependencies.constrains.push(version_set_id);
         }
 
@@ -534,35 +896,27 @@ impl<'a> DependencyProvider for CondaDependencyProvider<'a> {
     ) -> Vec<SolvableId> {
         let spec = self.pool.resolve_version_set(version_set);
 
-        candidates
+        let filtered: Vec<SolvableId> = candidates
             .iter()
             .copied()
             .filter(|c| {
                 let record = &self.pool.resolve_solvable(*c).record;
                 match record {
-                    SolverPackageRecord::Record(rec) => spec.matches(*rec) != inverse,
-                    SolverPackageRecord::VirtualPackage(GenericVirtualPackage {
-                        version,
-                        build_string,
-                        ..
-                    }) => {
-                        if let Some(spec) = spec.version.as_ref() {
-                            if !spec.matches(version) {
-                                return inverse;
-                            }
-                        }
-
-                        if let Some(build_match) = spec.build.as_ref() {
-                            if !build_match.matches(build_string) {
-                                return inverse;
-                            }
-                        }
-
-                        !inverse
+                    SolverPackageRecord::Record(rec) | SolverPackageRecord::Extra(rec, _) => {
+                        spec.matches(*rec) != inverse
+                    }
+                    SolverPackageRecord::VirtualPackage(package) => {
+                        spec.matches(*package) != inverse
                     }
                 }
             })
-            .collect()
+            .collect();
+
+        if !inverse && filtered.is_empty() && !candidates.is_empty() {
+            self.record_conflicting_spec(version_set);
+        }
+
+        filtered
     }
 
     fn should_cancel_with_value(&self) -> Option<Box<dyn std::any::Any>> {
@@ -582,7 +936,6 @@ pub struct Solver;
 impl super::SolverImpl for Solver {
     type RepoData<'a> = RepoData<'a>;
 
-    #[allow(clippy::redundant_closure_for_method_calls)]
     fn solve<
         'a,
         R: IntoRepoData<'a, Self::RepoData<'a>>,
@@ -591,96 +944,309 @@ impl super::SolverImpl for Solver {
         &mut self,
         task: SolverTask<TAvailablePackagesIterator>,
     ) -> Result<Vec<RepoDataRecord>, SolveError> {
-        let stop_time = task
-            .timeout
-            .map(|timeout| std::time::SystemTime::now() + timeout);
-
-        // Construct a provider that can serve the data.
-        let provider = CondaDependencyProvider::new(
-            task.available_packages.into_iter().map(|r| r.into()),
-            &task.locked_packages,
-            &task.pinned_packages,
-            &task.virtual_packages,
-            task.specs.clone().as_ref(),
-            stop_time,
-            task.channel_priority,
-            task.exclude_newer,
-            task.strategy,
-        )?;
-
-        // Construct the requirements that the solver needs to satisfy.
-        let virtual_package_requirements = task.virtual_packages.iter().map(|spec| {
-            let name_id = provider.pool.intern_package_name(spec.name.as_normalized());
-            provider
-                .pool
-                .intern_version_set(name_id, NamelessMatchSpec::default().into())
+        self.solve_with_statistics(task)
+            .map(|result| result.records)
+    }
+
+    #[allow(clippy::redundant_closure_for_method_calls)]
+    #[instrument(name = "solve", skip_all, fields(backend = "resolvo", specs = task.specs.len()))]
+    fn solve_with_statistics<
+        'a,
+        R: IntoRepoData<'a, Self::RepoData<'a>>,
+        TAvailablePackagesIterator: IntoIterator<Item = R>,
+    >(
+        &mut self,
+        mut task: SolverTask<TAvailablePackagesIterator>,
+    ) -> Result<SolverResult, SolveError> {
+        let start = std::time::SystemTime::now();
+        task.apply_exclude_newer_to_locked()?;
+        task.check_virtual_package_constraints()?;
+        let task = task.map_available_packages(|available_packages| {
+            available_packages
+                .into_iter()
+                .map(|r| r.into())
+                .collect::<Vec<RepoData<'a>>>()
         });
+        solve_repo_data(task, start, RepoDataRecord::clone)
+    }
+}
 
-        let root_requirements = task.specs.iter().map(|spec| {
-            let (name, nameless_spec) = spec.clone().into_nameless();
-            let name = name.expect("cannot use matchspec without a name");
-            let name_id = provider.pool.intern_package_name(name.as_normalized());
-            provider
-                .pool
-                .intern_version_set(name_id, nameless_spec.into())
+impl Solver {
+    /// Resolve the dependencies like
+    /// [`crate::SolverImpl::solve_with_statistics`] but share the records with
+    /// the caller instead of cloning them.
+    ///
+    /// The available packages are passed as `Arc`s and the records of the
+    /// solution are clones of these `Arc`s, which avoids copying every record
+    /// of large solutions. Locked and pinned packages that are part of the
+    /// solution are wrapped in a new `Arc`.
+    #[instrument(name = "solve", skip_all, fields(backend = "resolvo", specs = task.specs.len()))]
+    pub fn solve_shared<
+        'a,
+        R: IntoIterator<Item = &'a Arc<RepoDataRecord>>,
+        TAvailablePackagesIterator: IntoIterator<Item = R>,
+    >(
+        &mut self,
+        mut task: SolverTask<TAvailablePackagesIterator>,
+    ) -> Result<SolverResult<Arc<RepoDataRecord>>, SolveError> {
+        let start = std::time::SystemTime::now();
+        task.apply_exclude_newer_to_locked()?;
+        task.check_virtual_package_constraints()?;
+
+        // Remember the `Arc` of every record so the records of the solution
+        // can be mapped back to them.
+        let mut shared: HashMap<*const RepoDataRecord, &'a Arc<RepoDataRecord>> = HashMap::new();
+        let task = task.map_available_packages(|available_packages| {
+            available_packages
+                .into_iter()
+                .map(|records| RepoData {
+                    records: records
+                        .into_iter()
+                        .map(|record| {
+                            shared.insert(Arc::as_ptr(record), record);
+                            &**record
+                        })
+                        .collect(),
+                })
+                .collect::<Vec<_>>()
         });
+        solve_repo_data(task, start, |record| {
+            shared
+                .get(&(record as *const RepoDataRecord))
+                .map_or_else(|| Arc::new(record.clone()), |&record| Arc::clone(record))
+        })
+    }
+}
 
-        let all_requirements = virtual_package_requirements
-            .chain(root_requirements)
-            .collect();
+/// Solves the task, possibly with best effort. The records of the solution
+/// are converted with `to_record`.
+fn solve_repo_data<'a, R>(
+    mut task: SolverTask<Vec<RepoData<'a>>>,
+    start: std::time::SystemTime,
+    to_record: impl Fn(&RepoDataRecord) -> R,
+) -> Result<SolverResult<R>, SolveError> {
+    for repo_data in &mut task.available_packages {
+        crate::normalize_record_order(&mut repo_data.records);
+    }
 
-        let root_constraints = task
-            .constraints
-            .iter()
-            .map(|spec| {
-                let (name, spec) = spec.clone().into_nameless();
-                let name = name.expect("cannot use matchspec without a name");
-                let name_id = provider.pool.intern_package_name(name.as_normalized());
-                provider.pool.intern_version_set(name_id, spec.into())
-            })
-            .collect();
+    let Some(timeout) = task.timeout.filter(|_| task.best_effort) else {
+        let stop_time = task.timeout.map(|timeout| start + timeout);
+        return solve_once(&task, stop_time, true, &to_record);
+    };
+    solve_best_effort(start, timeout, |stop_time, dependency_aware_sorting| {
+        solve_once(&task, Some(stop_time), dependency_aware_sorting, &to_record)
+    })
+}
 
-        // Construct a solver and solve the problems in the queue
-        let mut solver = LibSolvRsSolver::new(provider);
-        let solvables = solver.solve(all_requirements, root_constraints).map_err(
-            |unsolvable_or_cancelled| {
-                match unsolvable_or_cancelled {
-                    UnsolvableOrCancelled::Unsolvable(problem) => {
-                        SolveError::Unsolvable(vec![problem
-                            .display_user_friendly(&solver)
-                            .to_string()])
-                    }
-                    // We are not doing this as of yet
-                    // put a generic message in here for now
-                    UnsolvableOrCancelled::Cancelled(_) => SolveError::Cancelled,
+/// Solves with best effort. Only half of the time is spent trying to find an
+/// optimal solution, if that times out `solve` is called again without
+/// dependency aware sorting of the candidates.
+fn solve_best_effort<R>(
+    start: std::time::SystemTime,
+    timeout: std::time::Duration,
+    mut solve: impl FnMut(std::time::SystemTime, bool) -> Result<SolverResult<R>, SolveError>,
+) -> Result<SolverResult<R>, SolveError> {
+    match solve(start + timeout / 2, true) {
+        Err(SolveError::Cancelled(diagnostics)) if diagnostics.timed_out => {
+            tracing::warn!(
+                "could not find an optimal solution in time, falling back to a best effort solve"
+            );
+            let mut result = solve(start + timeout, false)?;
+            result.statistics.solve_duration += diagnostics.elapsed;
+            result.quality = SolveQuality::BestEffort {
+                compromises: vec![SolveCompromise::DependencyAwareOrdering],
+                optimal_attempt: diagnostics,
+            };
+            Ok(result)
+        }
+        result => result,
+    }
+}
+
+/// Solves the task once. If `dependency_aware_sorting` is `false` candidates
+/// with the same version and build number are not ordered by their
+/// dependencies, which is cheaper but might result in a less optimal solution.
+#[instrument(skip_all, fields(dependency_aware_sorting = dependency_aware_sorting))]
+fn solve_once<'a, R>(
+    task: &'a SolverTask<Vec<RepoData<'a>>>,
+    stop_time: Option<std::time::SystemTime>,
+    dependency_aware_sorting: bool,
+    to_record: &impl Fn(&RepoDataRecord) -> R,
+) -> Result<SolverResult<R>, SolveError> {
+    let load_start = Instant::now();
+
+    // Construct a provider that can serve the data.
+    let mut provider = CondaDependencyProvider::new(
+        task.available_packages.iter().cloned(),
+        &task.locked_packages,
+        &task.pinned_packages,
+        &task.virtual_packages,
+        &task.specs,
+        stop_time,
+        task.channel_priority,
+        &task.channel_policies,
+        task.exclude_newer,
+        task.strategy,
+    )?;
+    provider.dependency_aware_sorting = dependency_aware_sorting;
+    provider.no_deps = task.no_deps;
+    provider.track_features = task.track_features;
+    provider
+        .candidate_ordering
+        .clone_from(&task.candidate_ordering);
+
+    // Construct the requirements that the solver needs to satisfy.
+    let virtual_package_requirements = task.virtual_packages.iter().map(|spec| {
+        let name_id = provider.pool.intern_package_name(spec.name.as_normalized());
+        provider
+            .pool
+            .intern_version_set(name_id, NamelessMatchSpec::default().into())
+    });
+
+    let root_requirements = task.specs.iter().flat_map(|spec| {
+        let (name, nameless_spec) = spec.clone().into_nameless();
+        let name = name.expect("cannot use matchspec without a name");
+        let name_id = provider.pool.intern_package_name(name.as_normalized());
+        let version_set_id = provider
+            .pool
+            .intern_version_set(name_id, nameless_spec.into());
+        std::iter::once(version_set_id).chain(extra_requirements(&provider.pool, version_set_id))
+    });
+
+    let all_requirements = virtual_package_requirements
+        .chain(root_requirements)
+        .collect();
+
+    let root_constraints = task
+        .constraints
+        .iter()
+        .map(|spec| {
+            let (name, spec) = spec.clone().into_nameless();
+            let name = name.expect("cannot use matchspec without a name");
+            let name_id = provider.pool.intern_package_name(name.as_normalized());
+            provider.pool.intern_version_set(name_id, spec.into())
+        })
+        .collect();
+
+    let load_duration = load_start.elapsed();
+
+    // Construct a solver and solve the problems in the queue
+    let solve_start = Instant::now();
+    let solve_span = tracing::debug_span!("run_solver").entered();
+    let mut solver = LibSolvRsSolver::new(provider);
+    let solvables =
+        solver
+            .solve(all_requirements, root_constraints)
+            .map_err(|unsolvable_or_cancelled| match unsolvable_or_cancelled {
+                UnsolvableOrCancelled::Unsolvable(problem) => {
+                    SolveError::Unsolvable(vec![problem.display_user_friendly(&solver).to_string()])
                 }
+                UnsolvableOrCancelled::Cancelled(reason) => SolveError::Cancelled(
+                    solver
+                        .provider()
+                        .cancellation_diagnostics(reason.as_ref(), solve_start.elapsed()),
+                ),
+            })?;
+    tracing::debug!(
+        candidates_considered = solver.provider().candidates_considered.get(),
+        dependencies_requested = solver.provider().dependencies_requested.get(),
+        "solved the requirements"
+    );
+    let solve_duration = solve_start.elapsed();
+    drop(solve_span);
+
+    // Get the resulting packages from the solver.
+    let extract_start = Instant::now();
+    let extract_span = tracing::debug_span!("extract_result").entered();
+    let required_records: Vec<_> = solvables
+        .into_iter()
+        .filter_map(
+            |id| match solver.provider().pool.resolve_solvable(id).record {
+                SolverPackageRecord::Record(rec) => Some(to_record(rec)),
+                SolverPackageRecord::Extra(..) | SolverPackageRecord::VirtualPackage(_) => None,
             },
-        )?;
-
-        // Get the resulting packages from the solver.
-        let required_records = solvables
-            .into_iter()
-            .filter_map(
-                |id| match solver.provider().pool.resolve_solvable(id).record {
-                    SolverPackageRecord::Record(rec) => Some(rec.clone()),
-                    SolverPackageRecord::VirtualPackage(_) => None,
-                },
-            )
-            .collect();
+        )
+        .collect();
+    tracing::debug!(records = required_records.len(), "extracted the solution");
+    drop(extract_span);
+
+    let statistics = SolveStatistics {
+        candidates_considered: solver.provider().candidates_considered.get(),
+        // resolvo does not expose its decisions, learned clauses and restarts.
+        decisions: None,
+        dependencies_requested: Some(solver.provider().dependencies_requested.get()),
+        clauses_learned: None,
+        restarts: None,
+        load_duration,
+        solve_duration,
+        extract_duration: extract_start.elapsed(),
+        sort_duration: Some(solver.provider().sort_duration.get()),
+        match_specs_parsed: Some(solver.provider().parse_match_spec_cache.borrow().len() as u64),
+        match_spec_cache_hits: Some(solver.provider().parse_match_spec_cache_hits.get()),
+    };
+    tracing::debug!("solve statistics: {statistics:?}");
+
+    Ok(SolverResult {
+        records: required_records,
+        statistics,
+        quality: SolveQuality::Optimal,
+    })
+}
 
-        Ok(required_records)
+/// Parses the match specs of the given records in parallel. The parsed
+/// dependencies are cached in the records themselves, the parsed constraints
+/// are returned.
+///
+/// Parsing stops when `stop_time` is reached, the specs that were not parsed
+/// yet are parsed lazily when the solver requests them. The solver itself
+/// notices the timeout and cancels the solve.
+#[instrument(skip_all, fields(records = records.len()))]
+fn parse_match_specs<'a>(
+    records: &[&'a PackageRecord],
+    stop_time: Option<std::time::SystemTime>,
+) -> HashMap<&'a str, Result<MatchSpec, ParseMatchSpecError>> {
+    let timed_out = || stop_time.is_some_and(|stop_time| std::time::SystemTime::now() > stop_time);
+
+    let parsed_depends = records.par_iter().try_for_each(|record| {
+        if timed_out() {
+            return Err(());
+        }
+        record.parsed_depends();
+        Ok(())
+    });
+    if parsed_depends.is_err() {
+        return HashMap::default();
     }
+
+    records
+        .iter()
+        .flat_map(|record| record.constrains.iter().map(String::as_str))
+        .collect::<HashSet<_>>()
+        .into_par_iter()
+        .map(|spec| {
+            (!timed_out()).then(|| (spec, MatchSpec::from_str(spec, ParseStrictness::Lenient)))
+        })
+        .while_some()
+        .collect()
 }
 
 fn parse_match_spec<'a>(
     pool: &Pool<SolverMatchSpec<'a>>,
     spec_str: &'a str,
+    parsed: Option<&Result<MatchSpec, ParseMatchSpecError>>,
     parse_match_spec_cache: &mut HashMap<&'a str, VersionSetId>,
+    pre_parsed_match_specs: &mut HashMap<&'a str, Result<MatchSpec, ParseMatchSpecError>>,
+    cache_hits: &Cell<u64>,
 ) -> Result<VersionSetId, ParseMatchSpecError> {
     if let Some(spec_id) = parse_match_spec_cache.get(spec_str) {
+        cache_hits.set(cache_hits.get() + 1);
         Ok(*spec_id)
     } else {
-        let match_spec = MatchSpec::from_str(spec_str, ParseStrictness::Lenient)?;
+        let match_spec = match (parsed, pre_parsed_match_specs.remove(spec_str)) {
+            (Some(match_spec), _) => match_spec.clone()?,
+            (None, Some(match_spec)) => match_spec?,
+            (None, None) => MatchSpec::from_str(spec_str, ParseStrictness::Lenient)?,
+        };
         let (name, spec) = match_spec.into_nameless();
         let dependency_name = pool.intern_package_name(
             name.as_ref()
@@ -692,3 +1258,207 @@ fn parse_match_spec<'a>(
         Ok(version_set_id)
     }
 }
+
+/// Adds a candidate for every extra of the record. The candidates are named
+/// after the record and the extra, e.g. `foo[bar]`, and depend on the record
+/// and the dependencies of the extra. Requesting an extra therefore requires
+/// one of its candidates.
+fn add_extra_candidates<'a>(
+    pool: &Pool<SolverMatchSpec<'a>>,
+    records: &mut HashMap<NameId, Candidates>,
+    record: &'a RepoDataRecord,
+) {
+    for extra in record.package_record.extra_depends.keys() {
+        let name = pool.intern_package_name(extra_package_name(
+            record.package_record.name.as_normalized(),
+            extra,
+        ));
+        let solvable = pool.intern_solvable(name, SolverPackageRecord::Extra(record, extra));
+        records.entry(name).or_default().candidates.push(solvable);
+    }
+}
+
+/// Returns the requirements on the candidates of the extras that are
+/// requested by a version set, see [`add_extra_candidates`].
+fn extra_requirements(
+    pool: &Pool<SolverMatchSpec<'_>>,
+    version_set: VersionSetId,
+) -> Vec<VersionSetId> {
+    let spec = pool.resolve_version_set(version_set);
+    let Some(extras) = spec.extras.as_ref().filter(|extras| !extras.is_empty()) else {
+        return Vec::new();
+    };
+    let name = pool
+        .resolve_package_name(pool.resolve_version_set_package_name(version_set))
+        .clone();
+    let extra_spec = NamelessMatchSpec {
+        extras: None,
+        ..spec.inner.clone()
+    };
+    extras
+        .iter()
+        .map(|extra| {
+            let name_id = pool.intern_package_name(extra_package_name(&name, extra));
+            pool.intern_version_set(name_id, extra_spec.clone().into())
+        })
+        .collect()
+}
+
+/// Returns the name of the candidates of an extra of a package.
+fn extra_package_name(name: &str, extra: &str) -> String {
+    format!("{name}[{extra}]")
+}
+
+/// Assigns the same id to all the representations of a channel, e.g. urls
+/// that only differ in a trailing slash.
+#[derive(Default)]
+struct ChannelIds<'a> {
+    by_channel: HashMap<&'a str, usize>,
+    by_normalized_channel: HashMap<String, usize>,
+}
+
+impl<'a> ChannelIds<'a> {
+    fn id(&mut self, channel: &'a str) -> usize {
+        if let Some(&id) = self.by_channel.get(channel) {
+            return id;
+        }
+        let next_id = self.by_normalized_channel.len();
+        let id = *self
+            .by_normalized_channel
+            .entry(crate::normalized_channel(channel))
+            .or_insert(next_id);
+        self.by_channel.insert(channel, id);
+        id
+    }
+}
+
+/// Returns true if the channel of a record refers to `channel`, which is
+/// either the url or the name of a channel. A name refers to the full path of
+/// the channel on its server, e.g. `conda-forge` or `pytorch/label/nightly`,
+/// so `conda-forge` does not match `https://example.com/foo/conda-forge`.
+fn channel_matches(record_channel: &str, channel: &str) -> bool {
+    let channel = channel.trim_end_matches('/');
+    match (
+        record_channel.parse::<ChannelUrl>(),
+        channel.parse::<ChannelUrl>(),
+    ) {
+        (Ok(record_channel), Ok(channel)) => record_channel == channel,
+        (Ok(record_channel), Err(_)) => {
+            record_channel.url().path().trim_matches('/') == channel.trim_start_matches('/')
+        }
+        _ => record_channel.trim_end_matches('/') == channel,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use rattler_conda_types::{PackageName, PackageRecord, Version};
+
+    use super::{channel_matches, parse_match_specs, solve_best_effort};
+    use crate::{
+        CancellationDiagnostics, SolveCompromise, SolveError, SolveQuality, SolveStatistics,
+        SolverResult,
+    };
+
+    #[test]
+    fn test_solve_best_effort() {
+        let start = SystemTime::now();
+        let timeout = Duration::from_secs(10);
+        let diagnostics = CancellationDiagnostics {
+            timed_out: true,
+            elapsed: Duration::from_secs(5),
+            dependencies_requested: 42,
+            ..CancellationDiagnostics::default()
+        };
+
+        // The optimal attempt times out, the cheaper attempt gets the rest of
+        // the time.
+        let mut attempts = Vec::new();
+        let result: SolverResult =
+            solve_best_effort(start, timeout, |stop_time, dependency_aware_sorting| {
+                attempts.push((stop_time, dependency_aware_sorting));
+                if dependency_aware_sorting {
+                    Err(SolveError::Cancelled(diagnostics.clone()))
+                } else {
+                    Ok(SolverResult {
+                        records: Vec::new(),
+                        statistics: SolveStatistics {
+                            solve_duration: Duration::from_secs(1),
+                            ..SolveStatistics::default()
+                        },
+                        quality: SolveQuality::Optimal,
+                    })
+                }
+            })
+            .unwrap();
+        assert_eq!(
+            attempts,
+            [
+                (start + Duration::from_secs(5), true),
+                (start + timeout, false)
+            ]
+        );
+        assert_eq!(result.statistics.solve_duration, Duration::from_secs(6));
+        assert_eq!(
+            result.quality,
+            SolveQuality::BestEffort {
+                compromises: vec![SolveCompromise::DependencyAwareOrdering],
+                optimal_attempt: diagnostics,
+            }
+        );
+
+        // Other errors are not retried.
+        let mut attempts = 0;
+        let err = solve_best_effort(start, timeout, |_, _| -> Result<SolverResult, _> {
+            attempts += 1;
+            Err(SolveError::Cancelled(CancellationDiagnostics::default()))
+        })
+        .unwrap_err();
+        assert!(matches!(err, SolveError::Cancelled(_)));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_channel_matches() {
+        let conda_forge = "https://conda.anaconda.org/conda-forge/";
+        assert!(channel_matches(conda_forge, "conda-forge"));
+        assert!(channel_matches(conda_forge, "conda-forge/"));
+        assert!(channel_matches(
+            conda_forge,
+            "https://conda.anaconda.org/conda-forge"
+        ));
+        assert!(!channel_matches(
+            conda_forge,
+            "https://prefix.dev/conda-forge"
+        ));
+        assert!(!channel_matches(
+            "https://example.com/foo/conda-forge/",
+            "conda-forge"
+        ));
+        assert!(channel_matches(
+            "https://conda.anaconda.org/pytorch/label/nightly/",
+            "pytorch/label/nightly"
+        ));
+        assert!(channel_matches("conda-forge", "conda-forge"));
+    }
+
+    #[test]
+    fn test_parse_match_specs_timeout() {
+        let mut record = PackageRecord::new(
+            PackageName::new_unchecked("foo"),
+            Version::major(1),
+            "0".to_string(),
+        );
+        record.constrains = vec!["bar >=2".to_string()];
+        let records = [&record];
+
+        let parsed = parse_match_specs(&records, None);
+        assert!(parsed["bar >=2"].is_ok());
+
+        // Nothing is parsed up front once the timeout has passed.
+        let parsed = parse_match_specs(&records, Some(SystemTime::now() - Duration::from_secs(1)));
+        assert!(parsed.is_empty());
+    }
+}
@@ -1,13 +1,15 @@
-use std::{str::FromStr, time::Instant};
+use std::{collections::HashMap, str::FromStr, time::Instant};
 
 use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
 use rattler_conda_types::{
-    Channel, ChannelConfig, GenericVirtualPackage, MatchSpec, NoArchType, PackageRecord,
-    ParseStrictness, RepoData, RepoDataRecord, Version,
+    Channel, ChannelConfig, GenericVirtualPackage, MatchSpec, PackageName, PackageRecord,
+    ParseStrictness, PrefixRecord, RepoData, RepoDataRecord, Version,
 };
 use rattler_repodata_gateway::sparse::SparseRepoData;
-use rattler_solve::{ChannelPriority, SolveError, SolveStrategy, SolverImpl, SolverTask};
+use rattler_solve::{
+    ChannelPriority, PackageChannelPolicy, SolveError, SolveStrategy, SolverImpl, SolverTask,
+};
 use url::Url;
 
 fn channel_config() -> ChannelConfig {
@@ -86,29 +88,17 @@ fn installed_package(
         url: Url::from_str("http://example.com").unwrap(),
         channel: channel.to_string(),
         file_name: "dummy-filename".to_string(),
-        package_record: PackageRecord {
-            name: name.parse().unwrap(),
-            version: version.parse().unwrap(),
-            build: build.to_string(),
-            build_number,
-            subdir: subdir.to_string(),
-            md5: Some(dummy_md5_hash()),
-            sha256: Some(dummy_sha256_hash()),
-            size: None,
-            arch: None,
-            platform: None,
-            depends: Vec::new(),
-            constrains: Vec::new(),
-            track_features: Vec::new(),
-            features: None,
-            noarch: NoArchType::default(),
-            license: None,
-            license_family: None,
-            timestamp: None,
-            legacy_bz2_size: None,
-            legacy_bz2_md5: None,
-            purls: None,
-            run_exports: None,
+        package_record: {
+            let mut record = PackageRecord::new(
+                name.parse().unwrap(),
+                version.parse::<Version>().unwrap(),
+                build.to_string(),
+            );
+            record.build_number = build_number;
+            record.subdir = subdir.to_string();
+            record.md5 = Some(dummy_md5_hash());
+            record.sha256 = Some(dummy_sha256_hash());
+            record
         },
     }
 }
@@ -264,6 +254,34 @@ macro_rules! solver_backend_tests {
             assert_eq!(result[0].package_record.to_string(), "bors=1.0=bla_1");
         }
 
+        #[test]
+        fn test_solve_installed_packages() {
+            let repo_data = read_repodata(&dummy_channel_json_path());
+            let installed = PrefixRecord::from_repodata_record(
+                installed_package("conda-forge", "linux-64", "bors", "1.0", "bla_1", 1),
+                None,
+                None,
+                Vec::new(),
+                None,
+                None,
+            );
+
+            let solve = |update: &[PackageName]| {
+                let task = SolverTask {
+                    specs: vec![MatchSpec::from_str("bors", ParseStrictness::Lenient).unwrap()],
+                    ..SolverTask::from_iter([&repo_data])
+                }
+                .with_installed_packages([installed.clone()], update);
+                let result = <$T>::default().solve(task).unwrap();
+                assert_eq!(result.len(), 1);
+                result[0].package_record.to_string()
+            };
+
+            // The installed version is favored unless an update is requested.
+            assert_eq!(solve(&[]), "bors=1.0=bla_1");
+            assert_eq!(solve(&["bors".parse().unwrap()]), "bors=2.1=bla_1");
+        }
+
         #[test]
         fn test_solve_with_error() {
             let result = solve::<$T>(
@@ -296,6 +314,98 @@ macro_rules! solver_backend_tests {
             insta::assert_debug_snapshot!(err);
         }
 
+        #[test]
+        fn test_solve_no_deps() {
+            let pkgs = solve::<$T>(
+                dummy_channel_json_path(),
+                SimpleSolveTask {
+                    specs: &["foobar", "bar"],
+                    no_deps: true,
+                    ..SimpleSolveTask::default()
+                },
+            )
+            .unwrap();
+
+            let pkgs = pkgs
+                .iter()
+                .map(|pkg| pkg.package_record.to_string())
+                .sorted()
+                .collect::<Vec<_>>();
+            assert_eq!(pkgs, ["bar=1.0=unix_py36h1af98f8_2", "foobar=2.1=bla_1"]);
+
+            // The dependency of foobar on bors <2 is ignored.
+            let result = solve::<$T>(
+                dummy_channel_json_path(),
+                SimpleSolveTask {
+                    specs: &["foobar", "bors>=2"],
+                    no_deps: true,
+                    ..SimpleSolveTask::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(result.len(), 2);
+
+            // Conflicts between the requested packages are still reported.
+            let result = solve::<$T>(
+                dummy_channel_json_path(),
+                SimpleSolveTask {
+                    specs: &["foobar", "foobar<2.1"],
+                    constraints: vec!["foobar>=2.1"],
+                    no_deps: true,
+                    ..SimpleSolveTask::default()
+                },
+            );
+            assert!(matches!(result.err(), Some(SolveError::Unsolvable(_))));
+        }
+
+        #[test]
+        fn test_solve_no_deps_constrains() {
+            use rattler_solve::SolverImpl;
+
+            let record = |name: &str, version: &str| {
+                let mut record =
+                    installed_package("conda-forge", "linux-64", name, version, "h0_0", 0);
+                record.file_name = format!("{name}-{version}-h0_0.conda");
+                record
+            };
+
+            // `a` depends on a package that does not exist and constrains `b`.
+            let mut a = record("a", "1.0");
+            a.package_record.depends = vec!["missing".to_string()];
+            a.package_record.constrains = vec!["b <2".to_string()];
+            let records = vec![a, record("b", "1.0"), record("b", "2.0")];
+
+            let task = |specs: &[&str]| rattler_solve::SolverTask {
+                specs: specs
+                    .iter()
+                    .map(|spec| {
+                        rattler_conda_types::MatchSpec::from_str(
+                            spec,
+                            rattler_conda_types::ParseStrictness::Lenient,
+                        )
+                        .unwrap()
+                    })
+                    .collect(),
+                no_deps: true,
+                ..rattler_solve::SolverTask::from_iter([&records])
+            };
+
+            // The constrains of `a` rules out the highest version of `b`.
+            let pkgs = <$T>::default()
+                .solve(task(&["a", "b"]))
+                .unwrap()
+                .iter()
+                .map(|pkg| pkg.package_record.to_string())
+                .sorted()
+                .collect::<Vec<_>>();
+            assert_eq!(pkgs, ["a=1.0=h0_0", "b=1.0=h0_0"]);
+
+            // A requested package that is ruled out by the constrains of
+            // another requested package makes the problem unsolvable.
+            let result = <$T>::default().solve(task(&["a", "b>=2"]));
+            assert!(matches!(result.err(), Some(SolveError::Unsolvable(_))));
+        }
+
         #[test]
         fn test_solve_dummy_repo_missing_virtual_package() {
             let result = solve::<$T>(
@@ -526,6 +636,88 @@ macro_rules! solver_backend_tests {
             assert_eq!(&info.file_name, "foo-3.0.2-py36h1af98f8_1.tar.bz2", "even though there is a conda version available we expect the tar.bz2 version because we exclude the .conda version based on the timestamp");
         }
 
+        #[test]
+        fn test_exclude_newer_applies_to_locked() {
+            use rattler_solve::SolverImpl;
+
+            let date = "2021-12-12T12:12:12Z".parse::<DateTime<Utc>>().unwrap();
+            let records = super::read_repodata(&dummy_channel_json_path());
+            let newest_foo = records
+                .iter()
+                .find(|record| record.file_name == "foo-4.0.2-py36h1af98f8_2.tar.bz2")
+                .unwrap()
+                .clone();
+            let specs = vec![rattler_conda_types::MatchSpec::from_str(
+                "foo",
+                rattler_conda_types::ParseStrictness::Lenient,
+            )
+            .unwrap()];
+
+            // Locked packages that are newer than the cutoff are ignored.
+            let pkgs = <$T>::default()
+                .solve(rattler_solve::SolverTask {
+                    locked_packages: vec![newest_foo.clone()],
+                    specs: specs.clone(),
+                    exclude_newer: Some(date),
+                    exclude_newer_applies_to_locked: true,
+                    ..rattler_solve::SolverTask::from_iter([&records])
+                })
+                .unwrap();
+            assert_eq!(1, pkgs.len());
+            assert_eq!(&pkgs[0].file_name, "foo-3.0.2-py36h1af98f8_1.tar.bz2");
+
+            // Pinned packages that are newer than the cutoff result in an error.
+            let result = <$T>::default().solve(rattler_solve::SolverTask {
+                pinned_packages: vec![newest_foo],
+                specs,
+                exclude_newer: Some(date),
+                exclude_newer_applies_to_locked: true,
+                ..rattler_solve::SolverTask::from_iter([&records])
+            });
+            assert!(matches!(
+                result,
+                Err(rattler_solve::SolveError::PinnedPackageExcludedByDate { .. })
+            ));
+        }
+
+        #[test]
+        fn test_virtual_package_constraints() {
+            use rattler_solve::SolverImpl;
+
+            let records = super::read_repodata(&dummy_channel_json_path());
+            let task = |constraint: &str| rattler_solve::SolverTask {
+                specs: vec![rattler_conda_types::MatchSpec::from_str(
+                    "bar",
+                    rattler_conda_types::ParseStrictness::Lenient,
+                )
+                .unwrap()],
+                constraints: vec![rattler_conda_types::MatchSpec::from_str(
+                    constraint,
+                    rattler_conda_types::ParseStrictness::Lenient,
+                )
+                .unwrap()],
+                virtual_packages: vec![GenericVirtualPackage {
+                    name: rattler_conda_types::PackageName::new_unchecked("__unix"),
+                    version: Version::from_str("1.0").unwrap(),
+                    build_string: "0".to_string(),
+                }],
+                ..rattler_solve::SolverTask::from_iter([&records])
+            };
+
+            let pkgs = <$T>::default().solve(task("__unix>=1")).unwrap();
+            assert_eq!(1, pkgs.len());
+            assert_eq!("bar", pkgs[0].package_record.name.as_normalized());
+
+            // Constraints on virtual packages that are not provided are ignored.
+            assert!(<$T>::default().solve(task("__glibc<2.35")).is_ok());
+
+            let result = <$T>::default().solve(task("__unix<1"));
+            assert!(matches!(
+                result,
+                Err(rattler_solve::SolveError::VirtualPackageConstraintViolated { .. })
+            ));
+        }
+
         #[test]
         fn test_duplicate_record() {
             use rattler_solve::SolverImpl;
@@ -542,6 +734,32 @@ macro_rules! solver_backend_tests {
             }
         }
 
+        #[test]
+        fn test_solve_statistics() {
+            use rattler_solve::SolverImpl;
+
+            let records = super::read_repodata(&dummy_channel_json_path());
+            let task = rattler_solve::SolverTask {
+                specs: vec![rattler_conda_types::MatchSpec::from_str(
+                    "foo<4",
+                    rattler_conda_types::ParseStrictness::Lenient,
+                )
+                .unwrap()],
+                ..rattler_solve::SolverTask::from_iter([&records])
+            };
+
+            let result = <$T>::default().solve_with_statistics(task).unwrap();
+            assert_eq!(result.records.len(), 1);
+            assert!(result.statistics.candidates_considered > 0);
+            assert!(
+                result.statistics.decisions.is_some_and(|decisions| decisions > 0)
+                    || result
+                        .statistics
+                        .dependencies_requested
+                        .is_some_and(|requested| requested > 0)
+            );
+        }
+
         #[test]
         fn test_constraints() {
             // There following package is provided as .tar.bz and as .conda in repodata.json
@@ -597,6 +815,39 @@ macro_rules! solver_backend_tests {
 
             insta::assert_snapshot!(output);
         }
+
+        #[test]
+        fn test_noarch_python_requires_python() {
+            use rattler_solve::SolverImpl;
+
+            // A noarch python package that does not explicitly depend on python.
+            let mut noarch =
+                installed_package("conda-forge", "noarch", "six", "1.16.0", "pyh0_0", 0);
+            noarch.file_name = "six-1.16.0-pyh0_0.conda".to_string();
+            noarch.package_record.noarch = rattler_conda_types::NoArchType::python();
+            let mut python =
+                installed_package("conda-forge", "linux-64", "python", "3.12.0", "h0_cpython", 0);
+            python.file_name = "python-3.12.0-h0_cpython.conda".to_string();
+            let records = vec![noarch, python];
+
+            let task = rattler_solve::SolverTask {
+                specs: vec![rattler_conda_types::MatchSpec::from_str(
+                    "six",
+                    rattler_conda_types::ParseStrictness::Lenient,
+                )
+                .unwrap()],
+                ..rattler_solve::SolverTask::from_iter([&records])
+            };
+
+            let mut names = <$T>::default()
+                .solve(task)
+                .unwrap()
+                .into_iter()
+                .map(|record| record.package_record.name.as_normalized().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            assert_eq!(names, vec!["python", "six"]);
+        }
     };
 }
 
@@ -604,7 +855,9 @@ macro_rules! solver_backend_tests {
 mod libsolv_c {
     #![allow(unused_imports)] // For some reason windows thinks this is an unused import.
 
-    use rattler_solve::{ChannelPriority, SolveStrategy};
+    use std::collections::HashMap;
+
+    use rattler_solve::{ChannelPriority, SolveStrategy, TrackFeaturesPolicy};
 
     use super::{
         dummy_channel_json_path, installed_package, solve, solve_real_world, FromStr,
@@ -652,9 +905,15 @@ mod libsolv_c {
                 constraints: Vec::new(),
                 pinned_packages: Vec::new(),
                 timeout: None,
+                best_effort: false,
                 channel_priority: ChannelPriority::default(),
+                channel_policies: HashMap::new(),
                 exclude_newer: None,
+                exclude_newer_applies_to_locked: false,
                 strategy: SolveStrategy::default(),
+                candidate_ordering: None,
+                track_features: TrackFeaturesPolicy::default(),
+                no_deps: false,
             })
             .unwrap();
 
@@ -697,10 +956,12 @@ mod libsolv_c {
 
 #[cfg(feature = "resolvo")]
 mod resolvo {
+    use std::sync::Arc;
+
     use rattler_conda_types::{
         MatchSpec, PackageRecord, ParseStrictness, RepoDataRecord, VersionWithSource,
     };
-    use rattler_solve::{SolveStrategy, SolverImpl, SolverTask};
+    use rattler_solve::{SolveStrategy, SolverImpl, SolverTask, TrackFeaturesPolicy};
     use url::Url;
 
     use super::{
@@ -842,6 +1103,246 @@ mod resolvo {
         );
     }
 
+    #[test]
+    fn test_virtual_package_build_glob() {
+        let virtual_packages = || {
+            vec![GenericVirtualPackage {
+                name: "__cuda".parse().unwrap(),
+                version: Version::from_str("12.2").unwrap(),
+                build_string: "cuda_0".to_string(),
+            }]
+        };
+
+        let result = solve::<rattler_solve::resolvo::Solver>(
+            dummy_channel_json_path(),
+            SimpleSolveTask {
+                specs: &["__cuda >=12 cuda_*"],
+                virtual_packages: virtual_packages(),
+                ..SimpleSolveTask::default()
+            },
+        );
+        assert!(result.unwrap().is_empty());
+
+        let result = solve::<rattler_solve::resolvo::Solver>(
+            dummy_channel_json_path(),
+            SimpleSolveTask {
+                specs: &["__cuda >=12 cpu_*"],
+                virtual_packages: virtual_packages(),
+                ..SimpleSolveTask::default()
+            },
+        );
+        assert!(matches!(result, Err(SolveError::Unsolvable(_))));
+    }
+
+    #[test]
+    fn test_timeout_diagnostics() {
+        let repo_data = super::read_repodata(&dummy_channel_json_path());
+        let task = SolverTask {
+            specs: vec![MatchSpec::from_str("foobar", ParseStrictness::Lenient).unwrap()],
+            timeout: Some(std::time::Duration::ZERO),
+            ..SolverTask::from_iter([&repo_data])
+        };
+
+        let err = rattler_solve::resolvo::Solver.solve(task).unwrap_err();
+        let SolveError::Cancelled(diagnostics) = &err else {
+            panic!("expected the solve to be cancelled, got: {err}");
+        };
+        assert!(diagnostics.timed_out);
+        assert!(err.to_string().contains("timeout was reached"));
+    }
+
+    #[test]
+    fn test_solve_shared() {
+        let repo_data = super::read_repodata(&dummy_channel_json_path())
+            .into_iter()
+            .map(Arc::new)
+            .collect::<Vec<_>>();
+        let task = SolverTask {
+            specs: vec![MatchSpec::from_str("foobar", ParseStrictness::Lenient).unwrap()],
+            ..SolverTask::from_iter(Vec::<Vec<&RepoDataRecord>>::new())
+        }
+        .map_available_packages(|_| [&repo_data]);
+
+        let result = rattler_solve::resolvo::Solver.solve_shared(task).unwrap();
+        assert_eq!(result.records.len(), 2);
+
+        // The records of the solution are the records that were passed in.
+        for record in &result.records {
+            assert!(repo_data
+                .iter()
+                .any(|available| Arc::ptr_eq(available, record)));
+        }
+    }
+
+    #[test]
+    fn test_best_effort() {
+        let repo_data = super::read_repodata(&dummy_channel_json_path());
+
+        // With enough time the solution is optimal.
+        let task = SolverTask {
+            specs: vec![MatchSpec::from_str("foobar", ParseStrictness::Lenient).unwrap()],
+            timeout: Some(std::time::Duration::from_secs(60)),
+            best_effort: true,
+            ..SolverTask::from_iter([&repo_data])
+        };
+        let result = rattler_solve::resolvo::Solver
+            .solve_with_statistics(task)
+            .unwrap();
+        assert!(result.quality.is_optimal());
+
+        // If there is no time at all, the best effort solve is also cancelled.
+        let task = SolverTask {
+            specs: vec![MatchSpec::from_str("foobar", ParseStrictness::Lenient).unwrap()],
+            timeout: Some(std::time::Duration::ZERO),
+            best_effort: true,
+            ..SolverTask::from_iter([&repo_data])
+        };
+        let err = rattler_solve::resolvo::Solver.solve(task).unwrap_err();
+        assert!(matches!(err, SolveError::Cancelled(_)), "{err}");
+    }
+
+    #[test]
+    fn test_candidate_ordering() {
+        let repo_data = super::read_repodata(&dummy_channel_json_path());
+        let specs = vec![MatchSpec::from_str("foo", ParseStrictness::Lenient).unwrap()];
+
+        // By default the highest version is selected.
+        let task = SolverTask {
+            specs: specs.clone(),
+            ..SolverTask::from_iter([&repo_data])
+        };
+        let records = rattler_solve::resolvo::Solver.solve(task).unwrap();
+        assert_eq!(records[0].package_record.version.to_string(), "4.0.2");
+
+        // A custom ordering can prefer other candidates.
+        let task = SolverTask {
+            specs,
+            candidate_ordering: Some(Arc::new(|candidates: &mut [&RepoDataRecord]| {
+                candidates.sort_by_key(|r| r.package_record.version.to_string() != "3.0.2");
+            })),
+            ..SolverTask::from_iter([&repo_data])
+        };
+        let records = rattler_solve::resolvo::Solver.solve(task).unwrap();
+        assert_eq!(records[0].package_record.version.to_string(), "3.0.2");
+    }
+
+    #[test]
+    fn test_track_features_policy() {
+        let record = |version: &str, build_number: u64, track_features: &[&str]| {
+            let mut record =
+                installed_package("conda-forge", "linux-64", "foo", version, "0", build_number);
+            record.file_name = format!("foo-{version}-{build_number}.conda");
+            record.package_record.track_features =
+                track_features.iter().map(ToString::to_string).collect();
+            record
+        };
+        let solve = |records: &Vec<RepoDataRecord>, track_features| {
+            let task = SolverTask {
+                specs: vec![MatchSpec::from_str("foo", ParseStrictness::Lenient).unwrap()],
+                track_features,
+                ..SolverTask::from_iter([records])
+            };
+            let records = rattler_solve::resolvo::Solver.solve(task).unwrap();
+            records[0].file_name.clone()
+        };
+
+        // A newer version with track features.
+        let records = vec![record("1.0", 0, &[]), record("2.0", 0, &["debug"])];
+        assert_eq!(
+            solve(&records, TrackFeaturesPolicy::Strict),
+            "foo-1.0-0.conda"
+        );
+        assert_eq!(
+            solve(&records, TrackFeaturesPolicy::SameVersion),
+            "foo-2.0-0.conda"
+        );
+        assert_eq!(
+            solve(&records, TrackFeaturesPolicy::Ignore),
+            "foo-2.0-0.conda"
+        );
+
+        // A higher build number with track features.
+        let records = vec![record("1.0", 0, &[]), record("1.0", 1, &["debug"])];
+        assert_eq!(
+            solve(&records, TrackFeaturesPolicy::SameVersion),
+            "foo-1.0-0.conda"
+        );
+        assert_eq!(
+            solve(&records, TrackFeaturesPolicy::Ignore),
+            "foo-1.0-1.conda"
+        );
+    }
+
+    #[test]
+    fn test_extras() {
+        let record = |name: &str, version: &str| {
+            let mut record = installed_package("conda-forge", "linux-64", name, version, "0", 0);
+            record.file_name = format!("{name}-{version}-0.conda");
+            record
+        };
+        let mut foo = record("foo", "1.0");
+        foo.package_record.extra_depends =
+            [("bar".to_string(), vec!["bar >=2".to_string()])].into();
+        let records = vec![
+            foo,
+            record("foo", "2.0"),
+            record("bar", "1.0"),
+            record("bar", "2.0"),
+        ];
+        let solve = |specs: &[&str]| {
+            let task = SolverTask {
+                specs: specs
+                    .iter()
+                    .map(|spec| MatchSpec::from_str(spec, ParseStrictness::Lenient).unwrap())
+                    .collect(),
+                ..SolverTask::from_iter([&records])
+            };
+            let mut records = rattler_solve::resolvo::Solver
+                .solve(task)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.file_name)
+                .collect::<Vec<_>>();
+            records.sort();
+            records
+        };
+
+        // Without the extra the newest version is selected.
+        assert_eq!(solve(&["foo"]), vec!["foo-2.0-0.conda"]);
+
+        // Only the older version provides the extra, which adds its
+        // dependencies.
+        assert_eq!(
+            solve(&["foo[extras=[bar]]"]),
+            vec!["bar-2.0-0.conda", "foo-1.0-0.conda"]
+        );
+    }
+
+    #[test]
+    fn test_solve_statistics_resolvo() {
+        let specs = vec![MatchSpec::from_str("xtensor", ParseStrictness::Lenient).unwrap()];
+        let names = specs.iter().filter_map(|s| s.name.clone());
+        let available_packages = super::SparseRepoData::load_records_recursive(
+            [super::read_conda_forge_sparse_repo_data()],
+            names,
+            None,
+        )
+        .unwrap();
+
+        let task = SolverTask {
+            specs,
+            ..SolverTask::from_iter(&available_packages)
+        };
+        let statistics = rattler_solve::resolvo::Solver
+            .solve_with_statistics(task)
+            .unwrap()
+            .statistics;
+        assert!(statistics.sort_duration.unwrap() <= statistics.solve_duration);
+        assert!(statistics.match_specs_parsed.unwrap() > 0);
+        let hit_rate = statistics.match_spec_cache_hit_rate().unwrap();
+        assert!((0.0..=1.0).contains(&hit_rate));
+    }
+
     /// Try to solve a package with a direct url, and then try to do it again
     /// without having it in the repodata.
     #[test]
@@ -929,6 +1430,7 @@ struct SimpleSolveTask<'a> {
     virtual_packages: Vec<GenericVirtualPackage>,
     exclude_newer: Option<DateTime<Utc>>,
     strategy: SolveStrategy,
+    no_deps: bool,
 }
 
 fn solve<T: SolverImpl + Default>(
@@ -957,6 +1459,7 @@ fn solve<T: SolverImpl + Default>(
         pinned_packages: task.pinned_packages,
         exclude_newer: task.exclude_newer,
         strategy: task.strategy,
+        no_deps: task.no_deps,
         ..SolverTask::from_iter([&repo_data])
     };
 
@@ -1164,6 +1667,52 @@ fn channel_specific_requirement() {
     );
 }
 
+#[test]
+fn subdir_specific_requirement() {
+    let result = solve::<rattler_solve::resolvo::Solver>(
+        dummy_channel_json_path(),
+        SimpleSolveTask {
+            specs: &["cuda-version[subdir=noarch]"],
+            ..SimpleSolveTask::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].package_record.subdir, "noarch");
+
+    let err = solve::<rattler_solve::resolvo::Solver>(
+        dummy_channel_json_path(),
+        SimpleSolveTask {
+            specs: &["foobar[subdir=osx-64]"],
+            ..SimpleSolveTask::default()
+        },
+    )
+    .unwrap_err();
+    let SolveError::Unsolvable(messages) = err else {
+        panic!("expected the solve to be unsolvable");
+    };
+    assert!(messages
+        .iter()
+        .any(|message| message.contains("candidate not in requested subdir: 'osx-64'")));
+}
+
+#[cfg(feature = "libsolv_c")]
+#[test]
+fn subdir_specific_requirement_libsolv_c() {
+    let err = solve::<rattler_solve::libsolv_c::Solver>(
+        dummy_channel_json_path(),
+        SimpleSolveTask {
+            specs: &["cuda-version[subdir=noarch]"],
+            ..SimpleSolveTask::default()
+        },
+    )
+    .unwrap_err();
+    assert!(
+        matches!(&err, SolveError::UnsupportedOperations(operations) if operations == &["subdir"]),
+        "{err}"
+    );
+}
+
 #[test]
 fn channel_priority_strict() {
     // Solve with conda-forge as the first channel
@@ -1225,6 +1774,92 @@ fn channel_priority_disabled() {
     );
 }
 
+fn solve_with_channel_policy<T: SolverImpl + Default>(
+    spec_str: &str,
+    repo_data: Vec<&SparseRepoData>,
+    policy: PackageChannelPolicy,
+) -> Result<Vec<RepoDataRecord>, SolveError> {
+    let spec = MatchSpec::from_str(spec_str, ParseStrictness::Lenient).unwrap();
+    let name = spec.name.clone().unwrap();
+    let available_packages =
+        SparseRepoData::load_records_recursive(repo_data, [name.clone()], None).unwrap();
+
+    let task = SolverTask {
+        specs: vec![spec],
+        channel_policies: HashMap::from([(name, policy)]),
+        ..SolverTask::from_iter(&available_packages)
+    };
+    T::default().solve(task)
+}
+
+#[test]
+fn channel_policy_per_package() {
+    let repodata = vec![
+        read_conda_forge_sparse_repo_data(),
+        read_pytorch_sparse_repo_data(),
+    ];
+
+    // Pinning a package to a channel takes precedence over strict channel
+    // priority.
+    let records = solve_with_channel_policy::<rattler_solve::resolvo::Solver>(
+        "pytorch-cpu",
+        repodata.clone(),
+        PackageChannelPolicy::Only(String::from("pytorch")),
+    )
+    .unwrap();
+    let record = records
+        .iter()
+        .find(|record| record.package_record.name.as_normalized() == "pytorch-cpu")
+        .unwrap();
+    assert_eq!(record.channel, "https://conda.anaconda.org/pytorch/");
+
+    // The channel priority can be relaxed for a single package.
+    solve_with_channel_policy::<rattler_solve::resolvo::Solver>(
+        "pytorch-cpu=0.4.1=py36_cpu_1",
+        repodata.clone(),
+        PackageChannelPolicy::Priority(ChannelPriority::Disabled),
+    )
+    .unwrap();
+
+    // The reason why candidates are excluded is part of the error message.
+    let err = solve_with_channel_policy::<rattler_solve::resolvo::Solver>(
+        "pytorch-cpu=0.4.1=py36_cpu_1",
+        repodata,
+        PackageChannelPolicy::Only(String::from("https://conda.anaconda.org/conda-forge/")),
+    )
+    .unwrap_err();
+    let SolveError::Unsolvable(messages) = err else {
+        panic!("expected an unsolvable error, got {err}");
+    };
+    assert!(
+        messages[0].contains(
+            "candidate not in the channel 'https://conda.anaconda.org/conda-forge/' that \
+             'pytorch-cpu' is pinned to"
+        ),
+        "{}",
+        messages[0]
+    );
+}
+
+#[cfg(feature = "libsolv_c")]
+#[test]
+fn channel_policy_unsupported_libsolv_c() {
+    let repodata = vec![
+        read_conda_forge_sparse_repo_data(),
+        read_pytorch_sparse_repo_data(),
+    ];
+    let err = solve_with_channel_policy::<rattler_solve::libsolv_c::Solver>(
+        "pytorch-cpu",
+        repodata,
+        PackageChannelPolicy::Only(String::from("pytorch")),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(&err, SolveError::UnsupportedOperations(operations) if operations == &["channel_policies"]),
+        "{err}"
+    );
+}
+
 #[cfg(feature = "libsolv_c")]
 #[test]
 #[should_panic(
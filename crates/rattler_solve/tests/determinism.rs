@@ -0,0 +1,131 @@
+//! Tests that verify that solves are deterministic: the same task always
+//! results in the same solution, regardless of the order in which the records
+//! of a repodata are provided.
+
+use std::str::FromStr;
+
+use rattler_conda_types::{
+    Channel, ChannelConfig, MatchSpec, ParseStrictness, RepoData, RepoDataRecord,
+};
+use rattler_repodata_gateway::sparse::SparseRepoData;
+use rattler_solve::{SolverImpl, SolverTask};
+
+fn channel_config() -> ChannelConfig {
+    ChannelConfig::default_with_root_dir(std::env::current_dir().unwrap())
+}
+
+fn repodata_path(channel: &str, subdir: &str) -> String {
+    format!(
+        "{}/../../test-data/channels/{channel}/{subdir}/repodata.json",
+        env!("CARGO_MANIFEST_DIR")
+    )
+}
+
+fn read_repodata(channel: &str, subdir: &str) -> Vec<RepoDataRecord> {
+    let repo_data: RepoData =
+        serde_json::from_str(&std::fs::read_to_string(repodata_path(channel, subdir)).unwrap())
+            .unwrap();
+    repo_data.into_repo_data_records(&Channel::from_str(channel, &channel_config()).unwrap())
+}
+
+/// Reads the records of the packages that are required to solve the specs,
+/// like `solve_real_world` in `backends.rs` does.
+fn read_sparse_repodata(
+    channel: &str,
+    subdirs: &[&str],
+    specs: &[&str],
+) -> Vec<Vec<RepoDataRecord>> {
+    let sparse_repo_datas = subdirs
+        .iter()
+        .map(|subdir| {
+            SparseRepoData::new(
+                Channel::from_str(channel, &channel_config()).unwrap(),
+                (*subdir).to_string(),
+                repodata_path(channel, subdir),
+                None,
+            )
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let names = specs.iter().filter_map(|spec| {
+        MatchSpec::from_str(spec, ParseStrictness::Lenient)
+            .unwrap()
+            .name
+    });
+    SparseRepoData::load_records_recursive(&sparse_repo_datas, names, None).unwrap()
+}
+
+/// Returns the records in different orders.
+fn permutations(records: &[RepoDataRecord]) -> Vec<Vec<&RepoDataRecord>> {
+    let forward = records.iter().collect::<Vec<_>>();
+    let reversed = records.iter().rev().collect::<Vec<_>>();
+    let mut interleaved = records.iter().step_by(2).collect::<Vec<_>>();
+    interleaved.extend(records.iter().skip(1).step_by(2));
+    vec![forward, reversed, interleaved]
+}
+
+/// Solves the specs for every permutation of the repodata and asserts that
+/// every solve results in exactly the same records.
+fn assert_deterministic<T: SolverImpl + Default>(repodata: &[Vec<RepoDataRecord>], specs: &[&str]) {
+    let specs = specs
+        .iter()
+        .map(|spec| MatchSpec::from_str(spec, ParseStrictness::Lenient).unwrap())
+        .collect::<Vec<_>>();
+
+    let orders = repodata
+        .iter()
+        .map(|records| permutations(records))
+        .collect::<Vec<_>>();
+    let solve = |permutation: usize| {
+        let available_packages = orders
+            .iter()
+            .map(|permutations| permutations[permutation].iter().copied())
+            .collect::<Vec<_>>();
+        let task = SolverTask {
+            specs: specs.clone(),
+            ..SolverTask::from_iter(available_packages)
+        };
+        T::default()
+            .solve(task)
+            .unwrap()
+            .into_iter()
+            .map(|record| record.url.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // Solving the first permutation twice also verifies that repeated solves
+    // of the same task are stable.
+    let expected = solve(0);
+    assert!(!expected.is_empty());
+    for permutation in 0..orders[0].len() {
+        assert_eq!(solve(permutation), expected, "permutation {permutation}");
+    }
+}
+
+macro_rules! determinism_tests {
+    ($T:path) => {
+        #[test]
+        fn test_dummy_channel() {
+            let repodata = vec![crate::read_repodata("dummy", "linux-64")];
+            crate::assert_deterministic::<$T>(&repodata, &["foobar", "foo"]);
+        }
+
+        #[test]
+        fn test_conda_forge() {
+            let specs = ["python=3.9", "numpy"];
+            let repodata =
+                crate::read_sparse_repodata("conda-forge", &["linux-64", "noarch"], &specs);
+            crate::assert_deterministic::<$T>(&repodata, &specs);
+        }
+    };
+}
+
+#[cfg(feature = "libsolv_c")]
+mod libsolv_c {
+    determinism_tests!(rattler_solve::libsolv_c::Solver);
+}
+
+#[cfg(feature = "resolvo")]
+mod resolvo {
+    determinism_tests!(rattler_solve::resolvo::Solver);
+}
@@ -11,6 +11,7 @@
 use libloading::Symbol;
 use once_cell::sync::OnceCell;
 use rattler_conda_types::Version;
+use std::path::PathBuf;
 use std::process::Command;
 use std::{
     mem::MaybeUninit,
@@ -33,6 +34,13 @@ pub fn detect_cuda_version() -> Option<Version> {
         // Dynamically loading a library is not supported on musl so we have to fall-back to using
         // the nvidia-smi command.
         detect_cuda_version_via_nvidia_smi()
+    } else if cfg!(windows) {
+        // Depending on the driver version `nvml.dll` is not always on the search path. If it
+        // cannot be found fall back to `nvcuda.dll` which is always installed in the system
+        // directory by the driver, and as a last resort to the nvidia-smi command.
+        detect_cuda_version_via_nvml()
+            .or_else(detect_cuda_version_via_libcuda)
+            .or_else(detect_cuda_version_via_nvidia_smi)
     } else {
         detect_cuda_version_via_nvml()
     }
@@ -50,7 +58,9 @@ pub fn detect_cuda_version_via_nvml() -> Option<Version> {
     // Try to open the library
     let library = nvml_library_paths()
         .iter()
-        .find_map(|path| unsafe { libloading::Library::new(*path).ok() })?;
+        .map(PathBuf::from)
+        .chain(nvml_driver_library_paths())
+        .find_map(|path| unsafe { libloading::Library::new(path).ok() })?;
 
     // Get the initialization function. We first try to get `nvmlInit_v2` but if we can't find that
     // we use the `nvmlInit` function.
@@ -134,6 +144,30 @@ fn nvml_library_paths() -> &'static [&'static str] {
     FILENAMES
 }
 
+/// Returns additional locations of the nvml library that depend on the system configuration.
+///
+/// On Windows, recent drivers install `nvml.dll` in the system directory but older drivers install
+/// it in the `NVSMI` directory of the NVIDIA installation which is not on the search path.
+fn nvml_driver_library_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if cfg!(windows) {
+        if let Some(system_root) = std::env::var_os("SystemRoot") {
+            paths.push(PathBuf::from(system_root).join("System32").join("nvml.dll"));
+        }
+        for program_files in ["ProgramW6432", "ProgramFiles"] {
+            if let Some(program_files) = std::env::var_os(program_files) {
+                paths.push(
+                    PathBuf::from(program_files)
+                        .join("NVIDIA Corporation")
+                        .join("NVSMI")
+                        .join("nvml.dll"),
+                );
+            }
+        }
+    }
+    paths
+}
+
 /// Attempts to detect the version of CUDA present in the current operating system by loading the
 /// cuda runtime library and querying the CUDA driver version.
 ///
@@ -32,10 +32,14 @@ pub mod cuda;
 pub mod libc;
 pub mod linux;
 pub mod osx;
+pub mod system_requirements;
+pub mod win;
 
 use archspec::cpu::Microarchitecture;
 use once_cell::sync::OnceCell;
-use rattler_conda_types::{GenericVirtualPackage, PackageName, Platform, Version};
+use rattler_conda_types::{
+    GenericVirtualPackage, PackageName, ParseVersionError, Platform, Version,
+};
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
@@ -43,12 +47,13 @@ use crate::osx::ParseOsxVersionError;
 use libc::DetectLibCError;
 use linux::ParseLinuxVersionError;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+pub use system_requirements::{SystemRequirements, SystemRequirementsError};
 
 /// An enum that represents all virtual package types provided by this library.
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub enum VirtualPackage {
     /// Available on windows
-    Win,
+    Win(Windows),
 
     /// Available on unix based platforms
     Unix,
@@ -72,11 +77,7 @@ pub enum VirtualPackage {
 impl From<VirtualPackage> for GenericVirtualPackage {
     fn from(package: VirtualPackage) -> Self {
         match package {
-            VirtualPackage::Win => GenericVirtualPackage {
-                name: PackageName::new_unchecked("__win"),
-                version: Version::major(0),
-                build_string: "0".into(),
-            },
+            VirtualPackage::Win(windows) => windows.into(),
             VirtualPackage::Unix => GenericVirtualPackage {
                 name: PackageName::new_unchecked("__unix"),
                 version: Version::major(0),
@@ -97,9 +98,104 @@ impl VirtualPackage {
     pub fn current() -> Result<&'static [Self], DetectVirtualPackageError> {
         static DETECED_VIRTUAL_PACKAGES: OnceCell<Vec<VirtualPackage>> = OnceCell::new();
         DETECED_VIRTUAL_PACKAGES
-            .get_or_try_init(try_detect_virtual_packages)
+            .get_or_try_init(|| try_detect_virtual_packages(&VirtualPackageOverrides::default()))
             .map(Vec::as_slice)
     }
+
+    /// Detects the virtual packages of the current system like [`VirtualPackage::current`], but
+    /// uses the overridden versions instead of the detected versions where an override is given.
+    ///
+    /// Unlike [`VirtualPackage::current`] the result is not memoized, the system is inspected
+    /// every time this function is called.
+    pub fn detect(
+        overrides: &VirtualPackageOverrides,
+    ) -> Result<Vec<Self>, DetectVirtualPackageError> {
+        try_detect_virtual_packages(overrides)
+    }
+}
+
+/// Describes where the value of an overridden virtual package comes from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Override {
+    /// Use the value of the environment variable that conda uses to override the virtual package,
+    /// e.g. `CONDA_OVERRIDE_CUDA`. If the variable is not set the virtual package is detected.
+    DefaultEnvVar,
+
+    /// Use the value of the given environment variable. If the variable is not set the virtual
+    /// package is detected.
+    EnvVar(String),
+
+    /// Use the given value.
+    String(String),
+}
+
+impl Override {
+    /// Returns the value of the override, or `None` if the virtual package should be detected.
+    fn value(&self, default_env_var: &str) -> Option<String> {
+        match self {
+            Override::DefaultEnvVar => std::env::var(default_env_var).ok(),
+            Override::EnvVar(name) => std::env::var(name).ok(),
+            Override::String(value) => Some(value.clone()),
+        }
+    }
+}
+
+/// Overrides for the detection of virtual packages, see [`VirtualPackage::detect`].
+///
+/// This makes it possible to solve for another machine than the current one. The value of an
+/// override is the version of the virtual package (or the name of the microarchitecture for
+/// `__archspec`). An empty value disables the virtual package, e.g. an empty `cuda` override
+/// disables the detection of cuda.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VirtualPackageOverrides {
+    /// Overrides the version of `__osx`, the default environment variable is
+    /// `CONDA_OVERRIDE_OSX`.
+    pub osx: Option<Override>,
+
+    /// Overrides the version of `__glibc`, the default environment variable is
+    /// `CONDA_OVERRIDE_GLIBC`.
+    pub libc: Option<Override>,
+
+    /// Overrides the version of `__cuda`, the default environment variable is
+    /// `CONDA_OVERRIDE_CUDA`.
+    pub cuda: Option<Override>,
+
+    /// Overrides the microarchitecture of `__archspec`, the default environment variable is
+    /// `CONDA_OVERRIDE_ARCHSPEC`.
+    pub archspec: Option<Override>,
+}
+
+impl VirtualPackageOverrides {
+    /// Returns overrides that use the environment variables that conda uses, e.g.
+    /// `CONDA_OVERRIDE_CUDA`, for all virtual packages that can be overridden.
+    pub fn from_env() -> Self {
+        Self {
+            osx: Some(Override::DefaultEnvVar),
+            libc: Some(Override::DefaultEnvVar),
+            cuda: Some(Override::DefaultEnvVar),
+            archspec: Some(Override::DefaultEnvVar),
+        }
+    }
+}
+
+/// Returns the value of an override, or `None` if the virtual package should be detected.
+fn override_value(value: Option<&Override>, default_env_var: &str) -> Option<String> {
+    value.and_then(|value| value.value(default_env_var))
+}
+
+/// Parses the version of an overridden virtual package. An empty value disables the package.
+fn parse_override_version(value: &str) -> Result<Option<Version>, DetectVirtualPackageError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    value
+        .parse()
+        .map(Some)
+        .map_err(|source| DetectVirtualPackageError::InvalidOverride {
+            value: value.to_string(),
+            source,
+        })
 }
 
 /// An error that might be returned by [`VirtualPackage::current`].
@@ -114,10 +210,19 @@ pub enum DetectVirtualPackageError {
 
     #[error(transparent)]
     DetectLibC(#[from] DetectLibCError),
+
+    #[error("invalid version '{value}' of a virtual package override")]
+    InvalidOverride {
+        value: String,
+        #[source]
+        source: ParseVersionError,
+    },
 }
 
 // Detect the available virtual packages on the system
-fn try_detect_virtual_packages() -> Result<Vec<VirtualPackage>, DetectVirtualPackageError> {
+fn try_detect_virtual_packages(
+    overrides: &VirtualPackageOverrides,
+) -> Result<Vec<VirtualPackage>, DetectVirtualPackageError> {
     let mut result = Vec::new();
     let platform = Platform::current();
 
@@ -126,35 +231,91 @@ fn try_detect_virtual_packages() -> Result<Vec<VirtualPackage>, DetectVirtualPac
     }
 
     if platform.is_windows() {
-        result.push(VirtualPackage::Win);
+        result.push(Windows::current().into());
     }
 
     if platform.is_linux() {
         if let Some(linux_version) = Linux::current()? {
             result.push(linux_version.into());
         }
-        if let Some(libc) = LibC::current()? {
+        let libc = match override_value(overrides.libc.as_ref(), "CONDA_OVERRIDE_GLIBC") {
+            Some(value) => parse_override_version(&value)?.map(|version| LibC {
+                family: String::from("glibc"),
+                version,
+            }),
+            None => LibC::current()?,
+        };
+        if let Some(libc) = libc {
             result.push(libc.into());
         }
     }
 
     if platform.is_osx() {
-        if let Some(osx) = Osx::current()? {
+        let osx = match override_value(overrides.osx.as_ref(), "CONDA_OVERRIDE_OSX") {
+            Some(value) => parse_override_version(&value)?.map(|version| Osx { version }),
+            None => Osx::current()?,
+        };
+        if let Some(osx) = osx {
             result.push(osx.into());
         }
     }
 
-    if let Some(cuda) = Cuda::current() {
+    let cuda = match override_value(overrides.cuda.as_ref(), "CONDA_OVERRIDE_CUDA") {
+        Some(value) => parse_override_version(&value)?.map(|version| Cuda { version }),
+        None => Cuda::current(),
+    };
+    if let Some(cuda) = cuda {
         result.push(cuda.into());
     }
 
-    if let Some(archspec) = Archspec::current() {
+    let archspec = match override_value(overrides.archspec.as_ref(), "CONDA_OVERRIDE_ARCHSPEC") {
+        Some(value) => Some(value.trim())
+            .filter(|name| !name.is_empty())
+            .map(Archspec::from_name),
+        None => Archspec::current(),
+    };
+    if let Some(archspec) = archspec {
         result.push(archspec.into());
     }
 
     Ok(result)
 }
 
+/// Windows virtual package description
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
+pub struct Windows {
+    /// The version of Windows, e.g. `10.0.22631`. `None` if the version could not be determined.
+    pub version: Option<Version>,
+}
+
+impl Windows {
+    /// Returns the Windows version of the current platform.
+    ///
+    /// The version is `None` if the current platform is not Windows or if the version could not
+    /// be determined.
+    pub fn current() -> Self {
+        Self {
+            version: win::windows_version(),
+        }
+    }
+}
+
+impl From<Windows> for GenericVirtualPackage {
+    fn from(windows: Windows) -> Self {
+        GenericVirtualPackage {
+            name: PackageName::new_unchecked("__win"),
+            version: windows.version.unwrap_or_else(|| Version::major(0)),
+            build_string: "0".into(),
+        }
+    }
+}
+
+impl From<Windows> for VirtualPackage {
+    fn from(windows: Windows) -> Self {
+        VirtualPackage::Win(windows)
+    }
+}
+
 /// Linux virtual package description
 #[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
 pub struct Linux {
@@ -281,11 +442,7 @@ impl<'de> Deserialize<'de> for Archspec {
         D: Deserializer<'de>,
     {
         let name = String::deserialize(deserializer)?;
-        let spec = archspec::cpu::Microarchitecture::known_targets()
-            .get(&name)
-            .cloned()
-            .unwrap_or_else(|| Arc::new(archspec::cpu::Microarchitecture::generic(&name)));
-        Ok(Self { spec })
+        Ok(Self::from_name(&name))
     }
 }
 
@@ -315,6 +472,16 @@ impl Archspec {
         archspec::cpu::host().ok().map(Into::into)
     }
 
+    /// Returns the microarchitecture with the given name. Unknown names result in a generic
+    /// microarchitecture.
+    pub fn from_name(name: &str) -> Self {
+        archspec::cpu::Microarchitecture::known_targets()
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| Arc::new(archspec::cpu::Microarchitecture::generic(name)))
+            .into()
+    }
+
     /// Returns the minimal supported archspec architecture for the given
     /// platform.
     #[allow(clippy::match_same_arms)]
@@ -325,7 +492,7 @@ impl Archspec {
             Platform::NoArch | Platform::Unknown => return None,
             Platform::EmscriptenWasm32 | Platform::WasiWasm32 => return None,
             Platform::Win32 | Platform::Linux32 => "x86",
-            Platform::Win64 | Platform::Osx64 | Platform::Linux64 => "x86_64",
+            Platform::Win64 | Platform::Osx64 | Platform::Linux64 | Platform::FreeBsd64 => "x86_64",
             Platform::LinuxAarch64 | Platform::LinuxArmV6l | Platform::LinuxArmV7l => "aarch64",
             Platform::LinuxPpc64le => "ppc64le",
             Platform::LinuxPpc64 => "ppc64",
@@ -342,15 +509,7 @@ impl Archspec {
             Platform::OsxArm64 => "m1",
         };
 
-        Some(
-            archspec::cpu::Microarchitecture::known_targets()
-                .get(archspec_name)
-                .cloned()
-                .unwrap_or_else(|| {
-                    Arc::new(archspec::cpu::Microarchitecture::generic(archspec_name))
-                })
-                .into(),
-        )
+        Some(Self::from_name(archspec_name))
     }
 }
 
@@ -405,11 +564,67 @@ impl From<Osx> for VirtualPackage {
 
 #[cfg(test)]
 mod test {
-    use crate::VirtualPackage;
+    use rattler_conda_types::{GenericVirtualPackage, Platform};
+
+    use crate::{Override, VirtualPackage, VirtualPackageOverrides};
 
     #[test]
     fn doesnt_crash() {
         let virtual_packages = VirtualPackage::current().unwrap();
         println!("{virtual_packages:?}");
     }
+
+    #[test]
+    fn test_overrides() {
+        let overrides = VirtualPackageOverrides {
+            osx: Some(Override::String(String::from("13.0"))),
+            libc: Some(Override::String(String::from("2.28"))),
+            cuda: Some(Override::String(String::from("12.1"))),
+            archspec: Some(Override::String(String::from("x86_64_v3"))),
+        };
+        let virtual_packages = VirtualPackage::detect(&overrides)
+            .unwrap()
+            .into_iter()
+            .map(GenericVirtualPackage::from)
+            .map(|package| package.to_string())
+            .collect::<Vec<_>>();
+
+        assert!(virtual_packages.contains(&String::from("__cuda=12.1=0")));
+        assert!(virtual_packages.contains(&String::from("__archspec=1=x86_64_v3")));
+        let platform = Platform::current();
+        if platform.is_linux() {
+            assert!(virtual_packages.contains(&String::from("__glibc=2.28=0")));
+        }
+        if platform.is_osx() {
+            assert!(virtual_packages.contains(&String::from("__osx=13.0=0")));
+        }
+
+        // An empty override disables the virtual package.
+        let overrides = VirtualPackageOverrides {
+            cuda: Some(Override::String(String::new())),
+            ..VirtualPackageOverrides::default()
+        };
+        assert!(!VirtualPackage::detect(&overrides)
+            .unwrap()
+            .iter()
+            .any(|package| matches!(package, VirtualPackage::Cuda(_))));
+
+        // An unset environment variable falls back to detection.
+        let overrides = VirtualPackageOverrides {
+            cuda: Some(Override::EnvVar(String::from(
+                "RATTLER_TEST_UNSET_CUDA_OVERRIDE",
+            ))),
+            ..VirtualPackageOverrides::default()
+        };
+        assert_eq!(
+            VirtualPackage::detect(&overrides).unwrap(),
+            VirtualPackage::current().unwrap()
+        );
+
+        let overrides = VirtualPackageOverrides {
+            cuda: Some(Override::String(String::from("a.b!"))),
+            ..VirtualPackageOverrides::default()
+        };
+        assert!(VirtualPackage::detect(&overrides).is_err());
+    }
 }
@@ -32,17 +32,58 @@ pub enum DetectLibCError {
 /// binary can still run on a glibc based system. For environments we are
 /// interested in the libc family that is available on the *system*.
 ///
-/// Currently this code is only able to detect glibc properly. We can add more
+/// Currently this code is able to detect glibc and musl. We can add more
 /// detection methods in the future.
 #[cfg(unix)]
 fn try_detect_libc_version() -> Result<Option<(String, Version)>, DetectLibCError> {
     // Run `ldd --version` to detect the libc version and family on the system.
     // `ldd` is shipped with libc so if an error occured during its execution we
-    // can assume no libc is available on the system.
+    // assume libc is not available, unless the musl dynamic linker is found.
     let output = match std::process::Command::new("ldd").arg("--version").output() {
+        Err(e) => {
+            tracing::info!("failed to execute `ldd --version`: {e}.");
+            return try_detect_musl_version();
+        }
+        Ok(output) => output,
+    };
+
+    if let Some(version) = parse_glibc_ldd_version(&String::from_utf8_lossy(&output.stdout))? {
+        return Ok(Some((String::from("glibc"), version)));
+    }
+
+    // The `ldd` of musl prints its version to stderr.
+    if let Some(version) = parse_musl_version(&String::from_utf8_lossy(&output.stderr))? {
+        return Ok(Some((String::from("musl"), version)));
+    }
+
+    try_detect_musl_version()
+}
+
+/// Detects the version of musl by executing the musl dynamic linker, which
+/// prints its version when executed without arguments. This also works on
+/// systems that do not ship `ldd`.
+#[cfg(unix)]
+fn try_detect_musl_version() -> Result<Option<(String, Version)>, DetectLibCError> {
+    let Some(linker) = std::fs::read_dir("/lib")
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(std::ffi::OsStr::to_str)
+                .is_some_and(|name| name.starts_with("ld-musl-") && name.ends_with(".so.1"))
+        })
+    else {
+        tracing::info!("could not find glibc or musl. Assuming libc is not available.");
+        return Ok(None);
+    };
+
+    let output = match std::process::Command::new(&linker).output() {
         Err(e) => {
             tracing::info!(
-                "failed to execute `ldd --version`: {e}. Assuming libc is not available."
+                "failed to execute `{}`: {e}. Assuming libc is not available.",
+                linker.display()
             );
             return Ok(None);
         }
@@ -50,8 +91,8 @@ fn try_detect_libc_version() -> Result<Option<(String, Version)>, DetectLibCErro
     };
 
     Ok(
-        parse_glibc_ldd_version(&String::from_utf8_lossy(&output.stdout))?
-            .map(|version| (String::from("glibc"), version)),
+        parse_musl_version(&String::from_utf8_lossy(&output.stderr))?
+            .map(|version| (String::from("musl"), version)),
     )
 }
 
@@ -73,6 +114,31 @@ fn parse_glibc_ldd_version(input: &str) -> Result<Option<Version>, DetectLibCErr
     Ok(None)
 }
 
+/// Parses the version from the output of musl's `ldd` or dynamic linker, e.g.
+///
+/// ```text
+/// musl libc (x86_64)
+/// Version 1.2.4
+/// Dynamic Program Path: /lib/ld-musl-x86_64.so.1
+/// ```
+#[cfg(any(test, unix))]
+fn parse_musl_version(input: &str) -> Result<Option<Version>, DetectLibCError> {
+    static MUSL_RE: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
+        regex::Regex::new("(?mi)^musl libc.*\\n\\s*Version ([0-9]+(?:\\.[0-9]+)*)").unwrap()
+    });
+
+    if let Some(version_match) = MUSL_RE
+        .captures(input)
+        .and_then(|captures| captures.get(1))
+        .map(|version_match| version_match.as_str())
+    {
+        let version = std::str::FromStr::from_str(version_match)?;
+        return Ok(Some(version));
+    }
+
+    Ok(None)
+}
+
 #[cfg(not(unix))]
 const fn try_detect_libc_version() -> Result<Option<(String, Version)>, DetectLibCError> {
     Ok(None)
@@ -105,5 +171,25 @@ mod test {
             parse_glibc_ldd_version("ldd (GNU libc) 2.31").unwrap(),
             Some(Version::from_str("2.31").unwrap())
         );
+        assert_eq!(
+            parse_glibc_ldd_version("musl libc (x86_64)\nVersion 1.2.4\n").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    pub fn test_parse_musl_version() {
+        assert_eq!(
+            parse_musl_version(
+                "musl libc (x86_64)\nVersion 1.2.4\nDynamic Program Path: /lib/ld-musl-x86_64.so.1\n"
+            )
+            .unwrap(),
+            Some(Version::from_str("1.2.4").unwrap())
+        );
+        assert_eq!(
+            parse_musl_version("musl libc (aarch64)\r\nVersion 1.1.24\r\n").unwrap(),
+            Some(Version::from_str("1.1.24").unwrap())
+        );
+        assert_eq!(parse_musl_version("ldd (GNU libc) 2.31").unwrap(), None);
     }
 }
@@ -0,0 +1,262 @@
+//! Construct the virtual packages of a system that is described declaratively.
+//!
+//! Detecting virtual packages only works for the current machine. To solve an environment for
+//! another machine, e.g. a deployment target, the virtual packages of that machine can instead be
+//! derived from a [`SystemRequirements`] description of it.
+
+use rattler_conda_types::{GenericVirtualPackage, Platform, Version};
+use serde::Deserialize;
+
+use crate::{Archspec, Cuda, LibC, Linux, Osx, VirtualPackage, Windows};
+
+/// Describes the capabilities of a system for which an environment is created.
+///
+/// Use [`SystemRequirements::virtual_packages`] to construct the virtual packages that represent
+/// the system on a specific platform. Entries that are not specified are not added as virtual
+/// packages, except for the `__unix`/`__win` packages and the `__archspec` package which default
+/// to the minimal architecture of the platform.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SystemRequirements {
+    /// The version of the Linux kernel (`__linux`).
+    pub linux: Option<Version>,
+
+    /// The family and version of the libc implementation, e.g. glibc 2.28 (`__glibc`).
+    pub libc: Option<LibC>,
+
+    /// The maximum Cuda version that is supported by the driver (`__cuda`).
+    pub cuda: Option<Version>,
+
+    /// The version of macOS (`__osx`).
+    pub macos: Option<Version>,
+
+    /// The version of Windows (`__win`).
+    pub windows: Option<Version>,
+
+    /// The name of the microarchitecture of the CPU, e.g. `x86_64_v3` (`__archspec`).
+    pub archspec: Option<String>,
+}
+
+/// An error that is returned when [`SystemRequirements`] are invalid.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum SystemRequirementsError {
+    /// Two entries describe systems that cannot be the same system.
+    #[error("the system requirements '{0}' and '{1}' cannot be combined")]
+    Conflict(&'static str, &'static str),
+
+    /// An entry does not apply to the platform.
+    #[error("the system requirement '{0}' does not apply to {1}")]
+    IncompatiblePlatform(&'static str, Platform),
+
+    /// The microarchitecture is not known.
+    #[error("unknown microarchitecture '{0}'")]
+    UnknownArchspec(String),
+}
+
+impl SystemRequirements {
+    /// Returns the names of the entries that are specified.
+    fn specified(&self) -> Vec<&'static str> {
+        [
+            ("linux", self.linux.is_some()),
+            ("libc", self.libc.is_some()),
+            ("cuda", self.cuda.is_some()),
+            ("macos", self.macos.is_some()),
+            ("windows", self.windows.is_some()),
+            ("archspec", self.archspec.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, specified)| specified.then_some(name))
+        .collect()
+    }
+
+    /// Validates that the entries describe a single system, e.g. a system cannot have both a
+    /// macOS version and a libc version.
+    pub fn validate(&self) -> Result<(), SystemRequirementsError> {
+        const CONFLICTS: &[(&str, &str)] = &[
+            ("linux", "macos"),
+            ("linux", "windows"),
+            ("libc", "macos"),
+            ("libc", "windows"),
+            ("macos", "windows"),
+            ("cuda", "macos"),
+        ];
+
+        let specified = self.specified();
+        if let Some((a, b)) = CONFLICTS
+            .iter()
+            .find(|(a, b)| specified.contains(a) && specified.contains(b))
+        {
+            return Err(SystemRequirementsError::Conflict(*a, *b));
+        }
+
+        if let Some(name) = &self.archspec {
+            if !archspec::cpu::Microarchitecture::known_targets().contains_key(name.as_str()) {
+                return Err(SystemRequirementsError::UnknownArchspec(name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the virtual packages of a system with these requirements on the given platform.
+    ///
+    /// Returns an error if the requirements are invalid (see [`Self::validate`]) or if an entry
+    /// does not apply to the platform, e.g. a macOS version for a Linux platform.
+    pub fn virtual_packages(
+        &self,
+        platform: Platform,
+    ) -> Result<Vec<VirtualPackage>, SystemRequirementsError> {
+        self.validate()?;
+
+        let applies = |name: &'static str, applies: bool| {
+            if applies {
+                Ok(())
+            } else {
+                Err(SystemRequirementsError::IncompatiblePlatform(
+                    name, platform,
+                ))
+            }
+        };
+
+        let mut result = Vec::new();
+        if platform.is_unix() {
+            result.push(VirtualPackage::Unix);
+        }
+        if platform.is_windows() {
+            result.push(
+                Windows {
+                    version: self.windows.clone(),
+                }
+                .into(),
+            );
+        } else if self.windows.is_some() {
+            return Err(SystemRequirementsError::IncompatiblePlatform(
+                "windows", platform,
+            ));
+        }
+        if let Some(version) = &self.linux {
+            applies("linux", platform.is_linux())?;
+            result.push(
+                Linux {
+                    version: version.clone(),
+                }
+                .into(),
+            );
+        }
+        if let Some(libc) = &self.libc {
+            applies("libc", platform.is_linux())?;
+            result.push(libc.clone().into());
+        }
+        if let Some(version) = &self.macos {
+            applies("macos", platform.is_osx())?;
+            result.push(
+                Osx {
+                    version: version.clone(),
+                }
+                .into(),
+            );
+        }
+        if let Some(version) = &self.cuda {
+            applies("cuda", !platform.is_osx())?;
+            result.push(
+                Cuda {
+                    version: version.clone(),
+                }
+                .into(),
+            );
+        }
+        let archspec = match &self.archspec {
+            Some(name) => Some(Archspec::from_name(name)),
+            None => Archspec::from_platform(platform),
+        };
+        if let Some(archspec) = archspec {
+            result.push(archspec.into());
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the virtual packages like [`Self::virtual_packages`] but converted to
+    /// [`GenericVirtualPackage`]s, e.g. to pass them to a solver.
+    pub fn generic_virtual_packages(
+        &self,
+        platform: Platform,
+    ) -> Result<Vec<GenericVirtualPackage>, SystemRequirementsError> {
+        Ok(self
+            .virtual_packages(platform)?
+            .into_iter()
+            .map(GenericVirtualPackage::from)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use rattler_conda_types::{Platform, Version};
+
+    use super::{SystemRequirements, SystemRequirementsError};
+    use crate::LibC;
+
+    #[test]
+    fn test_virtual_packages() {
+        let requirements = SystemRequirements {
+            linux: Some(Version::from_str("5.10").unwrap()),
+            libc: Some(LibC {
+                family: String::from("glibc"),
+                version: Version::from_str("2.28").unwrap(),
+            }),
+            cuda: Some(Version::from_str("12.1").unwrap()),
+            ..SystemRequirements::default()
+        };
+        let packages = requirements
+            .generic_virtual_packages(Platform::Linux64)
+            .unwrap()
+            .into_iter()
+            .map(|package| package.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            packages,
+            [
+                "__unix=0=0",
+                "__linux=5.10=0",
+                "__glibc=2.28=0",
+                "__cuda=12.1=0",
+                "__archspec=1=x86_64"
+            ]
+        );
+
+        assert_eq!(
+            requirements.virtual_packages(Platform::Osx64),
+            Err(SystemRequirementsError::IncompatiblePlatform(
+                "linux",
+                Platform::Osx64
+            ))
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let requirements = SystemRequirements {
+            macos: Some(Version::from_str("13.0").unwrap()),
+            cuda: Some(Version::from_str("12.1").unwrap()),
+            ..SystemRequirements::default()
+        };
+        assert_eq!(
+            requirements.validate(),
+            Err(SystemRequirementsError::Conflict("cuda", "macos"))
+        );
+
+        let requirements = SystemRequirements {
+            archspec: Some(String::from("not-a-cpu")),
+            ..SystemRequirements::default()
+        };
+        assert_eq!(
+            requirements.validate(),
+            Err(SystemRequirementsError::UnknownArchspec(String::from(
+                "not-a-cpu"
+            )))
+        );
+    }
+}
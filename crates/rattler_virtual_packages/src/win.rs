@@ -0,0 +1,92 @@
+//! Low-level functions to detect the Windows version of the system. See [`windows_version`].
+
+use once_cell::sync::OnceCell;
+use rattler_conda_types::Version;
+
+/// Returns the Windows version of the current platform as `major.minor.build` (e.g.
+/// `10.0.22631`).
+///
+/// Returns `None` if the current platform is not Windows or if the version could not be
+/// determined.
+pub fn windows_version() -> Option<Version> {
+    static DETECTED_WINDOWS_VERSION: OnceCell<Option<Version>> = OnceCell::new();
+    DETECTED_WINDOWS_VERSION
+        .get_or_init(try_detect_windows_version)
+        .clone()
+}
+
+/// Detects the current Windows version by calling `RtlGetVersion` from `ntdll.dll`.
+///
+/// Unlike `GetVersionEx`, `RtlGetVersion` is not affected by the compatibility manifest of the
+/// executable and therefore always returns the actual version of the operating system.
+#[cfg(windows)]
+fn try_detect_windows_version() -> Option<Version> {
+    use libloading::Symbol;
+    use std::{mem::MaybeUninit, os::raw::c_long};
+
+    /// The `OSVERSIONINFOW` struct from the Windows API.
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    struct OsVersionInfoW {
+        dwOSVersionInfoSize: u32,
+        dwMajorVersion: u32,
+        dwMinorVersion: u32,
+        dwBuildNumber: u32,
+        dwPlatformId: u32,
+        szCSDVersion: [u16; 128],
+    }
+
+    let library = unsafe { libloading::Library::new("ntdll.dll") }.ok()?;
+    let rtl_get_version: Symbol<'_, unsafe extern "system" fn(*mut OsVersionInfoW) -> c_long> =
+        unsafe { library.get(b"RtlGetVersion\0") }.ok()?;
+
+    let mut info = MaybeUninit::<OsVersionInfoW>::zeroed();
+    // The size of the struct must be set before calling the function.
+    unsafe {
+        (*info.as_mut_ptr()).dwOSVersionInfoSize = std::mem::size_of::<OsVersionInfoW>() as u32;
+    }
+
+    // A status of 0 is `STATUS_SUCCESS`.
+    if unsafe { rtl_get_version(info.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let info = unsafe { info.assume_init() };
+
+    windows_version_from_parts(info.dwMajorVersion, info.dwMinorVersion, info.dwBuildNumber)
+}
+
+#[cfg(not(windows))]
+const fn try_detect_windows_version() -> Option<Version> {
+    None
+}
+
+/// Constructs the version of the `__win` virtual package from the individual components of the
+/// Windows version. This matches the format of Python's `platform.version()` which is used by
+/// conda.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn windows_version_from_parts(major: u32, minor: u32, build: u32) -> Option<Version> {
+    format!("{major}.{minor}.{build}").parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::windows_version_from_parts;
+
+    #[test]
+    pub fn test_version_from_parts() {
+        assert_eq!(
+            windows_version_from_parts(10, 0, 22631)
+                .unwrap()
+                .to_string(),
+            "10.0.22631"
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    pub fn doesnt_crash() {
+        let version = super::try_detect_windows_version();
+        println!("Windows version {version:?}");
+        assert!(version.is_some());
+    }
+}
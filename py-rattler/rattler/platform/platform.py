@@ -21,6 +21,7 @@ PlatformLiteral = Literal[
     "win-32",
     "win-64",
     "win-arm64",
+    "freebsd-64",
     "emscripten-32",
 ]
 
@@ -39,6 +39,39 @@ class SparseRepoData:
             )
         self._sparse = PySparseRepoData(channel._channel, subdir, str(path))
 
+    @classmethod
+    def from_bytes(cls, channel: Channel, subdir: str, data: bytes) -> SparseRepoData:
+        """
+        Construct an instance from the contents of a `repodata.json` file that
+        are already in memory, e.g. because they were downloaded by the caller.
+        This avoids writing the repodata to disk before it can be used.
+
+        Examples
+        --------
+        ```python
+        >>> from rattler import Channel, ChannelConfig
+        >>> channel = Channel("dummy", ChannelConfig())
+        >>> subdir = "linux-64"
+        >>> path = "../test-data/channels/dummy/linux-64/repodata.json"
+        >>> with open(path, "rb") as f:
+        ...     sparse_data = SparseRepoData.from_bytes(channel, subdir, f.read())
+        >>> sparse_data
+        SparseRepoData(subdir="linux-64")
+        >>>
+        ```
+        """
+        if not isinstance(channel, Channel):
+            raise TypeError(
+                "SparseRepoData.from_bytes received unsupported type "
+                f" {type(channel).__name__!r} for the `channel` parameter"
+            )
+        if not isinstance(data, (bytes, bytearray, memoryview)):
+            raise TypeError(
+                "SparseRepoData.from_bytes received unsupported type "
+                f" {type(data).__name__!r} for the `data` parameter"
+            )
+        return cls._from_py_sparse_repo_data(PySparseRepoData.from_bytes(channel._channel, subdir, bytes(data)))
+
     def package_names(self) -> List[str]:
         """
         Returns a list over all package names in this repodata file.
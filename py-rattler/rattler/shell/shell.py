@@ -86,6 +86,7 @@ class Shell:
     fish = PyShellEnum.Fish
     xonsh = PyShellEnum.Xonsh
     powershell = PyShellEnum.PowerShell
+    pwsh = PyShellEnum.PowerShellCore
     cmd_exe = PyShellEnum.CmdExe
 
 
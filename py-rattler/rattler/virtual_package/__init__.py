@@ -1,4 +1,5 @@
 from rattler.virtual_package.generic import GenericVirtualPackage
+from rattler.virtual_package.overrides import Override, VirtualPackageOverrides
 from rattler.virtual_package.virtual_package import VirtualPackage
 
-__all__ = ["GenericVirtualPackage", "VirtualPackage"]
+__all__ = ["GenericVirtualPackage", "Override", "VirtualPackage", "VirtualPackageOverrides"]
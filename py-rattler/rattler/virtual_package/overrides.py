@@ -0,0 +1,164 @@
+from __future__ import annotations
+
+from typing import Optional, Union
+
+from rattler.rattler import PyOverride, PyVirtualPackageOverrides
+
+
+class Override:
+    """
+    Describes where the value of an overridden virtual package comes from.
+    """
+
+    _override: PyOverride
+
+    @classmethod
+    def _from_py_override(cls, py_override: PyOverride) -> Override:
+        override = cls.__new__(cls)
+        override._override = py_override
+        return override
+
+    @staticmethod
+    def default_env_var() -> Override:
+        """
+        Use the environment variable that conda uses to override the virtual
+        package, e.g. `CONDA_OVERRIDE_CUDA`. If the variable is not set the
+        virtual package is detected.
+        """
+        return Override._from_py_override(PyOverride.default_env_var())
+
+    @staticmethod
+    def env_var(name: str) -> Override:
+        """
+        Use the value of the given environment variable. If the variable is
+        not set the virtual package is detected.
+        """
+        return Override._from_py_override(PyOverride.env_var(name))
+
+    @staticmethod
+    def string(value: str) -> Override:
+        """
+        Use the given value. An empty value disables the virtual package.
+
+        Examples
+        --------
+        ```python
+        >>> Override.string("12.1")
+        Override.string("12.1")
+        >>>
+        ```
+        """
+        return Override._from_py_override(PyOverride.string(value))
+
+    def __repr__(self) -> str:
+        if (value := self._override.value) is not None:
+            return f'Override.string("{value}")'
+        if (name := self._override.env_var_name) is not None:
+            return f'Override.env_var("{name}")'
+        return "Override.default_env_var()"
+
+
+def _to_py_override(value: Union[Override, str, None]) -> Optional[PyOverride]:
+    if value is None:
+        return None
+    if isinstance(value, str):
+        return PyOverride.string(value)
+    return value._override
+
+
+def _from_py_override(value: Optional[PyOverride]) -> Optional[Override]:
+    return None if value is None else Override._from_py_override(value)
+
+
+class VirtualPackageOverrides:
+    """
+    Overrides for the detection of virtual packages, which makes it possible
+    to solve for another machine than the current one.
+
+    The value of an override is the version of the virtual package (or the
+    name of the microarchitecture for `__archspec`). A string is used as the
+    value as is. An empty value disables the virtual package, e.g. `cuda=""`
+    disables the detection of cuda.
+
+    Examples
+    --------
+    ```python
+    >>> overrides = VirtualPackageOverrides(cuda="", libc="2.17")
+    >>> overrides.cuda
+    Override.string("")
+    >>> overrides.libc = Override.env_var("MY_GLIBC_VERSION")
+    >>> overrides.libc
+    Override.env_var("MY_GLIBC_VERSION")
+    >>> VirtualPackageOverrides.from_env().cuda
+    Override.default_env_var()
+    >>>
+    ```
+    """
+
+    _overrides: PyVirtualPackageOverrides
+
+    def __init__(
+        self,
+        osx: Union[Override, str, None] = None,
+        libc: Union[Override, str, None] = None,
+        cuda: Union[Override, str, None] = None,
+        archspec: Union[Override, str, None] = None,
+    ) -> None:
+        self._overrides = PyVirtualPackageOverrides(
+            _to_py_override(osx),
+            _to_py_override(libc),
+            _to_py_override(cuda),
+            _to_py_override(archspec),
+        )
+
+    @staticmethod
+    def from_env() -> VirtualPackageOverrides:
+        """
+        Returns overrides that use the environment variables that conda uses,
+        e.g. `CONDA_OVERRIDE_CUDA`, for all virtual packages.
+        """
+        overrides = VirtualPackageOverrides.__new__(VirtualPackageOverrides)
+        overrides._overrides = PyVirtualPackageOverrides.from_env()
+        return overrides
+
+    @property
+    def osx(self) -> Optional[Override]:
+        """The override of the version of `__osx`."""
+        return _from_py_override(self._overrides.osx)
+
+    @osx.setter
+    def osx(self, value: Union[Override, str, None]) -> None:
+        self._overrides.osx = _to_py_override(value)
+
+    @property
+    def libc(self) -> Optional[Override]:
+        """The override of the version of `__glibc`."""
+        return _from_py_override(self._overrides.libc)
+
+    @libc.setter
+    def libc(self, value: Union[Override, str, None]) -> None:
+        self._overrides.libc = _to_py_override(value)
+
+    @property
+    def cuda(self) -> Optional[Override]:
+        """The override of the version of `__cuda`."""
+        return _from_py_override(self._overrides.cuda)
+
+    @cuda.setter
+    def cuda(self, value: Union[Override, str, None]) -> None:
+        self._overrides.cuda = _to_py_override(value)
+
+    @property
+    def archspec(self) -> Optional[Override]:
+        """The override of the microarchitecture of `__archspec`."""
+        return _from_py_override(self._overrides.archspec)
+
+    @archspec.setter
+    def archspec(self, value: Union[Override, str, None]) -> None:
+        self._overrides.archspec = _to_py_override(value)
+
+    def __repr__(self) -> str:
+        return (
+            f"VirtualPackageOverrides(osx={self.osx!r}, libc={self.libc!r}, "
+            f"cuda={self.cuda!r}, archspec={self.archspec!r})"
+        )
@@ -1,9 +1,10 @@
 from __future__ import annotations
 
 from rattler.rattler import PyVirtualPackage
-from typing import List
+from typing import List, Optional
 
 from rattler.virtual_package.generic import GenericVirtualPackage
+from rattler.virtual_package.overrides import VirtualPackageOverrides
 
 
 class VirtualPackage:
@@ -24,6 +25,25 @@ class VirtualPackage:
         """
         return [VirtualPackage._from_py_virtual_package(vp) for vp in PyVirtualPackage.current()]
 
+    @staticmethod
+    def detect(overrides: Optional[VirtualPackageOverrides] = None) -> List[VirtualPackage]:
+        """
+        Returns virtual packages detected for the current system, using the
+        overridden versions where an override is given. Unlike `current` the
+        system is inspected every time this function is called.
+
+        Examples
+        --------
+        ```python
+        >>> packages = VirtualPackage.detect(VirtualPackageOverrides(cuda="12.1", archspec="x86_64"))
+        >>> [str(p.into_generic()) for p in packages if p.into_generic().name.normalized == "__cuda"]
+        ['__cuda=12.1=0']
+        >>>
+        ```
+        """
+        overrides = overrides or VirtualPackageOverrides()
+        return [VirtualPackage._from_py_virtual_package(vp) for vp in PyVirtualPackage.detect(overrides._overrides)]
+
     def into_generic(self) -> GenericVirtualPackage:
         """
         Returns a GenericVirtualPackage from VirtualPackage.
@@ -161,6 +161,7 @@ impl PySourceConfig {
                 zstd_enabled,
                 bz2_enabled,
                 cache_action: cache_action.0,
+                ..SourceConfig::default()
             },
         }
     }
@@ -1,10 +1,11 @@
 use std::{path::PathBuf, sync::Arc};
 
-use pyo3::{pyclass, pymethods, PyResult, Python};
+use pyo3::{pyclass, pymethods, types::PyBytes, PyResult, Python};
 
 use rattler_repodata_gateway::sparse::SparseRepoData;
 
 use crate::channel::PyChannel;
+use crate::error::PyRattlerError;
 use crate::package_name::PyPackageName;
 use crate::record::PyRecord;
 
@@ -36,6 +37,18 @@ impl PySparseRepoData {
         Ok(SparseRepoData::new(channel.into(), subdir, path, None)?.into())
     }
 
+    #[staticmethod]
+    pub fn from_bytes(channel: PyChannel, subdir: String, data: &PyBytes) -> PyResult<Self> {
+        Ok(SparseRepoData::from_bytes(
+            channel.into(),
+            subdir,
+            data.as_bytes().to_vec().into(),
+            None,
+        )
+        .map_err(|err| PyRattlerError::from(std::io::Error::from(err)))?
+        .into())
+    }
+
     pub fn package_names(&self) -> Vec<String> {
         self.inner
             .package_names()
@@ -3,7 +3,7 @@ use crate::platform::PyPlatform;
 use pyo3::{exceptions::PyValueError, pyclass, pymethods, FromPyObject, PyAny, PyResult};
 use rattler_shell::{
     activation::{ActivationResult, ActivationVariables, Activator, PathModificationBehavior},
-    shell::{Bash, CmdExe, Fish, PowerShell, ShellEnum, Xonsh, Zsh},
+    shell::{Bash, CmdExe, Fish, PowerShell, PowerShellCore, ShellEnum, Xonsh, Zsh},
 };
 use std::path::{Path, PathBuf};
 
@@ -106,6 +106,7 @@ pub enum PyShellEnum {
     Xonsh,
     CmdExe,
     PowerShell,
+    PowerShellCore,
     Fish,
 }
 
@@ -117,6 +118,7 @@ impl PyShellEnum {
             PyShellEnum::Xonsh => Xonsh.into(),
             PyShellEnum::CmdExe => CmdExe.into(),
             PyShellEnum::PowerShell => PowerShell::default().into(),
+            PyShellEnum::PowerShellCore => PowerShellCore.into(),
             PyShellEnum::Fish => Fish.into(),
         }
     }
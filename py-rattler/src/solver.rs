@@ -2,8 +2,10 @@ use chrono::DateTime;
 use pyo3::{exceptions::PyValueError, pyfunction, FromPyObject, PyAny, PyErr, PyResult, Python};
 use pyo3_asyncio::tokio::future_into_py;
 use rattler_repodata_gateway::sparse::SparseRepoData;
-use rattler_solve::{resolvo::Solver, RepoDataIter, SolveStrategy, SolverImpl, SolverTask};
-use std::sync::Arc;
+use rattler_solve::{
+    resolvo::Solver, RepoDataIter, SolveStrategy, SolverImpl, SolverTask, TrackFeaturesPolicy,
+};
+use std::{collections::HashMap, sync::Arc};
 use tokio::task::JoinError;
 
 use crate::{
@@ -83,9 +85,15 @@ pub fn py_solve(
                 specs: specs.into_iter().map(Into::into).collect(),
                 constraints: constraints.into_iter().map(Into::into).collect(),
                 timeout: timeout.map(std::time::Duration::from_micros),
+                best_effort: false,
                 channel_priority: channel_priority.into(),
+                channel_policies: HashMap::new(),
                 exclude_newer,
+                exclude_newer_applies_to_locked: false,
                 strategy: strategy.map_or_else(Default::default, |v| v.0),
+                candidate_ordering: None,
+                track_features: TrackFeaturesPolicy::default(),
+                no_deps: false,
             };
 
             Ok::<_, PyErr>(
@@ -159,9 +167,15 @@ pub fn py_solve_with_sparse_repodata(
                 specs: specs.into_iter().map(Into::into).collect(),
                 constraints: constraints.into_iter().map(Into::into).collect(),
                 timeout: timeout.map(std::time::Duration::from_micros),
+                best_effort: false,
                 channel_priority: channel_priority.into(),
+                channel_policies: HashMap::new(),
                 exclude_newer,
+                exclude_newer_applies_to_locked: false,
                 strategy: strategy.map_or_else(Default::default, |v| v.0),
+                candidate_ordering: None,
+                track_features: TrackFeaturesPolicy::default(),
+                no_deps: false,
             };
 
             Ok::<_, PyErr>(
@@ -1,8 +1,150 @@
 use pyo3::{pyclass, pymethods, PyResult};
-use rattler_virtual_packages::VirtualPackage;
+use rattler_virtual_packages::{Override, VirtualPackage, VirtualPackageOverrides};
 
 use crate::{error::PyRattlerError, generic_virtual_package::PyGenericVirtualPackage};
 
+#[pyclass]
+#[repr(transparent)]
+#[derive(Clone)]
+pub struct PyOverride {
+    pub(crate) inner: Override,
+}
+
+impl From<Override> for PyOverride {
+    fn from(value: Override) -> Self {
+        Self { inner: value }
+    }
+}
+
+impl From<PyOverride> for Override {
+    fn from(value: PyOverride) -> Self {
+        value.inner
+    }
+}
+
+#[pymethods]
+impl PyOverride {
+    /// Uses the environment variable that conda uses to override the virtual package.
+    #[staticmethod]
+    pub fn default_env_var() -> Self {
+        Override::DefaultEnvVar.into()
+    }
+
+    /// Uses the given environment variable to override the virtual package.
+    #[staticmethod]
+    pub fn env_var(name: String) -> Self {
+        Override::EnvVar(name).into()
+    }
+
+    /// Uses the given value to override the virtual package.
+    #[staticmethod]
+    pub fn string(value: String) -> Self {
+        Override::String(value).into()
+    }
+
+    /// Returns the name of the environment variable, or `None` if the default environment
+    /// variable or a value is used.
+    #[getter]
+    pub fn env_var_name(&self) -> Option<String> {
+        match &self.inner {
+            Override::EnvVar(name) => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the override, or `None` if an environment variable is used.
+    #[getter]
+    pub fn value(&self) -> Option<String> {
+        match &self.inner {
+            Override::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!("{:?}", self.inner)
+    }
+}
+
+#[pyclass]
+#[repr(transparent)]
+#[derive(Clone)]
+pub struct PyVirtualPackageOverrides {
+    pub(crate) inner: VirtualPackageOverrides,
+}
+
+impl From<VirtualPackageOverrides> for PyVirtualPackageOverrides {
+    fn from(value: VirtualPackageOverrides) -> Self {
+        Self { inner: value }
+    }
+}
+
+#[pymethods]
+impl PyVirtualPackageOverrides {
+    #[new]
+    #[pyo3(signature = (osx=None, libc=None, cuda=None, archspec=None))]
+    pub fn __init__(
+        osx: Option<PyOverride>,
+        libc: Option<PyOverride>,
+        cuda: Option<PyOverride>,
+        archspec: Option<PyOverride>,
+    ) -> Self {
+        VirtualPackageOverrides {
+            osx: osx.map(Into::into),
+            libc: libc.map(Into::into),
+            cuda: cuda.map(Into::into),
+            archspec: archspec.map(Into::into),
+        }
+        .into()
+    }
+
+    /// Returns overrides that use the environment variables that conda uses.
+    #[staticmethod]
+    pub fn from_env() -> Self {
+        VirtualPackageOverrides::from_env().into()
+    }
+
+    #[getter]
+    pub fn osx(&self) -> Option<PyOverride> {
+        self.inner.osx.clone().map(Into::into)
+    }
+
+    #[setter]
+    pub fn set_osx(&mut self, value: Option<PyOverride>) {
+        self.inner.osx = value.map(Into::into);
+    }
+
+    #[getter]
+    pub fn libc(&self) -> Option<PyOverride> {
+        self.inner.libc.clone().map(Into::into)
+    }
+
+    #[setter]
+    pub fn set_libc(&mut self, value: Option<PyOverride>) {
+        self.inner.libc = value.map(Into::into);
+    }
+
+    #[getter]
+    pub fn cuda(&self) -> Option<PyOverride> {
+        self.inner.cuda.clone().map(Into::into)
+    }
+
+    #[setter]
+    pub fn set_cuda(&mut self, value: Option<PyOverride>) {
+        self.inner.cuda = value.map(Into::into);
+    }
+
+    #[getter]
+    pub fn archspec(&self) -> Option<PyOverride> {
+        self.inner.archspec.clone().map(Into::into)
+    }
+
+    #[setter]
+    pub fn set_archspec(&mut self, value: Option<PyOverride>) {
+        self.inner.archspec = value.map(Into::into);
+    }
+}
+
 #[pyclass]
 #[repr(transparent)]
 #[derive(Clone)]
@@ -33,6 +175,15 @@ impl PyVirtualPackage {
             .map_err(PyRattlerError::from)?)
     }
 
+    /// Returns virtual packages detected for the current system, using the overridden versions
+    /// where an override is given.
+    #[staticmethod]
+    pub fn detect(overrides: &PyVirtualPackageOverrides) -> PyResult<Vec<Self>> {
+        Ok(VirtualPackage::detect(&overrides.inner)
+            .map(|vp| vp.into_iter().map(Into::into).collect::<Vec<_>>())
+            .map_err(PyRattlerError::from)?)
+    }
+
     pub fn as_generic(&self) -> PyGenericVirtualPackage {
         self.clone().into()
     }
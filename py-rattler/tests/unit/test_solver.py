@@ -160,3 +160,19 @@ async def test_solve_with_repodata() -> None:
     assert isinstance(solved_data, list)
     assert isinstance(solved_data[0], RepoDataRecord)
     assert len(solved_data) == 2
+
+
+@pytest.mark.asyncio
+async def test_solve_with_repodata_from_bytes() -> None:
+    linux64_chan = Channel("conda-forge")
+    data_dir = os.path.join(os.path.dirname(__file__), "../../../test-data/")
+    linux64_path = os.path.join(data_dir, "channels/dummy/linux-64/repodata.json")
+    with open(linux64_path, "rb") as f:
+        linux64_data = SparseRepoData.from_bytes(linux64_chan, "linux-64", f.read())
+
+    solved_data = await solve_with_sparse_repodata(
+        [MatchSpec("foobar")],
+        [linux64_data],
+    )
+
+    assert len(solved_data) == 2
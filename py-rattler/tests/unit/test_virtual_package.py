@@ -0,0 +1,37 @@
+from typing import Dict, List
+
+import pytest
+from rattler import Override, VirtualPackage, VirtualPackageOverrides
+from rattler.exceptions import DetectVirtualPackageError
+
+
+def _generic_names(packages: List[VirtualPackage]) -> Dict[str, str]:
+    return {p.into_generic().name.normalized: str(p.into_generic()) for p in packages}
+
+
+def test_detect_with_overrides() -> None:
+    packages = _generic_names(VirtualPackage.detect(VirtualPackageOverrides(cuda="12.1", archspec="x86_64_v3")))
+    assert packages["__cuda"] == "__cuda=12.1=0"
+    assert packages["__archspec"] == "__archspec=1=x86_64_v3"
+
+
+def test_disable_cuda() -> None:
+    packages = _generic_names(VirtualPackage.detect(VirtualPackageOverrides(cuda="")))
+    assert "__cuda" not in packages
+
+
+def test_env_var_override(monkeypatch: pytest.MonkeyPatch) -> None:
+    monkeypatch.setenv("CONDA_OVERRIDE_CUDA", "11.8")
+    monkeypatch.setenv("MY_CUDA_VERSION", "12.4")
+
+    packages = _generic_names(VirtualPackage.detect(VirtualPackageOverrides.from_env()))
+    assert packages["__cuda"] == "__cuda=11.8=0"
+
+    overrides = VirtualPackageOverrides(cuda=Override.env_var("MY_CUDA_VERSION"))
+    packages = _generic_names(VirtualPackage.detect(overrides))
+    assert packages["__cuda"] == "__cuda=12.4=0"
+
+
+def test_invalid_override() -> None:
+    with pytest.raises(DetectVirtualPackageError):
+        VirtualPackage.detect(VirtualPackageOverrides(cuda="a.b!"))